@@ -15,11 +15,12 @@ use futures::StreamExt;
 use http::StatusCode;
 use rand::Rng;
 use serde::Deserialize;
+use serde_json::value::RawValue;
 use serde_json::{json, Value};
 use spacetimedb::address::Address;
 use spacetimedb::auth::identity::encode_token;
 use spacetimedb::database_logger::DatabaseLogger;
-use spacetimedb::error::DatabaseError;
+use spacetimedb::error::{DBError, DatabaseError};
 use spacetimedb::host::DescribedEntityType;
 use spacetimedb::host::EntityDef;
 use spacetimedb::host::ReducerArgs;
@@ -30,14 +31,17 @@ use spacetimedb::identity::Identity;
 use spacetimedb::json::client_api::StmtResultJson;
 use spacetimedb::messages::control_db::{Database, DatabaseInstance};
 use spacetimedb::sql;
-use spacetimedb::sql::execute::{ctx_sql, translate_col};
+use spacetimedb::sql::execute::{ctx_sql, translate_col, ResultSink};
 use spacetimedb_client_api_messages::name::{self, DnsLookupResponse, DomainName, PublishOp, PublishResult};
 use spacetimedb_client_api_messages::recovery::{RecoveryCode, RecoveryCodeResponse};
 use spacetimedb_data_structures::map::HashMap;
 use spacetimedb_lib::address::AddressForUrl;
 use spacetimedb_lib::identity::AuthCtx;
+use spacetimedb_lib::relation::Header;
+use spacetimedb_lib::sats::db::auth::StAccess;
 use spacetimedb_lib::sats::WithTypespace;
-use spacetimedb_lib::ProductTypeElement;
+use spacetimedb_lib::{ProductTypeElement, ProductValue};
+use std::sync::Arc;
 
 pub(crate) struct DomainParsingRejection;
 
@@ -519,6 +523,36 @@ pub struct SqlParams {
 #[derive(Deserialize)]
 pub struct SqlQueryParams {}
 
+/// Serializes the rows of each result of a `SQL` execution to JSON as they're produced,
+/// so that a large result is never held in memory as [`ProductValue`]s.
+#[derive(Default)]
+struct JsonResults {
+    /// The header of each result, and the JSON array of its rows serialized so far, without the closing `]`.
+    results: Vec<(Arc<Header>, Vec<u8>)>,
+}
+
+impl ResultSink for JsonResults {
+    fn start_result(&mut self, head: &Arc<Header>, _: StAccess) -> Result<(), DBError> {
+        self.results.push((head.clone(), vec![b'[']));
+        Ok(())
+    }
+
+    fn push_rows(&mut self, rows: Vec<ProductValue>) -> Result<usize, DBError> {
+        let (_, json) = self
+            .results
+            .last_mut()
+            .expect("rows pushed before the start of a result");
+        let len = json.len();
+        for row in rows {
+            if json.len() > 1 {
+                json.push(b',');
+            }
+            serde_json::to_writer(&mut *json, &row).map_err(anyhow::Error::from)?;
+        }
+        Ok(json.len() - len)
+    }
+}
+
 pub async fn sql<S>(
     State(worker_ctx): State<S>,
     Path(SqlParams { name_or_address }): Path<SqlParams>,
@@ -569,8 +603,9 @@ where
     };
 
     let stdb = &dbic.relational_db;
-    let results = match sql::execute::run(stdb, &body, auth) {
-        Ok(results) => results,
+    let mut results = JsonResults::default();
+    let stats = match sql::execute::run_into(stdb, &body, auth, &mut results) {
+        Ok(stats) => stats,
         Err(err) => {
             log::warn!("{}", err);
             return if let Some(auth_err) = err.get_auth_error() {
@@ -582,26 +617,36 @@ where
             };
         }
     };
+    tracing::info!(
+        rows_scanned = stats.rows_scanned,
+        rows_returned = stats.rows_returned,
+        index_seeks = stats.index_seeks,
+        bytes_serialized = stats.bytes_serialized,
+        cpu_time = ?stats.cpu_time,
+        "sql executed"
+    );
 
     let json = stdb.with_read_only(&ctx_sql(stdb), |tx| {
         results
+            .results
             .into_iter()
-            .map(|result| {
-                let rows = result.data;
-                let schema = result
-                    .head
+            .map(|(head, mut rows)| {
+                rows.push(b']');
+                let rows = String::from_utf8(rows).map_err(log_and_500)?;
+                let rows = RawValue::from_string(rows).map_err(log_and_500)?;
+                let schema = head
                     .fields
                     .iter()
                     .map(|x| {
                         let ty = x.algebraic_type.clone();
-                        let name = translate_col(tx, x.field);
+                        let name = x.name.clone().or_else(|| translate_col(tx, x.field));
                         ProductTypeElement::new(ty, name)
                     })
                     .collect();
-                StmtResultJson { schema, rows }
+                Ok(StmtResultJson { schema, rows })
             })
-            .collect::<Vec<_>>()
-    });
+            .collect::<axum::response::Result<Vec<_>>>()
+    })?;
 
     Ok((StatusCode::OK, axum::Json(json)))
 }
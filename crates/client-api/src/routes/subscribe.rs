@@ -242,12 +242,15 @@ async fn ws_client_actor_inner(
                 } else {
                     let send_all = async {
                         let id = client.id.identity;
+                        let mut bytes_sent = 0;
                         for msg in rx_buf.drain(..n).map(|msg| datamsg_to_wsmsg(msg.serialize(client.protocol))) {
                             WORKER_METRICS.websocket_sent.with_label_values(&id).inc();
                             WORKER_METRICS.websocket_sent_msg_size.with_label_values(&id).observe(msg.len() as f64);
+                            bytes_sent += msg.len();
                             // feed() buffers the message, but does not necessarily send it
                             ws.feed(msg).await?;
                         }
+                        client.metrics.bytes_sent.inc_by(bytes_sent as u64);
                         // now we flush all the messages to the socket
                         ws.flush().await
                     };
@@ -9,7 +9,7 @@ use crate::error::DBError;
 use crate::host::{ModuleHost, ReducerArgs, ReducerCallError, ReducerCallResult};
 use crate::protobuf::client_api::Subscribe;
 use crate::util::prometheus_handle::IntGaugeExt;
-use crate::worker_metrics::WORKER_METRICS;
+use crate::worker_metrics::{ClientMetrics, WORKER_METRICS};
 use derive_more::From;
 use futures::prelude::*;
 use spacetimedb_lib::identity::RequestId;
@@ -86,6 +86,7 @@ pub struct ClientConnection {
     sender: Arc<ClientConnectionSender>,
     pub database_instance_id: u64,
     pub module: ModuleHost,
+    pub metrics: Arc<ClientMetrics<'static>>,
 }
 
 impl Deref for ClientConnection {
@@ -166,6 +167,7 @@ impl ClientConnection {
         let this = Self {
             sender,
             database_instance_id,
+            metrics: Arc::new(WORKER_METRICS.client_metrics(db, id.identity)),
             module,
         };
 
@@ -180,6 +182,7 @@ impl ClientConnection {
         Self {
             sender: Arc::new(ClientConnectionSender::dummy(id, protocol)),
             database_instance_id,
+            metrics: Arc::new(WORKER_METRICS.client_metrics(module.info().address, id.identity)),
             module,
         }
     }
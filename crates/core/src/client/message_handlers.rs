@@ -49,6 +49,8 @@ pub async fn handle(client: &ClientConnection, message: DataMessage, timer: Inst
         .with_label_values(&client.database_instance_id, message_kind)
         .inc();
 
+    client.metrics.bytes_received.inc_by(message.len() as u64);
+
     match message {
         DataMessage::Text(message) => handle_text(client, message, timer).await,
         DataMessage::Binary(message_buf) => handle_binary(client, message_buf, timer).await,
@@ -182,9 +184,10 @@ impl DecodedMessage<'_> {
                 request_id,
             } => {
                 let res = client.call_reducer(reducer, args, request_id, timer).await;
+                let label = client.module.info().reducer_labels.get(reducer);
                 WORKER_METRICS
                     .request_round_trip
-                    .with_label_values(&WorkloadType::Reducer, &address, reducer)
+                    .with_label_values(&WorkloadType::Reducer, &address, label)
                     .observe(timer.elapsed().as_secs_f64());
                 res.map(drop).map_err(|e| (Some(reducer), e.into()))
             }
@@ -1,8 +1,12 @@
 use std::ops::RangeBounds;
 
+use crate::db::db_metrics::record_index_scan;
 use crate::error::DBError;
-use spacetimedb_sats::relation::DbTable;
+use crate::execution_context::{ExecutionContext, MetricType};
+use spacetimedb_sats::relation::{DbTable, Header};
 use spacetimedb_sats::AlgebraicValue;
+use spacetimedb_table::btree_index::BTreeIndexKeyIter;
+use std::sync::Arc;
 
 use super::datastore::locking_tx_datastore::{Iter, IterByColRange};
 
@@ -10,11 +14,36 @@ use super::datastore::locking_tx_datastore::{Iter, IterByColRange};
 pub struct TableCursor<'a> {
     pub table: DbTable,
     pub iter: Iter<'a>,
+    /// The execution context in which the rows fetched are recorded.
+    ctx: &'a ExecutionContext,
+    /// The number of rows yielded so far.
+    pub num_rows_fetched: u64,
 }
 
 impl<'a> TableCursor<'a> {
-    pub fn new(table: DbTable, iter: Iter<'a>) -> Result<Self, DBError> {
-        Ok(Self { table, iter })
+    pub fn new(ctx: &'a ExecutionContext, table: DbTable, iter: Iter<'a>) -> Result<Self, DBError> {
+        Ok(Self {
+            table,
+            iter,
+            ctx,
+            num_rows_fetched: 0,
+        })
+    }
+}
+
+impl Drop for TableCursor<'_> {
+    fn drop(&mut self) {
+        // Recorded once per cursor, rather than per row,
+        // so the metrics lock isn't taken on the hot path.
+        let mut metrics = self.ctx.metrics.write();
+        let get_table_name = || self.table.head.table_name.to_string();
+        metrics.inc_by(self.table.table_id, MetricType::FullTableScans, 1, get_table_name);
+        metrics.inc_by(
+            self.table.table_id,
+            MetricType::RowsFetched,
+            self.num_rows_fetched,
+            get_table_name,
+        );
     }
 }
 
@@ -23,10 +52,109 @@ impl<'a> TableCursor<'a> {
 pub struct IndexCursor<'a, R: RangeBounds<AlgebraicValue>> {
     pub table: &'a DbTable,
     pub iter: IterByColRange<'a, R>,
+    /// The execution context in which the index seek, or scan, and the rows fetched are recorded.
+    ctx: &'a ExecutionContext,
+    /// The number of rows yielded so far.
+    pub num_rows_fetched: u64,
+    /// Whether the underlying iterator has been run to completion.
+    pub exhausted: bool,
+    /// Whether this cursor serves the point or range lookup of a `Query::IndexScan`,
+    /// rather than, e.g., one side of a merge join or one range of an `IndexMultiScan`.
+    /// Only such lookups are recorded by [`record_index_scan`].
+    pub is_lookup: bool,
 }
 
 impl<'a, R: RangeBounds<AlgebraicValue>> IndexCursor<'a, R> {
-    pub fn new(table: &'a DbTable, iter: IterByColRange<'a, R>) -> Result<Self, DBError> {
-        Ok(Self { table, iter })
+    pub fn new(ctx: &'a ExecutionContext, table: &'a DbTable, iter: IterByColRange<'a, R>) -> Result<Self, DBError> {
+        Ok(Self {
+            table,
+            iter,
+            ctx,
+            num_rows_fetched: 0,
+            exhausted: false,
+            is_lookup: false,
+        })
+    }
+
+    /// Marks this cursor as serving the lookup of a `Query::IndexScan`; see [`IndexCursor::is_lookup`].
+    pub fn for_lookup(mut self) -> Self {
+        self.is_lookup = true;
+        self
+    }
+}
+
+impl<R: RangeBounds<AlgebraicValue>> Drop for IndexCursor<'_, R> {
+    fn drop(&mut self) {
+        // Only count lookups that ran to completion,
+        // not ones abandoned early, e.g., because the other side of a join was empty.
+        if self.is_lookup && self.exhausted && !matches!(self.iter, IterByColRange::Scan(_)) {
+            record_index_scan(
+                self.ctx.database(),
+                self.table.table_id,
+                &self.table.head.table_name,
+                self.num_rows_fetched,
+            );
+        }
+        let mut metrics = self.ctx.metrics.write();
+        let get_table_name = || self.table.head.table_name.to_string();
+        // Without an index on the columns, the range is served by scanning the whole table.
+        let access = match self.iter {
+            IterByColRange::Scan(_) => MetricType::FullTableScans,
+            IterByColRange::Index(_) | IterByColRange::CommittedIndex(_) => MetricType::IndexSeeks,
+        };
+        metrics.inc_by(self.table.table_id, access, 1, get_table_name);
+        metrics.inc_by(
+            self.table.table_id,
+            MetricType::RowsFetched,
+            self.num_rows_fetched,
+            get_table_name,
+        );
+    }
+}
+
+/// A relational iterator over the keys of a storage level index,
+/// answering a covering index scan without fetching any row of the table.
+pub struct IndexKeyCursor<'a> {
+    pub table: &'a DbTable,
+    /// The header of the indexed columns of `table`, which make up the rows yielded.
+    pub head: Arc<Header>,
+    pub iter: BTreeIndexKeyIter<'a>,
+    /// The execution context in which the index seek is recorded.
+    ctx: &'a ExecutionContext,
+    /// The number of keys yielded so far.
+    pub num_keys_yielded: u64,
+    /// Whether the underlying iterator has been run to completion.
+    pub exhausted: bool,
+}
+
+impl<'a> IndexKeyCursor<'a> {
+    pub fn new(ctx: &'a ExecutionContext, table: &'a DbTable, head: Arc<Header>, iter: BTreeIndexKeyIter<'a>) -> Self {
+        Self {
+            table,
+            head,
+            iter,
+            ctx,
+            num_keys_yielded: 0,
+            exhausted: false,
+        }
+    }
+}
+
+impl Drop for IndexKeyCursor<'_> {
+    fn drop(&mut self) {
+        if self.exhausted {
+            record_index_scan(
+                self.ctx.database(),
+                self.table.table_id,
+                &self.table.head.table_name,
+                self.num_keys_yielded,
+            );
+        }
+        // No row is fetched, so only the seek is recorded.
+        let get_table_name = || self.table.head.table_name.to_string();
+        self.ctx
+            .metrics
+            .write()
+            .inc_by(self.table.table_id, MetricType::IndexSeeks, 1, get_table_name);
     }
 }
@@ -16,7 +16,7 @@ use crate::{
             },
             traits::TxData,
         },
-        db_metrics::DB_METRICS,
+        db_metrics::{record_table_size, DB_METRICS},
     },
     error::TableError,
     execution_context::{ExecutionContext, MetricType},
@@ -30,11 +30,12 @@ use spacetimedb_sats::{
         auth::{StAccess, StTableType},
         def::TableSchema,
     },
+    relation::{ColumnStats, ColumnStatsMap},
     AlgebraicValue, ProductValue,
 };
 use spacetimedb_table::{
     blob_store::{BlobStore, HashMapBlobStore},
-    btree_index::BTreeIndex,
+    btree_index::{BTreeIndex, BTreeIndexKeyIter},
     indexes::{RowPointer, SquashedOffset},
     table::{IndexScanIter, InsertError, RowRef, Table},
 };
@@ -346,6 +347,18 @@ impl CommittedState {
         self.tables.get(&table_id)?.index_seek(&self.blob_store, cols, range)
     }
 
+    /// Returns an iterator over the keys of the index on exactly `cols` of the table `table_id`
+    /// matching `range`, yielding a key once for every row with it,
+    /// or `None` if there's no such index.
+    pub fn index_seek_keys<'a>(
+        &'a self,
+        table_id: TableId,
+        cols: &ColList,
+        range: &impl RangeBounds<AlgebraicValue>,
+    ) -> Option<BTreeIndexKeyIter<'a>> {
+        self.tables.get(&table_id)?.index_seek_keys(cols, range)
+    }
+
     // TODO(perf, deep-integration): Make this method `unsafe`. Add the following to the docs:
     //
     // # Safety
@@ -377,6 +390,15 @@ impl CommittedState {
 
         self.next_tx_offset += 1;
 
+        // The tables whose size may change by applying the transaction.
+        let changed_tables = tx_state
+            .delete_tables
+            .keys()
+            .merge(tx_state.insert_tables.keys())
+            .dedup()
+            .copied()
+            .collect::<Vec<_>>();
+
         // First, apply deletes. This will free up space in the committed tables.
         self.merge_apply_deletes(&mut tx_data, tx_state.delete_tables, ctx);
 
@@ -385,9 +407,21 @@ impl CommittedState {
 
         self.merge_apply_inserts(&mut tx_data, tx_state.insert_tables, tx_state.blob_store, ctx);
 
+        self.record_table_sizes(ctx, changed_tables);
+
         tx_data
     }
 
+    /// Updates the size gauges of the tables identified by `table_ids`.
+    fn record_table_sizes(&self, ctx: &ExecutionContext, table_ids: impl IntoIterator<Item = TableId>) {
+        for table_id in table_ids {
+            if let Some(table) = self.tables.get(&table_id) {
+                let bytes = table.bytes_occupied() as u64;
+                record_table_size(ctx.database(), table_id, &table.get_schema().table_name, bytes);
+            }
+        }
+    }
+
     fn merge_apply_deletes(
         &mut self,
         tx_data: &mut TxData,
@@ -501,6 +535,28 @@ impl CommittedState {
         self.tables.get(&table_id)
     }
 
+    /// Returns the number of distinct values in each column of the table `table_id`
+    /// that has an index on it alone, as counted by the keys of that index.
+    ///
+    /// Columns without such an index, and every column of an unknown table, are left out.
+    pub fn column_stats(&self, table_id: TableId) -> ColumnStatsMap {
+        let Some(table) = self.get_table(table_id) else {
+            return ColumnStatsMap::new();
+        };
+        table
+            .indexes
+            .iter()
+            .filter(|(cols, _)| cols.is_singleton())
+            .map(|(cols, index)| {
+                let stats = ColumnStats {
+                    distinct_count: Some(index.num_keys()),
+                    ..ColumnStats::default()
+                };
+                (cols.head(), stats)
+            })
+            .collect()
+    }
+
     pub fn get_table_mut(&mut self, table_id: TableId) -> Option<&mut Table> {
         self.tables.get_mut(&table_id)
     }
@@ -18,10 +18,10 @@ use crate::{
                 TxData, TxDatastore,
             },
         },
-        db_metrics::{DB_METRICS, MAX_TX_CPU_TIME},
+        db_metrics::{record_row_churn, record_rows_touched, DB_METRICS, MAX_TX_CPU_TIME},
     },
     error::DBError,
-    execution_context::ExecutionContext,
+    execution_context::{ExecutionContext, MetricType},
 };
 use anyhow::{anyhow, Context};
 use parking_lot::{Mutex, RwLock};
@@ -30,6 +30,7 @@ use spacetimedb_primitives::{ColList, ConstraintId, IndexId, SequenceId, TableId
 use spacetimedb_sats::db::def::{IndexDef, SequenceDef, TableDef, TableSchema};
 use spacetimedb_sats::{bsatn, buffer::BufReader, hash::Hash, AlgebraicValue, ProductValue};
 use spacetimedb_table::{indexes::RowPointer, table::RowRef};
+use std::collections::BTreeMap;
 use std::sync::Arc;
 use std::time::Instant;
 use std::{borrow::Cow, time::Duration};
@@ -394,7 +395,7 @@ impl MutTxDatastore for Locking {
 pub(crate) fn record_metrics(ctx: &ExecutionContext, tx_timer: Instant, lock_wait_time: Duration, committed: bool) {
     let workload = &ctx.workload();
     let db = &ctx.database();
-    let reducer = ctx.reducer_name();
+    let reducer = ctx.reducer_label();
     let elapsed_time = tx_timer.elapsed();
     let cpu_time = elapsed_time - lock_wait_time;
 
@@ -432,6 +433,46 @@ pub(crate) fn record_metrics(ctx: &ExecutionContext, tx_timer: Instant, lock_wai
         .set(max_cpu_time);
 }
 
+/// Records, per table, the ratio of rows deleted to rows inserted by `tx_data`,
+/// if it is the transaction of a reducer.
+fn record_row_churn_metrics(ctx: &ExecutionContext, tx_data: &TxData) {
+    if ctx.reducer_context().is_none() {
+        return;
+    }
+    let mut churn = BTreeMap::<_, (_, u64, u64)>::new();
+    for (table_id, table_name, rows) in tx_data.inserts_with_table_name() {
+        churn.entry(*table_id).or_insert((table_name, 0, 0)).1 += rows.len() as u64;
+    }
+    for (table_id, table_name, rows) in tx_data.deletes_with_table_name() {
+        churn.entry(*table_id).or_insert((table_name, 0, 0)).2 += rows.len() as u64;
+    }
+    for (table_id, (table_name, inserted, deleted)) in churn {
+        record_row_churn(
+            ctx.database(),
+            ctx.reducer_label(),
+            table_id,
+            table_name,
+            inserted,
+            deleted,
+        );
+    }
+}
+
+/// Records the number of rows inserted, deleted, and fetched by the committed transaction of `ctx`.
+fn record_rows_touched_metrics(ctx: &ExecutionContext) {
+    let metrics = ctx.metrics.read();
+    let rows = [
+        MetricType::RowsInserted,
+        MetricType::RowsDeleted,
+        MetricType::RowsFetched,
+    ]
+    .into_iter()
+    .map(|ty| metrics.total(ty))
+    .sum();
+    drop(metrics);
+    record_rows_touched(ctx.workload(), ctx.database(), ctx.reducer_label(), rows);
+}
+
 impl MutTx for Locking {
     type MutTx = MutTxId;
 
@@ -468,6 +509,8 @@ impl MutTx for Locking {
         let timer = tx.timer;
         // TODO(cloutiertyler): We should probably track the tx.commit() time separately.
         let res = tx.commit(ctx);
+        record_row_churn_metrics(ctx, &res);
+        record_rows_touched_metrics(ctx);
 
         // Record metrics for the transaction at the very end right before we drop
         // the MutTx and release the lock.
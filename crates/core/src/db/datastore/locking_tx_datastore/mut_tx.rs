@@ -32,6 +32,7 @@ use spacetimedb_sats::{
         },
         error::SchemaErrors,
     },
+    relation::ColumnStatsMap,
     AlgebraicValue, ProductType, ProductValue,
 };
 use spacetimedb_table::{
@@ -680,6 +681,14 @@ impl MutTxId {
         })
     }
 
+    /// Returns statistics about the columns of the table `table_id`, as committed,
+    /// for the optimizer to consult when choosing among its indices.
+    ///
+    /// The rows inserted or deleted by this transaction aren't counted.
+    pub fn column_stats(&self, table_id: TableId) -> ColumnStatsMap {
+        self.committed_state_write_lock.column_stats(table_id)
+    }
+
     pub fn commit(self, ctx: &ExecutionContext) -> TxData {
         let Self {
             mut committed_state_write_lock,
@@ -7,7 +7,8 @@ use super::{
 };
 use crate::execution_context::ExecutionContext;
 use spacetimedb_primitives::{ColList, TableId};
-use spacetimedb_sats::{db::def::TableSchema, AlgebraicValue};
+use spacetimedb_sats::{db::def::TableSchema, relation::ColumnStatsMap, AlgebraicValue};
+use spacetimedb_table::btree_index::BTreeIndexKeyIter;
 use std::sync::Arc;
 use std::{
     ops::RangeBounds,
@@ -59,6 +60,24 @@ impl StateView for TxId {
 }
 
 impl TxId {
+    /// Returns an iterator over the keys of the index on exactly `cols` of the table `table_id`
+    /// matching `range`, yielding a key once for every row with it, without reading the rows,
+    /// or `None` if there's no such index.
+    pub fn index_keys_by_col_range<'a>(
+        &'a self,
+        table_id: TableId,
+        cols: &ColList,
+        range: &impl RangeBounds<AlgebraicValue>,
+    ) -> Option<BTreeIndexKeyIter<'a>> {
+        self.committed_state_shared_lock.index_seek_keys(table_id, cols, range)
+    }
+
+    /// Returns statistics about the columns of the table `table_id`, as committed,
+    /// for the optimizer to consult when choosing among its indices.
+    pub fn column_stats(&self, table_id: TableId) -> ColumnStatsMap {
+        self.committed_state_shared_lock.column_stats(table_id)
+    }
+
     pub(crate) fn release(self, ctx: &ExecutionContext) {
         record_metrics(ctx, self.timer, self.lock_wait_time, true);
     }
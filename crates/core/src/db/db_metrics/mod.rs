@@ -1,11 +1,14 @@
 use crate::execution_context::WorkloadType;
 use once_cell::sync::Lazy;
+use prometheus::core::Collector;
+use prometheus::proto::{Metric, MetricFamily};
 use prometheus::{GaugeVec, HistogramVec, IntCounterVec, IntGaugeVec};
-use spacetimedb_data_structures::map::HashMap;
+use spacetimedb_data_structures::map::{HashMap, HashSet};
 use spacetimedb_lib::Address;
-use spacetimedb_metrics::metrics_group;
+use spacetimedb_metrics::{metrics_group, OVERFLOW_LABEL};
 use spacetimedb_primitives::TableId;
-use std::sync::Mutex;
+use std::sync::{Mutex, RwLock};
+use std::time::Duration;
 
 metrics_group!(
     #[non_exhaustive]
@@ -15,6 +18,11 @@ metrics_group!(
         #[labels(db: Address, table_id: u32, table_name: str)]
         pub rdb_num_table_rows: IntGaugeVec,
 
+        #[name = spacetime_table_size_bytes]
+        #[help = "The approximate number of bytes occupied by a table, including its pages and its large blobs"]
+        #[labels(db: Address, table_id: u32, table_name: str)]
+        pub rdb_table_size: IntGaugeVec,
+
         #[name = spacetime_num_rows_inserted_cumulative]
         #[help = "The cumulative number of rows inserted into a table"]
         #[labels(txn_type: WorkloadType, db: Address, reducer_or_query: str, table_id: u32, table_name: str)]
@@ -25,6 +33,11 @@ metrics_group!(
         #[labels(txn_type: WorkloadType, db: Address, reducer_or_query: str, table_id: u32, table_name: str)]
         pub rdb_num_rows_deleted: IntCounterVec,
 
+        #[name = spacetime_row_churn_ratio]
+        #[help = "The ratio of rows deleted to rows inserted in a table by the most recent transaction of a reducer"]
+        #[labels(db: Address, reducer: str, table_id: u32, table_name: str)]
+        pub rdb_row_churn_ratio: GaugeVec,
+
         #[name = spacetime_num_rows_fetched_cumulative]
         #[help = "The cumulative number of rows fetched from a table"]
         #[labels(txn_type: WorkloadType, db: Address, reducer_or_query: str, table_id: u32, table_name: str)]
@@ -40,11 +53,37 @@ metrics_group!(
         #[labels(txn_type: WorkloadType, db: Address, reducer_or_query: str, table_id: u32, table_name: str)]
         pub rdb_num_index_seeks: IntCounterVec,
 
+        #[name = spacetime_empty_index_scans_cumulative]
+        #[help = "The cumulative number of index scans of a table that completed without fetching any rows"]
+        #[labels(db: Address, table_id: u32, table_name: str)]
+        pub rdb_num_empty_index_scans: IntCounterVec,
+
+        #[name = spacetime_num_seq_scans_cumulative]
+        #[help = "The cumulative number of filters on a table that the optimizer could not serve by an index, i.e., that fall back to a sequential scan"]
+        #[labels(txn_type: WorkloadType, db: Address, reducer_or_query: str, table_id: u32, table_name: str)]
+        pub rdb_num_seq_scans: IntCounterVec,
+
+        #[name = spacetime_num_blob_fetches_cumulative]
+        #[help = "The cumulative number of large blobs fetched from the blob store to serialize the rows of a table"]
+        #[labels(txn_type: WorkloadType, db: Address, table_id: u32, table_name: str)]
+        pub rdb_num_blob_fetches: IntCounterVec,
+
+        #[name = spacetime_full_table_scans_total]
+        #[help = "The cumulative number of sequential scans of a table, i.e., scans not using an index"]
+        #[labels(db: Address, table_name: str, reducer_or_query: str)]
+        pub rdb_num_full_table_scans: IntCounterVec,
+
         #[name = spacetime_num_txns_cumulative]
         #[help = "The cumulative number of transactions, including both commits and rollbacks"]
         #[labels(txn_type: WorkloadType, db: Address, reducer: str, committed: bool)]
         pub rdb_num_txns: IntCounterVec,
 
+        #[name = spacetime_rows_touched_per_txn]
+        #[help = "The number of rows inserted, deleted, and fetched by a committed transaction"]
+        #[labels(txn_type: WorkloadType, db: Address, reducer: str)]
+        #[buckets(1.0, 10.0, 100.0, 1_000.0, 10_000.0, 100_000.0, 1_000_000.0)]
+        pub rdb_rows_touched_per_txn: HistogramVec,
+
         #[name = spacetime_txn_elapsed_time_sec]
         #[help = "The total elapsed (wall) time of a transaction (in seconds)"]
         #[labels(txn_type: WorkloadType, db: Address, reducer: str)]
@@ -66,6 +105,19 @@ metrics_group!(
         #[labels(txn_type: WorkloadType, db: Address, reducer: str)]
         pub rdb_txn_cpu_time_sec_max: GaugeVec,
 
+        #[name = spacetime_query_compile_time_sec]
+        #[help = "The time spent compiling a query (in seconds)"]
+        #[labels(db: Address, txn_type: WorkloadType)]
+        #[buckets(
+            1e-6, 5e-6, 1e-5, 5e-5, 1e-4, 5e-4, 1e-3, 0.005, 0.01, 0.025, 0.05, 0.1, 0.25, 0.5, 1.0, 2.5, 5.0, 10.0
+        )]
+        pub rdb_query_compile_time_sec: HistogramVec,
+
+        #[name = spacetime_query_compile_time_sec_max]
+        #[help = "The time spent compiling the slowest query (in seconds)"]
+        #[labels(db: Address, txn_type: WorkloadType)]
+        pub rdb_query_compile_time_sec_max: GaugeVec,
+
         #[name = spacetime_message_log_size_bytes]
         #[help = "For a given database, the number of bytes occupied by its message log"]
         #[labels(db: Address)]
@@ -75,20 +127,293 @@ metrics_group!(
         #[help = "For a given module, the size of its log file (in bytes)"]
         #[labels(db: Address)]
         pub module_log_file_size: IntGaugeVec,
+
+        #[name = spacetime_query_plan_changed_total]
+        #[help = "The cumulative number of times the optimizer planned a query differently than the last time it planned it"]
+        #[labels(db: Address)]
+        pub query_plan_changed: IntCounterVec,
+
+        #[name = spacetime_query_plan_cache_total]
+        #[help = "The cumulative number of times a compiled query plan was looked up for reuse, by whether it was found"]
+        #[labels(db: Address, result: str)]
+        pub query_plan_cache: IntCounterVec,
+
+        #[state]
+        reducer_filter: RwLock<ReducerLabelFilter>,
     }
 );
 
+/// Configures the buckets of the transaction timing histograms of [`DbMetrics`],
+/// e.g., finer sub-millisecond buckets for low-latency databases,
+/// or coarser ones for databases running long reducers.
+///
+/// The default buckets span 1µs to 10s.
+#[derive(Debug, Clone, PartialEq)]
+pub struct MetricsConfig {
+    /// The upper bounds, in seconds, of the buckets of [`DbMetrics::rdb_txn_elapsed_time_sec`].
+    pub txn_elapsed_time_buckets: Vec<f64>,
+    /// The upper bounds, in seconds, of the buckets of [`DbMetrics::rdb_txn_cpu_time_sec`].
+    pub txn_cpu_time_buckets: Vec<f64>,
+}
+
+impl Default for MetricsConfig {
+    fn default() -> Self {
+        Self {
+            txn_elapsed_time_buckets: RdbTxnElapsedTimeSecHistogramVec::default_buckets(),
+            txn_cpu_time_buckets: RdbTxnCpuTimeSecHistogramVec::default_buckets(),
+        }
+    }
+}
+
+/// The buckets of a histogram in a [`MetricsConfig`] are empty or not strictly increasing.
+#[derive(thiserror::Error, Debug, PartialEq)]
+#[error("The buckets of `{metric}` should be non-empty and strictly increasing, but are {buckets:?}")]
+pub struct InvalidBuckets {
+    pub metric: &'static str,
+    pub buckets: Vec<f64>,
+}
+
+impl DbMetrics {
+    /// Returns metrics whose transaction timing histograms use the buckets of `config`.
+    pub fn with_config(config: &MetricsConfig) -> Result<Self, InvalidBuckets> {
+        let histograms = [
+            ("rdb_txn_elapsed_time_sec", &config.txn_elapsed_time_buckets),
+            ("rdb_txn_cpu_time_sec", &config.txn_cpu_time_buckets),
+        ];
+        for (metric, buckets) in histograms {
+            if buckets.is_empty() || !buckets.windows(2).all(|pair| pair[0] < pair[1]) {
+                return Err(InvalidBuckets {
+                    metric,
+                    buckets: buckets.clone(),
+                });
+            }
+        }
+
+        Ok(Self::with_buckets(|field| {
+            histograms
+                .iter()
+                .find(|(metric, _)| *metric == field)
+                .map(|(_, buckets)| buckets.to_vec())
+        }))
+    }
+}
+
+/// Selects the reducers whose names [`DbMetrics`] uses as the values of `reducer` labels,
+/// bounding the number of series when reducers are numerous or named after user input.
+/// All other reducers are recorded under [`OVERFLOW_LABEL`].
+#[derive(Debug, Clone, Default, PartialEq)]
+pub enum ReducerLabelFilter {
+    /// Keep the names of all reducers.
+    #[default]
+    All,
+    /// Keep only the names in the set.
+    Allow(HashSet<String>),
+    /// Keep all names but those in the set.
+    Deny(HashSet<String>),
+}
+
+impl ReducerLabelFilter {
+    /// Returns whether the name of `reducer` is kept as the value of a `reducer` label.
+    pub fn keeps(&self, reducer: &str) -> bool {
+        match self {
+            Self::All => true,
+            Self::Allow(names) => names.contains(reducer),
+            Self::Deny(names) => !names.contains(reducer),
+        }
+    }
+}
+
+impl DbMetrics {
+    /// Returns these metrics, with the `reducer` labels of the reducers `filter` doesn't keep
+    /// recorded as [`OVERFLOW_LABEL`].
+    pub fn with_reducer_filter(self, filter: ReducerLabelFilter) -> Self {
+        self.set_reducer_filter(filter);
+        self
+    }
+
+    /// Replaces the filter of the `reducer` labels of these metrics by `filter`.
+    ///
+    /// This affects only the modules loaded from now on,
+    /// as the labels of a module's reducers are resolved when it is loaded;
+    /// a series already recorded under the name of a reducer remains.
+    pub fn set_reducer_filter(&self, filter: ReducerLabelFilter) {
+        *self.reducer_filter.write().unwrap() = filter;
+    }
+
+    /// Resolves the values of the `reducer` labels of a module's `reducers`,
+    /// and of the `reducer_or_query` labels of their transactions.
+    ///
+    /// Called once, when the module is loaded,
+    /// so that recording a metric doesn't consult the filter.
+    pub fn reducer_labels<'a>(&self, reducers: impl IntoIterator<Item = &'a str>) -> ReducerLabels {
+        let filter = self.reducer_filter.read().unwrap();
+        let kept = reducers.into_iter().filter(|reducer| filter.keeps(reducer));
+        ReducerLabels {
+            kept: kept.map(Into::into).collect(),
+        }
+    }
+}
+
+/// The values of the `reducer` labels of the reducers of a module,
+/// as resolved by [`DbMetrics::reducer_labels`].
+///
+/// Every metric labeled by a reducer must be recorded with the value [`ReducerLabels::get`] returns.
+#[derive(Debug, Clone, Default)]
+pub struct ReducerLabels {
+    /// The names of the reducers which are kept as the values of `reducer` labels.
+    kept: HashSet<Box<str>>,
+}
+
+impl ReducerLabels {
+    /// Returns whether `reducer` is recorded under [`OVERFLOW_LABEL`] rather than its name.
+    ///
+    /// A name which isn't that of a reducer of the module, e.g., of a call to a nonexistent reducer, is too.
+    /// The empty name of a transaction not run by a reducer never is.
+    pub fn overflows(&self, reducer: &str) -> bool {
+        !reducer.is_empty() && !self.kept.contains(reducer)
+    }
+
+    /// Returns the value of the `reducer` label for `reducer`,
+    /// which is [`OVERFLOW_LABEL`] unless the filter kept its name.
+    pub fn get<'a>(&self, reducer: &'a str) -> &'a str {
+        if self.overflows(reducer) {
+            OVERFLOW_LABEL
+        } else {
+            reducer
+        }
+    }
+}
+
+/// Identifies a single metric by its family name and its label values.
+type MetricKey = (String, Vec<(String, String)>);
+
+fn metric_key(family: &MetricFamily, metric: &Metric) -> MetricKey {
+    let labels = metric
+        .get_label()
+        .iter()
+        .map(|label| (label.get_name().to_owned(), label.get_value().to_owned()))
+        .collect();
+    (family.get_name().to_owned(), labels)
+}
+
+/// The values of all [`DbMetrics`] at some point in time.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct MetricsSnapshot {
+    metrics: HashMap<MetricKey, Metric>,
+}
+
+impl MetricsSnapshot {
+    fn new(families: &[MetricFamily]) -> Self {
+        let metrics = families
+            .iter()
+            .flat_map(|family| {
+                family
+                    .get_metric()
+                    .iter()
+                    .map(move |m| (metric_key(family, m), m.clone()))
+            })
+            .collect();
+        Self { metrics }
+    }
+
+    /// Returns whether `metric` of `family` has the same value in this snapshot.
+    fn is_unchanged(&self, family: &MetricFamily, metric: &Metric) -> bool {
+        self.metrics.get(&metric_key(family, metric)) == Some(metric)
+    }
+}
+
+/// The [`DbMetrics`] that changed since a [`MetricsSnapshot`] was taken.
+#[derive(Debug, Clone)]
+pub struct MetricsDelta {
+    /// The changed metrics, grouped by family.
+    /// Families without changes are omitted.
+    pub families: Vec<MetricFamily>,
+    /// A snapshot of all metrics, to compare the next delta against.
+    pub snapshot: MetricsSnapshot,
+}
+
+impl DbMetrics {
+    /// Returns a snapshot of the current values of all metrics.
+    pub fn snapshot(&self) -> MetricsSnapshot {
+        MetricsSnapshot::new(&self.collect())
+    }
+
+    /// Returns only the metrics whose values changed since `last` was taken,
+    /// which is cheaper to serialize in a tight internal telemetry loop.
+    ///
+    /// This is a best-effort optimization.
+    /// A metric is considered changed if its value differs in any way from `last`,
+    /// and a full scrape via [`Collector::collect`] remains available.
+    pub fn changed_since(&self, last: &MetricsSnapshot) -> MetricsDelta {
+        let mut families = self.collect();
+        let snapshot = MetricsSnapshot::new(&families);
+        for family in &mut families {
+            let mut metrics = family.take_metric().into_vec();
+            metrics.retain(|metric| !last.is_unchanged(family, metric));
+            family.set_metric(metrics.into());
+        }
+        families.retain(|family| !family.get_metric().is_empty());
+        MetricsDelta { families, snapshot }
+    }
+}
+
 type ReducerLabel = (Address, WorkloadType, String);
-type AddressLabel = (Address, WorkloadType);
 
 pub static MAX_TX_CPU_TIME: Lazy<Mutex<HashMap<ReducerLabel, f64>>> = Lazy::new(|| Mutex::new(HashMap::new()));
-pub static MAX_QUERY_COMPILE_TIME: Lazy<Mutex<HashMap<AddressLabel, f64>>> = Lazy::new(|| Mutex::new(HashMap::new()));
+pub static MAX_QUERY_COMPILE_TIME: Lazy<Mutex<HashMap<(Address, WorkloadType), f64>>> =
+    Lazy::new(|| Mutex::new(HashMap::new()));
 pub static DB_METRICS: Lazy<DbMetrics> = Lazy::new(DbMetrics::new);
 
+/// The maximum number of queries whose last plan is remembered by [`record_query_plan`].
+const MAX_QUERY_PLANS: usize = 10_000;
+
+/// The hash of the last plan the optimizer produced for a query,
+/// keyed by database and the hash of the query before optimization.
+static LAST_QUERY_PLANS: Lazy<Mutex<HashMap<(Address, u64), u64>>> = Lazy::new(|| Mutex::new(HashMap::new()));
+
+/// Resets all the state tracking a maximum, i.e., both the `*_max` gauges and the maps they are fed from,
+/// so that a maximum recorded before the reset is never reported after it.
+///
+/// Any new maximum, e.g., one in the style of [`MAX_TX_CPU_TIME`], must be reset here as well.
 pub fn reset_counters() {
     // Reset max reducer durations
     DB_METRICS.rdb_txn_cpu_time_sec_max.0.reset();
     MAX_TX_CPU_TIME.lock().unwrap().clear();
+    // Reset max query compile durations,
+    // under the lock that `record_query_compile_time` sets the gauge under.
+    let mut max_compile_time = MAX_QUERY_COMPILE_TIME.lock().unwrap();
+    DB_METRICS.rdb_query_compile_time_sec_max.0.reset();
+    max_compile_time.clear();
+}
+
+/// Returns the total cpu time, in seconds, of the transactions of `db` per workload type,
+/// summed over the [`DbMetrics::rdb_txn_cpu_time_sec`] of all of its reducers and queries.
+///
+/// The sums are read from the histograms as collected for a scrape,
+/// so they match what Prometheus would see at the same point in time.
+/// Workload types without any transactions are omitted.
+pub fn workload_cpu_summary(db: Address) -> HashMap<WorkloadType, f64> {
+    let db = db.to_hex();
+    let mut summary = HashMap::new();
+    for family in DB_METRICS.rdb_txn_cpu_time_sec.collect() {
+        for metric in family.get_metric() {
+            let label = |name: &str| {
+                metric
+                    .get_label()
+                    .iter()
+                    .find(|label| label.get_name() == name)
+                    .map(|label| label.get_value())
+            };
+            if label("db") != Some(db.as_str()) {
+                continue;
+            }
+            let Some(workload) = label("txn_type").and_then(|workload| workload.parse().ok()) else {
+                continue;
+            };
+            *summary.entry(workload).or_default() += metric.get_histogram().get_sample_sum();
+        }
+    }
+    summary
 }
 
 /// Returns the number of committed rows in the table named by `table_name` and identified by `table_id` in the database `db_address`.
@@ -98,3 +423,466 @@ pub fn table_num_rows(db_address: Address, table_id: TableId, table_name: &str)
         .with_label_values(&db_address, &table_id.0, table_name)
         .get() as _
 }
+
+/// Records that an index scan of the table named by `table_name` and identified by `table_id`
+/// in the database `db` completed having fetched `num_rows_fetched` rows.
+///
+/// Only the point and range lookups of a `Query::IndexScan` are recorded,
+/// and of those, only the ones that fetched no rows are counted.
+/// Scans the optimizer proved empty, and so never executed, are not recorded at all.
+pub fn record_index_scan(db: Address, table_id: TableId, table_name: &str, num_rows_fetched: u64) {
+    if num_rows_fetched == 0 {
+        DB_METRICS
+            .rdb_num_empty_index_scans
+            .with_label_values(&db, &table_id.0, table_name)
+            .inc();
+    }
+}
+
+/// Records that the table named by `table_name` and identified by `table_id` in the database `db`
+/// occupies approximately `bytes` bytes, including both its pages and its large blobs.
+pub fn record_table_size(db: Address, table_id: TableId, table_name: &str, bytes: u64) {
+    DB_METRICS
+        .rdb_table_size
+        .with_label_values(&db, &table_id.0, table_name)
+        .set(bytes as _);
+}
+
+/// Records that serializing rows of the table named by `table_name` and identified by `table_id`
+/// in the database `db` fetched `fetches` large blobs from the blob store.
+///
+/// Only rows with var-len members stored as large blobs require such fetches,
+/// see [`RowRef::num_large_blobs`](spacetimedb_table::table::RowRef::num_large_blobs).
+pub fn record_blob_fetches(workload: WorkloadType, db: Address, table_id: TableId, table_name: &str, fetches: u64) {
+    if fetches > 0 {
+        DB_METRICS
+            .rdb_num_blob_fetches
+            .with_label_values(&workload, &db, &table_id.0, table_name)
+            .inc_by(fetches);
+    }
+}
+
+/// Records that compiling a query of the `workload` in the database `db` took `elapsed`.
+pub fn record_query_compile_time(db: Address, workload: WorkloadType, elapsed: Duration) {
+    let elapsed = elapsed.as_secs_f64();
+    DB_METRICS
+        .rdb_query_compile_time_sec
+        .with_label_values(&db, &workload)
+        .observe(elapsed);
+
+    // Set the gauge while still holding the lock,
+    // so that a concurrent, smaller maximum can't overwrite it.
+    let mut guard = MAX_QUERY_COMPILE_TIME.lock().unwrap();
+    let max = guard.entry((db, workload)).or_insert(elapsed);
+    if elapsed >= *max {
+        *max = elapsed;
+        DB_METRICS
+            .rdb_query_compile_time_sec_max
+            .with_label_values(&db, &workload)
+            .set(elapsed);
+    }
+}
+
+/// Returns the ratio of `deleted` to `inserted` rows,
+/// or just `deleted` if no rows were inserted.
+fn row_churn_ratio(inserted: u64, deleted: u64) -> f64 {
+    if inserted == 0 {
+        deleted as f64
+    } else {
+        deleted as f64 / inserted as f64
+    }
+}
+
+/// Records that a committed transaction of the `workload` and `reducer` in the database `db`
+/// inserted, deleted, and fetched `rows` rows in total,
+/// e.g., to find reducers that occasionally process huge batches.
+///
+/// `reducer` is the value of the `reducer` label; see [`ReducerLabels`].
+pub fn record_rows_touched(workload: WorkloadType, db: Address, reducer: &str, rows: u64) {
+    DB_METRICS
+        .rdb_rows_touched_per_txn
+        .with_label_values(&workload, &db, reducer)
+        .observe(rows as f64);
+}
+
+/// Records that the most recent transaction of `reducer` in the database `db`
+/// inserted `inserted` and deleted `deleted` rows
+/// in the table named by `table_name` and identified by `table_id`.
+///
+/// `reducer` is the value of the `reducer` label; see [`ReducerLabels`].
+pub fn record_row_churn(db: Address, reducer: &str, table_id: TableId, table_name: &str, inserted: u64, deleted: u64) {
+    DB_METRICS
+        .rdb_row_churn_ratio
+        .with_label_values(&db, reducer, &table_id.0, table_name)
+        .set(row_churn_ratio(inserted, deleted));
+}
+
+/// Records that the optimizer planned the query hashing to `query` in the database `db`
+/// as the plan hashing to `plan`.
+///
+/// If the query was last planned differently,
+/// e.g., because the table statistics changed,
+/// this increments [`DbMetrics::query_plan_changed`] and returns `true`.
+pub fn record_query_plan(db: Address, query: u64, plan: u64) -> bool {
+    let mut plans = LAST_QUERY_PLANS.lock().unwrap();
+    // Forget all plans rather than grow without bound,
+    // which at worst misses a plan change until the query is planned again.
+    if plans.len() >= MAX_QUERY_PLANS && !plans.contains_key(&(db, query)) {
+        plans.clear();
+    }
+    let changed = plans.insert((db, query), plan).is_some_and(|last| last != plan);
+    if changed {
+        DB_METRICS.query_plan_changed.with_label_values(&db).inc();
+    }
+    changed
+}
+
+/// Records that a compiled plan for a query in the database `db` was looked up for reuse,
+/// and was either found, if `hit`, or had to be compiled anew.
+pub fn record_plan_cache(db: Address, hit: bool) {
+    let result = if hit { "hit" } else { "miss" };
+    DB_METRICS.query_plan_cache.with_label_values(&db, result).inc();
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use prometheus::core::Metric as _;
+
+    #[test]
+    fn delta_omits_unchanged_metrics() {
+        let metrics = DbMetrics::new();
+        let [db_1, db_2] = [1, 2].map(Address::from_u128);
+        metrics.message_log_size.with_label_values(&db_1).set(10);
+        metrics.message_log_size.with_label_values(&db_2).set(20);
+        let last = metrics.snapshot();
+
+        metrics.message_log_size.with_label_values(&db_2).set(30);
+        let delta = metrics.changed_since(&last);
+        let [family] = &*delta.families else {
+            panic!("expected a single changed family, but found {:?}", delta.families);
+        };
+        assert_eq!(family.get_name(), "spacetime_message_log_size_bytes");
+        let [metric] = family.get_metric() else {
+            panic!("expected a single changed metric, but found {:?}", family.get_metric());
+        };
+        assert_eq!(metric.get_label()[0].get_value(), db_2.to_hex().as_str());
+        assert_eq!(metric.get_gauge().get_value(), 30.0);
+
+        // Nothing changed since the last delta.
+        assert!(metrics.changed_since(&delta.snapshot).families.is_empty());
+    }
+
+    #[test]
+    fn query_compile_time_buckets() {
+        let db = Address::from_u128(5);
+        record_query_compile_time(db, WorkloadType::Subscribe, Duration::from_micros(300));
+
+        let histogram = DB_METRICS
+            .rdb_query_compile_time_sec
+            .with_label_values(&db, &WorkloadType::Subscribe)
+            .metric();
+        let histogram = histogram.get_histogram();
+        assert_eq!(histogram.get_sample_count(), 1);
+        // 300us lands in the `(1e-4, 5e-4]` bucket.
+        let count_le = |upper_bound: f64| {
+            let bucket = histogram
+                .get_bucket()
+                .iter()
+                .find(|bucket| bucket.get_upper_bound() == upper_bound)
+                .unwrap();
+            bucket.get_cumulative_count()
+        };
+        assert_eq!(count_le(1e-4), 0);
+        assert_eq!(count_le(5e-4), 1);
+
+        // Other workloads are observed separately.
+        let sql = DB_METRICS
+            .rdb_query_compile_time_sec
+            .with_label_values(&db, &WorkloadType::Sql);
+        assert_eq!(sql.get_sample_count(), 0);
+    }
+
+    #[test]
+    fn table_size_per_table() {
+        let db = Address::from_u128(6);
+        record_table_size(db, TableId(7), "small", 100);
+        record_table_size(db, TableId(8), "large", 1 << 20);
+        // The latest size replaces the previous one.
+        record_table_size(db, TableId(7), "small", 200);
+
+        let size =
+            |table_id: u32, table_name: &str| DB_METRICS.rdb_table_size.with_label_values(&db, &table_id, table_name);
+        assert_eq!(size(7, "small").get(), 200);
+        assert_eq!(size(8, "large").get(), 1 << 20);
+
+        let labels = size(7, "small").metric();
+        let labels = labels
+            .get_label()
+            .iter()
+            .map(|label| (label.get_name(), label.get_value()))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            labels,
+            [("db", db.to_hex().as_str()), ("table_id", "7"), ("table_name", "small")]
+        );
+    }
+
+    #[test]
+    fn txn_time_buckets_from_config() {
+        let config = MetricsConfig {
+            txn_elapsed_time_buckets: vec![1e-5, 1e-4, 1e-3],
+            ..MetricsConfig::default()
+        };
+        let metrics = DbMetrics::with_config(&config).unwrap();
+        let db = Address::from_u128(11);
+
+        let elapsed = metrics
+            .rdb_txn_elapsed_time_sec
+            .with_label_values(&WorkloadType::Reducer, &db, "reducer");
+        elapsed.observe(5e-5);
+        elapsed.observe(2.0);
+        let elapsed = elapsed.metric();
+        let buckets = elapsed.get_histogram().get_bucket();
+        let bounds: Vec<_> = buckets.iter().map(|bucket| bucket.get_upper_bound()).collect();
+        assert_eq!(bounds, [1e-5, 1e-4, 1e-3]);
+        let counts: Vec<_> = buckets.iter().map(|bucket| bucket.get_cumulative_count()).collect();
+        assert_eq!(counts, [0, 1, 1]);
+        assert_eq!(elapsed.get_histogram().get_sample_count(), 2);
+
+        // The cpu time keeps its default buckets.
+        let cpu = metrics
+            .rdb_txn_cpu_time_sec
+            .with_label_values(&WorkloadType::Reducer, &db, "reducer")
+            .metric();
+        assert_eq!(
+            cpu.get_histogram().get_bucket().len(),
+            MetricsConfig::default().txn_cpu_time_buckets.len()
+        );
+
+        let invalid = MetricsConfig {
+            txn_cpu_time_buckets: vec![1e-3, 1e-3],
+            ..MetricsConfig::default()
+        };
+        assert_eq!(
+            DbMetrics::with_config(&invalid).err(),
+            Some(InvalidBuckets {
+                metric: "rdb_txn_cpu_time_sec",
+                buckets: vec![1e-3, 1e-3],
+            })
+        );
+    }
+
+    #[test]
+    fn blob_fetches_only_for_large_var_len_members() {
+        use spacetimedb_lib::{AlgebraicType, ProductType};
+        use spacetimedb_sats::db::def::{TableDef, TableSchema};
+        use spacetimedb_sats::product;
+        use spacetimedb_table::{
+            blob_store::HashMapBlobStore, indexes::SquashedOffset, table::Table, var_len::VarLenGranule,
+        };
+
+        let db = Address::from_u128(10);
+        let table = |id: u32, ty: AlgebraicType| {
+            let def = TableDef::from_product("", ProductType::from([ty]));
+            Table::new(
+                TableSchema::from_def(id.into(), def).into(),
+                SquashedOffset::COMMITTED_STATE,
+            )
+        };
+        let mut blob_store = HashMapBlobStore::default();
+        let mut fetches = |table: &mut Table, row| {
+            let row_ref = table.insert(&mut blob_store, &row).unwrap().1;
+            let fetches = row_ref.num_large_blobs() as u64;
+            let table_id = table.schema.table_id;
+            record_blob_fetches(WorkloadType::Reducer, db, table_id, "table", fetches);
+            DB_METRICS
+                .rdb_num_blob_fetches
+                .with_label_values(&WorkloadType::Reducer, &db, &table_id.0, "table")
+                .get()
+        };
+
+        // Rows of a fixed-size type are always serialized via the fast path.
+        let mut fixed = table(1, AlgebraicType::U64);
+        assert_eq!(fetches(&mut fixed, product![42u64]), 0);
+
+        // Short strings are stored inline, long ones in the blob store.
+        let mut var_len = table(2, AlgebraicType::String);
+        assert_eq!(fetches(&mut var_len, product!["short"]), 0);
+        let long = "x".repeat(VarLenGranule::OBJECT_SIZE_BLOB_THRESHOLD + 1);
+        assert_eq!(fetches(&mut var_len, product![long]), 1);
+    }
+
+    #[test]
+    fn row_churn_without_inserts() {
+        let db = Address::from_u128(3);
+        let churn = |inserted, deleted| {
+            record_row_churn(db, "reducer", TableId(4), "table", inserted, deleted);
+            DB_METRICS
+                .rdb_row_churn_ratio
+                .with_label_values(&db, "reducer", &4, "table")
+                .get()
+        };
+        assert_eq!(churn(4, 2), 0.5);
+        // Without inserts, the ratio is the number of deletes rather than a division by zero.
+        assert_eq!(churn(0, 3), 3.0);
+        assert_eq!(churn(0, 0), 0.0);
+    }
+
+    #[test]
+    fn reset_counters_resets_all_maxima() {
+        let db = Address::from_u128(13);
+        let reducer = (db, WorkloadType::Reducer, "reducer".to_owned());
+        MAX_TX_CPU_TIME.lock().unwrap().insert(reducer.clone(), 1.0);
+        record_query_compile_time(db, WorkloadType::Sql, Duration::from_millis(5));
+        record_query_compile_time(db, WorkloadType::Sql, Duration::from_millis(1));
+        let max_compile_time = || {
+            DB_METRICS
+                .rdb_query_compile_time_sec_max
+                .with_label_values(&db, &WorkloadType::Sql)
+                .get()
+        };
+        assert_eq!(max_compile_time(), 0.005);
+
+        reset_counters();
+
+        // Other tests may record metrics concurrently, so only check the entries recorded above.
+        assert!(!MAX_TX_CPU_TIME.lock().unwrap().contains_key(&reducer));
+        assert!(!MAX_QUERY_COMPILE_TIME
+            .lock()
+            .unwrap()
+            .contains_key(&(db, WorkloadType::Sql)));
+        assert_eq!(max_compile_time(), 0.0);
+    }
+
+    #[test]
+    fn workload_cpu_summary_per_workload() {
+        let db = Address::from_u128(14);
+        let observe = |workload: WorkloadType, reducer: &str, secs: f64| {
+            DB_METRICS
+                .rdb_txn_cpu_time_sec
+                .with_label_values(&workload, &db, reducer)
+                .observe(secs)
+        };
+        observe(WorkloadType::Reducer, "a", 0.5);
+        observe(WorkloadType::Reducer, "b", 0.25);
+        observe(WorkloadType::Reducer, "b", 0.125);
+        observe(WorkloadType::Subscribe, "query", 2.0);
+        // Other databases aren't included.
+        DB_METRICS
+            .rdb_txn_cpu_time_sec
+            .with_label_values(&WorkloadType::Reducer, &Address::from_u128(15), "a")
+            .observe(8.0);
+
+        let expected = [(WorkloadType::Reducer, 0.875), (WorkloadType::Subscribe, 2.0)];
+        assert_eq!(workload_cpu_summary(db), expected.into_iter().collect());
+    }
+
+    #[test]
+    fn rows_touched_buckets() {
+        let db = Address::from_u128(16);
+        record_rows_touched(WorkloadType::Reducer, db, "batch", 5);
+        record_rows_touched(WorkloadType::Reducer, db, "batch", 10);
+        record_rows_touched(WorkloadType::Reducer, db, "batch", 250_000);
+
+        let histogram = DB_METRICS
+            .rdb_rows_touched_per_txn
+            .with_label_values(&WorkloadType::Reducer, &db, "batch")
+            .metric();
+        let histogram = histogram.get_histogram();
+        assert_eq!(histogram.get_sample_count(), 3);
+        assert_eq!(histogram.get_sample_sum(), 250_015.0);
+        let counts: Vec<_> = histogram
+            .get_bucket()
+            .iter()
+            .map(|bucket| (bucket.get_upper_bound(), bucket.get_cumulative_count()))
+            .collect();
+        assert_eq!(
+            counts,
+            [
+                (1.0, 0),
+                (10.0, 2),
+                (100.0, 2),
+                (1_000.0, 2),
+                (10_000.0, 2),
+                (100_000.0, 2),
+                (1_000_000.0, 3),
+            ]
+        );
+    }
+
+    #[test]
+    fn plan_cache_hits_and_misses() {
+        let db = Address::from_u128(17);
+        record_plan_cache(db, false);
+        record_plan_cache(db, true);
+        record_plan_cache(db, true);
+
+        let count = |result| DB_METRICS.query_plan_cache.with_label_values(&db, result).get();
+        assert_eq!(count("hit"), 2);
+        assert_eq!(count("miss"), 1);
+    }
+    #[test]
+    fn empty_index_scans() {
+        let db = Address::from_u128(19);
+        record_index_scan(db, TableId(1), "hit", 3);
+        record_index_scan(db, TableId(2), "miss", 0);
+
+        let count = |table_id: u32, table_name| {
+            DB_METRICS
+                .rdb_num_empty_index_scans
+                .with_label_values(&db, &table_id, table_name)
+                .get()
+        };
+        assert_eq!(count(1, "hit"), 0);
+        assert_eq!(count(2, "miss"), 1);
+    }
+
+    #[test]
+    fn reducer_labels_outside_allowlist() {
+        let allow = ["a", "b"].map(String::from).into_iter().collect();
+        let metrics = DbMetrics::new().with_reducer_filter(ReducerLabelFilter::Allow(allow));
+        let db = Address::from_u128(18);
+        let labels = metrics.reducer_labels(["a", "b", "c"]);
+        for reducer in ["a", "b", "c"] {
+            let reducer = labels.get(reducer);
+            metrics
+                .rdb_num_txns
+                .with_label_values(&WorkloadType::Reducer, &db, reducer, &true)
+                .inc();
+        }
+
+        let mut reducers: Vec<_> = metrics.rdb_num_txns.collect()[0]
+            .get_metric()
+            .iter()
+            .map(|metric| {
+                let label = metric.get_label().iter().find(|label| label.get_name() == "reducer");
+                label.unwrap().get_value().to_owned()
+            })
+            .collect();
+        reducers.sort();
+        assert_eq!(reducers, ["__other__", "a", "b"]);
+        let txns = |reducer| {
+            metrics
+                .rdb_num_txns
+                .with_label_values(&WorkloadType::Reducer, &db, reducer, &true)
+                .get()
+        };
+        assert_eq!(txns(OVERFLOW_LABEL), 1);
+        assert_eq!(txns("c"), 0);
+
+        // The labels of a module's reducers are resolved once, so a new filter applies to modules loaded later.
+        metrics.set_reducer_filter(ReducerLabelFilter::Deny(["a".to_owned()].into_iter().collect()));
+        assert_eq!(labels.get("a"), "a");
+        // A denylist keeps all other names, as does the empty name of a transaction without a reducer.
+        let labels = metrics.reducer_labels(["a", "c"]);
+        assert_eq!(labels.get("a"), OVERFLOW_LABEL);
+        assert_eq!(labels.get("c"), "c");
+        assert_eq!(labels.get(""), "");
+        // A name which isn't that of a reducer of the module is never kept.
+        assert_eq!(labels.get("d"), OVERFLOW_LABEL);
+
+        // By default, all names are kept.
+        assert_eq!(DbMetrics::new().reducer_labels(["c"]).get("c"), "c");
+    }
+}
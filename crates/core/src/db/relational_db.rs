@@ -1019,6 +1019,7 @@ mod tests {
         system_tables, StConstraintRow, StIndexRow, StSequenceRow, StTableRow, ST_CONSTRAINTS_ID, ST_INDEXES_ID,
         ST_SEQUENCES_ID, ST_TABLES_ID,
     };
+    use crate::db::db_metrics::ReducerLabels;
     use crate::db::relational_db::tests_utils::TestDB;
     use crate::error::IndexError;
     use crate::execution_context::ReducerContext;
@@ -1661,6 +1662,7 @@ mod tests {
                 timestamp,
                 arg_bsatn: Bytes::new(),
             },
+            &ReducerLabels::default(),
         );
 
         let row_ty = ProductType::from([("le_boeuf", AlgebraicType::I32)]);
@@ -1686,6 +1688,7 @@ mod tests {
                         timestamp,
                         arg_bsatn: Bytes::new(),
                     },
+                    &ReducerLabels::default(),
                 ),
                 tx,
             )
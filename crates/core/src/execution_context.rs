@@ -5,18 +5,25 @@ use derive_more::Display;
 use parking_lot::RwLock;
 use spacetimedb_commitlog::{payload::txdata, Varchar};
 use spacetimedb_lib::{Address, Identity};
+use spacetimedb_metrics::OVERFLOW_LABEL;
 use spacetimedb_primitives::TableId;
 use spacetimedb_sats::bsatn;
 
 use crate::util::slow::SlowQueryConfig;
-use crate::{db::db_metrics::DB_METRICS, host::Timestamp};
+use crate::{
+    db::db_metrics::{ReducerLabels, DB_METRICS},
+    host::Timestamp,
+};
 
+#[derive(Clone, Copy)]
 pub enum MetricType {
     IndexSeeks,
     KeysScanned,
     RowsFetched,
     RowsInserted,
     RowsDeleted,
+    FullTableScans,
+    SeqScans,
 }
 
 #[derive(Default, Clone)]
@@ -27,6 +34,8 @@ struct BufferMetric {
     pub rows_fetched: u64,
     pub rows_inserted: u64,
     pub rows_deleted: u64,
+    pub full_table_scans: u64,
+    pub seq_scans: u64,
     pub cache_table_name: String,
 }
 
@@ -48,11 +57,29 @@ impl BufferMetric {
             MetricType::RowsDeleted => {
                 self.rows_deleted += val;
             }
+            MetricType::FullTableScans => {
+                self.full_table_scans += val;
+            }
+            MetricType::SeqScans => {
+                self.seq_scans += val;
+            }
         }
     }
 }
 
 impl BufferMetric {
+    pub fn get(&self, ty: MetricType) -> u64 {
+        match ty {
+            MetricType::IndexSeeks => self.index_seeks,
+            MetricType::KeysScanned => self.keys_scanned,
+            MetricType::RowsFetched => self.rows_fetched,
+            MetricType::RowsInserted => self.rows_inserted,
+            MetricType::RowsDeleted => self.rows_deleted,
+            MetricType::FullTableScans => self.full_table_scans,
+            MetricType::SeqScans => self.seq_scans,
+        }
+    }
+
     pub fn new(table_id: TableId, table_name: String) -> Self {
         Self {
             table_id,
@@ -81,6 +108,11 @@ impl Metrics {
         self.0.iter().any(|x| x.table_id == table_id)
     }
 
+    /// Returns the value of the metric `ty` summed across all tables.
+    pub fn total(&self, ty: MetricType) -> u64 {
+        self.0.iter().map(|metric| metric.get(ty)).sum()
+    }
+
     #[allow(dead_code)]
     fn flush(&mut self, workload: &WorkloadType, database: &Address, reducer: &str) {
         macro_rules! flush_metric {
@@ -105,6 +137,13 @@ impl Metrics {
             flush_metric!(DB_METRICS.rdb_num_rows_fetched, metric, rows_fetched);
             flush_metric!(DB_METRICS.rdb_num_rows_inserted, metric, rows_inserted);
             flush_metric!(DB_METRICS.rdb_num_rows_deleted, metric, rows_deleted);
+            flush_metric!(DB_METRICS.rdb_num_seq_scans, metric, seq_scans);
+            if metric.full_table_scans > 0 {
+                DB_METRICS
+                    .rdb_num_full_table_scans
+                    .with_label_values(database, &metric.cache_table_name, reducer)
+                    .inc_by(metric.full_table_scans);
+            }
         });
     }
 }
@@ -118,6 +157,8 @@ pub struct ExecutionContext {
     database: Address,
     /// The reducer from which the current transaction originated.
     reducer: Option<ReducerContext>,
+    /// Whether the metrics of the transaction are labeled [`OVERFLOW_LABEL`] rather than by the name of `reducer`.
+    reducer_overflows: bool,
     /// The type of workload that is being executed.
     workload: WorkloadType,
     /// The Metrics to be reported for this transaction.
@@ -181,7 +222,7 @@ impl From<&ReducerContext> for txdata::Inputs {
 /// A transaction can be executing a reducer.
 /// It can be used to satisfy a one-off sql query or subscription.
 /// It can also be an internal operation that is not associated with a reducer or sql request.
-#[derive(Clone, Copy, Display, Hash, PartialEq, Eq, strum::AsRefStr)]
+#[derive(Clone, Copy, Debug, Display, Hash, PartialEq, Eq, strum::AsRefStr, strum::EnumString)]
 pub enum WorkloadType {
     Reducer,
     Sql,
@@ -207,15 +248,20 @@ impl ExecutionContext {
         Self {
             database,
             reducer,
+            reducer_overflows: false,
             workload,
             metrics: <_>::default(),
             slow_query_config,
         }
     }
 
-    /// Returns an [ExecutionContext] for a reducer transaction.
-    pub fn reducer(database: Address, ctx: ReducerContext) -> Self {
-        Self::new(database, Some(ctx), WorkloadType::Reducer, Default::default())
+    /// Returns an [ExecutionContext] for a reducer transaction,
+    /// whose metrics are labeled by the reducer as `labels` resolve it.
+    pub fn reducer(database: Address, ctx: ReducerContext, labels: &ReducerLabels) -> Self {
+        let reducer_overflows = labels.overflows(&ctx.name);
+        let mut ctx = Self::new(database, Some(ctx), WorkloadType::Reducer, Default::default());
+        ctx.reducer_overflows = reducer_overflows;
+        ctx
     }
 
     /// Returns an [ExecutionContext] for a one-off sql query.
@@ -250,6 +296,17 @@ impl ExecutionContext {
         self.reducer.as_ref().map(|ctx| ctx.name.as_str()).unwrap_or_default()
     }
 
+    /// Returns the value of the `reducer` label of the metrics of this transaction;
+    /// see [`ReducerLabels`].
+    #[inline]
+    pub fn reducer_label(&self) -> &str {
+        if self.reducer_overflows {
+            OVERFLOW_LABEL
+        } else {
+            self.reducer_name()
+        }
+    }
+
     /// If this is a reducer context, returns the full reducer metadata.
     #[inline]
     pub fn reducer_context(&self) -> Option<&ReducerContext> {
@@ -267,7 +324,7 @@ impl Drop for ExecutionContext {
     fn drop(&mut self) {
         let workload = self.workload;
         let database = self.database;
-        let reducer = self.reducer_name();
+        let reducer = self.reducer_label();
         self.metrics.write().flush(&workload, &database, reducer);
     }
 }
@@ -11,8 +11,10 @@ use super::timestamp::Timestamp;
 use crate::database_instance_context::DatabaseInstanceContext;
 use crate::database_logger::{BacktraceProvider, LogLevel, Record};
 use crate::db::datastore::locking_tx_datastore::MutTxId;
+use crate::db::db_metrics::record_blob_fetches;
 use crate::error::{IndexError, NodesError};
 use crate::execution_context::ExecutionContext;
+use crate::sql::compiler::optimize_query_in;
 use crate::vm::{build_query, TxMode};
 use spacetimedb_lib::filter::CmpArgs;
 use spacetimedb_lib::operator::OpQuery;
@@ -302,12 +304,18 @@ impl InstanceEnv {
         let stdb = &*self.dbic.relational_db;
         let tx = &mut *self.tx.get()?;
 
+        let mut blob_fetches = 0;
         for row in stdb.iter_mut(ctx, tx, table_id)? {
+            blob_fetches += row.num_large_blobs() as u64;
             // Write the ref directly to the BSATN `chunked_writer` buffer.
             chunked_writer.write_row_ref_to_scratch(row).unwrap();
             // Flush at row boundaries.
             chunked_writer.flush();
         }
+        if blob_fetches > 0 {
+            let table_name = stdb.table_name_from_id_mut(ctx, tx, table_id)?.unwrap_or_default();
+            record_blob_fetches(ctx.workload(), ctx.database(), table_id, &table_name, blob_fetches);
+        }
 
         Ok(chunked_writer.into_chunks())
     }
@@ -362,9 +370,8 @@ impl InstanceEnv {
         .map_err(NodesError::DecodeFilter)?;
 
         // TODO(Centril): consider caching from `filter: &[u8] -> query: QueryExpr`.
-        let query = QueryExpr::new(schema.as_ref())
-            .with_select(filter_to_column_op(table_id, filter))
-            .optimize(&|table_id, table_name| stdb.row_count(table_id, table_name));
+        let query = QueryExpr::new(schema.as_ref()).with_select(filter_to_column_op(table_id, filter));
+        let query = optimize_query_in(ctx, stdb, query);
 
         // TODO(Centril): Conditionally dump the `query` to a file and compare against integration test.
         // Invent a system where we can make these kinds of "optimization path tests".
@@ -375,10 +382,21 @@ impl InstanceEnv {
 
         // write all rows and flush at row boundaries.
         let mut chunked_writer = ChunkedWriter::default();
+        let mut blob_fetches = 0;
         while let Some(row) = query.next()? {
+            if let RelValue::Row(row_ref) = &row {
+                blob_fetches += row_ref.num_large_blobs() as u64;
+            }
             chunked_writer.write_rel_value_to_scratch(&row).unwrap();
             chunked_writer.flush();
         }
+        record_blob_fetches(
+            ctx.workload(),
+            ctx.database(),
+            table_id,
+            &schema.table_name,
+            blob_fetches,
+        );
         Ok(chunked_writer.into_chunks())
     }
 }
@@ -3,6 +3,7 @@ use crate::client::{ClientActorId, ClientConnectionSender};
 use crate::database_instance_context::DatabaseInstanceContext;
 use crate::database_logger::LogLevel;
 use crate::db::datastore::traits::TxData;
+use crate::db::db_metrics::ReducerLabels;
 use crate::db::update::UpdateDatabaseError;
 use crate::energy::EnergyQuanta;
 use crate::error::DBError;
@@ -132,6 +133,7 @@ impl From<DatabaseTableUpdate> for TableUpdate {
             table_id: table.table_id.into(),
             table_name: table.table_name.into(),
             table_row_operations,
+            columns: Vec::new(),
         }
     }
 }
@@ -151,6 +153,7 @@ impl From<DatabaseTableUpdate> for TableUpdateJson {
             table_id: table.table_id.into(),
             table_name: table.table_name,
             table_row_operations,
+            columns: Vec::new(),
         }
     }
 }
@@ -324,6 +327,8 @@ pub struct ModuleInfo {
     pub module_hash: Hash,
     pub typespace: Typespace,
     pub reducers: ReducersMap,
+    /// The values of the `reducer` labels of the metrics of `reducers`.
+    pub reducer_labels: ReducerLabels,
     pub catalog: HashMap<Box<str>, EntityDef>,
     pub log_tx: tokio::sync::broadcast::Sender<bytes::Bytes>,
     pub subscriptions: ModuleSubscriptions,
@@ -636,7 +641,7 @@ impl ModuleHost {
             // Record the time spent waiting in the queue
             let _guard = WORKER_METRICS
                 .reducer_wait_time
-                .with_label_values(&self.info.address, reducer)
+                .with_label_values(&self.info.address, self.info.reducer_labels.get(reducer))
                 .start_timer();
             self.inner.get_instance(self.info.address).await?
         };
@@ -703,6 +708,7 @@ impl ModuleHost {
                             timestamp: Timestamp::now(),
                             arg_bsatn: Bytes::new(),
                         },
+                        &self.info.reducer_labels,
                     ),
                     |_| anyhow::Ok(()),
                 )
@@ -13,6 +13,7 @@ use crate::database_instance_context::DatabaseInstanceContext;
 use crate::database_logger::{LogLevel, Record, SystemLogger};
 use crate::db::datastore::locking_tx_datastore::MutTxId;
 use crate::db::datastore::traits::IsolationLevel;
+use crate::db::db_metrics::DB_METRICS;
 use crate::energy::{EnergyMonitor, EnergyQuanta, ReducerBudget, ReducerFingerprint};
 use crate::execution_context::{self, ExecutionContext, ReducerContext};
 use crate::host::instance_env::InstanceEnv;
@@ -184,6 +185,7 @@ impl<T: WasmModule> WasmModuleHostActor<T> {
         )
         .collect();
         let reducers = ReducersMap(reducers.into_iter().map(|x| (x.name.clone(), x)).collect());
+        let reducer_labels = DB_METRICS.reducer_labels(reducers.0.keys().map(|name| &**name));
 
         let info = Arc::new(ModuleInfo {
             identity: database_instance_context.identity,
@@ -191,6 +193,7 @@ impl<T: WasmModule> WasmModuleHostActor<T> {
             module_hash,
             typespace,
             reducers,
+            reducer_labels,
             catalog,
             log_tx,
             subscriptions,
@@ -538,7 +541,7 @@ impl<T: WasmInstance> WasmModuleInstance<T> {
         let tx = tx.unwrap_or_else(|| stdb.begin_mut_tx(IsolationLevel::Serializable));
         let _guard = WORKER_METRICS
             .reducer_plus_query_duration
-            .with_label_values(&address, op.name)
+            .with_label_values(&address, self.info.reducer_labels.get(op.name))
             .with_timer(tx.timer);
 
         let mut tx_slot = self.instance.instance_env().tx.clone();
@@ -550,7 +553,7 @@ impl<T: WasmInstance> WasmModuleInstance<T> {
             energy.used = tracing::field::Empty,
         )
         .entered();
-        let ctx = ExecutionContext::reducer(address, ReducerContext::from(op.clone()));
+        let ctx = ExecutionContext::reducer(address, ReducerContext::from(op.clone()), &self.info.reducer_labels);
         // run the call_reducer call in rayon. it's important that we don't acquire a lock inside a rayon task,
         // as that can lead to deadlock.
         let (ctx, tx, result) = rayon::scope(|_| tx_slot.set(ctx, tx, || self.instance.call_reducer(op, budget)));
@@ -591,7 +594,12 @@ impl<T: WasmInstance> WasmModuleInstance<T> {
 
                 WORKER_METRICS
                     .wasm_instance_errors
-                    .with_label_values(&caller_identity, &self.info.module_hash, &caller_address, reducer_name)
+                    .with_label_values(
+                        &caller_identity,
+                        &self.info.module_hash,
+                        &caller_address,
+                        self.info.reducer_labels.get(reducer_name),
+                    )
                     .inc();
 
                 // discard this instance
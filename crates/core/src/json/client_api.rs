@@ -1,5 +1,6 @@
 use bytestring::ByteString;
 use serde::Serialize;
+use serde_json::value::RawValue;
 use spacetimedb_lib::Address;
 use spacetimedb_lib::AlgebraicValue;
 use spacetimedb_lib::Identity;
@@ -65,6 +66,9 @@ pub struct TableUpdateJson {
     pub table_id: u32,
     pub table_name: Box<str>,
     pub table_row_operations: Vec<TableRowOperationJson>,
+    /// The positions in the table of the columns each row holds,
+    /// or empty if the rows are whole rows of the table.
+    pub columns: Vec<u32>,
 }
 
 #[serde_as]
@@ -102,7 +106,8 @@ pub struct TransactionUpdateJson {
 #[derive(Debug, Clone, Serialize)]
 pub struct StmtResultJson {
     pub schema: ProductType,
-    pub rows: Vec<ProductValue>,
+    /// The JSON array of the rows, serialized as they were produced.
+    pub rows: Box<RawValue>,
 }
 
 #[derive(Debug, Clone, Serialize)]
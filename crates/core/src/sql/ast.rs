@@ -1,12 +1,13 @@
 use crate::config::ReadConfigOption;
+use crate::db::datastore::locking_tx_datastore::{tx::TxId, MutTxId};
 use crate::db::relational_db::{MutTx, RelationalDB, Tx};
 use crate::error::{DBError, PlanError};
 use spacetimedb_data_structures::map::HashMap;
-use spacetimedb_primitives::{ColList, ConstraintKind, Constraints};
+use spacetimedb_primitives::{ColList, ConstraintKind, Constraints, TableId};
 use spacetimedb_sats::db::auth::StAccess;
 use spacetimedb_sats::db::def::{ColumnDef, ConstraintDef, TableDef, TableSchema};
 use spacetimedb_sats::db::error::RelationError;
-use spacetimedb_sats::relation::{FieldExpr, FieldName};
+use spacetimedb_sats::relation::{ColumnStatsMap, FieldExpr, FieldName};
 use spacetimedb_sats::{AlgebraicType, AlgebraicValue};
 use spacetimedb_vm::errors::ErrorVm;
 use spacetimedb_vm::expr::{ColumnOp, DbType, Expr};
@@ -292,6 +293,28 @@ pub enum SqlAst {
     },
 }
 
+impl SqlAst {
+    /// Returns an error if the query compiled from this statement would be nested deeper than `max_depth`;
+    /// see [`QueryExpr::validate_depth`](spacetimedb_vm::expr::QueryExpr::validate_depth).
+    ///
+    /// Compiling the `WHERE` clause recurses over it, e.g., to split it into its conjuncts,
+    /// so this is checked on the clause as parsed, before compiling it.
+    /// Joins are compiled flat, so they don't add to the depth beyond that of the clause.
+    pub fn validate_depth(&self, max_depth: usize) -> Result<(), ErrorVm> {
+        let (SqlAst::Select { selection, .. } | SqlAst::Update { selection, .. } | SqlAst::Delete { selection, .. }) =
+            self
+        else {
+            return Ok(());
+        };
+        // The compiled query nests the clause below its root.
+        let depth = 1 + selection.as_ref().map_or(0, |selection| selection.clause.depth());
+        if depth > max_depth {
+            return Err(ErrorVm::TooDeep { depth, max_depth });
+        }
+        Ok(())
+    }
+}
+
 fn extract_field<'a>(
     tables: impl Clone + Iterator<Item = &'a TableSchema>,
     of: &SqlExpr,
@@ -476,6 +499,10 @@ fn compile_where(table: &From, filter: Option<SqlExpr>) -> Result<Option<Selecti
 
 pub trait TableSchemaView {
     fn find_table(&self, db: &RelationalDB, t: Table) -> Result<Arc<TableSchema>, PlanError>;
+
+    /// Returns statistics about the columns of the table `table_id`,
+    /// which the optimizer consults when choosing among its indices.
+    fn column_stats(&self, table_id: TableId) -> ColumnStatsMap;
 }
 
 impl TableSchemaView for Tx {
@@ -489,6 +516,10 @@ impl TableSchemaView for Tx {
         db.schema_for_table(self, table_id)
             .map_err(move |e| PlanError::DatabaseInternal(Box::new(e)))
     }
+
+    fn column_stats(&self, table_id: TableId) -> ColumnStatsMap {
+        TxId::column_stats(self, table_id)
+    }
 }
 
 impl TableSchemaView for MutTx {
@@ -502,6 +533,10 @@ impl TableSchemaView for MutTx {
         db.schema_for_table_mut(self, table_id)
             .map_err(|e| PlanError::DatabaseInternal(Box::new(e)))
     }
+
+    fn column_stats(&self, table_id: TableId) -> ColumnStatsMap {
+        MutTxId::column_stats(self, table_id)
+    }
 }
 
 /// Compiles the `FROM` clause
@@ -535,6 +570,16 @@ fn compile_from<T: TableSchemaView>(db: &RelationalDB, tx: &T, from: &[TableWith
                         let expr = compile_expr_value(tables, None, x.clone())?;
                         match expr {
                             ColumnOp::Field(_) => {}
+                            ColumnOp::In { .. } | ColumnOp::InTuples { .. } => {
+                                return Err(PlanError::Unsupported {
+                                    feature: "Can't use IN on JOIN clause".into(),
+                                });
+                            }
+                            ColumnOp::Len(_) => {
+                                return Err(PlanError::Unsupported {
+                                    feature: "Can't use len on JOIN clause".into(),
+                                });
+                            }
                             ColumnOp::Cmp { op, lhs, rhs } => {
                                 let op = match op {
                                     OpQuery::Cmp(op) => op,
@@ -1,14 +1,18 @@
+use crate::db::db_metrics::{record_query_compile_time, record_query_plan};
 use crate::db::relational_db::RelationalDB;
 use crate::error::{DBError, PlanError};
+use crate::execution_context::{ExecutionContext, MetricType, WorkloadType};
 use crate::sql::ast::{compile_to_ast, Column, From, Join, Selection, SqlAst};
+use crate::sql::execute::ctx_sql;
 use core::ops::Deref;
 use spacetimedb_data_structures::map::HashMap;
 use spacetimedb_sats::db::auth::StAccess;
 use spacetimedb_sats::db::def::{TableDef, TableSchema};
 use spacetimedb_sats::relation::{self, DbTable, FieldExpr, FieldName, Header};
-use spacetimedb_vm::expr::{CrudExpr, DbType, Expr, QueryExpr, SourceExpr};
+use spacetimedb_vm::expr::{CrudExpr, DbType, Expr, OptimizeOptions, QueryExpr, SourceExpr, DEFAULT_MAX_QUERY_DEPTH};
 use spacetimedb_vm::operator::OpCmp;
 use std::sync::Arc;
+use std::time::Instant;
 
 use super::ast::TableSchemaView;
 
@@ -19,6 +23,24 @@ const MAX_SQL_LENGTH: usize = 50_000;
 
 /// Compile the `SQL` expression into an `ast`
 pub fn compile_sql<T: TableSchemaView>(db: &RelationalDB, tx: &T, sql_text: &str) -> Result<Vec<CrudExpr>, DBError> {
+    compile_sql_for(db, tx, sql_text, WorkloadType::Sql)
+}
+
+/// Like [`compile_sql`], but records the compile time
+/// under the `workload` the query is compiled for, e.g., a subscription.
+pub fn compile_sql_for<T: TableSchemaView>(
+    db: &RelationalDB,
+    tx: &T,
+    sql_text: &str,
+    workload: WorkloadType,
+) -> Result<Vec<CrudExpr>, DBError> {
+    let start = Instant::now();
+    let result = compile(db, tx, sql_text);
+    record_query_compile_time(db.address(), workload, start.elapsed());
+    result
+}
+
+fn compile<T: TableSchemaView>(db: &RelationalDB, tx: &T, sql_text: &str) -> Result<Vec<CrudExpr>, DBError> {
     if sql_text.len() > MAX_SQL_LENGTH {
         return Err(anyhow::anyhow!("SQL query exceeds maximum allowed length: \"{sql_text:.120}...\"").into());
     }
@@ -29,7 +51,7 @@ pub fn compile_sql<T: TableSchemaView>(db: &RelationalDB, tx: &T, sql_text: &str
     let mut results = Vec::with_capacity(ast.len());
 
     for sql in ast {
-        results.push(compile_statement(db, sql).map_err(|error| DBError::Plan {
+        results.push(compile_statement(db, tx, sql).map_err(|error| DBError::Plan {
             sql: sql_text.to_string(),
             error,
         })?);
@@ -54,8 +76,27 @@ fn compile_where(mut q: QueryExpr, filter: Selection) -> QueryExpr {
     q
 }
 
+/// Attaches to `source` the statistics about the columns of its table as seen by `tx`,
+/// so that the optimizer can tell a selective index from an unselective one.
+fn with_column_stats<T: TableSchemaView>(tx: &T, source: SourceExpr) -> SourceExpr {
+    let Some(table_id) = source.table_id() else {
+        return source;
+    };
+    let stats = tx.column_stats(table_id);
+    if stats.is_empty() {
+        source
+    } else {
+        source.with_column_stats(stats)
+    }
+}
+
 /// Compiles a `SELECT ...` clause
-fn compile_select(table: From, project: Vec<Column>, selection: Option<Selection>) -> Result<QueryExpr, PlanError> {
+fn compile_select<T: TableSchemaView>(
+    tx: &T,
+    table: From,
+    project: Vec<Column>,
+    selection: Option<Selection>,
+) -> Result<QueryExpr, PlanError> {
     let mut not_found = Vec::with_capacity(project.len());
     let mut col_ids = Vec::new();
     let mut qualified_wildcards = Vec::new();
@@ -88,13 +129,13 @@ fn compile_select(table: From, project: Vec<Column>, selection: Option<Selection
         });
     }
 
-    let source_expr: SourceExpr = table.root.deref().into();
+    let source_expr = with_column_stats(tx, table.root.deref().into());
     let mut q = QueryExpr::new(source_expr);
 
     for join in table.joins {
         match join {
             Join::Inner { rhs, on } => {
-                let rhs_source_expr: SourceExpr = rhs.deref().into();
+                let rhs_source_expr = with_column_stats(tx, rhs.deref().into());
                 match on.op {
                     OpCmp::Eq => {}
                     x => unreachable!("Unsupported operator `{x}` for joins"),
@@ -102,12 +143,12 @@ fn compile_select(table: From, project: Vec<Column>, selection: Option<Selection
                 // Always construct inner joins, never semijoins.
                 // The query optimizer can rewrite certain inner joins into semijoins later in the pipeline.
                 // The full pipeline for a query like `SELECT lhs.* FROM lhs JOIN rhs ON lhs.a = rhs.a` is:
-                // - We produce `[JoinInner(semi: false), Project]`.
-                // - Optimizer rewrites to `[JoinInner(semi: true)]`.
+                // - We produce `[JoinInner(kind: Inner), Project]`.
+                // - Optimizer rewrites to `[JoinInner(kind: Semi)]`.
                 // - Optimizer rewrites to `[IndexJoin]`.
                 // For incremental queries, this all happens on the original query with `DbTable` sources.
                 // Then, the query is "incrementalized" by replacing the sources with `MemTable`s,
-                // and the `IndexJoin` is rewritten back into a `JoinInner(semi: true)`.
+                // and the `IndexJoin` is rewritten back into a `JoinInner(kind: Semi)`.
                 q = q.with_join_inner(rhs_source_expr, on.lhs, on.rhs, false);
             }
         }
@@ -176,7 +217,10 @@ fn compile_delete(table: Arc<TableSchema>, selection: Option<Selection>) -> Crud
     } else {
         query
     };
-    CrudExpr::Delete { query }
+    CrudExpr::Delete {
+        query,
+        expected_max_rows: None,
+    }
 }
 
 /// Compiles a `UPDATE ...` clause
@@ -192,7 +236,16 @@ fn compile_update(
         query
     };
 
-    CrudExpr::Update { delete, assignments }
+    let assignments = assignments
+        .into_iter()
+        .map(|(field, value)| (field, value.into()))
+        .collect();
+
+    CrudExpr::Update {
+        delete,
+        assignments,
+        expected_max_rows: None,
+    }
 }
 
 /// Compiles a `CREATE TABLE ...` clause
@@ -210,13 +263,17 @@ fn compile_drop(name: String, kind: DbType, table_access: StAccess) -> CrudExpr
 }
 
 /// Compiles a `SQL` clause
-fn compile_statement(db: &RelationalDB, statement: SqlAst) -> Result<CrudExpr, PlanError> {
+fn compile_statement<T: TableSchemaView>(db: &RelationalDB, tx: &T, statement: SqlAst) -> Result<CrudExpr, PlanError> {
+    // Reject statements nested too deeply for the recursive passes over them,
+    // starting with compiling their `WHERE` clause, before any of those passes.
+    statement.validate_depth(DEFAULT_MAX_QUERY_DEPTH)?;
+
     let q = match statement {
         SqlAst::Select {
             from,
             project,
             selection,
-        } => CrudExpr::Query(compile_select(from, project, selection)?),
+        } => CrudExpr::Query(compile_select(tx, from, project, selection)?),
         SqlAst::Insert { table, columns, values } => compile_insert(&table, columns, values),
         SqlAst::Update {
             table,
@@ -234,25 +291,58 @@ fn compile_statement(db: &RelationalDB, statement: SqlAst) -> Result<CrudExpr, P
         SqlAst::ReadVar { name } => CrudExpr::ReadVar { name },
     };
 
-    Ok(q.optimize(&|table_id, table_name| db.row_count(table_id, table_name)))
+    Ok(match q {
+        CrudExpr::Query(query) => CrudExpr::Query(optimize_query(db, query)),
+        q => q,
+    })
+}
+
+/// Optimizes `query` using the table statistics of `db`,
+/// counting in [`DbMetrics::query_plan_changed`](crate::db::db_metrics::DbMetrics::query_plan_changed)
+/// whether the resulting plan differs from the one produced for the same query the last time.
+pub(crate) fn optimize_query(db: &RelationalDB, query: QueryExpr) -> QueryExpr {
+    let input = query.structural_hash();
+    let plan = optimize_query_in(&ctx_sql(db), db, query);
+    record_query_plan(db.address(), input, plan.structural_hash());
+    plan
+}
+
+/// Optimizes `query` using the table statistics of `db`,
+/// counting each filter that falls back to a sequential scan of a table
+/// in [`DbMetrics::rdb_num_seq_scans`](crate::db::db_metrics::DbMetrics::rdb_num_seq_scans)
+/// under the labels of `ctx`.
+pub(crate) fn optimize_query_in(ctx: &ExecutionContext, db: &RelationalDB, query: QueryExpr) -> QueryExpr {
+    query.optimize_reporting_seq_scans(
+        &|table_id, table_name| db.row_count(table_id, table_name),
+        &OptimizeOptions::default(),
+        &mut |table| {
+            let get_table_name = || table.head.table_name.to_string();
+            ctx.metrics
+                .write()
+                .inc_by(table.table_id, MetricType::SeqScans, 1, get_table_name);
+        },
+    )
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
     use crate::db::datastore::traits::IsolationLevel;
+    use crate::db::db_metrics::DB_METRICS;
     use crate::db::relational_db::tests_utils::TestDB;
-    use crate::execution_context::ExecutionContext;
+    use crate::execution_context::{ExecutionContext, WorkloadType};
     use crate::sql::execute::tests::run_for_testing;
     use crate::vm::tests::create_table_with_rows;
     use spacetimedb_lib::error::{ResultTest, TestError};
     use spacetimedb_lib::operator::OpQuery;
     use spacetimedb_lib::{Address, Identity};
-    use spacetimedb_primitives::{col_list, ColList, TableId};
+    use spacetimedb_primitives::{col_list, ColId, ColList, TableId};
     use spacetimedb_sats::{product, AlgebraicType, AlgebraicValue, ProductType};
-    use spacetimedb_vm::expr::{ColumnOp, IndexJoin, IndexScan, JoinExpr, Query};
+    use spacetimedb_vm::errors::ErrorVm;
+    use spacetimedb_vm::expr::{ColumnOp, IndexJoin, IndexScan, JoinExpr, JoinKind, Query};
     use std::convert::From;
     use std::ops::Bound;
+    use std::sync::atomic::{AtomicI64, Ordering};
 
     fn assert_index_scan(
         op: &Query,
@@ -260,7 +350,10 @@ mod tests {
         low_bound: Bound<AlgebraicValue>,
         up_bound: Bound<AlgebraicValue>,
     ) -> TableId {
-        if let Query::IndexScan(IndexScan { table, columns, bounds }) = op {
+        if let Query::IndexScan(IndexScan {
+            table, columns, bounds, ..
+        }) = op
+        {
             assert_eq!(columns, &cols.into(), "Columns don't match");
             assert_eq!(bounds.0, low_bound, "Lower bound don't match");
             assert_eq!(bounds.1, up_bound, "Upper bound don't match");
@@ -449,6 +542,37 @@ mod tests {
         Ok(())
     }
 
+    #[test]
+    fn compile_index_eq_and_unselective_eq() -> ResultTest<()> {
+        let db = TestDB::durable()?;
+
+        // Create table [test] with indexes on [id] and [flag],
+        // where every row has a distinct [id] but the same [flag].
+        let schema = &[("id", AlgebraicType::U64), ("flag", AlgebraicType::Bool)];
+        let indexes = &[(0.into(), "id"), (1.into(), "flag")];
+        let table_id = db.create_table_for_test("test", schema, indexes)?;
+        db.with_auto_commit(&ExecutionContext::default(), |tx| {
+            for id in 0..20u64 {
+                db.insert(tx, table_id, product![id, true])?;
+            }
+            Ok::<(), TestError>(())
+        })?;
+
+        let tx = db.begin_tx();
+        // The distinct values counted by the indexes reach the optimizer,
+        // which filters by [flag] rather than seek on it, as that would yield every row.
+        let sql = "select * from test where id = 1 and flag = true";
+        let CrudExpr::Query(QueryExpr { source, query }) = compile_sql(&db, &tx, sql)?.remove(0) else {
+            panic!("Expected QueryExpr");
+        };
+        let distinct_count = |col: u32| source.column_stats()?.get(&ColId(col))?.distinct_count;
+        assert_eq!((distinct_count(0), distinct_count(1)), (Some(20), Some(1)));
+        assert_eq!(2, query.len());
+        assert_one_eq_index_scan(&query[0], 0, 1u64.into());
+        assert_select(&query[1]);
+        Ok(())
+    }
+
     #[test]
     fn compile_index_multi_eq_and_eq() -> ResultTest<()> {
         let db = TestDB::durable()?;
@@ -613,7 +737,7 @@ mod tests {
                 table: rhs_table,
                 col: rhs_field,
             },
-            semi: false,
+            kind: JoinKind::Inner,
         }) = query[1]
         else {
             panic!("unexpected operator {:#?}", query[1]);
@@ -691,7 +815,7 @@ mod tests {
                 table: rhs_table,
                 col: rhs_field,
             },
-            semi: false,
+            kind: JoinKind::Inner,
         }) = query[1]
         else {
             panic!("unexpected operator {:#?}", query[1]);
@@ -750,7 +874,7 @@ mod tests {
                 table: rhs_table,
                 col: rhs_field,
             },
-            semi: false,
+            kind: JoinKind::Inner,
         }) = query[0]
         else {
             panic!("unexpected operator {:#?}", query[0]);
@@ -837,7 +961,7 @@ mod tests {
                 table: rhs_table,
                 col: rhs_field,
             },
-            semi: false,
+            kind: JoinKind::Inner,
         }) = query[1]
         else {
             panic!("unexpected operator {:#?}", query[1]);
@@ -900,14 +1024,11 @@ mod tests {
                     source: SourceExpr::DbTable(DbTable { table_id, .. }),
                     query: rhs,
                 },
-            probe_field: FieldName {
-                table: probe_table,
-                col: probe_field,
-            },
+            probe_fields,
             index_side: SourceExpr::DbTable(DbTable {
                 table_id: index_table, ..
             }),
-            index_col,
+            index_cols,
             ..
         }) = &query[0]
         else {
@@ -916,9 +1037,8 @@ mod tests {
 
         assert_eq!(*table_id, rhs_id);
         assert_eq!(*index_table, lhs_id);
-        assert_eq!(index_col, &1.into());
-        assert_eq!(*probe_field, 0.into());
-        assert_eq!(*probe_table, rhs_id);
+        assert_eq!(*index_cols, ColList::new(1.into()));
+        assert_eq!(probe_fields[..], [FieldName::new(rhs_id, 0.into())]);
 
         assert_eq!(2, rhs.len());
 
@@ -997,14 +1117,11 @@ mod tests {
                     source: SourceExpr::DbTable(DbTable { table_id, .. }),
                     query: rhs,
                 },
-            probe_field: FieldName {
-                table: probe_table,
-                col: probe_field,
-            },
+            probe_fields,
             index_side: SourceExpr::DbTable(DbTable {
                 table_id: index_table, ..
             }),
-            index_col,
+            index_cols,
             ..
         }) = &query[0]
         else {
@@ -1013,9 +1130,8 @@ mod tests {
 
         assert_eq!(*table_id, rhs_id);
         assert_eq!(*index_table, lhs_id);
-        assert_eq!(index_col, &1.into());
-        assert_eq!(*probe_field, 0.into());
-        assert_eq!(*probe_table, rhs_id);
+        assert_eq!(*index_cols, ColList::new(1.into()));
+        assert_eq!(probe_fields[..], [FieldName::new(rhs_id, 0.into())]);
 
         assert_eq!(2, rhs.len());
 
@@ -1115,4 +1231,93 @@ mod tests {
         assert!(compile_sql(&db, &db.begin_tx(), "select * from B join A on B.y = A.x").is_ok());
         Ok(())
     }
+
+    #[test]
+    fn count_query_plan_changes() -> ResultTest<()> {
+        let row_count = Arc::new(AtomicI64::new(i64::MAX));
+        let db = TestDB::durable()?.with_row_count({
+            let row_count = row_count.clone();
+            Arc::new(move |_, _| row_count.load(Ordering::Relaxed))
+        });
+
+        // Create tables [lhs] and [rhs] with an index on [id]
+        let schema = &[("id", AlgebraicType::U64), ("x", AlgebraicType::U64)];
+        let indexes = &[(0.into(), "id")];
+        db.create_table_for_test("lhs", schema, indexes)?;
+        db.create_table_for_test("rhs", schema, indexes)?;
+
+        // Compiles to an index join, whose sides are swapped when re-optimized if [lhs] is small.
+        let sql = "select lhs.* from lhs join rhs on lhs.id = rhs.id where rhs.x = 3";
+        let CrudExpr::Query(join) = compile_sql(&db, &db.begin_tx(), sql)?.remove(0) else {
+            panic!("Expected QueryExpr");
+        };
+        let plan_changes = || DB_METRICS.query_plan_changed.with_label_values(&db.address()).get();
+
+        // Planning the same query with the same statistics yields the same plan.
+        let plan = optimize_query(&db, join.clone());
+        assert_eq!(plan, optimize_query(&db, join.clone()));
+        let before = plan_changes();
+
+        // Shrinking [lhs] flips the plan, which should be counted.
+        row_count.store(5, Ordering::Relaxed);
+        assert_ne!(plan, optimize_query(&db, join));
+        assert!(plan_changes() > before);
+        Ok(())
+    }
+
+    #[test]
+    fn count_seq_scans() -> ResultTest<()> {
+        let db = TestDB::durable()?;
+        let schema = &[("a", AlgebraicType::U64), ("b", AlgebraicType::U64)];
+        let table_id = db.create_table_for_test("seq_scan", schema, &[(0.into(), "a")])?;
+        let seq_scans = || {
+            DB_METRICS
+                .rdb_num_seq_scans
+                .with_label_values(&WorkloadType::Sql, &db.address(), "", &table_id.0, "seq_scan")
+                .get()
+        };
+        let before = seq_scans();
+
+        // A filter on the unindexed column [b] falls back to a scan of [seq_scan].
+        compile_sql(&db, &db.begin_tx(), "select * from seq_scan where b = 1")?;
+        assert_eq!(seq_scans(), before + 1);
+
+        // A filter on the indexed column [a] does not.
+        compile_sql(&db, &db.begin_tx(), "select * from seq_scan where a = 1")?;
+        assert_eq!(seq_scans(), before + 1);
+        Ok(())
+    }
+
+    #[test]
+    fn reject_deep_where_before_compiling() -> ResultTest<()> {
+        let db = TestDB::durable()?;
+        let table_id = db.create_table_for_test("deep", &[("a", AlgebraicType::U64)], &[])?;
+        let tx = db.begin_tx();
+        let schema = db.schema_for_table(&tx, table_id)?;
+        let a = FieldName::new(table_id, 0.into());
+        // `SELECT * FROM deep WHERE a = 0 AND a = 1 AND ...`, whose compiled query has a depth of `n + 2`.
+        let select = |n: u64| {
+            let clause = (1..n).fold(ColumnOp::cmp(a, OpCmp::Eq, 0u64), |op, i| {
+                ColumnOp::and(op, ColumnOp::cmp(a, OpCmp::Eq, i))
+            });
+            SqlAst::Select {
+                from: crate::sql::ast::From::new(schema.clone()),
+                project: vec![Column::Wildcard],
+                selection: Some(Selection { clause }),
+            }
+        };
+
+        let max = DEFAULT_MAX_QUERY_DEPTH as u64;
+        assert!(compile_statement(&db, &tx, select(max - 2)).is_ok());
+        match compile_statement(&db, &tx, select(max - 1)) {
+            Err(PlanError::VmError(ErrorVm::TooDeep { depth, max_depth })) => {
+                assert_eq!(
+                    (depth, max_depth),
+                    (DEFAULT_MAX_QUERY_DEPTH + 1, DEFAULT_MAX_QUERY_DEPTH)
+                );
+            }
+            result => panic!("Expected the statement to be too deep, got {result:?}"),
+        }
+        Ok(())
+    }
 }
@@ -3,31 +3,120 @@ use crate::db::datastore::locking_tx_datastore::state_view::StateView;
 use crate::db::datastore::locking_tx_datastore::tx::TxId;
 use crate::db::relational_db::RelationalDB;
 use crate::error::DBError;
-use crate::execution_context::ExecutionContext;
+use crate::execution_context::{ExecutionContext, MetricType};
 use crate::util::slow::SlowQueryLogger;
 use crate::vm::{DbProgram, TxMode};
 use spacetimedb_lib::identity::AuthCtx;
 use spacetimedb_lib::relation::FieldName;
 use spacetimedb_lib::{ProductType, ProductValue};
+use spacetimedb_sats::db::auth::StAccess;
+use spacetimedb_sats::relation::Header;
 use spacetimedb_vm::eval::run_ast;
 use spacetimedb_vm::expr::{CodeResult, CrudExpr, Expr};
+use spacetimedb_vm::program::ProgramVm;
 use spacetimedb_vm::relation::MemTable;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
 
 pub struct StmtResult {
     pub schema: ProductType,
     pub rows: Vec<ProductValue>,
 }
 
+/// The resources used by a single execution of a `SQL` statement.
+///
+/// The row and seek counts are the same ones recorded in the [ExecutionContext]
+/// and later reported as `rdb_num_rows_fetched` and `rdb_num_index_seeks`.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct QueryStats {
+    /// The number of rows read from the tables, before any filtering.
+    pub rows_scanned: u64,
+    /// The number of rows in the result.
+    pub rows_returned: u64,
+    /// The number of index probes, including each probe of an index join.
+    pub index_seeks: u64,
+    /// The number of bytes the [ResultSink] serialized the result rows into.
+    pub bytes_serialized: u64,
+    /// The time spent executing the statement inside the transaction,
+    /// excluding compilation, waiting for the transaction and committing it.
+    pub cpu_time: Duration,
+}
+
 // TODO(cloutiertyler): we could do this the swift parsing way in which
 // we always generate a plan, but it may contain errors
 
-pub(crate) fn collect_result(result: &mut Vec<MemTable>, r: CodeResult) -> Result<(), DBError> {
+/// The maximum number of rows of a streamed result handed to a [ResultSink] at once.
+pub const STREAM_CHUNK_ROWS: usize = 1024;
+
+/// Receives the result of each statement of a `SQL` execution as it's produced,
+/// e.g., to serialize it for the client.
+///
+/// The rows of a [CodeResult::Stream] are handed over in chunks of at most [STREAM_CHUNK_ROWS] rows
+/// as they're read, so a large result is never held in memory as a whole.
+pub trait ResultSink {
+    /// Starts the result of the next statement, whose rows have the header `head`.
+    fn start_result(&mut self, head: &Arc<Header>, table_access: StAccess) -> Result<(), DBError>;
+
+    /// Appends `rows` to the result of the current statement.
+    ///
+    /// Returns the number of bytes `rows` were serialized into,
+    /// or `0` if the sink keeps them as [ProductValue]s.
+    fn push_rows(&mut self, rows: Vec<ProductValue>) -> Result<usize, DBError>;
+}
+
+/// Collects each result into a [MemTable].
+impl ResultSink for Vec<MemTable> {
+    fn start_result(&mut self, head: &Arc<Header>, table_access: StAccess) -> Result<(), DBError> {
+        self.push(MemTable::new(head.clone(), table_access, Vec::new()));
+        Ok(())
+    }
+
+    fn push_rows(&mut self, rows: Vec<ProductValue>) -> Result<usize, DBError> {
+        let table = self.last_mut().expect("rows pushed before the start of a result");
+        table.data.extend(rows);
+        Ok(0)
+    }
+}
+
+/// Passes the results on to `sink`, counting the rows and bytes of the [QueryStats] on the way.
+struct CountingSink<'a, S> {
+    sink: &'a mut S,
+    stats: QueryStats,
+}
+
+impl<S: ResultSink> ResultSink for CountingSink<'_, S> {
+    fn start_result(&mut self, head: &Arc<Header>, table_access: StAccess) -> Result<(), DBError> {
+        self.sink.start_result(head, table_access)
+    }
+
+    fn push_rows(&mut self, rows: Vec<ProductValue>) -> Result<usize, DBError> {
+        self.stats.rows_returned += rows.len() as u64;
+        let bytes = self.sink.push_rows(rows)?;
+        self.stats.bytes_serialized += bytes as u64;
+        Ok(bytes)
+    }
+}
+
+pub(crate) fn collect_result(sink: &mut impl ResultSink, r: CodeResult) -> Result<(), DBError> {
     match r {
         CodeResult::Value(_) => {}
-        CodeResult::Table(x) => result.push(x),
+        CodeResult::Table(x) => {
+            sink.start_result(&x.head, x.table_access)?;
+            sink.push_rows(x.data)?;
+        }
+        CodeResult::Stream(mut x) => {
+            sink.start_result(&x.head, x.table_access)?;
+            loop {
+                let rows = x.by_ref().take(STREAM_CHUNK_ROWS).collect::<Result<Vec<_>, _>>()?;
+                if rows.is_empty() {
+                    break;
+                }
+                sink.push_rows(rows)?;
+            }
+        }
         CodeResult::Block(lines) => {
             for x in lines {
-                collect_result(result, x)?;
+                collect_result(sink, x)?;
             }
         }
         CodeResult::Halt(err) => return Err(DBError::VmUser(err)),
@@ -47,28 +136,82 @@ pub fn ctx_sql(db: &RelationalDB) -> ExecutionContext {
 ///
 /// Also, in case the execution takes more than x, log it as `slow query`
 pub fn execute_sql(db: &RelationalDB, sql: &str, ast: Vec<CrudExpr>, auth: AuthCtx) -> Result<Vec<MemTable>, DBError> {
-    fn execute(p: &mut DbProgram<'_, '_>, ast: Vec<CrudExpr>) -> Result<Vec<MemTable>, DBError> {
-        let mut result = Vec::with_capacity(ast.len());
-        let query = Expr::Block(ast.into_iter().map(|x| Expr::Crud(Box::new(x))).collect());
-        // SQL queries can never reference `MemTable`s, so pass an empty `SourceSet`.
-        collect_result(&mut result, run_ast(p, query, [].into()).into())?;
-        Ok(result)
-    }
+    let mut result = Vec::with_capacity(ast.len());
+    execute_sql_into(db, sql, ast, auth, &mut result)?;
+    Ok(result)
+}
 
+/// Like [execute_sql], but hands the results to `sink` as they're produced,
+/// and returns the [QueryStats] of the execution.
+pub fn execute_sql_into(
+    db: &RelationalDB,
+    sql: &str,
+    ast: Vec<CrudExpr>,
+    auth: AuthCtx,
+    sink: &mut impl ResultSink,
+) -> Result<QueryStats, DBError> {
     let ctx = ctx_sql(db);
-    let slow_logger = SlowQueryLogger::query(&ctx, sql);
-    let result = if CrudExpr::is_reads(&ast) {
-        db.with_read_only(&ctx, |tx| {
-            execute(&mut DbProgram::new(&ctx, db, &mut TxMode::Tx(tx), auth), ast)
+    let mut stats = execute_sql_in(&ctx, db, sql, ast, auth, sink)?;
+
+    let metrics = ctx.metrics.read();
+    stats.rows_scanned = metrics.total(MetricType::RowsFetched);
+    stats.index_seeks = metrics.total(MetricType::IndexSeeks);
+    drop(metrics);
+
+    Ok(stats)
+}
+
+fn execute_sql_in(
+    ctx: &ExecutionContext,
+    db: &RelationalDB,
+    sql: &str,
+    ast: Vec<CrudExpr>,
+    auth: AuthCtx,
+    sink: &mut impl ResultSink,
+) -> Result<QueryStats, DBError> {
+    fn execute(
+        p: &mut DbProgram<'_, '_>,
+        ast: Vec<CrudExpr>,
+        sink: &mut impl ResultSink,
+    ) -> Result<QueryStats, DBError> {
+        let start = Instant::now();
+        let mut sink = CountingSink {
+            sink,
+            stats: QueryStats::default(),
+        };
+        for crud in ast {
+            match crud {
+                // A large result is streamed out of the transaction, see `DbProgram::eval_query_stream`,
+                // so it's handed to the `sink` before running the next statement.
+                CrudExpr::Query(query) => {
+                    // SQL queries can never reference `MemTable`s, so pass an empty `SourceSet`.
+                    let r = p
+                        .eval_query_stream(&query, &mut [].into())
+                        .unwrap_or_else(|err| CodeResult::Halt(err.into()));
+                    collect_result(&mut sink, r)?;
+                }
+                crud => collect_result(&mut sink, run_ast(p, Expr::Crud(Box::new(crud)), [].into()).into())?,
+            }
+        }
+        Ok(QueryStats {
+            cpu_time: start.elapsed(),
+            ..sink.stats
+        })
+    }
+
+    let slow_logger = SlowQueryLogger::query(ctx, sql);
+    let stats = if CrudExpr::is_reads(&ast) {
+        db.with_read_only(ctx, |tx| {
+            execute(&mut DbProgram::new(ctx, db, &mut TxMode::Tx(tx), auth), ast, sink)
         })
     } else {
-        db.with_auto_commit(&ctx, |mut_tx| {
-            execute(&mut DbProgram::new(&ctx, db, &mut mut_tx.into(), auth), ast)
+        db.with_auto_commit(ctx, |mut_tx| {
+            execute(&mut DbProgram::new(ctx, db, &mut mut_tx.into(), auth), ast, sink)
         })
     }?;
     slow_logger.log();
 
-    Ok(result)
+    Ok(stats)
 }
 
 /// Run the `SQL` string using the `auth` credentials
@@ -77,6 +220,18 @@ pub fn run(db: &RelationalDB, sql_text: &str, auth: AuthCtx) -> Result<Vec<MemTa
     execute_sql(db, sql_text, ast, auth)
 }
 
+/// Like [run], but hands the results to `sink` as they're produced,
+/// and returns the [QueryStats] of the execution.
+pub fn run_into(
+    db: &RelationalDB,
+    sql_text: &str,
+    auth: AuthCtx,
+    sink: &mut impl ResultSink,
+) -> Result<QueryStats, DBError> {
+    let ast = db.with_read_only(&ctx_sql(db), |tx| compile_sql(db, tx, sql_text))?;
+    execute_sql_into(db, sql_text, ast, auth, sink)
+}
+
 /// Translates a `FieldName` to the field's name.
 pub fn translate_col(tx: &TxId, field: FieldName) -> Option<Box<str>> {
     Some(
@@ -93,12 +248,18 @@ pub(crate) mod tests {
     use crate::db::datastore::system_tables::{ST_TABLES_ID, ST_TABLES_NAME};
     use crate::db::relational_db::tests_utils::TestDB;
     use crate::vm::tests::create_table_with_rows;
+    use crate::vm::STREAM_MIN_ROWS;
+    use spacetimedb_lib::bsatn;
     use spacetimedb_lib::error::{ResultTest, TestError};
     use spacetimedb_primitives::{col_list, ColId};
     use spacetimedb_sats::db::auth::{StAccess, StTableType};
     use spacetimedb_sats::relation::Header;
     use spacetimedb_sats::{product, AlgebraicType, ProductType};
+    use spacetimedb_vm::errors::{ErrorKind, ErrorLang};
     use spacetimedb_vm::eval::test_helpers::{create_game_data, mem_table, mem_table_without_table_name};
+    use spacetimedb_vm::expr::{Query, QueryExpr};
+    use std::sync::atomic::{AtomicI64, Ordering};
+    use std::sync::Arc;
 
     /// Short-cut for simplify test execution
     pub(crate) fn run_for_testing(db: &RelationalDB, sql_text: &str) -> Result<Vec<MemTable>, DBError> {
@@ -138,6 +299,112 @@ pub(crate) mod tests {
         Ok(())
     }
 
+    #[test]
+    fn test_stream_large_result() -> ResultTest<()> {
+        let row_count = Arc::new(AtomicI64::new(0));
+        let (db, input) = create_data(10)?;
+        let db = db.with_row_count({
+            let row_count = row_count.clone();
+            Arc::new(move |_, _| row_count.load(Ordering::Relaxed))
+        });
+        let sql = "SELECT * FROM inventory";
+
+        // Returns whether the result of `sql` is streamed rather than materialized.
+        let is_streamed = || {
+            let ctx = ExecutionContext::default();
+            db.with_read_only(&ctx, |tx| {
+                let CrudExpr::Query(query) = compile_sql(&db, tx, sql).unwrap().remove(0) else {
+                    panic!("Expected a query");
+                };
+                let mut tx_mode = (&*tx).into();
+                let p = &mut DbProgram::new(&ctx, &db, &mut tx_mode, AuthCtx::for_testing());
+                let result = p.eval_query_stream(&query, &mut [].into()).unwrap();
+                matches!(result, CodeResult::Stream(_))
+            })
+        };
+
+        // A small result is materialized.
+        assert!(!is_streamed());
+        let materialized = run_for_testing(&db, sql)?;
+
+        // A large one is streamed, yielding the same rows through `execute_sql`.
+        row_count.store(STREAM_MIN_ROWS as i64 + 1, Ordering::Relaxed);
+        assert!(is_streamed());
+        let streamed = run_for_testing(&db, sql)?;
+        assert_eq!(streamed, materialized);
+        assert_eq!(streamed[0].data, input.data);
+
+        // As is one of unknown size.
+        row_count.store(-1, Ordering::Relaxed);
+        assert!(is_streamed());
+        Ok(())
+    }
+
+    #[test]
+    fn test_stream_in_chunks() -> ResultTest<()> {
+        let (db, input) = create_data(2 * STREAM_CHUNK_ROWS as u64 + 1)?;
+        // Stream every result, as its size is unknown.
+        let db = db.with_row_count(Arc::new(|_, _| -1));
+
+        /// Records the size of each chunk of rows, rather than the rows.
+        #[derive(Default)]
+        struct Chunks {
+            results: usize,
+            chunks: Vec<usize>,
+        }
+        impl ResultSink for Chunks {
+            fn start_result(&mut self, _: &Arc<Header>, _: StAccess) -> Result<(), DBError> {
+                self.results += 1;
+                Ok(())
+            }
+            fn push_rows(&mut self, rows: Vec<ProductValue>) -> Result<usize, DBError> {
+                self.chunks.push(rows.len());
+                Ok(0)
+            }
+        }
+
+        let sql = "SELECT * FROM inventory";
+        let mut chunks = Chunks::default();
+        run_into(&db, sql, AuthCtx::for_testing(), &mut chunks)?;
+        assert_eq!(chunks.results, 1);
+        assert_eq!(chunks.chunks, [STREAM_CHUNK_ROWS, STREAM_CHUNK_ROWS, 1]);
+
+        // Collecting the chunks yields all the rows.
+        let result = run_for_testing(&db, sql)?;
+        assert_eq!(result[0].data, input.data);
+        Ok(())
+    }
+
+    #[test]
+    fn test_stream_reads_only_consumed_rows() -> ResultTest<()> {
+        let (db, _) = create_data(2 * STREAM_MIN_ROWS as u64)?;
+        let db = db.with_row_count(Arc::new(|_, _| -1));
+
+        // Returns the number of rows read from `inventory` when consuming `take` rows of the result of `sql`.
+        let rows_fetched = |sql, take| {
+            let ctx = ExecutionContext::default();
+            db.with_read_only(&ctx, |tx| {
+                let CrudExpr::Query(query) = compile_sql(&db, tx, sql).unwrap().remove(0) else {
+                    panic!("Expected a query");
+                };
+                let mut tx_mode = (&*tx).into();
+                let p = &mut DbProgram::new(&ctx, &db, &mut tx_mode, AuthCtx::for_testing());
+                let CodeResult::Stream(stream) = p.eval_query_stream(&query, &mut [].into()).unwrap() else {
+                    panic!("Expected a stream");
+                };
+                assert_eq!(stream.take(take).count(), take);
+            });
+            let metrics = ctx.metrics.read();
+            metrics.total(MetricType::RowsFetched)
+        };
+
+        // Rows are read from the table only as they are consumed.
+        let sql = "SELECT * FROM inventory";
+        assert_eq!(rows_fetched(sql, 10), 10);
+        assert_eq!(rows_fetched(sql, STREAM_MIN_ROWS + 1), STREAM_MIN_ROWS as u64 + 1);
+        Ok(())
+    }
+
     #[test]
     fn test_select_star_table() -> ResultTest<()> {
         let (db, input) = create_data(1)?;
@@ -461,6 +728,50 @@ pub(crate) mod tests {
         Ok(())
     }
 
+    #[test]
+    fn test_expected_max_rows() -> ResultTest<()> {
+        let (db, _input) = create_data(3)?;
+
+        let run_guarded = |sql_text: &str, max_rows| -> Result<Vec<MemTable>, DBError> {
+            let ast = db.with_read_only(&ctx_sql(&db), |tx| compile_sql(&db, tx, sql_text))?;
+            let ast = ast
+                .into_iter()
+                .map(|expr| expr.with_expected_max_rows(max_rows))
+                .collect();
+            execute_sql(&db, sql_text, ast, AuthCtx::for_testing())
+        };
+        let count_rows = || -> ResultTest<usize> {
+            let result = run_for_testing(&db, "SELECT * FROM inventory")?;
+            Ok(result.iter().map(|x| x.data.len()).sum())
+        };
+        let assert_too_many_rows = |result: Result<_, DBError>| {
+            assert!(matches!(
+                result,
+                Err(DBError::VmUser(ErrorLang {
+                    kind: ErrorKind::OutOfBounds,
+                    ..
+                }))
+            ));
+        };
+
+        // Deleting every row trips the guard, so the transaction is rolled back.
+        assert_too_many_rows(run_guarded("DELETE FROM inventory", 2));
+        assert_eq!(count_rows()?, 3);
+
+        // The same goes for updates.
+        assert_too_many_rows(run_guarded("UPDATE inventory SET name = 'c'", 2));
+        let result = run_for_testing(&db, "SELECT * FROM inventory WHERE name = 'c'")?;
+        assert!(result.iter().all(|x| x.data.is_empty()));
+
+        // A guard which covers the affected rows doesn't trip.
+        run_guarded("DELETE FROM inventory WHERE inventory.inventory_id = 3", 1)?;
+        assert_eq!(count_rows()?, 2);
+        run_guarded("DELETE FROM inventory", 2)?;
+        assert_eq!(count_rows()?, 0);
+
+        Ok(())
+    }
+
     #[test]
     fn test_update() -> ResultTest<()> {
         let (db, input) = create_data(1)?;
@@ -722,4 +1033,91 @@ SELECT * FROM inventory",
         assert!(result[0].data.is_empty());
         Ok(())
     }
+
+    #[test]
+    fn test_query_stats() -> ResultTest<()> {
+        let (db, input) = create_data(10)?;
+
+        let sql = "SELECT * FROM inventory WHERE inventory_id > 5";
+
+        /// Serializes the rows to `BSATN`, like a client would.
+        #[derive(Default)]
+        struct Bsatn(Vec<u8>);
+        impl ResultSink for Bsatn {
+            fn start_result(&mut self, _: &Arc<Header>, _: StAccess) -> Result<(), DBError> {
+                Ok(())
+            }
+            fn push_rows(&mut self, rows: Vec<ProductValue>) -> Result<usize, DBError> {
+                let len = self.0.len();
+                for row in rows {
+                    bsatn::to_writer(&mut self.0, &row).map_err(anyhow::Error::from)?;
+                }
+                Ok(self.0.len() - len)
+            }
+        }
+
+        let mut sink = Bsatn::default();
+        let stats = run_into(&db, sql, AuthCtx::for_testing(), &mut sink)?;
+        let expected = &input.data[5..];
+        assert_eq!(
+            stats,
+            QueryStats {
+                rows_scanned: 10,
+                rows_returned: 5,
+                index_seeks: 0,
+                bytes_serialized: sink.0.len() as u64,
+                cpu_time: stats.cpu_time,
+            }
+        );
+        let bytes: Vec<u8> = expected.iter().flat_map(|row| bsatn::to_vec(row).unwrap()).collect();
+        assert_eq!(sink.0, bytes);
+
+        // Collecting the rows doesn't serialize them.
+        let mut result = Vec::new();
+        let stats = run_into(&db, sql, AuthCtx::for_testing(), &mut result)?;
+        assert_eq!(result[0].data, expected);
+        assert_eq!((stats.rows_returned, stats.bytes_serialized), (5, 0));
+        Ok(())
+    }
+
+    #[test]
+    fn test_merge_join() -> ResultTest<()> {
+        let db = TestDB::durable()?;
+
+        let schema = &[("id", AlgebraicType::U64), ("x", AlgebraicType::U64)];
+        let lhs_id = db.create_table_for_test("lhs", schema, &[(0.into(), "lhs_id")])?;
+        let schema = &[("id", AlgebraicType::U64), ("y", AlgebraicType::U64)];
+        let rhs_id = db.create_table_for_test("rhs", schema, &[(0.into(), "rhs_id")])?;
+
+        // Insert out of key order, with duplicate keys on both sides.
+        db.with_auto_commit(&ExecutionContext::default(), |tx| -> Result<_, DBError> {
+            for (id, x) in [(3u64, 30u64), (1, 10), (2, 20), (3, 31), (5, 50)] {
+                db.insert(tx, lhs_id, product![id, x])?;
+            }
+            for (id, y) in [(2u64, 200u64), (3, 300), (3, 301), (4, 400), (1, 100)] {
+                db.insert(tx, rhs_id, product![id, y])?;
+            }
+            Ok(())
+        })?;
+
+        let sql = "SELECT * FROM lhs JOIN rhs ON lhs.id = rhs.id";
+        let ast = db.with_read_only(&ctx_sql(&db), |tx| compile_sql(&db, tx, sql))?;
+        let CrudExpr::Query(QueryExpr { query, .. }) = &ast[0] else {
+            panic!("unexpected expression: {:#?}", ast[0]);
+        };
+        assert!(matches!(query[0], Query::MergeJoin(_)), "{query:#?}");
+
+        let mut result = execute_sql(&db, sql, ast, AuthCtx::for_testing())?.remove(0).data;
+        result.sort();
+        let expected = [
+            product![1u64, 10u64, 1u64, 100u64],
+            product![2u64, 20u64, 2u64, 200u64],
+            product![3u64, 30u64, 3u64, 300u64],
+            product![3u64, 30u64, 3u64, 301u64],
+            product![3u64, 31u64, 3u64, 300u64],
+            product![3u64, 31u64, 3u64, 301u64],
+        ];
+        assert_eq!(result, expected);
+        Ok(())
+    }
 }
@@ -1,7 +1,7 @@
 use super::query::{self, Supported};
 use super::subscription::{IncrementalJoin, SupportedQuery};
 use crate::db::relational_db::{RelationalDB, Tx};
-use crate::error::DBError;
+use crate::error::{DBError, SubscriptionError};
 use crate::execution_context::ExecutionContext;
 use crate::host::module_host::{
     rel_value_to_table_row_op_binary, rel_value_to_table_row_op_json, DatabaseTableUpdate, DatabaseTableUpdateRelValue,
@@ -13,12 +13,14 @@ use crate::vm::{build_query, TxMode};
 use spacetimedb_client_api_messages::client_api::TableUpdate;
 use spacetimedb_lib::ProductValue;
 use spacetimedb_primitives::TableId;
-use spacetimedb_sats::relation::DbTable;
+use spacetimedb_sats::relation::{DbTable, FieldExpr, Header};
+use spacetimedb_vm::errors::ErrorVm;
 use spacetimedb_vm::eval::IterRows;
 use spacetimedb_vm::expr::{NoInMemUsed, Query, QueryExpr, SourceExpr, SourceId};
 use spacetimedb_vm::rel_ops::RelOps;
 use spacetimedb_vm::relation::RelValue;
 use std::hash::Hash;
+use std::sync::Arc;
 
 /// A hash for uniquely identifying query execution units,
 /// to avoid recompilation of queries that have an open subscription.
@@ -83,6 +85,12 @@ pub struct ExecutionUnit {
     /// A version of the plan optimized for `eval_incr`,
     /// whose source is an in-memory table, as if by [`query::to_mem_table`].
     eval_incr_plan: EvalIncrPlan,
+    /// The header of the rows this unit returns,
+    /// i.e. that of the return table projected to the columns the query selects.
+    return_header: Arc<Header>,
+    /// The positions in the return table of the columns of [`Self::return_header`],
+    /// or empty if the rows are whole rows of the return table.
+    columns: Vec<u32>,
 }
 
 /// An ExecutionUnit is uniquely identified by its QueryHash.
@@ -128,6 +136,37 @@ impl ExecutionUnit {
         QueryExpr { source, query }
     }
 
+    /// Returns the header of the rows `plan` returns,
+    /// along with the positions of its columns in the return table,
+    /// which are left empty if the rows are whole rows of the table.
+    ///
+    /// Only the selected columns are serialized to clients,
+    /// so the positions tell them which columns of the table they received.
+    fn compile_return_header(plan: &SupportedQuery) -> Result<(Arc<Header>, Vec<u32>), DBError> {
+        let source = &plan.expr.source;
+        let head = source.head();
+        let Some(Query::Project(cols, _, _)) = plan.expr.query.last() else {
+            return Ok((head.clone(), Vec::new()));
+        };
+
+        // A projected value is not a column of the table,
+        // so there is no way to describe it to clients.
+        let columns = cols
+            .iter()
+            .map(|(col, _)| match col {
+                FieldExpr::Name(field) => head.column_pos(*field).map(|pos| pos.0),
+                FieldExpr::Value(_) => None,
+            })
+            .collect::<Option<Vec<_>>>()
+            .ok_or_else(|| SubscriptionError::Unsupported(plan.sql.clone()))?;
+
+        if columns.iter().copied().eq(0..head.fields.len() as u32) {
+            return Ok((head.clone(), Vec::new()));
+        }
+        let header = source.project_header(cols).map_err(ErrorVm::from)?;
+        Ok((header, columns))
+    }
+
     pub fn new(eval_plan: SupportedQuery, hash: QueryHash) -> Result<Self, DBError> {
         // Pre-compile the `expr` as fully as possible, twice, for two different paths:
         // - `eval_incr_plan`, for incremental updates from an `SourceExpr::InMemory` table.
@@ -145,11 +184,14 @@ impl ExecutionUnit {
                 ..
             } => EvalIncrPlan::Semijoin(IncrementalJoin::new(expr)?),
         };
+        let (return_header, columns) = Self::compile_return_header(&eval_plan)?;
         Ok(ExecutionUnit {
             hash,
             sql: eval_plan.sql,
             eval_plan: eval_plan.expr,
             eval_incr_plan,
+            return_header,
+            columns,
         })
     }
 
@@ -182,6 +224,18 @@ impl ExecutionUnit {
         self.return_db_table().head.table_name.clone()
     }
 
+    /// The header of the rows this query returns,
+    /// which only has the columns of the return table the query selects.
+    pub fn return_header(&self) -> &Arc<Header> {
+        &self.return_header
+    }
+
+    /// The positions in the return table of the columns this query returns,
+    /// or empty if it returns whole rows.
+    pub fn columns(&self) -> &[u32] {
+        &self.columns
+    }
+
     /// The table on which this query filters rows.
     /// In the case of a single table select,
     /// this is the same as the return table.
@@ -226,6 +280,7 @@ impl ExecutionUnit {
             table_id: self.return_table().into(),
             table_name: self.return_name(),
             table_row_operations,
+            columns: self.columns.clone(),
         }))
     }
 
@@ -246,6 +301,7 @@ impl ExecutionUnit {
             table_id: self.return_table().into(),
             table_name: self.return_name().into(),
             table_row_operations,
+            columns: self.columns.clone(),
         }))
     }
 
@@ -146,7 +146,7 @@ impl SubscriptionManager {
                     let unit = self.queries.get(hash)?;
                     match unit.eval_incr(&ctx, db, tx, &unit.sql, tables.iter().copied()) {
                         Ok(None) => None,
-                        Ok(Some(table)) => Some((hash, table)),
+                        Ok(Some(table)) => Some((hash, unit.columns(), table)),
                         Err(err) => {
                             // TODO: log an id for the subscription somehow as well
                             tracing::error!(err = &err as &dyn std::error::Error, "subscription eval_incr failed");
@@ -157,7 +157,7 @@ impl SubscriptionManager {
                 // If N clients are subscribed to a query,
                 // we copy the DatabaseTableUpdate N times,
                 // which involves cloning BSATN (binary) or product values (json).
-                .flat_map_iter(|(hash, delta)| {
+                .flat_map_iter(|(hash, columns, delta)| {
                     let table_id = delta.table_id;
                     let table_name = delta.table_name;
                     // Store at most one copy of the serialization to BSATN
@@ -177,20 +177,22 @@ impl SubscriptionManager {
                                 Either::Right(ops_json.get_or_insert_with(|| (&delta.updates).into()).clone())
                             }
                         };
-                        (id, table_id, table_name.clone(), ops)
+                        (id, table_id, columns, table_name.clone(), ops)
                     })
                 })
                 .collect::<Vec<_>>()
                 .into_iter()
                 // For each subscriber, aggregate all the updates for the same table.
-                // That is, we build a map `(subscriber_id, table_id) -> updates`.
+                // That is, we build a map `(subscriber_id, table_id, columns) -> updates`.
+                // Updates of queries selecting different columns of a table have rows of different shapes,
+                // so they are kept apart.
                 // A particular subscriber uses only one protocol,
                 // so we'll have either `TableUpdate` (`Protocol::Binary`)
                 // or `TableUpdateJson` (`Protocol::Text`).
                 .fold(
-                    HashMap::<(&Id, TableId), Either<TableUpdate, TableUpdateJson>>::new(),
-                    |mut tables, (id, table_id, table_name, ops)| {
-                        match tables.entry((id, table_id)) {
+                    HashMap::<(&Id, TableId, &[u32]), Either<TableUpdate, TableUpdateJson>>::new(),
+                    |mut tables, (id, table_id, columns, table_name, ops)| {
+                        match tables.entry((id, table_id, columns)) {
                             Entry::Occupied(mut entry) => match ops {
                                 Either::Left(ops) => {
                                     entry.get_mut().as_mut().unwrap_left().table_row_operations.extend(ops)
@@ -204,11 +206,13 @@ impl SubscriptionManager {
                                     table_id: table_id.into(),
                                     table_name: table_name.into(),
                                     table_row_operations: ops,
+                                    columns: columns.to_vec(),
                                 }),
                                 Either::Right(ops) => Either::Right(TableUpdateJson {
                                     table_id: table_id.into(),
                                     table_name,
                                     table_row_operations: ops,
+                                    columns: columns.to_vec(),
                                 }),
                             })),
                         }
@@ -221,7 +225,7 @@ impl SubscriptionManager {
                 // we must stitch together the `TableUpdate*`s into an aggregated list.
                 .fold(
                     HashMap::<&Id, Either<Vec<TableUpdate>, Vec<TableUpdateJson>>>::new(),
-                    |mut updates, ((id, _), update)| {
+                    |mut updates, ((id, _, _), update)| {
                         let entry = updates.entry(id);
                         match update {
                             Either::Left(update) => entry
@@ -1,6 +1,7 @@
 use crate::db::relational_db::{RelationalDB, Tx};
 use crate::error::{DBError, SubscriptionError};
-use crate::sql::compiler::compile_sql;
+use crate::execution_context::WorkloadType;
+use crate::sql::compiler::compile_sql_for;
 use crate::subscription::subscription::SupportedQuery;
 use once_cell::sync::Lazy;
 use regex::Regex;
@@ -32,7 +33,7 @@ pub fn compile_read_only_query(
     // Remove redundant whitespace, and in particular newlines, for debug info.
     let input = WHITESPACE.replace_all(input, " ");
 
-    let compiled = compile_sql(relational_db, tx, &input)?;
+    let compiled = compile_sql_for(relational_db, tx, &input, WorkloadType::Subscribe)?;
     let mut queries = Vec::with_capacity(compiled.len());
     for q in compiled {
         return Err(SubscriptionError::SideEffect(match q {
@@ -80,7 +81,7 @@ pub fn classify(expr: &QueryExpr) -> Option<Supported> {
         return Some(Supported::Semijoin);
     }
     for op in &expr.query {
-        if let JoinInner(_) = op {
+        if let JoinInner(_) | JoinLeft(_) | MergeJoin(_) = op {
             return None;
         }
     }
@@ -96,8 +97,10 @@ mod tests {
     use crate::db::relational_db::MutTx;
     use crate::execution_context::ExecutionContext;
     use crate::host::module_host::{DatabaseTableUpdate, DatabaseUpdate};
+    use crate::sql::compiler::compile_sql;
     use crate::sql::execute::collect_result;
     use crate::sql::execute::tests::run_for_testing;
+    use crate::subscription::execution_unit::{ExecutionUnit, QueryHash};
     use crate::subscription::subscription::{get_all, ExecutionSet};
     use crate::util::slow::SlowQueryConfig;
     use crate::vm::tests::create_table_with_rows;
@@ -574,6 +577,45 @@ mod tests {
         Ok(())
     }
 
+    #[test]
+    fn test_subscribe_projection() -> ResultTest<()> {
+        let db = TestDB::durable()?;
+
+        let names = (0..10).map(|i| format!("c{i}")).collect_vec();
+        let schema = names.iter().map(|name| (&**name, AlgebraicType::U64)).collect_vec();
+        let table_id = db.create_table_for_test("wide", &schema, &[])?;
+        let row = product!(0u64, 1u64, 2u64, 3u64, 4u64, 5u64, 6u64, 7u64, 8u64, 9u64);
+        db.with_auto_commit(&ExecutionContext::default(), |tx| {
+            insert_row(&db, tx, table_id, row.clone())
+        })?;
+
+        let sql = "SELECT c7, c3 FROM wide WHERE c0 = 0";
+        let tx = db.begin_tx();
+        let query = compile_read_only_query(&db, &tx, sql)?.pop().unwrap();
+        let unit = ExecutionUnit::new(query, QueryHash::from_string(sql))?;
+
+        // Only the selected columns are described...
+        assert_eq!(unit.columns(), [7, 3]);
+        assert_eq!(unit.return_header().fields.len(), 2);
+
+        // ...and serialized.
+        let ctx = ExecutionContext::subscribe(db.address(), SlowQueryConfig::default());
+        let update = unit.eval_binary(&ctx, &db, &tx, sql)?.unwrap();
+        assert_eq!(update.columns, [7, 3]);
+        assert_eq!(update.table_row_operations.len(), 1);
+        assert_eq!(update.table_row_operations[0].row, to_vec(&product!(7u64, 3u64))?);
+
+        // Selecting every column sends whole rows.
+        let sql = "SELECT * FROM wide";
+        let query = compile_read_only_query(&db, &tx, sql)?.pop().unwrap();
+        let unit = ExecutionUnit::new(query, QueryHash::from_string(sql))?;
+        assert!(unit.columns().is_empty());
+        let update = unit.eval_binary(&ctx, &db, &tx, sql)?.unwrap();
+        assert_eq!(update.table_row_operations[0].row, to_vec(&row)?);
+
+        Ok(())
+    }
+
     #[test]
     fn test_classify() -> ResultTest<()> {
         let db = TestDB::durable()?;
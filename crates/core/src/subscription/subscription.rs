@@ -43,9 +43,9 @@ use spacetimedb_primitives::TableId;
 use spacetimedb_sats::db::auth::{StAccess, StTableType};
 use spacetimedb_sats::relation::DbTable;
 use spacetimedb_vm::errors::ErrorVm;
-use spacetimedb_vm::expr::{self, IndexJoin, Query, QueryExpr, SourceProvider, SourceSet};
+use spacetimedb_vm::expr::{self, Query, QueryExpr, SourceProvider, SourceSet};
 use spacetimedb_vm::rel_ops::RelOps;
-use spacetimedb_vm::relation::{MemTable, RelValue};
+use spacetimedb_vm::relation::RelValue;
 use std::hash::Hash;
 use std::iter;
 use std::ops::Deref;
@@ -180,16 +180,6 @@ pub struct IncrementalJoin {
 }
 
 impl IncrementalJoin {
-    fn optimize_query(join: IndexJoin) -> QueryExpr {
-        let expr = QueryExpr::from(join);
-        // Because (at least) one of the two tables will be a `MemTable`,
-        // and therefore not have indexes,
-        // the `row_count` function we pass to `optimize` is useless;
-        // either the `DbTable` must be used as the index side,
-        // or for the `A- join B-` case, the join must be rewritten to not use indexes.
-        expr.optimize(&|_, _| 0)
-    }
-
     /// Return the query plan where the lhs is a delta table.
     fn plan_for_delta_lhs(&self) -> &QueryExpr {
         if self.return_index_rows {
@@ -232,17 +222,11 @@ impl IncrementalJoin {
             .context("expected a physical database table")?
             .clone();
 
-        let (virtual_index_plan, _sources) = with_delta_table(join.clone(), Some(Vec::new()), None);
-        debug_assert_eq!(_sources.len(), 1);
-        let virtual_index_plan = Self::optimize_query(virtual_index_plan);
-
-        let (virtual_probe_plan, _sources) = with_delta_table(join.clone(), None, Some(Vec::new()));
-        debug_assert_eq!(_sources.len(), 1);
-        let virtual_probe_plan = Self::optimize_query(virtual_probe_plan);
-
-        let (virtual_plan, _sources) = with_delta_table(join.clone(), Some(Vec::new()), Some(Vec::new()));
-        debug_assert_eq!(_sources.len(), 2);
-        let virtual_plan = virtual_plan.to_inner_join();
+        let Ok([virtual_index_plan, virtual_probe_plan, virtual_plan]) =
+            <[_; 3]>::try_from(join.clone().incremental_plans())
+        else {
+            unreachable!("an index join has three incremental plans")
+        };
 
         let return_index_rows = join.return_index_rows;
 
@@ -495,30 +479,6 @@ impl IncrementalJoin {
     }
 }
 
-/// Replace an [IndexJoin]'s scan or fetch operation with a delta table.
-/// A delta table consists purely of updates or changes to the base table.
-fn with_delta_table(
-    mut join: IndexJoin,
-    index_side: Option<Vec<ProductValue>>,
-    probe_side: Option<Vec<ProductValue>>,
-) -> (IndexJoin, SourceSet<Vec<ProductValue>, 2>) {
-    let mut sources = SourceSet::empty();
-
-    if let Some(index_side) = index_side {
-        let head = join.index_side.head().clone();
-        let table_access = join.index_side.table_access();
-        join.index_side = sources.add_mem_table(MemTable::new(head, table_access, index_side));
-    }
-
-    if let Some(probe_side) = probe_side {
-        let head = join.probe_side.source.head().clone();
-        let table_access = join.probe_side.source.table_access();
-        join.probe_side.source = sources.add_mem_table(MemTable::new(head, table_access, probe_side));
-    }
-
-    (join, sources)
-}
-
 /// A set of independent single or multi-query execution units.
 #[derive(Debug, PartialEq, Eq)]
 pub struct ExecutionSet {
@@ -640,9 +600,10 @@ mod tests {
     use crate::db::relational_db::tests_utils::TestDB;
     use crate::sql::compiler::compile_sql;
     use spacetimedb_lib::error::ResultTest;
+    use spacetimedb_primitives::ColList;
     use spacetimedb_sats::relation::{DbTable, FieldName};
     use spacetimedb_sats::{product, AlgebraicType};
-    use spacetimedb_vm::expr::{CrudExpr, IndexJoin, Query, SourceExpr};
+    use spacetimedb_vm::expr::{CrudExpr, IndexJoin, JoinKind, Query, SourceExpr};
 
     #[test]
     // Compile an index join after replacing the index side with a virtual table.
@@ -703,16 +664,14 @@ mod tests {
                     source: SourceExpr::InMemory { .. },
                     query: ref lhs,
                 },
-            probe_field: FieldName {
-                table: probe_table,
-                col: probe_field,
-            },
+            probe_fields,
             index_side: SourceExpr::DbTable(DbTable {
                 table_id: index_table, ..
             }),
             index_select: Some(_),
-            index_col,
+            index_cols,
             return_index_rows: false,
+            projection: None,
         } = join
         else {
             panic!("unexpected index join {:#?}", join);
@@ -722,9 +681,8 @@ mod tests {
 
         // Assert that original index and probe tables have been swapped.
         assert_eq!(index_table, rhs_id);
-        assert_eq!(index_col, 0.into());
-        assert_eq!(probe_field, 1.into());
-        assert_eq!(probe_table, lhs_id);
+        assert_eq!(index_cols, ColList::new(0.into()));
+        assert_eq!(probe_fields[..], [FieldName::new(lhs_id, 1.into())]);
         Ok(())
     }
 
@@ -789,16 +747,14 @@ mod tests {
                     source: SourceExpr::InMemory { .. },
                     query: ref rhs,
                 },
-            probe_field: FieldName {
-                table: probe_table,
-                col: probe_field,
-            },
+            probe_fields,
             index_side: SourceExpr::DbTable(DbTable {
                 table_id: index_table, ..
             }),
             index_select: None,
-            index_col,
+            index_cols,
             return_index_rows: true,
+            projection: None,
         } = join
         else {
             panic!("unexpected index join {:#?}", join);
@@ -808,9 +764,8 @@ mod tests {
 
         // Assert that original index and probe tables have not been swapped.
         assert_eq!(index_table, lhs_id);
-        assert_eq!(index_col, 1.into());
-        assert_eq!(probe_field, 0.into());
-        assert_eq!(probe_table, rhs_id);
+        assert_eq!(index_cols, ColList::new(1.into()));
+        assert_eq!(probe_fields[..], [FieldName::new(rhs_id, 0.into())]);
         Ok(())
     }
 
@@ -870,6 +825,6 @@ mod tests {
         };
         assert!(incr_join.rhs.source.is_mem_table());
         assert_ne!(incr_join.rhs.source.head(), expr.source.head());
-        assert!(incr_join.semi);
+        assert_eq!(incr_join.kind, JoinKind::Semi);
     }
 }
@@ -1,11 +1,11 @@
 //! The [DbProgram] that execute arbitrary queries & code against the database.
 
-use crate::db::cursor::{IndexCursor, TableCursor};
+use crate::db::cursor::{IndexCursor, IndexKeyCursor, TableCursor};
 use crate::db::datastore::locking_tx_datastore::IterByColRange;
 use crate::db::relational_db::{MutTx, RelationalDB, Tx};
-use crate::execution_context::ExecutionContext;
+use crate::execution_context::{ExecutionContext, MetricType};
 use core::ops::RangeBounds;
-use itertools::Itertools;
+use smallvec::SmallVec;
 use spacetimedb_data_structures::map::HashMap;
 use spacetimedb_lib::identity::AuthCtx;
 use spacetimedb_primitives::*;
@@ -13,12 +13,13 @@ use spacetimedb_sats::db::def::TableDef;
 use spacetimedb_sats::relation::{DbTable, FieldExpr, FieldName, Header, RowCount};
 use spacetimedb_sats::{AlgebraicValue, ProductValue};
 use spacetimedb_vm::errors::ErrorVm;
-use spacetimedb_vm::eval::{join_inner, IterRows};
+use spacetimedb_vm::eval::{join_inner, join_left, semi_join_in_memory, IterRows};
 use spacetimedb_vm::expr::*;
 use spacetimedb_vm::iterators::RelIter;
 use spacetimedb_vm::program::{ProgramVm, Sources};
-use spacetimedb_vm::rel_ops::{EmptyRelOps, RelOps};
-use spacetimedb_vm::relation::{MemTable, RelValue};
+use spacetimedb_vm::rel_ops::{Concat, EmptyRelOps, MergeJoin, RelOps};
+use spacetimedb_vm::relation::{MemTable, RelValue, RowStream};
+use std::borrow::Cow;
 use std::ops::Bound;
 use std::sync::Arc;
 
@@ -90,7 +91,18 @@ pub fn build_query<'a>(
 
     for op in &query.query {
         result = Some(match op {
-            Query::IndexScan(IndexScan { table, columns, bounds }) if db_table => {
+            // A full range scan filters nothing and needs no index order,
+            // so read the table sequentially instead of traversing the index.
+            Query::IndexScan(index_scan) if index_scan.is_full_range() => result
+                .take()
+                .map(Ok)
+                .unwrap_or_else(|| get_table(ctx, stdb, tx, &query.source, sources))?,
+            Query::IndexScan(IndexScan {
+                table,
+                columns,
+                bounds,
+                covering,
+            }) if db_table => {
                 if !bound_is_satisfiable(&bounds.0, &bounds.1) {
                     // If the bound is impossible to satisfy
                     // because the lower bound is greater than the upper bound, or both bounds are excluded and equal,
@@ -98,9 +110,16 @@ pub fn build_query<'a>(
                     // This avoids a panic in `BTreeMap`'s `NodeRef::search_tree_for_bifurcation`,
                     // which is very unhappy about unsatisfiable bounds.
                     Box::new(EmptyRelOps::new(table.head.clone())) as Box<IterRows<'a>>
+                } else if let Some(keys) = covering
+                    .then(|| index_key_cursor(ctx, tx, table, columns, bounds))
+                    .transpose()?
+                    .flatten()
+                {
+                    keys
                 } else {
                     let bounds = (bounds.start_bound(), bounds.end_bound());
-                    iter_by_col_range(ctx, stdb, tx, table, columns.clone(), bounds)?
+                    let cursor = index_cursor(ctx, stdb, tx, table, columns.clone(), bounds)?;
+                    Box::new(cursor.for_lookup()) as Box<IterRows<'a>>
                 }
             }
             Query::IndexScan(index_scan) => {
@@ -118,47 +137,46 @@ pub fn build_query<'a>(
                     // return an empty iterator.
                     // Unlike the above case, this is not necessary, as the below `select` will never panic,
                     // but it's still nice to avoid needlessly traversing a bunch of rows.
-                    // TODO: We should change the compiler to not emit an `IndexScan` in this case,
-                    // so that this branch is unreachable.
-                    // The current behavior is a hack
-                    // because this patch was written (2024-04-01 pgoldman) a short time before the BitCraft alpha,
-                    // and a more invasive change was infeasible.
+                    // Optimized queries contain a `Query::NeverReturns` instead of such scans,
+                    // but unoptimized ones may still reach this branch.
                     Box::new(EmptyRelOps::new(index_scan.table.head.clone())) as Box<IterRows<'a>>
-                } else if cols.is_singleton() {
-                    // For singleton constraints, we compare the column directly against `bounds`.
-                    let head = cols.head().idx();
-                    let iter = result.select(move |row| Ok(bounds.contains(&*row.read_column(head).unwrap())));
-                    Box::new(iter) as Box<IterRows<'a>>
                 } else {
-                    // For multi-col constraints, these are stored as bounds of product values,
-                    // so we need to project these into single-col bounds and compare against the column.
-                    // Project start/end `Bound<AV>`s to `Bound<Vec<AV>>`s.
-                    let start_bound = bounds.0.as_ref().map(|av| &av.as_product().unwrap().elements);
-                    let end_bound = bounds.1.as_ref().map(|av| &av.as_product().unwrap().elements);
-                    // Construct the query:
-                    let iter = result.select(move |row| {
-                        // Go through each column position,
-                        // project to a `Bound<AV>` for the position,
-                        // and compare against the column in the row.
-                        // All columns must match to include the row,
-                        // which is essentially the same as a big `AND` of `ColumnOp`s.
-                        Ok(cols.iter().enumerate().all(|(idx, col)| {
-                            let start_bound = start_bound.map(|pv| &pv[idx]);
-                            let end_bound = end_bound.map(|pv| &pv[idx]);
-                            let read_col = row.read_column(col.idx()).unwrap();
-                            (start_bound, end_bound).contains(&*read_col)
-                        }))
-                    });
-                    Box::new(iter)
+                    let iter = result.select(move |row| Ok(index_bounds_contain(row, cols, bounds)));
+                    Box::new(iter) as Box<IterRows<'a>>
                 }
             }
+            Query::IndexMultiScan(IndexMultiScan { table, columns, ranges }) if db_table => {
+                // Skip the unsatisfiable ranges for the same reason as for `IndexScan` above.
+                let iters = ranges
+                    .iter()
+                    .filter(|bounds| bound_is_satisfiable(&bounds.0, &bounds.1))
+                    .map(|bounds| {
+                        let bounds = (bounds.start_bound(), bounds.end_bound());
+                        iter_by_col_range(ctx, stdb, tx, table, columns.clone(), bounds)
+                    })
+                    .collect::<Result<Vec<_>, _>>()?;
+                Box::new(Concat::new(table.head.clone(), iters)) as Box<IterRows<'a>>
+            }
+            Query::IndexMultiScan(index_scan) => {
+                let result = result
+                    .take()
+                    .map(Ok)
+                    .unwrap_or_else(|| get_table(ctx, stdb, tx, &query.source, sources))?;
+
+                let cols = &index_scan.columns;
+                let ranges = &index_scan.ranges;
+                let iter =
+                    result.select(move |row| Ok(ranges.iter().any(|bounds| index_bounds_contain(row, cols, bounds))));
+                Box::new(iter)
+            }
             Query::IndexJoin(IndexJoin {
                 probe_side,
-                probe_field,
+                probe_fields,
                 index_side,
                 index_select,
-                index_col,
+                index_cols,
                 return_index_rows,
+                projection,
             }) => {
                 if result.is_some() {
                     return Err(anyhow::anyhow!("Invalid query: `IndexJoin` must be the first operator").into());
@@ -168,24 +186,53 @@ pub fn build_query<'a>(
                 let index_table = index_side.table_id().unwrap();
                 let index_header = index_side.head();
                 let probe_side = build_query(ctx, stdb, tx, probe_side, sources)?;
-                let probe_col = probe_side
-                    .head()
-                    .column_pos(*probe_field)
-                    .expect("query compiler should have ensured the column exist");
+                let probe_cols = probe_fields
+                    .iter()
+                    .map(|field| {
+                        probe_side
+                            .head()
+                            .column_pos(*field)
+                            .expect("query compiler should have ensured the column exist")
+                    })
+                    .collect();
+                let projection = match projection {
+                    Some(cols) => {
+                        let head = if *return_index_rows {
+                            index_header
+                        } else {
+                            probe_side.head()
+                        };
+                        Some((&cols[..], Arc::new(head.project(&cols[..])?)))
+                    }
+                    None => None,
+                };
                 Box::new(IndexSemiJoin {
                     ctx,
                     db: stdb,
                     tx,
                     probe_side,
-                    probe_col,
+                    probe_cols,
                     index_header,
                     index_select,
                     index_table,
-                    index_col: *index_col,
+                    index_cols,
                     index_iter: None,
                     return_index_rows: *return_index_rows,
+                    projection,
+                    num_index_seeks: 0,
+                    num_rows_fetched: 0,
                 })
             }
+            Query::MergeJoin(join) => {
+                if result.is_some() {
+                    return Err(anyhow::anyhow!("Invalid query: `MergeJoin` must be the first operator").into());
+                }
+                let lhs = iter_in_index_order(ctx, stdb, tx, &join.lhs, join.col_lhs)?;
+                let rhs = iter_in_index_order(ctx, stdb, tx, &join.rhs, join.col_rhs)?;
+                let col_lhs = lhs.head().column_pos_or_err(join.col_lhs)?;
+                let col_rhs = rhs.head().column_pos_or_err(join.col_rhs)?;
+                Box::new(MergeJoin::new(lhs, rhs, col_lhs.idx(), col_rhs.idx(), join.semi))
+            }
             Query::Select(cmp) => {
                 let result = result
                     .take()
@@ -195,19 +242,26 @@ pub fn build_query<'a>(
                 let iter = result.select(move |row| cmp.compare(row, &header));
                 Box::new(iter)
             }
-            Query::Project(cols, _) => {
+            Query::Project(cols, _, distinct) => {
                 let result = result
                     .take()
                     .map(Ok)
                     .unwrap_or_else(|| get_table(ctx, stdb, tx, &query.source, sources))?;
-                if cols.is_empty() {
+                let result: Box<IterRows<'_>> = if cols.is_empty() {
                     result
                 } else {
                     let header = result.head().clone();
                     let iter = result.project(cols, move |cols, row| {
-                        Ok(RelValue::Projection(row.project_owned(cols, &header)?))
+                        Ok(RelValue::Projection(
+                            row.project_owned(cols.iter().map(|(col, _)| col), &header)?,
+                        ))
                     })?;
                     Box::new(iter)
+                };
+                if *distinct {
+                    Box::new(result.distinct())
+                } else {
+                    result
                 }
             }
             Query::JoinInner(join) => {
@@ -218,6 +272,43 @@ pub fn build_query<'a>(
                 let rhs = build_query(ctx, stdb, tx, &join.rhs, sources)?;
                 join_inner(lhs, rhs, join)?
             }
+            Query::JoinLeft(join) => {
+                let lhs = result
+                    .take()
+                    .map(Ok)
+                    .unwrap_or_else(|| get_table(ctx, stdb, tx, &query.source, sources))?;
+                let rhs = build_query(ctx, stdb, tx, &join.rhs, sources)?;
+                join_left(lhs, rhs, join)?
+            }
+            Query::SemiJoinInMemory { source, probe_field } => {
+                let lhs = result
+                    .take()
+                    .map(Ok)
+                    .unwrap_or_else(|| get_table(ctx, stdb, tx, &query.source, sources))?;
+                let rhs = get_table(ctx, stdb, tx, source, sources)?;
+                semi_join_in_memory(lhs, rhs, *probe_field)?
+            }
+            Query::Unnest(unnest) => {
+                let result = result
+                    .take()
+                    .map(Ok)
+                    .unwrap_or_else(|| get_table(ctx, stdb, tx, &query.source, sources))?;
+                Box::new(result.unnest(unnest.field, unnest.outer)?)
+            }
+            Query::NeverReturns => {
+                // Don't fetch the source if it's still to be read, as none of its rows are returned.
+                let head = result
+                    .as_ref()
+                    .map_or_else(|| query.source.head(), |result| result.head());
+                Box::new(EmptyRelOps::new(head.clone()))
+            }
+            Query::Limit { limit, offset } => {
+                let result = result
+                    .take()
+                    .map(Ok)
+                    .unwrap_or_else(|| get_table(ctx, stdb, tx, &query.source, sources))?;
+                Box::new(result.limit(*limit, *offset))
+            }
         })
     }
 
@@ -226,6 +317,35 @@ pub fn build_query<'a>(
         .unwrap_or_else(|| get_table(ctx, stdb, tx, &query.source, sources))
 }
 
+/// Returns whether the columns `cols` of `row` are within the index `bounds`.
+fn index_bounds_contain(
+    row: &RelValue<'_>,
+    cols: &ColList,
+    bounds: &(Bound<AlgebraicValue>, Bound<AlgebraicValue>),
+) -> bool {
+    if cols.is_singleton() {
+        // For singleton constraints, we compare the column directly against `bounds`.
+        return bounds.contains(&*row.read_column(cols.head().idx()).unwrap());
+    }
+
+    // For multi-col constraints, these are stored as bounds of product values,
+    // so we need to project these into single-col bounds and compare against the column.
+    // Project start/end `Bound<AV>`s to `Bound<Vec<AV>>`s.
+    let start_bound = bounds.0.as_ref().map(|av| &av.as_product().unwrap().elements);
+    let end_bound = bounds.1.as_ref().map(|av| &av.as_product().unwrap().elements);
+    // Go through each column position,
+    // project to a `Bound<AV>` for the position,
+    // and compare against the column in the row.
+    // All columns must match to include the row,
+    // which is essentially the same as a big `AND` of `ColumnOp`s.
+    cols.iter().enumerate().all(|(idx, col)| {
+        let start_bound = start_bound.map(|pv| &pv[idx]);
+        let end_bound = end_bound.map(|pv| &pv[idx]);
+        let read_col = row.read_column(col.idx()).unwrap();
+        (start_bound, end_bound).contains(&*read_col)
+    })
+}
+
 /// Resolve `query` to a table iterator,
 /// either taken from an in-memory table, in the case of [`SourceExpr::InMemory`],
 /// or from a physical table, in the case of [`SourceExpr::DbTable`].
@@ -255,7 +375,7 @@ fn get_table<'a>(
                 TxMode::MutTx(tx) => stdb.iter_mut(ctx, tx, x.table_id)?,
                 TxMode::Tx(tx) => stdb.iter(ctx, tx, x.table_id)?,
             };
-            Box::new(TableCursor::new(x.clone(), iter)?) as Box<IterRows<'_>>
+            Box::new(TableCursor::new(ctx, x.clone(), iter)?) as Box<IterRows<'_>>
         }
     })
 }
@@ -273,19 +393,80 @@ fn in_mem_to_rel_ops<'a>(
     Box::new(RelIter::new(head, rc, source)) as Box<IterRows<'a>>
 }
 
-fn iter_by_col_range<'a>(
+/// Returns a cursor over the rows of `table` whose `columns` are within `range`.
+fn index_cursor<'a, R: RangeBounds<AlgebraicValue> + 'a>(
     ctx: &'a ExecutionContext,
     db: &'a RelationalDB,
     tx: &'a TxMode,
     table: &'a DbTable,
     columns: ColList,
-    range: impl RangeBounds<AlgebraicValue> + 'a,
-) -> Result<Box<dyn RelOps<'a> + 'a>, ErrorVm> {
+    range: R,
+) -> Result<IndexCursor<'a, R>, ErrorVm> {
     let iter = match tx {
         TxMode::MutTx(tx) => db.iter_by_col_range_mut(ctx, tx, table.table_id, columns, range)?,
         TxMode::Tx(tx) => db.iter_by_col_range(ctx, tx, table.table_id, columns, range)?,
     };
-    Ok(Box::new(IndexCursor::new(table, iter)?) as Box<IterRows<'_>>)
+    Ok(IndexCursor::new(ctx, table, iter)?)
+}
+
+/// Returns the keys of the index on exactly `columns` of `table` within `bounds`,
+/// as rows of the indexed columns, for a covering scan to read instead of the rows of `table`.
+///
+/// Returns `None` when the scan has to fetch the rows after all:
+/// for a mutable transaction, as its inserts and deletes aren't reflected in the committed index,
+/// and for a scan on the leading columns of a wider index.
+fn index_key_cursor<'a>(
+    ctx: &'a ExecutionContext,
+    tx: &'a TxMode,
+    table: &'a DbTable,
+    columns: &ColList,
+    bounds: &(Bound<AlgebraicValue>, Bound<AlgebraicValue>),
+) -> Result<Option<Box<IterRows<'a>>>, ErrorVm> {
+    let TxMode::Tx(tx) = tx else {
+        return Ok(None);
+    };
+    let Some(iter) = tx.index_keys_by_col_range(table.table_id, columns, bounds) else {
+        return Ok(None);
+    };
+    let fields = columns
+        .iter()
+        .map(|col| FieldName::new(table.table_id, col))
+        .collect::<Vec<_>>();
+    let head = Arc::new(table.head.project(&fields[..])?);
+    Ok(Some(Box::new(IndexKeyCursor::new(ctx, table, head, iter))))
+}
+
+fn iter_by_col_range<'a>(
+    ctx: &'a ExecutionContext,
+    db: &'a RelationalDB,
+    tx: &'a TxMode,
+    table: &'a DbTable,
+    columns: ColList,
+    range: impl RangeBounds<AlgebraicValue> + 'a,
+) -> Result<Box<dyn RelOps<'a> + 'a>, ErrorVm> {
+    Ok(Box::new(index_cursor(ctx, db, tx, table, columns, range)?) as Box<IterRows<'_>>)
+}
+
+/// Returns the rows of `table` sorted by `field`, using the index on exactly `field`.
+fn iter_in_index_order<'a>(
+    ctx: &'a ExecutionContext,
+    db: &'a RelationalDB,
+    tx: &'a TxMode,
+    table: &'a DbTable,
+    field: FieldName,
+) -> Result<Box<IterRows<'a>>, ErrorVm> {
+    let col = table.head.column_pos_or_err(field)?;
+    let iter = iter_by_col_range(ctx, db, tx, table, col.into(), ..)?;
+    Ok(match tx {
+        TxMode::Tx(_) => iter,
+        // The rows inserted by a mutable transaction are yielded after the committed ones,
+        // rather than in index order, so sort them all.
+        TxMode::MutTx(_) => {
+            let mut rows = iter.collect_vec(|row| row)?;
+            rows.sort_by_cached_key(|row| row.read_column(col.idx()).unwrap().into_owned());
+            Box::new(RelIter::new(table.head.clone(), RowCount::exact(rows.len()), rows))
+        }
+    })
 }
 
 /// An index join operator that returns matching rows from the index side.
@@ -293,18 +474,22 @@ pub struct IndexSemiJoin<'a, 'c, Rhs: RelOps<'a>> {
     /// An iterator for the probe side.
     /// The values returned will be used to probe the index.
     pub probe_side: Rhs,
-    /// The column whose value will be used to probe the index.
-    pub probe_col: ColId,
+    /// The columns whose values will be used to probe the index,
+    /// in the order of `index_cols`.
+    pub probe_cols: SmallVec<[ColId; 2]>,
     /// The header for the index side of the join.
     pub index_header: &'c Arc<Header>,
     /// An optional predicate to evaluate over the matching rows of the index.
     pub index_select: &'c Option<ColumnOp>,
     /// The table id on which the index is defined.
     pub index_table: TableId,
-    /// The column id for which the index is defined.
-    pub index_col: ColId,
+    /// The columns for which the index is defined.
+    pub index_cols: &'c ColList,
     /// Is this a left or right semijoin?
     pub return_index_rows: bool,
+    /// The columns of the returned rows to emit, if not all of them,
+    /// and the header of the rows they make up.
+    pub projection: Option<(&'c [FieldExpr], Arc<Header>)>,
     /// An iterator for the index side.
     /// A new iterator will be instantiated for each row on the probe side.
     pub index_iter: Option<IterByColRange<'a, AlgebraicValue>>,
@@ -314,6 +499,29 @@ pub struct IndexSemiJoin<'a, 'c, Rhs: RelOps<'a>> {
     pub tx: &'a TxMode<'a>,
     /// The execution context for the current transaction.
     ctx: &'a ExecutionContext,
+    /// The number of times the index was probed.
+    num_index_seeks: u64,
+    /// The number of rows fetched from the index side.
+    num_rows_fetched: u64,
+}
+
+impl<'a, Rhs: RelOps<'a>> Drop for IndexSemiJoin<'a, '_, Rhs> {
+    fn drop(&mut self) {
+        let mut metrics = self.ctx.metrics.write();
+        let get_table_name = || self.index_header.table_name.to_string();
+        metrics.inc_by(
+            self.index_table,
+            MetricType::IndexSeeks,
+            self.num_index_seeks,
+            get_table_name,
+        );
+        metrics.inc_by(
+            self.index_table,
+            MetricType::RowsFetched,
+            self.num_rows_fetched,
+            get_table_name,
+        );
+    }
 }
 
 impl<'a, Rhs: RelOps<'a>> IndexSemiJoin<'a, '_, Rhs> {
@@ -325,50 +533,76 @@ impl<'a, Rhs: RelOps<'a>> IndexSemiJoin<'a, '_, Rhs> {
         })
     }
 
-    fn map(&self, index_row: RelValue<'a>, probe_row: Option<RelValue<'a>>) -> RelValue<'a> {
-        if let Some(value) = probe_row {
-            if !self.return_index_rows {
-                return value;
-            }
-        }
-        index_row
+    fn map(&self, index_row: RelValue<'a>, probe_row: Option<RelValue<'a>>) -> Result<RelValue<'a>, ErrorVm> {
+        let row = match probe_row {
+            Some(value) if !self.return_index_rows => value,
+            _ => index_row,
+        };
+        let Some((cols, _)) = &self.projection else {
+            return Ok(row);
+        };
+        Ok(RelValue::Projection(
+            row.project_owned(cols.iter(), self.returned_head())?,
+        ))
     }
-}
 
-impl<'a, Rhs: RelOps<'a>> RelOps<'a> for IndexSemiJoin<'a, '_, Rhs> {
-    fn head(&self) -> &Arc<Header> {
+    /// Returns the header of the returned rows, before any projection.
+    fn returned_head(&self) -> &Arc<Header> {
         if self.return_index_rows {
             self.index_header
         } else {
             self.probe_side.head()
         }
     }
+}
+
+impl<'a, Rhs: RelOps<'a>> RelOps<'a> for IndexSemiJoin<'a, '_, Rhs> {
+    fn head(&self) -> &Arc<Header> {
+        match &self.projection {
+            Some((_, head)) => head,
+            None => self.returned_head(),
+        }
+    }
 
     fn next(&mut self) -> Result<Option<RelValue<'a>>, ErrorVm> {
         // Return a value from the current index iterator, if not exhausted.
         if self.return_index_rows {
             while let Some(value) = self.index_iter.as_mut().and_then(|iter| iter.next()) {
+                self.num_rows_fetched += 1;
                 let value = RelValue::Row(value);
                 if self.filter(&value)? {
-                    return Ok(Some(self.map(value, None)));
+                    return Ok(Some(self.map(value, None)?));
                 }
             }
         }
 
         // Otherwise probe the index with a row from the probe side.
         let table_id = self.index_table;
-        let col_id = self.index_col;
+        let cols = self.index_cols;
         while let Some(mut row) = self.probe_side.next()? {
-            if let Some(value) = row.read_or_take_column(self.probe_col.idx()) {
+            let value = match &*self.probe_cols {
+                [col] => row.read_or_take_column(col.idx()),
+                // A composite index is probed with the product of the values of its columns.
+                probe_cols => probe_cols
+                    .iter()
+                    .map(|col| row.read_column(col.idx()).map(Cow::into_owned))
+                    .collect::<Option<ProductValue>>()
+                    .map(AlgebraicValue::product),
+            };
+            if let Some(value) = value {
                 let mut index_iter = match self.tx {
-                    TxMode::MutTx(tx) => self.db.iter_by_col_range_mut(self.ctx, tx, table_id, col_id, value)?,
-                    TxMode::Tx(tx) => self.db.iter_by_col_range(self.ctx, tx, table_id, col_id, value)?,
+                    TxMode::MutTx(tx) => self
+                        .db
+                        .iter_by_col_range_mut(self.ctx, tx, table_id, cols.clone(), value)?,
+                    TxMode::Tx(tx) => self.db.iter_by_col_range(self.ctx, tx, table_id, cols.clone(), value)?,
                 };
+                self.num_index_seeks += 1;
                 while let Some(value) = index_iter.next() {
+                    self.num_rows_fetched += 1;
                     let value = RelValue::Row(value);
                     if self.filter(&value)? {
                         self.index_iter = Some(index_iter);
-                        return Ok(Some(self.map(value, Some(row))));
+                        return Ok(Some(self.map(value, Some(row))?));
                     }
                 }
             }
@@ -377,6 +611,10 @@ impl<'a, Rhs: RelOps<'a>> RelOps<'a> for IndexSemiJoin<'a, '_, Rhs> {
     }
 }
 
+/// The estimated number of rows above which [`DbProgram`] streams the result of a query,
+/// rather than materializing it; see [`ProgramVm::eval_query_stream`].
+pub const STREAM_MIN_ROWS: usize = 1024;
+
 /// A [ProgramVm] implementation that carry a [RelationalDB] for it
 /// query execution
 pub struct DbProgram<'db, 'tx> {
@@ -415,7 +653,8 @@ impl<'db, 'tx> DbProgram<'db, 'tx> {
     fn _execute_update<const N: usize>(
         &mut self,
         delete: &QueryExpr,
-        mut assigns: HashMap<FieldName, FieldExpr>,
+        mut assigns: HashMap<FieldName, AssignExpr>,
+        expected_max_rows: Option<u64>,
         sources: Sources<'_, N>,
     ) -> Result<Code, ErrorVm> {
         let result = self._eval_query(delete, sources)?;
@@ -427,32 +666,30 @@ impl<'db, 'tx> DbProgram<'db, 'tx> {
             .source
             .get_db_table()
             .expect("source for Update should be a DbTable");
-
-        self._execute_delete(table.table_id, deleted.data.clone());
+        check_expected_max_rows(&table.head.table_name, expected_max_rows, deleted.data.len())?;
 
         // Replace the columns in the matched rows with the assigned
-        // values. No typechecking is performed here, nor that all
+        // values, computed from the matched rows before any is deleted.
+        // No typechecking is performed here, nor that all
         // assignments are consumed.
-        let exprs: Vec<Option<FieldExpr>> = table.head.fields.iter().map(|col| assigns.remove(&col.field)).collect();
+        let exprs: Vec<Option<AssignExpr>> = table.head.fields.iter().map(|col| assigns.remove(&col.field)).collect();
         let insert_rows = deleted
             .data
-            .into_iter()
+            .iter()
             .map(|row| {
-                let elements = row
-                    .into_iter()
+                row.elements
+                    .iter()
                     .zip(&exprs)
-                    .map(|(val, expr)| {
-                        if let Some(FieldExpr::Value(assigned)) = expr {
-                            assigned.clone()
-                        } else {
-                            val
-                        }
+                    .map(|(val, expr)| match expr {
+                        Some(expr) => expr.eval(&RelValue::ProjRef(row), &table.head),
+                        None => Ok(val.clone()),
                     })
-                    .collect();
-
-                ProductValue { elements }
+                    .collect::<Result<_, _>>()
+                    .map(|elements| ProductValue { elements })
             })
-            .collect_vec();
+            .collect::<Result<Vec<_>, ErrorVm>>()?;
+
+        self._execute_delete(table.table_id, deleted.data);
 
         self._execute_insert(table, insert_rows)
     }
@@ -461,12 +698,20 @@ impl<'db, 'tx> DbProgram<'db, 'tx> {
         self.db.delete_by_rel(self.tx.unwrap_mut(), table, rows)
     }
 
-    fn _delete_query<const N: usize>(&mut self, query: &QueryExpr, sources: Sources<'_, N>) -> Result<Code, ErrorVm> {
+    fn _delete_query<const N: usize>(
+        &mut self,
+        query: &QueryExpr,
+        expected_max_rows: Option<u64>,
+        sources: Sources<'_, N>,
+    ) -> Result<Code, ErrorVm> {
         match self._eval_query(query, sources)? {
-            Code::Table(result) => Ok(Code::Value(
-                self._execute_delete(query.source.table_id().unwrap(), result.data)
-                    .into(),
-            )),
+            Code::Table(result) => {
+                check_expected_max_rows(query.source.table_name(), expected_max_rows, result.data.len())?;
+                Ok(Code::Value(
+                    self._execute_delete(query.source.table_id().unwrap(), result.data)
+                        .into(),
+                ))
+            }
             r => Ok(r),
         }
     }
@@ -516,6 +761,22 @@ impl<'db, 'tx> DbProgram<'db, 'tx> {
     }
 }
 
+/// Returns an error if an `UPDATE` or `DELETE` of `table` would affect
+/// more than the `expected_max_rows`, if any.
+///
+/// This is checked before any rows are modified,
+/// and the error causes the enclosing transaction to be rolled back.
+fn check_expected_max_rows(table: &str, expected_max_rows: Option<u64>, affected: usize) -> Result<(), ErrorVm> {
+    match expected_max_rows {
+        Some(expected_max_rows) if affected as u64 > expected_max_rows => Err(ErrorVm::TooManyRows {
+            table: table.into(),
+            expected_max_rows,
+            affected: affected as u64,
+        }),
+        _ => Ok(()),
+    }
+}
+
 impl ProgramVm for DbProgram<'_, '_> {
     // Safety: For DbProgram with tx = TxMode::Tx variant, all queries must match to CrudCode::Query and no other branch.
     fn eval_query<const N: usize>(&mut self, query: CrudExpr, sources: Sources<'_, N>) -> Result<Code, ErrorVm> {
@@ -524,14 +785,43 @@ impl ProgramVm for DbProgram<'_, '_> {
         match query {
             CrudExpr::Query(query) => self._eval_query(&query, sources),
             CrudExpr::Insert { table, rows } => self._execute_insert(&table, rows),
-            CrudExpr::Update { delete, assignments } => self._execute_update(&delete, assignments, sources),
-            CrudExpr::Delete { query } => self._delete_query(&query, sources),
+            CrudExpr::Update {
+                delete,
+                assignments,
+                expected_max_rows,
+            } => self._execute_update(&delete, assignments, expected_max_rows, sources),
+            CrudExpr::Delete {
+                query,
+                expected_max_rows,
+            } => self._delete_query(&query, expected_max_rows, sources),
             CrudExpr::CreateTable { table } => self._create_table(table),
             CrudExpr::Drop { name, kind, .. } => self._drop(&name, kind),
             CrudExpr::SetVar { name, value } => self._set_config(name, value),
             CrudExpr::ReadVar { name } => self._read_config(name),
         }
     }
+
+    /// Only a result estimated to have more than [`STREAM_MIN_ROWS`] rows is streamed,
+    /// as a stream reads the tables as it is consumed, holding on to the transaction meanwhile.
+    fn eval_query_stream<'a, const N: usize>(
+        &'a mut self,
+        query: &'a QueryExpr,
+        sources: Sources<'_, N>,
+    ) -> Result<CodeResult<'a>, ErrorVm> {
+        query.check_auth(self.auth.owner, self.auth.caller)?;
+
+        let rows = query.estimated_output_rows(&|table_id, table_name| self.db.row_count(table_id, table_name));
+        if rows.max.is_some_and(|max| max <= STREAM_MIN_ROWS) {
+            return self._eval_query(query, sources).map(CodeResult::from);
+        }
+
+        tracing::trace!(table = query.source.table_name());
+        let result = build_query(self.ctx, self.db, self.tx, query, &mut |id| {
+            sources.take(id).map(|mt| mt.into_iter().map(RelValue::Projection))
+        })?;
+        let stream = RowStream::from_rel_ops(query.source.table_access(), result);
+        Ok(CodeResult::Stream(stream))
+    }
 }
 
 impl<'a> RelOps<'a> for TableCursor<'a> {
@@ -540,7 +830,9 @@ impl<'a> RelOps<'a> for TableCursor<'a> {
     }
 
     fn next(&mut self) -> Result<Option<RelValue<'a>>, ErrorVm> {
-        Ok(self.iter.next().map(RelValue::Row))
+        let row = self.iter.next();
+        self.num_rows_fetched += row.is_some() as u64;
+        Ok(row.map(RelValue::Row))
     }
 }
 
@@ -550,7 +842,32 @@ impl<'a, R: RangeBounds<AlgebraicValue>> RelOps<'a> for IndexCursor<'a, R> {
     }
 
     fn next(&mut self) -> Result<Option<RelValue<'a>>, ErrorVm> {
-        Ok(self.iter.next().map(RelValue::Row))
+        let row = self.iter.next();
+        self.num_rows_fetched += row.is_some() as u64;
+        self.exhausted |= row.is_none();
+        Ok(row.map(RelValue::Row))
+    }
+}
+
+impl<'a> RelOps<'a> for IndexKeyCursor<'a> {
+    fn head(&self) -> &Arc<Header> {
+        &self.head
+    }
+
+    fn next(&mut self) -> Result<Option<RelValue<'a>>, ErrorVm> {
+        let Some(key) = self.iter.next() else {
+            self.exhausted = true;
+            return Ok(None);
+        };
+        self.num_keys_yielded += 1;
+        // The key of a multi-column index is already a product of the values of its columns.
+        let row = if self.head.fields.len() == 1 {
+            ProductValue::from(key)
+        } else {
+            key.into_product()
+                .expect("the key of a multi-column index should be a product")
+        };
+        Ok(Some(RelValue::Projection(row)))
     }
 }
 
@@ -563,16 +880,19 @@ pub(crate) mod tests {
         ST_COLUMNS_NAME, ST_INDEXES_ID, ST_INDEXES_NAME, ST_SEQUENCES_ID, ST_SEQUENCES_NAME, ST_TABLES_ID,
         ST_TABLES_NAME,
     };
+    use crate::db::db_metrics::DB_METRICS;
     use crate::db::relational_db::tests_utils::TestDB;
+    use crate::error::DBError;
     use crate::execution_context::ExecutionContext;
     use spacetimedb_lib::error::ResultTest;
+    use spacetimedb_lib::Address;
     use spacetimedb_sats::db::auth::{StAccess, StTableType};
     use spacetimedb_sats::db::def::{ColumnDef, IndexDef, IndexType, TableSchema};
-    use spacetimedb_sats::relation::FieldName;
+    use spacetimedb_sats::relation::{FieldExpr, FieldName};
     use spacetimedb_sats::{product, AlgebraicType, ProductType, ProductValue};
     use spacetimedb_vm::eval::run_ast;
     use spacetimedb_vm::eval::test_helpers::{mem_table, mem_table_one_u64, scalar};
-    use spacetimedb_vm::operator::OpCmp;
+    use spacetimedb_vm::operator::{OpCmp, OpMath};
 
     pub(crate) fn create_table_with_rows(
         db: &RelationalDB,
@@ -790,4 +1110,284 @@ pub(crate) mod tests {
 
         Ok(())
     }
+
+    #[test]
+    fn test_query_point_lookup() -> ResultTest<()> {
+        let stdb = TestDB::durable()?;
+        let schema = st_table_schema();
+
+        let q = QueryExpr::point_lookup(
+            (&schema).into(),
+            StTableFields::TableId.col_id().into(),
+            scalar(ST_TABLES_ID),
+        )?;
+        let st_table_row = StTableRow {
+            table_id: ST_TABLES_ID,
+            table_name: ST_TABLES_NAME.into(),
+            table_type: StTableType::System,
+            table_access: StAccess::Public,
+        }
+        .into();
+        check_catalog(&stdb, ST_TABLES_NAME, st_table_row, q, &schema);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_full_range_index_scan_is_sequential() -> ResultTest<()> {
+        let db = TestDB::durable()?;
+
+        let (schema, row) = db.with_auto_commit(&ExecutionContext::default(), |tx| create_inv_table(&db, tx))?;
+        let index = IndexDef::btree("idx_1".into(), ColId(0), true);
+        db.with_auto_commit(&ExecutionContext::default(), |tx| {
+            db.create_index(tx, schema.table_id, index)
+        })?;
+
+        let mut q = QueryExpr::new(&*schema);
+        q.query.push(Query::IndexScan(IndexScan {
+            table: q.source.get_db_table().unwrap().clone(),
+            columns: ColId(0).into(),
+            bounds: (Bound::Unbounded, Bound::Unbounded),
+            covering: false,
+        }));
+
+        let ctx = ExecutionContext::default();
+        let result = db.with_read_only(&ctx, |tx| {
+            let mut tx_mode = (&*tx).into();
+            let p = &mut DbProgram::new(&ctx, &db, &mut tx_mode, AuthCtx::for_testing());
+            match run_ast(p, q.into(), [].into()) {
+                Code::Table(x) => x,
+                x => panic!("invalid result {x}"),
+            }
+        });
+        assert_eq!(result.data, [row]);
+
+        // The rows were fetched without seeking into the index.
+        let metrics = ctx.metrics.read();
+        assert_eq!(metrics.total(MetricType::IndexSeeks), 0);
+        assert_eq!(metrics.total(MetricType::RowsFetched), 1);
+        Ok(())
+    }
+
+    #[test]
+    fn test_covering_index_scan_fetches_no_rows() -> ResultTest<()> {
+        let db = TestDB::durable()?;
+
+        let (schema, _) = db.with_auto_commit(&ExecutionContext::default(), |tx| create_inv_table(&db, tx))?;
+        let index = IndexDef::btree("idx_1".into(), ColId(0), true);
+        db.with_auto_commit(&ExecutionContext::default(), |tx| {
+            db.create_index(tx, schema.table_id, index)
+        })?;
+        let id = FieldName::new(schema.table_id, 0.into());
+
+        // Returns the result of `SELECT inventory_id FROM inventory WHERE inventory_id >= 1`,
+        // and the number of index seeks and rows fetched to compute it.
+        let run = |covering: bool| {
+            let mut q = QueryExpr::new(&*schema);
+            q.query.push(Query::IndexScan(IndexScan {
+                table: q.source.get_db_table().unwrap().clone(),
+                columns: ColId(0).into(),
+                bounds: (Bound::Included(1u64.into()), Bound::Unbounded),
+                covering,
+            }));
+            let q = q.with_project(&[id.into()], None);
+
+            let ctx = ExecutionContext::default();
+            let result = db.with_read_only(&ctx, |tx| {
+                let mut tx_mode = (&*tx).into();
+                let p = &mut DbProgram::new(&ctx, &db, &mut tx_mode, AuthCtx::for_testing());
+                match run_ast(p, q.into(), [].into()) {
+                    Code::Table(x) => x,
+                    x => panic!("invalid result {x}"),
+                }
+            });
+            let metrics = ctx.metrics.read();
+            let accesses = (
+                metrics.total(MetricType::IndexSeeks),
+                metrics.total(MetricType::RowsFetched),
+            );
+            (result.data, accesses)
+        };
+
+        // A covering scan reads the ids from the index, without fetching the row.
+        assert_eq!(run(true), (vec![product![1u64]], (1, 0)));
+        assert_eq!(run(false), (vec![product![1u64]], (1, 1)));
+        Ok(())
+    }
+
+    #[test]
+    fn test_index_join_projection() -> ResultTest<()> {
+        let db = TestDB::durable()?;
+
+        let (schema, _) = db.with_auto_commit(&ExecutionContext::default(), |tx| create_inv_table(&db, tx))?;
+        let index = IndexDef::btree("idx_1".into(), ColId(0), true);
+        db.with_auto_commit(&ExecutionContext::default(), |tx| {
+            db.create_index(tx, schema.table_id, index)
+        })?;
+
+        let data = mem_table_one_u64(u32::MAX.into());
+        let probe_field = *data.get_field_pos(0).unwrap();
+        let mut sources = SourceSet::<_, 1>::empty();
+        let probe_side = sources.add_mem_table(data);
+        let name = FieldName::new(schema.table_id, 1.into());
+        let q = QueryExpr::from(IndexJoin {
+            probe_side: probe_side.into(),
+            probe_fields: [probe_field].into_iter().collect(),
+            index_side: (&*schema).into(),
+            index_select: None,
+            index_cols: ColId(0).into(),
+            return_index_rows: true,
+            projection: Some(vec![name.into()]),
+        });
+        let result = run_query(&db, q, sources);
+
+        // Only the projected column of the matching index row is emitted.
+        let fields: Vec<_> = result.head.fields.iter().map(|col| col.field).collect();
+        assert_eq!(fields, [name]);
+        assert_eq!(result.data, [product!["health"]]);
+        Ok(())
+    }
+
+    #[test]
+    fn test_full_table_scans_metric() -> ResultTest<()> {
+        let db = TestDB::durable()?;
+
+        let (schema, _) = db.with_auto_commit(&ExecutionContext::default(), |tx| create_inv_table(&db, tx))?;
+        let index = IndexDef::btree("idx_1".into(), ColId(0), true);
+        db.with_auto_commit(&ExecutionContext::default(), |tx| {
+            db.create_index(tx, schema.table_id, index)
+        })?;
+        let table = DbTable::from(&*schema);
+
+        // Returns the number of full table scans and index seeks of running `q`.
+        let count_accesses = |q: QueryExpr| {
+            let ctx = ExecutionContext::default();
+            db.with_read_only(&ctx, |tx| {
+                let mut tx_mode = (&*tx).into();
+                let p = &mut DbProgram::new(&ctx, &db, &mut tx_mode, AuthCtx::for_testing());
+                run_ast(p, q.into(), [].into());
+            });
+            let metrics = ctx.metrics.read();
+            (
+                metrics.total(MetricType::FullTableScans),
+                metrics.total(MetricType::IndexSeeks),
+            )
+        };
+
+        let name = FieldName::new(schema.table_id, 1.into());
+        let filter = QueryExpr::new(&*schema).with_select_cmp(OpCmp::Eq, name, scalar("health"));
+        assert_eq!(count_accesses(filter), (1, 0));
+
+        let lookup = QueryExpr::new(&*schema).with_index_eq(table, ColId(0).into(), 1u64.into());
+        assert_eq!(count_accesses(lookup), (0, 1));
+        Ok(())
+    }
+
+    #[test]
+    fn test_empty_index_scans_metric() -> ResultTest<()> {
+        let db = TestDB::durable()?;
+
+        let schema_ty = ProductType::from([("id", AlgebraicType::U64)]);
+        let [lhs, rhs] = db.with_auto_commit(&ExecutionContext::default(), |tx| -> ResultTest<_> {
+            let lhs = create_table_with_rows(&db, tx, "empty_scans_lhs", schema_ty.clone(), &[product![1u64]])?;
+            let rhs = create_table_with_rows(&db, tx, "empty_scans_rhs", schema_ty.clone(), &[])?;
+            for schema in [&lhs, &rhs] {
+                let index = IndexDef::btree(format!("idx_{}", schema.table_name).into(), ColId(0), true);
+                db.create_index(tx, schema.table_id, index)?;
+            }
+            Ok([lhs, rhs])
+        })?;
+        let [lhs_table, rhs_table] = [&lhs, &rhs].map(|schema| DbTable::from(&**schema));
+
+        let address = Address::from_u128(20);
+        // Returns the number of empty index scans of `lhs` and `rhs` recorded after running `q`.
+        let count_empty_scans = |q: QueryExpr| {
+            let ctx = ExecutionContext::internal(address);
+            db.with_read_only(&ctx, |tx| {
+                let mut tx_mode = (&*tx).into();
+                let p = &mut DbProgram::new(&ctx, &db, &mut tx_mode, AuthCtx::for_testing());
+                run_ast(p, q.into(), [].into());
+            });
+            [&lhs, &rhs].map(|schema| {
+                DB_METRICS
+                    .rdb_num_empty_index_scans
+                    .with_label_values(&address, &schema.table_id.0, &schema.table_name)
+                    .get()
+            })
+        };
+
+        // A point lookup that finds a row isn't counted, but one that doesn't is.
+        let hit = QueryExpr::new(&*lhs).with_index_eq(lhs_table.clone(), ColId(0).into(), 1u64.into());
+        assert_eq!(count_empty_scans(hit), [0, 0]);
+        let miss = QueryExpr::new(&*lhs).with_index_eq(lhs_table.clone(), ColId(0).into(), 2u64.into());
+        assert_eq!(count_empty_scans(miss), [1, 0]);
+
+        // Nor are the ranges of an `IndexMultiScan` that find no rows.
+        let mut multi_scan = QueryExpr::new(&*lhs);
+        multi_scan.query.push(Query::IndexMultiScan(IndexMultiScan {
+            table: lhs_table.clone(),
+            columns: ColId(0).into(),
+            ranges: [1u64, 2, 3]
+                .map(|v| (Bound::Included(v.into()), Bound::Included(v.into())))
+                .to_vec(),
+        }));
+        assert_eq!(count_empty_scans(multi_scan), [1, 0]);
+
+        // Nor the full range cursors of a merge join, even of an empty table.
+        let field = |schema: &TableSchema| FieldName::new(schema.table_id, ColId(0));
+        let mut merge_join = QueryExpr::new(&*lhs);
+        merge_join.query.push(Query::MergeJoin(MergeJoinExpr {
+            lhs: lhs_table,
+            rhs: rhs_table,
+            col_lhs: field(&lhs),
+            col_rhs: field(&rhs),
+            semi: false,
+        }));
+        assert_eq!(count_empty_scans(merge_join), [1, 0]);
+        Ok(())
+    }
+
+    #[test]
+    fn test_update_assignments() -> ResultTest<()> {
+        let db = TestDB::durable()?;
+
+        let schema_ty = ProductType::from([("id", AlgebraicType::U64), ("balance", AlgebraicType::U64)]);
+        let rows = [product!(1u64, 100u64), product!(2u64, 50u64)];
+        let schema = db.with_auto_commit(&ExecutionContext::default(), |tx| {
+            create_table_with_rows(&db, tx, "account", schema_ty, &rows)
+        })?;
+        let id = FieldName::new(schema.table_id, 0.into());
+        let balance = FieldName::new(schema.table_id, 1.into());
+
+        // Runs `UPDATE account SET balance = assigned WHERE id = 1`,
+        // returning the rows of `account` afterwards.
+        let update = |assigned: AssignExpr| -> ResultTest<Vec<ProductValue>> {
+            let crud = CrudExpr::Update {
+                delete: QueryExpr::new(&*schema).with_select_cmp(OpCmp::Eq, id, scalar(1u64)),
+                assignments: [(balance, assigned)].into_iter().collect(),
+                expected_max_rows: None,
+            };
+            let ctx = ExecutionContext::default();
+            db.with_auto_commit(&ctx, |tx| {
+                let mut tx_mode = tx.into();
+                let p = &mut DbProgram::new(&ctx, &db, &mut tx_mode, AuthCtx::for_testing());
+                match run_ast(p, Expr::Crud(Box::new(crud)), [].into()) {
+                    Code::Pass => Ok::<_, DBError>(()),
+                    x => panic!("invalid result {x}"),
+                }
+            })?;
+            let mut rows = run_query(&db, QueryExpr::new(&*schema), [].into()).data;
+            rows.sort();
+            Ok(rows)
+        };
+
+        // `balance = balance - 10`
+        let withdraw = AssignExpr::math(OpMath::Minus, FieldExpr::Name(balance), FieldExpr::Value(scalar(10u64)));
+        assert_eq!(update(withdraw)?, [product!(1u64, 90u64), product!(2u64, 50u64)]);
+
+        // `balance = 7`
+        let literal = AssignExpr::from(FieldExpr::Value(scalar(7u64)));
+        assert_eq!(update(literal)?, [product!(1u64, 7u64), product!(2u64, 50u64)]);
+        Ok(())
+    }
 }
@@ -1,9 +1,12 @@
 use crate::execution_context::WorkloadType;
 use crate::hash::Hash;
 use once_cell::sync::Lazy;
-use prometheus::{HistogramVec, IntCounterVec, IntGaugeVec};
+use parking_lot::Mutex;
+use prometheus::{HistogramVec, IntCounter, IntCounterVec, IntGaugeVec};
+use spacetimedb_data_structures::map::HashMap;
 use spacetimedb_lib::{Address, Identity};
-use spacetimedb_metrics::metrics_group;
+use spacetimedb_metrics::{metrics_group, OVERFLOW_LABEL};
+use std::hash::Hash as StdHash;
 
 metrics_group!(
     pub struct WorkerMetrics {
@@ -32,6 +35,16 @@ metrics_group!(
         #[labels(identity: Identity)]
         pub websocket_sent_msg_size: HistogramVec,
 
+        #[name = spacetime_client_bytes_sent_total]
+        #[help = "The number of bytes sent to a client"]
+        #[labels(db: Address, identity: str)]
+        pub client_bytes_sent: IntCounterVec,
+
+        #[name = spacetime_client_bytes_received_total]
+        #[help = "The number of bytes received from a client"]
+        #[labels(db: Address, identity: str)]
+        pub client_bytes_received: IntCounterVec,
+
         #[name = spacetime_worker_instance_operation_queue_length]
         #[help = "Length of the wait queue for access to a module instance."]
         #[labels(database_address: Address)]
@@ -75,7 +88,184 @@ metrics_group!(
         #[help = "The time spent executing a reducer (in seconds), plus the time spent evaluating its subscription queries"]
         #[labels(db: Address, reducer: str)]
         pub reducer_plus_query_duration: HistogramVec,
+
+        #[state]
+        client_labels: CardinalityGuard<Address, Identity>,
     }
 );
 
 pub static WORKER_METRICS: Lazy<WorkerMetrics> = Lazy::new(WorkerMetrics::new);
+
+/// The per-client metrics of a connection of the client `identity` to the database `db`,
+/// resolved once when the connection is established.
+///
+/// The `identity` label of the client counts against the bound of `db`
+/// until the last connection of the client to `db` is dropped,
+/// which also removes the client's series.
+/// Clients beyond the bound are recorded under [`OVERFLOW_LABEL`].
+pub struct ClientMetrics<'a> {
+    metrics: &'a WorkerMetrics,
+    db: Address,
+    identity: Identity,
+    /// Whether `identity` got a label of its own.
+    admitted: bool,
+    pub bytes_sent: IntCounter,
+    pub bytes_received: IntCounter,
+}
+
+impl WorkerMetrics {
+    /// Returns the per-client metrics of a new connection of the client `identity` to the database `db`.
+    pub fn client_metrics(&self, db: Address, identity: Identity) -> ClientMetrics<'_> {
+        let admitted = self.client_labels.admit(db, identity);
+        let hex = identity.to_hex();
+        let label = if admitted { &*hex } else { OVERFLOW_LABEL };
+        ClientMetrics {
+            metrics: self,
+            db,
+            identity,
+            admitted,
+            bytes_sent: self.client_bytes_sent.with_label_values(&db, label),
+            bytes_received: self.client_bytes_received.with_label_values(&db, label),
+        }
+    }
+}
+
+impl Drop for ClientMetrics<'_> {
+    fn drop(&mut self) {
+        if !self.admitted {
+            return;
+        }
+        let metrics = self.metrics;
+        metrics.client_labels.release(&self.db, &self.identity, || {
+            let identity = self.identity.to_hex();
+            let _ = metrics.client_bytes_sent.remove_label_values(&self.db, &identity);
+            let _ = metrics.client_bytes_received.remove_label_values(&self.db, &identity);
+        });
+    }
+}
+
+/// Bounds the number of distinct values of a metric label within each scope,
+/// e.g., to keep the number of time series in check when labeling by client per database.
+///
+/// A value is admitted until it has been released as many times as it was admitted,
+/// making room for another value.
+/// Once `limit` distinct values of a scope are admitted,
+/// any further values are refused, and should be recorded under [`OVERFLOW_LABEL`].
+pub struct CardinalityGuard<S, V> {
+    limit: usize,
+    /// The number of admissions of each admitted value, by scope.
+    admitted: Mutex<HashMap<S, HashMap<V, usize>>>,
+}
+
+impl<S, V> Default for CardinalityGuard<S, V> {
+    fn default() -> Self {
+        Self::new(Self::DEFAULT_LIMIT)
+    }
+}
+
+impl<S, V> CardinalityGuard<S, V> {
+    /// The number of distinct values admitted per scope by default.
+    pub const DEFAULT_LIMIT: usize = 10_000;
+
+    pub fn new(limit: usize) -> Self {
+        Self {
+            limit,
+            admitted: <_>::default(),
+        }
+    }
+}
+
+impl<S: Eq + StdHash, V: Eq + StdHash> CardinalityGuard<S, V> {
+    /// Admits `value` within `scope` if it's admitted already or there's still room for it,
+    /// returning whether it was admitted.
+    ///
+    /// Each admission must be matched by a [`CardinalityGuard::release`].
+    pub fn admit(&self, scope: S, value: V) -> bool {
+        let mut admitted = self.admitted.lock();
+        let values = admitted.entry(scope).or_default();
+        if let Some(count) = values.get_mut(&value) {
+            *count += 1;
+        } else if values.len() < self.limit {
+            values.insert(value, 1);
+        } else {
+            return false;
+        }
+        true
+    }
+
+    /// Releases an admission of `value` within `scope`.
+    ///
+    /// When this was the last admission of `value`, evicts it and calls `on_evict`,
+    /// e.g., to remove the series labeled by `value`,
+    /// before `value` can be admitted again.
+    pub fn release(&self, scope: &S, value: &V, on_evict: impl FnOnce()) {
+        let mut admitted = self.admitted.lock();
+        let Some(values) = admitted.get_mut(scope) else {
+            return;
+        };
+        let Some(count) = values.get_mut(value) else {
+            return;
+        };
+        *count -= 1;
+        if *count == 0 {
+            values.remove(value);
+            if values.is_empty() {
+                admitted.remove(scope);
+            }
+            on_evict();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn cardinality_guard_collapses_overflow() {
+        let guard = CardinalityGuard::new(2);
+        assert!(guard.admit(0, "a"));
+        assert!(guard.admit(0, "b"));
+        assert!(!guard.admit(0, "c"));
+        // Values admitted before the limit was reached stay admitted, and other scopes have their own limit.
+        assert!(guard.admit(0, "a"));
+        assert!(guard.admit(1, "c"));
+
+        // "a" is evicted only once both of its admissions are released.
+        let mut evicted = 0;
+        guard.release(&0, &"a", || evicted += 1);
+        assert_eq!(evicted, 0);
+        assert!(!guard.admit(0, "c"));
+        guard.release(&0, &"a", || evicted += 1);
+        assert_eq!(evicted, 1);
+        assert!(guard.admit(0, "c"));
+    }
+
+    #[test]
+    fn client_bytes_counters_advance() {
+        let metrics = WorkerMetrics::new();
+        let db = Address::from_u128(1);
+        let identity = Identity::from_byte_array([7; 32]);
+        let hex = identity.to_hex();
+        let received = || metrics.client_bytes_received.with_label_values(&db, &hex).get();
+        let sent = || metrics.client_bytes_sent.with_label_values(&db, &hex).get();
+
+        // Simulate a client sending a request and receiving two messages in response.
+        let client = metrics.client_metrics(db, identity);
+        client.bytes_received.inc_by(42);
+        client.bytes_sent.inc_by(100);
+        client.bytes_sent.inc_by(28);
+        assert_eq!(received(), 42);
+        assert_eq!(sent(), 128);
+
+        // A second connection of the same client shares its series,
+        // which are removed once the client's last connection is dropped.
+        let second = metrics.client_metrics(db, identity);
+        drop(client);
+        second.bytes_sent.inc();
+        assert_eq!(sent(), 129);
+        drop(second);
+        assert_eq!(sent(), 0);
+        assert_eq!(received(), 0);
+    }
+}
@@ -16,6 +16,14 @@ pub enum OpCmp {
     LtEq,
     Gt,
     GtEq,
+    /// Like [`OpCmp::NotEq`], but spelled out for values that may be absent,
+    /// i.e., a none is distinct from any some but not from another none.
+    IsDistinctFrom,
+    /// Like [`OpCmp::Eq`], but spelled out for values that may be absent,
+    /// i.e., a none matches another none.
+    IsNotDistinctFrom,
+    /// Like [`OpCmp::Eq`], but for strings that are equal up to case, e.g., `"Alice"` and `"alice"`.
+    EqIgnoreCase,
 }
 
 impl From<OpCmp> for &str {
@@ -27,6 +35,9 @@ impl From<OpCmp> for &str {
             OpCmp::LtEq => "std::cmp::le",
             OpCmp::Gt => "std::cmp::gt",
             OpCmp::GtEq => "std::cmp::ge",
+            OpCmp::IsDistinctFrom => "std::cmp::is_distinct_from",
+            OpCmp::IsNotDistinctFrom => "std::cmp::is_not_distinct_from",
+            OpCmp::EqIgnoreCase => "std::cmp::eq_ignore_case",
         }
     }
 }
@@ -37,6 +48,9 @@ impl OpCmp {
         match self {
             OpCmp::Eq => self,
             OpCmp::NotEq => self,
+            OpCmp::IsDistinctFrom => self,
+            OpCmp::IsNotDistinctFrom => self,
+            OpCmp::EqIgnoreCase => self,
             OpCmp::Lt => OpCmp::Gt,
             OpCmp::LtEq => OpCmp::GtEq,
             OpCmp::Gt => OpCmp::Lt,
@@ -92,7 +106,7 @@ impl From<OpLogic> for &str {
     }
 }
 
-#[derive(Debug, Copy, Clone, Eq, PartialEq, PartialOrd, Ord, Hash, From)]
+#[derive(Debug, Copy, Clone, Eq, PartialEq, PartialOrd, Ord, Hash, From, Serialize, Deserialize)]
 pub enum OpQuery {
     Cmp(OpCmp),
     Logic(OpLogic),
@@ -121,6 +135,9 @@ impl fmt::Display for OpCmp {
             OpCmp::LtEq => "<=",
             OpCmp::Gt => ">",
             OpCmp::GtEq => ">=",
+            OpCmp::IsDistinctFrom => "is distinct from",
+            OpCmp::IsNotDistinctFrom => "is not distinct from",
+            OpCmp::EqIgnoreCase => "eq ignore case",
         };
         write!(f, "{x}")
     }
@@ -1 +1,5 @@
 pub mod typed_prometheus;
+
+/// The value of a label that all values beyond a bound on its cardinality are recorded under,
+/// e.g., the clients or reducers that don't get a series of their own.
+pub const OVERFLOW_LABEL: &str = "__other__";
@@ -4,17 +4,27 @@ use prometheus::core::{Metric, MetricVec, MetricVecBuilder};
 macro_rules! metrics_group {
     ($(#[$attr:meta])* $type_vis:vis struct $type_name:ident {
         $(#[name = $name:ident] #[help = $help:expr] $(#[labels($($labels:ident: $labelty:ty),*)])? $(#[buckets($($bucket:literal),*)])? $vis:vis $field:ident: $ty:ident,)*
+        $(#[state] $state_vis:vis $state_field:ident: $state_ty:ty,)*
     }) => {
         $(#[$attr])*
         $type_vis struct $type_name {
             $($vis $field: $crate::metrics_group!(@fieldtype $field $ty $(($($labels)*))?),)*
+            $($state_vis $state_field: $state_ty,)*
         }
         $($crate::metrics_group!(@maketype $vis $field $ty $(($($labels: $labelty),*))? $(($($bucket)*))?);)*
         impl $type_name {
             #[allow(clippy::new_without_default)]
             pub fn new() -> Self {
+                Self::with_buckets(|_| None)
+            }
+
+            /// Like [`Self::new`], but each histogram uses the buckets `buckets` returns for its field name,
+            /// if any, rather than its default buckets.
+            #[allow(unused_mut, unused_variables)]
+            pub fn with_buckets(mut buckets: impl FnMut(&str) -> Option<Vec<f64>>) -> Self {
                 Self {
-                    $($field: $crate::make_collector!($crate::metrics_group!(@fieldtype $field $ty $(($($labels)*))?), stringify!($name), $help),)*
+                    $($field: $crate::metrics_group!(@collector $field $ty $name $help, $(($($labels)*))? $(($($bucket)*))?, buckets),)*
+                    $($state_field: Default::default(),)*
                 }
             }
         }
@@ -38,6 +48,17 @@ macro_rules! metrics_group {
             }
         }
     };
+    (@collector $field:ident $ty:ident $name:ident $help:expr, ($($labels:tt)*) ($($bucket:literal)*), $buckets:ident) => {
+        $crate::typed_prometheus::paste! {
+            match $buckets(stringify!($field)) {
+                Some(buckets) => [< $field:camel $ty >]::with_buckets(prometheus::Opts::new(stringify!($name), $help), buckets).unwrap(),
+                None => $crate::make_collector!([< $field:camel $ty >], stringify!($name), $help),
+            }
+        }
+    };
+    (@collector $field:ident $ty:ident $name:ident $help:expr, $(($($labels:tt)*))?, $buckets:ident) => {
+        $crate::make_collector!($crate::metrics_group!(@fieldtype $field $ty $(($($labels)*))?), stringify!($name), $help)
+    };
     (@fieldtype $field:ident $ty:ident ($($labels:tt)*)) => { $crate::typed_prometheus::paste! { [< $field:camel $ty >] } };
     (@fieldtype $field:ident $ty:ident) => { $ty };
     (@maketype $vis:vis $field:ident $ty:ident ($($labels:tt)*)) => {
@@ -74,10 +95,20 @@ macro_rules! metrics_histogram_vec {
         $vis struct $name($vecty);
         impl $name {
             pub fn with_opts(opts: prometheus::Opts) -> prometheus::Result<Self> {
-                let opts = prometheus::HistogramOpts::from(opts).buckets(vec![$(f64::from($bucket)),*]);
+                Self::with_buckets(opts, Self::default_buckets())
+            }
+
+            /// Like [`Self::with_opts`], but with `buckets` rather than the default buckets.
+            pub fn with_buckets(opts: prometheus::Opts, buckets: Vec<f64>) -> prometheus::Result<Self> {
+                let opts = prometheus::HistogramOpts::from(opts).buckets(buckets);
                 $vecty::new(opts.into(), &[$(stringify!($labels)),+]).map(Self)
             }
 
+            /// Returns the upper bounds of the default buckets of this histogram.
+            pub fn default_buckets() -> Vec<f64> {
+                vec![$(f64::from($bucket)),*]
+            }
+
             pub fn with_label_values(&self, $($labels: &$labelty),+) -> <$vecty as $crate::typed_prometheus::ExtractMetricVecT>::M {
                 use $crate::typed_prometheus::AsPrometheusLabel as _;
                 self.0.with_label_values(&[ $($labels.as_prometheus_str().as_ref()),+ ])
@@ -111,6 +142,12 @@ macro_rules! metrics_vec {
                 use $crate::typed_prometheus::AsPrometheusLabel as _;
                 self.0.with_label_values(&[ $($labels.as_prometheus_str().as_ref()),+ ])
             }
+
+            /// Removes the series with the given label values, returning an error if there is none.
+            pub fn remove_label_values(&self, $($labels: &$labelty),+) -> prometheus::Result<()> {
+                use $crate::typed_prometheus::AsPrometheusLabel as _;
+                self.0.remove_label_values(&[ $($labels.as_prometheus_str().as_ref()),+ ])
+            }
         }
 
         impl prometheus::core::Collector for $name {
@@ -929,7 +929,18 @@ impl From<&TableSchema> for Header {
             .map(|x| Column::new(FieldName::new(value.table_id, x.col_pos), x.col_type.clone()))
             .collect();
 
-        Header::new(value.table_id, value.table_name.clone(), fields, constraints)
+        // As with tables, a column whose name starts with `_` is private.
+        let private_columns = value
+            .columns
+            .iter()
+            .filter(|x| StAccess::for_name(&x.col_name) == StAccess::Private)
+            .map(|x| FieldName::new(value.table_id, x.col_pos))
+            .collect();
+
+        Header {
+            private_columns,
+            ..Header::new(value.table_id, value.table_name.clone(), fields, constraints)
+        }
     }
 }
 
@@ -1541,4 +1552,19 @@ mod tests {
             }])
         );
     }
+
+    #[test]
+    fn test_header_private_columns() {
+        let t = TableDef::new(
+            "test".into(),
+            vec![
+                ColumnDef::sys("id", AlgebraicType::U64),
+                ColumnDef::sys("_secret", AlgebraicType::String),
+            ],
+        );
+        let schema = t.into_schema(TableId(3));
+
+        let header = Header::from(&schema);
+        assert_eq!(header.private_columns, [FieldName::new(TableId(3), ColId(1))]);
+    }
 }
@@ -80,6 +80,8 @@ pub enum AuthError {
     OwnerRequired,
     #[error("Constraint `{named}` is private")]
     ConstraintPrivate { named: String },
+    #[error("Column `{column}` of table `{table}` is private")]
+    ColumnPrivate { table: String, column: FieldName },
 }
 
 #[derive(thiserror::Error, Debug)]
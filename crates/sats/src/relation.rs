@@ -2,14 +2,17 @@ use crate::algebraic_value::AlgebraicValue;
 use crate::db::auth::{StAccess, StTableType};
 use crate::db::error::{RelationError, TypeError};
 use crate::satn::Satn;
-use crate::{algebraic_type, AlgebraicType};
+use crate::{algebraic_type, de, ser, AlgebraicType};
 use core::fmt;
 use core::hash::Hash;
 use derive_more::From;
 use spacetimedb_primitives::{ColId, ColList, ColListBuilder, Constraints, TableId};
+use std::collections::BTreeMap;
+use std::ops::Bound;
 use std::sync::Arc;
 
-#[derive(Clone, Copy, PartialEq, PartialOrd, Eq, Ord, Hash)]
+#[derive(Clone, Copy, PartialEq, PartialOrd, Eq, Ord, Hash, ser::Serialize, de::Deserialize)]
+#[sats(crate = crate)]
 pub struct FieldName {
     pub table: TableId,
     pub col: ColId,
@@ -75,24 +78,52 @@ pub enum FieldExprRef<'a> {
 }
 
 // TODO(perf): Remove `Clone` derivation.
-#[derive(Debug, Clone, Eq, PartialEq, Ord, PartialOrd, Hash)]
+#[derive(Debug, Clone, Eq, PartialEq, Ord, PartialOrd, Hash, ser::Serialize, de::Deserialize)]
+#[sats(crate = crate)]
 pub struct Column {
     pub field: FieldName,
     pub algebraic_type: AlgebraicType,
+    /// The name the column was given by a projection, e.g., `SELECT a AS b`.
+    ///
+    /// Without one, the column is named as `field` is in the schema of its table.
+    pub name: Option<Box<str>>,
 }
 
 impl Column {
     pub fn new(field: FieldName, algebraic_type: AlgebraicType) -> Self {
-        Self { field, algebraic_type }
+        Self {
+            field,
+            algebraic_type,
+            name: None,
+        }
     }
 }
 
+/// The predicate of a partial index, i.e., an index over only the rows satisfying the predicate.
+///
+/// The predicate is a conjunction of ranges that the values of columns must lie within,
+/// where e.g., `deleted = false` is the range `[false, false]`.
+#[derive(Debug, PartialEq, Eq, Hash, Clone)]
+pub struct PartialIndexPredicate {
+    pub ranges: Vec<(ColId, Bound<AlgebraicValue>, Bound<AlgebraicValue>)>,
+}
+
 #[derive(Debug, PartialEq, Eq, Hash, Clone)]
 pub struct Header {
     pub table_id: TableId,
     pub table_name: Box<str>,
     pub fields: Vec<Column>,
     pub constraints: Vec<(ColList, Constraints)>,
+    /// The partial indexes of the table, keyed by the columns they index.
+    ///
+    /// These are intentionally not part of `constraints`,
+    /// as a partial index can only serve queries that imply its predicate.
+    pub partial_indexes: Vec<(ColList, PartialIndexPredicate)>,
+    /// The fields of the table that only the database owner may read,
+    /// even when the table itself is public.
+    ///
+    /// A header built from a `TableSchema` marks the columns whose name starts with `_` as private.
+    pub private_columns: Vec<FieldName>,
 }
 
 impl Header {
@@ -107,9 +138,24 @@ impl Header {
             table_name,
             fields,
             constraints,
+            partial_indexes: Vec::new(),
+            private_columns: Vec::new(),
         }
     }
 
+    /// Adds a partial index on `columns` covering the rows that satisfy `predicate`.
+    pub fn with_partial_index(mut self, columns: ColList, predicate: PartialIndexPredicate) -> Self {
+        self.partial_indexes.push((columns, predicate));
+        self
+    }
+
+    /// Marks the column at `col` as readable only by the database owner.
+    pub fn with_private_column(mut self, col: ColId) -> Self {
+        let field = self.fields[col.idx()].field;
+        self.private_columns.push(field);
+        self
+    }
+
     /// Equivalent to what [`Clone::clone`] would do.
     ///
     /// `Header` intentionally does not implement `Clone`,
@@ -117,12 +163,16 @@ impl Header {
     /// However, we don't care about performance in error paths,
     /// and we need to embed owned `Header`s in error objects to report useful messages.
     pub fn clone_for_error(&self) -> Self {
-        Header::new(
-            self.table_id,
-            self.table_name.clone(),
-            self.fields.clone(),
-            self.constraints.clone(),
-        )
+        Self {
+            partial_indexes: self.partial_indexes.clone(),
+            private_columns: self.private_columns.clone(),
+            ..Header::new(
+                self.table_id,
+                self.table_name.clone(),
+                self.fields.clone(),
+                self.constraints.clone(),
+            )
+        }
     }
 
     /// Finds the index of the column wth a matching `FieldName`.
@@ -183,8 +233,30 @@ impl Header {
         }
 
         let constraints = self.retain_constraints(&to_keep.build().unwrap());
+        let private_columns = self
+            .private_columns
+            .iter()
+            .filter(|field| p.iter().any(|c| c.field == **field))
+            .copied()
+            .collect();
+
+        Ok(Self {
+            private_columns,
+            ..Self::new(self.table_id, self.table_name.clone(), p, constraints)
+        })
+    }
 
-        Ok(Self::new(self.table_id, self.table_name.clone(), p, constraints))
+    /// Like [`Header::project`], but the columns paired with an alias are named by it,
+    /// see [`Column::name`].
+    pub fn project_aliased(&self, cols: &[(FieldExpr, Option<Box<str>>)]) -> Result<Self, RelationError> {
+        let exprs = cols.iter().map(|(col, _)| col.clone()).collect::<Vec<_>>();
+        let mut head = self.project(&exprs)?;
+        for (column, (_, alias)) in head.fields.iter_mut().zip(cols) {
+            if let Some(alias) = alias {
+                column.name = Some(alias.clone());
+            }
+        }
+        Ok(head)
     }
 
     /// Adds the fields &  [Constraints] from `right` to this [`Header`],
@@ -206,7 +278,13 @@ impl Header {
         let mut fields = self.fields.clone();
         fields.extend(right.fields.iter().cloned());
 
-        Self::new(self.table_id, self.table_name.clone(), fields, constraints)
+        let mut private_columns = self.private_columns.clone();
+        private_columns.extend(right.private_columns.iter().copied());
+
+        Self {
+            private_columns,
+            ..Self::new(self.table_id, self.table_name.clone(), fields, constraints)
+        }
     }
 }
 
@@ -259,13 +337,36 @@ pub trait Relation {
     fn row_count(&self) -> RowCount;
 }
 
+/// Estimated statistics about the values of a single column.
+///
+/// These are hints for the optimizer; every estimate is optional,
+/// and a missing estimate leaves the optimizer's choices unchanged.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Hash)]
+pub struct ColumnStats {
+    /// The estimated number of distinct values in the column.
+    pub distinct_count: Option<u64>,
+    /// The smallest value in the column.
+    pub min: Option<AlgebraicValue>,
+    /// The largest value in the column.
+    pub max: Option<AlgebraicValue>,
+}
+
+/// Statistics hints for the columns of a table.
+pub type ColumnStatsMap = BTreeMap<ColId, ColumnStats>;
+
 /// A stored table from [RelationalDB]
-#[derive(Debug, Clone, Eq, PartialEq, Hash)]
+///
+/// Two `DbTable`s are equal when they name the same table,
+/// whatever statistics about its columns are attached,
+/// as those change with the rows of the table.
+#[derive(Debug, Clone)]
 pub struct DbTable {
     pub head: Arc<Header>,
     pub table_id: TableId,
     pub table_type: StTableType,
     pub table_access: StAccess,
+    /// Statistics about the columns of the table, if known, to guide the optimizer.
+    pub column_stats: Option<Arc<ColumnStatsMap>>,
 }
 
 impl DbTable {
@@ -275,10 +376,31 @@ impl DbTable {
             table_id,
             table_type,
             table_access,
+            column_stats: None,
         }
     }
 }
 
+impl PartialEq for DbTable {
+    fn eq(&self, other: &Self) -> bool {
+        self.head == other.head
+            && self.table_id == other.table_id
+            && self.table_type == other.table_type
+            && self.table_access == other.table_access
+    }
+}
+
+impl Eq for DbTable {}
+
+impl Hash for DbTable {
+    fn hash<H: core::hash::Hasher>(&self, state: &mut H) {
+        self.head.hash(state);
+        self.table_id.hash(state);
+        self.table_type.hash(state);
+        self.table_access.hash(state);
+    }
+}
+
 impl Relation for DbTable {
     fn head(&self) -> &Arc<Header> {
         &self.head
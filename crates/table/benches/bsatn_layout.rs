@@ -0,0 +1,90 @@
+use core::iter;
+use criterion::{black_box, criterion_group, criterion_main, Criterion};
+use spacetimedb_primitives::TableId;
+use spacetimedb_sats::db::def::{TableDef, TableSchema};
+use spacetimedb_sats::{bsatn, product, AlgebraicType, AlgebraicValue, ProductType, ProductValue};
+use spacetimedb_table::bflatn_from::serialize_row_from_page;
+use spacetimedb_table::bflatn_to_bsatn_fast_path::PartialBsatnLayout;
+use spacetimedb_table::blob_store::HashMapBlobStore;
+use spacetimedb_table::indexes::SquashedOffset;
+use spacetimedb_table::table::Table;
+
+fn make_table(ty: ProductType) -> Table {
+    let schema = TableSchema::from_def(TableId(0), TableDef::from_product("bsatn_layout", ty));
+    Table::new(schema.into(), SquashedOffset::COMMITTED_STATE)
+}
+
+/// Compare serializing one row of type `ty` through a [`PartialBsatnLayout`]
+/// against the general `bflatn_from` serializer.
+fn bench_row(c: &mut Criterion, name: &str, ty: ProductType, val: ProductValue) {
+    let mut blob_store = HashMapBlobStore::default();
+    let mut table = make_table(ty);
+    let layout = PartialBsatnLayout::for_row_type(table.row_layout()).unwrap();
+    let ptr = table.insert(&mut blob_store, &val).unwrap().1.pointer();
+    let row_ref = table.get_row_ref(&blob_store, ptr).unwrap();
+
+    let (page, offset) = row_ref.page_and_offset();
+    let row = page.get_row_data(offset, table.row_layout().size());
+
+    let mut group = c.benchmark_group(name);
+
+    group.bench_function("PartialBsatnLayout", |b| {
+        let mut buf = Vec::new();
+        b.iter(|| {
+            buf.clear();
+            unsafe { layout.serialize_row_extend(&mut buf, row, page, &blob_store) }.unwrap();
+            black_box(&buf);
+        });
+    });
+
+    group.bench_function("serialize_row_from_page", |b| {
+        let mut buf = Vec::new();
+        b.iter(|| {
+            buf.clear();
+            let ser = bsatn::Serializer::new(&mut buf);
+            unsafe { serialize_row_from_page(ser, page, &blob_store, offset, table.row_layout()) }.unwrap();
+            black_box(&buf);
+        });
+    });
+}
+
+fn mixed_row(c: &mut Criterion) {
+    bench_row(
+        c,
+        "bsatn_layout/(u64, u64, String, u32)",
+        ProductType::from([
+            AlgebraicType::U64,
+            AlgebraicType::U64,
+            AlgebraicType::String,
+            AlgebraicType::U32,
+        ]),
+        product![0xa5a5_a5a5u64, 0x5a5a_5a5au64, "hello world", 0xa5a5u32],
+    );
+}
+
+fn wide_mixed_row(c: &mut Criterion) {
+    let ty = iter::repeat(AlgebraicType::U64)
+        .take(16)
+        .chain([AlgebraicType::String])
+        .chain(iter::repeat(AlgebraicType::U32).take(8))
+        .collect();
+    let val = (0..16u64)
+        .map(AlgebraicValue::from)
+        .chain([AlgebraicValue::from("hello world")])
+        .chain((0..8u32).map(AlgebraicValue::from))
+        .collect();
+
+    bench_row(c, "bsatn_layout/(u64 x 16, String, u32 x 8)", ty, val);
+}
+
+fn string_only_row(c: &mut Criterion) {
+    bench_row(
+        c,
+        "bsatn_layout/(String,)",
+        ProductType::from([AlgebraicType::String]),
+        product!["hello world"],
+    );
+}
+
+criterion_group!(bsatn_layout, mixed_row, wide_mixed_row, string_only_row);
+criterion_main!(bsatn_layout);
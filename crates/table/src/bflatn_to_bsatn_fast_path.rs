@@ -19,17 +19,32 @@
 //! This module will make 2 `memcpy`s (or actually, `<[u8]>::copy_from_slice`s):
 //! one of 20 bytes to copy the leading `(u64, u64, u32)`, which contains no padding,
 //! and then one of 8 bytes to copy the trailing `u64`, skipping over 4 bytes of padding in between.
+//!
+//! Row types which do not have a fixed BSATN length can still use a [`PartialBsatnLayout`],
+//! which `memcpy`s the fixed-length runs of the row
+//! and falls back to [`bflatn_from`] only for the members in between,
+//! i.e. var-len columns and sums whose variants have different BSATN layouts.
+//! For example, a row of type `(u64, u64, String, u32)` will make one `memcpy` of 16 bytes,
+//! dispatch once to serialize the `String`, and then make one `memcpy` of 4 bytes.
 
 use super::{
+    bflatn_from::serialize_value,
+    blob_store::BlobStore,
     indexes::{Byte, Bytes},
     layout::{
         AlgebraicTypeLayout, HasLayout, PrimitiveType, ProductTypeElementLayout, ProductTypeLayout, RowTypeLayout,
         SumTypeLayout, SumTypeVariantLayout,
     },
+    page::Page,
     util::range_move,
 };
-use core::mem::MaybeUninit;
+use core::cell::Cell;
+use core::mem::{self, MaybeUninit};
 use core::ptr;
+use spacetimedb_data_structures::map::HashMap;
+use spacetimedb_sats::bsatn::{self, ser::BsatnError};
+use std::sync::{Arc, Mutex, OnceLock};
+use thiserror::Error;
 
 /// A precomputed BSATN layout for a type whose encoded length is a known constant,
 /// enabling fast BFLATN -> BSATN conversion.
@@ -42,8 +57,17 @@ pub struct StaticBsatnLayout {
     /// avoiding potentially-expensive `realloc`s.
     pub(crate) bsatn_length: u16,
 
+    /// The maximum length of the encoded BSATN representation of a value of this type,
+    /// in bytes.
+    ///
+    /// This is the same as `bsatn_length` when the layout is [fixed](Self::is_fixed).
+    /// Otherwise, `bsatn_length` is the minimum length instead.
+    max_bsatn_length: u16,
+
     /// A series of `memcpy` invocations from a BFLATN row into a BSATN buffer
     /// which are sufficient to BSATN serialize the row.
+    ///
+    /// Empty if the layout is not [fixed](Self::is_fixed).
     fields: Box<[MemcpyField]>,
 }
 
@@ -52,12 +76,14 @@ impl StaticBsatnLayout {
     ///
     /// # Safety
     ///
+    /// - `self` must be [fixed](Self::is_fixed).
     /// - `buf` must be at least `self.bsatn_length` long.
     /// - `row` must store a valid, initialized instance of the BFLATN row type
     ///   for which `self` was computed.
     ///   As a consequence of this, for every `field` in `self.fields`,
     ///   `row[field.bflatn_offset .. field.bflatn_offset + length]` will be initialized.
     pub unsafe fn serialize_row_into(&self, buf: &mut [MaybeUninit<Byte>], row: &Bytes) {
+        debug_assert!(self.is_fixed());
         debug_assert!(buf.len() >= self.bsatn_length as usize);
         for field in &self.fields[..] {
             // SAFETY: forward caller requirements.
@@ -65,16 +91,361 @@ impl StaticBsatnLayout {
         }
     }
 
+    /// Returns the exact length, in bytes, of `n_rows` rows of this type
+    /// encoded in BSATN back-to-back, as by [`StaticBsatnLayout::serialize_rows_into`].
+    ///
+    /// Only meaningful when `self` is [fixed](Self::is_fixed).
+    pub fn batch_byte_len(&self, n_rows: usize) -> usize {
+        self.bsatn_length as usize * n_rows
+    }
+
+    /// Serialize each of `rows` from BFLATN to BSATN into `buf`, back-to-back,
+    /// so that the `i`-th row is written to `buf[i * len .. (i + 1) * len]`,
+    /// where `len` is the length of a single encoded row.
+    ///
+    /// The length of `buf` is checked once up front rather than for each row.
+    ///
+    /// # Safety
+    ///
+    /// - `self` must be [fixed](Self::is_fixed).
+    /// - `buf` must be at least `self.batch_byte_len(n)` long,
+    ///   where `n` is the number of rows yielded by `rows`.
+    ///   In particular, `rows` must not yield more rows than its [`Iterator::size_hint`] promises.
+    /// - Each of `rows` must store a valid, initialized instance of the BFLATN row type
+    ///   for which `self` was computed, as for [`StaticBsatnLayout::serialize_row_into`].
+    pub unsafe fn serialize_rows_into<'a>(&self, buf: &mut [MaybeUninit<Byte>], rows: impl Iterator<Item = &'a Bytes>) {
+        debug_assert!(self.is_fixed());
+        debug_assert!(buf.len() >= self.batch_byte_len(rows.size_hint().0));
+        let len = self.bsatn_length as usize;
+        for (i, row) in rows.enumerate() {
+            // SAFETY: The caller promised that `buf` is long enough for every row,
+            // so `i * len .. (i + 1) * len` is in bounds.
+            let sink = unsafe { buf.get_unchecked_mut(i * len..(i + 1) * len) };
+            // SAFETY: forward caller requirements.
+            // `sink` is exactly `self.bsatn_length` long, as required.
+            unsafe { self.serialize_row_into(sink, row) };
+        }
+    }
+
+    /// Serialize `row` from BFLATN to BSATN,
+    /// pushing the encoded bytes onto the end of `out`, similar to [`Vec::extend`].
+    ///
+    /// Unlike [`StaticBsatnLayout::serialize_row_into`],
+    /// this manages the spare capacity of `out` itself,
+    /// so it can be called in a loop to serialize many rows back-to-back into one buffer.
+    ///
+    /// If `row` is not an instance of the BFLATN row type for which `self` was computed,
+    /// the appended bytes are meaningless, but nothing worse happens.
+    ///
+    /// Panics if `self` is not [fixed](Self::is_fixed)
+    /// or if `row` is too short to hold the members copied by `self`.
+    pub fn serialize_row_append(&self, out: &mut Vec<u8>, row: &Bytes) {
+        assert!(self.is_fixed(), "can only serialize using a fixed `StaticBsatnLayout`");
+        assert!(
+            self.fields
+                .iter()
+                .all(|field| field.bflatn_offset as usize + field.length as usize <= row.len()),
+            "row of {} bytes is too short for this `StaticBsatnLayout`",
+            row.len(),
+        );
+
+        // Get an uninitialized slice within `out` of the correct length.
+        let start = out.len();
+        let len = self.bsatn_length as usize;
+        out.reserve(len);
+        let sink = &mut out.spare_capacity_mut()[..len];
+
+        // (1) Write the row into the slice using a series of `memcpy`s.
+        // SAFETY:
+        // - `self` is fixed, as checked above.
+        // - `sink` was constructed with exactly the correct length above.
+        // - Every `field.bflatn_offset .. field.bflatn_offset + field.length` is in bounds of `row`,
+        //   as checked above, and is initialized, as `row` is a shared slice of bytes.
+        //   Should `row` not be of the type `self` was computed for,
+        //   we'll merely copy the wrong bytes.
+        unsafe { self.serialize_row_into(sink, row) };
+
+        // SAFETY: In (1), we initialized `start .. start + len`
+        // and we had initialized up to `start` before,
+        // so now we have initialized up to `start + len`.
+        unsafe { out.set_len(start + len) }
+    }
+
     /// Construct a `StaticBsatnLayout` for converting BFLATN rows of `row_type` into BSATN.
     ///
     /// Returns `None` if `row_type` contains a column which does not have a constant length in BSATN,
     /// either a [`VarLenType`]
-    /// or a [`SumTypeLayout`] whose variants do not have the same "live" unpadded length.
+    /// or a [`SumTypeLayout`] whose variants do not have the same "live" unpadded length,
+    /// or if an offset or length in the layout would exceed `u16::MAX`.
     pub fn for_row_type(row_type: &RowTypeLayout) -> Option<Self> {
         let mut builder = LayoutBuilder::new_builder();
         builder.visit_product(row_type.product())?;
         Some(builder.build())
     }
+
+    /// Construct a `StaticBsatnLayout` for converting BFLATN values of the sum type `sum` into BSATN.
+    ///
+    /// Unlike [`StaticBsatnLayout::for_row_type`], this doesn't fail
+    /// when the variants of `sum` have different BSATN layouts,
+    /// but rather returns a layout which is not [fixed](Self::is_fixed)
+    /// and can only be used to pre-size buffers.
+    ///
+    /// Returns `None` if `sum` has no variants,
+    /// or if any of its variants does not itself have a fixed BSATN layout.
+    pub fn for_sum_type(sum: &SumTypeLayout) -> Option<Self> {
+        let mut builder = LayoutBuilder::new_builder();
+        if builder.visit_sum(sum).is_some() {
+            return Some(builder.build());
+        }
+
+        // The variants differ, so record the range of their lengths, plus one byte for the tag.
+        let mut lengths = sum.variants.iter().map(|variant| {
+            LayoutBuilder::variant_layout(variant).and_then(|layout| layout.bsatn_length.checked_add(1))
+        });
+        let first = lengths.next()??;
+        let (bsatn_length, max_bsatn_length) = lengths.try_fold((first, first), |(min, max), len| {
+            len.map(|len| (min.min(len), max.max(len)))
+        })?;
+        Some(Self {
+            bsatn_length,
+            max_bsatn_length,
+            fields: [].into(),
+        })
+    }
+
+    /// Returns whether the layout can be used to serialize values,
+    /// in which case every value of this type has the same BSATN length.
+    pub fn is_fixed(&self) -> bool {
+        // A layout which isn't fixed has no `memcpy`s, but at least the tag of the sum to serialize,
+        // whereas a fixed layout only lacks `memcpy`s when there's nothing to serialize.
+        // Note that the variants of a sum may all have the same length, but different layouts.
+        self.bsatn_length == 0 || !self.fields.is_empty()
+    }
+
+    /// Returns the maximum length of the encoded BSATN representation of a value of this type,
+    /// in bytes.
+    pub fn max_bsatn_length(&self) -> u16 {
+        self.max_bsatn_length
+    }
+
+    /// Checks that the `memcpy`s of `self` stay in bounds
+    /// of a BFLATN row of `row_len` bytes and of a BSATN buffer of `self.bsatn_length` bytes,
+    /// and that no two of them write to the same bytes of the buffer.
+    ///
+    /// A layout computed by [`StaticBsatnLayout::for_row_type`] is always valid for rows of its type,
+    /// but one which came from elsewhere should be validated once
+    /// before serializing any rows with [`StaticBsatnLayout::serialize_row_into`].
+    pub fn validate(&self, row_len: usize) -> Result<(), LayoutError> {
+        for field in &self.fields[..] {
+            if field.bflatn_offset as usize + field.length as usize > row_len {
+                return Err(LayoutError::OutOfRow {
+                    bflatn_offset: field.bflatn_offset,
+                    length: field.length,
+                    row_len,
+                });
+            }
+            if field.bsatn_offset as usize + field.length as usize > self.bsatn_length as usize {
+                return Err(LayoutError::OutOfBsatn {
+                    bsatn_offset: field.bsatn_offset,
+                    length: field.length,
+                    bsatn_length: self.bsatn_length,
+                });
+            }
+        }
+
+        let mut fields = self.fields.to_vec();
+        fields.sort_unstable_by_key(|field| field.bsatn_offset);
+        for pair in fields.windows(2) {
+            let (first, second) = (pair[0], pair[1]);
+            // Both ends are within `self.bsatn_length`, as checked above, so this can't overflow.
+            if first.bsatn_offset + first.length > second.bsatn_offset {
+                return Err(LayoutError::Overlapping {
+                    first_bsatn_offset: first.bsatn_offset,
+                    second_bsatn_offset: second.bsatn_offset,
+                });
+            }
+        }
+
+        Ok(())
+    }
+}
+
+/// An error returned by [`StaticBsatnLayout::validate`] for a layout
+/// whose `memcpy`s would read or write out of bounds.
+#[derive(Error, Debug, PartialEq, Eq)]
+pub enum LayoutError {
+    #[error("Copying {length} bytes from offset {bflatn_offset} reads past the end of a row of {row_len} bytes")]
+    OutOfRow {
+        bflatn_offset: u16,
+        length: u16,
+        row_len: usize,
+    },
+    #[error("Copying {length} bytes to offset {bsatn_offset} writes past the end of a BSATN encoding of {bsatn_length} bytes")]
+    OutOfBsatn {
+        bsatn_offset: u16,
+        length: u16,
+        bsatn_length: u16,
+    },
+    #[error("Copies to offsets {first_bsatn_offset} and {second_bsatn_offset} write to the same bytes of the BSATN encoding")]
+    Overlapping {
+        first_bsatn_offset: u16,
+        second_bsatn_offset: u16,
+    },
+}
+
+/// The number of row types for which [`static_layout_for`] remembers a layout.
+const STATIC_LAYOUT_CACHE_CAPACITY: usize = 256;
+
+/// Returns the [`StaticBsatnLayout`] for `row_type`,
+/// as computed by [`StaticBsatnLayout::for_row_type`].
+///
+/// The layouts of recently seen row types are cached process-wide,
+/// including the absence of a layout,
+/// so that the [`Table`](crate::table::Table)s made for the same row type share one layout,
+/// and making one for a row type with a `String` column
+/// does not rediscover each time that it has no static layout.
+pub fn static_layout_for(row_type: &RowTypeLayout) -> Option<Arc<StaticBsatnLayout>> {
+    static CACHE: OnceLock<Mutex<StaticLayoutCache>> = OnceLock::new();
+    let cache = CACHE.get_or_init(|| Mutex::new(StaticLayoutCache::new(STATIC_LAYOUT_CACHE_CAPACITY)));
+    // The cache is always left in a consistent state, so a poisoned lock is fine to use.
+    let mut cache = cache.lock().unwrap_or_else(|e| e.into_inner());
+    cache.get_or_compute(row_type)
+}
+
+/// A cache of the [`StaticBsatnLayout`]s of at most `capacity` row types,
+/// evicting the least recently used one when full.
+struct StaticLayoutCache {
+    capacity: usize,
+    /// The layout of each row type, together with the `clock` when it was last used.
+    layouts: HashMap<RowTypeLayout, (Option<Arc<StaticBsatnLayout>>, u64)>,
+    /// Incremented on each lookup.
+    clock: u64,
+}
+
+impl StaticLayoutCache {
+    fn new(capacity: usize) -> Self {
+        Self {
+            capacity,
+            layouts: HashMap::default(),
+            clock: 0,
+        }
+    }
+
+    fn get_or_compute(&mut self, row_type: &RowTypeLayout) -> Option<Arc<StaticBsatnLayout>> {
+        self.clock += 1;
+        if let Some((layout, last_used)) = self.layouts.get_mut(row_type) {
+            *last_used = self.clock;
+            return layout.clone();
+        }
+
+        if self.layouts.len() >= self.capacity {
+            // Misses should be rare, as schemas rarely change,
+            // so a linear search for the least recently used entry is fine.
+            let lru = self.layouts.iter().min_by_key(|(_, (_, last_used))| *last_used);
+            if let Some(lru) = lru.map(|(row_type, _)| row_type.clone()) {
+                self.layouts.remove(&lru);
+            }
+        }
+
+        let layout = StaticBsatnLayout::for_row_type(row_type).map(Arc::new);
+        self.layouts.insert(row_type.clone(), (layout.clone(), self.clock));
+        layout
+    }
+}
+
+/// A precomputed BSATN layout for a type whose encoded length is not a known constant,
+/// consisting of fixed-length runs which can be `memcpy`ed
+/// interleaved with "holes" which must be serialized by the general [`crate::bflatn_from`] path.
+#[derive(PartialEq, Eq, Debug, Clone)]
+pub struct PartialBsatnLayout {
+    /// The segments of the row, in order.
+    ///
+    /// No two [`BsatnSegment::Fixed`] are adjacent,
+    /// and no [`BsatnSegment::Fixed`] is empty.
+    segments: Box<[BsatnSegment]>,
+}
+
+/// A segment of a [`PartialBsatnLayout`].
+#[derive(PartialEq, Eq, Debug, Clone)]
+enum BsatnSegment {
+    /// A run of fixed-length members, serialized by a series of `memcpy`s.
+    ///
+    /// The `bsatn_offset`s of the run's fields are relative to the start of the run
+    /// rather than to the start of the row,
+    /// as the BSATN lengths of the preceding holes are not known ahead of time.
+    Fixed(StaticBsatnLayout),
+
+    /// A member without a fixed BSATN length,
+    /// serialized by dispatching on its type.
+    Hole {
+        /// Offset in the BFLATN row at which the member is stored, in bytes.
+        bflatn_offset: u16,
+        /// The type of the member.
+        ty: AlgebraicTypeLayout,
+    },
+}
+
+impl PartialBsatnLayout {
+    /// Serialize `row` from BFLATN to BSATN,
+    /// pushing the encoded bytes onto the end of `buf`, similar to [`Vec::extend`].
+    ///
+    /// # Safety
+    ///
+    /// - `row` must store a valid, initialized instance of the BFLATN row type
+    ///   for which `self` was computed.
+    /// - For any `vlr: VarLenRef` stored in `row`,
+    ///   `vlr.first_offset` must either be `NULL` or point to a valid granule in `page`.
+    pub unsafe fn serialize_row_extend(
+        &self,
+        buf: &mut Vec<u8>,
+        row: &Bytes,
+        page: &Page,
+        blob_store: &dyn BlobStore,
+    ) -> Result<(), BsatnError> {
+        for segment in &self.segments[..] {
+            match segment {
+                BsatnSegment::Fixed(run) => {
+                    // Get an uninitialized slice within `buf` of the correct length.
+                    let start = buf.len();
+                    let len = run.bsatn_length as usize;
+                    buf.reserve(len);
+                    let sink = &mut buf.spare_capacity_mut()[..len];
+
+                    // (1) Write the run into the slice using a series of `memcpy`s.
+                    // SAFETY:
+                    // - Forward caller requirement #1.
+                    // - `sink` was constructed with exactly the correct length above.
+                    unsafe { run.serialize_row_into(sink, row) };
+
+                    // SAFETY: In (1), we initialized `start .. start + len`
+                    // and we had initialized up to `start` before,
+                    // so now we have initialized up to `start + len`.
+                    unsafe { buf.set_len(start + len) }
+                }
+                BsatnSegment::Hole { bflatn_offset, ty } => {
+                    let curr_offset = Cell::new(*bflatn_offset as usize);
+                    // SAFETY:
+                    // - Per caller requirement #1,
+                    //   the member at `bflatn_offset` is valid at `ty` and properly aligned for `ty`.
+                    // - Per caller requirement #2, `VarLenRef`s won't be dangling.
+                    unsafe { serialize_value(bsatn::Serializer::new(buf), row, page, blob_store, &curr_offset, ty)? }
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Construct a `PartialBsatnLayout` for converting BFLATN rows of `row_type` into BSATN.
+    ///
+    /// Unlike [`StaticBsatnLayout::for_row_type`], this applies to every row type,
+    /// recording a hole for each member which does not have a constant length in BSATN.
+    ///
+    /// Returns `None` only if an offset or length in the layout would exceed `u16::MAX`.
+    pub fn for_row_type(row_type: &RowTypeLayout) -> Option<Self> {
+        let mut builder = LayoutBuilder::new_partial_builder();
+        builder.visit_product(row_type.product())?;
+        Some(builder.build_partial())
+    }
 }
 
 /// An identifier for a series of bytes within a BFLATN row
@@ -108,6 +479,8 @@ impl MemcpyField {
     /// - `row` must be exactly `self.bflatn_offset + self.length` long.
     unsafe fn copy(&self, buf: &mut [MaybeUninit<Byte>], row: &Bytes) {
         let len = self.length as usize;
+        debug_assert!(self.bsatn_offset as usize + len <= buf.len());
+        debug_assert!(self.bflatn_offset as usize + len <= row.len());
         // SAFETY: forward caller requirement #1.
         let to = unsafe { buf.get_unchecked_mut(range_move(0..len, self.bsatn_offset as usize)) };
         let dst = to.as_mut_ptr().cast();
@@ -126,12 +499,33 @@ impl MemcpyField {
     fn is_empty(&self) -> bool {
         self.length == 0
     }
+
+    /// Extends `self` to also copy `next`, if `next` directly follows `self`
+    /// in both BFLATN and BSATN, returning whether it did.
+    fn absorb(&mut self, next: &MemcpyField) -> bool {
+        let contiguous = self.bflatn_offset + self.length == next.bflatn_offset
+            && self.bsatn_offset + self.length == next.bsatn_offset;
+        if contiguous {
+            self.length += next.length;
+        }
+        contiguous
+    }
 }
 
-/// A builder for a [`StaticBsatnLayout`].
+/// A builder for a [`StaticBsatnLayout`] or a [`PartialBsatnLayout`].
+///
+/// The `visit_*` methods return `None` rather than wrapping
+/// when an offset or the end of a field would exceed `u16::MAX`,
+/// so the end of every field in `fields` fits in a `u16` in both BFLATN and BSATN.
 struct LayoutBuilder {
     /// Always at least one element.
+    ///
+    /// When building a [`PartialBsatnLayout`], these are the fields of the current run.
     fields: Vec<MemcpyField>,
+
+    /// The completed segments preceding the current run,
+    /// or `None` if building a [`StaticBsatnLayout`].
+    segments: Option<Vec<BsatnSegment>>,
 }
 
 impl LayoutBuilder {
@@ -142,15 +536,40 @@ impl LayoutBuilder {
                 bsatn_offset: 0,
                 length: 0,
             }],
+            segments: None,
+        }
+    }
+
+    fn new_partial_builder() -> Self {
+        Self {
+            segments: Some(Vec::new()),
+            ..Self::new_builder()
         }
     }
 
     fn build(self) -> StaticBsatnLayout {
-        let LayoutBuilder { fields } = self;
-        let fields: Vec<_> = fields.into_iter().filter(|field| !field.is_empty()).collect();
+        let LayoutBuilder { fields, .. } = self;
+        let mut fields: Vec<_> = fields.into_iter().filter(|field| !field.is_empty()).collect();
+        // Dropping empty marker fields may leave neighbors which are contiguous
+        // in both BFLATN and BSATN, so merge those into a single `memcpy`.
+        fields.dedup_by(|next, prev| prev.absorb(next));
         let bsatn_length = fields.last().map(|last| last.bsatn_offset + last.length).unwrap_or(0);
         let fields = fields.into_boxed_slice();
-        StaticBsatnLayout { bsatn_length, fields }
+        StaticBsatnLayout {
+            bsatn_length,
+            max_bsatn_length: bsatn_length,
+            fields,
+        }
+    }
+
+    fn build_partial(mut self) -> PartialBsatnLayout {
+        let mut segments = self.segments.take().unwrap_or_default();
+        let run = self.build();
+        if !run.fields.is_empty() {
+            segments.push(BsatnSegment::Fixed(run));
+        }
+        let segments = segments.into_boxed_slice();
+        PartialBsatnLayout { segments }
     }
 
     fn current_field(&self) -> &MemcpyField {
@@ -163,11 +582,13 @@ impl LayoutBuilder {
 
     fn next_bflatn_offset(&self) -> u16 {
         let last = self.current_field();
+        // Can't overflow, as `visit_primitive` ensured that the end of `last` fits.
         last.bflatn_offset + last.length
     }
 
     fn next_bsatn_offset(&self) -> u16 {
         let last = self.current_field();
+        // Can't overflow, as `visit_primitive` ensured that the end of `last` fits.
         last.bsatn_offset + last.length
     }
 
@@ -180,7 +601,7 @@ impl LayoutBuilder {
     }
 
     fn visit_product_element(&mut self, elt: &ProductTypeElementLayout, product_base_offset: u16) -> Option<()> {
-        let elt_offset = product_base_offset + elt.offset;
+        let elt_offset = product_base_offset.checked_add(elt.offset)?;
         let next_bflatn_offset = self.next_bflatn_offset();
         if next_bflatn_offset != elt_offset {
             // Padding between previous element and this element,
@@ -202,57 +623,86 @@ impl LayoutBuilder {
 
     fn visit_value(&mut self, val: &AlgebraicTypeLayout) -> Option<()> {
         match val {
-            AlgebraicTypeLayout::Sum(sum) => self.visit_sum(sum),
+            // `visit_sum` doesn't touch `self` before failing,
+            // so it's fine to record a hole in its place.
+            AlgebraicTypeLayout::Sum(sum) => self.visit_sum(sum).or_else(|| self.visit_hole(val)),
             AlgebraicTypeLayout::Product(prod) => self.visit_product(prod),
-            AlgebraicTypeLayout::Primitive(prim) => {
-                self.visit_primitive(prim);
-                Some(())
-            }
+            AlgebraicTypeLayout::Primitive(prim) => self.visit_primitive(prim),
 
             // Var-len types (obviously) don't have a known BSATN length,
-            // so fail.
-            AlgebraicTypeLayout::VarLen(_) => None,
+            // so record a hole, or fail if we're building a `StaticBsatnLayout`.
+            AlgebraicTypeLayout::VarLen(_) => self.visit_hole(val),
         }
     }
 
+    /// Ends the current run and records a hole for a member of type `ty`
+    /// stored at the next BFLATN offset.
+    ///
+    /// Returns `None` if building a [`StaticBsatnLayout`], which cannot contain holes.
+    fn visit_hole(&mut self, ty: &AlgebraicTypeLayout) -> Option<()> {
+        let bflatn_offset = self.next_bflatn_offset();
+        let segments = self.segments.as_mut()?;
+
+        // Start a new run after the hole.
+        // Its BSATN offsets are relative to the end of the hole.
+        let next_run = MemcpyField {
+            bflatn_offset: u16::try_from(ty.size())
+                .ok()
+                .and_then(|size| bflatn_offset.checked_add(size))?,
+            bsatn_offset: 0,
+            length: 0,
+        };
+        let fields = mem::replace(&mut self.fields, vec![next_run]);
+
+        let run = LayoutBuilder { fields, segments: None }.build();
+        if !run.fields.is_empty() {
+            segments.push(BsatnSegment::Fixed(run));
+        }
+        segments.push(BsatnSegment::Hole {
+            bflatn_offset,
+            ty: ty.clone(),
+        });
+        Some(())
+    }
+
     fn visit_sum(&mut self, sum: &SumTypeLayout) -> Option<()> {
         // If the sum has no variants, it's the never type, so there's no point in computing a layout.
         let first_variant = sum.variants.first()?;
 
-        let variant_layout = |variant: &SumTypeVariantLayout| {
-            let mut builder = LayoutBuilder::new_builder();
-            builder.visit_value(&variant.ty)?;
-            Some(builder.build())
-        };
-
         // Check that the variants all have the same `StaticBsatnLayout`.
         // If they don't, bail.
-        let first_variant_layout = variant_layout(first_variant)?;
+        let first_variant_layout = Self::variant_layout(first_variant)?;
         for later_variant in &sum.variants[1..] {
-            let later_variant_layout = variant_layout(later_variant)?;
+            let later_variant_layout = Self::variant_layout(later_variant)?;
             if later_variant_layout != first_variant_layout {
                 return None;
             }
         }
 
+        // Check that the whole sum fits in both BFLATN and BSATN before touching `self`,
+        // so that none of the steps below fails halfway.
+        self.next_bflatn_offset().checked_add(u16::try_from(sum.size()).ok()?)?;
+        self.next_bsatn_offset()
+            .checked_add(first_variant_layout.bsatn_length)?
+            .checked_add(1)?;
+
         if first_variant_layout.bsatn_length == 0 {
             // For C-style enums (those without payloads),
             // simply serialize the tag and move on.
-            self.current_field_mut().length += 1;
-            return Some(());
+            return self.visit_primitive(&PrimitiveType::U8);
         }
 
         // Now that we've reached this point, we know that `first_variant_layout`
         // applies to the values of all the variants.
 
         let tag_bflatn_offset = self.next_bflatn_offset();
-        let payload_bflatn_offset = tag_bflatn_offset + sum.payload_offset;
+        let payload_bflatn_offset = tag_bflatn_offset.checked_add(sum.payload_offset)?;
 
         let tag_bsatn_offset = self.next_bsatn_offset();
-        let payload_bsatn_offset = tag_bsatn_offset + 1;
+        let payload_bsatn_offset = tag_bsatn_offset.checked_add(1)?;
 
         // Serialize the tag, consolidating into the previous memcpy if possible.
-        self.visit_primitive(&PrimitiveType::U8);
+        self.visit_primitive(&PrimitiveType::U8)?;
 
         if sum.payload_offset > 1 {
             // Add an empty marker field to keep track of padding.
@@ -270,21 +720,37 @@ impl LayoutBuilder {
         Some(())
     }
 
-    fn visit_primitive(&mut self, prim: &PrimitiveType) {
-        self.current_field_mut().length += prim.size() as u16
+    /// Returns the `StaticBsatnLayout` of the payload of `variant`,
+    /// or `None` if it doesn't have one.
+    fn variant_layout(variant: &SumTypeVariantLayout) -> Option<StaticBsatnLayout> {
+        let mut builder = LayoutBuilder::new_builder();
+        builder.visit_value(&variant.ty)?;
+        Some(builder.build())
+    }
+
+    /// Extends the current field by the size of `prim`,
+    /// or returns `None` if its end would then exceed `u16::MAX`.
+    fn visit_primitive(&mut self, prim: &PrimitiveType) -> Option<()> {
+        let field = self.current_field_mut();
+        let length = field.length.checked_add(prim.size() as u16)?;
+        field.bflatn_offset.checked_add(length)?;
+        field.bsatn_offset.checked_add(length)?;
+        field.length = length;
+        Some(())
     }
 }
 
 #[cfg(test)]
 mod test {
     use super::*;
-    use crate::blob_store::HashMapBlobStore;
+    use crate::{bflatn_from::serialize_row_from_page, blob_store::HashMapBlobStore};
     use proptest::prelude::*;
-    use spacetimedb_sats::{bsatn, proptest::generate_typed_row, AlgebraicType, ProductType};
+    use spacetimedb_sats::{bsatn, product, proptest::generate_typed_row, AlgebraicType, ProductType};
 
-    fn assert_expected_layout(ty: ProductType, bsatn_length: u16, fields: &[(u16, u16, u16)]) {
-        let expected_layout = StaticBsatnLayout {
+    fn static_layout(bsatn_length: u16, fields: &[(u16, u16, u16)]) -> StaticBsatnLayout {
+        StaticBsatnLayout {
             bsatn_length,
+            max_bsatn_length: bsatn_length,
             fields: fields
                 .iter()
                 .copied()
@@ -294,7 +760,11 @@ mod test {
                     length,
                 })
                 .collect(),
-        };
+        }
+    }
+
+    fn assert_expected_layout(ty: ProductType, bsatn_length: u16, fields: &[(u16, u16, u16)]) {
+        let expected_layout = static_layout(bsatn_length, fields);
         let row_type = RowTypeLayout::from(ty);
         let Some(computed_layout) = StaticBsatnLayout::for_row_type(&row_type) else {
             panic!("assert_expected_layout: Computed `None` for row {row_type:#?}\nExpected:{expected_layout:#?}");
@@ -441,6 +911,41 @@ mod test {
         }
     }
 
+    #[test]
+    fn known_sums_max_bsatn_length() {
+        fn sum_layout<const N: usize>(variants: [AlgebraicType; N]) -> Option<StaticBsatnLayout> {
+            let AlgebraicType::Sum(sum) = AlgebraicType::sum(variants) else {
+                unreachable!()
+            };
+            StaticBsatnLayout::for_sum_type(&sum.into())
+        }
+
+        // The variants' lengths differ, so the layout merely bounds the BSATN length.
+        let layout = sum_layout([AlgebraicType::unit(), AlgebraicType::U8, AlgebraicType::U32]).unwrap();
+        assert!(!layout.is_fixed());
+        assert_eq!(layout.bsatn_length, 1);
+        assert_eq!(layout.max_bsatn_length(), 5);
+
+        // The variants' lengths are the same, but not their BFLATN layouts.
+        let layout = sum_layout([
+            AlgebraicType::product([AlgebraicType::U8, AlgebraicType::U32]),
+            AlgebraicType::product([AlgebraicType::U32, AlgebraicType::U8]),
+        ])
+        .unwrap();
+        assert!(!layout.is_fixed());
+        assert_eq!(layout.max_bsatn_length(), 6);
+
+        // Same layouts, so the BSATN length is fixed.
+        let layout = sum_layout([AlgebraicType::U32, AlgebraicType::I32]).unwrap();
+        assert!(layout.is_fixed());
+        assert_eq!(layout.bsatn_length, 5);
+        assert_eq!(layout.max_bsatn_length(), 5);
+
+        // No bound for var-len variants.
+        assert_eq!(sum_layout([AlgebraicType::U8, AlgebraicType::String]), None);
+        assert_eq!(sum_layout([]), None);
+    }
+
     #[test]
     fn known_types_not_applicable() {
         for ty in [
@@ -458,6 +963,162 @@ mod test {
         }
     }
 
+    #[test]
+    fn known_types_expected_partial_layout() {
+        let hole = |bflatn_offset, ty: AlgebraicType| BsatnSegment::Hole {
+            bflatn_offset,
+            ty: ty.into(),
+        };
+        let sum = AlgebraicType::sum([AlgebraicType::U8, AlgebraicType::U16]);
+
+        for (ty, segments) in [
+            (ProductType::new([].into()), vec![]),
+            (
+                ProductType::from([AlgebraicType::U64, AlgebraicType::U32]),
+                vec![BsatnSegment::Fixed(static_layout(12, &[(0, 0, 12)]))],
+            ),
+            (
+                ProductType::from([AlgebraicType::String]),
+                vec![hole(0, AlgebraicType::String)],
+            ),
+            (
+                ProductType::from([
+                    AlgebraicType::U64,
+                    AlgebraicType::U64,
+                    AlgebraicType::String,
+                    AlgebraicType::U32,
+                ]),
+                vec![
+                    BsatnSegment::Fixed(static_layout(16, &[(0, 0, 16)])),
+                    hole(16, AlgebraicType::String),
+                    // The run after the hole has BSATN offsets relative to its own start.
+                    BsatnSegment::Fixed(static_layout(4, &[(20, 0, 4)])),
+                ],
+            ),
+            (
+                ProductType::from([AlgebraicType::String, AlgebraicType::bytes()]),
+                vec![hole(0, AlgebraicType::String), hole(4, AlgebraicType::bytes())],
+            ),
+            (
+                // A sum whose variants have different lengths is also a hole.
+                ProductType::from([AlgebraicType::U8, sum.clone(), AlgebraicType::U8]),
+                vec![
+                    BsatnSegment::Fixed(static_layout(1, &[(0, 0, 1)])),
+                    hole(2, sum),
+                    BsatnSegment::Fixed(static_layout(1, &[(6, 0, 1)])),
+                ],
+            ),
+        ] {
+            let row_type = RowTypeLayout::from(ty);
+            let expected_layout = PartialBsatnLayout {
+                segments: segments.into(),
+            };
+            assert_eq!(PartialBsatnLayout::for_row_type(&row_type), Some(expected_layout));
+        }
+    }
+
+    #[test]
+    fn serialize_row_append_many() {
+        let mut blob_store = HashMapBlobStore::default();
+        // Padding between the members makes for several `memcpy`s per row.
+        let ty = ProductType::from([
+            AlgebraicType::U8,
+            AlgebraicType::U32,
+            AlgebraicType::U8,
+            AlgebraicType::U64,
+        ]);
+        let mut table = crate::table::test::table(ty);
+        let bsatn_layout = StaticBsatnLayout::for_row_type(table.row_layout()).unwrap();
+        assert!(bsatn_layout.fields.len() > 1);
+
+        let size = table.row_layout().size();
+        let ptrs = (0..5u8)
+            .map(|n| {
+                let row = product![n, 1000 * n as u32, !n, u64::MAX - n as u64];
+                table.insert(&mut blob_store, &row).unwrap().1.pointer()
+            })
+            .collect::<Vec<_>>();
+
+        // Start with existing contents, which must be kept intact.
+        let mut slow_path = vec![0xa5];
+        let mut fast_path = vec![0xa5];
+        for ptr in ptrs {
+            let row_ref = table.get_row_ref(&blob_store, ptr).unwrap();
+            slow_path.extend(bsatn::to_vec(&row_ref).unwrap());
+
+            let (page, offset) = row_ref.page_and_offset();
+            bsatn_layout.serialize_row_append(&mut fast_path, page.get_row_data(offset, size));
+        }
+        assert_eq!(fast_path.len(), 1 + 5 * bsatn_layout.bsatn_length as usize);
+        assert_eq!(slow_path, fast_path);
+    }
+
+    #[test]
+    fn serialize_rows_into_batch() {
+        let mut blob_store = HashMapBlobStore::default();
+        let ty = ProductType::from([AlgebraicType::U8, AlgebraicType::U32, AlgebraicType::U64]);
+        let mut table = crate::table::test::table(ty);
+        let bsatn_layout = StaticBsatnLayout::for_row_type(table.row_layout()).unwrap();
+
+        let size = table.row_layout().size();
+        let ptrs = (0..3u8)
+            .map(|n| {
+                let row = product![n, 1000 * n as u32, u64::MAX - n as u64];
+                table.insert(&mut blob_store, &row).unwrap().1.pointer()
+            })
+            .collect::<Vec<_>>();
+        let row_refs = ptrs
+            .into_iter()
+            .map(|ptr| table.get_row_ref(&blob_store, ptr).unwrap())
+            .collect::<Vec<_>>();
+        let rows = row_refs
+            .iter()
+            .map(|row_ref| {
+                let (page, offset) = row_ref.page_and_offset();
+                page.get_row_data(offset, size)
+            })
+            .collect::<Vec<_>>();
+
+        let mut individual = Vec::new();
+        for row in &rows {
+            bsatn_layout.serialize_row_append(&mut individual, row);
+        }
+
+        let len = bsatn_layout.batch_byte_len(rows.len());
+        assert_eq!(len, 3 * bsatn_layout.bsatn_length as usize);
+        let mut batch = Vec::with_capacity(len);
+        // SAFETY: The layout is fixed, `batch` has room for all three rows,
+        // and each row is an instance of the row type the layout was computed for.
+        unsafe {
+            bsatn_layout.serialize_rows_into(batch.spare_capacity_mut(), rows.iter().copied());
+            batch.set_len(len);
+        }
+        assert_eq!(batch, individual);
+    }
+
+    #[test]
+    fn build_merges_contiguous_fields() {
+        let field = |bflatn_offset, bsatn_offset, length| MemcpyField {
+            bflatn_offset,
+            bsatn_offset,
+            length,
+        };
+        let builder = LayoutBuilder {
+            fields: vec![
+                field(0, 0, 2),
+                // An empty marker, after which the next field is contiguous with the first.
+                field(2, 2, 0),
+                field(2, 2, 4),
+                // Padding in BFLATN, so not contiguous.
+                field(8, 6, 1),
+                // Contiguous in BFLATN, but not BSATN.
+                field(9, 8, 1),
+            ],
+            segments: None,
+        };
+        assert_eq!(builder.build(), static_layout(9, &[(0, 0, 6), (8, 6, 1), (9, 8, 1)]));
+    }
+
     proptest! {
         // The test `known_bsatn_same_as_bflatn_from` generates a lot of rejects,
         // as a vast majority of the space of `ProductType` does not have a fixed BSATN length.
@@ -504,5 +1165,115 @@ mod test {
 
             assert_eq!(slow_path, fast_path);
         }
+
+        #[test]
+        fn partial_bsatn_same_as_bflatn_from((ty, val) in generate_typed_row()) {
+            let mut blob_store = HashMapBlobStore::default();
+            let mut table = crate::table::test::table(ty);
+            let bsatn_layout = PartialBsatnLayout::for_row_type(table.row_layout()).unwrap();
+
+            let size = table.row_layout().size();
+            let ptr = table.insert(&mut blob_store, &val).unwrap().1.pointer();
+            let row_ref = table.get_row_ref(&blob_store, ptr).unwrap();
+
+            let (page, offset) = row_ref.page_and_offset();
+            let slow_path = {
+                let mut buf = Vec::new();
+                unsafe { serialize_row_from_page(bsatn::Serializer::new(&mut buf), page, &blob_store, offset, table.row_layout()) }.unwrap();
+                buf
+            };
+
+            let bytes = page.get_row_data(offset, size);
+            let mut fast_path = Vec::new();
+            unsafe { bsatn_layout.serialize_row_extend(&mut fast_path, bytes, page, &blob_store) }.unwrap();
+
+            assert_eq!(slow_path, fast_path);
+        }
+    }
+
+    #[test]
+    fn static_layout_for_caches() {
+        let fixed = RowTypeLayout::from(ProductType::from([AlgebraicType::U64, AlgebraicType::U32]));
+        let first = static_layout_for(&fixed).expect("a row of integers should have a static layout");
+        let second = static_layout_for(&fixed).unwrap();
+        assert!(Arc::ptr_eq(&first, &second));
+        assert_eq!(Some(&*first), StaticBsatnLayout::for_row_type(&fixed).as_ref());
+
+        let var_len = RowTypeLayout::from(ProductType::from([AlgebraicType::U64, AlgebraicType::String]));
+        assert_eq!(static_layout_for(&var_len), None);
+        assert_eq!(static_layout_for(&var_len), None);
+    }
+
+    #[test]
+    fn static_layout_cache_evicts_least_recently_used() {
+        let row_type = |ty: AlgebraicType| RowTypeLayout::from(ProductType::from([ty]));
+        let [a, b, c] = [AlgebraicType::U8, AlgebraicType::U16, AlgebraicType::String].map(row_type);
+
+        let mut cache = StaticLayoutCache::new(2);
+        let layout_a = cache.get_or_compute(&a).unwrap();
+        assert_eq!(cache.get_or_compute(&b).map(|l| l.bsatn_length), Some(2));
+        // Use `a` again, so that `b` becomes the least recently used.
+        assert!(Arc::ptr_eq(&layout_a, &cache.get_or_compute(&a).unwrap()));
+
+        // The absence of a layout is cached as well.
+        assert_eq!(cache.get_or_compute(&c), None);
+        assert_eq!(cache.layouts.len(), 2);
+        assert!(cache.layouts.contains_key(&a));
+        assert!(cache.layouts.contains_key(&c));
+        assert!(!cache.layouts.contains_key(&b));
+    }
+
+    #[test]
+    fn rows_wider_than_u16_have_no_layout() {
+        // A chunk of 1024 `u64`s takes 8 KiB in both BFLATN and BSATN.
+        let chunk = AlgebraicType::product([AlgebraicType::U64; 1024]);
+        let row_type = |chunks: usize| RowTypeLayout::from(ProductType::from_iter(vec![chunk.clone(); chunks]));
+
+        // 7 chunks, i.e., 56 KiB, still fit.
+        let layout = StaticBsatnLayout::for_row_type(&row_type(7)).unwrap();
+        assert_eq!(layout.bsatn_length, 7 * 8192);
+
+        // 9 chunks, i.e., 72 KiB, would wrap the offsets, so there's no layout rather than a bogus one.
+        let wide = row_type(9);
+        assert_eq!(StaticBsatnLayout::for_row_type(&wide), None);
+        assert_eq!(PartialBsatnLayout::for_row_type(&wide), None);
+    }
+
+    #[test]
+    fn validate_layout() {
+        // `(u8, u64)`, whose BFLATN representation has 7 bytes of padding after the `u8`.
+        let ty = ProductType::from([AlgebraicType::U8, AlgebraicType::U64]);
+        let row_type = RowTypeLayout::from(ty);
+        let row_len = row_type.size().len();
+        let layout = StaticBsatnLayout::for_row_type(&row_type).unwrap();
+        assert_eq!(layout, static_layout(9, &[(0, 0, 1), (8, 1, 8)]));
+        assert_eq!(layout.validate(row_len), Ok(()));
+
+        // Reading past the end of the row.
+        assert_eq!(
+            static_layout(9, &[(0, 0, 1), (12, 1, 8)]).validate(row_len),
+            Err(LayoutError::OutOfRow {
+                bflatn_offset: 12,
+                length: 8,
+                row_len,
+            })
+        );
+        // Writing past the end of the BSATN encoding.
+        assert_eq!(
+            static_layout(9, &[(0, 0, 1), (8, 2, 8)]).validate(row_len),
+            Err(LayoutError::OutOfBsatn {
+                bsatn_offset: 2,
+                length: 8,
+                bsatn_length: 9,
+            })
+        );
+        // Writing the same bytes twice, even when listed out of order.
+        assert_eq!(
+            static_layout(9, &[(8, 1, 8), (0, 0, 2)]).validate(row_len),
+            Err(LayoutError::Overlapping {
+                first_bsatn_offset: 0,
+                second_bsatn_offset: 1,
+            })
+        );
     }
 }
@@ -76,6 +76,29 @@ impl<'a> Iterator for TypedMultiMapRangeIter<'a> {
     }
 }
 
+impl TypedMultiMapRangeIter<'_> {
+    /// Advances the iterator, returning the key of the next row rather than a pointer to it.
+    fn next_key(&mut self) -> Option<AlgebraicValue> {
+        match self {
+            TypedMultiMapRangeIter::Bool(ref mut this) => this.next_entry().map(|(k, _)| AlgebraicValue::Bool(*k)),
+            TypedMultiMapRangeIter::U8(ref mut this) => this.next_entry().map(|(k, _)| AlgebraicValue::U8(*k)),
+            TypedMultiMapRangeIter::I8(ref mut this) => this.next_entry().map(|(k, _)| AlgebraicValue::I8(*k)),
+            TypedMultiMapRangeIter::U16(ref mut this) => this.next_entry().map(|(k, _)| AlgebraicValue::U16(*k)),
+            TypedMultiMapRangeIter::I16(ref mut this) => this.next_entry().map(|(k, _)| AlgebraicValue::I16(*k)),
+            TypedMultiMapRangeIter::U32(ref mut this) => this.next_entry().map(|(k, _)| AlgebraicValue::U32(*k)),
+            TypedMultiMapRangeIter::I32(ref mut this) => this.next_entry().map(|(k, _)| AlgebraicValue::I32(*k)),
+            TypedMultiMapRangeIter::U64(ref mut this) => this.next_entry().map(|(k, _)| AlgebraicValue::U64(*k)),
+            TypedMultiMapRangeIter::I64(ref mut this) => this.next_entry().map(|(k, _)| AlgebraicValue::I64(*k)),
+            TypedMultiMapRangeIter::U128(ref mut this) => this.next_entry().map(|(k, _)| AlgebraicValue::U128(*k)),
+            TypedMultiMapRangeIter::I128(ref mut this) => this.next_entry().map(|(k, _)| AlgebraicValue::I128(*k)),
+            TypedMultiMapRangeIter::String(ref mut this) => {
+                this.next_entry().map(|(k, _)| AlgebraicValue::String(k.clone()))
+            }
+            TypedMultiMapRangeIter::AlgebraicValue(ref mut this) => this.next_entry().map(|(k, _)| k.clone()),
+        }
+    }
+}
+
 /// An iterator over rows matching a certain [`AlgebraicValue`] on the [`BTreeIndex`].
 pub struct BTreeIndexRangeIter<'a> {
     /// The iterator seeking for matching values.
@@ -102,6 +125,21 @@ impl BTreeIndexRangeIter<'_> {
     }
 }
 
+/// An iterator over the keys of the rows matching a certain [`AlgebraicValue`] on the [`BTreeIndex`],
+/// yielding a key once for every row with it, without reading the rows.
+pub struct BTreeIndexKeyIter<'a> {
+    /// The iterator seeking for matching values.
+    iter: TypedMultiMapRangeIter<'a>,
+}
+
+impl Iterator for BTreeIndexKeyIter<'_> {
+    type Item = AlgebraicValue;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.iter.next_key()
+    }
+}
+
 /// A `MultiMap` from a key type determined at runtime to `RowPointer`.
 ///
 /// See module docs for info about specialization.
@@ -315,6 +353,25 @@ impl TypedIndex {
             TypedIndex::AlgebraicValue(ref this) => this.len(),
         }
     }
+
+    /// Returns the number of distinct keys in the index.
+    fn num_keys(&self) -> usize {
+        match self {
+            TypedIndex::Bool(ref this) => this.num_keys(),
+            TypedIndex::U8(ref this) => this.num_keys(),
+            TypedIndex::I8(ref this) => this.num_keys(),
+            TypedIndex::U16(ref this) => this.num_keys(),
+            TypedIndex::I16(ref this) => this.num_keys(),
+            TypedIndex::U32(ref this) => this.num_keys(),
+            TypedIndex::I32(ref this) => this.num_keys(),
+            TypedIndex::U64(ref this) => this.num_keys(),
+            TypedIndex::I64(ref this) => this.num_keys(),
+            TypedIndex::U128(ref this) => this.num_keys(),
+            TypedIndex::I128(ref this) => this.num_keys(),
+            TypedIndex::String(ref this) => this.num_keys(),
+            TypedIndex::AlgebraicValue(ref this) => this.num_keys(),
+        }
+    }
 }
 
 /// A B-Tree based index on a set of [`ColId`]s of a table.
@@ -415,6 +472,22 @@ impl BTreeIndex {
         }
     }
 
+    /// Returns the number of distinct values in the indexed columns of the table,
+    /// i.e., the number of distinct keys in this index.
+    pub fn num_keys(&self) -> u64 {
+        self.idx.num_keys() as u64
+    }
+
+    /// Returns an iterator over the keys in this index that fall within the specified `range`,
+    /// yielding each key once for every row with it.
+    ///
+    /// The key of a multi-column index is a product of the values of its columns.
+    pub fn seek_keys(&self, range: &impl RangeBounds<AlgebraicValue>) -> BTreeIndexKeyIter<'_> {
+        BTreeIndexKeyIter {
+            iter: self.idx.values_in_range(range),
+        }
+    }
+
     /// Extends [`BTreeIndex`] with `rows`.
     /// Returns whether every element in `rows` was inserted.
     pub fn build_from_rows<'table>(
@@ -514,10 +587,12 @@ mod test {
 
             index.insert(&cols, row_ref).unwrap();
             prop_assert_eq!(index.idx.len(), 1);
+            prop_assert_eq!(index.num_keys(), 1);
             prop_assert_eq!(index.contains_any(&value), true);
 
             prop_assert_eq!(index.delete(&cols, row_ref).unwrap(), true);
             prop_assert_eq!(index.idx.len(), 0);
+            prop_assert_eq!(index.num_keys(), 0);
             prop_assert_eq!(index.contains_any(&value), false);
         }
 
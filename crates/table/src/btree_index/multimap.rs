@@ -36,6 +36,10 @@ impl<K: Ord, V: Ord> MultiMap<K, V> {
             // The `vset` is not sorted, so we have to do a linear scan first.
             if let Some(idx) = vset.iter().position(|v| v == val) {
                 vset.swap_remove(idx);
+                // Drop the key along with its last value, so that `num_keys` stays exact.
+                if vset.is_empty() {
+                    self.map.remove(key);
+                }
                 return true;
             }
         }
@@ -57,6 +61,11 @@ impl<K: Ord, V: Ord> MultiMap<K, V> {
         self.map.values().map(|ptrs| ptrs.len()).sum()
     }
 
+    /// Returns the number of distinct keys in the multimap.
+    pub fn num_keys(&self) -> usize {
+        self.map.len()
+    }
+
     /// Returns whether there are any entries in the multimap.
     #[allow(unused)] // No use for this currently.
     pub fn is_empty(&self) -> bool {
@@ -74,19 +83,18 @@ impl<K: Ord, V: Ord> MultiMap<K, V> {
 pub struct MultiMapRangeIter<'a, K, V> {
     /// The outer iterator seeking for matching keys in the range.
     outer: Range<'a, K, SmallVec<[V; 1]>>,
-    /// The inner iterator for the value set for a found key.
-    inner: Option<slice::Iter<'a, V>>,
+    /// The inner iterator for the value set for a found key, along with that key.
+    inner: Option<(&'a K, slice::Iter<'a, V>)>,
 }
 
-impl<'a, K, V> Iterator for MultiMapRangeIter<'a, K, V> {
-    type Item = &'a V;
-
-    fn next(&mut self) -> Option<Self::Item> {
+impl<'a, K, V> MultiMapRangeIter<'a, K, V> {
+    /// Advances the iterator, returning the next value along with its key.
+    pub fn next_entry(&mut self) -> Option<(&'a K, &'a V)> {
         loop {
-            if let Some(inner) = self.inner.as_mut() {
+            if let Some((key, inner)) = self.inner.as_mut() {
                 if let Some(val) = inner.next() {
                     // While the inner iterator has elements, yield them.
-                    return Some(val);
+                    return Some((key, val));
                 }
             }
 
@@ -94,8 +102,16 @@ impl<'a, K, V> Iterator for MultiMapRangeIter<'a, K, V> {
             self.inner = None;
             // Advance and get a new inner, if possible, or quit.
             // We'll come back and yield elements from it in the next iteration.
-            let (_, next) = self.outer.next()?;
-            self.inner = Some(next.iter());
+            let (key, next) = self.outer.next()?;
+            self.inner = Some((key, next.iter()));
         }
     }
 }
+
+impl<'a, K, V> Iterator for MultiMapRangeIter<'a, K, V> {
+    type Item = &'a V;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.next_entry().map(|(_, val)| val)
+    }
+}
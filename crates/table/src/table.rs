@@ -1,9 +1,9 @@
 use super::{
-    bflatn_from::serialize_row_from_page,
+    bflatn_from::{serialize_row_from_page, vlr_blob_bytes},
     bflatn_to::write_row_to_pages,
-    bflatn_to_bsatn_fast_path::StaticBsatnLayout,
+    bflatn_to_bsatn_fast_path::{static_layout_for, PartialBsatnLayout, StaticBsatnLayout},
     blob_store::{BlobStore, NullBlobStore},
-    btree_index::{BTreeIndex, BTreeIndexRangeIter},
+    btree_index::{BTreeIndex, BTreeIndexKeyIter, BTreeIndexRangeIter},
     eq::eq_row_in_page,
     eq_to_pv::eq_row_in_page_to_pv,
     indexes::{Bytes, PageIndex, PageOffset, RowHash, RowPointer, Size, SquashedOffset},
@@ -15,10 +15,11 @@ use super::{
     row_hash::hash_row_in_page,
     row_type_visitor::{row_type_visitor, VarLenVisitorProgram},
     static_assert_size,
+    var_len::VarLenMembers,
 };
 use core::hash::{Hash, Hasher};
-use core::ops::RangeBounds;
-use core::{fmt, ptr};
+use core::ops::{Bound, RangeBounds};
+use core::{fmt, mem, ptr};
 use spacetimedb_data_structures::map::HashMap;
 use spacetimedb_primitives::{ColId, ColList};
 use spacetimedb_sats::{
@@ -40,8 +41,6 @@ use thiserror::Error;
 pub struct Table {
     /// Page manager and row layout grouped together, for `RowRef` purposes.
     inner: TableInner,
-    /// The visitor program for `row_layout`.
-    visitor_prog: VarLenVisitorProgram,
     /// Maps `RowHash -> [RowPointer]` where a [`RowPointer`] points into `pages`.
     pointer_map: PointerMap,
     /// The indices associated with a set of columns of the table.
@@ -52,6 +51,10 @@ pub struct Table {
     /// depending on whether this is a tx scratchpad table
     /// or a committed table.
     squashed_offset: SquashedOffset,
+    /// The number of bytes the large blobs of the rows in `pages` occupy in the blob store.
+    ///
+    /// A blob shared by several rows is counted once per row.
+    blob_store_bytes: usize,
 }
 
 impl Table {
@@ -75,7 +78,15 @@ pub(crate) struct TableInner {
     row_layout: RowTypeLayout,
     /// A [`StaticBsatnLayout`] for fast BFLATN -> BSATN serialization,
     /// if the [`RowTypeLayout`] has a static BSATN length and layout.
-    static_bsatn_layout: Option<StaticBsatnLayout>,
+    ///
+    /// Shared with the other tables of the same row type, see [`static_layout_for`].
+    static_bsatn_layout: Option<Arc<StaticBsatnLayout>>,
+    /// A [`PartialBsatnLayout`] for BFLATN -> BSATN serialization
+    /// which `memcpy`s the fixed-length runs of a row,
+    /// if the [`RowTypeLayout`] does not have a static BSATN layout.
+    partial_bsatn_layout: Option<PartialBsatnLayout>,
+    /// The visitor program for `row_layout`.
+    visitor_prog: VarLenVisitorProgram,
     /// The page manager that holds rows
     /// including both their fixed and variable components.
     pages: Pages,
@@ -111,7 +122,7 @@ impl TableInner {
     }
 }
 
-static_assert_size!(Table, 240);
+static_assert_size!(Table, 264);
 
 /// Various error that can happen on table insertion.
 #[derive(Error, Debug)]
@@ -134,12 +145,17 @@ impl Table {
     /// Creates a new empty table with the given `schema` and `squashed_offset`.
     pub fn new(schema: Arc<TableSchema>, squashed_offset: SquashedOffset) -> Self {
         let row_layout: RowTypeLayout = schema.get_row_type().clone().into();
-        let static_bsatn_layout = StaticBsatnLayout::for_row_type(&row_layout);
+        let static_bsatn_layout = static_layout_for(&row_layout);
+        let partial_bsatn_layout = match static_bsatn_layout {
+            None => PartialBsatnLayout::for_row_type(&row_layout),
+            Some(_) => None,
+        };
         let visitor_prog = row_type_visitor(&row_layout);
         Self::new_with_indexes_capacity(
             schema,
             row_layout,
             static_bsatn_layout,
+            partial_bsatn_layout,
             visitor_prog,
             squashed_offset,
             0,
@@ -234,11 +250,7 @@ impl Table {
             // roll back our optimistic insert to avoid violating set semantics.
 
             // SAFETY: we just inserted `ptr`, so it must be valid.
-            unsafe {
-                self.inner
-                    .pages
-                    .delete_row(&self.visitor_prog, self.row_size(), ptr, blob_store)
-            };
+            unsafe { self.delete_internal_skip_pointer_map(blob_store, ptr) };
             return Err(InsertError::Duplicate(existing_row));
         }
 
@@ -265,7 +277,7 @@ impl Table {
         let ptr = unsafe {
             write_row_to_pages(
                 &mut self.inner.pages,
-                &self.visitor_prog,
+                &self.inner.visitor_prog,
                 blob_store,
                 &self.inner.row_layout,
                 row,
@@ -273,6 +285,8 @@ impl Table {
             )
         }?;
         // SAFETY: We just inserted `ptr`, so it must be present.
+        self.blob_store_bytes += unsafe { self.row_blob_store_bytes(blob_store, ptr) };
+        // SAFETY: Likewise.
         let row_ref = unsafe { self.inner.get_row_ref_unchecked(blob_store, ptr) };
 
         Ok(row_ref)
@@ -361,6 +375,10 @@ impl Table {
     ///
     /// `ptr` must point to a valid, live row in this table.
     pub unsafe fn delete_internal_skip_pointer_map(&mut self, blob_store: &mut dyn BlobStore, ptr: RowPointer) {
+        // Account for the blobs while they're still in the blob store.
+        // SAFETY: `ptr` points to a valid row in this table, per our invariants.
+        self.blob_store_bytes -= unsafe { self.row_blob_store_bytes(blob_store, ptr) };
+
         // Delete the physical row.
         //
         // SAFETY:
@@ -370,7 +388,7 @@ impl Table {
         unsafe {
             self.inner
                 .pages
-                .delete_row(&self.visitor_prog, self.row_size(), ptr, blob_store)
+                .delete_row(&self.inner.visitor_prog, self.row_size(), ptr, blob_store)
         };
     }
 
@@ -504,6 +522,15 @@ impl Table {
         &self.schema
     }
 
+    /// Returns the approximate number of bytes this table occupies,
+    /// i.e., those of its pages, including both the fixed-len and var-len parts of its rows,
+    /// plus those of its rows' large blobs in the blob store.
+    ///
+    /// A blob shared by several rows is counted once per row.
+    pub fn bytes_occupied(&self) -> usize {
+        self.inner.pages.len() * mem::size_of::<Page>() + self.blob_store_bytes
+    }
+
     /// Runs a mutation on the [`TableSchema`] of this table.
     ///
     /// This uses a clone-on-write mechanism.
@@ -537,6 +564,9 @@ impl Table {
     /// returns an iterator over the [`BTreeIndex`] that yields all the [`RowRef`]s
     /// matching the specified `range` in the indexed column.
     ///
+    /// Otherwise, when there's an index whose leading columns are `cols`,
+    /// the iterator scans the part of that index where the values of `cols` are in `range`.
+    ///
     /// Matching is defined by `Ord for AlgebraicValue`.
     pub fn index_seek<'a>(
         &'a self,
@@ -544,16 +574,58 @@ impl Table {
         cols: &ColList,
         range: &impl RangeBounds<AlgebraicValue>,
     ) -> Option<IndexScanIter<'a>> {
-        self.indexes.get(cols).map(|index| {
-            let btree_index_iter = index.seek(range);
-            IndexScanIter {
+        if let Some(index) = self.indexes.get(cols) {
+            return Some(IndexScanIter {
                 table: self,
                 blob_store,
-                btree_index_iter,
+                btree_index_iter: index.seek(range),
+                prefix: None,
+            });
+        }
+
+        let (_, index) = self.indexes.iter().find(|(index_cols, _)| {
+            index_cols.len() > cols.len() && cols.iter().zip(index_cols.iter()).all(|(col, idx)| col == idx)
+        })?;
+        // The keys of a multi-column index are products,
+        // and a product of the leading values alone sorts before all the keys starting with them.
+        // So start the scan at the lower bound on `cols`, if any,
+        // and leave it to the iterator to skip an excluded bound and to stop past the upper bound.
+        let start = match range.start_bound() {
+            Bound::Included(value) | Bound::Excluded(value) => {
+                let elements = if cols.is_singleton() {
+                    [value.clone()].into()
+                } else {
+                    value.as_product()?.elements.clone()
+                };
+                Bound::Included(AlgebraicValue::product(elements))
             }
+            Bound::Unbounded => Bound::Unbounded,
+        };
+        Some(IndexScanIter {
+            table: self,
+            blob_store,
+            btree_index_iter: index.seek(&(start, Bound::Unbounded)),
+            prefix: Some(PrefixRange {
+                cols: cols.clone(),
+                start: range.start_bound().cloned(),
+                end: range.end_bound().cloned(),
+            }),
         })
     }
 
+    /// When there's an index for exactly `cols`,
+    /// returns an iterator over the keys of that index matching the specified `range`,
+    /// yielding each key once for every row with it, without reading the rows.
+    ///
+    /// Unlike [`Table::index_seek`], an index merely led by `cols` isn't used.
+    pub fn index_seek_keys(
+        &self,
+        cols: &ColList,
+        range: &impl RangeBounds<AlgebraicValue>,
+    ) -> Option<BTreeIndexKeyIter<'_>> {
+        Some(self.indexes.get(cols)?.seek_keys(range))
+    }
+
     /// Clones the structure of this table into a new one with
     /// the same schema, visitor program, and indices.
     /// The new table will be completely empty
@@ -562,9 +634,10 @@ impl Table {
         let schema = self.schema.clone();
         let layout = self.row_layout().clone();
         let sbl = self.inner.static_bsatn_layout.clone();
-        let visitor = self.visitor_prog.clone();
+        let pbl = self.inner.partial_bsatn_layout.clone();
+        let visitor = self.inner.visitor_prog.clone();
         let mut new =
-            Table::new_with_indexes_capacity(schema, layout, sbl, visitor, squashed_offset, self.indexes.len());
+            Table::new_with_indexes_capacity(schema, layout, sbl, pbl, visitor, squashed_offset, self.indexes.len());
 
         for (cols, index) in self.indexes.iter() {
             // `new` is known to be empty (we just constructed it!),
@@ -712,9 +785,30 @@ impl<'a> RowRef<'a> {
         self.table.static_bsatn_layout.as_ref().map(|s| s.bsatn_length as usize)
     }
 
+    /// Returns the number of var-len members of this row that are stored as large blobs,
+    /// i.e., how many blobs serializing the row has to fetch from the blob store.
+    ///
+    /// This is always zero for rows whose types have a static BSATN layout,
+    /// as those are serialized without ever touching the blob store.
+    pub fn num_large_blobs(&self) -> usize {
+        if self.table.static_bsatn_layout.is_some() {
+            return 0;
+        }
+
+        let (page, offset) = self.page_and_offset();
+        let fixed_row = page.get_row_data(offset, self.table.row_layout.size());
+        // SAFETY:
+        // - Existence of a `RowRef` treated as proof of row's validity,
+        //   so `fixed_row` is a valid row of `self.table.row_layout`.
+        // - `self.table.visitor_prog` is proper for `self.table.row_layout`,
+        //   as the two are tied together in `Table::new`.
+        let var_len_refs = unsafe { self.table.visitor_prog.visit_var_len(fixed_row) };
+        var_len_refs.filter(|vlr| vlr.is_large_blob()).count()
+    }
+
     /// BSATN-encode the row referred to by `self` into a freshly-allocated `Vec<u8>`.
     ///
-    /// This method will use a [`StaticBsatnLayout`] if one is available,
+    /// This method will use a [`StaticBsatnLayout`] or [`PartialBsatnLayout`] if one is available,
     /// and may therefore be faster than calling [`bsatn::to_vec`].
     pub fn to_bsatn_vec(&self) -> Result<Vec<u8>, BsatnError> {
         if let Some(static_bsatn_layout) = &self.table.static_bsatn_layout {
@@ -740,14 +834,16 @@ impl<'a> RowRef<'a> {
             unsafe { buf.set_len(bsatn_len) }
             Ok(buf)
         } else {
-            bsatn::to_vec(self)
+            let mut buf = Vec::new();
+            self.to_bsatn_extend(&mut buf)?;
+            Ok(buf)
         }
     }
 
     /// BSATN-encode the row referred to by `self` into `buf`,
     /// pushing `self`'s bytes onto the end of `buf`, similar to [`Vec::extend`].
     ///
-    /// This method will use a [`StaticBsatnLayout`] if one is available,
+    /// This method will use a [`StaticBsatnLayout`] or [`PartialBsatnLayout`] if one is available,
     /// and may therefore be faster than calling [`bsatn::to_writer`].
     pub fn to_bsatn_extend(&self, buf: &mut Vec<u8>) -> Result<(), BsatnError> {
         if let Some(static_bsatn_layout) = &self.table.static_bsatn_layout {
@@ -776,6 +872,16 @@ impl<'a> RowRef<'a> {
             unsafe { buf.set_len(start + len) }
 
             Ok(())
+        } else if let Some(partial_bsatn_layout) = &self.table.partial_bsatn_layout {
+            // Find the row referred to by `self`.
+            let (page, offset) = self.page_and_offset();
+            let row = page.get_row_data(offset, self.table.row_layout.size());
+
+            // Write the fixed-length runs of the row using `memcpy`s,
+            // and the rest using the `bsatn_from` serializer.
+            // SAFETY: Existence of a `RowRef` treated as proof
+            // of row's validity and type information's correctness.
+            unsafe { partial_bsatn_layout.serialize_row_extend(buf, row, page, self.blob_store) }
         } else {
             // Use the slower, but more general, `bsatn_from` serializer to write the row.
             bsatn::to_writer(buf, self)
@@ -903,22 +1009,70 @@ pub struct IndexScanIter<'a> {
     blob_store: &'a dyn BlobStore,
     /// The iterator performing the index scan yielding row pointers.
     btree_index_iter: BTreeIndexRangeIter<'a>,
+    /// When scanning an index on more columns than those sought,
+    /// the sought leading columns and the range of their values.
+    prefix: Option<PrefixRange>,
+}
+
+/// The range of the values of the leading `cols` of a multi-column index
+/// that an [`IndexScanIter`] yields the rows of.
+struct PrefixRange {
+    cols: ColList,
+    start: Bound<AlgebraicValue>,
+    end: Bound<AlgebraicValue>,
+}
+
+impl PrefixRange {
+    /// Returns whether `key`, the values of `self.cols` of a row, is before `self.start`.
+    fn is_before_start(&self, key: &AlgebraicValue) -> bool {
+        match &self.start {
+            Bound::Included(start) => key < start,
+            Bound::Excluded(start) => key <= start,
+            Bound::Unbounded => false,
+        }
+    }
+
+    /// Returns whether `key`, the values of `self.cols` of a row, is past `self.end`.
+    fn is_past_end(&self, key: &AlgebraicValue) -> bool {
+        match &self.end {
+            Bound::Included(end) => key > end,
+            Bound::Excluded(end) => key >= end,
+            Bound::Unbounded => false,
+        }
+    }
 }
 
 impl<'a> Iterator for IndexScanIter<'a> {
     type Item = RowRef<'a>;
 
     fn next(&mut self) -> Option<Self::Item> {
-        let ptr = self.btree_index_iter.next()?;
-        // FIXME: Determine if this is correct and if so use `_unchecked`.
-        // Will a table's index necessarily hold only pointers into that index?
-        // Edge case: if an index is added during a transaction which then scans that index,
-        // it appears that the newly-created `TxState` index
-        // will also hold pointers into the `CommittedState`.
-        //
-        // SAFETY: Assuming this is correct,
-        // `ptr` came from the index, which always holds pointers to valid rows.
-        self.table.get_row_ref(self.blob_store, ptr)
+        loop {
+            let ptr = self.btree_index_iter.next()?;
+            // FIXME: Determine if this is correct and if so use `_unchecked`.
+            // Will a table's index necessarily hold only pointers into that index?
+            // Edge case: if an index is added during a transaction which then scans that index,
+            // it appears that the newly-created `TxState` index
+            // will also hold pointers into the `CommittedState`.
+            //
+            // SAFETY: Assuming this is correct,
+            // `ptr` came from the index, which always holds pointers to valid rows.
+            let row_ref = self.table.get_row_ref(self.blob_store, ptr)?;
+            let Some(prefix) = &self.prefix else {
+                return Some(row_ref);
+            };
+
+            // The index is sorted by the leading columns first,
+            // so all the rows past the end of the range come after those in it.
+            let key = row_ref
+                .project_not_empty(&prefix.cols)
+                .expect("the sought columns should be in the index");
+            if prefix.is_past_end(&key) {
+                return None;
+            }
+            if !prefix.is_before_start(&key) {
+                return Some(row_ref);
+            }
+        }
     }
 }
 
@@ -963,27 +1117,34 @@ impl Table {
         }
     }
 
-    /// Returns a new empty table with the given `schema`, `row_layout`, and `static_bsatn_layout`s
+    /// Returns a new empty table with the given `schema`, `row_layout`,
+    /// `static_bsatn_layout`, and `partial_bsatn_layout`s
     /// and with a specified capacity for the `indexes` of the table.
     fn new_with_indexes_capacity(
         schema: Arc<TableSchema>,
         row_layout: RowTypeLayout,
-        static_bsatn_layout: Option<StaticBsatnLayout>,
+        static_bsatn_layout: Option<Arc<StaticBsatnLayout>>,
+        partial_bsatn_layout: Option<PartialBsatnLayout>,
         visitor_prog: VarLenVisitorProgram,
         squashed_offset: SquashedOffset,
         indexes_capacity: usize,
     ) -> Self {
+        if let Some(layout) = &static_bsatn_layout {
+            debug_assert_eq!(layout.validate(row_layout.size().len()), Ok(()));
+        }
         Self {
             inner: TableInner {
                 row_layout,
                 static_bsatn_layout,
+                partial_bsatn_layout,
+                visitor_prog,
                 pages: Pages::default(),
             },
-            visitor_prog,
             schema,
             indexes: HashMap::with_capacity(indexes_capacity),
             pointer_map: PointerMap::default(),
             squashed_offset,
+            blob_store_bytes: 0,
         }
     }
 
@@ -1022,12 +1183,32 @@ impl Table {
         let (page, offset) = self.inner.page_and_offset(ptr);
         page.get_row_data(offset, self.row_size())
     }
+
+    /// Returns the number of bytes the large blobs of the row at `ptr` occupy in `blob_store`.
+    ///
+    /// # Safety
+    ///
+    /// `ptr` must point to a valid, live row in this table.
+    unsafe fn row_blob_store_bytes(&self, blob_store: &dyn BlobStore, ptr: RowPointer) -> usize {
+        let (page, offset) = self.inner.page_and_offset(ptr);
+        let fixed_row = page.get_row_data(offset, self.row_size());
+        // SAFETY:
+        // - `ptr` points to a valid row, so `fixed_row` is a valid row of `self.row_layout`.
+        // - `self.visitor_prog` is proper for `self.row_layout`, as the two are tied together in `Table::new`.
+        let var_len_refs = unsafe { self.inner.visitor_prog.visit_var_len(fixed_row) };
+        var_len_refs
+            .filter(|vlr| vlr.is_large_blob())
+            // SAFETY: As `vlr` is a blob, `vlr.first_granule` always points to a valid granule.
+            .map(|vlr| unsafe { vlr_blob_bytes(page, blob_store, *vlr) }.len())
+            .sum()
+    }
 }
 
 #[cfg(test)]
 pub(crate) mod test {
     use super::*;
     use crate::blob_store::HashMapBlobStore;
+    use crate::var_len::VarLenGranule;
     use proptest::prelude::*;
     use proptest::test_runner::TestCaseResult;
     use spacetimedb_sats::bsatn::to_vec;
@@ -1117,6 +1298,19 @@ pub(crate) mod test {
         Ok(())
     }
 
+    #[test]
+    fn tables_share_static_layout() {
+        let ty = ProductType::from([AlgebraicType::U32, AlgebraicType::I64]);
+        let (a, b) = (table(ty.clone()), table(ty));
+        let (a, b) = (a.inner.static_bsatn_layout, b.inner.static_bsatn_layout);
+        assert!(Arc::ptr_eq(a.as_ref().unwrap(), b.as_ref().unwrap()));
+
+        assert!(table([AlgebraicType::String].into())
+            .inner
+            .static_bsatn_layout
+            .is_none());
+    }
+
     #[test]
     fn repro_serialize_bsatn_empty_array() {
         let ty = AlgebraicType::array(AlgebraicType::U64);
@@ -1229,4 +1423,148 @@ pub(crate) mod test {
         // Miri should not have any issue with this call either.
         table.get_row_ref(&NullBlobStore, ptr).unwrap().to_product_value();
     }
+
+    #[test]
+    fn bytes_occupied_counts_large_blobs() {
+        let mut blob_store = HashMapBlobStore::default();
+        let mut table = table(ProductType::from([AlgebraicType::String]));
+        assert_eq!(table.bytes_occupied(), 0);
+
+        // A short string is stored within the page.
+        table.insert(&mut blob_store, &product!["short"]).unwrap();
+        let page_bytes = mem::size_of::<Page>();
+        assert_eq!(table.bytes_occupied(), page_bytes);
+
+        // A long one goes to the blob store.
+        let long = "x".repeat(VarLenGranule::OBJECT_SIZE_BLOB_THRESHOLD + 1);
+        let row = product![long.clone()];
+        let ptr = table.insert(&mut blob_store, &row).unwrap().1.pointer();
+        assert_eq!(table.bytes_occupied(), page_bytes + long.len());
+
+        // Rolling back a duplicate insertion leaves it as it was.
+        assert!(matches!(
+            table.insert(&mut blob_store, &row),
+            Err(InsertError::Duplicate(_))
+        ));
+        assert_eq!(table.bytes_occupied(), page_bytes + long.len());
+
+        table.delete(&mut blob_store, ptr, |_| ()).unwrap();
+        assert_eq!(table.bytes_occupied(), page_bytes);
+    }
+
+    #[test]
+    fn num_large_blobs_only_for_long_var_len_members() {
+        let mut blob_store = HashMapBlobStore::default();
+
+        // Rows with a static BSATN layout never touch the blob store.
+        let mut fixed = table(ProductType::from([AlgebraicType::U64, AlgebraicType::U32]));
+        let row_ref = fixed.insert(&mut blob_store, &product![1u64, 2u32]).unwrap().1;
+        assert_eq!(row_ref.num_large_blobs(), 0);
+
+        let mut var_len = table(ProductType::from([AlgebraicType::String, AlgebraicType::String]));
+        let row_ref = var_len.insert(&mut blob_store, &product!["short", "short"]).unwrap().1;
+        assert_eq!(row_ref.num_large_blobs(), 0);
+
+        let long = "x".repeat(VarLenGranule::OBJECT_SIZE_BLOB_THRESHOLD + 1);
+        let row_ref = var_len.insert(&mut blob_store, &product![long, "short"]).unwrap().1;
+        assert_eq!(row_ref.num_large_blobs(), 1);
+    }
+
+    #[test]
+    fn index_seek_on_leading_columns() {
+        let mut table = table(ProductType::from([
+            AlgebraicType::U64,
+            AlgebraicType::U64,
+            AlgebraicType::U64,
+        ]));
+        let cols = spacetimedb_primitives::col_list![0, 1];
+        let index = BTreeIndex::new(
+            spacetimedb_primitives::IndexId(0),
+            &table.inner.row_layout,
+            &cols,
+            false,
+            "a_b",
+        )
+        .unwrap();
+        table.insert_index(&NullBlobStore, cols, index);
+        for (a, b) in [(1u64, 2u64), (0, 5), (2, 0), (1, 0), (2, 9), (1, 7)] {
+            table.insert(&mut NullBlobStore, &product![a, b, a + b]).unwrap();
+        }
+
+        let seek = |cols: ColList, range: (Bound<AlgebraicValue>, Bound<AlgebraicValue>)| {
+            let rows = table.index_seek(&NullBlobStore, &cols, &range)?;
+            Some(rows.map(|row| row.to_product_value()).collect::<Vec<_>>())
+        };
+        let point = |value: AlgebraicValue| (Bound::Included(value.clone()), Bound::Included(value));
+
+        // A seek on the leading column yields its matches in the order of the index.
+        assert_eq!(
+            seek(ColId(0).into(), point(1u64.into())).unwrap(),
+            [
+                product![1u64, 0u64, 1u64],
+                product![1u64, 2u64, 3u64],
+                product![1u64, 7u64, 8u64]
+            ],
+        );
+        // Excluded bounds are respected.
+        assert_eq!(
+            seek(
+                ColId(0).into(),
+                (Bound::Excluded(0u64.into()), Bound::Excluded(2u64.into()))
+            )
+            .unwrap(),
+            [
+                product![1u64, 0u64, 1u64],
+                product![1u64, 2u64, 3u64],
+                product![1u64, 7u64, 8u64]
+            ],
+        );
+        assert_eq!(
+            seek(ColId(0).into(), (Bound::Excluded(1u64.into()), Bound::Unbounded)).unwrap(),
+            [product![2u64, 0u64, 2u64], product![2u64, 9u64, 11u64]],
+        );
+        // The index can't serve a seek on a column other than its leading one.
+        assert!(seek(ColId(1).into(), point(0u64.into())).is_none());
+        assert!(seek(ColId(2).into(), point(3u64.into())).is_none());
+    }
+
+    #[test]
+    fn index_seek_keys_yields_a_key_per_row() {
+        let mut table = table(ProductType::from([AlgebraicType::U64, AlgebraicType::U64]));
+        for cols in [ColId(0).into(), spacetimedb_primitives::col_list![0, 1]] {
+            let index = BTreeIndex::new(
+                spacetimedb_primitives::IndexId(0),
+                &table.inner.row_layout,
+                &cols,
+                false,
+                "index",
+            )
+            .unwrap();
+            table.insert_index(&NullBlobStore, cols, index);
+        }
+        for (a, b) in [(1u64, 2u64), (0, 5), (2, 0), (1, 0)] {
+            table.insert(&mut NullBlobStore, &product![a, b]).unwrap();
+        }
+
+        let keys = |cols: ColList, range: (Bound<AlgebraicValue>, Bound<AlgebraicValue>)| {
+            Some(table.index_seek_keys(&cols, &range)?.collect::<Vec<_>>())
+        };
+
+        // A key is yielded once for each of the rows with it.
+        assert_eq!(
+            keys(ColId(0).into(), (Bound::Included(1u64.into()), Bound::Unbounded)).unwrap(),
+            [1u64, 1, 2].map(AlgebraicValue::U64),
+        );
+        // The keys of a multi-column index are products.
+        assert_eq!(
+            keys(
+                spacetimedb_primitives::col_list![0, 1],
+                (Bound::Unbounded, Bound::Excluded(product![1u64, 2u64].into()))
+            )
+            .unwrap(),
+            [product![0u64, 5u64], product![1u64, 0u64]].map(AlgebraicValue::from),
+        );
+        // Only an index on exactly the columns is used.
+        assert!(keys(ColId(1).into(), (Bound::Unbounded, Bound::Unbounded)).is_none());
+    }
 }
@@ -0,0 +1,65 @@
+use criterion::{black_box, criterion_group, criterion_main, Criterion};
+use spacetimedb_lib::operator::OpCmp;
+use spacetimedb_primitives::TableId;
+use spacetimedb_sats::db::def::{TableDef, TableSchema};
+use spacetimedb_sats::relation::{FieldName, Header};
+use spacetimedb_sats::{product, AlgebraicType, AlgebraicValue, ProductType};
+use spacetimedb_table::blob_store::HashMapBlobStore;
+use spacetimedb_table::indexes::SquashedOffset;
+use spacetimedb_table::table::Table;
+use spacetimedb_vm::expr::ColumnOp;
+use spacetimedb_vm::relation::RelValue;
+
+const ROWS: u64 = 10_000;
+
+fn filter(c: &mut Criterion) {
+    let ty = ProductType::from([
+        AlgebraicType::U64,
+        AlgebraicType::I32,
+        AlgebraicType::Bool,
+        AlgebraicType::String,
+    ]);
+    let schema = TableSchema::from_def(TableId(0), TableDef::from_product("t", ty));
+    let header = Header::from(&schema);
+    let mut table = Table::new(schema.into(), SquashedOffset::COMMITTED_STATE);
+    let mut blob_store = HashMapBlobStore::default();
+    for n in 0..ROWS {
+        let row = product![
+            n,
+            n as i32 - 5_000,
+            n % 2 == 0,
+            AlgebraicValue::String(n.to_string().into())
+        ];
+        table.insert(&mut blob_store, &row).unwrap();
+    }
+
+    let field = |col: u32| FieldName::new(TableId(0), col.into());
+    // Primitive columns are compared on the bytes of the row,
+    // while the string column must be read into an `AlgebraicValue`.
+    let filters: [(&str, ColumnOp); 4] = [
+        ("u64_eq", ColumnOp::cmp(field(0), OpCmp::Eq, 42u64)),
+        ("i32_lt", ColumnOp::cmp(field(1), OpCmp::Lt, 0i32)),
+        ("bool_eq", ColumnOp::cmp(field(2), OpCmp::Eq, true)),
+        (
+            "string_eq",
+            ColumnOp::cmp(field(3), OpCmp::Eq, AlgebraicValue::String("42".into())),
+        ),
+    ];
+
+    let mut group = c.benchmark_group("filter");
+    for (name, op) in &filters {
+        group.bench_function(*name, |b| {
+            b.iter(|| {
+                let rows = table.scan_rows(&blob_store);
+                black_box(
+                    rows.filter(|row| op.compare(&RelValue::Row(*row), &header).unwrap())
+                        .count(),
+                )
+            })
+        });
+    }
+    group.finish();
+}
+
+criterion_group!(benches, filter);
+criterion_main!(benches);
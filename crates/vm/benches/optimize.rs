@@ -0,0 +1,19 @@
+use criterion::{black_box, criterion_group, criterion_main, BatchSize, Criterion};
+use spacetimedb_vm::eval::test_helpers::optimizer_fixtures;
+
+fn optimize(c: &mut Criterion) {
+    let mut group = c.benchmark_group("optimize");
+    for (name, query) in optimizer_fixtures() {
+        group.bench_function(name, |b| {
+            b.iter_batched(
+                || query.clone(),
+                |query| black_box(query.optimize(&|_, _| 1_000)),
+                BatchSize::SmallInput,
+            )
+        });
+    }
+    group.finish();
+}
+
+criterion_group!(benches, optimize);
+criterion_main!(benches);
@@ -1,9 +1,12 @@
+use spacetimedb_primitives::ColList;
 use spacetimedb_sats::db::error::{AuthError, RelationError};
+use spacetimedb_sats::relation::FieldName;
 use spacetimedb_sats::{AlgebraicType, AlgebraicValue};
 use std::fmt;
 use thiserror::Error;
 
 use crate::expr::SourceId;
+use crate::operator::OpMath;
 
 #[derive(Error, Debug)]
 pub enum ConfigError {
@@ -18,6 +21,33 @@ pub enum ConfigError {
 pub enum ErrorType {
     #[error("Field should resolve to `bool`, but it got the value `{{0.to_satn()}}`")]
     FieldBool(AlgebraicValue),
+    #[error("Field `{field}` should be an array to be unnested, but it has the type `{ty:?}`")]
+    FieldArray { field: FieldName, ty: AlgebraicType },
+    #[error("Field `{0}` not found in the header")]
+    FieldNotFound(FieldName),
+    #[error("Operand should be of type `bool`, but it has the type `{0:?}`")]
+    NotBool(AlgebraicType),
+    #[error("Operand of `len` should be an array or map, but it has the type `{0:?}`")]
+    NotCollection(AlgebraicType),
+    #[error("Operand of `len` should resolve to an array or map, but it got the value `{0:?}`")]
+    NotCollectionValue(AlgebraicValue),
+    #[error("Operands of a case-insensitive comparison should be strings, but got `{lhs:?}` and `{rhs:?}`")]
+    NotStrings { lhs: AlgebraicValue, rhs: AlgebraicValue },
+    #[error("Can't compare a value of type `{lhs:?}` with one of type `{rhs:?}`")]
+    Mismatch { lhs: AlgebraicType, rhs: AlgebraicType },
+    #[error("Field `{field}` of type `{ty:?}` can't be compared with a value of type `{value_ty:?}`")]
+    FieldMismatch {
+        field: FieldName,
+        ty: AlgebraicType,
+        value_ty: AlgebraicType,
+    },
+    #[error("Can't join `{lhs}` of type `{lhs_ty:?}` on `{rhs}` of type `{rhs_ty:?}`")]
+    JoinKeyMismatch {
+        lhs: FieldName,
+        lhs_ty: AlgebraicType,
+        rhs: FieldName,
+        rhs_ty: AlgebraicType,
+    },
     #[error("Error Parsing `{value}` into type [{ty}]: {err}")]
     Parse { value: String, ty: String, err: String },
 }
@@ -37,6 +67,30 @@ pub enum ErrorVm {
     Unsupported(String),
     #[error("No source table with index {0:?}")]
     NoSuchSource(SourceId),
+    #[error("No index on columns {columns:?} of table `{table}`")]
+    NoSuchIndex { table: Box<str>, columns: ColList },
+    #[error("Index on columns {columns:?} of table `{table}` expects keys of {} values, but got `{key:?}`", .columns.len())]
+    IndexKeyArity {
+        table: Box<str>,
+        columns: ColList,
+        key: AlgebraicValue,
+    },
+    #[error("Range over columns {columns:?} of table `{table}` is unbounded on both ends")]
+    UnboundedRange { table: Box<str>, columns: ColList },
+    #[error("Expected to affect at most {expected_max_rows} rows of table `{table}`, but would affect {affected}")]
+    TooManyRows {
+        table: Box<str>,
+        expected_max_rows: u64,
+        affected: u64,
+    },
+    #[error("Query is nested {depth} levels deep, exceeding the maximum of {max_depth}")]
+    TooDeep { depth: usize, max_depth: usize },
+    #[error("Can't evaluate `{lhs:?} {op} {rhs:?}`")]
+    Math {
+        op: OpMath,
+        lhs: AlgebraicValue,
+        rhs: AlgebraicValue,
+    },
     #[error("ConfigError: {0}")]
     Config(#[from] ConfigError),
     #[error("{0}")]
@@ -135,6 +189,12 @@ impl From<ErrorVm> for ErrorLang {
             ErrorVm::Lang(err) => err,
             ErrorVm::Auth(err) => ErrorLang::new(ErrorKind::Unauthorized, Some(&err.to_string())),
             ErrorVm::Config(err) => ErrorLang::new(ErrorKind::Db, Some(&err.to_string())),
+            err @ ErrorVm::NoSuchIndex { .. } => ErrorLang::new(ErrorKind::NotFound, Some(&err.to_string())),
+            err @ ErrorVm::IndexKeyArity { .. } => ErrorLang::new(ErrorKind::Params, Some(&err.to_string())),
+            err @ ErrorVm::UnboundedRange { .. } => ErrorLang::new(ErrorKind::Params, Some(&err.to_string())),
+            err @ ErrorVm::TooManyRows { .. } => ErrorLang::new(ErrorKind::OutOfBounds, Some(&err.to_string())),
+            err @ ErrorVm::TooDeep { .. } => ErrorLang::new(ErrorKind::OutOfBounds, Some(&err.to_string())),
+            err @ ErrorVm::Math { .. } => ErrorLang::new(ErrorKind::Invalid, Some(&err.to_string())),
             err @ ErrorVm::NoSuchSource(_) => ErrorLang {
                 kind: ErrorKind::Invalid,
                 msg: Some(format!("{err:?}")),
@@ -1,12 +1,13 @@
 use crate::errors::ErrorVm;
-use crate::expr::{Code, JoinExpr, SourceExpr, SourceSet};
+use crate::expr::{Code, JoinExpr, JoinKind, SourceExpr, SourceSet};
 use crate::expr::{Expr, Query};
 use crate::iterators::RelIter;
 use crate::program::{ProgramVm, Sources};
-use crate::rel_ops::RelOps;
+use crate::rel_ops::{EmptyRelOps, RelOps};
 use crate::relation::RelValue;
-use spacetimedb_sats::relation::Relation;
-use spacetimedb_sats::ProductValue;
+use spacetimedb_data_structures::map::HashSet;
+use spacetimedb_sats::relation::{Column, FieldName, Header, Relation};
+use spacetimedb_sats::{AlgebraicType, AlgebraicValue, ProductValue};
 use std::sync::Arc;
 
 pub type IterRows<'a> = dyn RelOps<'a> + 'a;
@@ -23,26 +24,36 @@ pub fn build_query<'a, const N: usize>(
 ) -> Result<Box<IterRows<'a>>, ErrorVm> {
     for q in query {
         result = match q {
-            Query::IndexScan(_) => {
+            Query::IndexScan(_) | Query::IndexMultiScan(_) => {
                 panic!("index scans unsupported on memory tables")
             }
             Query::IndexJoin(_) => {
                 panic!("index joins unsupported on memory tables")
             }
+            Query::MergeJoin(_) => {
+                panic!("merge joins unsupported on memory tables")
+            }
             Query::Select(cmp) => {
                 let header = result.head().clone();
                 let iter = result.select(move |row| cmp.compare(row, &header));
                 Box::new(iter)
             }
-            Query::Project(cols, _) => {
-                if cols.is_empty() {
+            Query::Project(cols, _, distinct) => {
+                let result: Box<IterRows<'_>> = if cols.is_empty() {
                     result
                 } else {
                     let header = result.head().clone();
                     let iter = result.project(cols, move |cols, row| {
-                        Ok(RelValue::Projection(row.project_owned(cols, &header)?))
+                        Ok(RelValue::Projection(
+                            row.project_owned(cols.iter().map(|(col, _)| col), &header)?,
+                        ))
                     })?;
                     Box::new(iter)
+                };
+                if *distinct {
+                    Box::new(result.distinct())
+                } else {
+                    result
                 }
             }
             Query::JoinInner(q) => {
@@ -50,6 +61,18 @@ pub fn build_query<'a, const N: usize>(
                 let rhs = build_query(rhs, &q.rhs.query, sources)?;
                 join_inner(result, rhs, q)?
             }
+            Query::JoinLeft(q) => {
+                let rhs = build_source_expr_query(sources, &q.rhs.source);
+                let rhs = build_query(rhs, &q.rhs.query, sources)?;
+                join_left(result, rhs, q)?
+            }
+            Query::SemiJoinInMemory { source, probe_field } => {
+                let rhs = build_source_expr_query(sources, source);
+                semi_join_in_memory(result, rhs, *probe_field)?
+            }
+            Query::Unnest(q) => Box::new(result.unnest(q.field, q.outer)?),
+            Query::Limit { limit, offset } => Box::new(result.limit(*limit, *offset)),
+            Query::NeverReturns => Box::new(EmptyRelOps::new(result.head().clone())),
         };
     }
     Ok(result)
@@ -69,15 +92,84 @@ pub fn join_inner<'a>(
     let key_rhs = move |row: &RelValue<'_>| row.read_column(col_rhs.idx()).unwrap().into_owned();
     let pred = move |l: &RelValue<'_>, r: &RelValue<'_>| l.read_column(col_lhs.idx()) == r.read_column(col_rhs.idx());
 
-    Ok(if q.semi {
-        let head = lhs_head.clone();
-        Box::new(lhs.join_inner(rhs, head, key_lhs, key_rhs, pred, move |l, _| l)?)
-    } else {
-        let head = Arc::new(lhs_head.extend(rhs_head));
-        Box::new(lhs.join_inner(rhs, head, key_lhs, key_rhs, pred, move |l, r| l.extend(r))?)
+    Ok(match q.kind {
+        JoinKind::Semi => Box::new(lhs.semi_join(rhs, key_lhs, key_rhs, pred)?),
+        JoinKind::Anti => Box::new(lhs.anti_join(rhs, key_lhs, key_rhs, pred)?),
+        JoinKind::Inner => {
+            let head = Arc::new(lhs_head.extend(rhs_head));
+            Box::new(lhs.join_inner(rhs, head, key_lhs, key_rhs, pred, move |l, r| l.extend(r))?)
+        }
+        JoinKind::Left => join_left(lhs, rhs, q)?,
     })
 }
 
+/// Left outer joins `lhs` with `rhs` on `q.col_lhs = q.col_rhs`.
+///
+/// The `rhs` columns of the result are optional:
+/// `Some` of the values of the matching row,
+/// or `None` for the rows of `lhs` without a match.
+pub fn join_left<'a>(
+    lhs: impl RelOps<'a> + 'a,
+    rhs: impl RelOps<'a> + 'a,
+    q: &'a JoinExpr,
+) -> Result<Box<IterRows<'a>>, ErrorVm> {
+    let lhs_head = lhs.head();
+    let rhs_head = rhs.head();
+    let col_lhs = lhs_head.column_pos_or_err(q.col_lhs)?;
+    let col_rhs = rhs_head.column_pos_or_err(q.col_rhs)?;
+
+    // The `rhs` columns may be `None`, so none of their constraints hold in the result.
+    let optional_fields = rhs_head
+        .fields
+        .iter()
+        .map(|col| Column {
+            algebraic_type: AlgebraicType::option(col.algebraic_type.clone()),
+            ..col.clone()
+        })
+        .collect();
+    let optional_head = Header {
+        private_columns: rhs_head.private_columns.clone(),
+        ..Header::new(rhs_head.table_id, rhs_head.table_name.clone(), optional_fields, vec![])
+    };
+    let head = Arc::new(lhs_head.extend(&optional_head));
+
+    let key_lhs = move |row: &RelValue<'_>| row.read_column(col_lhs.idx()).unwrap().into_owned();
+    let key_rhs = move |row: &RelValue<'_>| row.read_column(col_rhs.idx()).unwrap().into_owned();
+    let rhs_len = optional_head.fields.len();
+    let project = move |l: RelValue<'a>, r: Option<RelValue<'a>>| {
+        let r: ProductValue = match r {
+            Some(r) => r
+                .into_product_value()
+                .into_iter()
+                .map(AlgebraicValue::OptionSome)
+                .collect(),
+            None => (0..rhs_len).map(|_| AlgebraicValue::OptionNone()).collect(),
+        };
+        l.extend(RelValue::Projection(r))
+    };
+
+    Ok(Box::new(lhs.join_left(rhs, head, key_lhs, key_rhs, project)))
+}
+
+/// Returns the rows of `lhs` whose `probe_field` equals the single column of a row of `rhs`.
+///
+/// The values of `rhs` are collected to a `HashSet`, which every row of `lhs` then probes.
+pub fn semi_join_in_memory<'a>(
+    lhs: impl RelOps<'a> + 'a,
+    rhs: impl RelOps<'a>,
+    probe_field: FieldName,
+) -> Result<Box<IterRows<'a>>, ErrorVm> {
+    let probe_col = lhs.head().column_pos_or_err(probe_field)?;
+    let keys = rhs
+        .collect_vec(|row| row.read_column(0).map(|key| key.into_owned()))?
+        .into_iter()
+        .flatten()
+        .collect::<HashSet<_>>();
+    Ok(Box::new(lhs.select(move |row| {
+        Ok(row.read_column(probe_col.idx()).is_some_and(|key| keys.contains(&*key)))
+    })))
+}
+
 pub(crate) fn build_source_expr_query<'a, const N: usize>(
     sources: Sources<'_, N>,
     source: &SourceExpr,
@@ -147,11 +239,14 @@ pub fn run_ast<const N: usize, P: ProgramVm>(
 /// Used internally for testing SQL JOINS.
 #[doc(hidden)]
 pub mod test_helpers {
+    use crate::expr::{ColumnOp, QueryExpr, SourceExpr, SourceSet};
     use crate::relation::MemTable;
     use core::hash::BuildHasher as _;
     use spacetimedb_data_structures::map::DefaultHashBuilder;
-    use spacetimedb_primitives::TableId;
-    use spacetimedb_sats::relation::{Column, FieldName, Header};
+    use spacetimedb_lib::operator::{OpCmp, OpLogic, OpQuery};
+    use spacetimedb_primitives::{col_list, ColId, Constraints, TableId};
+    use spacetimedb_sats::db::auth::{StAccess, StTableType};
+    use spacetimedb_sats::relation::{Column, DbTable, FieldExpr, FieldName, Header};
     use spacetimedb_sats::{product, AlgebraicType, AlgebraicValue, ProductType, ProductValue};
     use std::sync::Arc;
 
@@ -228,6 +323,100 @@ pub mod test_helpers {
             location_ty,
         }
     }
+
+    /// Returns a header `t1` of 5 columns `a` to `e`,
+    /// with indexes on `a`, `b`, `(b, c)` and `(a, b, c, d)`,
+    /// along with the names of the columns and a value for each.
+    pub fn setup_best_index() -> (Header, [FieldName; 5], [AlgebraicValue; 5]) {
+        let table_id = 0.into();
+
+        let vals = [1, 2, 3, 4, 5].map(AlgebraicValue::U64);
+        let col_ids = [0, 1, 2, 3, 4].map(ColId);
+        let [a, b, c, d, _] = col_ids;
+        let fields = col_ids.map(|c| FieldName::new(table_id, c));
+        let cols = fields.map(|f| Column::new(f, AlgebraicType::I8));
+
+        let head1 = Header::new(
+            table_id,
+            "t1".into(),
+            cols.to_vec(),
+            vec![
+                //Index a
+                (a.into(), Constraints::primary_key()),
+                //Index b
+                (b.into(), Constraints::indexed()),
+                //Index b + c
+                (col_list![b, c], Constraints::unique()),
+                //Index a + b + c + d
+                (col_list![a, b, c, d], Constraints::indexed()),
+            ],
+        );
+
+        (head1, fields, vals)
+    }
+
+    /// Returns representative queries for benchmarking and testing the optimizer, by name:
+    ///
+    /// - `single_table_filter`: `t1` filtered by `a = 1 AND d > 4`.
+    /// - `index_join`: the rows of `t1` joined with a filtered in-memory table on `t1.a`.
+    /// - `three_table_chain`: `t1` joined with two in-memory tables, one after the other.
+    /// - `dnf_predicate`: `t1` filtered by `(a = 1 AND b = 2) OR (c = 3 AND d = 4)`.
+    ///
+    /// `t1` is the table of [`setup_best_index`].
+    pub fn optimizer_fixtures() -> Vec<(&'static str, QueryExpr)> {
+        let (head, [a, b, c, d, _], [v1, v2, v3, v4, _]) = setup_best_index();
+        let t1 = SourceExpr::DbTable(DbTable::new(
+            Arc::new(head),
+            a.table,
+            StTableType::User,
+            StAccess::Public,
+        ));
+        let cmp = |field: FieldName, op: OpCmp, value: &AlgebraicValue| {
+            ColumnOp::new(
+                OpQuery::Cmp(op),
+                ColumnOp::Field(FieldExpr::Name(field)),
+                ColumnOp::Field(FieldExpr::Value(value.clone())),
+            )
+        };
+        let and = |lhs, rhs| ColumnOp::new(OpQuery::Logic(OpLogic::And), lhs, rhs);
+        let or = |lhs, rhs| ColumnOp::new(OpQuery::Logic(OpLogic::Or), lhs, rhs);
+
+        let mut sources = SourceSet::<_, 3>::empty();
+        let ty = ProductType::from([AlgebraicType::U64, AlgebraicType::U64]);
+        let mut mem = |table_id: TableId| {
+            let table = mem_table(table_id, ty.clone(), [product!(1u64, 2u64), product!(3u64, 4u64)]);
+            let fields = [0, 1].map(|col| FieldName::new(table_id, col.into()));
+            (sources.add_mem_table(table), fields)
+        };
+
+        let single_table_filter =
+            QueryExpr::new(t1.clone()).with_select(and(cmp(a, OpCmp::Eq, &v1), cmp(d, OpCmp::Gt, &v4)));
+
+        let (probe, [probe_a, probe_b]) = mem(1.into());
+        let probe = QueryExpr::new(probe).with_select(cmp(probe_b, OpCmp::Eq, &v2));
+        let wildcard = t1.head().fields.iter().map(|col| col.field.into()).collect::<Vec<_>>();
+        let index_join = QueryExpr::new(t1.clone())
+            .with_join_inner(probe, a, probe_a, false)
+            .with_project(&wildcard, Some(a.table));
+
+        let (t2, [t2_a, t2_b]) = mem(2.into());
+        let (t3, [t3_a, _]) = mem(3.into());
+        let three_table_chain = QueryExpr::new(t1.clone())
+            .with_join_inner(t2, a, t2_a, false)
+            .with_join_inner(t3, t2_b, t3_a, false);
+
+        let dnf_predicate = QueryExpr::new(t1).with_select(or(
+            and(cmp(a, OpCmp::Eq, &v1), cmp(b, OpCmp::Eq, &v2)),
+            and(cmp(c, OpCmp::Eq, &v3), cmp(d, OpCmp::Eq, &v4)),
+        ));
+
+        vec![
+            ("single_table_filter", single_table_filter),
+            ("index_join", index_join),
+            ("three_table_chain", three_table_chain),
+            ("dnf_predicate", dnf_predicate),
+        ]
+    }
 }
 
 #[cfg(test)]
@@ -236,13 +425,14 @@ pub mod tests {
 
     use super::test_helpers::*;
     use super::*;
-    use crate::expr::{QueryExpr, SourceSet};
+    use crate::errors::{ErrorKind, ErrorLang};
+    use crate::expr::{CodeResult, ColumnOp, CrudExpr, QueryExpr, SourceSet};
     use crate::program::Program;
-    use crate::relation::MemTable;
-    use spacetimedb_lib::operator::{OpCmp, OpLogic};
+    use crate::relation::{MemTable, RowStream};
+    use spacetimedb_lib::operator::{OpCmp, OpLogic, OpQuery};
     use spacetimedb_sats::db::error::RelationError;
-    use spacetimedb_sats::relation::{FieldName, Header};
-    use spacetimedb_sats::{product, AlgebraicType, ProductType};
+    use spacetimedb_sats::relation::{FieldExpr, FieldName, Header};
+    use spacetimedb_sats::{product, AlgebraicType, AlgebraicValue, ArrayValue, ProductType};
 
     fn run_query<const N: usize>(p: &mut Program, ast: Expr, sources: SourceSet<Vec<ProductValue>, N>) -> MemTable {
         match run_ast(p, ast, sources) {
@@ -300,6 +490,62 @@ pub mod tests {
         );
     }
 
+    #[test]
+    fn test_project_aliased() {
+        let p = &mut Program;
+        let table = mem_table_one_u64(0.into());
+
+        let mut sources = SourceSet::<_, 1>::empty();
+        let source_expr = sources.add_mem_table(table.clone());
+
+        let field = *table.get_field_pos(0).unwrap();
+        let q = QueryExpr::new(source_expr).with_project_aliased(vec![(field.into(), Some("renamed".into()))], None);
+
+        let result = run_query(p, q.into(), sources);
+        assert_eq!(result.head.fields.len(), 1);
+        assert_eq!(result.head.fields[0].field, field);
+        assert_eq!(result.head.fields[0].name.as_deref(), Some("renamed"));
+        assert_eq!(result.data, [product![1u64]]);
+    }
+
+    #[test]
+    fn test_project_distinct() {
+        let p = &mut Program;
+        let ty = ProductType::from([AlgebraicType::U64, AlgebraicType::String]);
+        let rows = [(1u64, "a"), (2, "b"), (3, "a"), (3, "a"), (4, "b")];
+        let input = mem_table(0.into(), ty, rows.map(|(id, name)| product![id, name]));
+        let [id, name] = [0, 1].map(|pos| *input.get_field_pos(pos).unwrap());
+
+        let mut project = |cols: &[FieldName], distinct| {
+            let mut sources = SourceSet::<_, 1>::empty();
+            let source = QueryExpr::new(sources.add_mem_table(input.clone()));
+            let cols = cols.iter().copied().map(Into::into).collect::<Vec<FieldExpr>>();
+            let q = if distinct {
+                source.with_project_distinct(&cols, None)
+            } else {
+                source.with_project(&cols, None)
+            };
+            run_query(p, q.into(), sources).data
+        };
+
+        // Duplicate strings are collapsed, in the order they are first seen.
+        assert_eq!(project(&[name], true), [product!["a"], product!["b"]]);
+        assert_eq!(project(&[name], false).len(), 5);
+        // Rows are compared across all of the projected columns.
+        assert_eq!(
+            project(&[id, name], true),
+            [
+                product![1u64, "a"],
+                product![2u64, "b"],
+                product![3u64, "a"],
+                product![4u64, "b"]
+            ]
+        );
+        assert_eq!(project(&[id, name], false).len(), 5);
+        // Without any columns, whole rows are deduplicated.
+        assert_eq!(project(&[], true).len(), 4);
+    }
+
     #[test]
     fn test_join_inner() {
         let p = &mut Program;
@@ -363,6 +609,78 @@ pub mod tests {
         );
     }
 
+    #[test]
+    fn test_semijoin_many_matches() {
+        let p = &mut Program;
+        let lhs = mem_table(
+            0.into(),
+            ProductType::from([(None, AlgebraicType::U64)]),
+            [product![1u64]],
+        );
+        let rhs = mem_table(
+            1.into(),
+            ProductType::from([(None, AlgebraicType::U64)]),
+            [product![1u64], product![1u64], product![1u64]],
+        );
+        let col_lhs = *lhs.get_field_pos(0).unwrap();
+        let col_rhs = *rhs.get_field_pos(0).unwrap();
+
+        let mut sources = SourceSet::<_, 2>::empty();
+        let lhs_source = sources.add_mem_table(lhs);
+        let rhs_source = sources.add_mem_table(rhs);
+
+        // The lhs row matches all three rhs rows, but is returned only once.
+        let q = QueryExpr::new(lhs_source).with_join_inner(rhs_source, col_lhs, col_rhs, true);
+        let result = run_query(p, q.into(), sources);
+        assert_eq!(result.data, [product![1u64]]);
+    }
+
+    #[test]
+    fn test_antijoin() {
+        let p = &mut Program;
+        let lhs = mem_table(
+            0.into(),
+            ProductType::from([(None, AlgebraicType::U64)]),
+            [product![1u64], product![2u64], product![3u64]],
+        );
+        let rhs = mem_table(
+            1.into(),
+            ProductType::from([(None, AlgebraicType::U64)]),
+            [product![2u64], product![2u64], product![4u64]],
+        );
+        let col_lhs = *lhs.get_field_pos(0).unwrap();
+        let col_rhs = *rhs.get_field_pos(0).unwrap();
+
+        let mut sources = SourceSet::<_, 2>::empty();
+        let lhs_source = sources.add_mem_table(lhs);
+        let rhs_source = sources.add_mem_table(rhs);
+
+        // Only the lhs rows without any match are returned, each once, with the lhs header.
+        let q = QueryExpr::new(lhs_source).with_join(rhs_source, col_lhs, col_rhs, JoinKind::Anti);
+        let result = run_query(p, q.into(), sources);
+        assert_eq!(result.head.fields.len(), 1);
+        assert_eq!(result.data, [product![1u64], product![3u64]]);
+    }
+
+    #[test]
+    fn test_select_column_to_column() {
+        let p = &mut Program;
+        let input = mem_table(
+            0.into(),
+            ProductType::from([("start", AlgebraicType::U64), ("end", AlgebraicType::U64)]),
+            [product![1u64, 2u64], product![3u64, 3u64], product![5u64, 4u64]],
+        );
+        let start = *input.get_field_pos(0).unwrap();
+        let end = *input.get_field_pos(1).unwrap();
+
+        let mut sources = SourceSet::<_, 1>::empty();
+        let source_expr = sources.add_mem_table(input);
+
+        let q = QueryExpr::new(source_expr).with_select_cmp(OpCmp::Lt, start, end);
+        let result = run_query(p, q.into(), sources);
+        assert_eq!(result.data, [product![1u64, 2u64]]);
+    }
+
     #[test]
     fn test_query_logic() {
         let p = &mut Program;
@@ -547,4 +865,307 @@ pub mod tests {
             "Inventory"
         );
     }
+
+    #[test]
+    fn test_stream_result() {
+        let p = &mut Program;
+        let input = mem_table(
+            0.into(),
+            ProductType::from([AlgebraicType::U64]),
+            (0..10u64).map(|x| product![x]),
+        );
+        let field = *input.get_field_pos(0).unwrap();
+        let mut sources = SourceSet::<_, 1>::empty();
+        let source_expr = sources.add_mem_table(input);
+
+        let q = QueryExpr::new(source_expr).with_select_cmp(OpCmp::Lt, field, scalar(5u64));
+        let table = run_query(p, q.into(), sources);
+
+        // Consuming the stream row by row yields the same rows as the materialized result.
+        let stream = RowStream::from(table.clone());
+        assert_eq!(stream.head, table.head);
+        let rows = stream.collect::<Result<Vec<_>, _>>().unwrap();
+        assert_eq!(rows, table.data);
+
+        match CodeResult::Stream(table.clone().into()) {
+            CodeResult::Stream(stream) => assert_eq!(stream.materialize().unwrap(), table),
+            x => panic!("Unexpected result: {x:?}"),
+        }
+
+        // Errors in the stream are surfaced when materializing.
+        let rows = [Ok(product![0u64]), Err(ErrorVm::Unsupported("oops".into()))];
+        let stream = RowStream::new(table.head.clone(), table.table_access, rows.into_iter());
+        assert!(matches!(stream.materialize(), Err(ErrorVm::Unsupported(_))));
+    }
+
+    #[test]
+    fn test_stream_rows() {
+        let p = &mut Program;
+        let input = mem_table(
+            0.into(),
+            ProductType::from([AlgebraicType::U64]),
+            (0..100_000u64).map(|x| product![x]),
+        );
+        let head = input.head.clone();
+        let field = *input.get_field_pos(0).unwrap();
+        let mut sources = SourceSet::<_, 1>::empty();
+        let source_expr = sources.add_mem_table(input);
+
+        // Count the rows as they are produced, without collecting them.
+        let q = QueryExpr::new(source_expr).with_select_cmp(OpCmp::Lt, field, scalar(60_000u64));
+        let CodeResult::Stream(stream) = p.eval_query_stream(&q, &mut sources).unwrap() else {
+            panic!("Expected a stream");
+        };
+        assert_eq!(stream.head, head);
+        let mut count = 0u64;
+        for row in stream {
+            assert_eq!(row.unwrap(), product![count]);
+            count += 1;
+        }
+        assert_eq!(count, 60_000);
+    }
+
+    #[test]
+    fn test_unnest() {
+        let p = &mut Program;
+        let array = |elems: &[u64]| AlgebraicValue::Array(ArrayValue::from(Box::<[u64]>::from(elems)));
+        let ty = ProductType::from([AlgebraicType::U64, AlgebraicType::array(AlgebraicType::U64)]);
+        let input = mem_table(
+            0.into(),
+            ty,
+            [
+                product![1u64, array(&[10, 11])],
+                product![2u64, array(&[])],
+                product![3u64, array(&[30])],
+            ],
+        );
+        let field = *input.get_field_pos(1).unwrap();
+
+        let mut unnest = |outer| {
+            let mut sources = SourceSet::<_, 1>::empty();
+            let source_expr = sources.add_mem_table(input.clone());
+            let q = QueryExpr::new(source_expr).with_unnest(field, outer);
+            run_query(p, q.into(), sources)
+        };
+
+        // Inner unnest; the empty array yields no rows.
+        let result = unnest(false);
+        assert_eq!(result.head.fields[1].algebraic_type, AlgebraicType::U64);
+        assert_eq!(
+            result.data,
+            [product![1u64, 10u64], product![1u64, 11u64], product![3u64, 30u64]]
+        );
+
+        // Outer unnest; the empty array yields a row with `None`.
+        let result = unnest(true);
+        assert_eq!(
+            result.head.fields[1].algebraic_type,
+            AlgebraicType::option(AlgebraicType::U64)
+        );
+        let some = |x: u64| AlgebraicValue::OptionSome(x.into());
+        assert_eq!(
+            result.data,
+            [
+                product![1u64, some(10)],
+                product![1u64, some(11)],
+                product![2u64, AlgebraicValue::OptionNone()],
+                product![3u64, some(30)],
+            ]
+        );
+
+        // Only arrays can be unnested.
+        let mut sources = SourceSet::<_, 1>::empty();
+        let source_expr = sources.add_mem_table(input.clone());
+        let field = *input.get_field_pos(0).unwrap();
+        let q = QueryExpr::new(source_expr).with_unnest(field, false);
+        assert!(matches!(
+            run_ast(p, q.into(), sources),
+            Code::Halt(ErrorLang {
+                kind: ErrorKind::TypeMismatch,
+                ..
+            })
+        ));
+    }
+
+    #[test]
+    fn test_select_len() {
+        let p = &mut Program;
+        let array = |elems: &[u64]| AlgebraicValue::Array(ArrayValue::from(Box::<[u64]>::from(elems)));
+        let some = |elems: &[u64]| AlgebraicValue::OptionSome(array(elems));
+        let array_ty = AlgebraicType::array(AlgebraicType::U64);
+        let ty = ProductType::from([AlgebraicType::U64, array_ty.clone(), AlgebraicType::option(array_ty)]);
+        let input = mem_table(
+            0.into(),
+            ty,
+            [
+                product![1u64, array(&[10, 11, 12, 13]), some(&[10])],
+                product![2u64, array(&[]), some(&[])],
+                product![3u64, array(&[30]), AlgebraicValue::OptionNone()],
+            ],
+        );
+        let id = *input.get_field_pos(0).unwrap();
+
+        let mut select_ids = |col: usize, cmp: OpCmp, len: u64| {
+            let mut sources = SourceSet::<_, 1>::empty();
+            let source_expr = sources.add_mem_table(input.clone());
+            let field = *input.get_field_pos(col).unwrap();
+            let q = QueryExpr::new(source_expr)
+                .with_select(ColumnOp::new(
+                    OpQuery::Cmp(cmp),
+                    ColumnOp::len_of(field),
+                    ColumnOp::from(scalar(len)),
+                ))
+                .with_project(&[id.into()], None);
+            run_query(p, q.into(), sources).data
+        };
+
+        assert_eq!(select_ids(1, OpCmp::Gt, 3), [product![1u64]]);
+        // The empty array has length zero.
+        assert_eq!(select_ids(1, OpCmp::Eq, 0), [product![2u64]]);
+        assert_eq!(select_ids(1, OpCmp::LtEq, 1), [product![2u64], product![3u64]]);
+
+        // The length of an absent collection compares to nothing, not even with `!=`.
+        assert_eq!(select_ids(2, OpCmp::Lt, 5), [product![1u64], product![2u64]]);
+        assert_eq!(select_ids(2, OpCmp::NotEq, 1), [product![2u64]]);
+    }
+
+    #[test]
+    fn test_select_eq_ignore_case() {
+        let p = &mut Program;
+        let ty = ProductType::from([AlgebraicType::U64, AlgebraicType::String]);
+        let input = mem_table(
+            0.into(),
+            ty,
+            [product![1u64, "Alice"], product![2u64, "Bob"], product![3u64, "ALICE"]],
+        );
+        let [id, name] = [0, 1].map(|col| *input.get_field_pos(col).unwrap());
+
+        let mut select_ids = |field, value: AlgebraicValue| {
+            let mut sources = SourceSet::<_, 1>::empty();
+            let source_expr = sources.add_mem_table(input.clone());
+            let q = QueryExpr::new(source_expr)
+                .with_select(ColumnOp::new(
+                    OpQuery::Cmp(OpCmp::EqIgnoreCase),
+                    ColumnOp::from(field),
+                    ColumnOp::from(value),
+                ))
+                .with_project(&[id.into()], None);
+            run_ast(p, q.into(), sources)
+        };
+
+        let result = select_ids(name, scalar("alice"));
+        let Code::Table(result) = result else {
+            panic!("unexpected result {result}");
+        };
+        assert_eq!(result.data, [product![1u64], product![3u64]]);
+
+        // A case-insensitive comparison of a string with a number is an error.
+        let result = select_ids(name, scalar(1u64));
+        assert!(
+            matches!(
+                result,
+                Code::Halt(ErrorLang {
+                    kind: ErrorKind::TypeMismatch,
+                    ..
+                })
+            ),
+            "unexpected result {result}"
+        );
+    }
+
+    #[test]
+    fn test_reset_sources() {
+        let p = &mut Program;
+        let ty = ProductType::from([AlgebraicType::U64]);
+        let input = mem_table(0.into(), ty.clone(), [product![1u64], product![2u64]]);
+        let field = *input.get_field_pos(0).unwrap();
+
+        // Compile the plan once...
+        let mut sources = SourceSet::<_, 1>::empty();
+        let source_expr = sources.add_mem_table(input);
+        let q = QueryExpr::new(source_expr).with_select_cmp(OpCmp::GtEq, field, scalar(2u64));
+        let head = q.source.head().clone();
+        let mut run = |sources: &mut SourceSet<_, 1>| match eval(p, Code::Crud(CrudExpr::Query(q.clone())), sources) {
+            Code::Table(x) => x,
+            x => panic!("Unexpected result on query: {x}"),
+        };
+
+        // ...and run it over two batches of rows.
+        let result = run(&mut sources);
+        assert_eq!(result, MemTable::from_iter(head.clone(), [product![2u64]]));
+
+        sources.reset([vec![product![3u64], product![1u64], product![5u64]]]);
+        let result = run(&mut sources);
+        assert_eq!(result, MemTable::from_iter(head, [product![3u64], product![5u64]]));
+    }
+
+    #[test]
+    fn test_limit() {
+        let p = &mut Program;
+        let ty = ProductType::from([AlgebraicType::U64]);
+        let input = mem_table(0.into(), ty, (1..=5u64).map(|x| product![x]));
+
+        let mut limit = |limit, offset| {
+            let mut sources = SourceSet::<_, 1>::empty();
+            let source_expr = sources.add_mem_table(input.clone());
+            let q = QueryExpr::new(source_expr).with_limit(limit, offset);
+            run_query(p, q.into(), sources).data
+        };
+
+        assert_eq!(limit(2, 0), [product![1u64], product![2u64]]);
+        assert_eq!(limit(2, 2), [product![3u64], product![4u64]]);
+        // Running out of rows ends the result early.
+        assert_eq!(limit(10, 3), [product![4u64], product![5u64]]);
+        assert!(limit(10, 5).is_empty());
+        assert!(limit(0, 0).is_empty());
+    }
+
+    #[test]
+    fn test_join_left() {
+        let p = &mut Program;
+        let lhs = mem_table(0.into(), [AlgebraicType::U64], (1..=3u64).map(|x| product![x]));
+        let rhs_ty = [
+            AlgebraicType::U64,
+            AlgebraicType::String,
+            AlgebraicType::option(AlgebraicType::U32),
+        ];
+        let rhs = mem_table(
+            1.into(),
+            rhs_ty,
+            [
+                product![1u64, "a", Some(5u32)],
+                product![1u64, "b", None::<u32>],
+                product![3u64, "c", Some(7u32)],
+            ],
+        );
+        let col_lhs = *lhs.get_field_pos(0).unwrap();
+        let col_rhs = *rhs.get_field_pos(0).unwrap();
+
+        let mut sources = SourceSet::<_, 2>::empty();
+        let lhs = sources.add_mem_table(lhs);
+        let rhs = sources.add_mem_table(rhs);
+        let q = QueryExpr::new(lhs).with_join_left(rhs, col_lhs, col_rhs);
+        let result = run_query(p, q.into(), sources);
+
+        let types = result.head.fields.iter().map(|col| col.algebraic_type.clone());
+        assert_eq!(
+            types.collect::<Vec<_>>(),
+            [
+                AlgebraicType::U64,
+                AlgebraicType::option(AlgebraicType::U64),
+                AlgebraicType::option(AlgebraicType::String),
+                AlgebraicType::option(AlgebraicType::option(AlgebraicType::U32)),
+            ]
+        );
+        assert_eq!(
+            result.data,
+            [
+                product![1u64, Some(1u64), Some("a"), Some(Some(5u32))],
+                product![1u64, Some(1u64), Some("b"), Some(None::<u32>)],
+                // There's no match for `2`, so the rhs columns are `None`.
+                product![2u64, None::<u64>, None::<&str>, None::<Option<u32>>],
+                product![3u64, Some(3u64), Some("c"), Some(Some(7u32))],
+            ]
+        );
+    }
 }
@@ -1,6 +1,6 @@
-use crate::errors::{ErrorKind, ErrorLang, ErrorType, ErrorVm};
-use crate::operator::{OpCmp, OpLogic, OpQuery};
-use crate::relation::{MemTable, RelValue};
+use crate::errors::{ConfigError, ErrorKind, ErrorLang, ErrorType, ErrorVm};
+use crate::operator::{OpCmp, OpLogic, OpMath, OpQuery};
+use crate::relation::{MemTable, RelValue, RowStream};
 use arrayvec::ArrayVec;
 use derive_more::From;
 use smallvec::{smallvec, SmallVec};
@@ -10,19 +10,50 @@ use spacetimedb_primitives::*;
 use spacetimedb_sats::algebraic_value::AlgebraicValue;
 use spacetimedb_sats::db::auth::{StAccess, StTableType};
 use spacetimedb_sats::db::def::{TableDef, TableSchema};
-use spacetimedb_sats::db::error::AuthError;
-use spacetimedb_sats::relation::{DbTable, FieldExpr, FieldName, Header, Relation, RowCount};
-use spacetimedb_sats::ProductValue;
-use std::cmp::Reverse;
+use spacetimedb_sats::db::error::{AuthError, RelationError};
+pub use spacetimedb_sats::relation::{ColumnStats, ColumnStatsMap};
+use spacetimedb_sats::relation::{
+    DbTable, FieldExpr, FieldExprRef, FieldName, Header, PartialIndexPredicate, Relation, RowCount,
+};
+use spacetimedb_sats::satn::Satn;
+use spacetimedb_sats::{AlgebraicType, BuiltinType, ProductType, ProductValue};
+use std::cmp::{Ordering, Reverse};
 use std::collections::btree_map::Entry;
+use std::collections::hash_map::DefaultHasher;
 use std::collections::{BTreeMap, VecDeque};
+use std::hash::{Hash, Hasher};
 use std::ops::Bound;
+use std::str::FromStr;
 use std::sync::Arc;
 use std::{fmt, iter, mem};
 
 /// Trait for checking if the `caller` have access to `Self`
 pub trait AuthAccess {
     fn check_auth(&self, owner: Identity, caller: Identity) -> Result<(), AuthError>;
+
+    /// Like [`AuthAccess::check_auth`],
+    /// but reports every access violation rather than stopping at the first one.
+    ///
+    /// This is slower than `check_auth`, so should be used only to report errors.
+    fn check_auth_all(&self, owner: Identity, caller: Identity) -> Result<(), Vec<AuthError>> {
+        self.check_auth(owner, caller).map_err(|err| vec![err])
+    }
+}
+
+/// Returns an [`AuthError::TablePrivate`] for each of the `sources` the `caller` may not access.
+fn check_auth_sources(
+    sources: impl Iterator<Item = SourceExpr>,
+    owner: Identity,
+    caller: Identity,
+) -> Result<(), Vec<AuthError>> {
+    let errors: Vec<_> = sources
+        .filter_map(|table| table.check_auth(owner, caller).err())
+        .collect();
+    if errors.is_empty() {
+        Ok(())
+    } else {
+        Err(errors)
+    }
 }
 
 #[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Hash, From)]
@@ -34,11 +65,36 @@ pub enum ColumnOp {
         lhs: Box<ColumnOp>,
         rhs: Box<ColumnOp>,
     },
+    /// `field IN (values...)`, i.e., `field` is equal to any of `values`.
+    In {
+        field: FieldName,
+        values: Vec<AlgebraicValue>,
+    },
+    /// `(fields...) IN (values...)`, i.e., the tuple of `fields` is equal to any of `values`,
+    /// each of which is a product of one element per field.
+    InTuples {
+        fields: Vec<FieldName>,
+        values: Vec<AlgebraicValue>,
+    },
+    /// `len(op)`, i.e., the number of elements of the array or map `op` evaluates to, as a `u64`.
+    ///
+    /// The length of an absent collection, i.e., of a `none`, is unknown,
+    /// so no comparison with it holds, as with `NULL` in SQL.
+    /// No index stores the lengths of collections, so a filter on one is always served by a scan.
+    Len(Box<ColumnOp>),
 }
 
 type ColumnOpFlat = SmallVec<[ColumnOp; 1]>;
 type ColumnOpRefFlat<'a> = SmallVec<[&'a ColumnOp; 1]>;
 
+/// Returns the type of `field` in `header`.
+fn field_type(header: &Header, field: FieldName) -> Result<AlgebraicType, ErrorType> {
+    header
+        .column_pos(field)
+        .map(|pos| header.fields[pos.idx()].algebraic_type.clone())
+        .ok_or(ErrorType::FieldNotFound(field))
+}
+
 impl ColumnOp {
     pub fn new(op: OpQuery, lhs: ColumnOp, rhs: ColumnOp) -> Self {
         Self::Cmp {
@@ -56,13 +112,88 @@ impl ColumnOp {
         )
     }
 
+    /// Returns an op evaluating to the length of the array or map `op` evaluates to.
+    pub fn len_of(op: impl Into<ColumnOp>) -> Self {
+        Self::Len(Box::new(op.into()))
+    }
+
+    /// Returns an op where `field` must be equal to any of `values`.
+    pub fn in_list(field: impl Into<FieldName>, values: impl IntoIterator<Item = impl Into<AlgebraicValue>>) -> Self {
+        Self::In {
+            field: field.into(),
+            values: values.into_iter().map(Into::into).collect(),
+        }
+    }
+
+    /// Returns an op where the tuple of `fields` must be equal to any of `values`,
+    /// each of which is a product of one element per field.
+    pub fn in_tuples(
+        fields: impl IntoIterator<Item = impl Into<FieldName>>,
+        values: impl IntoIterator<Item = impl Into<AlgebraicValue>>,
+    ) -> Self {
+        Self::InTuples {
+            fields: fields.into_iter().map(Into::into).collect(),
+            values: values.into_iter().map(Into::into).collect(),
+        }
+    }
+
+    /// Returns `(fields...) IN (values...)` as a disjunction of conjunctions of equalities,
+    /// e.g., `(a = 1 AND b = 2) OR (a = 3 AND b = 4)` for `(a, b) IN ((1, 2), (3, 4))`.
+    ///
+    /// Returns `None` if any of `values` is not a product of one element per field.
+    fn expand_in_tuples(fields: &[FieldName], values: &[AlgebraicValue]) -> Option<Self> {
+        let disjuncts = values
+            .iter()
+            .map(|value| {
+                let elements = &value.as_product()?.elements;
+                (elements.len() == fields.len()).then(|| {
+                    fields
+                        .iter()
+                        .zip(elements.iter())
+                        .map(|(field, value)| Self::cmp(*field, OpCmp::Eq, value.clone()))
+                        .reduce(Self::and)
+                })
+            })
+            .collect::<Option<Vec<_>>>()?;
+        Some(
+            disjuncts
+                .into_iter()
+                // A tuple of no fields is equal to the empty product.
+                .map(|conjunction| conjunction.unwrap_or_else(|| Self::constant(true)))
+                .reduce(Self::or)
+                // No values; no row can match.
+                .unwrap_or_else(|| Self::constant(false)),
+        )
+    }
+
     /// Returns a new op where `lhs` and `rhs` are logically AND-ed together.
     fn and(lhs: ColumnOp, rhs: ColumnOp) -> Self {
         Self::new(OpQuery::Logic(OpLogic::And), lhs, rhs)
     }
 
+    /// Returns a new op where `lhs` and `rhs` are logically OR-ed together.
+    fn or(lhs: ColumnOp, rhs: ColumnOp) -> Self {
+        Self::new(OpQuery::Logic(OpLogic::Or), lhs, rhs)
+    }
+
     /// Returns an op where `col_i op value_i` are all `AND`ed together.
+    ///
+    /// Panics if `value` isn't a valid key for `cols`; see [`ColumnOp::try_and_cmp`].
     fn and_cmp(op: OpCmp, head: &Header, cols: &ColList, value: AlgebraicValue) -> Self {
+        Self::try_and_cmp(op, head, cols, value).unwrap_or_else(|err| panic!("{err}"))
+    }
+
+    /// Returns an op where `col_i op value_i` are all `AND`ed together.
+    ///
+    /// For a single column, `value` is used directly,
+    /// whereas for several columns, `value` must be a product of one value per column,
+    /// or [`ErrorVm::IndexKeyArity`] is returned.
+    /// A column of `cols` missing from `head` is a [`RelationError::FieldNotFound`].
+    pub fn try_and_cmp(op: OpCmp, head: &Header, cols: &ColList, value: AlgebraicValue) -> Result<Self, ErrorVm> {
+        if let Some(col) = cols.iter().find(|col| col.idx() >= head.fields.len()) {
+            let field = FieldName::new(head.table_id, col);
+            return Err(RelationError::FieldNotFound(head.clone_for_error(), field).into());
+        }
         let eq = |(col, value): (ColId, _)| {
             let field = head.fields[col.idx()].field;
             Self::cmp(field, op, value)
@@ -70,24 +201,47 @@ impl ColumnOp {
 
         // For singleton constraints, the `value` must be used directly.
         if cols.is_singleton() {
-            return eq((cols.head(), value));
+            return Ok(eq((cols.head(), value)));
         }
 
         // Otherwise, pair column ids and product fields together.
-        cols.iter()
-            .zip(value.into_product().unwrap())
-            .map(eq)
-            .reduce(Self::and)
-            .unwrap()
+        let key = match value {
+            AlgebraicValue::Product(key) if key.elements.len() == cols.len() as usize => key,
+            key => {
+                return Err(ErrorVm::IndexKeyArity {
+                    table: head.table_name.clone(),
+                    columns: cols.clone(),
+                    key,
+                })
+            }
+        };
+        Ok(cols.iter().zip(key).map(eq).reduce(Self::and).unwrap())
     }
 
     /// Returns an op where `cols` must be within bounds.
     /// This handles both the case of single-col bounds and multi-col bounds.
+    ///
+    /// Panics if a bound isn't a valid key for `cols`; see [`ColumnOp::try_from_op_col_bounds`].
     fn from_op_col_bounds(
         head: &Header,
         cols: &ColList,
         bounds: (Bound<AlgebraicValue>, Bound<AlgebraicValue>),
     ) -> Self {
+        Self::try_from_op_col_bounds(head, cols, bounds).unwrap_or_else(|err| panic!("{err}"))
+    }
+
+    /// Returns an op where `cols` must be within bounds.
+    /// This handles both the case of single-col bounds and multi-col bounds.
+    ///
+    /// Returns [`ErrorVm::IndexKeyArity`] if `cols` has several columns
+    /// and a bound isn't a product of one value per column,
+    /// and [`ErrorVm::UnboundedRange`] if neither bound restricts `cols`.
+    /// A column of `cols` missing from `head` is a [`RelationError::FieldNotFound`].
+    pub fn try_from_op_col_bounds(
+        head: &Header,
+        cols: &ColList,
+        bounds: (Bound<AlgebraicValue>, Bound<AlgebraicValue>),
+    ) -> Result<Self, ErrorVm> {
         let (cmp, value) = match bounds {
             // Equality; field <= value && field >= value <=> field = value
             (Bound::Included(a), Bound::Included(b)) if a == b => (OpCmp::Eq, a),
@@ -99,20 +253,96 @@ impl ColumnOp {
             (Bound::Unbounded, Bound::Included(value)) => (OpCmp::LtEq, value),
             // Exclusive upper bound => field < value
             (Bound::Unbounded, Bound::Excluded(value)) => (OpCmp::Lt, value),
-            (Bound::Unbounded, Bound::Unbounded) => unreachable!(),
+            (Bound::Unbounded, Bound::Unbounded) => {
+                return Err(ErrorVm::UnboundedRange {
+                    table: head.table_name.clone(),
+                    columns: cols.clone(),
+                })
+            }
             (lower_bound, upper_bound) => {
-                let lhs = Self::from_op_col_bounds(head, cols, (lower_bound, Bound::Unbounded));
-                let rhs = Self::from_op_col_bounds(head, cols, (Bound::Unbounded, upper_bound));
-                return ColumnOp::and(lhs, rhs);
+                let lhs = Self::try_from_op_col_bounds(head, cols, (lower_bound, Bound::Unbounded))?;
+                let rhs = Self::try_from_op_col_bounds(head, cols, (Bound::Unbounded, upper_bound))?;
+                return Ok(ColumnOp::and(lhs, rhs));
             }
         };
-        ColumnOp::and_cmp(cmp, head, cols, value)
+        ColumnOp::try_and_cmp(cmp, head, cols, value)
+    }
+
+    /// Returns an op where `cols` must be within any of `ranges`.
+    fn from_op_col_ranges(
+        head: &Header,
+        cols: &ColList,
+        ranges: Vec<(Bound<AlgebraicValue>, Bound<AlgebraicValue>)>,
+    ) -> Self {
+        let is_point =
+            |bounds: &(Bound<_>, Bound<_>)| matches!(bounds, (Bound::Included(a), Bound::Included(b)) if a == b);
+
+        // Points on a single column are an IN-list,
+        // and points on several columns are a tuple IN-list.
+        if ranges.iter().all(is_point) {
+            let values = ranges
+                .into_iter()
+                .map(|(lower, _)| match lower {
+                    Bound::Included(value) => value,
+                    _ => unreachable!(),
+                })
+                .collect();
+            if cols.is_singleton() {
+                let field = head.fields[cols.head().idx()].field;
+                return Self::In { field, values };
+            }
+            let fields = cols.iter().map(|col| head.fields[col.idx()].field).collect();
+            return Self::InTuples { fields, values };
+        }
+
+        ranges
+            .into_iter()
+            .map(|bounds| Self::from_op_col_bounds(head, cols, bounds))
+            .reduce(Self::or)
+            // No ranges; no row can match.
+            .unwrap_or_else(|| AlgebraicValue::Bool(false).into())
     }
 
     fn reduce(&self, row: &RelValue<'_>, value: &ColumnOp, header: &Header) -> Result<AlgebraicValue, ErrorLang> {
         match value {
             ColumnOp::Field(field) => Ok(row.get(field.borrowed(), header)?.into_owned()),
             ColumnOp::Cmp { op, lhs, rhs } => Ok(self.compare_bin_op(row, *op, lhs, rhs, header)?.into()),
+            ColumnOp::In { field, values } => Ok(Self::compare_in(row, *field, values, header)?.into()),
+            ColumnOp::InTuples { fields, values } => Ok(Self::compare_in_tuples(row, fields, values, header)?.into()),
+            ColumnOp::Len(op) => Ok(Self::collection_len(&self.reduce(row, op, header)?)?
+                .map_or_else(AlgebraicValue::OptionNone, AlgebraicValue::U64)),
+        }
+    }
+
+    /// Evaluates `op` as an operand of a comparison,
+    /// returning `None` if it is the length of an absent collection, which compares to nothing.
+    fn reduce_operand(
+        &self,
+        row: &RelValue<'_>,
+        op: &ColumnOp,
+        header: &Header,
+    ) -> Result<Option<AlgebraicValue>, ErrorLang> {
+        match op {
+            ColumnOp::Len(op) => Ok(Self::collection_len(&self.reduce(row, op, header)?)?.map(AlgebraicValue::U64)),
+            op => self.reduce(row, op, header).map(Some),
+        }
+    }
+
+    /// Returns the number of elements of the array or map `value`,
+    /// or `None` if `value` is `none`, i.e., an absent collection.
+    fn collection_len(value: &AlgebraicValue) -> Result<Option<u64>, ErrorType> {
+        match value {
+            AlgebraicValue::Array(array) => Ok(Some(array.len() as u64)),
+            AlgebraicValue::Map(map) => Ok(Some(map.len() as u64)),
+            // `some(collection)`.
+            AlgebraicValue::Sum(sum)
+                if sum.tag == 0 && matches!(&*sum.value, AlgebraicValue::Array(_) | AlgebraicValue::Map(_)) =>
+            {
+                Self::collection_len(&sum.value)
+            }
+            // `none`.
+            AlgebraicValue::Sum(sum) if sum.tag == 1 && *sum.value == AlgebraicValue::unit() => Ok(None),
+            value => Err(ErrorType::NotCollectionValue(value.clone())),
         }
     }
 
@@ -127,9 +357,35 @@ impl ColumnOp {
                 }
             }
             ColumnOp::Cmp { op, lhs, rhs } => Ok(self.compare_bin_op(row, *op, lhs, rhs, header)?),
+            ColumnOp::In { field, values } => Ok(Self::compare_in(row, *field, values, header)?),
+            ColumnOp::InTuples { fields, values } => Ok(Self::compare_in_tuples(row, fields, values, header)?),
+            ColumnOp::Len(_) => Err(ErrorType::NotBool(AlgebraicType::U64).into()),
         }
     }
 
+    fn compare_in(
+        row: &RelValue<'_>,
+        field: FieldName,
+        values: &[AlgebraicValue],
+        header: &Header,
+    ) -> Result<bool, ErrorVm> {
+        let value = row.get(FieldExprRef::Name(field), header)?;
+        Ok(values.contains(&*value))
+    }
+
+    fn compare_in_tuples(
+        row: &RelValue<'_>,
+        fields: &[FieldName],
+        values: &[AlgebraicValue],
+        header: &Header,
+    ) -> Result<bool, ErrorVm> {
+        let tuple = fields
+            .iter()
+            .map(|field| Ok(row.get(FieldExprRef::Name(*field), header)?.into_owned()))
+            .collect::<Result<ProductValue, ErrorVm>>()?;
+        Ok(values.contains(&AlgebraicValue::Product(tuple)))
+    }
+
     fn compare_bin_op(
         &self,
         row: &RelValue<'_>,
@@ -140,16 +396,21 @@ impl ColumnOp {
     ) -> Result<bool, ErrorVm> {
         match op {
             OpQuery::Cmp(op) => {
-                let lhs = self.reduce(row, lhs, header)?;
-                let rhs = self.reduce(row, rhs, header)?;
+                if let Some(result) = Self::compare_primitive_column(row, op, lhs, rhs, header) {
+                    return Ok(result);
+                }
 
-                Ok(match op {
-                    OpCmp::Eq => lhs == rhs,
-                    OpCmp::NotEq => lhs != rhs,
-                    OpCmp::Lt => lhs < rhs,
-                    OpCmp::LtEq => lhs <= rhs,
-                    OpCmp::Gt => lhs > rhs,
-                    OpCmp::GtEq => lhs >= rhs,
+                let lhs = self.reduce_operand(row, lhs, header)?;
+                let rhs = self.reduce_operand(row, rhs, header)?;
+
+                Ok(match (lhs, rhs) {
+                    (Some(lhs), Some(rhs)) if op == OpCmp::EqIgnoreCase => match eq_ignore_case(&lhs, &rhs) {
+                        Some(eq) => eq,
+                        None => return Err(ErrorType::NotStrings { lhs, rhs }.into()),
+                    },
+                    (Some(lhs), Some(rhs)) => compare_values(op, &lhs, &rhs),
+                    // The length of an absent collection compares to nothing.
+                    _ => false,
                 })
             }
             OpQuery::Logic(op) => {
@@ -164,6 +425,61 @@ impl ColumnOp {
         }
     }
 
+    /// Compares a column of `row` with a primitive value, i.e., a `bool` or an integer of at most 64 bits,
+    /// by reading the column straight out of the BFLATN bytes of the row,
+    /// rather than constructing an [`AlgebraicValue`] for it.
+    ///
+    /// Returns `None` when the comparison must be evaluated through [`ColumnOp::reduce`] instead,
+    /// i.e., unless `row` is a row in a table,
+    /// one of `lhs` and `rhs` is a column of `row` and the other a value of exactly the type of that column,
+    /// and `cmp` is not [`OpCmp::EqIgnoreCase`].
+    fn compare_primitive_column(
+        row: &RelValue<'_>,
+        cmp: OpCmp,
+        lhs: &ColumnOp,
+        rhs: &ColumnOp,
+        header: &Header,
+    ) -> Option<bool> {
+        let RelValue::Row(row_ref) = row else {
+            return None;
+        };
+        let (cmp, field, value) = match (lhs, rhs) {
+            (ColumnOp::Field(FieldExpr::Name(field)), ColumnOp::Field(FieldExpr::Value(value))) => (cmp, field, value),
+            (ColumnOp::Field(FieldExpr::Value(value)), ColumnOp::Field(FieldExpr::Name(field))) => {
+                (cmp.reverse(), field, value)
+            }
+            _ => return None,
+        };
+        if cmp == OpCmp::EqIgnoreCase {
+            return None;
+        }
+        let col = header.column_pos(*field)?;
+
+        // Reading the column fails, falling back to the general path,
+        // unless the column is of exactly the type of `value`.
+        macro_rules! compare_column {
+            ($($variant:ident => $ty:ty),*) => {
+                match value {
+                    $(AlgebraicValue::$variant(value) => {
+                        compare_ord(cmp, &row_ref.read_col::<$ty>(col).ok()?, value)
+                    })*
+                    _ => return None,
+                }
+            };
+        }
+        Some(compare_column!(
+            Bool => bool,
+            I8 => i8,
+            U8 => u8,
+            I16 => i16,
+            U16 => u16,
+            I32 => i32,
+            U32 => u32,
+            I64 => i64,
+            U64 => u64
+        ))
+    }
+
     pub fn compare(&self, row: &RelValue<'_>, header: &Header) -> Result<bool, ErrorVm> {
         match self {
             ColumnOp::Field(field) => {
@@ -171,7 +487,148 @@ impl ColumnOp {
                 Ok(*lhs.as_bool().unwrap())
             }
             ColumnOp::Cmp { op, lhs, rhs } => self.compare_bin_op(row, *op, lhs, rhs, header),
+            ColumnOp::In { field, values } => Self::compare_in(row, *field, values, header),
+            ColumnOp::InTuples { fields, values } => Self::compare_in_tuples(row, fields, values, header),
+            ColumnOp::Len(_) => Err(ErrorType::NotBool(AlgebraicType::U64).into()),
+        }
+    }
+
+    /// Checks that `self` is well-typed for rows of `header`, without evaluating it:
+    /// the operands of each comparison must be of the same type,
+    /// and the operands of `AND`/`OR`, as well as `self`, must be `bool`.
+    ///
+    /// Values whose type can't be inferred, i.e., sums, are accepted.
+    pub fn type_check(&self, header: &Header) -> Result<(), ErrorType> {
+        Self::expect_bool(self.infer_type(header)?)
+    }
+
+    /// Checks that each field compared to a value in `self` is of the type of that value,
+    /// reporting the field otherwise.
+    fn type_check_fields(&self, header: &Header) -> Result<(), ErrorType> {
+        let check = |field: FieldName, value: &AlgebraicValue| {
+            let ty = field_type(header, field)?;
+            match value.type_of() {
+                Some(value_ty) if value_ty != ty => Err(ErrorType::FieldMismatch { field, ty, value_ty }),
+                _ => Ok(()),
+            }
+        };
+
+        match self {
+            ColumnOp::Cmp {
+                op: OpQuery::Cmp(_),
+                lhs,
+                rhs,
+            } => match (&**lhs, &**rhs) {
+                (ColumnOp::Field(FieldExpr::Name(field)), ColumnOp::Field(FieldExpr::Value(value)))
+                | (ColumnOp::Field(FieldExpr::Value(value)), ColumnOp::Field(FieldExpr::Name(field))) => {
+                    check(*field, value)
+                }
+                _ => Ok(()),
+            },
+            ColumnOp::Cmp {
+                op: OpQuery::Logic(_),
+                lhs,
+                rhs,
+            } => {
+                lhs.type_check_fields(header)?;
+                rhs.type_check_fields(header)
+            }
+            ColumnOp::In { field, values } => values.iter().try_for_each(|value| check(*field, value)),
+            ColumnOp::Field(_) | ColumnOp::InTuples { .. } | ColumnOp::Len(_) => Ok(()),
+        }
+    }
+
+    fn expect_bool(ty: Option<AlgebraicType>) -> Result<(), ErrorType> {
+        match ty {
+            Some(ty) if ty != AlgebraicType::Bool => Err(ErrorType::NotBool(ty)),
+            _ => Ok(()),
+        }
+    }
+
+    fn expect_same(lhs: Option<AlgebraicType>, rhs: Option<AlgebraicType>) -> Result<(), ErrorType> {
+        match (lhs, rhs) {
+            (Some(lhs), Some(rhs)) if lhs != rhs => Err(ErrorType::Mismatch { lhs, rhs }),
+            _ => Ok(()),
+        }
+    }
+
+    /// Returns the type `self` evaluates to, if known.
+    fn infer_type(&self, header: &Header) -> Result<Option<AlgebraicType>, ErrorType> {
+        let field_type = |field: FieldName| field_type(header, field);
+
+        match self {
+            ColumnOp::Field(FieldExpr::Name(field)) => field_type(*field).map(Some),
+            ColumnOp::Field(FieldExpr::Value(value)) => Ok(value.type_of()),
+            ColumnOp::Cmp {
+                op: OpQuery::Cmp(_),
+                lhs,
+                rhs,
+            } => {
+                Self::expect_same(lhs.infer_type(header)?, rhs.infer_type(header)?)?;
+                Ok(Some(AlgebraicType::Bool))
+            }
+            ColumnOp::Cmp {
+                op: OpQuery::Logic(_),
+                lhs,
+                rhs,
+            } => {
+                Self::expect_bool(lhs.infer_type(header)?)?;
+                Self::expect_bool(rhs.infer_type(header)?)?;
+                Ok(Some(AlgebraicType::Bool))
+            }
+            ColumnOp::In { field, values } => {
+                let ty = field_type(*field)?;
+                for value in values {
+                    Self::expect_same(Some(ty.clone()), value.type_of())?;
+                }
+                Ok(Some(AlgebraicType::Bool))
+            }
+            ColumnOp::InTuples { fields, values } => {
+                let ty = fields
+                    .iter()
+                    .map(|field| field_type(*field))
+                    .collect::<Result<ProductType, _>>()?;
+                let ty = AlgebraicType::product(ty);
+                for value in values {
+                    Self::expect_same(Some(ty.clone()), value.type_of())?;
+                }
+                Ok(Some(AlgebraicType::Bool))
+            }
+            ColumnOp::Len(op) => {
+                let is_collection = |ty: &AlgebraicType| {
+                    matches!(ty, AlgebraicType::Builtin(BuiltinType::Array(_) | BuiltinType::Map(_)))
+                };
+                match op.infer_type(header)? {
+                    Some(AlgebraicType::Sum(sum)) if sum.as_option().is_some_and(is_collection) => {}
+                    Some(ty) if !is_collection(&ty) => return Err(ErrorType::NotCollection(ty)),
+                    _ => {}
+                }
+                Ok(Some(AlgebraicType::U64))
+            }
+        }
+    }
+
+    /// Returns the nesting depth of this op,
+    /// where a field or value has a depth of 1,
+    /// and e.g., `a = 1` has a depth of 2 and `a = 1 OR b = 2` one of 3.
+    ///
+    /// This is computed without recursion, unlike e.g., [`ColumnOp::flatten_ands`],
+    /// so it's safe to use for rejecting ops too deeply nested to process.
+    pub fn depth(&self) -> usize {
+        let mut max = 0;
+        let mut stack = vec![(self, 1)];
+        while let Some((op, depth)) = stack.pop() {
+            max = max.max(depth);
+            match op {
+                ColumnOp::Cmp { lhs, rhs, .. } => {
+                    stack.push((&**lhs, depth + 1));
+                    stack.push((&**rhs, depth + 1));
+                }
+                ColumnOp::Len(op) => stack.push((&**op, depth + 1)),
+                ColumnOp::Field(_) | ColumnOp::In { .. } | ColumnOp::InTuples { .. } => {}
+            }
         }
+        max
     }
 
     /// Flattens a nested conjunction of AND expressions.
@@ -225,51 +682,446 @@ impl ColumnOp {
         fill_vec(&mut buf, self);
         buf
     }
-}
 
-impl fmt::Display for ColumnOp {
-    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-        match self {
-            ColumnOp::Field(x) => {
-                write!(f, "{}", x)
-            }
-            ColumnOp::Cmp { op, lhs, rhs } => {
-                write!(f, "{} {} {}", lhs, op, rhs)
+    /// Flattens a nested disjunction of OR expressions.
+    ///
+    /// For example, `a = 1 OR b = 2 OR c = 3` becomes `[a = 1, b = 2, c = 3]`,
+    /// while `a = 1 OR (b = 2 AND c = 3)` becomes `[a = 1, b = 2 AND c = 3]`.
+    ///
+    /// This is the counterpart of [`ColumnOp::flatten_ands`],
+    /// e.g., for serving each disjunct with its own index.
+    pub fn flatten_ors(self) -> ColumnOpFlat {
+        fn fill_vec(buf: &mut ColumnOpFlat, op: ColumnOp) {
+            match op {
+                ColumnOp::Cmp {
+                    op: OpQuery::Logic(OpLogic::Or),
+                    lhs,
+                    rhs,
+                } => {
+                    fill_vec(buf, *lhs);
+                    fill_vec(buf, *rhs);
+                }
+                op => buf.push(op),
             }
         }
+        let mut buf = SmallVec::new();
+        fill_vec(&mut buf, self);
+        buf
     }
-}
 
-impl From<FieldName> for ColumnOp {
-    fn from(value: FieldName) -> Self {
-        ColumnOp::Field(value.into())
+    /// Flattens a nested disjunction of OR expressions.
+    ///
+    /// For example, `a = 1 OR b = 2 OR c = 3` becomes `[a = 1, b = 2, c = 3]`,
+    /// while `a = 1 OR (b = 2 AND c = 3)` becomes `[a = 1, b = 2 AND c = 3]`.
+    ///
+    /// This is the counterpart of [`ColumnOp::flatten_ands_ref`],
+    /// e.g., for serving each disjunct with its own index.
+    pub fn flatten_ors_ref(&self) -> ColumnOpRefFlat<'_> {
+        fn fill_vec<'a>(buf: &mut ColumnOpRefFlat<'a>, op: &'a ColumnOp) {
+            match op {
+                ColumnOp::Cmp {
+                    op: OpQuery::Logic(OpLogic::Or),
+                    lhs,
+                    rhs,
+                } => {
+                    fill_vec(buf, lhs);
+                    fill_vec(buf, rhs);
+                }
+                op => buf.push(op),
+            }
+        }
+        let mut buf = SmallVec::new();
+        fill_vec(&mut buf, self);
+        buf
     }
-}
 
-impl From<AlgebraicValue> for ColumnOp {
-    fn from(value: AlgebraicValue) -> Self {
-        ColumnOp::Field(value.into())
+    /// Returns the set of [`FieldName`]s referenced anywhere in this predicate,
+    /// in the order they are first encountered.
+    ///
+    /// For example, `(a = 1 AND b = 2) OR a = 3` returns `[a, b]`.
+    pub fn referenced_fields(&self) -> SmallVec<[FieldName; 4]> {
+        fn fill_vec(buf: &mut SmallVec<[FieldName; 4]>, op: &ColumnOp) {
+            match op {
+                ColumnOp::Field(FieldExpr::Name(field)) | ColumnOp::In { field, .. } => {
+                    // Predicates usually reference only a handful of fields,
+                    // so a linear scan beats hashing here.
+                    if !buf.contains(field) {
+                        buf.push(*field);
+                    }
+                }
+                ColumnOp::InTuples { fields, .. } => {
+                    for field in fields {
+                        if !buf.contains(field) {
+                            buf.push(*field);
+                        }
+                    }
+                }
+                ColumnOp::Field(FieldExpr::Value(_)) => {}
+                ColumnOp::Cmp { lhs, rhs, .. } => {
+                    fill_vec(buf, lhs);
+                    fill_vec(buf, rhs);
+                }
+                ColumnOp::Len(op) => fill_vec(buf, op),
+            }
+        }
+        let mut buf = SmallVec::new();
+        fill_vec(&mut buf, self);
+        buf
     }
-}
 
-impl From<Query> for Option<ColumnOp> {
-    fn from(value: Query) -> Self {
-        match value {
-            Query::IndexScan(op) => Some(ColumnOp::from_op_col_bounds(&op.table.head, &op.columns, op.bounds)),
-            Query::Select(op) => Some(op),
-            _ => None,
+    /// Returns an equivalent op where the comparisons between two literals are evaluated ahead of time,
+    /// e.g., `1 = 2 OR a = 3` becomes `a = 3`.
+    ///
+    /// The resulting `bool` literals are then simplified away:
+    /// `x AND false` becomes `false`, `x OR true` becomes `true`,
+    /// while `x AND true` and `x OR false` become `x`.
+    pub fn fold_constants(self) -> Self {
+        match self {
+            ColumnOp::Cmp {
+                op: OpQuery::Cmp(cmp),
+                lhs,
+                rhs,
+            } => match (lhs.fold_constants(), rhs.fold_constants()) {
+                (ColumnOp::Field(FieldExpr::Value(lhs)), ColumnOp::Field(FieldExpr::Value(rhs))) => {
+                    Self::constant(compare_values(cmp, &lhs, &rhs))
+                }
+                (lhs, rhs) => Self::new(OpQuery::Cmp(cmp), lhs, rhs),
+            },
+            ColumnOp::Cmp {
+                op: OpQuery::Logic(logic),
+                lhs,
+                rhs,
+            } => {
+                let (lhs, rhs) = (lhs.fold_constants(), rhs.fold_constants());
+                match (logic, lhs.as_constant(), rhs.as_constant()) {
+                    (OpLogic::And, Some(false), _) | (OpLogic::And, _, Some(false)) => Self::constant(false),
+                    (OpLogic::Or, Some(true), _) | (OpLogic::Or, _, Some(true)) => Self::constant(true),
+                    (OpLogic::And, Some(true), _) | (OpLogic::Or, Some(false), _) => rhs,
+                    (OpLogic::And, _, Some(true)) | (OpLogic::Or, _, Some(false)) => lhs,
+                    _ => Self::new(OpQuery::Logic(logic), lhs, rhs),
+                }
+            }
+            op => op,
         }
     }
-}
 
-/// An identifier for a data source (i.e. a table) in a query plan.
-///
-/// When compiling a query plan, rather than embedding the inputs in the plan,
-/// we annotate each input with a `SourceId`, and the compiled plan refers to its inputs by id.
-/// This allows the plan to be re-used with distinct inputs,
-/// assuming the inputs obey the same schema.
-///
-/// Note that re-using a query plan is only a good idea
+    /// Returns an equivalent op where the equalities on the same field in a disjunction
+    /// are collapsed into an IN-list that can be answered by index seeks,
+    /// e.g., `a = 1 OR b = 2 OR a = 3` becomes `a IN (1, 3) OR b = 2`.
+    pub fn collapse_or_eqs(self) -> Self {
+        match self {
+            ColumnOp::Cmp {
+                op: OpQuery::Logic(OpLogic::And),
+                lhs,
+                rhs,
+            } => Self::and(lhs.collapse_or_eqs(), rhs.collapse_or_eqs()),
+            ColumnOp::Cmp {
+                op: OpQuery::Logic(OpLogic::Or),
+                lhs,
+                rhs,
+            } => {
+                fn fill_vec(buf: &mut Vec<ColumnOp>, op: ColumnOp) {
+                    match op {
+                        ColumnOp::Cmp {
+                            op: OpQuery::Logic(OpLogic::Or),
+                            lhs,
+                            rhs,
+                        } => {
+                            fill_vec(buf, *lhs);
+                            fill_vec(buf, *rhs);
+                        }
+                        op => buf.push(op.collapse_or_eqs()),
+                    }
+                }
+                let mut disjuncts = Vec::new();
+                fill_vec(&mut disjuncts, *lhs);
+                fill_vec(&mut disjuncts, *rhs);
+                Self::collapse_eq_disjuncts(disjuncts)
+                    .into_iter()
+                    .reduce(Self::or)
+                    .unwrap()
+            }
+            op => op,
+        }
+    }
+
+    /// Collapses the equalities and IN-lists on the same field among `disjuncts`
+    /// into a single IN-list of the sorted values, in place of the first of them.
+    /// Lone equalities and the other disjuncts are kept as is.
+    fn collapse_eq_disjuncts(disjuncts: Vec<ColumnOp>) -> Vec<ColumnOp> {
+        // The values each field is compared to, in the order the fields are first encountered.
+        let mut fields: Vec<(FieldName, Vec<AlgebraicValue>, usize)> = Vec::new();
+        let mut add = |field: FieldName, values: &[AlgebraicValue]| match fields.iter_mut().find(|(f, ..)| *f == field)
+        {
+            Some((_, vals, count)) => {
+                vals.extend_from_slice(values);
+                *count += 1;
+            }
+            None => fields.push((field, values.to_vec(), 1)),
+        };
+        let as_eq = |op: &ColumnOp| match op {
+            ColumnOp::Cmp {
+                op: OpQuery::Cmp(OpCmp::Eq),
+                lhs,
+                rhs,
+            } => match (&**lhs, &**rhs) {
+                (ColumnOp::Field(FieldExpr::Name(field)), ColumnOp::Field(FieldExpr::Value(value)))
+                | (ColumnOp::Field(FieldExpr::Value(value)), ColumnOp::Field(FieldExpr::Name(field))) => {
+                    Some((*field, value.clone()))
+                }
+                _ => None,
+            },
+            _ => None,
+        };
+        for op in &disjuncts {
+            match op {
+                ColumnOp::In { field, values } => add(*field, values),
+                op => {
+                    if let Some((field, value)) = as_eq(op) {
+                        add(field, &[value]);
+                    }
+                }
+            }
+        }
+        let collapsed = |field: &FieldName| fields.iter().find(|(f, _, count)| f == field && *count > 1);
+
+        let mut out = Vec::with_capacity(disjuncts.len());
+        let mut done = HashSet::new();
+        for op in disjuncts {
+            let field = match &op {
+                ColumnOp::In { field, .. } => Some(*field),
+                op => as_eq(op).map(|(field, _)| field),
+            };
+            match field.and_then(|field| collapsed(&field)) {
+                Some((field, values, _)) => {
+                    if done.insert(*field) {
+                        out.push(Self::in_list_or_eq(*field, values.clone()));
+                    }
+                }
+                None => out.push(op),
+            }
+        }
+        out
+    }
+
+    /// Returns `field IN (values...)` with `values` sorted and deduplicated,
+    /// or `field = value` when there is a single value.
+    fn in_list_or_eq(field: FieldName, mut values: Vec<AlgebraicValue>) -> Self {
+        values.sort();
+        values.dedup();
+        if values.len() == 1 {
+            return Self::cmp(field, OpCmp::Eq, values.pop().unwrap());
+        }
+        ColumnOp::In { field, values }
+    }
+
+    /// Returns the constant op `value`.
+    fn constant(value: bool) -> Self {
+        ColumnOp::Field(FieldExpr::Value(AlgebraicValue::Bool(value)))
+    }
+
+    /// Returns the value of `self` if it is a constant `bool`.
+    fn as_constant(&self) -> Option<bool> {
+        match self {
+            ColumnOp::Field(FieldExpr::Value(value)) => value.as_bool().copied(),
+            _ => None,
+        }
+    }
+
+    /// Returns the estimated fraction of rows satisfying this op,
+    /// using fixed selectivities per comparison and assuming that the operands of `AND` and `OR` are independent.
+    fn selectivity(&self) -> f64 {
+        match self {
+            ColumnOp::Field(_) | ColumnOp::Len(_) => {
+                self.as_constant().map_or(0.5, |value| if value { 1.0 } else { 0.0 })
+            }
+            ColumnOp::Cmp {
+                op: OpQuery::Cmp(cmp), ..
+            } => cmp_selectivity(*cmp),
+            ColumnOp::Cmp {
+                op: OpQuery::Logic(OpLogic::And),
+                lhs,
+                rhs,
+            } => lhs.selectivity() * rhs.selectivity(),
+            ColumnOp::Cmp {
+                op: OpQuery::Logic(OpLogic::Or),
+                lhs,
+                rhs,
+            } => {
+                let (lhs, rhs) = (lhs.selectivity(), rhs.selectivity());
+                lhs + rhs - lhs * rhs
+            }
+            ColumnOp::In { values, .. } => (values.len() as f64 * EQ_SELECTIVITY).min(1.0),
+            ColumnOp::InTuples { fields, values } => {
+                (values.len() as f64 * EQ_SELECTIVITY.powi(fields.len() as i32)).min(1.0)
+            }
+        }
+    }
+
+    /// Returns an equivalent op in canonical form,
+    /// such that ops that only differ by the rewrites below canonicalize identically:
+    ///
+    /// 1. Comparisons of two constants are folded into `true` or `false`,
+    ///    which are then absorbed by the `AND`s and `OR`s around them.
+    /// 2. Comparisons are ordered field first, e.g., `5 > a` becomes `a < 5`,
+    ///    and comparisons of two fields are ordered by field.
+    /// 3. Equalities and IN-lists on the same field in a disjunction
+    ///    are collapsed into one IN-list, as by [`ColumnOp::collapse_or_eqs`],
+    ///    and IN-lists of a single value become equalities.
+    /// 4. The operands of nested `AND`s, and of nested `OR`s, are sorted and deduplicated,
+    ///    and so are the values of IN-lists.
+    pub fn canonicalize(self) -> Self {
+        match self {
+            ColumnOp::Field(_) => self,
+            ColumnOp::Len(op) => Self::Len(Box::new(op.canonicalize())),
+            ColumnOp::In { field, values } => Self::in_list_or_eq(field, values),
+            ColumnOp::InTuples { fields, mut values } => {
+                values.sort();
+                values.dedup();
+                ColumnOp::InTuples { fields, values }
+            }
+            ColumnOp::Cmp {
+                op: OpQuery::Cmp(cmp),
+                lhs,
+                rhs,
+            } => match (lhs.canonicalize(), rhs.canonicalize()) {
+                // A case-insensitive comparison of non-strings is left to fail during evaluation.
+                (ColumnOp::Field(FieldExpr::Value(lhs)), ColumnOp::Field(FieldExpr::Value(rhs)))
+                    if cmp != OpCmp::EqIgnoreCase || eq_ignore_case(&lhs, &rhs).is_some() =>
+                {
+                    Self::constant(compare_values(cmp, &lhs, &rhs))
+                }
+                (lhs @ ColumnOp::Field(FieldExpr::Value(_)), rhs @ ColumnOp::Field(FieldExpr::Name(_))) => {
+                    Self::new(OpQuery::Cmp(cmp.reverse()), rhs, lhs)
+                }
+                (ColumnOp::Field(FieldExpr::Name(lhs)), ColumnOp::Field(FieldExpr::Name(rhs))) if rhs < lhs => {
+                    Self::new(OpQuery::Cmp(cmp.reverse()), rhs.into(), lhs.into())
+                }
+                (lhs, rhs) => Self::new(OpQuery::Cmp(cmp), lhs, rhs),
+            },
+            ColumnOp::Cmp {
+                op: OpQuery::Logic(logic),
+                lhs,
+                rhs,
+            } => {
+                // Flattens the canonical operands of nested `logic`s into `buf`.
+                // The canonical form of an operand may itself be a `logic`,
+                // e.g., `(a AND b) OR false` becomes `a AND b`.
+                fn fill_vec(buf: &mut Vec<ColumnOp>, logic: OpLogic, op: ColumnOp) {
+                    match op {
+                        ColumnOp::Cmp {
+                            op: OpQuery::Logic(op),
+                            lhs,
+                            rhs,
+                        } if op == logic => {
+                            fill_vec(buf, logic, *lhs);
+                            fill_vec(buf, logic, *rhs);
+                        }
+                        op => {
+                            let op = op.canonicalize();
+                            if matches!(op, ColumnOp::Cmp { op: OpQuery::Logic(op), .. } if op == logic) {
+                                fill_vec(buf, logic, op);
+                            } else {
+                                buf.push(op);
+                            }
+                        }
+                    }
+                }
+                let mut operands = Vec::new();
+                fill_vec(&mut operands, logic, *lhs);
+                fill_vec(&mut operands, logic, *rhs);
+
+                // `true` is the identity of `AND` and absorbs `OR`, and vice versa for `false`.
+                let identity = logic == OpLogic::And;
+                if operands.iter().any(|op| op.as_constant() == Some(!identity)) {
+                    return Self::constant(!identity);
+                }
+                operands.retain(|op| op.as_constant().is_none());
+                if logic == OpLogic::Or {
+                    operands = Self::collapse_eq_disjuncts(operands);
+                }
+                operands.sort();
+                operands.dedup();
+
+                let combine = match logic {
+                    OpLogic::And => Self::and,
+                    OpLogic::Or => Self::or,
+                };
+                operands
+                    .into_iter()
+                    .reduce(combine)
+                    .unwrap_or_else(|| Self::constant(identity))
+            }
+        }
+    }
+}
+
+impl fmt::Display for ColumnOp {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ColumnOp::Field(x) => {
+                write!(f, "{}", x)
+            }
+            ColumnOp::Cmp { op, lhs, rhs } => {
+                write!(f, "{} {} {}", lhs, op, rhs)
+            }
+            ColumnOp::In { field, values } => {
+                write!(f, "{field} IN (")?;
+                for (pos, value) in values.iter().enumerate() {
+                    write!(f, "{}", value.to_satn())?;
+                    if pos + 1 < values.len() {
+                        write!(f, ", ")?;
+                    }
+                }
+                write!(f, ")")
+            }
+            ColumnOp::InTuples { fields, values } => {
+                let fields = fields.iter().map(|field| field.to_string()).collect::<Vec<_>>();
+                write!(f, "({}) IN (", fields.join(", "))?;
+                for (pos, value) in values.iter().enumerate() {
+                    write!(f, "{}", value.to_satn())?;
+                    if pos + 1 < values.len() {
+                        write!(f, ", ")?;
+                    }
+                }
+                write!(f, ")")
+            }
+            ColumnOp::Len(op) => write!(f, "len({op})"),
+        }
+    }
+}
+
+impl From<FieldName> for ColumnOp {
+    fn from(value: FieldName) -> Self {
+        ColumnOp::Field(value.into())
+    }
+}
+
+impl From<AlgebraicValue> for ColumnOp {
+    fn from(value: AlgebraicValue) -> Self {
+        ColumnOp::Field(value.into())
+    }
+}
+
+impl From<Query> for Option<ColumnOp> {
+    fn from(value: Query) -> Self {
+        match value {
+            Query::IndexScan(op) => Some(ColumnOp::from_op_col_bounds(&op.table.head, &op.columns, op.bounds)),
+            Query::IndexMultiScan(op) => Some(ColumnOp::from_op_col_ranges(&op.table.head, &op.columns, op.ranges)),
+            Query::Select(op) => Some(op),
+            _ => None,
+        }
+    }
+}
+
+/// An identifier for a data source (i.e. a table) in a query plan.
+///
+/// When compiling a query plan, rather than embedding the inputs in the plan,
+/// we annotate each input with a `SourceId`, and the compiled plan refers to its inputs by id.
+/// This allows the plan to be re-used with distinct inputs,
+/// assuming the inputs obey the same schema.
+///
+/// Note that re-using a query plan is only a good idea
 /// if the new inputs are similar to those used for compilation
 /// in terms of cardinality and distribution.
 #[derive(Debug, Copy, Clone, PartialEq, Eq, From, Hash)]
@@ -297,6 +1149,7 @@ pub trait SourceProvider<'a> {
     /// Taking the same `id` a second time may or may not yield the same source.
     /// Callers should not assume that a generic provider will yield it more than once.
     /// This means that a query plan may not include multiple references to the same [`SourceId`].
+    /// To run a plan again, over the same or new sources, see [`SourceSet::reset`].
     ///
     /// Implementations are also not obligated to inspect `id`, e.g., if there's only one option.
     fn take_source(&mut self, id: SourceId) -> Option<Self::Source>;
@@ -325,17 +1178,26 @@ impl<'a> SourceProvider<'a> for NoInMemUsed {
     }
 }
 
-/// A [`SourceProvider`] backed by an `ArrayVec`.
+/// A [`SourceProvider`] backed by an `ArrayVec`,
+/// which spills to a `Vec` when more than `N` entries are added.
 ///
 /// Internally, the `SourceSet` stores an `Option<T>` for each planned [`SourceId`]
 /// which are [`Option::take`]n out of the set.
 #[derive(Debug, PartialEq, Eq, Clone)]
 #[repr(transparent)]
-pub struct SourceSet<T, const N: usize>(
+pub struct SourceSet<T, const N: usize>(SourceSlots<T, N>);
+
+/// The storage of a [`SourceSet`].
+///
+/// A set starts out `Inline` and only becomes `Spilled` once it holds more than `N` entries,
+/// so `Spilled` always has a length greater than `N`.
+#[derive(Debug, PartialEq, Eq, Clone)]
+enum SourceSlots<T, const N: usize> {
     // Benchmarks showed an improvement in performance
     // on incr-select by ~10% by not using `Vec<Option<T>>`.
-    ArrayVec<Option<T>, N>,
-);
+    Inline(ArrayVec<Option<T>, N>),
+    Spilled(Vec<Option<T>>),
+}
 
 impl<'a, T: 'a + IntoIterator<Item = RelValue<'a>>, const N: usize> SourceProvider<'a> for SourceSet<T, N> {
     type Source = T;
@@ -347,26 +1209,62 @@ impl<'a, T: 'a + IntoIterator<Item = RelValue<'a>>, const N: usize> SourceProvid
 impl<T, const N: usize> From<[T; N]> for SourceSet<T, N> {
     #[inline]
     fn from(sources: [T; N]) -> Self {
-        Self(sources.map(Some).into())
+        Self(SourceSlots::Inline(sources.map(Some).into()))
     }
 }
 
 impl<T, const N: usize> SourceSet<T, N> {
     /// Returns an empty source set.
     pub fn empty() -> Self {
-        Self(ArrayVec::new())
+        Self(SourceSlots::Inline(ArrayVec::new()))
+    }
+
+    /// Returns the slots of this set, regardless of how they are stored.
+    fn slots(&self) -> &[Option<T>] {
+        match &self.0 {
+            SourceSlots::Inline(slots) => slots,
+            SourceSlots::Spilled(slots) => slots,
+        }
+    }
+
+    /// Returns the slots of this set mutably, regardless of how they are stored.
+    fn slots_mut(&mut self) -> &mut [Option<T>] {
+        match &mut self.0 {
+            SourceSlots::Inline(slots) => slots,
+            SourceSlots::Spilled(slots) => slots,
+        }
+    }
+
+    /// Returns whether this set has outgrown its inline capacity of `N`
+    /// and now stores its entries on the heap.
+    pub fn is_spilled(&self) -> bool {
+        matches!(self.0, SourceSlots::Spilled(_))
     }
 
     /// Get a fresh `SourceId` which can be used as the id for a new entry.
     fn next_id(&self) -> SourceId {
-        SourceId(self.0.len())
+        SourceId(self.len())
     }
 
     /// Insert an entry into this `SourceSet` so it can be used in a query plan,
     /// and return a [`SourceId`] which can be embedded in that plan.
+    ///
+    /// Once more than `N` entries have been added,
+    /// the set moves all of its entries to the heap.
+    /// Previously handed out [`SourceId`]s remain valid.
     pub fn add(&mut self, table: T) -> SourceId {
         let source_id = self.next_id();
-        self.0.push(Some(table));
+        match &mut self.0 {
+            SourceSlots::Inline(slots) => {
+                if let Err(err) = slots.try_push(Some(table)) {
+                    let mut spilled = Vec::with_capacity(N.max(1) * 2);
+                    spilled.extend(slots.drain(..));
+                    spilled.push(err.element());
+                    self.0 = SourceSlots::Spilled(spilled);
+                }
+            }
+            SourceSlots::Spilled(slots) => slots.push(Some(table)),
+        }
         source_id
     }
 
@@ -375,21 +1273,65 @@ impl<T, const N: usize> SourceSet<T, N> {
     ///
     /// Subsequent calls to `take` on the same `id` will return `None`.
     pub fn take(&mut self, id: SourceId) -> Option<T> {
-        self.0.get_mut(id.0).map(mem::take).unwrap_or_default()
+        self.slots_mut().get_mut(id.0).map(mem::take).unwrap_or_default()
+    }
+
+    /// Replaces the entries of this `SourceSet` with `sources`,
+    /// so that a query plan compiled against this set can be run again over new inputs.
+    ///
+    /// The [`SourceId`]s handed out by [`SourceSet::add`] are preserved,
+    /// i.e., the entry `id` is replaced by `sources[id.0]`,
+    /// regardless of whether it has been [taken](SourceSet::take) since.
+    ///
+    /// Each new entry must have the same schema as the one it replaces.
+    /// As the plan was compiled using the row counts of the original entries,
+    /// the new entries should also have similar numbers of rows,
+    /// or the plan may no longer be a good one.
+    ///
+    /// Panics if this set does not have exactly `N` slots,
+    /// which is also the case when it has [spilled](SourceSet::is_spilled).
+    pub fn reset(&mut self, sources: [T; N]) {
+        assert_eq!(
+            self.len(),
+            N,
+            "can only reset a `SourceSet` with all of its {N} slots added"
+        );
+        *self = sources.into();
+    }
+
+    /// Returns an iterator over the entries of this `SourceSet` which have not yet been [taken](SourceSet::take),
+    /// together with their [`SourceId`]s.
+    pub fn iter_remaining(&self) -> impl Iterator<Item = (SourceId, &T)> {
+        self.slots()
+            .iter()
+            .enumerate()
+            .filter_map(|(idx, slot)| Some((SourceId(idx), slot.as_ref()?)))
+    }
+
+    /// Returns an iterator over the [`SourceId`]s of the entries of this `SourceSet`
+    /// which have already been [taken](SourceSet::take).
+    ///
+    /// Useful to validate that a query plan consumed exactly the sources it declared.
+    pub fn consumed_ids(&self) -> impl Iterator<Item = SourceId> + '_ {
+        self.slots()
+            .iter()
+            .enumerate()
+            .filter(|(_, slot)| slot.is_none())
+            .map(|(idx, _)| SourceId(idx))
     }
 
     /// Returns the number of slots for [`MemTable`]s in this set.
     ///
     /// Calling `self.take_mem_table(...)` or `self.take_table(...)` won't affect this number.
     pub fn len(&self) -> usize {
-        self.0.len()
+        self.slots().len()
     }
 
     /// Returns whether this set has any slots for [`MemTable`]s.
     ///
     /// Calling `self.take_mem_table(...)` or `self.take_table(...)` won't affect whether the set is empty.
     pub fn is_empty(&self) -> bool {
-        self.0.is_empty()
+        self.slots().is_empty()
     }
 }
 
@@ -397,13 +1339,13 @@ impl<T, const N: usize> std::ops::Index<SourceId> for SourceSet<T, N> {
     type Output = Option<T>;
 
     fn index(&self, idx: SourceId) -> &Self::Output {
-        &self.0[idx.0]
+        &self.slots()[idx.0]
     }
 }
 
 impl<T, const N: usize> std::ops::IndexMut<SourceId> for SourceSet<T, N> {
     fn index_mut(&mut self, idx: SourceId) -> &mut Self::Output {
-        &mut self.0[idx.0]
+        &mut self.slots_mut()[idx.0]
     }
 }
 
@@ -434,6 +1376,8 @@ pub enum SourceExpr {
         table_type: StTableType,
         table_access: StAccess,
         row_count: RowCount,
+        /// Statistics about the columns of the table, if known, to guide the optimizer.
+        column_stats: Option<Arc<ColumnStatsMap>>,
     },
     /// A plan for a database table. Because [`DbTable`] is small and efficiently cloneable,
     /// no indirection into a [`SourceSet`] is required.
@@ -453,6 +1397,18 @@ impl SourceExpr {
         }
     }
 
+    /// If `self` is an in-memory source, replaces its [`SourceId`]
+    /// with the position of that id in `seen`, adding the id if not yet seen.
+    fn normalize_source_id(&mut self, seen: &mut Vec<SourceId>) {
+        if let SourceExpr::InMemory { source_id, .. } = self {
+            let pos = seen.iter().position(|id| id == source_id).unwrap_or_else(|| {
+                seen.push(*source_id);
+                seen.len() - 1
+            });
+            *source_id = SourceId(pos);
+        }
+    }
+
     pub fn table_name(&self) -> &str {
         &self.head().table_name
     }
@@ -493,6 +1449,26 @@ impl SourceExpr {
             table_type: StTableType::User,
             table_access,
             row_count: RowCount::exact(row_count),
+            column_stats: None,
+        }
+    }
+
+    /// Attaches `stats` about the columns of `self`,
+    /// which the optimizer consults when choosing among indices.
+    pub fn with_column_stats(mut self, stats: ColumnStatsMap) -> Self {
+        let stats = Some(Arc::new(stats));
+        match &mut self {
+            SourceExpr::InMemory { column_stats, .. } => *column_stats = stats,
+            SourceExpr::DbTable(db_table) => db_table.column_stats = stats,
+        }
+        self
+    }
+
+    /// Returns the statistics about the columns of `self`, if any were attached.
+    pub fn column_stats(&self) -> Option<&ColumnStatsMap> {
+        match self {
+            SourceExpr::InMemory { column_stats, .. } => column_stats.as_deref(),
+            SourceExpr::DbTable(db_table) => db_table.column_stats.as_deref(),
         }
     }
 
@@ -516,6 +1492,58 @@ impl SourceExpr {
             None
         }
     }
+
+    /// Returns the [`Header`] of the rows of `self` projected to `cols`, in that order.
+    ///
+    /// Unlike [`Header::project`], the constraints and partial indexes are renumbered
+    /// to the positions of their columns in the projection,
+    /// and those referring to any column left out are dropped.
+    /// The columns paired with an alias are named by it, as for [`Header::project_aliased`].
+    pub fn project_header(&self, cols: &[(FieldExpr, Option<Box<str>>)]) -> Result<Arc<Header>, RelationError> {
+        let head = self.head();
+        // Validates the names and synthesizes the columns of the values.
+        let projected = head.project_aliased(cols)?;
+
+        // The new position of each column of `head`, if kept.
+        let mut positions = vec![None; head.fields.len()];
+        for (new_pos, (col, _)) in cols.iter().enumerate() {
+            if let FieldExpr::Name(field) = col {
+                let pos = head.column_pos_or_err(*field)?;
+                positions[pos.idx()].get_or_insert(ColId::from(new_pos));
+            }
+        }
+        let remap = |col: ColId| positions[col.idx()];
+        let remap_list = |cols: &ColList| {
+            cols.iter()
+                .map(remap)
+                .collect::<Option<ColListBuilder>>()
+                .and_then(|cols| cols.build().ok())
+        };
+
+        let constraints = head
+            .constraints
+            .iter()
+            .filter_map(|(cols, constraints)| Some((remap_list(cols)?, *constraints)))
+            .collect();
+        let partial_indexes = head
+            .partial_indexes
+            .iter()
+            .filter_map(|(cols, predicate)| {
+                let ranges = predicate
+                    .ranges
+                    .iter()
+                    .map(|(col, lower, upper)| Some((remap(*col)?, lower.clone(), upper.clone())))
+                    .collect::<Option<_>>()?;
+                Some((remap_list(cols)?, PartialIndexPredicate { ranges }))
+            })
+            .collect();
+
+        Ok(Arc::new(Header {
+            constraints,
+            partial_indexes,
+            ..projected
+        }))
+    }
 }
 
 impl Relation for SourceExpr {
@@ -543,13 +1571,21 @@ impl From<&TableSchema> for SourceExpr {
 #[derive(Debug, Clone, Eq, PartialEq, Hash)]
 pub struct IndexJoin {
     pub probe_side: QueryExpr,
-    pub probe_field: FieldName,
+    /// The fields of the probe side whose values are used to probe the index,
+    /// where `probe_fields[i]` is joined with the `i`-th column of `index_cols`.
+    pub probe_fields: SmallVec<[FieldName; 2]>,
     pub index_side: SourceExpr,
     pub index_select: Option<ColumnOp>,
-    pub index_col: ColId,
+    /// The columns of the index on the `index_side`.
+    pub index_cols: ColList,
     /// If true, returns rows from the `index_side`.
     /// Otherwise, returns rows from the `probe_side`.
     pub return_index_rows: bool,
+    /// The columns of the returned rows to emit, if not all of them,
+    /// which are all columns of the returned side.
+    ///
+    /// Set by [`QueryExpr::optimize`] when fusing a projection directly following the join into it.
+    pub projection: Option<Vec<FieldExpr>>,
 }
 
 impl From<IndexJoin> for QueryExpr {
@@ -567,6 +1603,12 @@ impl From<IndexJoin> for QueryExpr {
 }
 
 impl IndexJoin {
+    /// Returns the fields of the `index_side` which are joined with the `probe_fields`.
+    fn index_fields(&self) -> impl '_ + Iterator<Item = FieldName> {
+        let head = self.index_side.head();
+        self.index_cols.iter().map(|col| head.fields[col.idx()].field)
+    }
+
     // Reorder the index and probe sides of an index join.
     // This is necessary if the indexed table has been replaced by a delta table.
     // A delta table is a virtual table consisting of changes or updates to a physical table.
@@ -575,13 +1617,26 @@ impl IndexJoin {
         if self.probe_side.source.is_mem_table() {
             return self;
         }
-        // It must have an index defined on the join field.
-        if !self
-            .probe_side
-            .source
-            .head()
-            .has_constraint(self.probe_field, Constraints::indexed())
-        {
+        // The compiler ensures the following unwrap is safe.
+        // The existence of these columns has already been verified,
+        // during construction of the index join.
+        let probe_head = self.probe_side.source.head();
+        let mut keys = self
+            .probe_fields
+            .iter()
+            .map(|field| probe_head.column_pos(*field).unwrap())
+            .zip(self.index_fields())
+            .collect::<SmallVec<[_; 2]>>();
+        // The new index columns are listed in ascending order, so order the new probe fields likewise.
+        keys.sort_unstable_by_key(|(col, _)| *col);
+        let probe_cols = keys
+            .iter()
+            .map(|(col, _)| *col)
+            .collect::<ColListBuilder>()
+            .build()
+            .unwrap();
+        // It must have an index defined on the join fields.
+        if !is_indexed_on(probe_head, &probe_cols) {
             return self;
         }
         // It must be a linear pipeline of selections.
@@ -589,26 +1644,27 @@ impl IndexJoin {
             .probe_side
             .query
             .iter()
-            .all(|op| matches!(op, Query::Select(_)) || matches!(op, Query::IndexScan(_)))
+            .all(|op| matches!(op, Query::Select(_) | Query::IndexScan(_) | Query::IndexMultiScan(_)))
         {
             return self;
         }
-        // The compiler ensures the following unwrap is safe.
-        // The existence of this column has already been verified,
-        // during construction of the index join.
-        let probe_column = self.probe_side.source.head().column_pos(self.probe_field).unwrap();
         match self.index_side.get_db_table() {
             // If the size of the indexed table is sufficiently large,
             // do not reorder.
             //
             // TODO: This determination is quite arbitrary.
-            // Ultimately we should be using cardinality estimation.
+            // Ultimately we should be using cardinality estimation,
+            // e.g., comparing the `QueryExpr::estimated_output_rows` of both sides.
             Some(DbTable { head, table_id, .. }) if row_count(*table_id, &head.table_name) > 500 => self,
+            // Likewise if its column statistics show it is large,
+            // as a table has at least as many rows as distinct values in any column.
+            Some(DbTable {
+                column_stats: Some(stats),
+                ..
+            }) if stats.values().filter_map(|s| s.distinct_count).any(|count| count > 500) => self,
             // If this is a delta table, we must reorder.
             // If this is a sufficiently small physical table, we should reorder.
             _ => {
-                // For the same reason the compiler also ensures this unwrap is safe.
-                let index_field = self.index_side.head().fields[self.index_col.idx()].field;
                 // Merge all selections from the original probe side into a single predicate.
                 // This includes an index scan if present.
                 let predicate = self
@@ -630,17 +1686,19 @@ impl IndexJoin {
                     // The new probe side consists of the updated rows.
                     // Plus any selections from the original index probe.
                     probe_side,
-                    // The new probe field is the previous index field.
-                    probe_field: index_field,
+                    // The new probe fields are the previous index fields.
+                    probe_fields: keys.into_iter().map(|(_, field)| field).collect(),
                     // The original probe table is now the table that is being probed.
                     index_side: self.probe_side.source,
                     // Any selections from the original probe side are pulled above the index lookup.
                     index_select: predicate,
-                    // The new index field is the previous probe field.
-                    index_col: probe_column,
+                    // The new index columns are the previous probe fields.
+                    index_cols: probe_cols,
                     // Because we have swapped the original index and probe sides of the join,
                     // the new index join needs to return rows from the opposite side.
                     return_index_rows: !self.return_index_rows,
+                    // The returned rows are still those of the same table, so the projection applies as is.
+                    projection: self.projection,
                 }
             }
         }
@@ -650,23 +1708,25 @@ impl IndexJoin {
     // This is needed for incremental evaluation of index joins.
     // In particular when there are updates to both the left and right tables.
     // In other words, when an index join has two delta tables.
+    //
+    // A join on multiple columns becomes an inner join on the first of them,
+    // followed by a filter on the rest and a projection of the returned side.
+    // A fused projection becomes a projection following the join.
     pub fn to_inner_join(self) -> QueryExpr {
-        let col_idx = self.index_side.head().fields[self.index_col.idx()].field;
+        let keys = self
+            .index_fields()
+            .zip(self.probe_fields.iter().copied())
+            .collect::<SmallVec<[_; 2]>>();
 
-        if self.return_index_rows {
-            let (col_lhs, col_rhs) = (col_idx, self.probe_field);
+        let projection = self.projection;
+        let mut expr = if self.return_index_rows {
             let rhs = self.probe_side;
 
             let source = self.index_side;
-            let inner_join = Query::JoinInner(JoinExpr::new(rhs, col_lhs, col_rhs, true));
-            let query = if let Some(predicate) = self.index_select {
-                vec![predicate.into(), inner_join]
-            } else {
-                vec![inner_join]
-            };
+            let mut query = Vec::from_iter(self.index_select.map(Query::from));
+            query.extend(Self::inner_join_on(&source, rhs, &keys));
             QueryExpr { source, query }
         } else {
-            let (col_lhs, col_rhs) = (self.probe_field, col_idx);
             let mut rhs: QueryExpr = self.index_side.into();
 
             if let Some(predicate) = self.index_select {
@@ -674,10 +1734,85 @@ impl IndexJoin {
             }
 
             let source = self.probe_side.source;
-            let inner_join = Query::JoinInner(JoinExpr::new(rhs, col_lhs, col_rhs, true));
-            let query = vec![inner_join];
+            let keys = keys
+                .iter()
+                .map(|(index_field, probe_field)| (*probe_field, *index_field));
+            let query = Self::inner_join_on(&source, rhs, &keys.collect::<SmallVec<[_; 2]>>());
             QueryExpr { source, query }
+        };
+        if let Some(cols) = projection {
+            expr.query.push(Query::Project(
+                cols.into_iter().map(|col| (col, None)).collect(),
+                None,
+                false,
+            ));
         }
+        expr
+    }
+
+    /// Returns the plans that evaluate this join incrementally,
+    /// i.e., over delta tables of the rows changed on its index and probe sides, in this order:
+    ///
+    /// 1. The delta of the index side joined with the probe side,
+    ///    reordered so that the latter is probed, as a delta table has no indexes.
+    /// 2. The index side joined with the delta of the probe side.
+    /// 3. The deltas of both sides, joined by an inner join, as neither has indexes.
+    ///
+    /// The first two plans read their delta from [`SourceId(0)`](SourceId).
+    /// The last reads the delta of the index side from `SourceId(0)`
+    /// and that of the probe side from `SourceId(1)`.
+    pub fn incremental_plans(self) -> Vec<QueryExpr> {
+        let delta = |source: &SourceExpr, id| {
+            SourceExpr::from_mem_table(source.head().clone(), source.table_access(), 0, SourceId(id))
+        };
+
+        let index_delta = IndexJoin {
+            index_side: delta(&self.index_side, 0),
+            ..self.clone()
+        };
+        let mut probe_delta = self.clone();
+        probe_delta.probe_side.source = delta(&self.probe_side.source, 0);
+        let mut both_deltas = self;
+        both_deltas.index_side = delta(&both_deltas.index_side, 0);
+        both_deltas.probe_side.source = delta(&both_deltas.probe_side.source, 1);
+
+        vec![
+            index_delta.reorder(|_, _| 0).into(),
+            probe_delta.into(),
+            both_deltas.to_inner_join(),
+        ]
+    }
+
+    /// Returns a semijoin of `source` with `rhs` on the `(lhs field, rhs field)` pairs of `keys`.
+    fn inner_join_on(source: &SourceExpr, rhs: QueryExpr, keys: &[(FieldName, FieldName)]) -> Vec<Query> {
+        let [(col_lhs, col_rhs), rest @ ..] = keys else {
+            unreachable!("an index join is on at least one column")
+        };
+        if rest.is_empty() {
+            return vec![Query::JoinInner(JoinExpr::new(rhs, *col_lhs, *col_rhs, JoinKind::Semi))];
+        }
+
+        // A semijoin can only compare a single pair of columns,
+        // so join on the first and filter on the rest, before projecting the columns of `source`.
+        let eq = |(lhs, rhs): &(FieldName, FieldName)| {
+            ColumnOp::new(
+                OpQuery::Cmp(OpCmp::Eq),
+                ColumnOp::Field((*lhs).into()),
+                ColumnOp::Field((*rhs).into()),
+            )
+        };
+        let filter = rest.iter().map(eq).reduce(ColumnOp::and).unwrap();
+        let cols = source
+            .head()
+            .fields
+            .iter()
+            .map(|col| (FieldExpr::Name(col.field), None))
+            .collect::<Vec<_>>();
+        vec![
+            Query::JoinInner(JoinExpr::new(rhs, *col_lhs, *col_rhs, JoinKind::Inner)),
+            Query::Select(filter),
+            Query::Project(cols, source.table_id(), false),
+        ]
     }
 }
 
@@ -686,35 +1821,97 @@ pub struct JoinExpr {
     pub rhs: QueryExpr,
     pub col_lhs: FieldName,
     pub col_rhs: FieldName,
-    /// If true, this is a left semi-join, returning rows only from the source table,
-    /// using the `rhs` as a filter.
-    ///
-    /// If false, this is an inner join, returning the concatenation of the matching rows.
-    pub semi: bool,
+    pub kind: JoinKind,
 }
 
 impl JoinExpr {
-    pub fn new(rhs: QueryExpr, col_lhs: FieldName, col_rhs: FieldName, semi: bool) -> Self {
+    pub fn new(rhs: QueryExpr, col_lhs: FieldName, col_rhs: FieldName, kind: JoinKind) -> Self {
         Self {
             rhs,
             col_lhs,
             col_rhs,
-            semi,
+            kind,
         }
     }
 }
 
-#[derive(Debug, Clone, Copy, Eq, PartialEq)]
-pub enum DbType {
-    Table,
-    Index,
-    Sequence,
-    Constraint,
+/// Which rows a [`JoinExpr`] returns.
+#[derive(Debug, Clone, Copy, Eq, PartialEq, Hash)]
+pub enum JoinKind {
+    /// An inner join, returning the concatenation of the matching rows.
+    Inner,
+    /// A left semi-join, returning rows only from the source table,
+    /// using the `rhs` as a filter.
+    /// Each row of the source table is returned at most once,
+    /// however many rows of the `rhs` it matches.
+    Semi,
+    /// A left anti-join, returning the rows of the source table that match no row of the `rhs`,
+    /// each exactly once.
+    /// This is the plan of `NOT EXISTS`.
+    Anti,
+    /// A left outer join, returning the concatenation of the matching rows,
+    /// and each row of the source table that matches no row of the `rhs`
+    /// with `None` for the columns of the `rhs`, see [`Query::JoinLeft`].
+    Left,
 }
 
-#[derive(Debug, Clone, Copy, Eq, PartialEq)]
-pub enum Crud {
-    Query,
+impl JoinKind {
+    /// Returns whether a join of this kind returns rows only from the source table.
+    pub fn returns_lhs_only(self) -> bool {
+        matches!(self, Self::Semi | Self::Anti)
+    }
+}
+
+impl fmt::Display for JoinKind {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Self::Inner => write!(f, "inner"),
+            Self::Semi => write!(f, "semi"),
+            Self::Anti => write!(f, "anti"),
+            Self::Left => write!(f, "left"),
+        }
+    }
+}
+
+/// A join of two tables on `col_lhs = col_rhs`,
+/// where each table has an index on exactly its join column.
+///
+/// Both tables are read in the order of their index and advanced in lockstep,
+/// so neither hashing nor nested looping is needed.
+/// Must be the first operation of a query, as it reads `lhs` itself.
+#[derive(Debug, Clone, Eq, PartialEq, Hash)]
+pub struct MergeJoinExpr {
+    pub lhs: DbTable,
+    pub rhs: DbTable,
+    pub col_lhs: FieldName,
+    pub col_rhs: FieldName,
+    /// If true, this is a left semi-join, returning rows only from `lhs`.
+    ///
+    /// If false, this is an inner join, returning the concatenation of the matching rows.
+    pub semi: bool,
+}
+
+/// Expands each element of an array column into its own row.
+#[derive(Debug, Clone, Copy, Eq, PartialEq, Hash)]
+pub struct UnnestExpr {
+    /// The array column.
+    pub field: FieldName,
+    /// Whether an empty array yields a single row with a `None` element,
+    /// rather than no rows at all.
+    pub outer: bool,
+}
+
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+pub enum DbType {
+    Table,
+    Index,
+    Sequence,
+    Constraint,
+}
+
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+pub enum Crud {
+    Query,
     Insert,
     Update,
     Delete,
@@ -732,10 +1929,14 @@ pub enum CrudExpr {
     },
     Update {
         delete: QueryExpr,
-        assignments: HashMap<FieldName, FieldExpr>,
+        assignments: HashMap<FieldName, AssignExpr>,
+        /// If set, the update fails, rather than affecting more rows than this.
+        expected_max_rows: Option<u64>,
     },
     Delete {
         query: QueryExpr,
+        /// If set, the delete fails, rather than affecting more rows than this.
+        expected_max_rows: Option<u64>,
     },
     CreateTable {
         table: TableDef,
@@ -745,23 +1946,202 @@ pub enum CrudExpr {
         kind: DbType,
         table_access: StAccess,
     },
+    /// Sets the system variable `name`, see [`CrudExpr::set_var`].
+    ///
+    /// The name and value are only checked on execution,
+    /// so that variables unknown to [`SystemVar`] can still be set.
     SetVar {
         name: String,
         value: AlgebraicValue,
     },
+    /// Reads the system variable `name`, see [`CrudExpr::read_var`].
     ReadVar {
         name: String,
     },
 }
 
+/// A known system variable, i.e., a runtime setting of the database
+/// read by [`CrudExpr::ReadVar`] and set by [`CrudExpr::SetVar`].
+#[derive(Debug, Clone, Copy, Eq, PartialEq, Hash)]
+pub enum SystemVar {
+    /// The duration in milliseconds after which an ad hoc query is logged as slow.
+    SlowAdHocQueryMs,
+    /// The duration in milliseconds after which an incremental update of subscriptions is logged as slow.
+    SlowTxUpdateMs,
+    /// The duration in milliseconds after which a subscription query is logged as slow.
+    SlowSubscriptionQueryMs,
+}
+
+impl SystemVar {
+    /// Returns the name of the variable, as used in `SET` and `SHOW`.
+    pub fn name(self) -> &'static str {
+        match self {
+            Self::SlowAdHocQueryMs => "slow_ad_hoc_query_ms",
+            Self::SlowTxUpdateMs => "slow_tx_update_ms",
+            Self::SlowSubscriptionQueryMs => "slow_subscription_query_ms",
+        }
+    }
+
+    /// Returns the type of the values the variable holds.
+    pub fn type_of(self) -> AlgebraicType {
+        match self {
+            Self::SlowAdHocQueryMs | Self::SlowTxUpdateMs | Self::SlowSubscriptionQueryMs => AlgebraicType::U64,
+        }
+    }
+}
+
+impl fmt::Display for SystemVar {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.name())
+    }
+}
+
+impl FromStr for SystemVar {
+    type Err = ConfigError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "slow_ad_hoc_query_ms" => Ok(Self::SlowAdHocQueryMs),
+            "slow_tx_update_ms" => Ok(Self::SlowTxUpdateMs),
+            "slow_subscription_query_ms" => Ok(Self::SlowSubscriptionQueryMs),
+            x => Err(ConfigError::NotFound(x.into())),
+        }
+    }
+}
+
+/// The value assigned to a column by a [`CrudExpr::Update`],
+/// computed from the row being updated, e.g., `balance - 10`.
+#[derive(Debug, Clone, Eq, PartialEq, From)]
+pub enum AssignExpr {
+    /// A field of the row being updated, or a literal.
+    #[from]
+    Field(FieldExpr),
+    /// `lhs op rhs`, where both operands are numbers of the same type.
+    Math {
+        op: OpMath,
+        lhs: Box<AssignExpr>,
+        rhs: Box<AssignExpr>,
+    },
+}
+
+impl AssignExpr {
+    pub fn math(op: OpMath, lhs: impl Into<AssignExpr>, rhs: impl Into<AssignExpr>) -> Self {
+        Self::Math {
+            op,
+            lhs: Box::new(lhs.into()),
+            rhs: Box::new(rhs.into()),
+        }
+    }
+
+    /// Evaluates `self` against the `row` being updated, which has the `header`.
+    ///
+    /// Fails if an operation overflows, divides by zero,
+    /// or is applied to operands that aren't numbers of the same type.
+    pub fn eval(&self, row: &RelValue<'_>, header: &Header) -> Result<AlgebraicValue, ErrorVm> {
+        match self {
+            AssignExpr::Field(field) => Ok(row.get(field.borrowed(), header)?.into_owned()),
+            AssignExpr::Math { op, lhs, rhs } => {
+                let lhs = lhs.eval(row, header)?;
+                let rhs = rhs.eval(row, header)?;
+                eval_math(*op, &lhs, &rhs).ok_or(ErrorVm::Math { op: *op, lhs, rhs })
+            }
+        }
+    }
+}
+
+/// Returns `lhs op rhs`, or `None` if the operands aren't numbers of the same type,
+/// or if the operation overflows or divides by zero.
+fn eval_math(op: OpMath, lhs: &AlgebraicValue, rhs: &AlgebraicValue) -> Option<AlgebraicValue> {
+    macro_rules! int {
+        ($lhs:expr, $rhs:expr) => {
+            match op {
+                OpMath::Add => $lhs.checked_add($rhs),
+                OpMath::Minus => $lhs.checked_sub($rhs),
+                OpMath::Mul => $lhs.checked_mul($rhs),
+                OpMath::Div => $lhs.checked_div($rhs),
+            }
+            .map(Into::into)
+        };
+    }
+    macro_rules! float {
+        ($lhs:expr, $rhs:expr) => {
+            Some(
+                match op {
+                    OpMath::Add => $lhs + $rhs,
+                    OpMath::Minus => $lhs - $rhs,
+                    OpMath::Mul => $lhs * $rhs,
+                    OpMath::Div => $lhs / $rhs,
+                }
+                .into(),
+            )
+        };
+    }
+
+    match (lhs, rhs) {
+        (AlgebraicValue::I8(lhs), AlgebraicValue::I8(rhs)) => int!(lhs, *rhs),
+        (AlgebraicValue::U8(lhs), AlgebraicValue::U8(rhs)) => int!(lhs, *rhs),
+        (AlgebraicValue::I16(lhs), AlgebraicValue::I16(rhs)) => int!(lhs, *rhs),
+        (AlgebraicValue::U16(lhs), AlgebraicValue::U16(rhs)) => int!(lhs, *rhs),
+        (AlgebraicValue::I32(lhs), AlgebraicValue::I32(rhs)) => int!(lhs, *rhs),
+        (AlgebraicValue::U32(lhs), AlgebraicValue::U32(rhs)) => int!(lhs, *rhs),
+        (AlgebraicValue::I64(lhs), AlgebraicValue::I64(rhs)) => int!(lhs, *rhs),
+        (AlgebraicValue::U64(lhs), AlgebraicValue::U64(rhs)) => int!(lhs, *rhs),
+        // Copy the values out of their `Packed` wrappers, as these can't be borrowed.
+        (AlgebraicValue::I128(lhs), AlgebraicValue::I128(rhs)) => {
+            let (lhs, rhs) = (lhs.0, rhs.0);
+            int!(lhs, rhs)
+        }
+        (AlgebraicValue::U128(lhs), AlgebraicValue::U128(rhs)) => {
+            let (lhs, rhs) = (lhs.0, rhs.0);
+            int!(lhs, rhs)
+        }
+        (AlgebraicValue::F32(lhs), AlgebraicValue::F32(rhs)) => float!(*lhs, *rhs),
+        (AlgebraicValue::F64(lhs), AlgebraicValue::F64(rhs)) => float!(*lhs, *rhs),
+        _ => None,
+    }
+}
+
 impl CrudExpr {
+    /// Returns a [`CrudExpr::SetVar`] setting `var` to `value`,
+    /// or an error if `value` isn't of the type of `var`.
+    pub fn set_var(var: SystemVar, value: AlgebraicValue) -> Result<Self, ErrorVm> {
+        let ty = var.type_of();
+        if value.type_of().as_ref() != Some(&ty) {
+            return Err(ConfigError::TypeError(var.name().into(), value, ty).into());
+        }
+        Ok(CrudExpr::SetVar {
+            name: var.name().into(),
+            value,
+        })
+    }
+
+    /// Returns a [`CrudExpr::ReadVar`] reading `var`.
+    pub fn read_var(var: SystemVar) -> Self {
+        CrudExpr::ReadVar {
+            name: var.name().into(),
+        }
+    }
+
     pub fn optimize(self, row_count: &impl Fn(TableId, &str) -> i64) -> Self {
         match self {
             CrudExpr::Query(x) => CrudExpr::Query(x.optimize(row_count)),
+            // Any `expected_max_rows` of an `Update` or `Delete`,
+            // and the `assignments` of an `Update`, are passed through unchanged.
             _ => self,
         }
     }
 
+    /// Guards an [`CrudExpr::Update`] or [`CrudExpr::Delete`]
+    /// against affecting more than `max_rows` rows.
+    ///
+    /// Has no effect on other kinds of expressions.
+    pub fn with_expected_max_rows(mut self, max_rows: u64) -> Self {
+        if let CrudExpr::Update { expected_max_rows, .. } | CrudExpr::Delete { expected_max_rows, .. } = &mut self {
+            *expected_max_rows = Some(max_rows);
+        }
+        self
+    }
+
     pub fn is_reads<'a>(exprs: impl IntoIterator<Item = &'a CrudExpr>) -> bool {
         exprs
             .into_iter()
@@ -774,6 +2154,88 @@ pub struct IndexScan {
     pub table: DbTable,
     pub columns: ColList,
     pub bounds: (Bound<AlgebraicValue>, Bound<AlgebraicValue>),
+    /// Whether the rest of the query reads only the columns of the index,
+    /// in which case the scan is answered from the keys of the index alone, without fetching each row.
+    /// Such a scan may then yield rows of the columns of the index only.
+    ///
+    /// Set by [`QueryExpr::optimize`]; a scan built otherwise is not covering.
+    pub covering: bool,
+}
+
+impl IndexScan {
+    /// Returns a scan of the rows of `table` within `bounds` on the index on exactly `columns`.
+    ///
+    /// Returns an error if `table` has no such index,
+    /// or if a bound of a multi-column index is not a product of one value per column.
+    pub fn try_new(
+        table: DbTable,
+        columns: ColList,
+        bounds: (Bound<AlgebraicValue>, Bound<AlgebraicValue>),
+    ) -> Result<Self, ErrorVm> {
+        if !is_index(&table.head, &columns) {
+            return Err(ErrorVm::NoSuchIndex {
+                table: table.head.table_name.clone(),
+                columns,
+            });
+        }
+        if !columns.is_singleton() {
+            let arity = columns.len() as usize;
+            let mismatch = [&bounds.0, &bounds.1].into_iter().find_map(|bound| match bound {
+                Bound::Included(key) | Bound::Excluded(key) => match key {
+                    AlgebraicValue::Product(key) if key.elements.len() == arity => None,
+                    key => Some(key.clone()),
+                },
+                Bound::Unbounded => None,
+            });
+            if let Some(key) = mismatch {
+                return Err(ErrorVm::IndexKeyArity {
+                    table: table.head.table_name.clone(),
+                    columns,
+                    key,
+                });
+            }
+        }
+
+        Ok(Self {
+            table,
+            columns,
+            bounds,
+            covering: false,
+        })
+    }
+
+    /// Returns whether the scan is unbounded on both sides,
+    /// in which case it filters nothing and is better served by a sequential scan of the table.
+    pub fn is_full_range(&self) -> bool {
+        matches!(self.bounds, (Bound::Unbounded, Bound::Unbounded))
+    }
+
+    /// Returns whether the scan seeks a single key.
+    fn is_point(&self) -> bool {
+        matches!(&self.bounds, (Bound::Included(lower), Bound::Included(upper)) if lower == upper)
+    }
+
+    /// Returns whether no key satisfies the bounds of the scan,
+    /// because the lower bound is greater than the upper bound, or both bounds are excluded and equal.
+    pub fn is_never(&self) -> bool {
+        match &self.bounds {
+            (Bound::Excluded(lower), Bound::Excluded(upper)) => lower >= upper,
+            (Bound::Included(lower), Bound::Excluded(upper))
+            | (Bound::Excluded(lower), Bound::Included(upper))
+            | (Bound::Included(lower), Bound::Included(upper)) => lower > upper,
+            _ => false,
+        }
+    }
+}
+
+/// Fetches the rows of `table` within any of the `ranges` of the index on `columns`,
+/// e.g., one point range per value of an IN-list.
+/// The ranges are expected not to overlap, so that no row is fetched twice.
+#[derive(Debug, Clone, Eq, PartialEq, Hash)]
+pub struct IndexMultiScan {
+    pub table: DbTable,
+    pub columns: ColList,
+    pub ranges: Vec<(Bound<AlgebraicValue>, Bound<AlgebraicValue>)>,
 }
 
 // An individual operation in a query.
@@ -781,21 +2243,48 @@ pub struct IndexScan {
 pub enum Query {
     // Fetching rows via an index.
     IndexScan(IndexScan),
+    // Fetching rows via several ranges of an index.
+    IndexMultiScan(IndexMultiScan),
     // Joining rows via an index.
     // Equivalent to Index Nested Loop Join.
     IndexJoin(IndexJoin),
+    // Joining the rows of two tables, both read in the order of an index on the join key.
+    // Equivalent to a Merge Join.
+    MergeJoin(MergeJoinExpr),
     // A filter over an intermediate relation.
     // In particular it does not utilize any indexes.
     // If it could it would have already been transformed into an IndexScan.
     Select(ColumnOp),
-    // Projects a set of columns.
+    // Projects a set of columns,
+    // each named by its alias, if any, in the resulting header.
     // The second argument is the table id for a qualified wildcard project.
     // If present, further optimizations are possible.
-    Project(Vec<FieldExpr>, Option<TableId>),
+    // The third argument is whether to deduplicate the projected rows, i.e., `SELECT DISTINCT`.
+    // A distinct projection holds every distinct row it has yielded in a hash set,
+    // so its memory grows with the size of its output.
+    Project(Vec<(FieldExpr, Option<Box<str>>)>, Option<TableId>, bool),
     // A join of two relations (base or intermediate) based on equality.
     // Equivalent to a Nested Loop Join.
     // Its operands my use indexes but the join itself does not.
     JoinInner(JoinExpr),
+    // A left outer join of two relations based on equality,
+    // which also returns the rows of the lhs without a match,
+    // padded with the default value of each column of the rhs.
+    // The `kind` of the `JoinExpr` is ignored.
+    JoinLeft(JoinExpr),
+    // A semijoin with the in-memory `source` of a single column,
+    // returning the rows whose `probe_field` equals a value of `source`.
+    // The values of `source` are hashed, so each row is probed in constant time.
+    SemiJoinInMemory { source: SourceExpr, probe_field: FieldName },
+    // Expands each element of an array column into its own row.
+    Unnest(UnnestExpr),
+    // Skips the first `offset` rows and yields at most `limit` of the rest.
+    // Stops reading its input once `limit` rows have been yielded.
+    Limit { limit: u64, offset: u64 },
+    // Yields no rows, without reading its input.
+    // Replaces operators which are known to never return any rows,
+    // e.g., an index scan with unsatisfiable bounds.
+    NeverReturns,
 }
 
 impl Query {
@@ -804,10 +2293,15 @@ impl Query {
     /// Sources are yielded from left to right. Duplicates are not filtered out.
     pub fn sources(&self) -> QuerySources {
         match self {
-            Self::Select(..) | Self::Project(..) => QuerySources::None,
+            Self::Select(..) | Self::Project(..) | Self::Unnest(..) | Self::Limit { .. } | Self::NeverReturns => {
+                QuerySources::None
+            }
             Self::IndexScan(scan) => QuerySources::One(Some(SourceExpr::DbTable(scan.table.clone()))),
+            Self::IndexMultiScan(scan) => QuerySources::One(Some(SourceExpr::DbTable(scan.table.clone()))),
             Self::IndexJoin(join) => QuerySources::Expr(join.probe_side.sources()),
-            Self::JoinInner(join) => QuerySources::Expr(join.rhs.sources()),
+            Self::JoinInner(join) | Self::JoinLeft(join) => QuerySources::Expr(join.rhs.sources()),
+            Self::SemiJoinInMemory { source, .. } => QuerySources::One(Some(source.clone())),
+            Self::MergeJoin(join) => QuerySources::One(Some(SourceExpr::DbTable(join.rhs.clone()))),
         }
     }
 }
@@ -830,6 +2324,25 @@ enum IndexArgument<'a> {
         value: AlgebraicValue,
         inclusive: bool,
     },
+    /// An inclusive range `lower..=upper`.
+    Range {
+        columns: &'a ColList,
+        lower: AlgebraicValue,
+        upper: AlgebraicValue,
+    },
+    /// One equality seek per value.
+    In {
+        columns: &'a ColList,
+        values: Vec<AlgebraicValue>,
+    },
+    /// The ranges `< value` and `> value`,
+    /// for an inequality on a low-cardinality column.
+    NotEq {
+        columns: &'a ColList,
+        value: AlgebraicValue,
+    },
+    /// An equality on `columns`, the leading columns of a multi-column index.
+    PrefixEq { columns: ColList, value: AlgebraicValue },
 }
 
 #[derive(Debug, PartialEq, Clone)]
@@ -842,6 +2355,9 @@ fn make_index_arg(cmp: OpCmp, columns: &ColList, value: AlgebraicValue) -> Index
     let arg = match cmp {
         OpCmp::Eq => IndexArgument::Eq { columns, value },
         OpCmp::NotEq => unreachable!("No IndexArgument for NotEq, caller should've filtered out"),
+        OpCmp::IsDistinctFrom | OpCmp::IsNotDistinctFrom | OpCmp::EqIgnoreCase => {
+            unreachable!("No IndexArgument for `{cmp}`, caller should've filtered out")
+        }
         // a < 5 => exclusive upper bound
         OpCmp::Lt => IndexArgument::UpperBound {
             columns,
@@ -870,6 +2386,67 @@ fn make_index_arg(cmp: OpCmp, columns: &ColList, value: AlgebraicValue) -> Index
     IndexColumnOp::Index(arg)
 }
 
+/// Returns the index argument for `columns IN values`.
+///
+/// When the values are contiguous integers, e.g., `IN (5, 6, 7, 8)`,
+/// they are served by the single range `5..=8` rather than by four seeks.
+fn make_in_index_arg<'a>(columns: &'a ColList, values: &[AlgebraicValue]) -> IndexColumnOp<'a> {
+    let mut values = values.to_vec();
+    values.sort_unstable();
+    values.dedup();
+
+    let arg = if let [value] = &*values {
+        IndexArgument::Eq {
+            columns,
+            value: value.clone(),
+        }
+    } else if let Some((lower, upper)) = contiguous_range(&values) {
+        IndexArgument::Range {
+            columns,
+            lower: lower.clone(),
+            upper: upper.clone(),
+        }
+    } else {
+        IndexArgument::In { columns, values }
+    };
+    IndexColumnOp::Index(arg)
+}
+
+/// Returns the first and last of the sorted, deduplicated `values`
+/// if there are at least two values and there is no integer of the same type between any two of them.
+///
+/// Values that are not integers, or that are integers of different types, are never contiguous.
+fn contiguous_range(values: &[AlgebraicValue]) -> Option<(&AlgebraicValue, &AlgebraicValue)> {
+    let [first, .., last] = values else {
+        return None;
+    };
+    values
+        .windows(2)
+        .all(|pair| int_successor(&pair[0]).as_ref() == Some(&pair[1]))
+        .then_some((first, last))
+}
+
+/// Returns the integer following `value` in its type.
+///
+/// Returns `None` if `value` is not an integer
+/// or if it is the maximum of its type, as contiguity does not wrap around.
+/// For example, the `u8`s `255` and `0` are not contiguous.
+fn int_successor(value: &AlgebraicValue) -> Option<AlgebraicValue> {
+    Some(match value {
+        AlgebraicValue::I8(x) => AlgebraicValue::I8(x.checked_add(1)?),
+        AlgebraicValue::U8(x) => AlgebraicValue::U8(x.checked_add(1)?),
+        AlgebraicValue::I16(x) => AlgebraicValue::I16(x.checked_add(1)?),
+        AlgebraicValue::U16(x) => AlgebraicValue::U16(x.checked_add(1)?),
+        AlgebraicValue::I32(x) => AlgebraicValue::I32(x.checked_add(1)?),
+        AlgebraicValue::U32(x) => AlgebraicValue::U32(x.checked_add(1)?),
+        AlgebraicValue::I64(x) => AlgebraicValue::I64(x.checked_add(1)?),
+        AlgebraicValue::U64(x) => AlgebraicValue::U64(x.checked_add(1)?),
+        AlgebraicValue::I128(x) => x.0.checked_add(1)?.into(),
+        AlgebraicValue::U128(x) => x.0.checked_add(1)?.into(),
+        _ => return None,
+    })
+}
+
 #[derive(Debug)]
 struct FieldValue<'a> {
     parent: &'a ColumnOp,
@@ -889,9 +2466,35 @@ impl<'a> FieldValue<'a> {
     }
 }
 
+/// A `field IN (values)` constraint extracted by [`extract_fields`].
+#[derive(Debug)]
+struct InList<'a> {
+    parent: &'a ColumnOp,
+    col: ColId,
+    values: &'a [AlgebraicValue],
+}
+
+/// A `(fields...) IN (values...)` constraint extracted by [`extract_fields`].
+#[derive(Debug)]
+struct TupleInList<'a> {
+    parent: &'a ColumnOp,
+    /// The columns of the fields, in order.
+    cols: SmallVec<[ColId; 2]>,
+    values: &'a [AlgebraicValue],
+}
+
 type IndexColumnOpSink<'a> = SmallVec<[IndexColumnOp<'a>; 1]>;
 type FieldsIndexed = HashSet<(FieldName, OpCmp)>;
 
+/// The maximum estimated number of distinct values of a column
+/// for which an inequality on it is served by the ranges on either side of the excluded value,
+/// rather than by a scan.
+const NOT_EQ_MAX_DISTINCT: u64 = 16;
+
+/// How many times more distinct values a column must have than another
+/// before an equality seek on the latter is demoted to a residual filter.
+const EQ_SEEK_DEMOTION_FACTOR: u64 = 10;
+
 /// Pick the best indices that can serve the constraints in `fields`
 /// where the indices are taken from `header`.
 ///
@@ -946,12 +2549,41 @@ type FieldsIndexed = HashSet<(FieldName, OpCmp)>;
 /// we would generate a single `IndexScan((age, height) > (18, 180))`.
 /// However, and depending on the table data, this might not be efficient,
 /// whereas `age = 18 AND height > 180` might.
-/// TODO: Revisit this to see if we want to restrict this or use statistics.
+/// So when `stats` has a distinct-count hint for `age` and there's an index on `age` alone,
+/// we instead use that for `age > 18` and scan for `height > 180`
+/// if that is estimated to read fewer rows; see [`prefer_leading_column_range`].
+///
+/// A partial index in `header.partial_indexes` is only considered
+/// when one of the constraints in `ops` implies each range of its predicate;
+/// see [`implies_partial_predicate`].
+///
+/// When `stats` has distinct-count hints for several columns
+/// that are each constrained by an equality served by a single-column index,
+/// only the far more selective seeks are kept;
+/// see [`demote_unselective_eq_seeks`].
 fn select_best_index<'a>(
     fields_indexed: &mut FieldsIndexed,
     header: &'a Header,
+    stats: &ColumnStatsMap,
     ops: &[&'a ColumnOp],
 ) -> IndexColumnOpSink<'a> {
+    let mut found: IndexColumnOpSink = IndexColumnOpSink::new();
+
+    // Collect fields into a multi-map `(col_id, cmp) -> [field]`.
+    // This gives us `log(N)` seek + deletion.
+    // TODO(Centril): Consider https://docs.rs/small-map/0.1.3/small_map/enum.SmallMap.html
+    let mut fields_map = BTreeMap::<_, SmallVec<[_; 1]>>::new();
+    let mut in_lists = SmallVec::<[_; 1]>::new();
+    let mut tuple_in_lists = SmallVec::<[_; 1]>::new();
+    extract_fields(
+        ops,
+        header,
+        &mut fields_map,
+        &mut in_lists,
+        &mut tuple_in_lists,
+        &mut found,
+    );
+
     // Collect and sort indices by their lengths, with longest first.
     // We do this so that multi-col indices are used first, as they are more efficient.
     // TODO(Centril): This could be computed when `Header` is constructed.
@@ -960,22 +2592,30 @@ fn select_best_index<'a>(
         .iter()
         .filter(|(_, c)| c.has_indexed())
         .map(|(cl, _)| cl)
+        // Partial indices can only serve the query if it implies their predicate.
+        .chain(
+            header
+                .partial_indexes
+                .iter()
+                .filter(|(_, predicate)| implies_partial_predicate(&fields_map, predicate))
+                .map(|(cl, _)| cl),
+        )
         .collect::<SmallVec<[_; 1]>>();
     indices.sort_unstable_by_key(|cl| Reverse(cl.len()));
 
-    let mut found: IndexColumnOpSink = IndexColumnOpSink::new();
-
-    // Collect fields into a multi-map `(col_id, cmp) -> [field]`.
-    // This gives us `log(N)` seek + deletion.
-    // TODO(Centril): Consider https://docs.rs/small-map/0.1.3/small_map/enum.SmallMap.html
-    let mut fields_map = BTreeMap::<_, SmallVec<[_; 1]>>::new();
-    extract_fields(ops, header, &mut fields_map, &mut found);
+    // Equality seeks on single-column indices,
+    // recorded so that the less selective ones can be demoted to scans afterwards.
+    let mut eq_seeks = SmallVec::<[EqSeek<'a>; 2]>::new();
 
     // Go through each operator and index,
     // consuming all field constraints that can be served by an index.
     //
-    // NOTE: We do not consider `OpCmp::NotEq` at the moment
+    // NOTE: We do not consider `OpCmp::NotEq` here
     // since those are typically not answered using an index.
+    // See below for the exception of low-cardinality columns.
+    // Neither do we consider `OpCmp::IsDistinctFrom`, `OpCmp::IsNotDistinctFrom`, and `OpCmp::EqIgnoreCase`,
+    // which are always served by a scan.
+    // TODO: Serve `OpCmp::EqIgnoreCase` by a case-insensitive index, once there is such a thing.
     for (col_list, cmp) in [OpCmp::Eq, OpCmp::Lt, OpCmp::LtEq, OpCmp::Gt, OpCmp::GtEq]
         .into_iter()
         .flat_map(|cmp| indices.iter().map(move |cl| (*cl, cmp)))
@@ -988,8 +2628,21 @@ fn select_best_index<'a>(
         if col_list.is_singleton() {
             // For a single column index,
             // we want to avoid the `ProductValue` indirection of below.
-            for FieldValue { cmp, value, field, .. } in fields_map.remove(&(col_list.head(), cmp)).into_iter().flatten()
+            for FieldValue {
+                parent,
+                cmp,
+                value,
+                field,
+            } in fields_map.remove(&(col_list.head(), cmp)).into_iter().flatten()
             {
+                if cmp == OpCmp::Eq {
+                    eq_seeks.push(EqSeek {
+                        pos: found.len(),
+                        col: col_list.head(),
+                        field,
+                        parent,
+                    });
+                }
                 found.push(make_index_arg(cmp, col_list, value.clone()));
                 fields_indexed.insert((field, cmp));
             }
@@ -997,6 +2650,8 @@ fn select_best_index<'a>(
             .iter()
             // (2) Ensure that every col has a field.
             .all(|col| fields_map.get(&(col, cmp)).filter(|fs| !fs.is_empty()).is_some())
+            // (3) Unless a range on the leading column alone is estimated to read fewer rows.
+            && !prefer_leading_column_range(&indices, stats, col_list, cmp)
         {
             // We've ensured `col_list ⊆ columns_of(field_map(cmp))`.
             // Construct the value to compare against.
@@ -1025,6 +2680,81 @@ fn select_best_index<'a>(
         }
     }
 
+    demote_unselective_eq_seeks(&mut found, fields_indexed, stats, &eq_seeks);
+
+    // Equalities on only some of the columns of a multi-column index
+    // can still be served by a seek on the index, as long as those columns lead it,
+    // as the index is sorted by its first column, then by its second, and so on.
+    for &col_list in indices.iter().filter(|cl| !cl.is_singleton()) {
+        let prefix = col_list
+            .iter()
+            .take_while(|col| fields_map.get(&(*col, OpCmp::Eq)).filter(|fs| !fs.is_empty()).is_some())
+            .collect::<SmallVec<[_; 2]>>();
+        if prefix.is_empty() {
+            continue;
+        }
+
+        let mut elems = Vec::with_capacity(prefix.len());
+        for &col in &prefix {
+            let Entry::Occupied(mut entry) = fields_map.entry((col, OpCmp::Eq)) else {
+                // We ensured above that the map is occupied for `(col, OpCmp::Eq)`.
+                unreachable!()
+            };
+            let fields = entry.get_mut();
+            let field = fields.pop().unwrap();
+            if fields.is_empty() {
+                entry.remove();
+            }
+            elems.push(field.value.clone());
+            fields_indexed.insert((field.field, OpCmp::Eq));
+        }
+        let value = match elems.len() {
+            1 => elems.pop().unwrap(),
+            _ => AlgebraicValue::product(elems),
+        };
+        let columns = prefix.into_iter().collect::<ColListBuilder>().build().unwrap();
+        found.push(IndexColumnOp::Index(IndexArgument::PrefixEq { columns, value }));
+    }
+
+    // An inequality on a column with few distinct values, e.g., `flag != true`,
+    // can be served by the ranges on either side of the excluded value
+    // of an index on exactly its column.
+    for &col_list in indices.iter().filter(|cl| cl.is_singleton()) {
+        let col = col_list.head();
+        if !fields_map.contains_key(&(col, OpCmp::NotEq)) || !is_low_cardinality(header, stats, col) {
+            continue;
+        }
+        for FieldValue { value, field, .. } in fields_map.remove(&(col, OpCmp::NotEq)).into_iter().flatten() {
+            found.push(IndexColumnOp::Index(IndexArgument::NotEq {
+                columns: col_list,
+                value: value.clone(),
+            }));
+            fields_indexed.insert((field, OpCmp::NotEq));
+        }
+    }
+
+    // An IN-list can only be served by an index on exactly its column.
+    for InList { parent, col, values } in in_lists {
+        match indices.iter().find(|cl| cl.is_singleton() && cl.head() == col) {
+            Some(col_list) => found.push(make_in_index_arg(col_list, values)),
+            None => found.push(IndexColumnOp::Scan(parent)),
+        }
+    }
+
+    // A tuple IN-list can only be served by an index on exactly its columns, in any order,
+    // by a batch of seeks, one per tuple.
+    for TupleInList { parent, cols, values } in tuple_in_lists {
+        let index = indices.iter().find(|cl| {
+            cl.len() as usize == cols.len()
+                && cols.iter().all(|col| cl.contains(*col))
+                && cl.iter().all(|col| cols.contains(&col))
+        });
+        match index.and_then(|col_list| Some((*col_list, reorder_tuples(col_list, &cols, values)?))) {
+            Some((col_list, values)) => found.push(make_in_index_arg(col_list, &values)),
+            None => found.push(IndexColumnOp::Scan(parent)),
+        }
+    }
+
     // The remaining constraints must be served by a scan.
     found.extend(
         fields_map
@@ -1036,53 +2766,367 @@ fn select_best_index<'a>(
     found
 }
 
-/// Extracts `name = val` when `lhs` is a field that exists and `rhs` is a value.
-fn ext_field_val<'a>(
-    header: &'a Header,
-    lhs: &'a ColumnOp,
-    rhs: &'a ColumnOp,
-) -> Option<(ColId, FieldName, &'a AlgebraicValue)> {
-    if let (ColumnOp::Field(FieldExpr::Name(name)), ColumnOp::Field(FieldExpr::Value(val))) = (lhs, rhs) {
-        return header.field_name(*name).map(|(id, col)| (id, col, val));
-    }
-    None
+/// Returns the tuples `values` on the columns `cols`
+/// with their elements reordered to match the index on `col_list`,
+/// as bare values rather than products if that index is on a single column.
+///
+/// Returns `None` if any of `values` is not a product of one element per column.
+fn reorder_tuples(col_list: &ColList, cols: &[ColId], values: &[AlgebraicValue]) -> Option<Vec<AlgebraicValue>> {
+    let positions = col_list
+        .iter()
+        .map(|col| cols.iter().position(|c| *c == col))
+        .collect::<Option<SmallVec<[usize; 2]>>>()?;
+    values
+        .iter()
+        .map(|value| {
+            let elements = &value.as_product()?.elements;
+            if elements.len() != cols.len() {
+                return None;
+            }
+            Some(match &*positions {
+                [pos] => elements[*pos].clone(),
+                positions => AlgebraicValue::product(
+                    positions
+                        .iter()
+                        .map(|pos| elements[*pos].clone())
+                        .collect::<ProductValue>(),
+                ),
+            })
+        })
+        .collect()
 }
 
-/// Extracts `name = val` when `op` is `name = val` and `name` exists.
-fn ext_cmp_field_val<'a>(
-    header: &'a Header,
-    op: &'a ColumnOp,
-) -> Option<(&'a OpCmp, ColId, FieldName, &'a AlgebraicValue)> {
-    match op {
-        ColumnOp::Cmp {
-            op: OpQuery::Cmp(op),
-            lhs,
-            rhs,
-        } => ext_field_val(header, lhs, rhs).map(|(id, f, v)| (op, id, f, v)),
-        _ => None,
+/// Returns whether a range `cmp` on the leading column of the multi-column index `col_list`
+/// should be served by an index on that column alone, rather than by a range on all of `col_list`,
+/// with the constraints on the other columns then served separately.
+///
+/// The range on `col_list` is lexicographic, so both read the rows strictly beyond the bound on the leading column.
+/// Of the rows tied with the bound on the leading column,
+/// the range on `col_list` also reads those beyond the bound on the rest,
+/// whereas the range on the leading column reads all of them if `cmp` is inclusive, and none otherwise.
+/// The cheaper plan is chosen by estimating these ties using the distinct count of the leading column in `stats`.
+/// Without such a hint, or without an index on the leading column in `indices`,
+/// the range on `col_list` is kept.
+fn prefer_leading_column_range(indices: &[&ColList], stats: &ColumnStatsMap, col_list: &ColList, cmp: OpCmp) -> bool {
+    let col = col_list.head();
+    if cmp == OpCmp::Eq || !indices.iter().any(|cl| cl.is_singleton() && cl.head() == col) {
+        return false;
     }
+    let Some(distinct_count) = stats.get(&col).and_then(|stats| stats.distinct_count) else {
+        return false;
+    };
+    let ties = 1.0 / distinct_count.max(1) as f64;
+    let composite = RANGE_SELECTIVITY + ties * RANGE_SELECTIVITY;
+    let leading = RANGE_SELECTIVITY
+        + if matches!(cmp, OpCmp::LtEq | OpCmp::GtEq) {
+            ties
+        } else {
+            0.0
+        };
+    leading < composite
 }
 
-/// Extracts a list of `field = val` constraints that *could* be answered by an index
-/// and populates those into `fields_map`.
-/// The [`ColumnOp`]s that don't fit `field = val`
-/// are made into [`IndexColumnOp::Scan`]s immediately which are added to `found`.
-fn extract_fields<'a>(
-    ops: &[&'a ColumnOp],
-    header: &'a Header,
-    fields_map: &mut BTreeMap<(ColId, OpCmp), SmallVec<[FieldValue<'a>; 1]>>,
-    found: &mut IndexColumnOpSink<'a>,
-) {
-    let mut add_field = |parent, op, field_col, field, val| {
-        let fv = FieldValue::new(parent, op, field, val);
-        fields_map.entry((field_col, op)).or_default().push(fv);
+/// Returns whether the constraints in `fields_map` imply the `predicate` of a partial index.
+///
+/// This is a simple, conservative check:
+/// each range in `predicate` must be implied by a single constraint on its column.
+/// For example, `deleted = false` implies `deleted = false` and `age > 20` implies `age >= 18`,
+/// but `age > 20 AND age < 30` does not imply `age BETWEEN 18 AND 40`.
+fn implies_partial_predicate(
+    fields_map: &BTreeMap<(ColId, OpCmp), SmallVec<[FieldValue<'_>; 1]>>,
+    predicate: &PartialIndexPredicate,
+) -> bool {
+    predicate.ranges.iter().all(|(col, lower, upper)| {
+        fields_map
+            .iter()
+            .filter(|((field_col, _), _)| field_col == col)
+            .flat_map(|(_, fields)| fields)
+            .any(|field| cmp_implies_range(field.cmp, field.value, lower, upper))
+    })
+}
+
+/// Returns whether `x cmp value` implies that `x` is within `lower..upper`.
+fn cmp_implies_range(
+    cmp: OpCmp,
+    value: &AlgebraicValue,
+    lower: &Bound<AlgebraicValue>,
+    upper: &Bound<AlgebraicValue>,
+) -> bool {
+    // The range of `x` satisfying `x cmp value`.
+    let (x_lower, x_upper) = match cmp {
+        OpCmp::Eq => (Bound::Included(value), Bound::Included(value)),
+        OpCmp::Lt => (Bound::Unbounded, Bound::Excluded(value)),
+        OpCmp::LtEq => (Bound::Unbounded, Bound::Included(value)),
+        OpCmp::Gt => (Bound::Excluded(value), Bound::Unbounded),
+        OpCmp::GtEq => (Bound::Included(value), Bound::Unbounded),
+        OpCmp::NotEq | OpCmp::IsDistinctFrom | OpCmp::IsNotDistinctFrom | OpCmp::EqIgnoreCase => return false,
     };
 
-    for op in ops {
-        match op {
-            ColumnOp::Cmp {
-                op: OpQuery::Cmp(cmp),
-                lhs,
+    let lower_implied = match (x_lower, lower) {
+        (_, Bound::Unbounded) => true,
+        (Bound::Unbounded, _) => false,
+        (Bound::Included(x), Bound::Excluded(l)) => x > l,
+        (Bound::Included(x) | Bound::Excluded(x), Bound::Included(l) | Bound::Excluded(l)) => x >= l,
+    };
+    let upper_implied = match (x_upper, upper) {
+        (_, Bound::Unbounded) => true,
+        (Bound::Unbounded, _) => false,
+        (Bound::Included(x), Bound::Excluded(u)) => x < u,
+        (Bound::Included(x) | Bound::Excluded(x), Bound::Included(u) | Bound::Excluded(u)) => x <= u,
+    };
+    lower_implied && upper_implied
+}
+
+/// Returns whether the column `col` of `header` has at most [`NOT_EQ_MAX_DISTINCT`] distinct values.
+///
+/// The number of distinct values is taken from the hint in `stats`, if any,
+/// and is otherwise known only for a `bool` or a simple enum column.
+fn is_low_cardinality(header: &Header, stats: &ColumnStatsMap, col: ColId) -> bool {
+    let distinct_count = stats.get(&col).and_then(|s| s.distinct_count).or_else(|| {
+        let ty = &header.fields.get(col.idx())?.algebraic_type;
+        if *ty == AlgebraicType::Bool {
+            return Some(2);
+        }
+        match ty {
+            AlgebraicType::Sum(sum) if sum.is_simple_enum() => Some(sum.variants.len() as u64),
+            _ => None,
+        }
+    });
+    distinct_count.is_some_and(|count| count <= NOT_EQ_MAX_DISTINCT)
+}
+
+/// An equality seek on a single-column index, chosen by [`select_best_index`].
+struct EqSeek<'a> {
+    /// The position of the seek in the sink.
+    pos: usize,
+    /// The indexed column.
+    col: ColId,
+    /// The field constrained by `parent`.
+    field: FieldName,
+    /// The constraint `field = value` served by the seek.
+    parent: &'a ColumnOp,
+}
+
+/// Demotes equality seeks on single-column indices to scans, i.e., residual filters,
+/// when `stats` indicates that another seek in `eq_seeks` is far more selective.
+///
+/// For example, given `WHERE id = 1 AND is_active = true` with an index on each column,
+/// seeking on `is_active` and intersecting the result with the seek on `id`
+/// costs more than filtering the few rows that `id = 1` yields.
+///
+/// Seeks on columns without a distinct-count hint are kept.
+fn demote_unselective_eq_seeks<'a>(
+    found: &mut IndexColumnOpSink<'a>,
+    fields_indexed: &mut FieldsIndexed,
+    stats: &ColumnStatsMap,
+    eq_seeks: &[EqSeek<'a>],
+) {
+    let distinct_count = |col: ColId| stats.get(&col).and_then(|s| s.distinct_count);
+    let Some(best) = eq_seeks.iter().filter_map(|seek| distinct_count(seek.col)).max() else {
+        return;
+    };
+
+    for seek in eq_seeks {
+        let Some(count) = distinct_count(seek.col) else {
+            continue;
+        };
+        if count < best && count.saturating_mul(EQ_SEEK_DEMOTION_FACTOR) <= best {
+            found[seek.pos] = IndexColumnOp::Scan(seek.parent);
+            // The constraint is no longer served by an index,
+            // so the scan must not be removed as redundant.
+            fields_indexed.remove(&(seek.field, OpCmp::Eq));
+        }
+    }
+}
+
+/// Why a predicate of a selection couldn't be served by an index.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum IndexMissKind {
+    /// No index of the table contains the column.
+    NoIndex,
+    /// An inequality, which is only served by an index on a column with few distinct values.
+    NotEqUnsupported,
+    /// The column is only indexed after the leading columns of multi-column indices,
+    /// which aren't all constrained by the same operator.
+    CompositePrefixGap,
+    /// The predicate is a branch of a disjunction.
+    OrBranch,
+    /// The operator, e.g., `IS DISTINCT FROM`, is never served by an index.
+    UnsupportedOp,
+    /// The column leads an index, but the predicate wasn't served by it,
+    /// e.g., as a scan was estimated to read fewer rows.
+    NotChosen,
+}
+
+/// A predicate on `field` of a selection served by a scan,
+/// as reported by [`QueryExpr::explain_index_usage`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct IndexMissReason {
+    pub field: FieldName,
+    /// The operator comparing `field` to a value.
+    /// An `IN`-list is reported as [`OpCmp::Eq`], as it is served by one equality seek per value.
+    pub op: OpCmp,
+    pub reason: IndexMissKind,
+}
+
+/// Like [`select_best_index`],
+/// but also records in `misses` why each predicate in `ops` on a field of `header`
+/// that is served by a scan couldn't be served by an index.
+fn select_best_index_explained<'a>(
+    fields_indexed: &mut FieldsIndexed,
+    header: &'a Header,
+    stats: &ColumnStatsMap,
+    ops: &[&'a ColumnOp],
+    misses: &mut Vec<IndexMissReason>,
+) -> IndexColumnOpSink<'a> {
+    let found = select_best_index(fields_indexed, header, stats, ops);
+
+    let mut seen = SmallVec::<[&ColumnOp; 1]>::new();
+    for op in &found {
+        let &IndexColumnOp::Scan(op) = op else {
+            continue;
+        };
+        // Both sides of `a = 1 AND b = 2` are scanned as their parent, which is then found twice.
+        if seen.iter().any(|seen| std::ptr::eq(*seen, op)) {
+            continue;
+        }
+        seen.push(op);
+
+        if let ColumnOp::Cmp {
+            op: OpQuery::Logic(OpLogic::Or),
+            ..
+        } = op
+        {
+            let branches = op.flatten_ors_ref();
+            let leaves = branches.into_iter().flat_map(|branch| branch.flatten_ands_ref());
+            for (field, cmp) in leaves.flat_map(predicate_fields) {
+                if header.field_name(field).is_some() {
+                    misses.push(IndexMissReason {
+                        field,
+                        op: cmp,
+                        reason: IndexMissKind::OrBranch,
+                    });
+                }
+            }
+            continue;
+        }
+
+        for leaf in op.flatten_ands_ref() {
+            for (field, cmp) in predicate_fields(leaf) {
+                let Some((col, _)) = header.field_name(field) else {
+                    // The field is of another table, whose indices are considered on their own.
+                    continue;
+                };
+                // A comparison served by an index, or redundant with one that is, isn't a miss.
+                if matches!(leaf, ColumnOp::Cmp { .. }) && fields_indexed.contains(&(field, cmp)) {
+                    continue;
+                }
+                misses.push(IndexMissReason {
+                    field,
+                    op: cmp,
+                    reason: index_miss_kind(header, col, cmp),
+                });
+            }
+        }
+    }
+
+    found
+}
+
+/// Returns the fields and operators of `op`
+/// when it compares a field to a value or is an `IN`-list.
+fn predicate_fields(op: &ColumnOp) -> SmallVec<[(FieldName, OpCmp); 1]> {
+    match op {
+        ColumnOp::Cmp {
+            op: OpQuery::Cmp(cmp),
+            lhs,
+            rhs,
+        } => match (&**lhs, &**rhs) {
+            (ColumnOp::Field(FieldExpr::Name(field)), ColumnOp::Field(FieldExpr::Value(_))) => {
+                smallvec![(*field, *cmp)]
+            }
+            _ => SmallVec::new(),
+        },
+        ColumnOp::In { field, .. } => smallvec![(*field, OpCmp::Eq)],
+        ColumnOp::InTuples { fields, .. } => fields.iter().map(|field| (*field, OpCmp::Eq)).collect(),
+        _ => SmallVec::new(),
+    }
+}
+
+/// Returns why the predicate `col cmp value` on `header` couldn't be served by an index.
+fn index_miss_kind(header: &Header, col: ColId, cmp: OpCmp) -> IndexMissKind {
+    match cmp {
+        OpCmp::NotEq => return IndexMissKind::NotEqUnsupported,
+        OpCmp::IsDistinctFrom | OpCmp::IsNotDistinctFrom | OpCmp::EqIgnoreCase => return IndexMissKind::UnsupportedOp,
+        OpCmp::Eq | OpCmp::Lt | OpCmp::LtEq | OpCmp::Gt | OpCmp::GtEq => {}
+    }
+
+    let mut indices = header
+        .constraints
+        .iter()
+        .filter(|(_, c)| c.has_indexed())
+        .map(|(cl, _)| cl)
+        .filter(|cl| cl.contains(col))
+        .peekable();
+    if indices.peek().is_none() {
+        IndexMissKind::NoIndex
+    } else if indices.all(|cl| cl.head() != col) {
+        IndexMissKind::CompositePrefixGap
+    } else {
+        IndexMissKind::NotChosen
+    }
+}
+
+/// Extracts `name = val` when `lhs` is a field that exists and `rhs` is a value.
+fn ext_field_val<'a>(
+    header: &'a Header,
+    lhs: &'a ColumnOp,
+    rhs: &'a ColumnOp,
+) -> Option<(ColId, FieldName, &'a AlgebraicValue)> {
+    if let (ColumnOp::Field(FieldExpr::Name(name)), ColumnOp::Field(FieldExpr::Value(val))) = (lhs, rhs) {
+        return header.field_name(*name).map(|(id, col)| (id, col, val));
+    }
+    None
+}
+
+/// Extracts `name = val` when `op` is `name = val` and `name` exists.
+fn ext_cmp_field_val<'a>(
+    header: &'a Header,
+    op: &'a ColumnOp,
+) -> Option<(&'a OpCmp, ColId, FieldName, &'a AlgebraicValue)> {
+    match op {
+        ColumnOp::Cmp {
+            op: OpQuery::Cmp(op),
+            lhs,
+            rhs,
+        } => ext_field_val(header, lhs, rhs).map(|(id, f, v)| (op, id, f, v)),
+        _ => None,
+    }
+}
+
+/// Extracts a list of `field = val` constraints that *could* be answered by an index
+/// and populates those into `fields_map`.
+/// Likewise, `field IN (vals)` constraints are added to `in_lists`.
+/// The [`ColumnOp`]s that don't fit either
+/// are made into [`IndexColumnOp::Scan`]s immediately which are added to `found`.
+fn extract_fields<'a>(
+    ops: &[&'a ColumnOp],
+    header: &'a Header,
+    fields_map: &mut BTreeMap<(ColId, OpCmp), SmallVec<[FieldValue<'a>; 1]>>,
+    in_lists: &mut SmallVec<[InList<'a>; 1]>,
+    tuple_in_lists: &mut SmallVec<[TupleInList<'a>; 1]>,
+    found: &mut IndexColumnOpSink<'a>,
+) {
+    let mut add_field = |parent, op, field_col, field, val| {
+        let fv = FieldValue::new(parent, op, field, val);
+        fields_map.entry((field_col, op)).or_default().push(fv);
+    };
+
+    for op in ops {
+        match op {
+            ColumnOp::Cmp {
+                op: OpQuery::Cmp(cmp),
+                lhs,
                 rhs,
             } => {
                 if let Some((field_col, field, val)) = ext_field_val(header, lhs, rhs) {
@@ -1105,22 +3149,231 @@ fn extract_fields<'a>(
                     }
                 }
             }
+            ColumnOp::In { field, values } => {
+                if let Some((col, _)) = header.field_name(*field) {
+                    in_lists.push(InList {
+                        parent: op,
+                        col,
+                        values,
+                    });
+                    continue;
+                }
+            }
+            ColumnOp::InTuples { fields, values } => {
+                let cols = fields
+                    .iter()
+                    .map(|field| header.column_pos(*field))
+                    .collect::<Option<_>>();
+                if let Some(cols) = cols {
+                    tuple_in_lists.push(TupleInList {
+                        parent: op,
+                        cols,
+                        values,
+                    });
+                    continue;
+                }
+            }
             ColumnOp::Cmp {
                 op: OpQuery::Logic(OpLogic::Or),
                 ..
             }
-            | ColumnOp::Field(_) => {}
+            | ColumnOp::Field(_)
+            | ColumnOp::Len(_) => {}
         }
 
         found.push(IndexColumnOp::Scan(op));
     }
 }
 
+/// Returns whether any column of a projection is renamed.
+fn is_aliased(cols: &[(FieldExpr, Option<Box<str>>)]) -> bool {
+    cols.iter().any(|(_, alias)| alias.is_some())
+}
+
+/// The estimated fraction of rows satisfying an equality.
+const EQ_SELECTIVITY: f64 = 0.1;
+
+/// The estimated fraction of rows satisfying a range comparison, e.g., `a < 5`.
+const RANGE_SELECTIVITY: f64 = 1.0 / 3.0;
+
+/// Returns whether `lhs cmp rhs` holds.
+///
+/// Values are compared structurally by `Eq`, so a none equals another none,
+/// but a some doesn't equal the value it wraps.
+/// `IsNotDistinctFrom` and `IsDistinctFrom` compare options null-safely instead; see [`is_not_distinct_from`].
+/// `EqIgnoreCase` never holds for values other than strings.
+fn compare_values(cmp: OpCmp, lhs: &AlgebraicValue, rhs: &AlgebraicValue) -> bool {
+    match cmp {
+        OpCmp::Eq => lhs == rhs,
+        OpCmp::NotEq => lhs != rhs,
+        OpCmp::IsNotDistinctFrom => is_not_distinct_from(lhs, rhs),
+        OpCmp::IsDistinctFrom => !is_not_distinct_from(lhs, rhs),
+        OpCmp::Lt => lhs < rhs,
+        OpCmp::LtEq => lhs <= rhs,
+        OpCmp::Gt => lhs > rhs,
+        OpCmp::GtEq => lhs >= rhs,
+        OpCmp::EqIgnoreCase => eq_ignore_case(lhs, rhs).unwrap_or(false),
+    }
+}
+
+/// Returns whether `lhs` and `rhs` are equal, treating an option compared with a plain value null-safely:
+/// a none is distinct from any value, and a some is not distinct from the value it wraps,
+/// as for `SQL`'s `IS NOT DISTINCT FROM` on a nullable column.
+///
+/// Values don't carry their type, so a sum compared with a value that isn't a sum is taken to be an option,
+/// with `some` as its first variant, as laid out by [`AlgebraicValue::OptionSome`].
+/// Two sums, e.g., two options, are compared structurally, so a none is not distinct from another none.
+fn is_not_distinct_from(lhs: &AlgebraicValue, rhs: &AlgebraicValue) -> bool {
+    match (lhs, rhs) {
+        (AlgebraicValue::Sum(_), AlgebraicValue::Sum(_)) => lhs == rhs,
+        (AlgebraicValue::Sum(option), value) | (value, AlgebraicValue::Sum(option)) => {
+            option.tag == 0 && *option.value == *value
+        }
+        _ => lhs == rhs,
+    }
+}
+
+/// Like [`compare_values`], but for two primitives of the same type,
+/// which are ordered as their [`AlgebraicValue`]s are.
+fn compare_ord<T: Ord>(cmp: OpCmp, lhs: &T, rhs: &T) -> bool {
+    match cmp {
+        OpCmp::Eq | OpCmp::IsNotDistinctFrom => lhs == rhs,
+        OpCmp::NotEq | OpCmp::IsDistinctFrom => lhs != rhs,
+        OpCmp::Lt => lhs < rhs,
+        OpCmp::LtEq => lhs <= rhs,
+        OpCmp::Gt => lhs > rhs,
+        OpCmp::GtEq => lhs >= rhs,
+        // Only strings are equal ignoring case.
+        OpCmp::EqIgnoreCase => false,
+    }
+}
+
+/// Returns whether the strings `lhs` and `rhs` are equal after lowercasing both,
+/// or `None` if either is not a string.
+fn eq_ignore_case(lhs: &AlgebraicValue, rhs: &AlgebraicValue) -> Option<bool> {
+    let (AlgebraicValue::String(lhs), AlgebraicValue::String(rhs)) = (lhs, rhs) else {
+        return None;
+    };
+    let fold = |s: &str| s.chars().flat_map(char::to_lowercase).collect::<SmallVec<[char; 32]>>();
+    Some(lhs == rhs || fold(lhs) == fold(rhs))
+}
+
+/// Compares the rows `a` and `b`, both of the `header`, by their columns `cols`, in order.
+///
+/// Values are ordered as comparisons, e.g., `field < value`, order them during query execution,
+/// so sorting or merging rows outside the VM agrees with the VM:
+/// sums are ordered by their tag first, products lexicographically,
+/// and values of different types by the order of their variants in [`AlgebraicValue`].
+///
+/// Returns an error if a column of `cols` is not in the `header`, or is missing from either row.
+pub fn compare_rel_values(
+    a: &RelValue<'_>,
+    b: &RelValue<'_>,
+    cols: &ColList,
+    header: &Header,
+) -> Result<Ordering, ErrorVm> {
+    // Check every column up front, so a missing one is reported even if an earlier one decides the order.
+    if let Some(col) = cols.iter().find(|col| col.idx() >= header.fields.len()) {
+        let field = FieldName::new(header.table_id, col);
+        return Err(RelationError::FieldNotFound(header.clone_for_error(), field).into());
+    }
+    for col in cols.iter() {
+        let (Some(lhs), Some(rhs)) = (a.read_column(col.idx()), b.read_column(col.idx())) else {
+            let field = header.fields[col.idx()].field;
+            return Err(RelationError::FieldNotFoundAtPos(col.idx(), field).into());
+        };
+        let ord = lhs.cmp(&rhs);
+        if ord.is_ne() {
+            return Ok(ord);
+        }
+    }
+    Ok(Ordering::Equal)
+}
+
+/// Returns the estimated fraction of rows satisfying `field cmp value`.
+fn cmp_selectivity(cmp: OpCmp) -> f64 {
+    match cmp {
+        OpCmp::Eq | OpCmp::IsNotDistinctFrom | OpCmp::EqIgnoreCase => EQ_SELECTIVITY,
+        OpCmp::NotEq | OpCmp::IsDistinctFrom => 1.0 - EQ_SELECTIVITY,
+        OpCmp::Lt | OpCmp::LtEq | OpCmp::Gt | OpCmp::GtEq => RANGE_SELECTIVITY,
+    }
+}
+
+/// Returns the estimated fraction of rows whose key falls within `bounds`.
+fn bounds_selectivity(bounds: &(Bound<AlgebraicValue>, Bound<AlgebraicValue>)) -> f64 {
+    match bounds {
+        (Bound::Included(lower), Bound::Included(upper)) if lower == upper => EQ_SELECTIVITY,
+        (Bound::Unbounded, Bound::Unbounded) => 1.0,
+        _ => RANGE_SELECTIVITY,
+    }
+}
+
+/// Returns whether `columns` of `head` are constrained to be unique.
+fn is_unique(head: &Header, columns: &ColList) -> bool {
+    head.constraints
+        .iter()
+        .any(|(cols, constraints)| cols == columns && constraints.has_unique())
+}
+
+/// Returns whether the column `field` of `head` is constrained to be unique.
+fn is_unique_field(head: &Header, field: FieldName) -> bool {
+    head.column_pos(field).is_some_and(|col| is_unique(head, &col.into()))
+}
+
+/// Returns the estimated number of rows in `table` according to `row_count`,
+/// which is unknown if `row_count` is negative.
+fn table_rows(table: &DbTable, row_count: &impl Fn(TableId, &str) -> i64) -> RowCount {
+    usize::try_from(row_count(table.table_id, &table.head.table_name))
+        .map_or_else(|_| RowCount::unknown(), RowCount::exact)
+}
+
+/// Returns the estimated number of rows in `source`.
+fn source_rows(source: &SourceExpr, row_count: &impl Fn(TableId, &str) -> i64) -> RowCount {
+    match source {
+        SourceExpr::DbTable(table) => table_rows(table, row_count),
+        SourceExpr::InMemory { row_count, .. } => *row_count,
+    }
+}
+
+/// Returns the estimated number of `input` rows kept by a filter of `selectivity`.
+fn filter_rows(input: RowCount, selectivity: f64) -> RowCount {
+    if selectivity >= 1.0 {
+        return input;
+    }
+    RowCount {
+        min: 0,
+        max: input.max.map(|max| (max as f64 * selectivity).ceil() as usize),
+    }
+}
+
+/// Returns the estimated number of rows produced by joining `lhs` and `rhs` rows on an equality,
+/// where `lhs_unique` and `rhs_unique` tell whether the join columns are unique on either side.
+///
+/// Each row matches at most one row of a side whose join column is unique.
+/// Without any such constraint,
+/// each row of the larger side is assumed to match a single row of the smaller side.
+/// A semi-join returns each `lhs` row at most once.
+fn join_rows(lhs: RowCount, rhs: RowCount, lhs_unique: bool, rhs_unique: bool, semi: bool) -> RowCount {
+    let (Some(l), Some(r)) = (lhs.max, rhs.max) else {
+        return RowCount::unknown();
+    };
+    let max = match (lhs_unique, rhs_unique) {
+        (true, true) => l.min(r),
+        (true, false) => r,
+        (false, true) => l,
+        (false, false) if l == 0 || r == 0 => 0,
+        (false, false) => l.max(r),
+    };
+    let max = if semi { max.min(l) } else { max };
+    RowCount { min: 0, max: Some(max) }
+}
+
 /// Sargable stands for Search ARGument ABLE.
 /// A sargable predicate is one that can be answered using an index.
 fn find_sargable_ops<'a>(
     fields_indexed: &mut FieldsIndexed,
     header: &'a Header,
+    stats: &ColumnStatsMap,
     op: &'a ColumnOp,
 ) -> SmallVec<[IndexColumnOp<'a>; 1]> {
     let mut ops_flat = op.flatten_ands_ref();
@@ -1128,10 +3381,58 @@ fn find_sargable_ops<'a>(
         match ops_flat.swap_remove(0) {
             // Special case; fast path for a single field.
             op @ ColumnOp::Field(_) => smallvec![IndexColumnOp::Scan(op)],
-            op => select_best_index(fields_indexed, header, &[op]),
+            op => select_best_index(fields_indexed, header, stats, &[op]),
         }
     } else {
-        select_best_index(fields_indexed, header, &ops_flat)
+        select_best_index(fields_indexed, header, stats, &ops_flat)
+    }
+}
+
+/// Returns whether `head` has an index on exactly the columns `cols`, in that order.
+fn is_index(head: &Header, cols: &ColList) -> bool {
+    head.constraints
+        .iter()
+        .any(|(index_cols, constraints)| index_cols == cols && constraints.has_indexed())
+}
+
+/// Returns whether `head` has an index that can be probed on the columns `cols`.
+///
+/// A single column may be served by any index containing it,
+/// while multiple columns need an index on exactly those columns.
+fn is_indexed_on(head: &Header, cols: &ColList) -> bool {
+    head.constraints.iter().any(|(index_cols, constraints)| {
+        constraints.has_indexed()
+            && if cols.is_singleton() {
+                index_cols.contains(cols.head())
+            } else {
+                index_cols == cols
+            }
+    })
+}
+
+/// The number of each kind of operator in a query,
+/// including those of its joined subqueries,
+/// as returned by [`QueryExpr::operator_counts`].
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct OperatorCounts {
+    /// [`Query::IndexScan`]s and [`Query::IndexMultiScan`]s.
+    pub index_scans: usize,
+    /// [`Query::Select`]s, as well as the `index_select`s of [`Query::IndexJoin`]s.
+    pub seq_selects: usize,
+    /// [`Query::JoinInner`]s of any [`JoinKind`], and [`Query::MergeJoin`]s.
+    pub inner_joins: usize,
+    /// [`Query::IndexJoin`]s.
+    pub index_joins: usize,
+    /// [`Query::JoinLeft`]s and [`Query::SemiJoinInMemory`]s.
+    pub other_joins: usize,
+    /// [`Query::Project`]s, as well as the projections fused into [`Query::IndexJoin`]s.
+    pub projections: usize,
+}
+
+impl OperatorCounts {
+    /// Returns the number of joins of any kind.
+    pub fn joins(&self) -> usize {
+        self.inner_joins + self.index_joins + self.other_joins
     }
 }
 
@@ -1195,15 +3496,175 @@ impl QueryExpr {
     pub fn reads_from_table(&self, id: &TableId) -> bool {
         self.source.table_id() == Some(*id)
             || self.query.iter().any(|q| match q {
-                Query::Select(_) | Query::Project(_, _) => false,
+                Query::Select(_)
+                | Query::Project(..)
+                | Query::Unnest(_)
+                | Query::Limit { .. }
+                | Query::NeverReturns => false,
                 Query::IndexScan(scan) => scan.table.table_id == *id,
-                Query::JoinInner(join) => join.rhs.reads_from_table(id),
+                Query::IndexMultiScan(scan) => scan.table.table_id == *id,
+                Query::JoinInner(join) | Query::JoinLeft(join) => join.rhs.reads_from_table(id),
+                Query::SemiJoinInMemory { source, .. } => source.table_id() == Some(*id),
+                Query::MergeJoin(join) => join.lhs.table_id == *id || join.rhs.table_id == *id,
                 Query::IndexJoin(join) => {
                     join.index_side.table_id() == Some(*id) || join.probe_side.reads_from_table(id)
                 }
             })
     }
 
+    /// Returns the ids of all the tables this query reads, including those of joined subqueries.
+    ///
+    /// In-memory sources have no [`TableId`] and so contribute none.
+    pub fn read_table_ids(&self) -> HashSet<TableId> {
+        let mut ids = HashSet::default();
+        self.collect_read_table_ids(&mut ids);
+        ids
+    }
+
+    fn collect_read_table_ids(&self, ids: &mut HashSet<TableId>) {
+        ids.extend(self.source.table_id());
+        for query in &self.query {
+            match query {
+                Query::Select(_)
+                | Query::Project(..)
+                | Query::Unnest(_)
+                | Query::Limit { .. }
+                | Query::NeverReturns => {}
+                Query::IndexScan(scan) => {
+                    ids.insert(scan.table.table_id);
+                }
+                Query::IndexMultiScan(scan) => {
+                    ids.insert(scan.table.table_id);
+                }
+                Query::JoinInner(join) | Query::JoinLeft(join) => join.rhs.collect_read_table_ids(ids),
+                Query::SemiJoinInMemory { source, .. } => ids.extend(source.table_id()),
+                Query::MergeJoin(join) => ids.extend([join.lhs.table_id, join.rhs.table_id]),
+                Query::IndexJoin(join) => {
+                    ids.extend(join.index_side.table_id());
+                    join.probe_side.collect_read_table_ids(ids);
+                }
+            }
+        }
+    }
+
+    /// Returns the index key ranges this query's [`IndexScan`]s and [`IndexMultiScan`]s cover,
+    /// as `(table, columns, bounds)`, including those of joined subqueries.
+    ///
+    /// A row of `table` can only be returned by the query if its key on `columns`
+    /// falls within one of the ranges listed for that table and those columns,
+    /// so subscriptions can cheaply rule out rows that cannot match.
+    pub fn key_ranges(&self) -> Vec<(TableId, ColList, (Bound<AlgebraicValue>, Bound<AlgebraicValue>))> {
+        let mut ranges = Vec::new();
+        for query in &self.query {
+            match query {
+                Query::IndexScan(scan) => {
+                    ranges.push((scan.table.table_id, scan.columns.clone(), scan.bounds.clone()));
+                }
+                Query::IndexMultiScan(scan) => ranges.extend(
+                    scan.ranges
+                        .iter()
+                        .map(|bounds| (scan.table.table_id, scan.columns.clone(), bounds.clone())),
+                ),
+                Query::JoinInner(join) | Query::JoinLeft(join) => ranges.extend(join.rhs.key_ranges()),
+                Query::IndexJoin(join) => ranges.extend(join.probe_side.key_ranges()),
+                Query::Select(_)
+                | Query::Project(..)
+                | Query::Unnest(_)
+                | Query::Limit { .. }
+                | Query::NeverReturns
+                | Query::MergeJoin(_)
+                | Query::SemiJoinInMemory { .. } => {}
+            }
+        }
+        ranges
+    }
+
+    /// Returns a query that looks up the rows of `table` where `columns = key`
+    /// using the index on exactly `columns`.
+    ///
+    /// The query is the single [`IndexScan`] that [`QueryExpr::with_index_eq`] produces for an empty query.
+    /// Returns an error if `table` has no such index.
+    pub fn point_lookup(table: DbTable, columns: ColList, key: AlgebraicValue) -> Result<Self, ErrorVm> {
+        if !is_index(&table.head, &columns) {
+            return Err(ErrorVm::NoSuchIndex {
+                table: table.head.table_name.clone(),
+                columns,
+            });
+        }
+
+        Ok(Self::new(SourceExpr::DbTable(table.clone())).with_index_eq(table, columns, key))
+    }
+
+    /// Checks that the comparisons in `self` are well-typed, without evaluating it:
+    /// each field compared to a value must be of the type of that value,
+    /// and the keys of each join must be of the same type.
+    ///
+    /// Fields are resolved in the headers of all the [`sources`](Self::sources) of `self`,
+    /// so selections on, and keys of, nested joins are checked as well.
+    pub fn type_check(&self) -> Result<(), ErrorType> {
+        let head = self.source.head();
+        let header = self.sources().fold(
+            Header::new(head.table_id, head.table_name.clone(), vec![], vec![]),
+            |header, source| header.extend(source.head()),
+        );
+        self.type_check_in(&header)
+    }
+
+    fn type_check_in(&self, header: &Header) -> Result<(), ErrorType> {
+        let select = |op: &ColumnOp| {
+            op.type_check_fields(header)?;
+            op.type_check(header)
+        };
+        let join_key = |lhs: FieldName, lhs_ty: AlgebraicType, rhs: FieldName| {
+            let rhs_ty = field_type(header, rhs)?;
+            if lhs_ty != rhs_ty {
+                return Err(ErrorType::JoinKeyMismatch {
+                    lhs,
+                    lhs_ty,
+                    rhs,
+                    rhs_ty,
+                });
+            }
+            Ok(())
+        };
+
+        for q in &self.query {
+            match q {
+                Query::Select(op) => select(op)?,
+                Query::IndexJoin(join) => {
+                    join.probe_side.type_check_in(header)?;
+                    if let Some(op) = &join.index_select {
+                        select(op)?;
+                    }
+                    let index_head = join.index_side.head();
+                    for (probe, col) in join.probe_fields.iter().zip(join.index_cols.iter()) {
+                        let index_field = &index_head.fields[col.idx()];
+                        join_key(index_field.field, index_field.algebraic_type.clone(), *probe)?;
+                    }
+                }
+                Query::JoinInner(join) | Query::JoinLeft(join) => {
+                    join.rhs.type_check_in(header)?;
+                    join_key(join.col_lhs, field_type(header, join.col_lhs)?, join.col_rhs)?;
+                }
+                Query::MergeJoin(join) => {
+                    join_key(join.col_lhs, field_type(header, join.col_lhs)?, join.col_rhs)?;
+                }
+                Query::SemiJoinInMemory { source, probe_field } => {
+                    if let Some(key) = source.head().fields.first() {
+                        join_key(key.field, key.algebraic_type.clone(), *probe_field)?;
+                    }
+                }
+                Query::IndexScan(_)
+                | Query::IndexMultiScan(_)
+                | Query::Project(..)
+                | Query::Unnest(_)
+                | Query::Limit { .. }
+                | Query::NeverReturns => {}
+            }
+        }
+        Ok(())
+    }
+
     // Generate an index scan for an equality predicate if this is the first operator.
     // Otherwise generate a select.
     // TODO: Replace these methods with a proper query optimization pass.
@@ -1213,7 +3674,12 @@ impl QueryExpr {
         // if this is the first operator in the list, generate index scan
         let Some(query) = self.query.pop() else {
             let bounds = point(value);
-            self.query.push(Query::IndexScan(IndexScan { table, columns, bounds }));
+            self.query.push(Query::IndexScan(IndexScan {
+                table,
+                columns,
+                bounds,
+                covering: false,
+            }));
             return self;
         };
         match query {
@@ -1235,13 +3701,13 @@ impl QueryExpr {
                 rhs,
                 col_lhs,
                 col_rhs,
-                semi,
+                kind,
             }) => {
                 self.query.push(Query::JoinInner(JoinExpr {
                     rhs: rhs.with_index_eq(table, columns, value),
                     col_lhs,
                     col_rhs,
-                    semi,
+                    kind,
                 }));
                 self
             }
@@ -1274,7 +3740,12 @@ impl QueryExpr {
         // if this is the first operator in the list, generate an index scan
         let Some(query) = self.query.pop() else {
             let bounds = (Self::bound(value, inclusive), Bound::Unbounded);
-            self.query.push(Query::IndexScan(IndexScan { table, columns, bounds }));
+            self.query.push(Query::IndexScan(IndexScan {
+                table,
+                columns,
+                bounds,
+                covering: false,
+            }));
             return self;
         };
         match query {
@@ -1296,13 +3767,13 @@ impl QueryExpr {
                 rhs,
                 col_lhs,
                 col_rhs,
-                semi,
+                kind,
             }) => {
                 self.query.push(Query::JoinInner(JoinExpr {
                     rhs: rhs.with_index_lower_bound(table, columns, value, inclusive),
                     col_lhs,
                     col_rhs,
-                    semi,
+                    kind,
                 }));
                 self
             }
@@ -1313,7 +3784,12 @@ impl QueryExpr {
                 ..
             }) if columns == lhs_col_id => {
                 let bounds = (Self::bound(value, inclusive), Bound::Included(upper));
-                self.query.push(Query::IndexScan(IndexScan { table, columns, bounds }));
+                self.query.push(Query::IndexScan(IndexScan {
+                    table,
+                    columns,
+                    bounds,
+                    covering: false,
+                }));
                 self
             }
             // merge with a preceding upper bounded index scan (exclusive)
@@ -1325,16 +3801,16 @@ impl QueryExpr {
                 // Queries like `WHERE x < 5 AND x > 5` never return any rows and are likely mistakes.
                 // Detect such queries and log a warning.
                 // Compute this condition early, then compute the resulting query and log it.
-                // TODO: We should not emit an `IndexScan` in this case.
-                // Further design work is necessary to decide whether this should be an error at query compile time,
-                // or whether we should emit a query plan which explicitly says that it will return 0 rows.
-                // The current behavior is a hack
-                // because this patch was written (2024-04-01 pgoldman) a short time before the BitCraft alpha,
-                // and a more invasive change was infeasible.
+                // `QueryExpr::optimize` replaces the resulting scan with `Query::NeverReturns`.
                 let is_never = !inclusive && value == upper;
 
                 let bounds = (Self::bound(value, inclusive), Bound::Excluded(upper));
-                self.query.push(Query::IndexScan(IndexScan { table, columns, bounds }));
+                self.query.push(Query::IndexScan(IndexScan {
+                    table,
+                    columns,
+                    bounds,
+                    covering: false,
+                }));
 
                 if is_never {
                     log::warn!("Query will select no rows due to equal excluded bounds: {self:?}")
@@ -1342,8 +3818,23 @@ impl QueryExpr {
 
                 self
             }
-            // merge with a preceding select
-            Query::Select(filter) => {
+            // tighten the lower bound of a preceding index scan, e.g., `a > 1 AND a > 3` => `a > 3`
+            Query::IndexScan(IndexScan {
+                columns: lhs_col_id,
+                bounds: (lower @ (Bound::Included(_) | Bound::Excluded(_)), upper),
+                ..
+            }) if columns == lhs_col_id => {
+                let bounds = (Self::tighter_bound(lower, Self::bound(value, inclusive), true), upper);
+                self.query.push(Query::IndexScan(IndexScan {
+                    table,
+                    columns,
+                    bounds,
+                    covering: false,
+                }));
+                self
+            }
+            // merge with a preceding select
+            Query::Select(filter) => {
                 let bounds = (Self::bound(value, inclusive), Bound::Unbounded);
                 let op = ColumnOp::from_op_col_bounds(&table.head, &columns, bounds);
                 self.query.push(Query::Select(ColumnOp::and(filter, op)));
@@ -1376,6 +3867,7 @@ impl QueryExpr {
                 table,
                 columns,
                 bounds: (Bound::Unbounded, Self::bound(value, inclusive)),
+                covering: false,
             }));
             return self;
         };
@@ -1398,13 +3890,13 @@ impl QueryExpr {
                 rhs,
                 col_lhs,
                 col_rhs,
-                semi,
+                kind,
             }) => {
                 self.query.push(Query::JoinInner(JoinExpr {
                     rhs: rhs.with_index_upper_bound(table, columns, value, inclusive),
                     col_lhs,
                     col_rhs,
-                    semi,
+                    kind,
                 }));
                 self
             }
@@ -1415,7 +3907,12 @@ impl QueryExpr {
                 ..
             }) if columns == lhs_col_id => {
                 let bounds = (Bound::Included(lower), Self::bound(value, inclusive));
-                self.query.push(Query::IndexScan(IndexScan { table, columns, bounds }));
+                self.query.push(Query::IndexScan(IndexScan {
+                    table,
+                    columns,
+                    bounds,
+                    covering: false,
+                }));
                 self
             }
             // merge with a preceding lower bounded index scan (exclusive)
@@ -1427,16 +3924,16 @@ impl QueryExpr {
                 // Queries like `WHERE x < 5 AND x > 5` never return any rows and are likely mistakes.
                 // Detect such queries and log a warning.
                 // Compute this condition early, then compute the resulting query and log it.
-                // TODO: We should not emit an `IndexScan` in this case.
-                // Further design work is necessary to decide whether this should be an error at query compile time,
-                // or whether we should emit a query plan which explicitly says that it will return 0 rows.
-                // The current behavior is a hack
-                // because this patch was written (2024-04-01 pgoldman) a short time before the BitCraft alpha,
-                // and a more invasive change was infeasible.
+                // `QueryExpr::optimize` replaces the resulting scan with `Query::NeverReturns`.
                 let is_never = !inclusive && value == lower;
 
                 let bounds = (Bound::Excluded(lower), Self::bound(value, inclusive));
-                self.query.push(Query::IndexScan(IndexScan { table, columns, bounds }));
+                self.query.push(Query::IndexScan(IndexScan {
+                    table,
+                    columns,
+                    bounds,
+                    covering: false,
+                }));
 
                 if is_never {
                     log::warn!("Query will select no rows due to equal excluded bounds: {self:?}")
@@ -1444,6 +3941,21 @@ impl QueryExpr {
 
                 self
             }
+            // tighten the upper bound of a preceding index scan, e.g., `a < 3 AND a < 1` => `a < 1`
+            Query::IndexScan(IndexScan {
+                columns: lhs_col_id,
+                bounds: (lower, upper @ (Bound::Included(_) | Bound::Excluded(_))),
+                ..
+            }) if columns == lhs_col_id => {
+                let bounds = (lower, Self::tighter_bound(upper, Self::bound(value, inclusive), false));
+                self.query.push(Query::IndexScan(IndexScan {
+                    table,
+                    columns,
+                    bounds,
+                    covering: false,
+                }));
+                self
+            }
             // merge with a preceding select
             Query::Select(filter) => {
                 let bounds = (Bound::Unbounded, Self::bound(value, inclusive));
@@ -1462,6 +3974,175 @@ impl QueryExpr {
         }
     }
 
+    // Generate an index scan for the range `(lower, upper)` or try merging with a previous index scan.
+    // Otherwise generate a select.
+    // Unlike `with_index_lower_bound` followed by `with_index_upper_bound`,
+    // both bounds always end up in the same scan or select.
+    // TODO: Replace these methods with a proper query optimization pass.
+    pub fn with_index_range(
+        mut self,
+        table: DbTable,
+        columns: ColList,
+        lower: Bound<AlgebraicValue>,
+        upper: Bound<AlgebraicValue>,
+    ) -> Self {
+        // an unbounded range filters nothing
+        if matches!((&lower, &upper), (Bound::Unbounded, Bound::Unbounded)) {
+            return self;
+        }
+
+        // if this is the first operator in the list, generate an index scan
+        let Some(query) = self.query.pop() else {
+            return self.with_range_scan(IndexScan {
+                table,
+                columns,
+                bounds: (lower, upper),
+                covering: false,
+            });
+        };
+        match query {
+            // try to push below join's lhs
+            Query::JoinInner(JoinExpr {
+                rhs:
+                    QueryExpr {
+                        source: SourceExpr::DbTable(ref db_table),
+                        ..
+                    },
+                ..
+            }) if table.table_id != db_table.table_id => {
+                self = self.with_index_range(table, columns, lower, upper);
+                self.query.push(query);
+                self
+            }
+            // try to push below join's rhs
+            Query::JoinInner(JoinExpr {
+                rhs,
+                col_lhs,
+                col_rhs,
+                kind,
+            }) => {
+                self.query.push(Query::JoinInner(JoinExpr {
+                    rhs: rhs.with_index_range(table, columns, lower, upper),
+                    col_lhs,
+                    col_rhs,
+                    kind,
+                }));
+                self
+            }
+            // intersect with a preceding index scan, e.g., `a > 1 AND 3 <= a < 5` => `3 <= a < 5`
+            Query::IndexScan(IndexScan {
+                columns: lhs_col_id,
+                bounds: (lhs_lower, lhs_upper),
+                ..
+            }) if columns == lhs_col_id => self.with_range_scan(IndexScan {
+                table,
+                columns,
+                bounds: (
+                    Self::tighter_bound(lhs_lower, lower, true),
+                    Self::tighter_bound(lhs_upper, upper, false),
+                ),
+                covering: false,
+            }),
+            // merge with a preceding select
+            Query::Select(filter) => {
+                let op = ColumnOp::from_op_col_bounds(&table.head, &columns, (lower, upper));
+                self.query.push(Query::Select(ColumnOp::and(filter, op)));
+                self
+            }
+            // else generate a new select
+            query => {
+                self.query.push(query);
+                let op = ColumnOp::from_op_col_bounds(&table.head, &columns, (lower, upper));
+                self.query.push(Query::Select(op));
+                self
+            }
+        }
+    }
+
+    // Push `scan` as the last operator, warning if it never returns any rows.
+    fn with_range_scan(mut self, scan: IndexScan) -> Self {
+        // Queries like `WHERE x > 5 AND x < 5` never return any rows and are likely mistakes.
+        // Detect such queries and log a warning.
+        // `QueryExpr::optimize` replaces the resulting scan with `Query::NeverReturns`.
+        let is_never = scan.is_never();
+        self.query.push(Query::IndexScan(scan));
+        if is_never {
+            log::warn!("Query will select no rows due to incompatible bounds: {self:?}")
+        }
+        self
+    }
+
+    // Generate an index scan with a seek per value if this is the first operator.
+    // Otherwise generate a select.
+    // TODO: Replace these methods with a proper query optimization pass.
+    pub fn with_index_in(self, table: DbTable, columns: ColList, values: Vec<AlgebraicValue>) -> Self {
+        let points = values
+            .into_iter()
+            .map(|v| (Bound::Included(v.clone()), Bound::Included(v)))
+            .collect();
+        self.with_index_ranges(table, columns, points)
+    }
+
+    // Generate an index scan with a seek per range if this is the first operator.
+    // Otherwise generate a select.
+    // TODO: Replace these methods with a proper query optimization pass.
+    pub fn with_index_ranges(
+        mut self,
+        table: DbTable,
+        columns: ColList,
+        ranges: Vec<(Bound<AlgebraicValue>, Bound<AlgebraicValue>)>,
+    ) -> Self {
+        // if this is the first operator in the list, generate an index scan
+        let Some(query) = self.query.pop() else {
+            self.query
+                .push(Query::IndexMultiScan(IndexMultiScan { table, columns, ranges }));
+            return self;
+        };
+        match query {
+            // try to push below join's lhs
+            Query::JoinInner(JoinExpr {
+                rhs:
+                    QueryExpr {
+                        source: SourceExpr::DbTable(ref db_table),
+                        ..
+                    },
+                ..
+            }) if table.table_id != db_table.table_id => {
+                self = self.with_index_ranges(table, columns, ranges);
+                self.query.push(query);
+                self
+            }
+            // try to push below join's rhs
+            Query::JoinInner(JoinExpr {
+                rhs,
+                col_lhs,
+                col_rhs,
+                kind,
+            }) => {
+                self.query.push(Query::JoinInner(JoinExpr {
+                    rhs: rhs.with_index_ranges(table, columns, ranges),
+                    col_lhs,
+                    col_rhs,
+                    kind,
+                }));
+                self
+            }
+            // merge with a preceding select
+            Query::Select(filter) => {
+                let op = ColumnOp::from_op_col_ranges(&table.head, &columns, ranges);
+                self.query.push(Query::Select(ColumnOp::and(filter, op)));
+                self
+            }
+            // else generate a new select
+            query => {
+                self.query.push(query);
+                let op = ColumnOp::from_op_col_ranges(&table.head, &columns, ranges);
+                self.query.push(Query::Select(op));
+                self
+            }
+        }
+    }
+
     pub fn with_select<O>(mut self, op: O) -> Self
     where
         O: Into<ColumnOp>,
@@ -1477,7 +4158,7 @@ impl QueryExpr {
                     rhs,
                     col_lhs,
                     col_rhs,
-                    semi,
+                    kind,
                 }),
                 ColumnOp::Cmp {
                     op: OpQuery::Cmp(cmp),
@@ -1490,7 +4171,7 @@ impl QueryExpr {
                 if self.source.head().column_pos(field).is_some() =>
                     {
                         self = self.with_select(ColumnOp::cmp(field, cmp, value));
-                        self.query.push(Query::JoinInner(JoinExpr { rhs, col_lhs, col_rhs, semi}));
+                        self.query.push(Query::JoinInner(JoinExpr { rhs, col_lhs, col_rhs, kind}));
                         self
                     }
                 (ColumnOp::Field(FieldExpr::Name(field)), ColumnOp::Field(FieldExpr::Value(value)))
@@ -1501,12 +4182,12 @@ impl QueryExpr {
                             rhs: rhs.with_select(ColumnOp::cmp(field, cmp, value)),
                             col_lhs,
                             col_rhs,
-                            semi,
+                            kind,
                         }));
                         self
                     }
                 (field, value) => {
-                    self.query.push(Query::JoinInner(JoinExpr { rhs, col_lhs, col_rhs, semi, }));
+                    self.query.push(Query::JoinInner(JoinExpr { rhs, col_lhs, col_rhs, kind, }));
                     self.query.push(Query::Select(ColumnOp::new(OpQuery::Cmp(cmp), field, value)));
                     self
                 }
@@ -1523,6 +4204,20 @@ impl QueryExpr {
         }
     }
 
+    /// Like [`QueryExpr::with_select`],
+    /// but first checks that every field referenced by `op` exists in `header`,
+    /// rather than failing on each row during execution.
+    pub fn with_checked_select<O>(self, op: O, header: &Header) -> Result<Self, ErrorVm>
+    where
+        O: Into<ColumnOp>,
+    {
+        let op = op.into();
+        for field in op.referenced_fields() {
+            header.column_pos_or_err(field)?;
+        }
+        Ok(self.with_select(op))
+    }
+
     pub fn with_select_cmp<LHS, RHS, O>(self, op: O, lhs: LHS, rhs: RHS) -> Self
     where
         LHS: Into<FieldExpr>,
@@ -1537,20 +4232,91 @@ impl QueryExpr {
     // The `wildcard_table_id` represents a projection of the form `table.*`.
     // This is used to determine if an inner join can be rewritten as an index join.
     pub fn with_project(self, cols: &[FieldExpr], wildcard_table_id: Option<TableId>) -> Self {
+        let cols = cols.iter().cloned().map(|col| (col, None)).collect();
+        self.with_project_aliased(cols, wildcard_table_id)
+    }
+
+    // Like `with_project`, but each column paired with an alias is named by it in the resulting header.
+    pub fn with_project_aliased(
+        self,
+        cols: Vec<(FieldExpr, Option<Box<str>>)>,
+        wildcard_table_id: Option<TableId>,
+    ) -> Self {
         let mut x = self;
         if !cols.is_empty() {
-            x.query.push(Query::Project(cols.into(), wildcard_table_id));
+            x.query.push(Query::Project(cols, wildcard_table_id, false));
         }
         x
     }
 
-    pub fn with_join_inner(self, with: impl Into<QueryExpr>, lhs: FieldName, rhs: FieldName, semi: bool) -> Self {
+    // Like `with_project`, but yields each distinct projected row only once, i.e., `SELECT DISTINCT`.
+    // Unlike `with_project`, an empty `cols` is kept, deduplicating whole rows.
+    pub fn with_project_distinct(self, cols: &[FieldExpr], wildcard_table_id: Option<TableId>) -> Self {
+        let cols = cols.iter().cloned().map(|col| (col, None)).collect();
         let mut x = self;
-        x.query
-            .push(Query::JoinInner(JoinExpr::new(with.into(), lhs, rhs, semi)));
+        x.query.push(Query::Project(cols, wildcard_table_id, true));
         x
     }
 
+    /// Appends a [`Query::Limit`] to the query operator pipeline,
+    /// which skips the first `offset` rows and yields at most `limit` of the rest.
+    ///
+    /// A preceding limit is merged with the new one.
+    pub fn with_limit(mut self, limit: u64, offset: u64) -> Self {
+        let limit = match self.query.pop() {
+            // Skip `offset` of the rows the preceding limit yields, and take at most `limit` of those left.
+            Some(Query::Limit {
+                limit: prev_limit,
+                offset: prev_offset,
+            }) => Query::Limit {
+                limit: prev_limit.saturating_sub(offset).min(limit),
+                offset: prev_offset.saturating_add(offset),
+            },
+            prev => {
+                self.query.extend(prev);
+                Query::Limit { limit, offset }
+            }
+        };
+        self.query.push(limit);
+        self
+    }
+
+    /// Appends the operations of `next` to the query operator pipeline,
+    /// so that they read the rows of this query as their input, as from a derived table.
+    ///
+    /// This composes query fragments built separately,
+    /// e.g., `next` may join and filter the result of a query built elsewhere.
+    /// Any subqueries of `next`, i.e., the rhs of its joins,
+    /// are visited by [`QueryExpr::sources`], [`QueryExpr::reads_from_table`], and [`QueryExpr::optimize`]
+    /// as those of this query.
+    pub fn then(mut self, next: Vec<Query>) -> Self {
+        self.query.extend(next);
+        self
+    }
+
+    pub fn with_unnest(mut self, field: FieldName, outer: bool) -> Self {
+        self.query.push(Query::Unnest(UnnestExpr { field, outer }));
+        self
+    }
+
+    pub fn with_join_inner(self, with: impl Into<QueryExpr>, lhs: FieldName, rhs: FieldName, semi: bool) -> Self {
+        let kind = if semi { JoinKind::Semi } else { JoinKind::Inner };
+        self.with_join(with, lhs, rhs, kind)
+    }
+
+    /// Joins `self` with `with` on `lhs = rhs`, returning the rows given by `kind`.
+    pub fn with_join(mut self, with: impl Into<QueryExpr>, lhs: FieldName, rhs: FieldName, kind: JoinKind) -> Self {
+        self.query
+            .push(Query::JoinInner(JoinExpr::new(with.into(), lhs, rhs, kind)));
+        self
+    }
+
+    pub fn with_join_left(mut self, with: impl Into<QueryExpr>, lhs: FieldName, rhs: FieldName) -> Self {
+        self.query
+            .push(Query::JoinLeft(JoinExpr::new(with.into(), lhs, rhs, JoinKind::Left)));
+        self
+    }
+
     fn bound(value: AlgebraicValue, inclusive: bool) -> Bound<AlgebraicValue> {
         if inclusive {
             Bound::Included(value)
@@ -1559,6 +4325,67 @@ impl QueryExpr {
         }
     }
 
+    /// Returns the more restrictive of the bounds `a` and `b`,
+    /// which are both lower bounds if `lower` holds, and both upper bounds otherwise.
+    /// Of two bounds on the same value, the exclusive one is more restrictive.
+    fn tighter_bound(a: Bound<AlgebraicValue>, b: Bound<AlgebraicValue>, lower: bool) -> Bound<AlgebraicValue> {
+        let a_is_tighter = match (&a, &b) {
+            (Bound::Unbounded, _) => false,
+            (_, Bound::Unbounded) => true,
+            (Bound::Included(x) | Bound::Excluded(x), Bound::Included(y) | Bound::Excluded(y)) => match x.cmp(y) {
+                Ordering::Equal => matches!(a, Bound::Excluded(_)),
+                ord => (ord == Ordering::Greater) == lower,
+            },
+        };
+        if a_is_tighter {
+            a
+        } else {
+            b
+        }
+    }
+
+    /// Intersects each [`IndexScan`] with an earlier one over the same columns of the same table,
+    /// provided only filters come between the two, so that the rows within both ranges aren't scanned twice.
+    ///
+    /// The earlier scan keeps its place, with its bounds narrowed to the intersection.
+    /// If the ranges are disjoint, the intersection [is never satisfied](IndexScan::is_never).
+    fn try_intersect_index_scans(self) -> QueryExpr {
+        let mut query: Vec<Query> = Vec::with_capacity(self.query.len());
+        for op in self.query {
+            let Query::IndexScan(scan) = op else {
+                query.push(op);
+                continue;
+            };
+            let earlier = query
+                .iter_mut()
+                .rev()
+                .take_while(|op| matches!(op, Query::Select(_) | Query::IndexScan(_)))
+                .find_map(|op| match op {
+                    Query::IndexScan(earlier)
+                        if earlier.table.table_id == scan.table.table_id && earlier.columns == scan.columns =>
+                    {
+                        Some(earlier)
+                    }
+                    _ => None,
+                });
+            match earlier {
+                Some(earlier) => {
+                    let (lower, upper) = mem::replace(&mut earlier.bounds, (Bound::Unbounded, Bound::Unbounded));
+                    let (other_lower, other_upper) = scan.bounds;
+                    earlier.bounds = (
+                        Self::tighter_bound(lower, other_lower, true),
+                        Self::tighter_bound(upper, other_upper, false),
+                    );
+                }
+                None => query.push(Query::IndexScan(scan)),
+            }
+        }
+        QueryExpr {
+            source: self.source,
+            query,
+        }
+    }
+
     /// Try to turn an inner join followed by a projection into a semijoin.
     ///
     /// This optimization recognizes queries of the form:
@@ -1569,7 +4396,7 @@ impl QueryExpr {
     ///   query: [
     ///     JoinInner(JoinExpr {
     ///       rhs: RHS,
-    ///       semi: false,
+    ///       kind: JoinKind::Inner,
     ///       ..
     ///     }),
     ///     Project(LHS.*),
@@ -1578,7 +4405,7 @@ impl QueryExpr {
     /// }
     /// ```
     ///
-    /// And combines the `JoinInner` with the `Project` into a `JoinInner` with `semi: true`.
+    /// And combines the `JoinInner` with the `Project` into a `JoinInner` with `kind: JoinKind::Semi`.
     ///
     /// Current limitations of this optimization:
     /// - The `JoinInner` must be the first (0th) element of the `query`.
@@ -1588,7 +4415,7 @@ impl QueryExpr {
     ///   which is fundamentally limited to operate on the first expr.
     ///   Note that we still get to optimize incremental joins, because we first optimize the original query
     ///   with [`DbTable`] sources, which results in an [`IndexJoin`]
-    ///   then we replace the sources with [`MemTable`]s and go back to a [`JoinInner`] with `semi: true`.
+    ///   then we replace the sources with [`MemTable`]s and go back to a [`JoinInner`] with `kind: JoinKind::Semi`.
     /// - The `Project` must immediately follow the `JoinInner`, with no intervening exprs.
     ///   Future work could search through intervening exprs to detect that the RHS table is unused.
     /// - The LHS/source table must be a [`DbTable`], not a [`MemTable`].
@@ -1611,7 +4438,7 @@ impl QueryExpr {
             rhs,
             col_lhs,
             col_rhs,
-            semi: false,
+            kind: JoinKind::Inner,
         }) = join_candidate
         else {
             // First (0th) expr is not an inner join. Bail.
@@ -1629,12 +4456,13 @@ impl QueryExpr {
                     rhs,
                     col_lhs,
                     col_rhs,
-                    semi: false,
+                    kind: JoinKind::Inner,
                 })],
             };
         };
-        let Query::Project(cols, Some(wildcard_table_id)) = project_candidate else {
-            // Second (1st) expr is not a wildcard projection. Bail.
+        // Deduplicating the rows changes the result, so a distinct projection must be kept.
+        let Query::Project(cols, Some(wildcard_table_id), false) = project_candidate else {
+            // Second (1st) expr is not a wildcard projection, or is a distinct one. Bail.
             return QueryExpr {
                 source,
                 query: itertools::chain![
@@ -1642,7 +4470,7 @@ impl QueryExpr {
                         rhs,
                         col_lhs,
                         col_rhs,
-                        semi: false
+                        kind: JoinKind::Inner
                     })),
                     Some(project_candidate),
                     exprs
@@ -1651,8 +4479,10 @@ impl QueryExpr {
             };
         };
 
-        if wildcard_table_id != source_table_id {
-            // Projection is selecting the RHS table. Bail.
+        // An aliased column isn't merely a column of the LHS,
+        // so the projection must be kept.
+        if wildcard_table_id != source_table_id || is_aliased(&cols) {
+            // Projection is selecting the RHS table or renaming a column. Bail.
             return QueryExpr {
                 source,
                 query: itertools::chain![
@@ -1660,9 +4490,9 @@ impl QueryExpr {
                         rhs,
                         col_lhs,
                         col_rhs,
-                        semi: false
+                        kind: JoinKind::Inner
                     })),
-                    Some(Query::Project(cols, Some(wildcard_table_id))),
+                    Some(Query::Project(cols, Some(wildcard_table_id), false)),
                     exprs
                 ]
                 .collect(),
@@ -1674,7 +4504,7 @@ impl QueryExpr {
             rhs,
             col_lhs,
             col_rhs,
-            semi: true,
+            kind: JoinKind::Semi,
         };
 
         QueryExpr {
@@ -1683,6 +4513,105 @@ impl QueryExpr {
         }
     }
 
+    /// Try to push a projection following an inner join into the join's rhs.
+    ///
+    /// This optimization recognizes queries of the form:
+    ///
+    /// ```ignore
+    /// QueryExpr {
+    ///   source: LHS,
+    ///   query: [
+    ///     ...
+    ///     JoinInner(JoinExpr {
+    ///       rhs: RHS,
+    ///       col_rhs: RHS.id,
+    ///       kind: JoinKind::Inner,
+    ///       ..
+    ///     }),
+    ///     Project([LHS.a, RHS.b], None),
+    ///     ...
+    ///   ]
+    /// }
+    /// ```
+    ///
+    /// And appends `Project([RHS.id, RHS.b], None)` to `RHS`,
+    /// so that the join does not carry the other columns of `RHS` only to drop them right after.
+    /// The join column `col_rhs` is always kept, as the join needs it.
+    ///
+    /// Current limitations of this optimization:
+    /// - The `Project` must immediately follow the `JoinInner` and must not be a wildcard projection.
+    ///   A wildcard projection of the LHS is better served by `try_semi_join`.
+    /// - `RHS` may only contain operations which preserve the header of its source,
+    ///   i.e., no nested joins or projections.
+    /// - The table of `RHS` must appear only once in the query,
+    ///   as otherwise its fields could also refer to another side of a join.
+    fn try_project_pushdown(mut self) -> QueryExpr {
+        let table_ids: Vec<_> = self.sources().map(|source| source.head().table_id).collect();
+
+        for idx in 1..self.query.len() {
+            let (joins, projects) = self.query.split_at_mut(idx);
+            let (Query::JoinInner(join), Query::Project(cols, None, _)) = (&mut joins[idx - 1], &projects[0]) else {
+                continue;
+            };
+            if join.kind.returns_lhs_only() {
+                // The rhs columns aren't carried through a semi- or antijoin anyway.
+                continue;
+            }
+            let preserves_header = join
+                .rhs
+                .query
+                .iter()
+                .all(|op| matches!(op, Query::Select(_) | Query::IndexScan(_) | Query::IndexMultiScan(_)));
+            let rhs_head = join.rhs.source.head();
+            let rhs_table_id = rhs_head.table_id;
+            if !preserves_header || table_ids.iter().filter(|id| **id == rhs_table_id).count() != 1 {
+                continue;
+            }
+
+            // Keep the fields of the rhs which are either projected or the join column,
+            // in the order of the rhs header.
+            let projected = |field: &FieldName| {
+                *field == join.col_rhs
+                    || cols
+                        .iter()
+                        .any(|(col, _)| matches!(col, FieldExpr::Name(name) if name == field))
+            };
+            let fields: Vec<_> = rhs_head
+                .fields
+                .iter()
+                .map(|col| col.field)
+                .filter(projected)
+                .map(|field| (FieldExpr::Name(field), None))
+                .collect();
+            if fields.len() == rhs_head.fields.len() {
+                // Every column of the rhs is needed, so there's nothing to trim.
+                continue;
+            }
+            join.rhs.query.push(Query::Project(fields, None, false));
+        }
+
+        self
+    }
+
+    /// Moves each [`Query::Limit`] below the projections directly preceding it.
+    ///
+    /// Projecting doesn't change the number of rows,
+    /// so limiting them first means only the rows that are kept get projected.
+    /// A distinct projection may drop rows, so a limit is never moved below one.
+    fn try_limit_pushdown(mut self) -> QueryExpr {
+        for idx in 1..self.query.len() {
+            if !matches!(self.query[idx], Query::Limit { .. }) {
+                continue;
+            }
+            let mut pos = idx;
+            while pos > 0 && matches!(self.query[pos - 1], Query::Project(_, _, false)) {
+                self.query.swap(pos - 1, pos);
+                pos -= 1;
+            }
+        }
+        self
+    }
+
     // Try to turn an applicable join into an index join.
     // An applicable join is one that can use an index to probe the lhs.
     // It must also project only the columns from the lhs.
@@ -1691,7 +4620,11 @@ impl QueryExpr {
     // where `Left` has an index defined on `id`.
     fn try_index_join(self) -> QueryExpr {
         let mut query = self;
-        // We expect a single operation - an inner join with `semi: true`.
+        // A join on multiple columns isn't a semijoin yet, so it's handled separately.
+        if query.query.len() == 3 {
+            return query.try_composite_index_join();
+        }
+        // We expect a single operation - an inner join with `kind: JoinKind::Semi`.
         // These can be transformed by `try_semi_join` from a sequence of two queries, an inner join followed by a wildcard project.
         if query.query.len() != 1 {
             return query;
@@ -1710,7 +4643,7 @@ impl QueryExpr {
                 rhs: probe_side,
                 col_lhs: index_field,
                 col_rhs: probe_field,
-                semi: true,
+                kind: JoinKind::Semi,
             }) => {
                 if !probe_side.query.is_empty() {
                     // An applicable join must have an index defined on the correct field.
@@ -1718,11 +4651,12 @@ impl QueryExpr {
                         if source.head().has_constraint(index_field, Constraints::indexed()) {
                             let index_join = IndexJoin {
                                 probe_side,
-                                probe_field,
+                                probe_fields: smallvec![probe_field],
                                 index_side: source.clone(),
                                 index_select: None,
-                                index_col,
+                                index_cols: index_col.into(),
                                 return_index_rows: true,
+                                projection: None,
                             };
                             let query = [Query::IndexJoin(index_join)].into();
                             return QueryExpr { source, query };
@@ -1733,7 +4667,7 @@ impl QueryExpr {
                     rhs: probe_side,
                     col_lhs: index_field,
                     col_rhs: probe_field,
-                    semi: true,
+                    kind: JoinKind::Semi,
                 });
                 QueryExpr {
                     source,
@@ -1747,40 +4681,318 @@ impl QueryExpr {
         }
     }
 
-    /// Look for filters that could use indexes
-    fn optimize_select(mut q: QueryExpr, op: ColumnOp, tables: &[SourceExpr]) -> QueryExpr {
-        // Go through each table schema referenced in the query.
-        // Find the first sargable condition and short-circuit.
-        let mut fields_found = HashSet::new();
-        for schema in tables {
-            for op in find_sargable_ops(&mut fields_found, schema.head(), &op) {
-                match &op {
-                    IndexColumnOp::Index(_) | IndexColumnOp::Scan(ColumnOp::Field(_)) => {}
-                    // Remove a duplicated/redundant operation on the same `field` and `op`
-                    // like `[ScanOrIndex::Index(a = 1), ScanOrIndex::Index(a = 1), ScanOrIndex::Scan(a = 1)]`
-                    IndexColumnOp::Scan(ColumnOp::Cmp { op, lhs, rhs: _ }) => {
-                        if let (ColumnOp::Field(FieldExpr::Name(col)), OpQuery::Cmp(cmp)) = (&**lhs, op) {
-                            if !fields_found.insert((*col, *cmp)) {
-                                continue;
-                            }
-                        }
-                    }
-                }
+    // Try to turn a join on multiple columns into an index join on a composite index.
+    // An applicable join equates the first pair of columns in the join itself
+    // and the other pairs in a filter directly following it,
+    // and is followed by a wildcard projection of the source table.
+    // As for single column index joins, the probe side must have operations of its own.
+    //
+    // Ex. SELECT Left.* FROM Left JOIN Right ON Left.a = Right.a WHERE Left.b = Right.b AND Right.c = 3
+    // where `Left` has an index on `(a, b)`.
+    fn try_composite_index_join(self) -> QueryExpr {
+        let (
+            Some(source_table_id),
+            [Query::JoinInner(join), Query::Select(filter), Query::Project(cols, Some(wildcard_table_id), false)],
+        ) = (self.source.table_id(), &*self.query)
+        else {
+            return self;
+        };
+        // The two sides must be told apart by their table.
+        if join.kind != JoinKind::Inner
+            || join.rhs.query.is_empty()
+            || *wildcard_table_id != source_table_id
+            || is_aliased(cols)
+            || join.rhs.source.table_id() == Some(source_table_id)
+        {
+            return self;
+        }
 
-                match op {
-                    IndexColumnOp::Index(idx) => match idx {
-                        // Found sargable equality condition for one of the table schemas.
-                        IndexArgument::Eq { columns, value } => {
-                            // `unwrap`  here is infallible because `is_sargable(schema, op)` implies `schema.is_db_table`
-                            // for any `op`.
-                            q = q.with_index_eq(schema.get_db_table().unwrap().clone(), columns.clone(), value);
-                        }
-                        // Found sargable range condition for one of the table schemas.
-                        IndexArgument::LowerBound {
-                            columns,
-                            value,
-                            inclusive,
-                        } => {
+        // Collect the `(index column, probe field)` pairs the join and the filter equate.
+        let index_head = self.source.head();
+        let probe_head = join.rhs.source.head();
+        let key = |index_field: FieldName, probe_field: FieldName| {
+            let index_col = index_head.column_pos(index_field)?;
+            probe_head.column_pos(probe_field)?;
+            Some((index_col, probe_field))
+        };
+        let Some(first_key) = key(join.col_lhs, join.col_rhs) else {
+            return self;
+        };
+        let mut keys: SmallVec<[_; 2]> = smallvec![first_key];
+        for op in filter.flatten_ands_ref() {
+            let ColumnOp::Cmp {
+                op: OpQuery::Cmp(OpCmp::Eq),
+                lhs,
+                rhs,
+            } = op
+            else {
+                return self;
+            };
+            let (ColumnOp::Field(FieldExpr::Name(lhs)), ColumnOp::Field(FieldExpr::Name(rhs))) = (&**lhs, &**rhs)
+            else {
+                return self;
+            };
+            // The equality may be written either way around.
+            let Some(pair) = key(*lhs, *rhs).or_else(|| key(*rhs, *lhs)) else {
+                return self;
+            };
+            keys.push(pair);
+        }
+        keys.sort_unstable_by_key(|(col, _)| *col);
+        // Each index column must be joined with a single probe field.
+        if keys.len() < 2 || keys.windows(2).any(|w| w[0].0 == w[1].0) {
+            return self;
+        }
+        let index_cols = keys
+            .iter()
+            .map(|(col, _)| *col)
+            .collect::<ColListBuilder>()
+            .build()
+            .unwrap();
+        if !is_indexed_on(index_head, &index_cols) {
+            return self;
+        }
+
+        let Some(Query::JoinInner(join)) = self.query.into_iter().next() else {
+            unreachable!()
+        };
+        let index_join = IndexJoin {
+            probe_side: join.rhs,
+            probe_fields: keys.into_iter().map(|(_, field)| field).collect(),
+            index_side: self.source.clone(),
+            index_select: None,
+            index_cols,
+            return_index_rows: true,
+            projection: None,
+        };
+        QueryExpr {
+            source: self.source,
+            query: vec![Query::IndexJoin(index_join)],
+        }
+    }
+
+    // Try to fuse a projection directly following an index join into the join,
+    // so that the join emits only the projected columns of the rows it returns.
+    // An applicable projection renames no columns, keeps duplicate rows,
+    // and reads only columns of the side whose rows the join returns.
+    //
+    // Ex. an index join probing the index of `Left` on `id` and returning the rows of `Left`,
+    // followed by a projection onto `Left.a`.
+    fn try_index_join_projection(mut self) -> QueryExpr {
+        let [Query::IndexJoin(join), Query::Project(cols, _, false)] = &mut *self.query else {
+            return self;
+        };
+        let returned = if join.return_index_rows {
+            join.index_side.head()
+        } else {
+            join.probe_side.source.head()
+        };
+        let fusable = join.projection.is_none()
+            && !is_aliased(cols)
+            && cols.iter().all(|(col, _)| match col {
+                FieldExpr::Name(field) => returned.column_pos(*field).is_some(),
+                FieldExpr::Value(_) => true,
+            });
+        if fusable {
+            join.projection = Some(cols.drain(..).map(|(col, _)| col).collect());
+            self.query.pop();
+        }
+        self
+    }
+
+    // Try to mark a leading index scan as covering,
+    // i.e., answerable from its index alone, as every column the rest of the query reads is in the index.
+    // An applicable scan is followed only by filters on columns of the index and limits,
+    // and then by a projection onto columns of the index.
+    // A filter on any other column needs the full row, and so does a query without a projection.
+    //
+    // Ex. SELECT a FROM t WHERE a > 5
+    // where `t` has an index on `a`.
+    fn try_covering_index_scan(mut self) -> QueryExpr {
+        let Some((Query::IndexScan(scan), rest)) = self.query.split_first_mut() else {
+            return self;
+        };
+        let (table_id, columns) = (scan.table.table_id, &scan.columns);
+        let in_index = |field: &FieldName| field.table == table_id && columns.contains(field.col);
+
+        let mut ops = rest.iter();
+        let covering = loop {
+            match ops.next() {
+                Some(Query::Select(op)) if op.referenced_fields().iter().all(in_index) => {}
+                Some(Query::Limit { .. }) => {}
+                Some(Query::Project(cols, _, _)) => {
+                    break cols.iter().all(|(col, _)| match col {
+                        FieldExpr::Name(field) => in_index(field),
+                        FieldExpr::Value(_) => true,
+                    })
+                }
+                _ => break false,
+            }
+        };
+        scan.covering = covering;
+        self
+    }
+
+    // Try to turn a join at the start of the query into a merge join.
+    // An applicable join reads both of its tables in full,
+    // i.e., neither side has operations of its own,
+    // and both tables have an index on exactly their join column.
+    //
+    // Ex. SELECT * FROM Left JOIN Right ON Left.id = Right.id
+    // where both `Left` and `Right` have an index on `id`.
+    fn try_merge_join(mut self) -> QueryExpr {
+        let is_index_ordered = |table: &DbTable, field: FieldName| {
+            table.head.column_pos(field).is_some_and(|col| {
+                table
+                    .head
+                    .constraints
+                    .iter()
+                    .any(|(cols, constraints)| *cols == ColList::new(col) && constraints.has_indexed())
+            })
+        };
+
+        let (SourceExpr::DbTable(lhs), Some(Query::JoinInner(join))) = (&self.source, self.query.first()) else {
+            return self;
+        };
+        let SourceExpr::DbTable(rhs) = &join.rhs.source else {
+            return self;
+        };
+        // A merge join has no antijoin mode.
+        if join.kind == JoinKind::Anti
+            || !join.rhs.query.is_empty()
+            || !is_index_ordered(lhs, join.col_lhs)
+            || !is_index_ordered(rhs, join.col_rhs)
+        {
+            return self;
+        }
+
+        let merge_join = MergeJoinExpr {
+            lhs: lhs.clone(),
+            rhs: rhs.clone(),
+            col_lhs: join.col_lhs,
+            col_rhs: join.col_rhs,
+            semi: join.kind == JoinKind::Semi,
+        };
+        self.query[0] = Query::MergeJoin(merge_join);
+        self
+    }
+
+    // Try to turn semijoins with an in-memory table of a single column into `SemiJoinInMemory`s,
+    // which probe a hash set of the values of that column.
+    // An applicable join reads the table in full, at most projecting it to the join column.
+    //
+    // Ex. SELECT Left.* FROM Left JOIN Ids ON Left.id = Ids.id
+    // where `Ids` is an in-memory table whose only column is `id`.
+    fn try_semi_join_in_memory(mut self) -> QueryExpr {
+        for query in &mut self.query {
+            let Query::JoinInner(JoinExpr {
+                rhs,
+                col_lhs,
+                col_rhs,
+                kind: JoinKind::Semi,
+            }) = query
+            else {
+                continue;
+            };
+            let is_key_column = matches!(&*rhs.source.head().fields, [key] if key.field == *col_rhs);
+            let reads_key = match &*rhs.query {
+                [] => true,
+                [Query::Project(cols, _, _)] => matches!(&**cols, [(FieldExpr::Name(field), _)] if *field == *col_rhs),
+                _ => false,
+            };
+            if rhs.source.is_mem_table() && is_key_column && reads_key {
+                *query = Query::SemiJoinInMemory {
+                    source: rhs.source.clone(),
+                    probe_field: *col_lhs,
+                };
+            }
+        }
+        self
+    }
+
+    /// Look for filters that could use indexes
+    ///
+    /// At most `max_expansion` index seeks are planned for the IN-lists of `op`;
+    /// any IN-list beyond that is served by a scan.
+    fn optimize_select(
+        mut q: QueryExpr,
+        op: ColumnOp,
+        tables: &[SourceExpr],
+        max_expansion: usize,
+        on_seq_scan: &mut impl FnMut(&DbTable),
+    ) -> QueryExpr {
+        // Go through each table schema referenced in the query.
+        // Find the first sargable condition and short-circuit.
+        let mut fields_found = HashSet::new();
+        let mut expansion = 0;
+        // Tables without column statistics are planned without hints.
+        let no_stats = ColumnStatsMap::new();
+        let referenced = op.referenced_fields();
+        // An IN-list on the columns of one of `tables` is planned when that table is visited,
+        // and one on the columns of any other table is kept as a filter when the first table is visited.
+        // Unlike `field = val`, it cannot be deduplicated by `(field, cmp)`.
+        let plans_in_list = |idx: usize, field: Option<&FieldName>| {
+            let owner = field.and_then(|field| tables.iter().position(|t| t.head().table_id == field.table));
+            owner.unwrap_or(0) == idx
+        };
+        for (idx, schema) in tables.iter().enumerate() {
+            let stats = schema.column_stats().unwrap_or(&no_stats);
+            let mut ops = find_sargable_ops(&mut fields_found, schema.head(), stats, &op);
+            // Plan the index seeks first, as a seek can't follow a selection,
+            // e.g., one demoted in favor of a more selective seek.
+            ops.sort_by_key(|op| matches!(op, IndexColumnOp::Scan(_)));
+            // A filter that no index can serve falls back to a sequential scan of the table.
+            // `MemTable`s are never indexed, so they are always scanned and not reported.
+            // Nor are the other tables of the query, e.g., of a join, which the filter doesn't read.
+            if let Some(table) = schema.get_db_table() {
+                let is_scan = |op: &IndexColumnOp<'_>| matches!(op, IndexColumnOp::Scan(_));
+                let filters_table = referenced.iter().any(|field| table.head.column_pos(*field).is_some());
+                if filters_table && !ops.is_empty() && ops.iter().all(is_scan) {
+                    on_seq_scan(table);
+                }
+            }
+            for op in ops {
+                match &op {
+                    IndexColumnOp::Index(_) | IndexColumnOp::Scan(ColumnOp::Field(_) | ColumnOp::Len(_)) => {}
+                    IndexColumnOp::Scan(ColumnOp::In { field, .. }) => {
+                        if !plans_in_list(idx, Some(field)) {
+                            continue;
+                        }
+                    }
+                    IndexColumnOp::Scan(ColumnOp::InTuples { fields, .. }) => {
+                        if !plans_in_list(idx, fields.first()) {
+                            continue;
+                        }
+                    }
+                    // Remove a duplicated/redundant operation on the same `field` and `op`
+                    // like `[ScanOrIndex::Index(a = 1), ScanOrIndex::Index(a = 1), ScanOrIndex::Scan(a = 1)]`
+                    IndexColumnOp::Scan(ColumnOp::Cmp { op, lhs, rhs: _ }) => {
+                        if let (ColumnOp::Field(FieldExpr::Name(col)), OpQuery::Cmp(cmp)) = (&**lhs, op) {
+                            if !fields_found.insert((*col, *cmp)) {
+                                continue;
+                            }
+                        }
+                    }
+                }
+
+                match op {
+                    IndexColumnOp::Index(idx) => match idx {
+                        // Found sargable equality condition for one of the table schemas.
+                        IndexArgument::Eq { columns, value } => {
+                            // `unwrap`  here is infallible because `is_sargable(schema, op)` implies `schema.is_db_table`
+                            // for any `op`.
+                            q = q.with_index_eq(schema.get_db_table().unwrap().clone(), columns.clone(), value);
+                        }
+                        // Found sargable equality condition on the leading columns of a multi-column index.
+                        IndexArgument::PrefixEq { columns, value } => {
+                            q = q.with_index_eq(schema.get_db_table().unwrap().clone(), columns, value);
+                        }
+                        // Found sargable range condition for one of the table schemas.
+                        IndexArgument::LowerBound {
+                            columns,
+                            value,
+                            inclusive,
+                        } => {
                             // `unwrap`  here is infallible because `is_sargable(schema, op)` implies `schema.is_db_table`
                             // for any `op`.
                             q = q.with_index_lower_bound(
@@ -1803,9 +5015,55 @@ impl QueryExpr {
                                 inclusive,
                             );
                         }
+                        // Found sargable IN-list of contiguous values for one of the table schemas.
+                        IndexArgument::Range { columns, lower, upper } => {
+                            let table = schema.get_db_table().unwrap();
+                            q = q.with_index_range(
+                                table.clone(),
+                                columns.clone(),
+                                Bound::Included(lower),
+                                Bound::Included(upper),
+                            );
+                        }
+                        // Found sargable IN-list for one of the table schemas.
+                        IndexArgument::In { columns, values } => {
+                            let table = schema.get_db_table().unwrap();
+                            if expansion + values.len() > max_expansion {
+                                log::warn!(
+                                    "IN-list of {} values exceeds the maximum expansion of {max_expansion} index seeks, falling back to a scan",
+                                    values.len()
+                                );
+                                let mut fields = columns.iter().map(|col| table.head.fields[col.idx()].field);
+                                q = q.with_select(if columns.is_singleton() {
+                                    ColumnOp::in_list(fields.next().unwrap(), values)
+                                } else {
+                                    ColumnOp::in_tuples(fields, values)
+                                });
+                            } else {
+                                expansion += values.len();
+                                q = q.with_index_in(table.clone(), columns.clone(), values);
+                            }
+                        }
+                        // Found sargable inequality on a low-cardinality column for one of the table schemas.
+                        IndexArgument::NotEq { columns, value } => {
+                            let table = schema.get_db_table().unwrap();
+                            let ranges = vec![
+                                (Bound::Unbounded, Bound::Excluded(value.clone())),
+                                (Bound::Excluded(value), Bound::Unbounded),
+                            ];
+                            q = q.with_index_ranges(table.clone(), columns.clone(), ranges);
+                        }
                     },
                     // Filter condition cannot be answered using an index.
-                    IndexColumnOp::Scan(scan) => q = q.with_select(scan.clone()),
+                    IndexColumnOp::Scan(scan) => {
+                        // A tuple IN-list without an index on its columns
+                        // is served by scanning for each tuple's conjunction of equalities.
+                        let expanded = match scan {
+                            ColumnOp::InTuples { fields, values } => ColumnOp::expand_in_tuples(fields, values),
+                            _ => None,
+                        };
+                        q = q.with_select(expanded.unwrap_or_else(|| scan.clone()));
+                    }
                 }
             }
         }
@@ -1813,828 +5071,4880 @@ impl QueryExpr {
         q
     }
 
-    pub fn optimize(mut self, row_count: &impl Fn(TableId, &str) -> i64) -> Self {
-        let mut q = Self {
-            source: self.source.clone(),
-            query: Vec::with_capacity(self.query.len()),
-        };
-
-        let tables = self.sources();
-        let tables: Vec<_> = core::iter::once(QuerySources::One(tables.head))
-            .chain(tables.tail)
-            .flat_map(|x| x.into_iter())
-            .collect();
-
-        if matches!(&*self.query, [Query::IndexJoin(_)]) {
-            if let Some(Query::IndexJoin(join)) = self.query.pop() {
-                q.query.push(Query::IndexJoin(join.reorder(row_count)));
-                return q;
-            }
-        }
-
-        for query in self.query {
-            match query {
-                Query::Select(op) => {
-                    q = Self::optimize_select(q, op, &tables);
+    /// Returns a best-effort estimate of the number of rows produced by each stage of this query,
+    /// using the table sizes given by `row_count`.
+    ///
+    /// The first estimate is for the [`SourceExpr`],
+    /// followed by one for the output of each operator in [`QueryExpr::query`].
+    /// Filters scale the estimate by a fixed selectivity per comparison,
+    /// and joins account for the fan-out permitted by unique constraints on the join columns;
+    /// e.g., an equality seek on a unique index yields at most one row.
+    /// The `max` of an estimate is the estimated number of rows, rather than a strict upper bound.
+    ///
+    /// A table whose `row_count` is negative, and operators that cannot be reasoned about,
+    /// such as [`Query::Unnest`], yield [`RowCount::unknown`].
+    pub fn estimated_rows(&self, row_count: &impl Fn(TableId, &str) -> i64) -> Vec<RowCount> {
+        let head = self.source.head();
+        let mut rows = source_rows(&self.source, row_count);
+        let mut estimates = Vec::with_capacity(self.query.len() + 1);
+        estimates.push(rows);
+        for op in &self.query {
+            rows = match op {
+                Query::IndexScan(scan) if is_unique(&scan.table.head, &scan.columns) && scan.is_point() => RowCount {
+                    min: 0,
+                    max: rows.max.map(|max| max.min(1)),
+                },
+                Query::IndexScan(scan) => filter_rows(rows, bounds_selectivity(&scan.bounds)),
+                Query::IndexMultiScan(scan) => {
+                    let selectivity = scan.ranges.iter().map(bounds_selectivity).sum::<f64>();
+                    filter_rows(rows, selectivity.min(1.0))
                 }
-                Query::JoinInner(join) => {
-                    q = q.with_join_inner(join.rhs.optimize(row_count), join.col_lhs, join.col_rhs, join.semi);
+                Query::Select(op) => filter_rows(rows, op.selectivity()),
+                Query::Project(..) => rows,
+                // Any row of the lhs may lack a match, and none is returned twice.
+                Query::JoinInner(join) if join.kind == JoinKind::Anti => RowCount { min: 0, max: rows.max },
+                Query::JoinInner(join) => join_rows(
+                    rows,
+                    join.rhs.estimated_output_rows(row_count),
+                    is_unique_field(head, join.col_lhs),
+                    is_unique_field(join.rhs.source.head(), join.col_rhs),
+                    join.kind == JoinKind::Semi,
+                ),
+                // Every row of the lhs is returned at least once.
+                Query::JoinLeft(join) => {
+                    let joined = join_rows(
+                        rows,
+                        join.rhs.estimated_output_rows(row_count),
+                        is_unique_field(head, join.col_lhs),
+                        is_unique_field(join.rhs.source.head(), join.col_rhs),
+                        false,
+                    );
+                    RowCount {
+                        min: rows.min,
+                        max: joined.max.zip(rows.max).map(|(joined, rows)| joined.max(rows)),
+                    }
+                }
+                // The values of the source are hashed into a set, so they're unique.
+                Query::SemiJoinInMemory { source, probe_field } => join_rows(
+                    rows,
+                    source_rows(source, row_count),
+                    is_unique_field(head, *probe_field),
+                    true,
+                    true,
+                ),
+                Query::MergeJoin(join) => join_rows(
+                    table_rows(&join.lhs, row_count),
+                    table_rows(&join.rhs, row_count),
+                    is_unique_field(&join.lhs.head, join.col_lhs),
+                    is_unique_field(&join.rhs.head, join.col_rhs),
+                    join.semi,
+                ),
+                Query::IndexJoin(join) => {
+                    let index_rows = source_rows(&join.index_side, row_count);
+                    let index_rows = filter_rows(
+                        index_rows,
+                        join.index_select.as_ref().map_or(1.0, ColumnOp::selectivity),
+                    );
+                    join_rows(
+                        join.probe_side.estimated_output_rows(row_count),
+                        index_rows,
+                        match &*join.probe_fields {
+                            [field] => is_unique_field(join.probe_side.source.head(), *field),
+                            _ => false,
+                        },
+                        is_unique(join.index_side.head(), &join.index_cols),
+                        false,
+                    )
+                }
+                Query::Unnest(_) => RowCount::unknown(),
+                Query::NeverReturns => RowCount::exact(0),
+                Query::Limit { limit, offset } => {
+                    let limit_rows = |rows: usize| {
+                        let rows = rows.saturating_sub(usize::try_from(*offset).unwrap_or(usize::MAX));
+                        rows.min(usize::try_from(*limit).unwrap_or(usize::MAX))
+                    };
+                    RowCount {
+                        min: limit_rows(rows.min),
+                        max: Some(rows.max.map_or(limit_rows(usize::MAX), limit_rows)),
+                    }
                 }
-                _ => q.query.push(query),
             };
+            estimates.push(rows);
         }
-
-        // Make sure to `try_semi_join` before `try_index_join`, as the latter depends on the former.
-        let q = q.try_semi_join();
-        let q = q.try_index_join();
-        if matches!(&*q.query, [Query::IndexJoin(_)]) {
-            return q.optimize(row_count);
-        }
-        q
+        estimates
     }
-}
 
-/// Iterator created by the [`QueryExpr::sources`] method.
-#[must_use = "iterators are lazy and do nothing unless consumed"]
-pub struct QueryExprSources {
-    head: Option<SourceExpr>,
-    tail: VecDeque<QuerySources>,
-}
+    /// Returns a best-effort estimate of the number of rows this query produces,
+    /// i.e., the last of [`QueryExpr::estimated_rows`].
+    pub fn estimated_output_rows(&self, row_count: &impl Fn(TableId, &str) -> i64) -> RowCount {
+        let mut estimates = self.estimated_rows(row_count);
+        estimates.pop().unwrap_or_else(RowCount::unknown)
+    }
 
-impl Iterator for QueryExprSources {
-    type Item = SourceExpr;
+    /// Returns an error if this query is nested deeper than `max_depth`,
+    /// counting both its joined subqueries and the predicates of its selections;
+    /// see [`ColumnOp::depth`].
+    ///
+    /// The passes over a query, e.g., [`QueryExpr::optimize`], recurse over its structure,
+    /// so this should guard queries built from untrusted input before processing them.
+    pub fn validate_depth(&self, max_depth: usize) -> Result<(), ErrorVm> {
+        let depth = self.depth();
+        if depth > max_depth {
+            return Err(ErrorVm::TooDeep { depth, max_depth });
+        }
+        Ok(())
+    }
 
-    fn next(&mut self) -> Option<Self::Item> {
-        self.head.take().or_else(|| {
-            while let Some(cur) = self.tail.front_mut() {
-                match cur.next() {
-                    None => {
-                        self.tail.pop_front();
-                        continue;
+    /// Returns the nesting depth of this query,
+    /// i.e., 1 plus the depth of its deepest predicate or joined subquery.
+    ///
+    /// Like [`ColumnOp::depth`], this is computed without recursion.
+    fn depth(&self) -> usize {
+        let mut max = 0;
+        let mut stack = vec![(self, 1)];
+        while let Some((query, depth)) = stack.pop() {
+            max = max.max(depth);
+            for op in &query.query {
+                match op {
+                    Query::Select(op) => max = max.max(depth + op.depth()),
+                    Query::JoinInner(join) | Query::JoinLeft(join) => stack.push((&join.rhs, depth + 1)),
+                    Query::IndexJoin(join) => {
+                        if let Some(op) = &join.index_select {
+                            max = max.max(depth + op.depth());
+                        }
+                        stack.push((&join.probe_side, depth + 1));
                     }
-                    Some(src) => return Some(src),
+                    _ => {}
                 }
             }
-
-            None
-        })
-    }
-}
-
-impl AuthAccess for Query {
-    fn check_auth(&self, owner: Identity, caller: Identity) -> Result<(), AuthError> {
-        if owner == caller {
-            return Ok(());
         }
+        max
+    }
 
-        for table in self.sources() {
-            if table.table_access() == StAccess::Private {
-                return Err(AuthError::TablePrivate {
-                    named: table.table_name().to_owned(),
-                });
+    /// Returns whether an inner join of this query, or of any joined subquery,
+    /// fails to relate its two sides and may thus produce their cartesian product.
+    ///
+    /// That's the case when the join keys are fields of the same table,
+    /// or when a key is absent from the header of its side of the join.
+    pub fn has_unconstrained_join(&self) -> bool {
+        // The lhs of a join sees the fields of the source and of the preceding inner joins.
+        let mut lhs_heads = vec![self.source.head()];
+        for op in &self.query {
+            match op {
+                Query::JoinInner(join) => {
+                    let rhs_head = join.rhs.source.head();
+                    if join.col_lhs.table == join.col_rhs.table
+                        || !lhs_heads.iter().any(|head| head.column_pos(join.col_lhs).is_some())
+                        || rhs_head.column_pos(join.col_rhs).is_none()
+                        || join.rhs.has_unconstrained_join()
+                    {
+                        return true;
+                    }
+                    if !join.kind.returns_lhs_only() {
+                        lhs_heads.push(rhs_head);
+                    }
+                }
+                Query::JoinLeft(join) => {
+                    let rhs_head = join.rhs.source.head();
+                    if join.col_lhs.table == join.col_rhs.table
+                        || !lhs_heads.iter().any(|head| head.column_pos(join.col_lhs).is_some())
+                        || rhs_head.column_pos(join.col_rhs).is_none()
+                        || join.rhs.has_unconstrained_join()
+                    {
+                        return true;
+                    }
+                    lhs_heads.push(rhs_head);
+                }
+                Query::IndexJoin(join) if join.probe_side.has_unconstrained_join() => return true,
+                _ => {}
             }
         }
-
-        Ok(())
+        false
     }
-}
-
-#[derive(Debug, Eq, PartialEq, From)]
-pub enum Expr {
-    #[from]
-    Value(AlgebraicValue),
-    Block(Vec<Expr>),
-    Ident(String),
-    Crud(Box<CrudExpr>),
-    Halt(ErrorLang),
-}
 
-impl From<QueryExpr> for Expr {
-    fn from(x: QueryExpr) -> Self {
-        Expr::Crud(Box::new(CrudExpr::Query(x)))
+    /// Returns the number of each kind of operator in this query,
+    /// summed over the query and its joined subqueries,
+    /// e.g., to reject plans exceeding a complexity budget before running them.
+    pub fn operator_counts(&self) -> OperatorCounts {
+        let mut counts = OperatorCounts::default();
+        self.count_operators(&mut counts);
+        counts
     }
-}
 
-impl fmt::Display for Query {
-    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-        match self {
-            Query::IndexScan(op) => {
-                write!(f, "index_scan {:?}", op)
-            }
-            Query::IndexJoin(op) => {
-                write!(f, "index_join {:?}", op)
-            }
-            Query::Select(q) => {
-                write!(f, "select {q}")
-            }
-            Query::Project(q, _) => {
-                write!(f, "project")?;
-                if !q.is_empty() {
-                    write!(f, " ")?;
+    fn count_operators(&self, counts: &mut OperatorCounts) {
+        for op in &self.query {
+            match op {
+                Query::IndexScan(_) | Query::IndexMultiScan(_) => counts.index_scans += 1,
+                Query::Select(_) => counts.seq_selects += 1,
+                Query::Project(..) => counts.projections += 1,
+                Query::JoinInner(join) => {
+                    counts.inner_joins += 1;
+                    join.rhs.count_operators(counts);
                 }
-                for (pos, x) in q.iter().enumerate() {
-                    write!(f, "{x}")?;
-                    if pos + 1 < q.len() {
-                        write!(f, ", ")?;
-                    }
+                Query::MergeJoin(_) => counts.inner_joins += 1,
+                Query::JoinLeft(join) => {
+                    counts.other_joins += 1;
+                    join.rhs.count_operators(counts);
                 }
-                Ok(())
-            }
-            Query::JoinInner(q) => {
-                write!(f, "&inner {:?} ON {} = {}", q.rhs, q.col_lhs, q.col_rhs)
+                Query::SemiJoinInMemory { .. } => counts.other_joins += 1,
+                Query::IndexJoin(join) => {
+                    counts.index_joins += 1;
+                    counts.seq_selects += join.index_select.is_some() as usize;
+                    counts.projections += join.projection.is_some() as usize;
+                    join.probe_side.count_operators(counts);
+                }
+                Query::Unnest(_) | Query::Limit { .. } | Query::NeverReturns => {}
             }
         }
     }
-}
-
-impl AuthAccess for SourceExpr {
-    fn check_auth(&self, owner: Identity, caller: Identity) -> Result<(), AuthError> {
-        if owner == caller || self.table_access() == StAccess::Public {
-            return Ok(());
-        }
 
-        Err(AuthError::TablePrivate {
-            named: self.table_name().to_string(),
-        })
+    /// Renders the plan of this query for debugging, in the style of `EXPLAIN`.
+    ///
+    /// The source comes first, followed by one line per operator in the order they are applied.
+    /// The plans of joined subqueries are indented below their join.
+    /// For example, a semijoin of `lhs` with a filtered `rhs` renders as:
+    ///
+    /// ```text
+    /// Source: lhs (table#0)
+    /// JoinInner: ON table#0.col#0 = table#1.col#0, kind: semi
+    ///   Source: rhs (table#1)
+    ///   Select: table#1.col#1 > 2
+    /// ```
+    pub fn explain(&self) -> String {
+        let mut out = String::new();
+        // Writing to a `String` cannot fail.
+        let _ = self.explain_into(&mut out, 0);
+        out
     }
-}
 
-impl AuthAccess for QueryExpr {
-    fn check_auth(&self, owner: Identity, caller: Identity) -> Result<(), AuthError> {
-        if owner == caller {
-            return Ok(());
-        }
-        self.source.check_auth(owner, caller)?;
-        for q in &self.query {
-            q.check_auth(owner, caller)?;
+    /// Returns the predicates of the selections of this query,
+    /// and of those of its joined subqueries,
+    /// that [`QueryExpr::optimize`] would serve by a scan rather than an index,
+    /// each with the reason why.
+    ///
+    /// This mirrors the choice of indices made by `optimize`, but doesn't change the query.
+    /// For example, for `WHERE a != 1 AND b = 2` where there's only an index on `[c, b]`,
+    /// this returns `a != 1` as [`IndexMissKind::NotEqUnsupported`]
+    /// and `b = 2` as [`IndexMissKind::CompositePrefixGap`].
+    pub fn explain_index_usage(&self) -> Vec<IndexMissReason> {
+        let mut misses = Vec::new();
+        self.explain_index_usage_into(&mut misses);
+        misses
+    }
+
+    fn explain_index_usage_into(&self, misses: &mut Vec<IndexMissReason>) {
+        let tables = self.sources();
+        let tables: Vec<_> = iter::once(QuerySources::One(tables.head))
+            .chain(tables.tail)
+            .flat_map(|x| x.into_iter())
+            .collect();
+        // Tables without column statistics are planned without hints.
+        let no_stats = ColumnStatsMap::new();
+
+        for query in &self.query {
+            match query {
+                Query::Select(op) => {
+                    let op = op.clone().fold_constants();
+                    if op.as_constant().is_some() {
+                        continue;
+                    }
+                    let op = op.collapse_or_eqs();
+                    let ops = op.flatten_ands_ref();
+                    let mut fields_found = HashSet::new();
+                    for schema in &tables {
+                        let stats = schema.column_stats().unwrap_or(&no_stats);
+                        select_best_index_explained(&mut fields_found, schema.head(), stats, &ops, misses);
+                    }
+                }
+                Query::JoinInner(join) | Query::JoinLeft(join) => join.rhs.explain_index_usage_into(misses),
+                _ => {}
+            }
         }
+    }
 
+    fn explain_into(&self, out: &mut String, depth: usize) -> fmt::Result {
+        use fmt::Write as _;
+
+        let indent = "  ".repeat(depth);
+        writeln!(out, "{indent}Source: {}", ExplainSource(&self.source))?;
+        for (idx, op) in self.query.iter().enumerate() {
+            match op {
+                Query::IndexScan(scan) => writeln!(
+                    out,
+                    "{indent}IndexScan: {}, cols {}, bounds {}{}",
+                    ExplainTable(&scan.table),
+                    ExplainCols(&scan.columns),
+                    ExplainBounds(&scan.bounds),
+                    if scan.covering { ", covering" } else { "" },
+                ),
+                Query::IndexMultiScan(scan) => {
+                    let ranges = scan.ranges.iter().map(|bounds| ExplainBounds(bounds).to_string());
+                    writeln!(
+                        out,
+                        "{indent}IndexMultiScan: {}, cols {}, ranges {}",
+                        ExplainTable(&scan.table),
+                        ExplainCols(&scan.columns),
+                        ranges.collect::<Vec<_>>().join(", "),
+                    )
+                }
+                Query::Select(op) => writeln!(out, "{indent}Select: {op}"),
+                Query::Project(cols, _, distinct) => {
+                    let cols = cols.iter().map(|col| ExplainProjectField(col).to_string());
+                    let distinct = if *distinct { " distinct" } else { "" };
+                    writeln!(
+                        out,
+                        "{indent}Project{distinct}: {}",
+                        cols.collect::<Vec<_>>().join(", ")
+                    )
+                }
+                Query::JoinInner(join) => {
+                    writeln!(
+                        out,
+                        "{indent}JoinInner: ON {} = {}, kind: {}",
+                        join.col_lhs, join.col_rhs, join.kind
+                    )?;
+                    join.rhs.explain_into(out, depth + 1)
+                }
+                Query::JoinLeft(join) => {
+                    writeln!(out, "{indent}JoinLeft: ON {} = {}", join.col_lhs, join.col_rhs)?;
+                    join.rhs.explain_into(out, depth + 1)
+                }
+                Query::SemiJoinInMemory { source, probe_field } => {
+                    writeln!(
+                        out,
+                        "{indent}SemiJoinInMemory: {probe_field} IN {}",
+                        ExplainSource(source)
+                    )
+                }
+                Query::IndexJoin(join) => {
+                    let index_select = join.index_select.as_ref().map(|op| op.to_string());
+                    let probe_fields = join.probe_fields.iter().map(|field| field.to_string());
+                    let projection = join.projection.as_ref().map(|cols| {
+                        let cols = cols.iter().map(|col| col.to_string()).collect::<Vec<_>>();
+                        format!(", projection: [{}]", cols.join(", "))
+                    });
+                    writeln!(
+                        out,
+                        "{indent}IndexJoin: index side {}, index cols {}, probe fields [{}], return_index_rows: {}, index_select: {}{}",
+                        ExplainSource(&join.index_side),
+                        ExplainCols(&join.index_cols),
+                        probe_fields.collect::<Vec<_>>().join(", "),
+                        join.return_index_rows,
+                        index_select.as_deref().unwrap_or("none"),
+                        projection.unwrap_or_default(),
+                    )?;
+                    join.probe_side.explain_into(out, depth + 1)
+                }
+                Query::MergeJoin(join) => writeln!(
+                    out,
+                    "{indent}MergeJoin: {} ON {} = {} with {}, semi: {}",
+                    ExplainTable(&join.lhs),
+                    join.col_lhs,
+                    join.col_rhs,
+                    ExplainTable(&join.rhs),
+                    join.semi
+                ),
+                Query::Unnest(unnest) => writeln!(out, "{indent}Unnest: {}, outer: {}", unnest.field, unnest.outer),
+                Query::NeverReturns => writeln!(out, "{indent}NeverReturns"),
+                Query::Limit { limit, offset } => {
+                    write!(out, "{indent}Limit: {limit}, offset: {offset}")?;
+                    if stops_scan_early(&self.query[..idx]) {
+                        write!(out, ", stops index scan early")?;
+                    }
+                    writeln!(out)
+                }
+            }?;
+        }
         Ok(())
     }
-}
 
-impl AuthAccess for CrudExpr {
-    fn check_auth(&self, owner: Identity, caller: Identity) -> Result<(), AuthError> {
-        if owner == caller {
-            return Ok(());
+    /// Returns an equivalent query in canonical form, e.g., for use as the key of a plan cache.
+    ///
+    /// The rewrites are applied in this order:
+    /// 1. Adjacent selections are merged into one selecting the conjunction of their predicates.
+    /// 2. Predicates are canonicalized by [`ColumnOp::canonicalize`],
+    ///    and selections whose predicate is always `true` are dropped.
+    /// 3. Projections onto every column of the source, in order, are dropped,
+    ///    unless a preceding operator changes the shape of the rows;
+    ///    see [`QueryExpr::remove_identity_projections`].
+    /// 4. Joined subqueries are canonicalized recursively.
+    ///
+    /// [`ColumnOp`] has no negation, so there is nothing to normalize by De Morgan's laws.
+    pub fn canonicalize(self) -> Self {
+        let mut query = Vec::with_capacity(self.query.len());
+        for op in self.query {
+            let op = match (query.pop(), op) {
+                (Some(Query::Select(lhs)), Query::Select(rhs)) => Query::Select(ColumnOp::and(lhs, rhs)),
+                (prev, op) => {
+                    query.extend(prev);
+                    op
+                }
+            };
+            query.push(op);
         }
-        // Anyone may query, so as long as the tables involved are public.
-        if let CrudExpr::Query(q) = self {
-            return q.check_auth(owner, caller);
+
+        let query = query
+            .into_iter()
+            .filter_map(|op| match op {
+                Query::Select(op) => {
+                    let op = op.canonicalize();
+                    (op.as_constant() != Some(true)).then_some(Query::Select(op))
+                }
+                Query::JoinInner(join) => Some(Query::JoinInner(JoinExpr {
+                    rhs: join.rhs.canonicalize(),
+                    ..join
+                })),
+                Query::JoinLeft(join) => Some(Query::JoinLeft(JoinExpr {
+                    rhs: join.rhs.canonicalize(),
+                    ..join
+                })),
+                Query::IndexJoin(join) => Some(Query::IndexJoin(IndexJoin {
+                    probe_side: join.probe_side.canonicalize(),
+                    index_select: join.index_select.map(ColumnOp::canonicalize),
+                    ..join
+                })),
+                op => Some(op),
+            })
+            .collect();
+
+        Self {
+            source: self.source,
+            query,
         }
+        .remove_identity_projections()
+    }
 
-        // Mutating operations require `owner == caller`.
-        Err(AuthError::OwnerRequired)
+    /// Drops the projections onto every column of the source, in order,
+    /// which leave the rows as they are, but still cost a copy of each row.
+    ///
+    /// Projections that reorder, rename, or leave out columns are kept,
+    /// as are distinct projections,
+    /// and any following an operator that changes the shape of the rows, e.g., a join.
+    pub fn remove_identity_projections(self) -> Self {
+        let head = self.source.head();
+        let is_identity = |cols: &[(FieldExpr, Option<Box<str>>)]| {
+            cols.len() == head.fields.len()
+                && cols
+                    .iter()
+                    .zip(&head.fields)
+                    .all(|((col, alias), column)| alias.is_none() && *col == FieldExpr::Name(column.field))
+        };
+        // Whether the rows are still shaped like those of the source.
+        let mut is_source_shaped = true;
+        let query = self
+            .query
+            .into_iter()
+            .filter(|op| match op {
+                Query::Project(cols, _, false) if is_source_shaped && is_identity(cols) => false,
+                op => {
+                    if !matches!(
+                        op,
+                        Query::Select(_)
+                            | Query::IndexScan(_)
+                            | Query::IndexMultiScan(_)
+                            | Query::Limit { .. }
+                            | Query::NeverReturns
+                    ) {
+                        is_source_shaped = false;
+                    }
+                    true
+                }
+            })
+            .collect();
+
+        Self {
+            source: self.source,
+            query,
+        }
+    }
+
+    /// Splits each [`Query::Select`] directly following a [`Query::JoinInner`] into its conjuncts,
+    /// and pushes each conjunct referring only to one side of the join below the join, into that side,
+    /// so that the join reads fewer rows.
+    ///
+    /// For example, `lhs JOIN rhs WHERE lhs.a = 1 AND rhs.b = 2 AND lhs.c = rhs.d`
+    /// becomes `(lhs WHERE lhs.a = 1) JOIN (rhs WHERE rhs.b = 2) WHERE lhs.c = rhs.d`.
+    ///
+    /// A conjunct referring to both sides, e.g., to a table joined with itself, stays above the join,
+    /// as does a conjunct on the lhs when the rows preceding the join aren't shaped like those of the source,
+    /// and a conjunct on the rhs of an antijoin.
+    pub fn push_selects_into_joins(self) -> Self {
+        let head = self.source.head().clone();
+        // Whether the rows are still shaped like those of the source.
+        let mut is_source_shaped = true;
+        let mut query = Vec::with_capacity(self.query.len());
+        let mut ops = self.query.into_iter().peekable();
+        while let Some(op) = ops.next() {
+            let Query::JoinInner(mut join) = op else {
+                is_source_shaped &= matches!(
+                    op,
+                    Query::Select(_)
+                        | Query::IndexScan(_)
+                        | Query::IndexMultiScan(_)
+                        | Query::Limit { .. }
+                        | Query::NeverReturns
+                );
+                query.push(op);
+                continue;
+            };
+            let filter = match ops.next_if(|op| matches!(op, Query::Select(_))) {
+                Some(Query::Select(filter)) => filter,
+                _ => {
+                    // A semi- or antijoin returns the rows of its lhs as they are.
+                    is_source_shaped &= join.kind.returns_lhs_only();
+                    query.push(Query::JoinInner(join));
+                    continue;
+                }
+            };
+
+            let rhs_head = join.rhs.source.head().clone();
+            let (mut lhs_filter, mut rhs_filter, mut join_filter) = (None, None, None);
+            for op in filter.flatten_ands() {
+                let fields = op.referenced_fields();
+                let on_lhs = fields.iter().filter(|&&field| head.column_pos(field).is_some()).count();
+                let on_rhs = fields
+                    .iter()
+                    .filter(|&&field| rhs_head.column_pos(field).is_some())
+                    .count();
+                let side = if fields.is_empty() {
+                    &mut join_filter
+                } else if is_source_shaped && on_lhs == fields.len() && on_rhs == 0 {
+                    &mut lhs_filter
+                } else if on_rhs == fields.len() && on_lhs == 0 && join.kind != JoinKind::Anti {
+                    // Filtering the rhs of an antijoin would return more rows, not fewer.
+                    &mut rhs_filter
+                } else {
+                    &mut join_filter
+                };
+                *side = Some(match side.take() {
+                    Some(filter) => ColumnOp::and(filter, op),
+                    None => op,
+                });
+            }
+
+            if let Some(filter) = lhs_filter {
+                query.push(Query::Select(filter));
+            }
+            if let Some(filter) = rhs_filter {
+                join.rhs = join.rhs.with_select(filter);
+            }
+            is_source_shaped &= join.kind.returns_lhs_only();
+            query.push(Query::JoinInner(join));
+            if let Some(filter) = join_filter {
+                query.push(Query::Select(filter));
+            }
+        }
+
+        Self {
+            source: self.source,
+            query,
+        }
+    }
+
+    /// Returns a hash of the structure of this query,
+    /// i.e., of its source and operators, including any literal values.
+    ///
+    /// The hash is deterministic, so equal queries hash equally across runs,
+    /// which allows e.g. comparing the plans the optimizer produces for a query over time.
+    ///
+    /// [`DbTable`] sources are hashed by identity,
+    /// but [`SourceExpr::InMemory`] sources are hashed by schema
+    /// and by the order in which they first appear in the plan, rather than by their [`SourceId`].
+    /// Two plans with the same structural hash are thus interchangeable,
+    /// provided their in-memory sources have matching schemas,
+    /// e.g., to key a cache of compiled plans.
+    pub fn structural_hash(&self) -> u64 {
+        let mut query = self.clone();
+        query.normalize_source_ids(&mut Vec::new());
+
+        let mut hasher = DefaultHasher::new();
+        query.hash(&mut hasher);
+        hasher.finish()
+    }
+
+    /// Renumbers the in-memory sources of `self`, including those of its joins,
+    /// by the order in which they first appear, where `seen` are the ones seen so far.
+    fn normalize_source_ids(&mut self, seen: &mut Vec<SourceId>) {
+        self.source.normalize_source_id(seen);
+        for q in &mut self.query {
+            match q {
+                Query::IndexJoin(join) => {
+                    join.probe_side.normalize_source_ids(seen);
+                    join.index_side.normalize_source_id(seen);
+                }
+                Query::JoinInner(join) | Query::JoinLeft(join) => join.rhs.normalize_source_ids(seen),
+                Query::SemiJoinInMemory { source, .. } => source.normalize_source_id(seen),
+                Query::IndexScan(_)
+                | Query::IndexMultiScan(_)
+                | Query::MergeJoin(_)
+                | Query::Select(_)
+                | Query::Project(..)
+                | Query::Unnest(_)
+                | Query::Limit { .. }
+                | Query::NeverReturns => {}
+            }
+        }
+    }
+
+    pub fn optimize(self, row_count: &impl Fn(TableId, &str) -> i64) -> Self {
+        self.optimize_with(row_count, &OptimizeOptions::default())
+    }
+
+    /// Optimizes the query like [`QueryExpr::optimize`],
+    /// but only applies the rewrites enabled in `opts`.
+    pub fn optimize_with(self, row_count: &impl Fn(TableId, &str) -> i64, opts: &OptimizeOptions) -> Self {
+        self.optimize_reporting_seq_scans(row_count, opts, &mut |_| {})
+    }
+
+    /// Optimizes the query like [`QueryExpr::optimize_with`],
+    /// calling `on_seq_scan` once per filter that no index of the [`DbTable`] it filters can serve,
+    /// i.e., that falls back to a sequential scan of that table.
+    pub fn optimize_reporting_seq_scans(
+        mut self,
+        row_count: &impl Fn(TableId, &str) -> i64,
+        opts: &OptimizeOptions,
+        on_seq_scan: &mut impl FnMut(&DbTable),
+    ) -> Self {
+        // Push filters below the joins before turning them into index scans,
+        // so that each lands on the side whose indexes can serve it.
+        if opts.enable_join_select_pushdown {
+            self = self.push_selects_into_joins();
+        }
+
+        let mut q = Self {
+            source: self.source.clone(),
+            query: Vec::with_capacity(self.query.len()),
+        };
+
+        let tables = self.sources();
+        let tables: Vec<_> = core::iter::once(QuerySources::One(tables.head))
+            .chain(tables.tail)
+            .flat_map(|x| x.into_iter())
+            .collect();
+
+        if matches!(&*self.query, [Query::IndexJoin(_)]) {
+            if let Some(Query::IndexJoin(join)) = self.query.pop() {
+                let join = if opts.enable_reorder {
+                    join.reorder(row_count)
+                } else {
+                    join
+                };
+                q.query.push(Query::IndexJoin(join));
+                return q;
+            }
+        }
+
+        for query in self.query {
+            match query {
+                Query::Select(op) if opts.enable_select_pushdown => {
+                    let op = op.fold_constants();
+                    match op.as_constant() {
+                        // The filter holds for every row, so there's nothing to do.
+                        Some(true) => {}
+                        Some(false) => {
+                            log::warn!("Query will select no rows due to a constant false filter: {q:?}");
+                            q.query.push(Query::Select(op));
+                        }
+                        None => {
+                            q = Self::optimize_select(q, op.collapse_or_eqs(), &tables, opts.max_expansion, on_seq_scan)
+                        }
+                    }
+                }
+                Query::JoinInner(join) => {
+                    let rhs = join.rhs.optimize_reporting_seq_scans(row_count, opts, on_seq_scan);
+                    q = q.with_join(rhs, join.col_lhs, join.col_rhs, join.kind);
+                }
+                // Left joins aren't rewritten into other joins yet,
+                // but their rhs is still optimized on its own.
+                Query::JoinLeft(join) => {
+                    let rhs = join.rhs.optimize_reporting_seq_scans(row_count, opts, on_seq_scan);
+                    q = q.with_join_left(rhs, join.col_lhs, join.col_rhs);
+                }
+                // Index order isn't needed, so drop the scan and read the source sequentially.
+                Query::IndexScan(scan) if scan.is_full_range() => {}
+                _ => q.query.push(query),
+            };
+        }
+
+        // Redundant predicates, e.g., on both sides of a join, may leave two scans of the same index,
+        // which are intersected into one, never satisfied if their ranges are disjoint.
+        q = q.try_intersect_index_scans();
+
+        // An index scan no key satisfies, e.g., for `x < 5 AND x > 5`, never returns any rows,
+        // so avoid touching its table at all.
+        for op in &mut q.query {
+            if matches!(op, Query::IndexScan(scan) if scan.is_never()) {
+                *op = Query::NeverReturns;
+            }
+        }
+
+        // Make sure to `try_semi_join` before `try_index_join`, as the latter depends on the former.
+        if opts.enable_semi_join {
+            q = q.try_semi_join();
+        }
+        if opts.enable_index_join {
+            q = q.try_index_join();
+        }
+        if opts.enable_merge_join {
+            q = q.try_merge_join();
+        }
+        if opts.enable_semi_join_in_memory {
+            q = q.try_semi_join_in_memory();
+        }
+        // Make sure to `try_project_pushdown` last, as the above expect the rhs of a join to be a plain table.
+        if opts.enable_project_pushdown {
+            q = q.try_project_pushdown();
+        }
+        // Make sure to `try_limit_pushdown` after the above, as they expect a projection to directly follow a join.
+        if opts.enable_limit_pushdown {
+            q = q.try_limit_pushdown();
+        }
+        if opts.enable_remove_identity_projections {
+            q = q.remove_identity_projections();
+        }
+        // Make sure to `try_index_join_projection` after the above, so as not to fuse an identity projection.
+        if opts.enable_index_join_projection {
+            q = q.try_index_join_projection();
+        }
+        // Make sure to `try_covering_index_scan` last, as it depends on the operators following the scan.
+        if opts.enable_covering_index_scans {
+            q = q.try_covering_index_scan();
+        }
+        if matches!(&*q.query, [Query::IndexJoin(_)]) {
+            return q.optimize_reporting_seq_scans(row_count, opts, on_seq_scan);
+        }
+        q
     }
 }
 
-#[derive(Debug, PartialEq)]
-pub enum Code {
-    Value(AlgebraicValue),
-    Table(MemTable),
-    Halt(ErrorLang),
-    Block(Vec<Code>),
-    Crud(CrudExpr),
-    Pass,
+/// The default for [`OptimizeOptions::max_expansion`].
+pub const DEFAULT_MAX_EXPANSION: usize = 1024;
+
+/// The maximum depth of a query compiled from SQL; see [`QueryExpr::validate_depth`].
+pub const DEFAULT_MAX_QUERY_DEPTH: usize = 1024;
+
+/// Flags and limits controlling which rewrites [`QueryExpr::optimize_with`] applies.
+///
+/// Every rewrite is enabled by default.
+/// Disabling individual rewrites is useful for bisecting an optimizer bug
+/// by comparing the results of the differently optimized queries.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct OptimizeOptions {
+    /// Rewrite an inner join followed by a wildcard projection of the lhs into a semijoin.
+    pub enable_semi_join: bool,
+    /// Rewrite a semijoin into an [`IndexJoin`] when the lhs has an index on the join column.
+    pub enable_index_join: bool,
+    /// Swap the index and probe sides of an [`IndexJoin`]; see [`IndexJoin::reorder`].
+    pub enable_reorder: bool,
+    /// Turn selections into index scans where possible.
+    pub enable_select_pushdown: bool,
+    /// Rewrite a join of two tables indexed on their join columns into a [`MergeJoinExpr`].
+    pub enable_merge_join: bool,
+    /// Rewrite a semijoin with a single-column in-memory table into a [`Query::SemiJoinInMemory`].
+    pub enable_semi_join_in_memory: bool,
+    /// Push a projection following an inner join into the rhs of the join.
+    pub enable_project_pushdown: bool,
+    /// Move a [`Query::Limit`] below the projections preceding it.
+    pub enable_limit_pushdown: bool,
+    /// Push the conjuncts of a selection following an inner join into the side of the join they refer to;
+    /// see [`QueryExpr::push_selects_into_joins`].
+    pub enable_join_select_pushdown: bool,
+    /// Drop projections that leave the rows as they are; see [`QueryExpr::remove_identity_projections`].
+    pub enable_remove_identity_projections: bool,
+    /// Mark an index scan as covering when the rest of the query reads only the columns of its index;
+    /// see [`IndexScan::covering`].
+    pub enable_covering_index_scans: bool,
+    /// Fuse a projection following an index join into the join; see [`IndexJoin::projection`].
+    pub enable_index_join_projection: bool,
+    /// The maximum number of index seeks a single predicate may expand into,
+    /// e.g., one per value of an IN-list.
+    /// Beyond that, the rest of the predicate is served by a scan,
+    /// bounding the work of planning adversarially large predicates.
+    pub max_expansion: usize,
 }
 
-impl fmt::Display for Code {
-    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-        match self {
-            Code::Value(x) => {
-                write!(f, "{:?}", &x)
-            }
-            Code::Block(_) => write!(f, "Block"),
-            x => todo!("{:?}", x),
+impl Default for OptimizeOptions {
+    fn default() -> Self {
+        Self {
+            enable_semi_join: true,
+            enable_index_join: true,
+            enable_reorder: true,
+            enable_select_pushdown: true,
+            enable_merge_join: true,
+            enable_semi_join_in_memory: true,
+            enable_project_pushdown: true,
+            enable_limit_pushdown: true,
+            enable_join_select_pushdown: true,
+            enable_remove_identity_projections: true,
+            enable_covering_index_scans: true,
+            enable_index_join_projection: true,
+            max_expansion: DEFAULT_MAX_EXPANSION,
         }
     }
 }
 
-#[derive(Debug, PartialEq)]
-pub enum CodeResult {
-    Value(AlgebraicValue),
-    Table(MemTable),
-    Block(Vec<CodeResult>),
-    Halt(ErrorLang),
-    Pass,
+/// Iterator created by the [`QueryExpr::sources`] method.
+#[must_use = "iterators are lazy and do nothing unless consumed"]
+pub struct QueryExprSources {
+    head: Option<SourceExpr>,
+    tail: VecDeque<QuerySources>,
 }
 
-impl From<Code> for CodeResult {
-    fn from(code: Code) -> Self {
-        match code {
-            Code::Value(x) => Self::Value(x),
-            Code::Table(x) => Self::Table(x),
-            Code::Halt(x) => Self::Halt(x),
-            Code::Block(x) => {
-                if x.is_empty() {
-                    Self::Pass
-                } else {
-                    Self::Block(x.into_iter().map(CodeResult::from).collect())
+impl Iterator for QueryExprSources {
+    type Item = SourceExpr;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.head.take().or_else(|| {
+            while let Some(cur) = self.tail.front_mut() {
+                match cur.next() {
+                    None => {
+                        self.tail.pop_front();
+                        continue;
+                    }
+                    Some(src) => return Some(src),
                 }
             }
-            Code::Pass => Self::Pass,
-            x => Self::Halt(ErrorLang::new(
-                ErrorKind::Compiler,
-                Some(&format!("Invalid result: {x}")),
-            )),
+
+            None
+        })
+    }
+}
+
+impl AuthAccess for Query {
+    fn check_auth(&self, owner: Identity, caller: Identity) -> Result<(), AuthError> {
+        if owner == caller {
+            return Ok(());
+        }
+
+        for table in self.sources() {
+            if table.table_access() == StAccess::Private {
+                return Err(AuthError::TablePrivate {
+                    named: table.table_name().to_owned(),
+                });
+            }
         }
+
+        Ok(())
+    }
+
+    fn check_auth_all(&self, owner: Identity, caller: Identity) -> Result<(), Vec<AuthError>> {
+        if owner == caller {
+            return Ok(());
+        }
+        check_auth_sources(self.sources(), owner, caller)
     }
 }
 
-#[cfg(test)]
-mod tests {
-    use super::*;
-    use spacetimedb_sats::relation::Column;
-    use spacetimedb_sats::{product, AlgebraicType, ProductType};
-    use typed_arena::Arena;
+#[derive(Debug, Eq, PartialEq, From)]
+pub enum Expr {
+    #[from]
+    Value(AlgebraicValue),
+    Block(Vec<Expr>),
+    Ident(String),
+    Crud(Box<CrudExpr>),
+    Halt(ErrorLang),
+}
+
+impl From<QueryExpr> for Expr {
+    fn from(x: QueryExpr) -> Self {
+        Expr::Crud(Box::new(CrudExpr::Query(x)))
+    }
+}
+
+impl fmt::Display for Query {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Query::IndexScan(op) => {
+                write!(f, "index_scan {:?}", op)
+            }
+            Query::IndexMultiScan(op) => {
+                write!(f, "index_multi_scan {:?}", op)
+            }
+            Query::IndexJoin(op) => {
+                write!(f, "index_join {:?}", op)
+            }
+            Query::MergeJoin(op) => {
+                write!(f, "merge_join {:?}", op)
+            }
+            Query::Select(q) => {
+                write!(f, "select {q}")
+            }
+            Query::Project(q, _, distinct) => {
+                write!(f, "project")?;
+                if *distinct {
+                    write!(f, " distinct")?;
+                }
+                if !q.is_empty() {
+                    write!(f, " ")?;
+                }
+                for (pos, x) in q.iter().enumerate() {
+                    write!(f, "{}", ExplainProjectField(x))?;
+                    if pos + 1 < q.len() {
+                        write!(f, ", ")?;
+                    }
+                }
+                Ok(())
+            }
+            Query::JoinInner(q) => {
+                write!(f, "&inner {:?} ON {} = {}", q.rhs, q.col_lhs, q.col_rhs)
+            }
+            Query::JoinLeft(q) => {
+                write!(f, "&left {:?} ON {} = {}", q.rhs, q.col_lhs, q.col_rhs)
+            }
+            Query::SemiJoinInMemory { source, probe_field } => {
+                write!(f, "&semi {probe_field} IN {source:?}")
+            }
+            Query::Unnest(q) => {
+                if q.outer {
+                    write!(f, "outer ")?;
+                }
+                write!(f, "unnest {}", q.field)
+            }
+            Query::Limit { limit, offset } => {
+                write!(f, "limit {limit} offset {offset}")
+            }
+            Query::NeverReturns => {
+                write!(f, "never")
+            }
+        }
+    }
+}
+
+/// Returns whether a [`Query::Limit`] following the operators `preceding`
+/// stops reading the rows of an index early.
+///
+/// This is the case when the rows stream directly from an [`IndexScan`],
+/// with at most some selections in between,
+/// as the scan is then only advanced until the limit is reached.
+fn stops_scan_early(preceding: &[Query]) -> bool {
+    match preceding {
+        [Query::IndexScan(_), rest @ ..] => rest.iter().all(|op| matches!(op, Query::Select(_))),
+        _ => false,
+    }
+}
+
+/// Renders a [`DbTable`] for [`QueryExpr::explain`].
+struct ExplainTable<'a>(&'a DbTable);
+
+impl fmt::Display for ExplainTable<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} (table#{})", self.0.head.table_name, self.0.table_id)
+    }
+}
+
+/// Renders a [`SourceExpr`] for [`QueryExpr::explain`].
+struct ExplainSource<'a>(&'a SourceExpr);
+
+impl fmt::Display for ExplainSource<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.0 {
+            SourceExpr::DbTable(table) => write!(f, "{}", ExplainTable(table)),
+            SourceExpr::InMemory { source_id, header, .. } => {
+                write!(f, "{} (source#{})", header.table_name, source_id.0)
+            }
+        }
+    }
+}
+
+/// Renders a column of a projection for [`QueryExpr::explain`], e.g., `table#0.col#1 AS name`.
+struct ExplainProjectField<'a>(&'a (FieldExpr, Option<Box<str>>));
+
+impl fmt::Display for ExplainProjectField<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.0 {
+            (col, Some(alias)) => write!(f, "{col} AS {alias}"),
+            (col, None) => write!(f, "{col}"),
+        }
+    }
+}
+
+/// Renders the columns of an index for [`QueryExpr::explain`], e.g., `[0, 2]`.
+struct ExplainCols<'a>(&'a ColList);
+
+impl fmt::Display for ExplainCols<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let cols = self.0.iter().map(|col| col.to_string()).collect::<Vec<_>>();
+        write!(f, "[{}]", cols.join(", "))
+    }
+}
+
+/// Renders the bounds of an index scan for [`QueryExpr::explain`] in interval notation,
+/// e.g., `[1, 10)` or `(5, +inf)`.
+struct ExplainBounds<'a>(&'a (Bound<AlgebraicValue>, Bound<AlgebraicValue>));
+
+impl fmt::Display for ExplainBounds<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match &self.0 .0 {
+            Bound::Included(value) => write!(f, "[{}", value.to_satn())?,
+            Bound::Excluded(value) => write!(f, "({}", value.to_satn())?,
+            Bound::Unbounded => write!(f, "(-inf")?,
+        }
+        match &self.0 .1 {
+            Bound::Included(value) => write!(f, ", {}]", value.to_satn()),
+            Bound::Excluded(value) => write!(f, ", {})", value.to_satn()),
+            Bound::Unbounded => write!(f, ", +inf)"),
+        }
+    }
+}
+
+impl AuthAccess for SourceExpr {
+    fn check_auth(&self, owner: Identity, caller: Identity) -> Result<(), AuthError> {
+        if owner == caller || self.table_access() == StAccess::Public {
+            return Ok(());
+        }
+
+        Err(AuthError::TablePrivate {
+            named: self.table_name().to_string(),
+        })
+    }
+}
+
+impl QueryExpr {
+    /// Pushes the fields read by the projections, selections, and join keys of `self`
+    /// and of the `rhs` and `probe_side` of its joins onto `fields`.
+    fn read_fields(&self, fields: &mut Vec<FieldName>) {
+        for q in &self.query {
+            match q {
+                Query::Project(cols, _, _) => fields.extend(cols.iter().filter_map(|(col, _)| match col {
+                    FieldExpr::Name(field) => Some(*field),
+                    FieldExpr::Value(_) => None,
+                })),
+                Query::Select(op) => fields.extend(op.referenced_fields()),
+                Query::IndexScan(IndexScan { table, columns, .. })
+                | Query::IndexMultiScan(IndexMultiScan { table, columns, .. }) => {
+                    fields.extend(columns.iter().map(|col| table.head.fields[col.idx()].field))
+                }
+                Query::IndexJoin(join) => {
+                    let index_head = join.index_side.head();
+                    fields.extend(join.index_cols.iter().map(|col| index_head.fields[col.idx()].field));
+                    fields.extend(join.probe_fields.iter().copied());
+                    fields.extend(join.index_select.iter().flat_map(|op| op.referenced_fields()));
+                    fields.extend(join.projection.iter().flatten().filter_map(|col| match col {
+                        FieldExpr::Name(field) => Some(*field),
+                        FieldExpr::Value(_) => None,
+                    }));
+                    join.probe_side.read_fields(fields);
+                }
+                Query::Unnest(unnest) => fields.push(unnest.field),
+                // Joining on a column reveals its values, as much as filtering on it does.
+                Query::JoinInner(join) | Query::JoinLeft(join) => {
+                    fields.extend([join.col_lhs, join.col_rhs]);
+                    join.rhs.read_fields(fields)
+                }
+                Query::MergeJoin(join) => fields.extend([join.col_lhs, join.col_rhs]),
+                Query::SemiJoinInMemory { probe_field, .. } => fields.push(*probe_field),
+                Query::Limit { .. } | Query::NeverReturns => {}
+            }
+        }
+    }
+
+    /// Returns an [`AuthError::ColumnPrivate`] for each private column of the sources of `self`
+    /// that `self` reads, either to project or to filter on it.
+    fn check_auth_columns(&self) -> Vec<AuthError> {
+        // `sources` includes those of the `rhs` and `probe_side` of any joins.
+        let mut private = Vec::new();
+        for source in self.sources() {
+            let head = source.head();
+            private.extend(
+                head.private_columns
+                    .iter()
+                    .map(|field| (*field, head.table_name.clone())),
+            );
+        }
+        if private.is_empty() {
+            return Vec::new();
+        }
+
+        let mut fields = Vec::new();
+        if self.query.iter().any(|q| matches!(q, Query::Project(..))) {
+            self.read_fields(&mut fields);
+        } else {
+            // Without a projection, every column of every source is returned.
+            fields.extend(private.iter().map(|(field, _)| *field));
+        }
+        fields.sort();
+        fields.dedup();
+
+        fields
+            .into_iter()
+            .filter_map(|column| {
+                let (_, table) = private.iter().find(|(field, _)| *field == column)?;
+                Some(AuthError::ColumnPrivate {
+                    table: table.to_string(),
+                    column,
+                })
+            })
+            .collect()
+    }
+}
+
+impl AuthAccess for QueryExpr {
+    fn check_auth(&self, owner: Identity, caller: Identity) -> Result<(), AuthError> {
+        if owner == caller {
+            return Ok(());
+        }
+        self.source.check_auth(owner, caller)?;
+        for q in &self.query {
+            q.check_auth(owner, caller)?;
+        }
+        if let Some(err) = self.check_auth_columns().into_iter().next() {
+            return Err(err);
+        }
+
+        Ok(())
+    }
+
+    fn check_auth_all(&self, owner: Identity, caller: Identity) -> Result<(), Vec<AuthError>> {
+        if owner == caller {
+            return Ok(());
+        }
+        // `sources` includes those of the `rhs` and `probe_side` of any joins.
+        let mut errors = check_auth_sources(self.sources(), owner, caller)
+            .err()
+            .unwrap_or_default();
+        errors.extend(self.check_auth_columns());
+        if errors.is_empty() {
+            Ok(())
+        } else {
+            Err(errors)
+        }
+    }
+}
+
+impl AuthAccess for CrudExpr {
+    fn check_auth(&self, owner: Identity, caller: Identity) -> Result<(), AuthError> {
+        if owner == caller {
+            return Ok(());
+        }
+        // Anyone may query, so as long as the tables involved are public.
+        if let CrudExpr::Query(q) = self {
+            return q.check_auth(owner, caller);
+        }
+
+        // Mutating operations require `owner == caller`.
+        Err(AuthError::OwnerRequired)
+    }
+
+    fn check_auth_all(&self, owner: Identity, caller: Identity) -> Result<(), Vec<AuthError>> {
+        match self {
+            CrudExpr::Query(q) => q.check_auth_all(owner, caller),
+            _ => self.check_auth(owner, caller).map_err(|err| vec![err]),
+        }
+    }
+}
+
+#[derive(Debug, PartialEq)]
+pub enum Code {
+    Value(AlgebraicValue),
+    Table(MemTable),
+    Halt(ErrorLang),
+    Block(Vec<Code>),
+    Crud(CrudExpr),
+    Pass,
+}
+
+impl fmt::Display for Code {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Code::Value(x) => {
+                write!(f, "{:?}", &x)
+            }
+            Code::Block(_) => write!(f, "Block"),
+            x => todo!("{:?}", x),
+        }
+    }
+}
+
+#[derive(Debug)]
+pub enum CodeResult<'a> {
+    Value(AlgebraicValue),
+    Table(MemTable),
+    /// A result whose rows are produced incrementally, for results too large to materialize;
+    /// see [`ProgramVm::eval_query_stream`](crate::program::ProgramVm::eval_query_stream).
+    Stream(RowStream<'a>),
+    Block(Vec<CodeResult<'a>>),
+    Halt(ErrorLang),
+    Pass,
+}
+
+impl From<Code> for CodeResult<'_> {
+    fn from(code: Code) -> Self {
+        match code {
+            Code::Value(x) => Self::Value(x),
+            Code::Table(x) => Self::Table(x),
+            Code::Halt(x) => Self::Halt(x),
+            Code::Block(x) => {
+                if x.is_empty() {
+                    Self::Pass
+                } else {
+                    Self::Block(x.into_iter().map(CodeResult::from).collect())
+                }
+            }
+            Code::Pass => Self::Pass,
+            x => Self::Halt(ErrorLang::new(
+                ErrorKind::Compiler,
+                Some(&format!("Invalid result: {x}")),
+            )),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::eval::test_helpers::{mem_table_one_u64, optimizer_fixtures, setup_best_index};
+    use spacetimedb_primitives::col_list;
+    use spacetimedb_sats::relation::Column;
+    use spacetimedb_sats::{product, AlgebraicType, ProductType};
+    use spacetimedb_table::blob_store::HashMapBlobStore;
+    use spacetimedb_table::indexes::SquashedOffset;
+    use spacetimedb_table::table::Table;
+    use typed_arena::Arena;
+
+    const ALICE: Identity = Identity::from_byte_array([1; 32]);
+    const BOB: Identity = Identity::from_byte_array([2; 32]);
+
+    // TODO(kim): Should better do property testing here, but writing generators
+    // on recursive types (ie. `Query` and friends) is tricky.
+
+    fn tables() -> [SourceExpr; 2] {
+        [
+            SourceExpr::InMemory {
+                source_id: SourceId(0),
+                header: Arc::new(Header {
+                    table_id: 42.into(),
+                    table_name: "foo".into(),
+                    fields: vec![],
+                    constraints: Default::default(),
+                    partial_indexes: Default::default(),
+                    private_columns: Default::default(),
+                }),
+                row_count: RowCount::unknown(),
+                table_type: StTableType::User,
+                table_access: StAccess::Private,
+                column_stats: None,
+            },
+            SourceExpr::DbTable(DbTable {
+                head: Arc::new(Header {
+                    table_id: 42.into(),
+                    table_name: "foo".into(),
+                    fields: vec![],
+                    constraints: vec![(ColId(42).into(), Constraints::indexed())],
+                    partial_indexes: Default::default(),
+                    private_columns: Default::default(),
+                }),
+                table_id: 42.into(),
+                table_type: StTableType::User,
+                table_access: StAccess::Private,
+                column_stats: None,
+            }),
+        ]
+    }
+
+    /// A table `name` with two `U64` columns, the first of which is indexed.
+    fn indexed_u64_table(id: u32, name: &str) -> TableSchema {
+        TableSchema::from_def(
+            TableId(id),
+            TableDef::new(
+                name.into(),
+                ProductType::from_iter([AlgebraicType::U64, AlgebraicType::U64]).into(),
+            )
+            .with_column_constraint(Constraints::indexed(), ColId(0)),
+        )
+    }
+
+    fn queries() -> impl IntoIterator<Item = Query> {
+        let [mem_table, db_table] = tables();
+        // Skip `Query::Select` and `QueryProject` -- they don't have table
+        // information
+        [
+            Query::IndexScan(IndexScan {
+                table: db_table.get_db_table().unwrap().clone(),
+                columns: ColList::new(42.into()),
+                bounds: (Bound::Included(22.into()), Bound::Unbounded),
+                covering: false,
+            }),
+            Query::IndexJoin(IndexJoin {
+                probe_side: mem_table.clone().into(),
+                probe_fields: smallvec![FieldName::new(mem_table.head().table_id, 0.into())],
+                index_side: SourceExpr::DbTable(DbTable {
+                    head: Arc::new(Header {
+                        table_id: db_table.head().table_id,
+                        table_name: db_table.table_name().into(),
+                        fields: vec![],
+                        constraints: Default::default(),
+                        partial_indexes: Default::default(),
+                        private_columns: Default::default(),
+                    }),
+                    table_id: db_table.head().table_id,
+                    table_type: StTableType::User,
+                    table_access: StAccess::Public,
+                    column_stats: None,
+                }),
+                index_select: None,
+                index_cols: ColList::new(22.into()),
+                return_index_rows: true,
+                projection: None,
+            }),
+            Query::JoinInner(JoinExpr {
+                col_rhs: FieldName::new(mem_table.head().table_id, 1.into()),
+                rhs: mem_table.into(),
+                col_lhs: FieldName::new(db_table.head().table_id, 1.into()),
+                kind: JoinKind::Inner,
+            }),
+        ]
+    }
+
+    fn query_exprs() -> impl IntoIterator<Item = QueryExpr> {
+        tables().map(|table| {
+            let mut expr = QueryExpr::from(table);
+            expr.query = queries().into_iter().collect();
+            expr
+        })
+    }
+
+    fn assert_owner_private<T: AuthAccess>(auth: &T) {
+        assert!(auth.check_auth(ALICE, ALICE).is_ok());
+        assert!(matches!(
+            auth.check_auth(ALICE, BOB),
+            Err(AuthError::TablePrivate { .. })
+        ));
+    }
+
+    fn assert_owner_required<T: AuthAccess>(auth: T) {
+        assert!(auth.check_auth(ALICE, ALICE).is_ok());
+        assert!(matches!(auth.check_auth(ALICE, BOB), Err(AuthError::OwnerRequired)));
+    }
+
+    fn mem_table(id: TableId, name: &str, fields: &[(u32, AlgebraicType, bool)]) -> SourceExpr {
+        let table_access = StAccess::Public;
+        let head = Header::new(
+            id,
+            name.into(),
+            fields
+                .iter()
+                .map(|(col, ty, _)| Column::new(FieldName::new(id, (*col).into()), ty.clone()))
+                .collect(),
+            fields
+                .iter()
+                .enumerate()
+                .filter(|(_, (_, _, indexed))| *indexed)
+                .map(|(i, _)| (ColId::from(i).into(), Constraints::indexed()))
+                .collect(),
+        );
+        SourceExpr::InMemory {
+            source_id: SourceId(0),
+            header: Arc::new(head),
+            row_count: RowCount::unknown(),
+            table_access,
+            table_type: StTableType::User,
+            column_stats: None,
+        }
+    }
+
+    #[test]
+    fn test_index_to_inner_join() {
+        let index_side = mem_table(
+            0.into(),
+            "index",
+            &[(0, AlgebraicType::U8, false), (1, AlgebraicType::U8, true)],
+        );
+        let probe_side = mem_table(
+            1.into(),
+            "probe",
+            &[(0, AlgebraicType::U8, false), (1, AlgebraicType::U8, true)],
+        );
+
+        let index_field = index_side.head().fields[1].field;
+        let probe_field = probe_side.head().fields[1].field;
+        let select_field = FieldName::new(index_side.head().table_id, 0.into());
+        let index_select = ColumnOp::cmp(select_field, OpCmp::Eq, 0u8);
+        let join = IndexJoin {
+            probe_side: probe_side.clone().into(),
+            probe_fields: smallvec![probe_field],
+            index_side: index_side.clone(),
+            index_select: Some(index_select.clone()),
+            index_cols: ColList::new(1.into()),
+            return_index_rows: false,
+            projection: None,
+        };
+
+        let expr = join.to_inner_join();
+
+        assert_eq!(expr.source, probe_side);
+        assert_eq!(expr.query.len(), 1);
+
+        let Query::JoinInner(ref join) = expr.query[0] else {
+            panic!("expected an inner join, but got {:#?}", expr.query[0]);
+        };
+
+        assert_eq!(join.col_lhs, probe_field);
+        assert_eq!(join.col_rhs, index_field);
+        assert_eq!(
+            join.rhs,
+            QueryExpr {
+                source: index_side,
+                query: vec![index_select.into()]
+            }
+        );
+        assert_eq!(join.kind, JoinKind::Semi);
+    }
+
+    #[test]
+    fn optimize_fuses_index_join_projection() {
+        let (index, probe) = (indexed_u64_table(0, "index"), indexed_u64_table(1, "probe"));
+        let index_b = FieldName::new(index.table_id, 1.into());
+        let probe_b = FieldName::new(probe.table_id, 1.into());
+        let join = IndexJoin {
+            probe_side: QueryExpr::new(&probe).with_select(ColumnOp::cmp(probe_b, OpCmp::Eq, 3u64)),
+            probe_fields: smallvec![FieldName::new(probe.table_id, 0.into())],
+            index_side: (&index).into(),
+            index_select: None,
+            index_cols: ColList::new(0.into()),
+            return_index_rows: true,
+            projection: None,
+        };
+        let project = |field: FieldName| QueryExpr::from(join.clone()).with_project(&[field.into()], None);
+        // Large enough not to swap the sides of the join.
+        let row_count = |_: TableId, _: &str| 1000;
+        let index_b_only = Some(vec![FieldExpr::Name(index_b)]);
+
+        // `index.b` is a column of the returned rows, so the join emits only it.
+        let fused = project(index_b).optimize(&row_count);
+        let [Query::IndexJoin(fused_join)] = &*fused.query else {
+            panic!("expected a single index join, got {:?}", fused.query);
+        };
+        assert_eq!(fused_join.projection, index_b_only);
+        assert!(fused
+            .explain()
+            .contains("index_select: none, projection: [table#0.col#1]"));
+
+        // `probe.b` is not, so the projection is kept.
+        let kept = project(probe_b).optimize(&row_count);
+        assert!(matches!(
+            &*kept.query,
+            [Query::IndexJoin(join), Query::Project(..)] if join.projection.is_none()
+        ));
+
+        // Swapping the sides of the join still returns the rows of `index`, so the projection applies as is.
+        let reordered = fused_join.clone().reorder(|_, _| 0);
+        assert!(!reordered.return_index_rows);
+        assert_eq!(reordered.projection, index_b_only);
+
+        // As an inner join, the projection follows the join.
+        let inner = fused_join.clone().to_inner_join();
+        assert!(matches!(
+            &*inner.query,
+            [Query::JoinInner(_), Query::Project(cols, None, false)] if *cols == [(FieldExpr::Name(index_b), None)]
+        ));
+    }
+
+    #[test]
+    fn index_join_incremental_plans() {
+        let (index, probe) = (indexed_u64_table(0, "index"), indexed_u64_table(1, "probe"));
+        let join = IndexJoin {
+            probe_side: QueryExpr::new(&probe),
+            probe_fields: smallvec![FieldName::new(probe.table_id, 0.into())],
+            index_side: (&index).into(),
+            index_select: None,
+            index_cols: ColList::new(0.into()),
+            return_index_rows: true,
+            projection: None,
+        };
+
+        // Returns the table `source` reads, and its source id if it's a delta table.
+        let read = |source: &SourceExpr| (source.head().table_id, source.source_id());
+
+        let plans = join.incremental_plans();
+        assert_eq!(plans.len(), 3);
+
+        // The delta of the index side probes the probe side.
+        let [Query::IndexJoin(join)] = &*plans[0].query else {
+            panic!("expected an index join, got {:?}", plans[0].query);
+        };
+        assert_eq!(read(&join.probe_side.source), (index.table_id, Some(SourceId(0))));
+        assert_eq!(read(&join.index_side), (probe.table_id, None));
+
+        // The delta of the probe side probes the index side.
+        let [Query::IndexJoin(join)] = &*plans[1].query else {
+            panic!("expected an index join, got {:?}", plans[1].query);
+        };
+        assert_eq!(read(&join.probe_side.source), (probe.table_id, Some(SourceId(0))));
+        assert_eq!(read(&join.index_side), (index.table_id, None));
+
+        // The deltas are joined with each other.
+        let [Query::JoinInner(join)] = &*plans[2].query else {
+            panic!("expected an inner join, got {:?}", plans[2].query);
+        };
+        assert_eq!(read(&plans[2].source), (index.table_id, Some(SourceId(0))));
+        assert_eq!(read(&join.rhs.source), (probe.table_id, Some(SourceId(1))));
+    }
+
+    fn make_field_value<'a>(
+        arena: &'a Arena<ColumnOp>,
+        (cmp, field, value): (OpCmp, FieldName, &'a AlgebraicValue),
+    ) -> FieldValue<'a> {
+        let from_expr = |expr| Box::new(ColumnOp::Field(expr));
+        let op = ColumnOp::Cmp {
+            op: OpQuery::Cmp(cmp),
+            lhs: from_expr(FieldExpr::Name(field)),
+            rhs: from_expr(FieldExpr::Value(value.clone())),
+        };
+        let parent = arena.alloc(op);
+        FieldValue::new(parent, cmp, field, value)
+    }
+
+    fn scan_eq<'a>(arena: &'a Arena<ColumnOp>, field: FieldName, val: &'a AlgebraicValue) -> IndexColumnOp<'a> {
+        scan(arena, OpCmp::Eq, field, val)
+    }
+
+    fn scan<'a>(
+        arena: &'a Arena<ColumnOp>,
+        cmp: OpCmp,
+        field: FieldName,
+        val: &'a AlgebraicValue,
+    ) -> IndexColumnOp<'a> {
+        IndexColumnOp::Scan(make_field_value(arena, (cmp, field, val)).parent)
+    }
+
+    #[test]
+    fn best_index() {
+        let (head1, fields, vals) = setup_best_index();
+        let [col_a, col_b, col_c, col_d, col_e] = fields;
+        let [val_a, val_b, val_c, val_d, val_e] = vals;
+
+        let arena = Arena::new();
+        let select_best_index = |fields: &[_]| {
+            let fields = fields
+                .iter()
+                .copied()
+                .map(|(col, val): (FieldName, _)| make_field_value(&arena, (OpCmp::Eq, col, val)).parent)
+                .collect::<Vec<_>>();
+            select_best_index(&mut <_>::default(), &head1, &<_>::default(), &fields)
+        };
+
+        let col_list_arena = Arena::new();
+        let idx_eq = |cols, val| make_index_arg(OpCmp::Eq, col_list_arena.alloc(cols), val);
+
+        // Check for simple scan
+        assert_eq!(
+            select_best_index(&[(col_d, &val_e)]),
+            [scan_eq(&arena, col_d, &val_e)].into(),
+        );
+
+        assert_eq!(
+            select_best_index(&[(col_a, &val_a)]),
+            [idx_eq(col_a.col.into(), val_a.clone())].into(),
+        );
+
+        assert_eq!(
+            select_best_index(&[(col_b, &val_b)]),
+            [idx_eq(col_b.col.into(), val_b.clone())].into(),
+        );
+
+        // Check for permutation
+        assert_eq!(
+            select_best_index(&[(col_b, &val_b), (col_c, &val_c)]),
+            [idx_eq(
+                col_list![col_b.col, col_c.col],
+                product![val_b.clone(), val_c.clone()].into()
+            )]
+            .into(),
+        );
+
+        assert_eq!(
+            select_best_index(&[(col_c, &val_c), (col_b, &val_b)]),
+            [idx_eq(
+                col_list![col_b.col, col_c.col],
+                product![val_b.clone(), val_c.clone()].into()
+            )]
+            .into(),
+        );
+
+        // Check for permutation
+        assert_eq!(
+            select_best_index(&[(col_a, &val_a), (col_b, &val_b), (col_c, &val_c), (col_d, &val_d)]),
+            [idx_eq(
+                col_list![col_a.col, col_b.col, col_c.col, col_d.col],
+                product![val_a.clone(), val_b.clone(), val_c.clone(), val_d.clone()].into(),
+            )]
+            .into(),
+        );
+
+        assert_eq!(
+            select_best_index(&[(col_b, &val_b), (col_a, &val_a), (col_d, &val_d), (col_c, &val_c)]),
+            [idx_eq(
+                col_list![col_a.col, col_b.col, col_c.col, col_d.col],
+                product![val_a.clone(), val_b.clone(), val_c.clone(), val_d.clone()].into(),
+            )]
+            .into()
+        );
+
+        // Check mix scan + index
+        assert_eq!(
+            select_best_index(&[(col_b, &val_b), (col_a, &val_a), (col_e, &val_e), (col_d, &val_d)]),
+            [
+                idx_eq(col_a.col.into(), val_a.clone()),
+                idx_eq(col_b.col.into(), val_b.clone()),
+                scan_eq(&arena, col_d, &val_d),
+                scan_eq(&arena, col_e, &val_e),
+            ]
+            .into()
+        );
+
+        assert_eq!(
+            select_best_index(&[(col_b, &val_b), (col_c, &val_c), (col_d, &val_d)]),
+            [
+                idx_eq(
+                    col_list![col_b.col, col_c.col],
+                    product![val_b.clone(), val_c.clone()].into(),
+                ),
+                scan_eq(&arena, col_d, &val_d),
+            ]
+            .into()
+        );
+    }
+
+    #[test]
+    fn best_index_range() {
+        let arena = Arena::new();
+
+        let (head1, fields, vals) = setup_best_index();
+        let [col_a, col_b, col_c, col_d, _] = fields;
+        let [val_a, val_b, val_c, val_d, _] = vals;
+
+        let select_best_index = |fields: &[_]| {
+            let fields = fields
+                .iter()
+                .map(|x| make_field_value(&arena, *x).parent)
+                .collect::<Vec<_>>();
+            select_best_index(&mut <_>::default(), &head1, &<_>::default(), &fields)
+        };
+
+        let col_list_arena = Arena::new();
+        let idx = |cmp, cols: &[FieldName], val: &AlgebraicValue| {
+            let columns = cols.iter().map(|c| c.col).collect::<ColListBuilder>().build().unwrap();
+            let columns = col_list_arena.alloc(columns);
+            make_index_arg(cmp, columns, val.clone())
+        };
+
+        // Same field indexed
+        assert_eq!(
+            select_best_index(&[(OpCmp::Gt, col_a, &val_a), (OpCmp::Lt, col_a, &val_b)]),
+            [idx(OpCmp::Lt, &[col_a], &val_b), idx(OpCmp::Gt, &[col_a], &val_a)].into()
+        );
+
+        // Same field scan
+        assert_eq!(
+            select_best_index(&[(OpCmp::Gt, col_d, &val_d), (OpCmp::Lt, col_d, &val_b)]),
+            [
+                scan(&arena, OpCmp::Lt, col_d, &val_b),
+                scan(&arena, OpCmp::Gt, col_d, &val_d)
+            ]
+            .into()
+        );
+        // One indexed other scan
+        assert_eq!(
+            select_best_index(&[(OpCmp::Gt, col_b, &val_b), (OpCmp::Lt, col_c, &val_c)]),
+            [idx(OpCmp::Gt, &[col_b], &val_b), scan(&arena, OpCmp::Lt, col_c, &val_c)].into()
+        );
+
+        // 1 multi-indexed 1 index
+        assert_eq!(
+            select_best_index(&[
+                (OpCmp::Eq, col_b, &val_b),
+                (OpCmp::GtEq, col_a, &val_a),
+                (OpCmp::Eq, col_c, &val_c),
+            ]),
+            [
+                idx(
+                    OpCmp::Eq,
+                    &[col_b, col_c],
+                    &product![val_b.clone(), val_c.clone()].into(),
+                ),
+                idx(OpCmp::GtEq, &[col_a], &val_a),
+            ]
+            .into()
+        );
+
+        // 1 indexed 2 scan
+        assert_eq!(
+            select_best_index(&[
+                (OpCmp::Gt, col_b, &val_b),
+                (OpCmp::Eq, col_a, &val_a),
+                (OpCmp::Lt, col_c, &val_c),
+            ]),
+            [
+                idx(OpCmp::Eq, &[col_a], &val_a),
+                idx(OpCmp::Gt, &[col_b], &val_b),
+                scan(&arena, OpCmp::Lt, col_c, &val_c),
+            ]
+            .into()
+        );
+    }
+
+    #[test]
+    fn test_auth_table() {
+        tables().iter().for_each(assert_owner_private)
+    }
+
+    #[test]
+    fn test_auth_query_code() {
+        for code in query_exprs() {
+            assert_owner_private(&code)
+        }
+    }
+
+    #[test]
+    fn test_auth_query() {
+        for query in queries() {
+            assert_owner_private(&query);
+        }
+    }
+
+    #[test]
+    fn test_auth_crud_code_query() {
+        for query in query_exprs() {
+            let crud = CrudExpr::Query(query);
+            assert_owner_private(&crud);
+        }
+    }
+
+    #[test]
+    fn test_auth_all_reports_every_private_table() {
+        let private = |id: u32, name: &str| {
+            SourceExpr::DbTable(DbTable {
+                head: Arc::new(Header {
+                    table_id: id.into(),
+                    table_name: name.into(),
+                    fields: vec![],
+                    constraints: vec![],
+                    partial_indexes: Default::default(),
+                    private_columns: Default::default(),
+                }),
+                table_id: id.into(),
+                table_type: StTableType::User,
+                table_access: StAccess::Private,
+                column_stats: None,
+            })
+        };
+        let [lhs, rhs] = [private(1, "lhs"), private(2, "rhs")];
+
+        let mut query = QueryExpr::new(lhs.clone());
+        query.query.push(Query::JoinInner(JoinExpr {
+            col_rhs: FieldName::new(rhs.head().table_id, 0.into()),
+            rhs: rhs.into(),
+            col_lhs: FieldName::new(lhs.head().table_id, 0.into()),
+            kind: JoinKind::Inner,
+        }));
+
+        assert!(query.check_auth_all(ALICE, ALICE).is_ok());
+
+        // `check_auth` only reports the first private table...
+        assert!(matches!(
+            query.check_auth(ALICE, BOB),
+            Err(AuthError::TablePrivate { named }) if named == "lhs"
+        ));
+
+        // ...while `check_auth_all` reports both.
+        let errors = query.check_auth_all(ALICE, BOB).unwrap_err();
+        let named = errors
+            .iter()
+            .map(|err| match err {
+                AuthError::TablePrivate { named } => named.as_str(),
+                err => panic!("unexpected error: {err}"),
+            })
+            .collect::<Vec<_>>();
+        assert_eq!(named, ["lhs", "rhs"]);
+
+        let crud = CrudExpr::Query(query);
+        assert_eq!(crud.check_auth_all(ALICE, BOB).unwrap_err().len(), 2);
+    }
+
+    #[test]
+    fn test_auth_private_column() {
+        let with_private_column = |table: SourceExpr, col: u32| match table {
+            SourceExpr::InMemory { header, .. } => SourceExpr::InMemory {
+                source_id: SourceId(0),
+                header: Arc::new(header.clone_for_error().with_private_column(ColId(col))),
+                row_count: RowCount::unknown(),
+                table_access: StAccess::Public,
+                table_type: StTableType::User,
+                column_stats: None,
+            },
+            table => table,
+        };
+        let fields = &[(0, AlgebraicType::U8, false), (1, AlgebraicType::U8, false)];
+        let lhs = with_private_column(mem_table(0.into(), "lhs", fields), 1);
+        let rhs = mem_table(1.into(), "rhs", fields);
+        let [public, private] = [0, 1].map(|col| FieldName::new(lhs.head().table_id, ColId(col)));
+
+        let assert_private = |query: &QueryExpr| {
+            assert!(query.check_auth(ALICE, ALICE).is_ok());
+            assert!(matches!(
+                query.check_auth(ALICE, BOB),
+                Err(AuthError::ColumnPrivate { table, column }) if table == "lhs" && column == private
+            ));
+        };
+
+        // Projecting only the public column is fine...
+        let query = QueryExpr::new(lhs.clone()).with_project(&[public.into()], None);
+        assert!(query.check_auth(ALICE, BOB).is_ok());
+
+        // ...but not projecting or filtering on the private one.
+        let query = QueryExpr::new(lhs.clone()).with_project(&[private.into()], None);
+        assert_private(&query);
+        let query = QueryExpr::new(lhs.clone())
+            .with_select_cmp(OpCmp::Eq, private, AlgebraicValue::U8(0))
+            .with_project(&[public.into()], None);
+        assert_private(&query);
+
+        // Without a projection, every column is read.
+        assert_private(&QueryExpr::new(lhs.clone()));
+
+        // The private column is also found on the `rhs` of a join.
+        let mut query = QueryExpr::new(rhs.clone());
+        query.query.push(Query::JoinInner(JoinExpr {
+            col_rhs: public,
+            rhs: QueryExpr::new(lhs.clone()).with_select_cmp(OpCmp::Eq, private, AlgebraicValue::U8(0)),
+            col_lhs: FieldName::new(rhs.head().table_id, 0.into()),
+            kind: JoinKind::Semi,
+        }));
+        let query = query.with_project(&[FieldName::new(rhs.head().table_id, 1.into()).into()], None);
+        assert_private(&query);
+
+        let errors = query.check_auth_all(ALICE, BOB).unwrap_err();
+        assert_eq!(errors.len(), 1);
+
+        // Joining on the private column reads it too, though only public columns are projected.
+        let rhs_id = FieldName::new(rhs.head().table_id, 0.into());
+        let rhs_public = FieldName::new(rhs.head().table_id, 1.into());
+        let query = QueryExpr::new(rhs.clone())
+            .with_join_inner(lhs.clone(), rhs_id, private, false)
+            .with_project(&[rhs_public.into()], None);
+        assert_private(&query);
+        // So does probing an index on it.
+        let mut query = QueryExpr::new(lhs.clone());
+        query.query.push(Query::IndexJoin(IndexJoin {
+            probe_side: rhs.clone().into(),
+            probe_fields: smallvec![rhs_id],
+            index_side: lhs.clone(),
+            index_select: None,
+            index_cols: ColId(1).into(),
+            return_index_rows: false,
+            projection: None,
+        }));
+        let query = query.with_project(&[rhs_public.into()], None);
+        assert_private(&query);
+    }
+
+    #[test]
+    fn test_auth_crud_code_insert() {
+        for table in tables().into_iter().filter_map(|s| s.get_db_table().cloned()) {
+            let crud = CrudExpr::Insert { table, rows: vec![] };
+            assert_owner_required(crud);
+        }
+    }
+
+    #[test]
+    fn test_auth_crud_code_update() {
+        for qc in query_exprs() {
+            let crud = CrudExpr::Update {
+                delete: qc,
+                assignments: Default::default(),
+                expected_max_rows: None,
+            };
+            assert_owner_required(crud);
+        }
+    }
+
+    #[test]
+    fn test_auth_crud_code_delete() {
+        for query in query_exprs() {
+            let crud = CrudExpr::Delete {
+                query,
+                expected_max_rows: None,
+            };
+            assert_owner_required(crud);
+        }
+    }
+
+    #[test]
+    fn test_auth_crud_code_create_table() {
+        let table = TableDef::new("etcpasswd".into(), vec![])
+            .with_access(StAccess::Public)
+            .with_type(StTableType::System); // hah!
+
+        let crud = CrudExpr::CreateTable { table };
+        assert_owner_required(crud);
+    }
+
+    #[test]
+    fn test_auth_crud_code_drop() {
+        let crud = CrudExpr::Drop {
+            name: "etcpasswd".into(),
+            kind: DbType::Table,
+            table_access: StAccess::Public,
+        };
+        assert_owner_required(crud);
+    }
+
+    #[test]
+    /// Tests that [`QueryExpr::optimize`] can rewrite inner joins followed by projections into semijoins.
+    fn optimize_inner_join_to_semijoin() {
+        let lhs = TableSchema::from_def(
+            TableId(0),
+            TableDef::new(
+                "lhs".into(),
+                ProductType::from_iter([AlgebraicType::I32, AlgebraicType::String]).into(),
+            ),
+        );
+        let rhs = TableSchema::from_def(
+            TableId(1),
+            TableDef::new(
+                "rhs".into(),
+                ProductType::from_iter([AlgebraicType::I32, AlgebraicType::I64]).into(),
+            ),
+        );
+
+        let lhs_source = SourceExpr::from(&lhs);
+        let rhs_source = SourceExpr::from(&rhs);
+
+        let q = QueryExpr::new(lhs_source.clone())
+            .with_join_inner(
+                rhs_source.clone(),
+                FieldName::new(lhs.table_id, 0.into()),
+                FieldName::new(rhs.table_id, 0.into()),
+                false,
+            )
+            .with_project(
+                &[0, 1].map(|c| FieldExpr::Name(FieldName::new(lhs.table_id, c.into()))),
+                Some(TableId(0)),
+            );
+        let q = q.optimize(&|_, _| 0);
+
+        assert_eq!(q.source, lhs_source, "Optimized query should read from lhs");
+
+        assert_eq!(
+            q.query.len(),
+            1,
+            "Optimized query should have a single member, a semijoin"
+        );
+        match &q.query[0] {
+            Query::JoinInner(JoinExpr { rhs, kind, .. }) => {
+                assert_eq!(*kind, JoinKind::Semi, "Optimized query should be a semijoin");
+                assert_eq!(rhs.source, rhs_source, "Optimized query should filter with rhs");
+                assert!(
+                    rhs.query.is_empty(),
+                    "Optimized query should not filter rhs before joining"
+                );
+            }
+            wrong => panic!("Expected an inner join, but found {wrong:?}"),
+        }
+    }
+
+    #[test]
+    /// Tests that [`QueryExpr::optimize`] will not rewrite inner joins which are not followed by projections to the LHS table.
+    fn optimize_inner_join_no_project() {
+        let lhs = TableSchema::from_def(
+            TableId(0),
+            TableDef::new(
+                "lhs".into(),
+                ProductType::from_iter([AlgebraicType::I32, AlgebraicType::String]).into(),
+            ),
+        );
+        let rhs = TableSchema::from_def(
+            TableId(1),
+            TableDef::new(
+                "rhs".into(),
+                ProductType::from_iter([AlgebraicType::I32, AlgebraicType::I64]).into(),
+            ),
+        );
+
+        let lhs_source = SourceExpr::from(&lhs);
+        let rhs_source = SourceExpr::from(&rhs);
+
+        let q = QueryExpr::new(lhs_source.clone()).with_join_inner(
+            rhs_source.clone(),
+            FieldName::new(lhs.table_id, 0.into()),
+            FieldName::new(rhs.table_id, 0.into()),
+            false,
+        );
+        let optimized = q.clone().optimize(&|_, _| 0);
+        assert_eq!(q, optimized);
+    }
+
+    #[test]
+    /// Tests that [`QueryExpr::optimize`] will not rewrite inner joins followed by projections to the RHS rather than LHS table.
+    fn optimize_inner_join_wrong_project() {
+        let lhs = TableSchema::from_def(
+            TableId(0),
+            TableDef::new(
+                "lhs".into(),
+                ProductType::from_iter([AlgebraicType::I32, AlgebraicType::String]).into(),
+            ),
+        );
+        let rhs = TableSchema::from_def(
+            TableId(1),
+            TableDef::new(
+                "rhs".into(),
+                ProductType::from_iter([AlgebraicType::I32, AlgebraicType::I64]).into(),
+            ),
+        );
+
+        let lhs_source = SourceExpr::from(&lhs);
+        let rhs_source = SourceExpr::from(&rhs);
+
+        let q = QueryExpr::new(lhs_source.clone())
+            .with_join_inner(
+                rhs_source.clone(),
+                FieldName::new(lhs.table_id, 0.into()),
+                FieldName::new(rhs.table_id, 0.into()),
+                false,
+            )
+            .with_project(
+                &[0, 1].map(|c| FieldExpr::Name(FieldName::new(rhs.table_id, c.into()))),
+                Some(TableId(1)),
+            );
+        let optimized = q.clone().optimize(&|_, _| 0);
+        assert_eq!(q, optimized);
+    }
+
+    #[test]
+    fn test_flatten_ors() {
+        let [a, b, c] = [0, 1, 2].map(|c| FieldName::new(TableId(0), ColId(c)));
+        let [eq_a, eq_b, eq_c] = [(a, 1u64), (b, 2), (c, 3)].map(|(field, val)| ColumnOp::cmp(field, OpCmp::Eq, val));
+
+        // a = 1 OR b = 2 OR c = 3
+        let op = ColumnOp::or(ColumnOp::or(eq_a.clone(), eq_b.clone()), eq_c.clone());
+        assert_eq!(op.flatten_ors_ref().as_slice(), [&eq_a, &eq_b, &eq_c]);
+        assert_eq!(op.flatten_ors().as_slice(), [eq_a.clone(), eq_b.clone(), eq_c.clone()]);
+
+        // a = 1 OR (b = 2 AND c = 3)
+        let and = ColumnOp::and(eq_b.clone(), eq_c.clone());
+        let op = ColumnOp::or(eq_a.clone(), and.clone());
+        assert_eq!(op.flatten_ors_ref().as_slice(), [&eq_a, &and]);
+        assert_eq!(op.flatten_ors().as_slice(), [eq_a.clone(), and.clone()]);
+        // ...which is a single conjunct.
+        assert_eq!(op.flatten_ands_ref().as_slice(), [&op]);
+        assert_eq!(and.flatten_ands().as_slice(), [eq_b, eq_c]);
+    }
+
+    #[test]
+    fn test_referenced_fields() {
+        let [a, b, c] = [0, 1, 2].map(|c| FieldName::new(TableId(0), ColId(c)));
+
+        // (a = 1 AND b = 2) OR (a = 3 AND c > 4)
+        let lhs = ColumnOp::and(ColumnOp::cmp(a, OpCmp::Eq, 1u64), ColumnOp::cmp(b, OpCmp::Eq, 2u64));
+        let rhs = ColumnOp::and(ColumnOp::cmp(a, OpCmp::Eq, 3u64), ColumnOp::cmp(c, OpCmp::Gt, 4u64));
+        let op = ColumnOp::new(OpQuery::Logic(OpLogic::Or), lhs, rhs);
+
+        assert_eq!(op.referenced_fields().as_slice(), &[a, b, c]);
+
+        // A literal-only predicate references no fields.
+        let op = ColumnOp::new(
+            OpQuery::Cmp(OpCmp::Eq),
+            AlgebraicValue::U64(1).into(),
+            AlgebraicValue::U64(1).into(),
+        );
+        assert!(op.referenced_fields().is_empty());
+    }
+
+    #[test]
+    fn best_index_selectivity() {
+        let (head1, fields, vals) = setup_best_index();
+        let [col_a, col_b, ..] = fields;
+        let [val_a, val_b, ..] = vals;
+
+        let arena = Arena::new();
+        let select_best_index = |stats: &ColumnStatsMap| {
+            let fields = [(col_a, &val_a), (col_b, &val_b)]
+                .map(|(col, val)| make_field_value(&arena, (OpCmp::Eq, col, val)).parent);
+            select_best_index(&mut <_>::default(), &head1, stats, &fields)
+        };
+        let stats = |a, b| {
+            ColumnStatsMap::from([
+                (
+                    col_a.col,
+                    ColumnStats {
+                        distinct_count: a,
+                        ..Default::default()
+                    },
+                ),
+                (
+                    col_b.col,
+                    ColumnStats {
+                        distinct_count: b,
+                        ..Default::default()
+                    },
+                ),
+            ])
+        };
+
+        let col_list_arena = Arena::new();
+        let idx_eq = |cols, val| make_index_arg(OpCmp::Eq, col_list_arena.alloc(cols), val);
+        let both_seeks: IndexColumnOpSink = [
+            idx_eq(col_a.col.into(), val_a.clone()),
+            idx_eq(col_b.col.into(), val_b.clone()),
+        ]
+        .into();
+
+        // Without hints, both equalities are served by seeks.
+        assert_eq!(select_best_index(&ColumnStatsMap::new()), both_seeks);
+        assert_eq!(select_best_index(&stats(None, Some(2))), both_seeks);
+
+        // Comparable selectivity keeps both seeks.
+        assert_eq!(select_best_index(&stats(Some(1000), Some(500))), both_seeks);
+
+        // `b` is far less selective than `a`, so it becomes a residual filter.
+        assert_eq!(
+            select_best_index(&stats(Some(1000), Some(2))),
+            [idx_eq(col_a.col.into(), val_a.clone()), scan_eq(&arena, col_b, &val_b)].into()
+        );
+        assert_eq!(
+            select_best_index(&stats(Some(3), Some(30))),
+            [scan_eq(&arena, col_a, &val_a), idx_eq(col_b.col.into(), val_b.clone())].into()
+        );
+
+        // A demoted seek must not be considered as served by an index.
+        let mut fields_indexed = FieldsIndexed::default();
+        let ops =
+            [(col_a, &val_a), (col_b, &val_b)].map(|(col, val)| make_field_value(&arena, (OpCmp::Eq, col, val)).parent);
+        super::select_best_index(&mut fields_indexed, &head1, &stats(Some(1000), Some(2)), &ops);
+        assert!(fields_indexed.contains(&(col_a, OpCmp::Eq)));
+        assert!(!fields_indexed.contains(&(col_b, OpCmp::Eq)));
+    }
+
+    #[test]
+    /// Tests that [`QueryExpr::optimize_with`] skips the index join rewrite when it is disabled.
+    fn optimize_with_index_join_disabled() {
+        let lhs = TableSchema::from_def(
+            TableId(0),
+            TableDef::new(
+                "lhs".into(),
+                ProductType::from_iter([AlgebraicType::I32, AlgebraicType::String]).into(),
+            )
+            .with_column_constraint(Constraints::indexed(), ColId(0)),
+        );
+        let rhs = TableSchema::from_def(
+            TableId(1),
+            TableDef::new(
+                "rhs".into(),
+                ProductType::from_iter([AlgebraicType::I32, AlgebraicType::I64]).into(),
+            ),
+        );
+
+        let lhs_source = SourceExpr::from(&lhs);
+        let rhs_source = SourceExpr::from(&rhs);
+
+        let probe_side = QueryExpr::new(rhs_source).with_select_cmp(
+            OpCmp::Eq,
+            FieldName::new(rhs.table_id, 1.into()),
+            AlgebraicValue::I64(3),
+        );
+        let q = QueryExpr::new(lhs_source)
+            .with_join_inner(
+                probe_side,
+                FieldName::new(lhs.table_id, 0.into()),
+                FieldName::new(rhs.table_id, 0.into()),
+                false,
+            )
+            .with_project(
+                &[0, 1].map(|c| FieldExpr::Name(FieldName::new(lhs.table_id, c.into()))),
+                Some(TableId(0)),
+            );
+
+        let optimized = q.clone().optimize(&|_, _| 0);
+        assert!(
+            matches!(&*optimized.query, [Query::IndexJoin(_)]),
+            "Optimized query should be an index join, but found {:?}",
+            optimized.query
+        );
+
+        let opts = OptimizeOptions {
+            enable_index_join: false,
+            ..<_>::default()
+        };
+        let optimized = q.optimize_with(&|_, _| 0, &opts);
+        match &*optimized.query {
+            [Query::JoinInner(JoinExpr { kind, .. })] => {
+                assert_eq!(*kind, JoinKind::Semi, "Optimized query should still be a semijoin");
+            }
+            wrong => panic!("Expected an inner join, but found {wrong:?}"),
+        }
+    }
+
+    #[test]
+    fn point_lookup() {
+        let (head1, fields, vals) = setup_best_index();
+        let [col_a, col_b, col_c, ..] = fields;
+        let [val_a, val_b, val_c, ..] = vals;
+        let table = DbTable::new(Arc::new(head1), TableId(0), StTableType::User, StAccess::Public);
+
+        // Single-column index.
+        let columns: ColList = col_a.col.into();
+        let q = QueryExpr::point_lookup(table.clone(), columns.clone(), val_a.clone()).unwrap();
+        assert_eq!(
+            q,
+            QueryExpr::new(SourceExpr::DbTable(table.clone())).with_index_eq(
+                table.clone(),
+                columns.clone(),
+                val_a.clone()
+            )
+        );
+        assert_eq!(
+            q.query,
+            [Query::IndexScan(IndexScan {
+                table: table.clone(),
+                columns,
+                bounds: (Bound::Included(val_a.clone()), Bound::Included(val_a)),
+                covering: false,
+            })]
+        );
+
+        // Multi-column index.
+        let columns = col_list![col_b.col, col_c.col];
+        let key: AlgebraicValue = product![val_b.clone(), val_c].into();
+        let q = QueryExpr::point_lookup(table.clone(), columns.clone(), key.clone()).unwrap();
+        assert_eq!(
+            q.query,
+            [Query::IndexScan(IndexScan {
+                table: table.clone(),
+                columns,
+                bounds: (Bound::Included(key.clone()), Bound::Included(key)),
+                covering: false,
+            })]
+        );
+
+        // `[c]` is only a suffix of an index, and `[c, b]` is not an index.
+        assert!(QueryExpr::point_lookup(table.clone(), col_c.col.into(), val_b.clone()).is_err());
+        assert!(matches!(
+            QueryExpr::point_lookup(table, col_list![col_c.col, col_b.col], val_b),
+            Err(ErrorVm::NoSuchIndex { .. })
+        ));
+    }
+
+    #[test]
+    fn index_scan_try_new() {
+        let (head1, fields, vals) = setup_best_index();
+        let [col_a, col_b, col_c, col_d, ..] = fields;
+        let [val_a, val_b, val_c, ..] = vals;
+        let table = DbTable::new(Arc::new(head1), TableId(0), StTableType::User, StAccess::Public);
+
+        // A range on a single-column index.
+        let bounds = (Bound::Included(val_a.clone()), Bound::Unbounded);
+        assert_eq!(
+            IndexScan::try_new(table.clone(), col_a.col.into(), bounds.clone()).unwrap(),
+            IndexScan {
+                table: table.clone(),
+                columns: col_a.col.into(),
+                bounds,
+                covering: false,
+            }
+        );
+
+        // `d` is not indexed.
+        let bounds = (Bound::Included(val_a.clone()), Bound::Included(val_a.clone()));
+        assert!(matches!(
+            IndexScan::try_new(table.clone(), col_d.col.into(), bounds),
+            Err(ErrorVm::NoSuchIndex { .. })
+        ));
+
+        // A key of `[b, c]` must have a value for both columns.
+        let columns = col_list![col_b.col, col_c.col];
+        let key: AlgebraicValue = product![val_b.clone(), val_c].into();
+        let bounds = (Bound::Included(key.clone()), Bound::Included(key));
+        assert!(IndexScan::try_new(table.clone(), columns.clone(), bounds).is_ok());
+        for key in [product![val_b.clone()].into(), val_b] {
+            let bounds = (Bound::Unbounded, Bound::Excluded(key));
+            assert!(matches!(
+                IndexScan::try_new(table.clone(), columns.clone(), bounds),
+                Err(ErrorVm::IndexKeyArity { .. })
+            ));
+        }
+    }
+
+    #[test]
+    fn explain_index_usage() {
+        let (head1, fields, vals) = setup_best_index();
+        let [col_a, col_b, col_c, _, col_e] = fields;
+        let [val_a, val_b, val_c, val_d, val_e] = vals;
+        let table = DbTable::new(Arc::new(head1), TableId(0), StTableType::User, StAccess::Public);
+        let miss = |field, op, reason| IndexMissReason { field, op, reason };
+
+        // `a = 1 AND b > 2` are served by the indices on `[a]` and `[b]`,
+        // `c` only follows `b` in the indices on `[b, c]` and `[a, b, c, d]`,
+        // and `e` isn't indexed at all.
+        let op = [
+            ColumnOp::cmp(col_a, OpCmp::Eq, val_a.clone()),
+            ColumnOp::cmp(col_b, OpCmp::Gt, val_b.clone()),
+            ColumnOp::cmp(col_c, OpCmp::Eq, val_c),
+            ColumnOp::cmp(col_e, OpCmp::NotEq, val_e.clone()),
+            ColumnOp::cmp(col_e, OpCmp::Lt, val_d),
+            ColumnOp::or(
+                ColumnOp::cmp(col_a, OpCmp::Eq, val_a),
+                ColumnOp::cmp(col_e, OpCmp::Eq, val_e),
+            ),
+        ]
+        .into_iter()
+        .reduce(ColumnOp::and)
+        .unwrap();
+        let q = QueryExpr::new(SourceExpr::DbTable(table)).with_select(op);
+
+        assert_eq!(
+            q.explain_index_usage(),
+            [
+                miss(col_a, OpCmp::Eq, IndexMissKind::OrBranch),
+                miss(col_e, OpCmp::Eq, IndexMissKind::OrBranch),
+                miss(col_c, OpCmp::Eq, IndexMissKind::CompositePrefixGap),
+                miss(col_e, OpCmp::NotEq, IndexMissKind::NotEqUnsupported),
+                miss(col_e, OpCmp::Lt, IndexMissKind::NoIndex),
+            ]
+        );
+    }
+
+    #[test]
+    fn best_index_prefix() {
+        let table_id = 0.into();
+        let [a, b, c] = [0, 1, 2].map(ColId);
+        let [col_a, col_b, col_c] = [a, b, c].map(|c| FieldName::new(table_id, c));
+        let cols = [col_a, col_b, col_c].map(|f| Column::new(f, AlgebraicType::U64));
+        let [val_1, val_2, val_3] = [1, 2, 3].map(AlgebraicValue::U64);
+
+        // A table with only an index on `[a, b, c]`.
+        let head = Header::new(
+            table_id,
+            "t".into(),
+            cols.to_vec(),
+            vec![(col_list![a, b, c], Constraints::indexed())],
+        );
+
+        let arena = Arena::new();
+        let select_best_index = |fields: &[_]| {
+            let fields = fields
+                .iter()
+                .copied()
+                .map(|(col, val): (FieldName, _)| make_field_value(&arena, (OpCmp::Eq, col, val)).parent)
+                .collect::<Vec<_>>();
+            select_best_index(&mut <_>::default(), &head, &<_>::default(), &fields)
+        };
+        let prefix_eq =
+            |columns: ColList, value: AlgebraicValue| IndexColumnOp::Index(IndexArgument::PrefixEq { columns, value });
+
+        // `a` leads the index, so it's served by a seek on the index.
+        assert_eq!(
+            select_best_index(&[(col_a, &val_1)]),
+            [prefix_eq(a.into(), val_1.clone())].into(),
+        );
+        // So are `a` and `b`.
+        assert_eq!(
+            select_best_index(&[(col_b, &val_2), (col_a, &val_1)]),
+            [prefix_eq(
+                col_list![a, b],
+                product![val_1.clone(), val_2.clone()].into()
+            )]
+            .into(),
+        );
+        // `a` and `c` are not a prefix of the index, so only `a` is served by it.
+        assert_eq!(
+            select_best_index(&[(col_a, &val_1), (col_c, &val_3)]),
+            [prefix_eq(a.into(), val_1.clone()), scan_eq(&arena, col_c, &val_3)].into(),
+        );
+        // Neither `b` nor `c` lead the index.
+        assert_eq!(
+            select_best_index(&[(col_b, &val_2), (col_c, &val_3)]),
+            [scan_eq(&arena, col_b, &val_2), scan_eq(&arena, col_c, &val_3)].into(),
+        );
+        // All of `a`, `b`, and `c` still use the whole index.
+        let col_list_arena = Arena::new();
+        assert_eq!(
+            select_best_index(&[(col_c, &val_3), (col_a, &val_1), (col_b, &val_2)]),
+            [make_index_arg(
+                OpCmp::Eq,
+                col_list_arena.alloc(col_list![a, b, c]),
+                product![val_1.clone(), val_2.clone(), val_3.clone()].into(),
+            )]
+            .into(),
+        );
+    }
+
+    #[test]
+    fn best_index_partial() {
+        let table_id = 0.into();
+        let [a, b] = [0, 1].map(ColId);
+        let [col_a, col_b] = [a, b].map(|c| FieldName::new(table_id, c));
+        let cols = [col_a, col_b].map(|f| Column::new(f, AlgebraicType::U64));
+        let [val_0, val_1, val_10, val_11] = [0, 1, 10, 11].map(AlgebraicValue::U64);
+
+        // A table with an index on `a` covering only the rows where `b = 0`.
+        let eq_0 = PartialIndexPredicate {
+            ranges: vec![(b, Bound::Included(val_0.clone()), Bound::Included(val_0.clone()))],
+        };
+        let head_eq = Header::new(table_id, "t1".into(), cols.to_vec(), vec![]).with_partial_index(a.into(), eq_0);
+        // A table with an index on `a` covering only the rows where `b > 10`.
+        let gt_10 = PartialIndexPredicate {
+            ranges: vec![(b, Bound::Excluded(val_10.clone()), Bound::Unbounded)],
+        };
+        let head_gt = Header::new(table_id, "t2".into(), cols.to_vec(), vec![]).with_partial_index(a.into(), gt_10);
+
+        fn select_best_index<'a>(
+            arena: &'a Arena<ColumnOp>,
+            head: &'a Header,
+            fields: &[(OpCmp, FieldName, &'a AlgebraicValue)],
+        ) -> IndexColumnOpSink<'a> {
+            let fields = fields
+                .iter()
+                .map(|x| make_field_value(arena, *x).parent)
+                .collect::<Vec<_>>();
+            super::select_best_index(&mut <_>::default(), head, &<_>::default(), &fields)
+        }
+
+        let arena = Arena::new();
+        let col_list_arena = Arena::new();
+        let idx_eq = |cols, val| make_index_arg(OpCmp::Eq, col_list_arena.alloc(cols), val);
+
+        // The query implies `b = 0`, so the partial index is used.
+        assert_eq!(
+            select_best_index(
+                &arena,
+                &head_eq,
+                &[(OpCmp::Eq, col_a, &val_1), (OpCmp::Eq, col_b, &val_0)]
+            ),
+            [idx_eq(a.into(), val_1.clone()), scan_eq(&arena, col_b, &val_0)].into(),
+        );
+        // The query does not constrain `b`, so it's not covered by the partial index.
+        assert_eq!(
+            select_best_index(&arena, &head_eq, &[(OpCmp::Eq, col_a, &val_1)]),
+            [scan_eq(&arena, col_a, &val_1)].into(),
+        );
+        // The query contradicts `b = 0`.
+        assert_eq!(
+            select_best_index(
+                &arena,
+                &head_eq,
+                &[(OpCmp::Eq, col_a, &val_1), (OpCmp::Eq, col_b, &val_1)]
+            ),
+            [scan_eq(&arena, col_a, &val_1), scan_eq(&arena, col_b, &val_1)].into(),
+        );
+
+        // `b >= 11` implies `b > 10`.
+        assert_eq!(
+            select_best_index(
+                &arena,
+                &head_gt,
+                &[(OpCmp::Eq, col_a, &val_1), (OpCmp::GtEq, col_b, &val_11)]
+            ),
+            [
+                idx_eq(a.into(), val_1.clone()),
+                scan(&arena, OpCmp::GtEq, col_b, &val_11)
+            ]
+            .into(),
+        );
+        // `b >= 10` does not imply `b > 10`.
+        assert_eq!(
+            select_best_index(
+                &arena,
+                &head_gt,
+                &[(OpCmp::Eq, col_a, &val_1), (OpCmp::GtEq, col_b, &val_10)]
+            ),
+            [
+                scan_eq(&arena, col_a, &val_1),
+                scan(&arena, OpCmp::GtEq, col_b, &val_10)
+            ]
+            .into(),
+        );
+        // Neither does `b < 11`.
+        assert_eq!(
+            select_best_index(
+                &arena,
+                &head_gt,
+                &[(OpCmp::Eq, col_a, &val_1), (OpCmp::Lt, col_b, &val_11)]
+            ),
+            [scan_eq(&arena, col_a, &val_1), scan(&arena, OpCmp::Lt, col_b, &val_11)].into(),
+        );
+    }
+
+    #[test]
+    fn best_index_in_list() {
+        let table_id = 0.into();
+        let [a, b] = [0, 1].map(ColId);
+        let [col_a, col_b] = [a, b].map(|c| FieldName::new(table_id, c));
+        let cols = [col_a, col_b].map(|f| Column::new(f, AlgebraicType::U8));
+        // Only `a` is indexed.
+        let head = Header::new(
+            table_id,
+            "t1".into(),
+            cols.to_vec(),
+            vec![(a.into(), Constraints::indexed())],
+        );
+        let columns = a.into();
+
+        fn select_best_index<'a>(head: &'a Header, op: &'a ColumnOp) -> IndexColumnOpSink<'a> {
+            super::select_best_index(&mut <_>::default(), head, &<_>::default(), &[op])
+        }
+        let index = |arg| -> IndexColumnOpSink { [IndexColumnOp::Index(arg)].into() };
+
+        // A contiguous list, in any order, is served by a single range.
+        let op = ColumnOp::in_list(col_a, [7u8, 5, 8, 6, 6]);
+        assert_eq!(
+            select_best_index(&head, &op),
+            index(IndexArgument::Range {
+                columns: &columns,
+                lower: 5u8.into(),
+                upper: 8u8.into(),
+            }),
+        );
+        // A sparse list is served by a seek per value.
+        let op = ColumnOp::in_list(col_a, [9u8, 5, 7]);
+        assert_eq!(
+            select_best_index(&head, &op),
+            index(IndexArgument::In {
+                columns: &columns,
+                values: vec![5u8.into(), 7u8.into(), 9u8.into()],
+            }),
+        );
+        // Contiguity does not wrap around at the bounds of the type.
+        let op = ColumnOp::in_list(col_a, [254u8, 255, 0]);
+        assert_eq!(
+            select_best_index(&head, &op),
+            index(IndexArgument::In {
+                columns: &columns,
+                values: vec![0u8.into(), 254u8.into(), 255u8.into()],
+            }),
+        );
+        // A single value is an equality.
+        let op = ColumnOp::in_list(col_a, [3u8, 3]);
+        assert_eq!(
+            select_best_index(&head, &op),
+            index(IndexArgument::Eq {
+                columns: &columns,
+                value: 3u8.into(),
+            }),
+        );
+        // Without an index, the list must be scanned.
+        let op = ColumnOp::in_list(col_b, [5u8, 6]);
+        assert_eq!(select_best_index(&head, &op), [IndexColumnOp::Scan(&op)].into());
+    }
+
+    #[test]
+    fn optimize_column_to_column_cmp() {
+        let table = TableSchema::from_def(
+            TableId(0),
+            TableDef::new(
+                "t".into(),
+                ProductType::from_iter([AlgebraicType::U8, AlgebraicType::U8]).into(),
+            )
+            .with_column_constraint(Constraints::indexed(), ColId(0))
+            .with_column_constraint(Constraints::indexed(), ColId(1)),
+        );
+        let a = FieldName::new(table.table_id, 0.into());
+        let b = FieldName::new(table.table_id, 1.into());
+        let a_lt_b = ColumnOp::new(
+            OpQuery::Cmp(OpCmp::Lt),
+            ColumnOp::Field(a.into()),
+            ColumnOp::Field(b.into()),
+        );
+
+        // Neither column is compared to a value, so neither index can serve the comparison.
+        let q = QueryExpr::new(&table).with_select(a_lt_b.clone()).optimize(&|_, _| 0);
+        assert_eq!(q.query, [Query::Select(a_lt_b.clone())]);
+
+        // Alongside a sargable comparison, it is still only a filter on the rows of the index scan.
+        let q = QueryExpr::new(&table)
+            .with_select(ColumnOp::and(a_lt_b.clone(), ColumnOp::cmp(a, OpCmp::Eq, 3u8)))
+            .optimize(&|_, _| 0);
+        match &*q.query {
+            [Query::IndexScan(scan), Query::Select(filter)] => {
+                assert_eq!(scan.columns, ColList::new(ColId(0)));
+                assert_eq!(*filter, a_lt_b);
+            }
+            wrong => panic!("Expected an index scan followed by a filter, but found {wrong:?}"),
+        }
+    }
+
+    #[test]
+    fn optimize_in_list() {
+        let table = TableSchema::from_def(
+            TableId(0),
+            TableDef::new("t".into(), ProductType::from_iter([AlgebraicType::U8]).into())
+                .with_column_constraint(Constraints::indexed(), ColId(0)),
+        );
+        let source = SourceExpr::from(&table);
+        let db_table = source.get_db_table().unwrap().clone();
+        let field = FieldName::new(table.table_id, 0.into());
+        let columns: ColList = ColId(0).into();
+        let optimize = |values: &[u8]| {
+            QueryExpr::new(source.clone())
+                .with_select(ColumnOp::in_list(field, values.iter().copied()))
+                .optimize(&|_, _| 0)
+                .query
+        };
+
+        // A contiguous list becomes a single range scan.
+        assert_eq!(
+            optimize(&[6, 5, 7]),
+            [Query::IndexScan(IndexScan {
+                table: db_table.clone(),
+                columns: columns.clone(),
+                bounds: (Bound::Included(5u8.into()), Bound::Included(7u8.into())),
+                covering: false,
+            })],
+        );
+        // A sparse list becomes a seek per value.
+        assert_eq!(
+            optimize(&[7, 5]),
+            [Query::IndexMultiScan(IndexMultiScan {
+                table: db_table,
+                columns,
+                ranges: [5u8, 7]
+                    .map(|v| (Bound::Included(v.into()), Bound::Included(v.into())))
+                    .to_vec(),
+            })],
+        );
+    }
+
+    #[test]
+    fn optimize_full_range_index_scan() {
+        let table = TableSchema::from_def(
+            TableId(0),
+            TableDef::new("t".into(), ProductType::from_iter([AlgebraicType::U8]).into())
+                .with_column_constraint(Constraints::indexed(), ColId(0)),
+        );
+        let source = SourceExpr::from(&table);
+        let scan = |bounds| {
+            Query::IndexScan(IndexScan {
+                table: source.get_db_table().unwrap().clone(),
+                columns: ColId(0).into(),
+                bounds,
+                covering: false,
+            })
+        };
+        let optimize = |query| {
+            let mut q = QueryExpr::new(source.clone());
+            q.query.push(query);
+            q.optimize(&|_, _| 0).query
+        };
+
+        // A full range scan is replaced by a sequential scan of the source.
+        assert!(optimize(scan((Bound::Unbounded, Bound::Unbounded))).is_empty());
+        // A bounded one is kept.
+        let bounded = scan((Bound::Included(5u8.into()), Bound::Unbounded));
+        assert_eq!(optimize(bounded.clone()), [bounded]);
+    }
+
+    #[test]
+    fn type_check() {
+        let table_id = TableId(0);
+        let [a, b] = [0, 1].map(|col| FieldName::new(table_id, ColId(col)));
+        let header = Header::new(
+            table_id,
+            "t".into(),
+            vec![Column::new(a, AlgebraicType::U64), Column::new(b, AlgebraicType::Bool)],
+            vec![],
+        );
+
+        // `a > 1 AND b OR a IN (2, 3)`
+        let ok = ColumnOp::or(
+            ColumnOp::and(ColumnOp::cmp(a, OpCmp::Gt, 1u64), ColumnOp::Field(FieldExpr::Name(b))),
+            ColumnOp::in_list(a, [2u64, 3]),
+        );
+        assert!(ok.type_check(&header).is_ok());
+
+        // `b = 1`
+        let mismatch = ColumnOp::cmp(b, OpCmp::Eq, 1u64);
+        match mismatch.type_check(&header) {
+            Err(ErrorType::Mismatch { lhs, rhs }) => assert_eq!((lhs, rhs), (AlgebraicType::Bool, AlgebraicType::U64)),
+            result => panic!("expected a mismatch, got {result:?}"),
+        }
+
+        // `b AND a`
+        let not_bool = ColumnOp::and(ColumnOp::Field(FieldExpr::Name(b)), ColumnOp::Field(FieldExpr::Name(a)));
+        match not_bool.type_check(&header) {
+            Err(ErrorType::NotBool(ty)) => assert_eq!(ty, AlgebraicType::U64),
+            result => panic!("expected a non-bool operand, got {result:?}"),
+        }
+    }
+
+    #[test]
+    fn query_type_check() {
+        let lhs = mem_table(
+            TableId(0),
+            "lhs",
+            &[(0, AlgebraicType::U64, false), (1, AlgebraicType::String, false)],
+        );
+        let rhs = mem_table(
+            TableId(1),
+            "rhs",
+            &[(0, AlgebraicType::U64, false), (1, AlgebraicType::Bool, false)],
+        );
+        let [lhs_a, lhs_b] = [0, 1].map(|col| FieldName::new(TableId(0), ColId(col)));
+        let [rhs_a, rhs_b] = [0, 1].map(|col| FieldName::new(TableId(1), ColId(col)));
+
+        // `lhs JOIN rhs ON lhs.a = rhs.a WHERE rhs.b = true AND lhs.b = 'x'`
+        let ok = QueryExpr::new(lhs.clone())
+            .with_join_inner(QueryExpr::new(rhs.clone()), lhs_a, rhs_a, false)
+            .with_select(ColumnOp::and(
+                ColumnOp::cmp(rhs_b, OpCmp::Eq, AlgebraicValue::Bool(true)),
+                ColumnOp::cmp(lhs_b, OpCmp::Eq, "x"),
+            ));
+        assert!(ok.type_check().is_ok());
+
+        // `lhs WHERE lhs.a > 1 AND lhs.b = 2`
+        let mismatch = QueryExpr::new(lhs.clone()).with_select(ColumnOp::and(
+            ColumnOp::cmp(lhs_a, OpCmp::Gt, 1u64),
+            ColumnOp::cmp(lhs_b, OpCmp::Eq, 2u64),
+        ));
+        match mismatch.type_check() {
+            Err(ErrorType::FieldMismatch { field, ty, value_ty }) => {
+                assert_eq!(
+                    (field, ty, value_ty),
+                    (lhs_b, AlgebraicType::String, AlgebraicType::U64)
+                )
+            }
+            result => panic!("expected a field mismatch, got {result:?}"),
+        }
+
+        // `lhs JOIN rhs ON lhs.b = rhs.b`
+        let join = QueryExpr::new(lhs).with_join_inner(QueryExpr::new(rhs), lhs_b, rhs_b, false);
+        match join.type_check() {
+            Err(ErrorType::JoinKeyMismatch {
+                lhs,
+                lhs_ty,
+                rhs,
+                rhs_ty,
+            }) => assert_eq!(
+                (lhs, lhs_ty, rhs, rhs_ty),
+                (lhs_b, AlgebraicType::String, rhs_b, AlgebraicType::Bool)
+            ),
+            result => panic!("expected a join key mismatch, got {result:?}"),
+        }
+    }
+
+    #[test]
+    fn optimize_merge_join() {
+        let table = |id: u32, name: &str| {
+            TableSchema::from_def(
+                TableId(id),
+                TableDef::new(
+                    name.into(),
+                    ProductType::from_iter([AlgebraicType::U8, AlgebraicType::U8]).into(),
+                )
+                .with_column_constraint(Constraints::indexed(), ColId(0)),
+            )
+        };
+        let (lhs, rhs) = (table(0, "lhs"), table(1, "rhs"));
+        let col_lhs = FieldName::new(lhs.table_id, 0.into());
+        let col_rhs = FieldName::new(rhs.table_id, 0.into());
+        let join = |rhs: QueryExpr| {
+            QueryExpr::new(&lhs)
+                .with_join_inner(rhs, col_lhs, col_rhs, false)
+                .optimize(&|_, _| 0)
+                .query
+        };
+
+        // Both tables are read in full, in the order of their index on the join column.
+        assert_eq!(
+            join(QueryExpr::new(&rhs)),
+            [Query::MergeJoin(MergeJoinExpr {
+                lhs: SourceExpr::from(&lhs).get_db_table().unwrap().clone(),
+                rhs: SourceExpr::from(&rhs).get_db_table().unwrap().clone(),
+                col_lhs,
+                col_rhs,
+                semi: false,
+            })]
+        );
+        // A filter on the rhs rules out reading it in index order.
+        let filtered = QueryExpr::new(&rhs).with_select_cmp(
+            OpCmp::Eq,
+            FieldName::new(rhs.table_id, 1.into()),
+            AlgebraicValue::U8(3),
+        );
+        assert!(matches!(&*join(filtered), [Query::JoinInner(_)]));
+    }
+
+    #[test]
+    fn project_header() {
+        let table_id = TableId(0);
+        let [a, b, c] = [0, 1, 2].map(|col| FieldName::new(table_id, ColId(col)));
+        let header = Header::new(
+            table_id,
+            "t".into(),
+            [a, b, c].map(|f| Column::new(f, AlgebraicType::U8)).into(),
+            vec![
+                (ColId(0).into(), Constraints::indexed()),
+                (col_list![ColId(1), ColId(2)], Constraints::unique()),
+                (ColId(2).into(), Constraints::indexed()),
+            ],
+        )
+        .with_partial_index(
+            ColId(2).into(),
+            PartialIndexPredicate {
+                ranges: vec![(ColId(0), Bound::Included(1u8.into()), Bound::Unbounded)],
+            },
+        );
+        let source = SourceExpr::DbTable(DbTable::new(
+            Arc::new(header),
+            table_id,
+            StTableType::User,
+            StAccess::Public,
+        ));
+
+        let projected = source
+            .project_header(&[(FieldExpr::Name(c), None), (FieldExpr::Name(a), None)])
+            .unwrap();
+
+        assert_eq!(
+            projected.fields,
+            [Column::new(c, AlgebraicType::U8), Column::new(a, AlgebraicType::U8)]
+        );
+        // The constraint on `(b, c)` is dropped, as `b` is left out.
+        assert_eq!(
+            projected.constraints,
+            [
+                (ColId(1).into(), Constraints::indexed()),
+                (ColId(0).into(), Constraints::indexed()),
+            ]
+        );
+        assert_eq!(
+            projected.partial_indexes,
+            [(
+                ColId(0).into(),
+                PartialIndexPredicate {
+                    ranges: vec![(ColId(1), Bound::Included(1u8.into()), Bound::Unbounded)],
+                }
+            )]
+        );
+
+        // Unknown fields are rejected.
+        let unknown = FieldName::new(table_id, ColId(3));
+        assert!(source.project_header(&[(FieldExpr::Name(unknown), None)]).is_err());
+    }
+
+    #[test]
+    fn optimize_in_list_max_expansion() {
+        let table = TableSchema::from_def(
+            TableId(0),
+            TableDef::new("t".into(), ProductType::from_iter([AlgebraicType::U8]).into())
+                .with_column_constraint(Constraints::indexed(), ColId(0)),
+        );
+        let source = SourceExpr::from(&table);
+        let field = FieldName::new(table.table_id, 0.into());
+        let opts = OptimizeOptions {
+            max_expansion: 2,
+            ..<_>::default()
+        };
+        let optimize = |values: &[u8]| {
+            QueryExpr::new(source.clone())
+                .with_select(ColumnOp::in_list(field, values.iter().copied()))
+                .optimize_with(&|_, _| 0, &opts)
+                .query
+        };
+
+        // Under the cap, the list expands into a seek per value.
+        assert!(matches!(&*optimize(&[5, 1]), [Query::IndexMultiScan(_)]));
+        // Over the cap, the list is served by a scan.
+        assert_eq!(
+            optimize(&[5, 1, 3]),
+            [Query::Select(ColumnOp::in_list(field, [1u8, 3, 5]))]
+        );
+    }
+
+    #[test]
+    fn optimize_in_list_on_other_table() {
+        let table = TableSchema::from_def(
+            TableId(0),
+            TableDef::new("t".into(), ProductType::from_iter([AlgebraicType::U8]).into())
+                .with_column_constraint(Constraints::indexed(), ColId(0)),
+        );
+        let source = SourceExpr::from(&table);
+        let field = FieldName::new(table.table_id, 0.into());
+        // Columns of a table that isn't a source of the query.
+        let [other_a, other_b] = [0, 1].map(|col| FieldName::new(TableId(1), ColId(col)));
+        let optimize = |in_list: ColumnOp| {
+            QueryExpr::new(source.clone())
+                .with_select(ColumnOp::and(ColumnOp::cmp(field, OpCmp::Eq, 1u8), in_list))
+                .optimize(&|_, _| 0)
+                .query
+        };
+
+        // The IN-list is kept as a filter rather than dropped.
+        let in_list = ColumnOp::in_list(other_a, [2u8, 4]);
+        match &*optimize(in_list.clone()) {
+            [Query::IndexScan(_), Query::Select(filter)] => assert_eq!(*filter, in_list),
+            wrong => panic!("Expected an index scan followed by the IN-list, but found {wrong:?}"),
+        }
+        let in_tuples = ColumnOp::in_tuples([other_a, other_b], [product![2u8, 3u8], product![4u8, 5u8]]);
+        match &*optimize(in_tuples) {
+            [Query::IndexScan(_), Query::Select(_)] => {}
+            wrong => panic!("Expected an index scan followed by the IN-list, but found {wrong:?}"),
+        }
+    }
+
+    #[test]
+    fn key_ranges() {
+        let table = TableSchema::from_def(
+            TableId(0),
+            TableDef::new("t".into(), ProductType::from_iter([AlgebraicType::U8]).into())
+                .with_column_constraint(Constraints::indexed(), ColId(0)),
+        );
+        let field = FieldName::new(table.table_id, 0.into());
+
+        // WHERE a BETWEEN 1 AND 10
+        let between = ColumnOp::and(
+            ColumnOp::cmp(field, OpCmp::GtEq, 1u8),
+            ColumnOp::cmp(field, OpCmp::LtEq, 10u8),
+        );
+        let q = QueryExpr::new(&table).with_select(between).optimize(&|_, _| 0);
+        assert_eq!(
+            q.key_ranges(),
+            [(
+                table.table_id,
+                ColId(0).into(),
+                (Bound::Included(1u8.into()), Bound::Included(10u8.into()))
+            )]
+        );
+
+        // A sequential scan covers no key range.
+        let q = QueryExpr::new(&table).with_select(ColumnOp::cmp(field, OpCmp::NotEq, 1u8));
+        assert!(q.key_ranges().is_empty());
+    }
+
+    #[test]
+    fn canonicalize() {
+        let table = TableSchema::from_def(
+            TableId(0),
+            TableDef::new(
+                "t".into(),
+                ProductType::from_iter([AlgebraicType::U8, AlgebraicType::U8]).into(),
+            ),
+        );
+        let a = FieldName::new(table.table_id, 0.into());
+        let b = FieldName::new(table.table_id, 1.into());
+        let value = |v: u8| ColumnOp::Field(FieldExpr::Value(v.into()));
+        let flipped = |v: u8, cmp: OpCmp, field: FieldName| ColumnOp::new(OpQuery::Cmp(cmp), value(v), field.into());
+        let plan = |ops: Vec<Query>| {
+            let mut q = QueryExpr::new(&table);
+            q.query = ops;
+            q.canonicalize()
+        };
 
-    const ALICE: Identity = Identity::from_byte_array([1; 32]);
-    const BOB: Identity = Identity::from_byte_array([2; 32]);
+        // a < 5 AND (b = 1 OR b = 2)
+        let expected = plan(vec![Query::Select(ColumnOp::and(
+            ColumnOp::cmp(a, OpCmp::Lt, 5u8),
+            ColumnOp::or(ColumnOp::cmp(b, OpCmp::Eq, 1u8), ColumnOp::cmp(b, OpCmp::Eq, 2u8)),
+        ))]);
+
+        // (b = 2 OR b = 1 OR 1 = b) AND 5 > a
+        let reordered = plan(vec![Query::Select(ColumnOp::and(
+            ColumnOp::or(
+                ColumnOp::or(ColumnOp::cmp(b, OpCmp::Eq, 2u8), ColumnOp::cmp(b, OpCmp::Eq, 1u8)),
+                flipped(1, OpCmp::Eq, b),
+            ),
+            flipped(5, OpCmp::Gt, a),
+        ))]);
+        assert_eq!(reordered, expected);
+
+        // Separate selections, constants, and an identity projection.
+        let split = plan(vec![
+            Query::Select(ColumnOp::new(OpQuery::Cmp(OpCmp::Eq), value(1), value(1))),
+            Query::Select(ColumnOp::or(
+                ColumnOp::cmp(b, OpCmp::Eq, 1u8),
+                ColumnOp::or(
+                    ColumnOp::new(OpQuery::Cmp(OpCmp::Lt), value(2), value(1)),
+                    ColumnOp::cmp(b, OpCmp::Eq, 2u8),
+                ),
+            )),
+            Query::Select(ColumnOp::cmp(a, OpCmp::Lt, 5u8)),
+            Query::Project(vec![(a.into(), None), (b.into(), None)], None, false),
+        ]);
+        assert_eq!(split, expected);
+
+        // Canonicalization is idempotent.
+        assert_eq!(expected.clone().canonicalize(), expected);
+
+        // A predicate that always holds selects everything.
+        let always = plan(vec![Query::Select(ColumnOp::or(
+            ColumnOp::cmp(a, OpCmp::Eq, 1u8),
+            ColumnOp::new(OpQuery::Cmp(OpCmp::LtEq), value(1), value(2)),
+        ))]);
+        assert!(always.query.is_empty());
+
+        // A projection that reorders the columns is kept.
+        let project = Query::Project(vec![(b.into(), None), (a.into(), None)], None, false);
+        assert_eq!(plan(vec![project.clone()]).query, [project]);
+    }
 
-    // TODO(kim): Should better do property testing here, but writing generators
-    // on recursive types (ie. `Query` and friends) is tricky.
+    #[test]
+    fn structural_hash_ignores_source_ids() {
+        let table = mem_table(TableId(0), "t", &[(0, AlgebraicType::U64, false)]);
+        let field = FieldName::new(TableId(0), 0.into());
+        let with_id = |id: usize| {
+            let mut source = table.clone();
+            if let SourceExpr::InMemory { source_id, .. } = &mut source {
+                *source_id = SourceId(id);
+            }
+            source
+        };
+        // Joins the sources `lhs` and `rhs`, both of the schema of `table`.
+        let join = |lhs: usize, rhs: usize| {
+            QueryExpr::new(with_id(lhs))
+                .with_join_inner(QueryExpr::new(with_id(rhs)), field, field, true)
+                .with_select_cmp(OpCmp::Eq, field, AlgebraicValue::U64(1))
+        };
 
-    fn tables() -> [SourceExpr; 2] {
-        [
-            SourceExpr::InMemory {
-                source_id: SourceId(0),
-                header: Arc::new(Header {
-                    table_id: 42.into(),
-                    table_name: "foo".into(),
-                    fields: vec![],
-                    constraints: Default::default(),
-                }),
-                row_count: RowCount::unknown(),
-                table_type: StTableType::User,
-                table_access: StAccess::Private,
-            },
-            SourceExpr::DbTable(DbTable {
-                head: Arc::new(Header {
-                    table_id: 42.into(),
-                    table_name: "foo".into(),
-                    fields: vec![],
-                    constraints: vec![(ColId(42).into(), Constraints::indexed())],
-                }),
-                table_id: 42.into(),
-                table_type: StTableType::User,
-                table_access: StAccess::Private,
-            }),
-        ]
-    }
+        assert_ne!(join(0, 1), join(3, 2));
+        assert_eq!(join(0, 1).structural_hash(), join(3, 2).structural_hash());
+        assert_eq!(join(1, 1).structural_hash(), join(4, 4).structural_hash());
 
-    fn queries() -> impl IntoIterator<Item = Query> {
-        let [mem_table, db_table] = tables();
-        // Skip `Query::Select` and `QueryProject` -- they don't have table
-        // information
-        [
-            Query::IndexScan(IndexScan {
-                table: db_table.get_db_table().unwrap().clone(),
-                columns: ColList::new(42.into()),
-                bounds: (Bound::Included(22.into()), Bound::Unbounded),
-            }),
-            Query::IndexJoin(IndexJoin {
-                probe_side: mem_table.clone().into(),
-                probe_field: FieldName::new(mem_table.head().table_id, 0.into()),
-                index_side: SourceExpr::DbTable(DbTable {
-                    head: Arc::new(Header {
-                        table_id: db_table.head().table_id,
-                        table_name: db_table.table_name().into(),
-                        fields: vec![],
-                        constraints: Default::default(),
-                    }),
-                    table_id: db_table.head().table_id,
-                    table_type: StTableType::User,
-                    table_access: StAccess::Public,
-                }),
-                index_select: None,
-                index_col: 22.into(),
-                return_index_rows: true,
-            }),
-            Query::JoinInner(JoinExpr {
-                col_rhs: FieldName::new(mem_table.head().table_id, 1.into()),
-                rhs: mem_table.into(),
-                col_lhs: FieldName::new(db_table.head().table_id, 1.into()),
-                semi: false,
-            }),
-        ]
+        // A self-join reads one source, rather than two with the same schema.
+        assert_ne!(join(0, 0).structural_hash(), join(0, 1).structural_hash());
     }
 
-    fn query_exprs() -> impl IntoIterator<Item = QueryExpr> {
-        tables().map(|table| {
-            let mut expr = QueryExpr::from(table);
-            expr.query = queries().into_iter().collect();
-            expr
-        })
-    }
+    #[test]
+    fn compare_rel_values_like_lt() {
+        let table_id = TableId(0);
+        let [a, b] = [0, 1].map(|col| FieldName::new(table_id, ColId(col)));
+        let header = Header::new(
+            table_id,
+            "t".into(),
+            vec![Column::new(a, AlgebraicType::U8), Column::new(b, AlgebraicType::U8)],
+            vec![],
+        );
+        let row = |lhs: AlgebraicValue, rhs: AlgebraicValue| RelValue::Projection(product![lhs, rhs]);
 
-    fn assert_owner_private<T: AuthAccess>(auth: &T) {
-        assert!(auth.check_auth(ALICE, ALICE).is_ok());
+        let pairs = [
+            // Products are ordered lexicographically.
+            (product![1u8, 9u8].into(), product![2u8, 0u8].into()),
+            (product![1u8, 2u8].into(), product![1u8, 3u8].into()),
+            // Sums are ordered by their tag first.
+            (
+                AlgebraicValue::sum(0, AlgebraicValue::U8(9)),
+                AlgebraicValue::sum(1, AlgebraicValue::U8(0)),
+            ),
+            (
+                AlgebraicValue::sum(1, AlgebraicValue::U8(0)),
+                AlgebraicValue::sum(1, AlgebraicValue::U8(1)),
+            ),
+            // Numbers of different types are ordered by their type, not their value.
+            (AlgebraicValue::U8(200), AlgebraicValue::I32(-1)),
+            (AlgebraicValue::I64(-5), AlgebraicValue::U64(0)),
+            (AlgebraicValue::U32(7), AlgebraicValue::U32(7)),
+        ];
+        for (lhs, rhs) in pairs {
+            let (x, y) = (row(lhs.clone(), 0u8.into()), row(rhs.clone(), 0u8.into()));
+            let ord = compare_rel_values(&x, &y, &ColList::new(ColId(0)), &header).unwrap();
+            assert_eq!(
+                ord,
+                compare_rel_values(&y, &x, &ColList::new(ColId(0)), &header)
+                    .unwrap()
+                    .reverse()
+            );
+
+            // `a < rhs`, evaluated for `x`, agrees.
+            let lt = ColumnOp::new(
+                OpQuery::Cmp(OpCmp::Lt),
+                ColumnOp::Field(FieldExpr::Name(a)),
+                ColumnOp::Field(FieldExpr::Value(rhs.clone())),
+            );
+            assert_eq!(lt.compare(&x, &header).unwrap(), ord.is_lt(), "{lhs:?} < {rhs:?}");
+        }
+
+        // Later columns break ties of earlier ones.
+        let (x, y) = (row(1u8.into(), 5u8.into()), row(1u8.into(), 7u8.into()));
+        assert_eq!(
+            compare_rel_values(&x, &y, &ColList::new(ColId(0)), &header).unwrap(),
+            Ordering::Equal
+        );
+        assert_eq!(
+            compare_rel_values(&x, &y, &col_list![0, 1], &header).unwrap(),
+            Ordering::Less
+        );
+
+        // A column the header lacks is an error, even when an earlier column decides the order.
+        let (x, y) = (row(1u8.into(), 5u8.into()), row(2u8.into(), 7u8.into()));
         assert!(matches!(
-            auth.check_auth(ALICE, BOB),
-            Err(AuthError::TablePrivate { .. })
+            compare_rel_values(&x, &y, &col_list![0, 2], &header),
+            Err(ErrorVm::Rel(RelationError::FieldNotFound(_, field))) if field == FieldName::new(table_id, ColId(2))
+        ));
+        // So is a row lacking a column of the header.
+        let short = RelValue::Projection(product![1u8]);
+        assert!(matches!(
+            compare_rel_values(&x, &short, &col_list![1], &header),
+            Err(ErrorVm::Rel(RelationError::FieldNotFoundAtPos(1, field))) if field == b
         ));
     }
 
-    fn assert_owner_required<T: AuthAccess>(auth: T) {
-        assert!(auth.check_auth(ALICE, ALICE).is_ok());
-        assert!(matches!(auth.check_auth(ALICE, BOB), Err(AuthError::OwnerRequired)));
+    #[test]
+    fn canonicalize_column_op() {
+        let a = FieldName::new(TableId(0), 0.into());
+        let b = FieldName::new(TableId(0), 1.into());
+        let hash = |op: &ColumnOp| {
+            let mut hasher = DefaultHasher::new();
+            op.hash(&mut hasher);
+            hasher.finish()
+        };
+
+        // a = 1 AND b = 2
+        let expected = ColumnOp::and(ColumnOp::cmp(a, OpCmp::Eq, 1u8), ColumnOp::cmp(b, OpCmp::Eq, 2u8)).canonicalize();
+        // b = 2 AND a = 1
+        let swapped = ColumnOp::and(ColumnOp::cmp(b, OpCmp::Eq, 2u8), ColumnOp::cmp(a, OpCmp::Eq, 1u8)).canonicalize();
+        assert_eq!(swapped, expected);
+        assert_eq!(hash(&swapped), hash(&expected));
+
+        // (a = 1 AND a = 1) AND (b = 2 AND a = 1)
+        let redundant = ColumnOp::and(
+            ColumnOp::and(ColumnOp::cmp(a, OpCmp::Eq, 1u8), ColumnOp::cmp(a, OpCmp::Eq, 1u8)),
+            ColumnOp::and(ColumnOp::cmp(b, OpCmp::Eq, 2u8), ColumnOp::cmp(a, OpCmp::Eq, 1u8)),
+        )
+        .canonicalize();
+        assert_eq!(redundant, expected);
+
+        // a = 1 AND a = 1
+        let duplicate = ColumnOp::and(ColumnOp::cmp(a, OpCmp::Eq, 1u8), ColumnOp::cmp(a, OpCmp::Eq, 1u8));
+        assert_eq!(duplicate.canonicalize(), ColumnOp::cmp(a, OpCmp::Eq, 1u8));
+
+        // Nested disjunctions are flattened and sorted likewise.
+        let or = |lhs, rhs| ColumnOp::or(lhs, rhs);
+        let lt = |field, v: u8| ColumnOp::cmp(field, OpCmp::Lt, v);
+        let left = or(or(lt(a, 1), lt(b, 2)), lt(a, 1)).canonicalize();
+        let right = or(lt(b, 2), or(lt(a, 1), lt(b, 2))).canonicalize();
+        assert_eq!(left, right);
+        assert_eq!(hash(&left), hash(&right));
     }
 
-    fn mem_table(id: TableId, name: &str, fields: &[(u32, AlgebraicType, bool)]) -> SourceExpr {
-        let table_access = StAccess::Public;
-        let head = Header::new(
-            id,
-            name.into(),
-            fields
-                .iter()
-                .map(|(col, ty, _)| Column::new(FieldName::new(id, (*col).into()), ty.clone()))
-                .collect(),
-            fields
-                .iter()
-                .enumerate()
-                .filter(|(_, (_, _, indexed))| *indexed)
-                .map(|(i, _)| (ColId::from(i).into(), Constraints::indexed()))
-                .collect(),
+    #[test]
+    fn collapse_or_eqs() {
+        let table = TableSchema::from_def(
+            TableId(0),
+            TableDef::new(
+                "t".into(),
+                ProductType::from_iter([AlgebraicType::U8, AlgebraicType::U8]).into(),
+            )
+            .with_column_constraint(Constraints::indexed(), ColId(0)),
         );
-        SourceExpr::InMemory {
-            source_id: SourceId(0),
-            header: Arc::new(head),
-            row_count: RowCount::unknown(),
-            table_access,
-            table_type: StTableType::User,
+        let a = FieldName::new(table.table_id, 0.into());
+        let b = FieldName::new(table.table_id, 1.into());
+        let eq = |field, value: u8| ColumnOp::cmp(field, OpCmp::Eq, value);
+
+        // a = 9 OR a = 1 OR a = 5
+        let ors = ColumnOp::or(ColumnOp::or(eq(a, 9), eq(a, 1)), eq(a, 5));
+        assert_eq!(ors.clone().collapse_or_eqs(), ColumnOp::in_list(a, [1u8, 5, 9]));
+
+        // The IN-list is served by a seek per value.
+        let q = QueryExpr::new(&table).with_select(ors).optimize(&|_, _| 0);
+        let [Query::IndexMultiScan(scan)] = &*q.query else {
+            panic!("expected an index multi-scan, got {:?}", q.query);
+        };
+        assert_eq!(scan.columns, ColId(0).into());
+        assert_eq!(
+            scan.ranges,
+            [1, 5, 9].map(|v| (
+                Bound::Included(AlgebraicValue::U8(v)),
+                Bound::Included(AlgebraicValue::U8(v))
+            ))
+        );
+
+        // Only the equalities on the same field are collapsed.
+        let mixed = ColumnOp::or(ColumnOp::or(eq(a, 3), eq(b, 2)), ColumnOp::or(eq(a, 1), eq(b, 4)));
+        assert_eq!(
+            mixed.collapse_or_eqs(),
+            ColumnOp::or(ColumnOp::in_list(a, [1u8, 3]), ColumnOp::in_list(b, [2u8, 4]))
+        );
+        let mixed = ColumnOp::or(
+            ColumnOp::or(eq(a, 3), ColumnOp::cmp(a, OpCmp::Gt, 7u8)),
+            ColumnOp::or(eq(b, 2), eq(a, 1)),
+        );
+        assert_eq!(
+            mixed.collapse_or_eqs(),
+            ColumnOp::or(
+                ColumnOp::or(ColumnOp::in_list(a, [1u8, 3]), ColumnOp::cmp(a, OpCmp::Gt, 7u8)),
+                eq(b, 2)
+            )
+        );
+    }
+
+    #[test]
+    fn optimizer_fixture_plans() {
+        // The benchmark inputs are only meaningful as long as they keep optimizing to these plans.
+        for (name, query) in optimizer_fixtures() {
+            let optimized = query.optimize(&|_, _| 1_000).query;
+            let expected = match name {
+                "single_table_filter" => matches!(&*optimized, [Query::IndexScan(_), Query::Select(_)]),
+                "index_join" => matches!(&*optimized, [Query::IndexJoin(_)]),
+                "three_table_chain" => matches!(&*optimized, [Query::JoinInner(_), Query::JoinInner(_)]),
+                "dnf_predicate" => matches!(&*optimized, [Query::Select(_)]),
+                name => panic!("no expected plan for fixture `{name}`"),
+            };
+            assert!(expected, "unexpected plan for fixture `{name}`: {optimized:?}");
         }
     }
 
     #[test]
-    fn test_index_to_inner_join() {
-        let index_side = mem_table(
-            0.into(),
-            "index",
-            &[(0, AlgebraicType::U8, false), (1, AlgebraicType::U8, true)],
-        );
-        let probe_side = mem_table(
-            1.into(),
-            "probe",
-            &[(0, AlgebraicType::U8, false), (1, AlgebraicType::U8, true)],
-        );
+    fn not_eq_index_ranges() {
+        let table = |indexed: bool| {
+            let def = TableDef::new(
+                "t".into(),
+                ProductType::from_iter([AlgebraicType::Bool, AlgebraicType::U8]).into(),
+            );
+            let def = if indexed {
+                def.with_column_constraint(Constraints::indexed(), ColId(0))
+                    .with_column_constraint(Constraints::indexed(), ColId(1))
+            } else {
+                def
+            };
+            TableSchema::from_def(TableId(0), def)
+        };
+        let flag = FieldName::new(TableId(0), 0.into());
+        let num = FieldName::new(TableId(0), 1.into());
+        let optimize = |table: &TableSchema, op: &ColumnOp| {
+            QueryExpr::new(table).with_select(op.clone()).optimize(&|_, _| 0).query
+        };
 
-        let index_field = index_side.head().fields[1].field;
-        let probe_field = probe_side.head().fields[1].field;
-        let select_field = FieldName::new(index_side.head().table_id, 0.into());
-        let index_select = ColumnOp::cmp(select_field, OpCmp::Eq, 0u8);
-        let join = IndexJoin {
-            probe_side: probe_side.clone().into(),
-            probe_field,
-            index_side: index_side.clone(),
-            index_select: Some(index_select.clone()),
-            index_col: 1.into(),
-            return_index_rows: false,
+        // WHERE flag != true, on an indexed boolean column, is served by the ranges on either side of `true`.
+        let not_true = ColumnOp::cmp(flag, OpCmp::NotEq, AlgebraicValue::Bool(true));
+        let query = optimize(&table(true), &not_true);
+        let [Query::IndexMultiScan(scan)] = &*query else {
+            panic!("expected an index multi-scan, got {query:?}");
         };
+        assert_eq!(scan.columns, ColId(0).into());
+        assert_eq!(
+            scan.ranges,
+            [
+                (Bound::Unbounded, Bound::Excluded(AlgebraicValue::Bool(true))),
+                (Bound::Excluded(AlgebraicValue::Bool(true)), Bound::Unbounded),
+            ]
+        );
 
-        let expr = join.to_inner_join();
+        // Without an index, it is served by a scan.
+        assert_eq!(optimize(&table(false), &not_true), [Query::Select(not_true)]);
 
-        assert_eq!(expr.source, probe_side);
-        assert_eq!(expr.query.len(), 1);
+        // So is an inequality on an indexed column of unknown cardinality.
+        let not_three = ColumnOp::cmp(num, OpCmp::NotEq, 3u8);
+        assert_eq!(optimize(&table(true), &not_three), [Query::Select(not_three)]);
+    }
 
-        let Query::JoinInner(ref join) = expr.query[0] else {
-            panic!("expected an inner join, but got {:#?}", expr.query[0]);
+    #[test]
+    fn best_index_not_eq_cardinality() {
+        let (head1, fields, vals) = setup_best_index();
+        let [_, col_b, ..] = fields;
+        let [_, val_b, ..] = vals;
+
+        let arena = Arena::new();
+        let select_best_index = |distinct_count| {
+            let stats = ColumnStatsMap::from([(
+                col_b.col,
+                ColumnStats {
+                    distinct_count,
+                    ..Default::default()
+                },
+            )]);
+            let op = make_field_value(&arena, (OpCmp::NotEq, col_b, &val_b)).parent;
+            select_best_index(&mut <_>::default(), &head1, &stats, &[op])
         };
 
-        assert_eq!(join.col_lhs, probe_field);
-        assert_eq!(join.col_rhs, index_field);
+        // A hint of few distinct values serves `b != 2` by the index on `b`.
+        let columns = col_b.col.into();
         assert_eq!(
-            join.rhs,
-            QueryExpr {
-                source: index_side,
-                query: vec![index_select.into()]
-            }
+            select_best_index(Some(3)),
+            [IndexColumnOp::Index(IndexArgument::NotEq {
+                columns: &columns,
+                value: val_b.clone(),
+            })]
+            .into()
         );
-        assert!(join.semi);
+
+        // Many or an unknown number of distinct values keep the scan.
+        let not_eq_scan: IndexColumnOpSink = [scan(&arena, OpCmp::NotEq, col_b, &val_b)].into();
+        assert_eq!(select_best_index(Some(1000)), not_eq_scan);
+        assert_eq!(select_best_index(None), not_eq_scan);
     }
 
-    fn setup_best_index() -> (Header, [FieldName; 5], [AlgebraicValue; 5]) {
-        let table_id = 0.into();
+    #[test]
+    fn estimated_rows() {
+        let table = |table_id: u32, name: &str, unique: bool| {
+            let def = TableDef::new(
+                name.into(),
+                ProductType::from_iter([AlgebraicType::U64, AlgebraicType::U64]).into(),
+            );
+            let def = if unique {
+                def.with_column_constraint(Constraints::unique(), ColId(0))
+            } else {
+                def
+            };
+            TableSchema::from_def(TableId(table_id), def)
+        };
+        let lhs = table(0, "lhs", true);
+        let rhs = table(1, "rhs", false);
+        let [lhs_a, lhs_b] = [0, 1].map(|col| FieldName::new(lhs.table_id, col.into()));
+        let rhs_a = FieldName::new(rhs.table_id, 0.into());
+        let row_count = |table_id: TableId, _: &str| match table_id.0 {
+            0 => 1000,
+            1 => 10,
+            _ => -1,
+        };
+        let plan = |table: &TableSchema, query: Vec<Query>| {
+            let mut q = QueryExpr::new(table);
+            q.query = query;
+            q.estimated_rows(&row_count)
+        };
+        let estimate = |max| RowCount { min: 0, max: Some(max) };
+
+        // A seek on a unique index yields at most one row.
+        let seek = Query::IndexScan(IndexScan {
+            table: (&lhs).into(),
+            columns: ColId(0).into(),
+            bounds: (Bound::Included(5u64.into()), Bound::Included(5u64.into())),
+            covering: false,
+        });
+        let range = Query::Select(ColumnOp::cmp(lhs_b, OpCmp::Gt, 3u64));
+        assert_eq!(
+            plan(&lhs, vec![seek, range.clone()]),
+            [RowCount::exact(1000), estimate(1), estimate(1)]
+        );
 
-        let vals = [1, 2, 3, 4, 5].map(AlgebraicValue::U64);
-        let col_ids = [0, 1, 2, 3, 4].map(ColId);
-        let [a, b, c, d, _] = col_ids;
-        let fields = col_ids.map(|c| FieldName::new(table_id, c));
-        let cols = fields.map(|f| Column::new(f, AlgebraicType::I8));
+        // Filters scale the estimate by their selectivity.
+        let eq = Query::Select(ColumnOp::cmp(lhs_b, OpCmp::Eq, 3u64));
+        assert_eq!(plan(&lhs, vec![eq]), [RowCount::exact(1000), estimate(100)]);
+        assert_eq!(plan(&lhs, vec![range]), [RowCount::exact(1000), estimate(334)]);
+
+        // Each `rhs` row matches at most one `lhs` row, as `lhs.a` is unique.
+        let join = Query::JoinInner(JoinExpr::new(QueryExpr::new(&rhs), lhs_a, rhs_a, JoinKind::Inner));
+        assert_eq!(plan(&lhs, vec![join]), [RowCount::exact(1000), estimate(10)]);
+
+        // The estimate is unknown for a table of unknown size.
+        let unknown = table(2, "unknown", false);
+        let join = Query::JoinInner(JoinExpr::new(
+            QueryExpr::new(&unknown),
+            lhs_a,
+            FieldName::new(unknown.table_id, 0.into()),
+            JoinKind::Inner,
+        ));
+        let q = QueryExpr {
+            source: (&lhs).into(),
+            query: vec![join],
+        };
+        assert_eq!(q.estimated_output_rows(&row_count), RowCount::unknown());
+    }
 
-        let head1 = Header::new(
-            table_id,
-            "t1".into(),
-            cols.to_vec(),
-            vec![
-                //Index a
-                (a.into(), Constraints::primary_key()),
-                //Index b
-                (b.into(), Constraints::indexed()),
-                //Index b + c
-                (col_list![b, c], Constraints::unique()),
-                //Index a + b + c + d
-                (col_list![a, b, c, d], Constraints::indexed()),
+    #[test]
+    fn explain() {
+        let lhs = indexed_u64_table(0, "lhs");
+        let rhs = indexed_u64_table(1, "rhs");
+        let [lhs_a, lhs_b] = [0, 1].map(|col| FieldName::new(lhs.table_id, col.into()));
+        let [rhs_a, rhs_b] = [0, 1].map(|col| FieldName::new(rhs.table_id, col.into()));
+
+        let rhs_plan = QueryExpr {
+            source: (&rhs).into(),
+            query: vec![Query::Select(ColumnOp::cmp(rhs_b, OpCmp::Gt, 2u64))],
+        };
+        let plan = QueryExpr {
+            source: (&lhs).into(),
+            query: vec![
+                Query::IndexScan(IndexScan {
+                    table: (&lhs).into(),
+                    columns: ColId(0).into(),
+                    bounds: (Bound::Included(1u64.into()), Bound::Excluded(10u64.into())),
+                    covering: false,
+                }),
+                Query::Select(ColumnOp::cmp(lhs_b, OpCmp::Eq, 3u64)),
+                Query::JoinInner(JoinExpr::new(rhs_plan.clone(), lhs_a, rhs_a, JoinKind::Semi)),
             ],
+        };
+        assert_eq!(
+            plan.explain(),
+            "\
+Source: lhs (table#0)
+IndexScan: lhs (table#0), cols [0], bounds [1, 10)
+Select: table#0.col#1 == 3
+JoinInner: ON table#0.col#0 = table#1.col#0, kind: semi
+  Source: rhs (table#1)
+  Select: table#1.col#1 > 2
+"
         );
 
-        (head1, fields, vals)
+        let index_join = QueryExpr::from(IndexJoin {
+            probe_side: rhs_plan,
+            probe_fields: smallvec![rhs_a],
+            index_side: (&lhs).into(),
+            index_select: None,
+            index_cols: ColId(0).into(),
+            return_index_rows: true,
+            projection: None,
+        });
+        assert_eq!(
+            index_join.explain(),
+            "\
+Source: lhs (table#0)
+IndexJoin: index side lhs (table#0), index cols [0], probe fields [table#1.col#0], return_index_rows: true, index_select: none
+  Source: rhs (table#1)
+  Select: table#1.col#1 > 2
+"
+        );
     }
 
-    fn make_field_value<'a>(
-        arena: &'a Arena<ColumnOp>,
-        (cmp, field, value): (OpCmp, FieldName, &'a AlgebraicValue),
-    ) -> FieldValue<'a> {
-        let from_expr = |expr| Box::new(ColumnOp::Field(expr));
-        let op = ColumnOp::Cmp {
-            op: OpQuery::Cmp(cmp),
-            lhs: from_expr(FieldExpr::Name(field)),
-            rhs: from_expr(FieldExpr::Value(value.clone())),
+    #[test]
+    fn in_tuples_index_scan() {
+        let table = |composite: bool| {
+            let def = TableDef::new(
+                "t".into(),
+                ProductType::from_iter([AlgebraicType::U8, AlgebraicType::U8, AlgebraicType::U8]).into(),
+            );
+            let def = if composite {
+                def.with_column_constraint(Constraints::indexed(), col_list![0, 1])
+            } else {
+                def.with_column_constraint(Constraints::indexed(), ColId(0))
+            };
+            TableSchema::from_def(TableId(0), def)
         };
-        let parent = arena.alloc(op);
-        FieldValue::new(parent, cmp, field, value)
+        let [a, b, c] = [0, 1, 2].map(|col| FieldName::new(TableId(0), col.into()));
+        let optimize = |table: &TableSchema, op: &ColumnOp| {
+            QueryExpr::new(table).with_select(op.clone()).optimize(&|_, _| 0).query
+        };
+        let point = |value: AlgebraicValue| (Bound::Included(value.clone()), Bound::Included(value));
+
+        // WHERE (a, b) IN ((1, 2), (3, 4)), on an index on `(a, b)`, is served by one seek per tuple.
+        let in_tuples = ColumnOp::in_tuples([a, b], [product![1u8, 2u8], product![3u8, 4u8]]);
+        let query = optimize(&table(true), &in_tuples);
+        let [Query::IndexMultiScan(scan)] = &*query else {
+            panic!("expected an index multi-scan, got {query:?}");
+        };
+        assert_eq!(scan.columns, col_list![0, 1]);
+        assert_eq!(
+            scan.ranges,
+            [point(product![1u8, 2u8].into()), point(product![3u8, 4u8].into())]
+        );
+
+        // The fields may be listed in any order; the tuples are reordered to match the index.
+        let in_tuples_rev = ColumnOp::in_tuples([b, a], [product![2u8, 1u8], product![4u8, 3u8]]);
+        assert_eq!(optimize(&table(true), &in_tuples_rev), query);
+
+        // Without a composite index, the list is expanded to a disjunction of equalities.
+        let expanded = ColumnOp::or(
+            ColumnOp::and(ColumnOp::cmp(a, OpCmp::Eq, 1u8), ColumnOp::cmp(b, OpCmp::Eq, 2u8)),
+            ColumnOp::and(ColumnOp::cmp(a, OpCmp::Eq, 3u8), ColumnOp::cmp(b, OpCmp::Eq, 4u8)),
+        );
+        assert_eq!(optimize(&table(false), &in_tuples), [Query::Select(expanded)]);
+
+        // So is a list over columns that do not match the composite index exactly.
+        let in_tuples_ac = ColumnOp::in_tuples([a, c], [product![1u8, 2u8]]);
+        let expanded = ColumnOp::and(ColumnOp::cmp(a, OpCmp::Eq, 1u8), ColumnOp::cmp(c, OpCmp::Eq, 2u8));
+        assert_eq!(optimize(&table(true), &in_tuples_ac), [Query::Select(expanded)]);
     }
 
-    fn scan_eq<'a>(arena: &'a Arena<ColumnOp>, field: FieldName, val: &'a AlgebraicValue) -> IndexColumnOp<'a> {
-        scan(arena, OpCmp::Eq, field, val)
+    #[test]
+    fn report_seq_scans() {
+        let table = TableSchema::from_def(
+            TableId(0),
+            TableDef::new(
+                "t".into(),
+                ProductType::from_iter([AlgebraicType::U8, AlgebraicType::U8]).into(),
+            )
+            .with_column_constraint(Constraints::indexed(), ColId(0)),
+        );
+        let [a, b] = [0, 1].map(|col| FieldName::new(TableId(0), col.into()));
+        let seq_scans = |query: QueryExpr| {
+            let mut scanned = Vec::new();
+            let opts = OptimizeOptions::default();
+            query.optimize_reporting_seq_scans(&|_, _| 0, &opts, &mut |table| scanned.push(table.table_id));
+            scanned
+        };
+
+        // WHERE b = 1 can't be served by an index, so `t` is scanned.
+        let unindexed = QueryExpr::new(&table).with_select(ColumnOp::cmp(b, OpCmp::Eq, 1u8));
+        assert_eq!(seq_scans(unindexed), [TableId(0)]);
+
+        // WHERE a = 1 is served by the index on `a`, even with a residual filter on `b`.
+        let indexed = QueryExpr::new(&table).with_select(ColumnOp::cmp(a, OpCmp::Eq, 1u8));
+        assert!(seq_scans(indexed.clone()).is_empty());
+        let residual = indexed.with_select(ColumnOp::cmp(b, OpCmp::Eq, 1u8));
+        assert!(seq_scans(residual).is_empty());
+
+        // A `MemTable` is never indexed, so scanning it is not reported.
+        let mem = mem_table_one_u64(TableId(1));
+        let field = mem.head.fields[0].field;
+        let mut sources = SourceSet::<_, 1>::empty();
+        let query = QueryExpr::new(sources.add_mem_table(mem)).with_select(ColumnOp::cmp(field, OpCmp::Eq, 1u64));
+        assert!(seq_scans(query).is_empty());
+
+        // Of a join, only the table the filter reads is reported.
+        let other = TableSchema::from_def(
+            TableId(2),
+            TableDef::new(
+                "u".into(),
+                ProductType::from_iter([AlgebraicType::U8, AlgebraicType::U8]).into(),
+            ),
+        );
+        let other_a = FieldName::new(TableId(2), 0.into());
+        let join = QueryExpr::new(&table)
+            .with_join_inner(SourceExpr::from(&other), a, other_a, false)
+            .with_select(ColumnOp::cmp(b, OpCmp::Eq, 1u8));
+        assert_eq!(seq_scans(join), [TableId(0)]);
     }
 
-    fn scan<'a>(
-        arena: &'a Arena<ColumnOp>,
-        cmp: OpCmp,
-        field: FieldName,
-        val: &'a AlgebraicValue,
-    ) -> IndexColumnOp<'a> {
-        IndexColumnOp::Scan(make_field_value(arena, (cmp, field, val)).parent)
+    #[test]
+    fn merge_same_direction_bounds() {
+        let table = TableSchema::from_def(
+            TableId(0),
+            TableDef::new("t".into(), ProductType::from_iter([AlgebraicType::U8]).into())
+                .with_column_constraint(Constraints::indexed(), ColId(0)),
+        );
+        let query = QueryExpr::new(&table);
+        let db_table = query.source.get_db_table().unwrap().clone();
+        let columns = ColList::from(ColId(0));
+        let bounds = |query: QueryExpr| {
+            let [Query::IndexScan(scan)] = &*query.query else {
+                panic!("expected a single index scan, got {:?}", query.query);
+            };
+            scan.bounds.clone()
+        };
+        let lower = |query: QueryExpr, value: u8, inclusive| {
+            query.with_index_lower_bound(db_table.clone(), columns.clone(), value.into(), inclusive)
+        };
+        let upper = |query: QueryExpr, value: u8, inclusive| {
+            query.with_index_upper_bound(db_table.clone(), columns.clone(), value.into(), inclusive)
+        };
+        let (inc, exc) = (Bound::Included, Bound::Excluded);
+        let val = AlgebraicValue::U8;
+
+        // a > 1 AND a > 3 => a > 3
+        let q = lower(lower(query.clone(), 1, false), 3, false);
+        assert_eq!(bounds(q), (exc(val(3)), Bound::Unbounded));
+        // a >= 5 AND a > 3 => a >= 5
+        let q = lower(lower(query.clone(), 5, true), 3, false);
+        assert_eq!(bounds(q), (inc(val(5)), Bound::Unbounded));
+        // a >= 3 AND a > 3 => a > 3, regardless of the order
+        let q = lower(lower(query.clone(), 3, true), 3, false);
+        assert_eq!(bounds(q), (exc(val(3)), Bound::Unbounded));
+        let q = lower(lower(query.clone(), 3, false), 3, true);
+        assert_eq!(bounds(q), (exc(val(3)), Bound::Unbounded));
+
+        // a < 3 AND a < 1 => a < 1
+        let q = upper(upper(query.clone(), 3, false), 1, false);
+        assert_eq!(bounds(q), (Bound::Unbounded, exc(val(1))));
+        // a <= 1 AND a <= 3 => a <= 1
+        let q = upper(upper(query.clone(), 1, true), 3, true);
+        assert_eq!(bounds(q), (Bound::Unbounded, inc(val(1))));
+        // a <= 1 AND a < 1 => a < 1, regardless of the order
+        let q = upper(upper(query.clone(), 1, true), 1, false);
+        assert_eq!(bounds(q), (Bound::Unbounded, exc(val(1))));
+        let q = upper(upper(query.clone(), 1, false), 1, true);
+        assert_eq!(bounds(q), (Bound::Unbounded, exc(val(1))));
+
+        // a > 1 AND a < 10 AND a >= 4 AND a <= 12 => 4 <= a < 10
+        let q = upper(lower(upper(lower(query, 1, false), 10, false), 4, true), 12, true);
+        assert_eq!(bounds(q), (inc(val(4)), exc(val(10))));
     }
 
     #[test]
-    fn best_index() {
+    fn best_index_leading_column_range() {
         let (head1, fields, vals) = setup_best_index();
-        let [col_a, col_b, col_c, col_d, col_e] = fields;
-        let [val_a, val_b, val_c, val_d, val_e] = vals;
+        let [_, col_b, col_c, ..] = fields;
+        let [_, val_b, val_c, ..] = vals;
 
         let arena = Arena::new();
-        let select_best_index = |fields: &[_]| {
-            let fields = fields
-                .iter()
-                .copied()
-                .map(|(col, val): (FieldName, _)| make_field_value(&arena, (OpCmp::Eq, col, val)).parent)
-                .collect::<Vec<_>>();
-            select_best_index(&mut <_>::default(), &head1, &fields)
+        let select_best_index = |cmp, distinct_count| {
+            let stats = ColumnStatsMap::from([(
+                col_b.col,
+                ColumnStats {
+                    distinct_count,
+                    ..Default::default()
+                },
+            )]);
+            let ops = [
+                make_field_value(&arena, (cmp, col_b, &val_b)).parent,
+                make_field_value(&arena, (cmp, col_c, &val_c)).parent,
+            ];
+            select_best_index(&mut <_>::default(), &head1, &stats, &ops)
         };
-
         let col_list_arena = Arena::new();
-        let idx_eq = |cols, val| make_index_arg(OpCmp::Eq, col_list_arena.alloc(cols), val);
+        let composite = |cmp| {
+            let columns = col_list_arena.alloc(col_list![col_b.col, col_c.col]);
+            let composite: IndexColumnOpSink = [make_index_arg(
+                cmp,
+                columns,
+                product![val_b.clone(), val_c.clone()].into(),
+            )]
+            .into();
+            composite
+        };
 
-        // Check for simple scan
+        // With a hint on `b`, `b > 2 AND c > 3` is served by the index on `b` and a scan for `c > 3`,
+        // as the range on `(b, c)` would also read the rows where `b = 2`.
+        let columns = col_b.col.into();
         assert_eq!(
-            select_best_index(&[(col_d, &val_e)]),
-            [scan_eq(&arena, col_d, &val_e)].into(),
+            select_best_index(OpCmp::Gt, Some(100)),
+            [
+                make_index_arg(OpCmp::Gt, &columns, val_b.clone()),
+                scan(&arena, OpCmp::Gt, col_c, &val_c),
+            ]
+            .into()
         );
+        // Without one, the range on `(b, c)` is kept.
+        assert_eq!(select_best_index(OpCmp::Gt, None), composite(OpCmp::Gt));
 
-        assert_eq!(
-            select_best_index(&[(col_a, &val_a)]),
-            [idx_eq(col_a.col.into(), val_a.clone())].into(),
-        );
+        // `b >= 2 AND c >= 3` is kept as a range on `(b, c)`,
+        // which reads fewer of the rows where `b = 2` than `b >= 2` does.
+        assert_eq!(select_best_index(OpCmp::GtEq, Some(100)), composite(OpCmp::GtEq));
+        assert_eq!(select_best_index(OpCmp::GtEq, Some(2)), composite(OpCmp::GtEq));
+    }
 
-        assert_eq!(
-            select_best_index(&[(col_b, &val_b)]),
-            [idx_eq(col_b.col.into(), val_b.clone())].into(),
-        );
+    #[test]
+    fn source_set_remaining_and_consumed() {
+        let mut sources = SourceSet::<_, 3>::empty();
+        let ids = [0, 1, 2].map(|id| {
+            let expr = sources.add_mem_table(mem_table_one_u64(TableId(id)));
+            expr.source_id().unwrap()
+        });
+        assert!(sources.consumed_ids().next().is_none());
+
+        assert!(sources.take(ids[1]).is_some());
+
+        let remaining = sources.iter_remaining().map(|(id, _)| id).collect::<Vec<_>>();
+        assert_eq!(remaining, [ids[0], ids[2]]);
+        assert_eq!(sources.consumed_ids().collect::<Vec<_>>(), [ids[1]]);
+
+        // Taking does not remove the slot itself.
+        assert_eq!(sources.len(), 3);
+    }
 
-        // Check for permutation
+    #[test]
+    fn source_set_spills_past_inline_capacity() {
+        let mut sources = SourceSet::<_, 2>::empty();
+        let ids = [0, 1, 2].map(|id| {
+            let expr = sources.add_mem_table(mem_table_one_u64(TableId(id)));
+            expr.source_id().unwrap()
+        });
+        assert!(sources.is_spilled());
+        assert_eq!(sources.len(), 3);
+        assert_eq!(ids, [SourceId(0), SourceId(1), SourceId(2)]);
+
+        // Entries added before spilling are still reachable by their ids.
+        assert!(sources[ids[0]].is_some());
+        assert!(sources.take(ids[2]).is_some());
+        assert!(sources.take(ids[2]).is_none());
+        assert!(sources[ids[2]].is_none());
+
+        assert_eq!(sources.consumed_ids().collect::<Vec<_>>(), [ids[2]]);
+        let remaining = sources.iter_remaining().map(|(id, _)| id).collect::<Vec<_>>();
+        assert_eq!(remaining, [ids[0], ids[1]]);
+    }
+
+    #[test]
+    fn source_set_provider_after_spilling() {
+        let mut sources = SourceSet::<Vec<RelValue<'static>>, 1>::empty();
+        let ids = [0u64, 1].map(|n| sources.add(vec![RelValue::Projection(product![n])]));
+        assert!(sources.is_spilled());
+
+        let rows = sources.take_source(ids[1]).unwrap();
+        assert_eq!(rows, [RelValue::Projection(product![1u64])]);
+        assert!(sources.take_source(ids[1]).is_none());
+        assert!(sources.take_source(ids[0]).is_some());
+    }
+
+    #[test]
+    fn optimize_project_pushdown_keeps_join_column() {
+        let lhs = TableSchema::from_def(
+            TableId(0),
+            TableDef::new(
+                "lhs".into(),
+                ProductType::from_iter([AlgebraicType::I32, AlgebraicType::String]).into(),
+            ),
+        );
+        let rhs = TableSchema::from_def(
+            TableId(1),
+            TableDef::new(
+                "rhs".into(),
+                ProductType::from_iter([AlgebraicType::I32, AlgebraicType::I64, AlgebraicType::String]).into(),
+            ),
+        );
+        let [lhs_id, lhs_name] = [0, 1].map(|c| FieldName::new(lhs.table_id, c.into()));
+        let [rhs_id, rhs_val, rhs_name] = [0, 1, 2].map(|c| FieldName::new(rhs.table_id, c.into()));
+
+        // SELECT lhs.name, rhs.val FROM lhs JOIN rhs ON lhs.id = rhs.id
+        let join = |project: &[FieldName]| {
+            QueryExpr::new(&lhs)
+                .with_join_inner(SourceExpr::from(&rhs), lhs_id, rhs_id, false)
+                .with_project(&project.iter().copied().map(FieldExpr::Name).collect::<Vec<_>>(), None)
+        };
+        let optimized = join(&[lhs_name, rhs_val]).optimize(&|_, _| 0);
+        let [Query::JoinInner(JoinExpr { rhs: rhs_expr, .. }), Query::Project(..)] = &*optimized.query else {
+            panic!(
+                "Expected a join followed by a projection, but found {:?}",
+                optimized.query
+            );
+        };
+        // The join column `rhs.id` is kept, though it isn't projected.
         assert_eq!(
-            select_best_index(&[(col_b, &val_b), (col_c, &val_c)]),
-            [idx_eq(
-                col_list![col_b.col, col_c.col],
-                product![val_b.clone(), val_c.clone()].into()
+            rhs_expr.query,
+            [Query::Project(
+                vec![(FieldExpr::Name(rhs_id), None), (FieldExpr::Name(rhs_val), None)],
+                None,
+                false
             )]
-            .into(),
         );
 
-        assert_eq!(
-            select_best_index(&[(col_c, &val_c), (col_b, &val_b)]),
-            [idx_eq(
-                col_list![col_b.col, col_c.col],
-                product![val_b.clone(), val_c.clone()].into()
-            )]
-            .into(),
+        // When every column of the rhs is needed, there's nothing to push down.
+        let q = join(&[lhs_name, rhs_val, rhs_name]);
+        assert_eq!(q.clone().optimize(&|_, _| 0), q);
+
+        // Nor when the pushdown is disabled.
+        let q = join(&[lhs_name, rhs_val]);
+        let opts = OptimizeOptions {
+            enable_project_pushdown: false,
+            ..<_>::default()
+        };
+        assert_eq!(q.clone().optimize_with(&|_, _| 0, &opts), q);
+    }
+
+    #[test]
+    fn compare_primitive_column_agrees_with_reduce() {
+        let ty = ProductType::from([AlgebraicType::U64, AlgebraicType::I32, AlgebraicType::Bool]);
+        let schema = TableSchema::from_def(TableId(0), TableDef::from_product("t", ty));
+        let header = Header::from(&schema);
+        let mut table = Table::new(schema.into(), SquashedOffset::COMMITTED_STATE);
+        let mut blob_store = HashMapBlobStore::default();
+        let rows = [
+            product![0u64, -5i32, false],
+            product![7u64, 0i32, true],
+            product![u64::MAX, i32::MAX, false],
+        ];
+        let ptrs = rows
+            .iter()
+            .map(|row| table.insert(&mut blob_store, row).unwrap().1.pointer())
+            .collect::<Vec<_>>();
+
+        let field = |col: u32| FieldName::new(TableId(0), col.into());
+        // The last value of each column is of another type, which the fast path leaves to `reduce`.
+        let values: [(FieldName, Vec<AlgebraicValue>); 3] = [
+            (field(0), vec![0u64.into(), 7u64.into(), u64::MAX.into(), 7u32.into()]),
+            (field(1), vec![(-5i32).into(), 0i32.into(), 1i32.into(), (-5i64).into()]),
+            (field(2), vec![false.into(), true.into(), 1u8.into()]),
+        ];
+        let cmps = [
+            OpCmp::Eq,
+            OpCmp::NotEq,
+            OpCmp::Lt,
+            OpCmp::LtEq,
+            OpCmp::Gt,
+            OpCmp::GtEq,
+            OpCmp::IsDistinctFrom,
+            OpCmp::IsNotDistinctFrom,
+        ];
+
+        for ptr in ptrs {
+            let row_ref = table.get_row_ref(&blob_store, ptr).unwrap();
+            let row = RelValue::Row(row_ref);
+            // A projection isn't read from the bytes of a row, so it's always compared through `reduce`.
+            let projection = RelValue::Projection(row_ref.to_product_value());
+            for (field, values) in &values {
+                for value in values {
+                    for cmp in cmps {
+                        let field_first = ColumnOp::cmp(*field, cmp, value.clone());
+                        let value_first = ColumnOp::new(
+                            OpQuery::Cmp(cmp),
+                            ColumnOp::Field(FieldExpr::Value(value.clone())),
+                            ColumnOp::Field(FieldExpr::Name(*field)),
+                        );
+                        for op in [field_first, value_first] {
+                            assert_eq!(
+                                op.compare(&row, &header).unwrap(),
+                                op.compare(&projection, &header).unwrap(),
+                                "{op}"
+                            );
+                        }
+                    }
+                }
+            }
+
+            // A case-insensitive comparison of non-strings still fails.
+            let op = ColumnOp::cmp(field(0), OpCmp::EqIgnoreCase, 0u64);
+            assert!(op.compare(&row, &header).is_err());
+        }
+    }
+
+    #[test]
+    fn fold_constants() {
+        let table = TableSchema::from_def(
+            TableId(0),
+            TableDef::new("t".into(), ProductType::from_iter([AlgebraicType::U8]).into())
+                .with_column_constraint(Constraints::indexed(), ColId(0)),
         );
+        let a = FieldName::new(table.table_id, 0.into());
+        let lit = |cmp, lhs: u8, rhs: u8| {
+            ColumnOp::new(
+                OpQuery::Cmp(cmp),
+                AlgebraicValue::U8(lhs).into(),
+                AlgebraicValue::U8(rhs).into(),
+            )
+        };
+        let a_eq_3 = ColumnOp::cmp(a, OpCmp::Eq, 3u8);
+
+        // 1 < 2 AND 2 <= 2
+        let op = ColumnOp::and(lit(OpCmp::Lt, 1, 2), lit(OpCmp::LtEq, 2, 2));
+        assert_eq!(op.clone().fold_constants(), ColumnOp::constant(true));
+        // A filter that always holds is dropped.
+        let q = QueryExpr::new(&table).with_select(op).optimize(&|_, _| 0);
+        assert!(q.query.is_empty(), "expected no filter, got {:?}", q.query);
+
+        // a = 3 AND 1 = 2
+        let op = ColumnOp::and(a_eq_3.clone(), lit(OpCmp::Eq, 1, 2));
+        assert_eq!(op.clone().fold_constants(), ColumnOp::constant(false));
+        // A filter that never holds is kept as is, rather than turned into an index scan.
+        let q = QueryExpr::new(&table).with_select(op).optimize(&|_, _| 0);
+        assert_eq!(q.query, [Query::Select(ColumnOp::constant(false))]);
+
+        // Only the literal comparisons are folded.
+        let op = ColumnOp::or(lit(OpCmp::Gt, 1, 2), a_eq_3.clone());
+        assert_eq!(op.fold_constants(), a_eq_3);
+        let op = ColumnOp::and(lit(OpCmp::NotEq, 1, 2), a_eq_3.clone());
+        assert_eq!(op.fold_constants(), a_eq_3);
+        let op = ColumnOp::or(a_eq_3.clone(), lit(OpCmp::GtEq, 2, 1));
+        assert_eq!(op.fold_constants(), ColumnOp::constant(true));
+        let op = ColumnOp::and(a_eq_3.clone(), ColumnOp::cmp(a, OpCmp::Lt, 5u8));
+        assert_eq!(op.clone().fold_constants(), op);
+    }
 
-        // Check for permutation
-        assert_eq!(
-            select_best_index(&[(col_a, &val_a), (col_b, &val_b), (col_c, &val_c), (col_d, &val_d)]),
-            [idx_eq(
-                col_list![col_a.col, col_b.col, col_c.col, col_d.col],
-                product![val_a.clone(), val_b.clone(), val_c.clone(), val_d.clone()].into(),
-            )]
-            .into(),
+    #[test]
+    fn with_checked_select() {
+        let table = TableSchema::from_def(
+            TableId(0),
+            TableDef::new(
+                "t".into(),
+                ProductType::from_iter([AlgebraicType::U8, AlgebraicType::U8]).into(),
+            ),
         );
+        let header = Header::from(&table);
+        let a = FieldName::new(table.table_id, 0.into());
+        let missing = FieldName::new(table.table_id, 2.into());
+        let q = || QueryExpr::new(&table);
 
+        // A valid field builds the same query as `with_select`.
+        let op = ColumnOp::cmp(a, OpCmp::Eq, 1u8);
         assert_eq!(
-            select_best_index(&[(col_b, &val_b), (col_a, &val_a), (col_d, &val_d), (col_c, &val_c)]),
-            [idx_eq(
-                col_list![col_a.col, col_b.col, col_c.col, col_d.col],
-                product![val_a.clone(), val_b.clone(), val_c.clone(), val_d.clone()].into(),
-            )]
-            .into()
+            q().with_checked_select(op.clone(), &header).unwrap(),
+            q().with_select(op)
         );
 
-        // Check mix scan + index
+        let assert_missing = |op: ColumnOp| match q().with_checked_select(op, &header) {
+            Err(ErrorVm::Rel(RelationError::FieldNotFound(_, field))) => assert_eq!(field, missing),
+            res => panic!("expected `{missing}` not to be found, got {res:?}"),
+        };
+        assert_missing(ColumnOp::cmp(missing, OpCmp::Eq, 1u8));
+        // An invalid field is caught even when only one side of an `AND` references it.
+        assert_missing(ColumnOp::and(
+            ColumnOp::cmp(a, OpCmp::Eq, 1u8),
+            ColumnOp::cmp(missing, OpCmp::Lt, 2u8),
+        ));
+    }
+
+    #[test]
+    fn optimize_composite_index_join() {
+        let table = |id, name: &str, indexed: ColList| {
+            TableSchema::from_def(
+                TableId(id),
+                TableDef::new(
+                    name.into(),
+                    ProductType::from_iter([AlgebraicType::U64, AlgebraicType::U64, AlgebraicType::U64]).into(),
+                )
+                .with_column_constraint(Constraints::indexed(), indexed),
+            )
+        };
+        let rhs = table(1, "rhs", ColList::new(2.into()));
+        let [rhs_a, rhs_b, rhs_c] = [0, 1, 2].map(|c| FieldName::new(rhs.table_id, c.into()));
+
+        // SELECT lhs.* FROM lhs JOIN rhs ON lhs.a = rhs.a WHERE lhs.b = rhs.b AND rhs.c = 3
+        let join = |lhs: &TableSchema| {
+            let [lhs_a, lhs_b, lhs_c] = [0, 1, 2].map(|c| FieldName::new(lhs.table_id, c.into()));
+            let cols = [lhs_a, lhs_b, lhs_c].map(FieldExpr::Name);
+            QueryExpr::new(lhs)
+                .with_join_inner(
+                    QueryExpr::new(&rhs).with_select(ColumnOp::cmp(rhs_c, OpCmp::Eq, 3u64)),
+                    lhs_a,
+                    rhs_a,
+                    false,
+                )
+                .with_select_cmp(OpCmp::Eq, lhs_b, rhs_b)
+                .with_project(&cols, Some(lhs.table_id))
+        };
+
+        // With an index on `(a, b)`, both columns are probed at once.
+        let lhs = table(0, "lhs", col_list![0, 1]);
+        let q = join(&lhs).optimize(&|_, _| 0);
+        let [Query::IndexJoin(index_join)] = &*q.query else {
+            panic!("expected an index join, got {:?}", q.query);
+        };
+        assert_eq!(index_join.index_cols, col_list![0, 1]);
+        assert_eq!(index_join.probe_fields[..], [rhs_a, rhs_b]);
+        assert!(index_join.return_index_rows);
+
+        // Converting it back yields a join on `a` filtered on `b`.
+        let inner = index_join.clone().to_inner_join();
+        let [Query::JoinInner(JoinExpr {
+            kind: JoinKind::Inner, ..
+        }), Query::Select(filter), Query::Project(_, Some(table_id), false)] = &*inner.query
+        else {
+            panic!("expected a join, filter and projection, got {:?}", inner.query);
+        };
+        assert_eq!(*table_id, lhs.table_id);
         assert_eq!(
-            select_best_index(&[(col_b, &val_b), (col_a, &val_a), (col_e, &val_e), (col_d, &val_d)]),
-            [
-                idx_eq(col_a.col.into(), val_a.clone()),
-                idx_eq(col_b.col.into(), val_b.clone()),
-                scan_eq(&arena, col_d, &val_d),
-                scan_eq(&arena, col_e, &val_e),
-            ]
-            .into()
+            filter.referenced_fields()[..],
+            [FieldName::new(lhs.table_id, 1.into()), rhs_b]
         );
 
-        assert_eq!(
-            select_best_index(&[(col_b, &val_b), (col_c, &val_c), (col_d, &val_d)]),
-            [
-                idx_eq(
-                    col_list![col_b.col, col_c.col],
-                    product![val_b.clone(), val_c.clone()].into(),
-                ),
-                scan_eq(&arena, col_d, &val_d),
-            ]
-            .into()
+        // With an index on just `a`, it's left as is.
+        let lhs = table(0, "lhs", ColList::new(0.into()));
+        let q = join(&lhs).optimize(&|_, _| 0);
+        assert!(
+            !q.query.iter().any(|op| matches!(op, Query::IndexJoin(_))),
+            "expected no index join, got {:?}",
+            q.query
         );
     }
 
     #[test]
-    fn best_index_range() {
-        let arena = Arena::new();
+    fn has_unconstrained_join() {
+        let table = |id, name: &str| {
+            TableSchema::from_def(
+                TableId(id),
+                TableDef::new(
+                    name.into(),
+                    ProductType::from_iter([AlgebraicType::U64, AlgebraicType::U64]).into(),
+                ),
+            )
+        };
+        let (lhs, rhs) = (table(0, "lhs"), table(1, "rhs"));
+        let [lhs_a, lhs_b] = [0, 1].map(|c| FieldName::new(lhs.table_id, c.into()));
+        let rhs_a = FieldName::new(rhs.table_id, 0.into());
+        let join =
+            |col_lhs, col_rhs| QueryExpr::new(&lhs).with_join_inner(SourceExpr::from(&rhs), col_lhs, col_rhs, false);
+
+        assert!(!QueryExpr::new(&lhs).has_unconstrained_join());
+        assert!(!join(lhs_a, rhs_a).has_unconstrained_join());
+        // Both keys are from `lhs`.
+        assert!(join(lhs_a, lhs_b).has_unconstrained_join());
+        // `rhs` has no third column.
+        assert!(join(lhs_a, FieldName::new(rhs.table_id, 2.into())).has_unconstrained_join());
+    }
 
-        let (head1, fields, vals) = setup_best_index();
-        let [col_a, col_b, col_c, col_d, _] = fields;
-        let [val_a, val_b, val_c, val_d, _] = vals;
+    #[test]
+    fn try_and_cmp_checks_key_arity() {
+        let (head, [_, col_b, col_c, ..], [_, val_b, val_c, ..]) = setup_best_index();
+        let cols = col_list![col_b.col, col_c.col];
+        let key: AlgebraicValue = product![val_b.clone(), val_c.clone()].into();
 
-        let select_best_index = |fields: &[_]| {
-            let fields = fields
-                .iter()
-                .map(|x| make_field_value(&arena, *x).parent)
-                .collect::<Vec<_>>();
-            select_best_index(&mut <_>::default(), &head1, &fields)
-        };
+        // A product of one value per column.
+        assert_eq!(
+            ColumnOp::try_and_cmp(OpCmp::Eq, &head, &cols, key.clone()).unwrap(),
+            ColumnOp::and(
+                ColumnOp::cmp(col_b, OpCmp::Eq, val_b.clone()),
+                ColumnOp::cmp(col_c, OpCmp::Eq, val_c.clone())
+            )
+        );
+        // A single column takes the value as is.
+        assert_eq!(
+            ColumnOp::try_and_cmp(OpCmp::Lt, &head, &col_b.col.into(), val_b.clone()).unwrap(),
+            ColumnOp::cmp(col_b, OpCmp::Lt, val_b.clone())
+        );
 
-        let col_list_arena = Arena::new();
-        let idx = |cmp, cols: &[FieldName], val: &AlgebraicValue| {
-            let columns = cols.iter().map(|c| c.col).collect::<ColListBuilder>().build().unwrap();
-            let columns = col_list_arena.alloc(columns);
-            make_index_arg(cmp, columns, val.clone())
+        // A scalar, or a product of the wrong arity, where a product of two values is expected.
+        for bad in [val_b.clone(), product![val_b.clone()].into()] {
+            match ColumnOp::try_and_cmp(OpCmp::Eq, &head, &cols, bad.clone()) {
+                Err(ErrorVm::IndexKeyArity { columns, key, .. }) => {
+                    assert_eq!(columns, cols);
+                    assert_eq!(key, bad);
+                }
+                res => panic!("expected an arity error, got {res:?}"),
+            }
+        }
+
+        // Either bound of a range may be malformed.
+        let bounds = (Bound::Included(key.clone()), Bound::Excluded(val_c));
+        assert!(matches!(
+            ColumnOp::try_from_op_col_bounds(&head, &cols, bounds),
+            Err(ErrorVm::IndexKeyArity { .. })
+        ));
+        let bounds = (Bound::Included(key.clone()), Bound::Included(key));
+        assert!(ColumnOp::try_from_op_col_bounds(&head, &cols, bounds).is_ok());
+
+        // A range without any bound restricts nothing.
+        assert!(matches!(
+            ColumnOp::try_from_op_col_bounds(&head, &cols, (Bound::Unbounded, Bound::Unbounded)),
+            Err(ErrorVm::UnboundedRange { columns, .. }) if columns == cols
+        ));
+
+        // A column outside of the header.
+        let missing = ColId(head.fields.len() as u32);
+        let bounds = (Bound::Included(val_b.clone()), Bound::Unbounded);
+        for res in [
+            ColumnOp::try_and_cmp(OpCmp::Eq, &head, &missing.into(), val_b.clone()),
+            ColumnOp::try_from_op_col_bounds(&head, &col_list![col_b.col, missing], bounds),
+        ] {
+            assert!(
+                matches!(res, Err(ErrorVm::Rel(RelationError::FieldNotFound(_, field))) if field.col == missing),
+                "expected a missing field, got {res:?}"
+            );
+        }
+    }
+
+    #[test]
+    fn validate_depth() {
+        let [lhs, rhs] = tables();
+        let field = FieldName::new(TableId(0), ColId(0));
+        // `a = 0 OR a = 1 OR ...` with `n` comparisons, nested to the left.
+        let ors = |n: u64| {
+            (0..n)
+                .map(|i| ColumnOp::cmp(field, OpCmp::Eq, i))
+                .reduce(ColumnOp::or)
+                .unwrap()
         };
+        assert_eq!(ors(1).depth(), 2);
+        assert_eq!(ors(3).depth(), 4);
 
-        // Same field indexed
+        // The query itself adds one level to that of its predicate.
+        let select = |source: &SourceExpr, n| QueryExpr::new(source.clone()).with_select(ors(n));
+        assert!(select(&lhs, 98).validate_depth(100).is_ok());
+        assert!(matches!(
+            select(&lhs, 99).validate_depth(100),
+            Err(ErrorVm::TooDeep {
+                depth: 101,
+                max_depth: 100
+            })
+        ));
+
+        // A joined subquery adds another level.
+        let join = |n| QueryExpr::new(lhs.clone()).with_join_inner(select(&rhs, n), field, field, false);
+        assert!(join(97).validate_depth(100).is_ok());
+        assert!(matches!(
+            join(98).validate_depth(100),
+            Err(ErrorVm::TooDeep { depth: 101, .. })
+        ));
+    }
+
+    #[test]
+    fn operator_counts() {
+        let (a, b, c) = (
+            indexed_u64_table(0, "a"),
+            indexed_u64_table(1, "b"),
+            indexed_u64_table(2, "c"),
+        );
+        let [a_0, a_1] = [0, 1].map(|col| FieldName::new(a.table_id, col.into()));
+        let b_0 = FieldName::new(b.table_id, 0.into());
+        let [c_0, c_1] = [0, 1].map(|col| FieldName::new(c.table_id, col.into()));
+
+        assert_eq!(QueryExpr::new(&a).operator_counts(), OperatorCounts::default());
+
+        // a JOIN (b JOIN (c WHERE c.1 = 1)), scanning `a` by its index.
+        let c_side = QueryExpr::new(&c).with_select(ColumnOp::cmp(c_1, OpCmp::Eq, 1u64));
+        let b_side = QueryExpr::new(&b).with_join_inner(c_side, b_0, c_0, false);
+        let q = QueryExpr::new(&a)
+            .with_index_lower_bound(DbTable::from(&a), ColId(0).into(), 1u64.into(), true)
+            .with_join_inner(b_side, a_0, b_0, false)
+            .with_project(&[a_1.into()], None)
+            .with_limit(10, 0);
+
+        let counts = q.operator_counts();
         assert_eq!(
-            select_best_index(&[(OpCmp::Gt, col_a, &val_a), (OpCmp::Lt, col_a, &val_b)]),
-            [idx(OpCmp::Lt, &[col_a], &val_b), idx(OpCmp::Gt, &[col_a], &val_a)].into()
+            counts,
+            OperatorCounts {
+                index_scans: 1,
+                seq_selects: 1,
+                inner_joins: 2,
+                index_joins: 0,
+                other_joins: 0,
+                projections: 1,
+            }
         );
+        assert_eq!(counts.joins(), 2);
+    }
 
-        // Same field scan
-        assert_eq!(
-            select_best_index(&[(OpCmp::Gt, col_d, &val_d), (OpCmp::Lt, col_d, &val_b)]),
-            [
-                scan(&arena, OpCmp::Lt, col_d, &val_b),
-                scan(&arena, OpCmp::Gt, col_d, &val_d)
-            ]
-            .into()
+    #[test]
+    fn with_index_range() {
+        let table = TableSchema::from_def(
+            TableId(0),
+            TableDef::new(
+                "t".into(),
+                ProductType::from_iter([AlgebraicType::U8, AlgebraicType::U8]).into(),
+            )
+            .with_column_constraint(Constraints::indexed(), ColId(0)),
         );
-        // One indexed other scan
-        assert_eq!(
-            select_best_index(&[(OpCmp::Gt, col_b, &val_b), (OpCmp::Lt, col_c, &val_c)]),
-            [idx(OpCmp::Gt, &[col_b], &val_b), scan(&arena, OpCmp::Lt, col_c, &val_c)].into()
+        let query = QueryExpr::new(&table);
+        let db_table = query.source.get_db_table().unwrap().clone();
+        let columns = ColList::from(ColId(0));
+        let range =
+            |query: QueryExpr, lower, upper| query.with_index_range(db_table.clone(), columns.clone(), lower, upper);
+        let scan = |query: &QueryExpr| match &*query.query {
+            [Query::IndexScan(scan)] => scan.clone(),
+            query => panic!("expected a single index scan, got {query:?}"),
+        };
+        let (inc, exc) = (Bound::Included, Bound::Excluded);
+        let val = AlgebraicValue::U8;
+
+        // 3 <= a <= 7
+        let q = range(query.clone(), inc(val(3)), inc(val(7)));
+        assert_eq!(scan(&q).bounds, (inc(val(3)), inc(val(7))));
+        // 3 < a < 7
+        let q = range(query.clone(), exc(val(3)), exc(val(7)));
+        assert_eq!(scan(&q).bounds, (exc(val(3)), exc(val(7))));
+        // a >= 3
+        let q = range(query.clone(), inc(val(3)), Bound::Unbounded);
+        assert_eq!(scan(&q).bounds, (inc(val(3)), Bound::Unbounded));
+        // An unbounded range adds nothing.
+        assert_eq!(range(query.clone(), Bound::Unbounded, Bound::Unbounded), query);
+
+        // a > 1 AND 3 <= a < 10 => 3 <= a < 10, in a single scan
+        let q = range(
+            range(query.clone(), exc(val(1)), Bound::Unbounded),
+            inc(val(3)),
+            exc(val(10)),
         );
+        assert_eq!(scan(&q).bounds, (inc(val(3)), exc(val(10))));
 
-        // 1 multi-indexed 1 index
-        assert_eq!(
-            select_best_index(&[
-                (OpCmp::Eq, col_b, &val_b),
-                (OpCmp::GtEq, col_a, &val_a),
-                (OpCmp::Eq, col_c, &val_c),
-            ]),
-            [
-                idx(
-                    OpCmp::Eq,
-                    &[col_b, col_c],
-                    &product![val_b.clone(), val_c.clone()].into(),
-                ),
-                idx(OpCmp::GtEq, &[col_a], &val_a),
-            ]
-            .into()
+        // 5 < a < 5 and 7 <= a <= 3 are never satisfied.
+        for (lower, upper) in [(exc(val(5)), exc(val(5))), (inc(val(7)), inc(val(3)))] {
+            let q = range(query.clone(), lower, upper);
+            assert!(scan(&q).is_never());
+            assert_eq!(q.optimize(&|_, _| 0).query, [Query::NeverReturns]);
+        }
+        // So is a range disjoint from that of a preceding scan.
+        let q = range(
+            range(query.clone(), inc(val(1)), inc(val(2))),
+            inc(val(4)),
+            Bound::Unbounded,
         );
+        assert!(scan(&q).is_never());
+
+        // Following another operator, both bounds go into a single select.
+        let b = FieldName::new(table.table_id, 1.into());
+        let filter = ColumnOp::cmp(b, OpCmp::Eq, 0u8);
+        let q = range(query.with_select(filter.clone()), inc(val(3)), exc(val(7)));
+        let a = FieldName::new(table.table_id, 0.into());
+        let bounds = ColumnOp::and(ColumnOp::cmp(a, OpCmp::GtEq, 3u8), ColumnOp::cmp(a, OpCmp::Lt, 7u8));
+        assert_eq!(q.query, [Query::Select(ColumnOp::and(filter, bounds))]);
+    }
 
-        // 1 indexed 2 scan
-        assert_eq!(
-            select_best_index(&[
-                (OpCmp::Gt, col_b, &val_b),
-                (OpCmp::Eq, col_a, &val_a),
-                (OpCmp::Lt, col_c, &val_c),
-            ]),
+    #[test]
+    fn with_limit() {
+        let [source, _] = tables();
+        let q = QueryExpr::new(source.clone()).with_limit(10, 5);
+        assert_eq!(q.query, [Query::Limit { limit: 10, offset: 5 }]);
+
+        // A second limit is applied to the rows the first one yields.
+        let q = q.with_limit(8, 4);
+        assert_eq!(q.query, [Query::Limit { limit: 6, offset: 9 }]);
+        // Skipping all of them leaves nothing to yield.
+        let q = q.with_limit(3, 6);
+        assert_eq!(q.query, [Query::Limit { limit: 0, offset: 15 }]);
+
+        // A limit following another operator is appended.
+        let field = FieldName::new(source.head().table_id, 0.into());
+        let q = QueryExpr::new(source)
+            .with_project(&[field.into()], None)
+            .with_limit(1, 0);
+        assert!(matches!(
+            &*q.query,
+            [Query::Project(..), Query::Limit { limit: 1, offset: 0 }]
+        ));
+    }
+
+    #[test]
+    fn optimize_limit_pushdown() {
+        let table = indexed_u64_table(0, "t");
+        let source = SourceExpr::from(&table);
+        let a = FieldName::new(table.table_id, 0.into());
+        let b = FieldName::new(table.table_id, 1.into());
+        let q = QueryExpr::new(source.clone())
+            .with_select(ColumnOp::cmp(a, OpCmp::GtEq, 3u64))
+            .with_project(&[b.into()], None)
+            .with_limit(10, 2);
+
+        // Projecting doesn't change the number of rows, so the limit goes first.
+        let optimized = q.clone().optimize(&|_, _| 0);
+        assert!(matches!(
+            &*optimized.query,
             [
-                idx(OpCmp::Eq, &[col_a], &val_a),
-                idx(OpCmp::Gt, &[col_b], &val_b),
-                scan(&arena, OpCmp::Lt, col_c, &val_c),
+                Query::IndexScan(_),
+                Query::Limit { limit: 10, offset: 2 },
+                Query::Project(..)
             ]
-            .into()
+        ));
+        // As it directly follows an index scan, that scan stops early.
+        assert_eq!(
+            optimized.explain(),
+            "\
+Source: t (table#0)
+IndexScan: t (table#0), cols [0], bounds [3, +inf)
+Limit: 10, offset: 2, stops index scan early
+Project: table#0.col#1
+"
         );
-    }
 
-    #[test]
-    fn test_auth_table() {
-        tables().iter().for_each(assert_owner_private)
+        // Nothing moves when the pushdown is disabled.
+        let opts = OptimizeOptions {
+            enable_limit_pushdown: false,
+            ..<_>::default()
+        };
+        assert!(matches!(
+            &*q.optimize_with(&|_, _| 0, &opts).query,
+            [Query::IndexScan(_), Query::Project(..), Query::Limit { .. }]
+        ));
     }
 
     #[test]
-    fn test_auth_query_code() {
-        for code in query_exprs() {
-            assert_owner_private(&code)
-        }
+    fn optimize_covering_index_scan() {
+        let table = indexed_u64_table(0, "t");
+        let [a, b] = [0, 1].map(|c| FieldName::new(table.table_id, ColId(c)));
+        let covering = |q: QueryExpr, opts: &OptimizeOptions| match &*q.optimize_with(&|_, _| 0, opts).query {
+            [Query::IndexScan(scan), ..] => scan.covering,
+            query => panic!("expected an index scan, got {query:?}"),
+        };
+        let opts = OptimizeOptions::default();
+        let select = QueryExpr::new(&table).with_select(ColumnOp::cmp(a, OpCmp::Gt, 5u64));
+
+        // `SELECT a FROM t WHERE a > 5` reads only the indexed column.
+        let project_a = select.clone().with_project(&[a.into()], None);
+        assert!(covering(project_a.clone(), &opts));
+        assert!(project_a
+            .clone()
+            .optimize(&|_, _| 0)
+            .explain()
+            .contains("bounds (5, +inf), covering"));
+
+        // `b` is not part of the index.
+        assert!(!covering(select.clone().with_project(&[b.into()], None), &opts));
+        // Neither is it when only filtered on.
+        let filter_b = select
+            .clone()
+            .with_select(ColumnOp::cmp(b, OpCmp::Eq, 3u64))
+            .with_project(&[a.into()], None);
+        assert!(!covering(filter_b, &opts));
+        // Without a projection, the scan yields entire rows.
+        assert!(!covering(select, &opts));
+
+        // Nothing is marked when the pass is disabled.
+        let opts = OptimizeOptions {
+            enable_covering_index_scans: false,
+            ..<_>::default()
+        };
+        assert!(!covering(project_a, &opts));
     }
 
     #[test]
-    fn test_auth_query() {
-        for query in queries() {
-            assert_owner_private(&query);
+    fn optimize_removes_identity_projections() {
+        let table = indexed_u64_table(0, "t");
+        let [a, b] = [0, 1].map(|c| FieldName::new(table.table_id, ColId(c)));
+        let select = QueryExpr::new(&table).with_select(ColumnOp::cmp(a, OpCmp::GtEq, 3u64));
+
+        // Projecting every column, in order, changes nothing.
+        let optimized = select
+            .clone()
+            .with_project(&[a.into(), b.into()], None)
+            .optimize(&|_, _| 0);
+        assert!(matches!(&*optimized.query, [Query::IndexScan(_)]));
+
+        // Reordering, renaming, or leaving out columns does.
+        let projections: [Vec<(FieldExpr, Option<Box<str>>)>; 3] = [
+            vec![(b.into(), None), (a.into(), None)],
+            vec![(a.into(), Some("renamed".into())), (b.into(), None)],
+            vec![(a.into(), None)],
+        ];
+        for cols in projections {
+            let optimized = select.clone().with_project_aliased(cols, None).optimize(&|_, _| 0);
+            assert!(matches!(&*optimized.query, [Query::IndexScan(_), Query::Project(..)]));
         }
+
+        // Nothing is dropped when the removal is disabled.
+        let opts = OptimizeOptions {
+            enable_remove_identity_projections: false,
+            ..<_>::default()
+        };
+        let optimized = select
+            .with_project(&[a.into(), b.into()], None)
+            .optimize_with(&|_, _| 0, &opts);
+        assert!(matches!(&*optimized.query, [Query::IndexScan(_), Query::Project(..)]));
     }
 
     #[test]
-    fn test_auth_crud_code_query() {
-        for query in query_exprs() {
-            let crud = CrudExpr::Query(query);
-            assert_owner_private(&crud);
-        }
+    fn optimize_keeps_distinct_projection() {
+        let (lhs, rhs) = (indexed_u64_table(0, "lhs"), indexed_u64_table(1, "rhs"));
+        let [lhs_id, lhs_a] = [0, 1].map(|c| FieldName::new(lhs.table_id, ColId(c)));
+        let rhs_id = FieldName::new(rhs.table_id, ColId(0));
+        let join = QueryExpr::new(&lhs).with_join_inner(SourceExpr::from(&rhs), lhs_id, rhs_id, false);
+        let wildcard: [FieldExpr; 2] = [lhs_id.into(), lhs_a.into()];
+
+        // A wildcard projection of the lhs turns the join into a semijoin...
+        let optimized = join
+            .clone()
+            .with_project(&wildcard, Some(lhs.table_id))
+            .optimize(&|_, _| 0);
+        assert!(!optimized.query.iter().any(|q| matches!(q, Query::Project(..))));
+        // ...but a distinct one is kept, as the join may match a row of the lhs more than once.
+        let optimized = join
+            .with_project_distinct(&wildcard, Some(lhs.table_id))
+            .optimize(&|_, _| 0);
+        assert!(matches!(&*optimized.query, [.., Query::Project(_, Some(_), true)]));
+
+        // Neither is an identity projection dropped, nor a limit moved below it.
+        let optimized = QueryExpr::new(&lhs)
+            .with_project_distinct(&wildcard, None)
+            .with_limit(1, 0)
+            .optimize(&|_, _| 0);
+        assert!(matches!(
+            &*optimized.query,
+            [Query::Project(_, None, true), Query::Limit { limit: 1, offset: 0 }]
+        ));
+        assert_eq!(
+            optimized.explain(),
+            "\
+Source: lhs (table#0)
+Project distinct: table#0.col#0, table#0.col#1
+Limit: 1, offset: 0
+"
+        );
     }
 
     #[test]
-    fn test_auth_crud_code_insert() {
-        for table in tables().into_iter().filter_map(|s| s.get_db_table().cloned()) {
-            let crud = CrudExpr::Insert { table, rows: vec![] };
-            assert_owner_required(crud);
-        }
+    fn push_selects_into_joins() {
+        let schema = |id: u32, name: &str| {
+            let columns = ProductType::from_iter([AlgebraicType::U64, AlgebraicType::U64, AlgebraicType::U64]);
+            TableSchema::from_def(TableId(id), TableDef::new(name.into(), columns.into()))
+        };
+        let (lhs, rhs) = (schema(0, "lhs"), schema(1, "rhs"));
+        let [lhs_id, lhs_a, lhs_c] = [0, 1, 2].map(|c| FieldName::new(lhs.table_id, ColId(c)));
+        let [rhs_id, rhs_b, rhs_d] = [0, 1, 2].map(|c| FieldName::new(rhs.table_id, ColId(c)));
+        let (lhs_source, rhs_source) = (SourceExpr::from(&lhs), SourceExpr::from(&rhs));
+
+        // lhs JOIN rhs WHERE lhs.a = 1 AND rhs.b = 2 AND lhs.c = rhs.d AND lhs.c > 5
+        let a_eq = ColumnOp::cmp(lhs_a, OpCmp::Eq, 1u64);
+        let b_eq = ColumnOp::cmp(rhs_b, OpCmp::Eq, 2u64);
+        let c_eq_d = ColumnOp::new(
+            OpQuery::Cmp(OpCmp::Eq),
+            ColumnOp::Field(lhs_c.into()),
+            ColumnOp::Field(rhs_d.into()),
+        );
+        let c_gt = ColumnOp::cmp(lhs_c, OpCmp::Gt, 5u64);
+        let filter = [a_eq.clone(), b_eq.clone(), c_eq_d.clone(), c_gt.clone()]
+            .into_iter()
+            .reduce(ColumnOp::and)
+            .unwrap();
+        let q = QueryExpr::new(lhs_source.clone())
+            .with_join_inner(rhs_source.clone(), lhs_id, rhs_id, false)
+            .with_select(filter);
+
+        // Each conjunct lands on the side it refers to, and the cross-table one stays above the join.
+        let expected = QueryExpr {
+            source: lhs_source,
+            query: vec![
+                Query::Select(ColumnOp::and(a_eq, c_gt)),
+                Query::JoinInner(JoinExpr::new(
+                    QueryExpr::new(rhs_source).with_select(b_eq),
+                    lhs_id,
+                    rhs_id,
+                    JoinKind::Inner,
+                )),
+                Query::Select(c_eq_d),
+            ],
+        };
+        assert_eq!(q.push_selects_into_joins(), expected);
     }
 
     #[test]
-    fn test_auth_crud_code_update() {
-        for qc in query_exprs() {
-            let crud = CrudExpr::Update {
-                delete: qc,
-                assignments: Default::default(),
-            };
-            assert_owner_required(crud);
-        }
+    fn push_selects_into_joins_keeps_cross_table_predicates() {
+        let table = TableSchema::from_def(
+            TableId(0),
+            TableDef::new(
+                "t".into(),
+                ProductType::from_iter([AlgebraicType::U64, AlgebraicType::U64]).into(),
+            ),
+        );
+        let [a, b] = [0, 1].map(|c| FieldName::new(table.table_id, ColId(c)));
+        let other = TableSchema::from_def(
+            TableId(1),
+            TableDef::new("u".into(), ProductType::from_iter([AlgebraicType::U64]).into()),
+        );
+        let other_a = FieldName::new(other.table_id, ColId(0));
+        let with_filter = |rhs: &TableSchema, rhs_field: FieldName, filter: ColumnOp| {
+            let mut q = QueryExpr::new(&table).with_join_inner(SourceExpr::from(rhs), a, rhs_field, false);
+            q.query.push(Query::Select(filter));
+            q
+        };
+
+        // A predicate comparing the columns of both sides can only be evaluated on the joined rows.
+        let cross = ColumnOp::new(
+            OpQuery::Cmp(OpCmp::Lt),
+            ColumnOp::Field(b.into()),
+            ColumnOp::Field(other_a.into()),
+        );
+        let q = with_filter(&other, other_a, cross);
+        assert_eq!(q.clone().push_selects_into_joins(), q);
+
+        // When a table is joined with itself, its fields refer to either side.
+        let q = with_filter(&table, a, ColumnOp::cmp(b, OpCmp::Eq, 1u64));
+        assert_eq!(q.clone().push_selects_into_joins(), q);
     }
 
     #[test]
-    fn test_auth_crud_code_delete() {
-        for query in query_exprs() {
-            let crud = CrudExpr::Delete { query };
-            assert_owner_required(crud);
+    fn limit_has_no_sources() {
+        // Like a selection, a limit reads from no table of its own.
+        assert!(matches!(
+            Query::Limit { limit: 1, offset: 0 }.sources(),
+            QuerySources::None
+        ));
+
+        // The sources of a query are unaffected by limiting it.
+        for expr in query_exprs() {
+            let limited = expr.clone().with_limit(1, 0);
+            assert_eq!(
+                limited.sources().collect::<Vec<_>>(),
+                expr.sources().collect::<Vec<_>>()
+            );
         }
     }
 
     #[test]
-    fn test_auth_crud_code_create_table() {
-        let table = TableDef::new("etcpasswd".into(), vec![])
-            .with_access(StAccess::Public)
-            .with_type(StTableType::System); // hah!
+    fn optimize_never_bounds() {
+        let table = TableSchema::from_def(
+            TableId(0),
+            TableDef::new("t".into(), ProductType::from_iter([AlgebraicType::U64]).into())
+                .with_column_constraint(Constraints::indexed(), ColId(0)),
+        );
+        let field = FieldName::new(table.table_id, 0.into());
+        let optimize = |lower: OpCmp, upper: OpCmp| {
+            QueryExpr::new(&table)
+                .with_select(ColumnOp::cmp(field, lower, 5u64))
+                .with_select(ColumnOp::cmp(field, upper, 5u64))
+                .optimize(&|_, _| 0)
+        };
 
-        let crud = CrudExpr::CreateTable { table };
-        assert_owner_required(crud);
+        // `x > 5 AND x < 5` never returns any rows, so the table isn't scanned at all.
+        let q = optimize(OpCmp::Gt, OpCmp::Lt);
+        assert_eq!(q.query, [Query::NeverReturns]);
+        assert_eq!(q.estimated_rows(&|_, _| 100).last(), Some(&RowCount::exact(0)));
+        assert_eq!(q.explain(), "Source: t (table#0)\nNeverReturns\n");
+
+        // Whereas the narrowest satisfiable range is kept.
+        let q = optimize(OpCmp::GtEq, OpCmp::LtEq);
+        match &*q.query {
+            [Query::IndexScan(scan)] => {
+                assert!(!scan.is_never());
+                assert_eq!(
+                    scan.bounds,
+                    (Bound::Included(5u64.into()), Bound::Included(5u64.into()))
+                );
+            }
+            query => panic!("expected a single index scan, got {query:?}"),
+        }
     }
 
     #[test]
-    fn test_auth_crud_code_drop() {
-        let crud = CrudExpr::Drop {
-            name: "etcpasswd".into(),
-            kind: DbType::Table,
-            table_access: StAccess::Public,
+    fn optimize_intersects_index_scans() {
+        let table = indexed_u64_table(0, "t");
+        let q = QueryExpr::new(&table);
+        let db_table = q.source.get_db_table().unwrap().clone();
+        let scan = |bounds: (Bound<u64>, Bound<u64>)| {
+            Query::IndexScan(IndexScan {
+                table: db_table.clone(),
+                columns: ColId(0).into(),
+                bounds: (bounds.0.map(Into::into), bounds.1.map(Into::into)),
+                covering: false,
+            })
         };
-        assert_owner_required(crud);
+        let filter = Query::Select(ColumnOp::cmp(FieldName::new(table.table_id, 1.into()), OpCmp::Eq, 7u64));
+        let optimize = |first, second| {
+            QueryExpr {
+                source: q.source.clone(),
+                query: vec![first, filter.clone(), second],
+            }
+            .optimize(&|_, _| 0)
+        };
+
+        // `[3, 10]` and `(5, +inf)` overlap, so a single scan of `(5, 10]` remains.
+        let optimized = optimize(
+            scan((Bound::Included(3), Bound::Included(10))),
+            scan((Bound::Excluded(5), Bound::Unbounded)),
+        );
+        assert_eq!(
+            optimized.query,
+            [scan((Bound::Excluded(5), Bound::Included(10))), filter.clone()]
+        );
+
+        // `[3, 5)` and `[5, +inf)` are disjoint, so no rows are returned.
+        let optimized = optimize(
+            scan((Bound::Included(3), Bound::Excluded(5))),
+            scan((Bound::Included(5), Bound::Unbounded)),
+        );
+        assert_eq!(optimized.query, [Query::NeverReturns, filter.clone()]);
     }
 
     #[test]
-    /// Tests that [`QueryExpr::optimize`] can rewrite inner joins followed by projections into semijoins.
-    fn optimize_inner_join_to_semijoin() {
-        let lhs = TableSchema::from_def(
+    fn optimize_with_column_stats() {
+        let table = TableSchema::from_def(
             TableId(0),
             TableDef::new(
-                "lhs".into(),
-                ProductType::from_iter([AlgebraicType::I32, AlgebraicType::String]).into(),
-            ),
-        );
-        let rhs = TableSchema::from_def(
-            TableId(1),
-            TableDef::new(
-                "rhs".into(),
-                ProductType::from_iter([AlgebraicType::I32, AlgebraicType::I64]).into(),
-            ),
+                "t".into(),
+                ProductType::from_iter([AlgebraicType::U64, AlgebraicType::U64]).into(),
+            )
+            .with_column_constraint(Constraints::indexed(), ColId(0))
+            .with_column_constraint(Constraints::indexed(), ColId(1)),
         );
+        let [a, b] = [0, 1].map(|c| FieldName::new(table.table_id, ColId(c)));
+        let stats = |a, b| {
+            ColumnStatsMap::from([
+                (
+                    ColId(0),
+                    ColumnStats {
+                        distinct_count: Some(a),
+                        ..Default::default()
+                    },
+                ),
+                (
+                    ColId(1),
+                    ColumnStats {
+                        distinct_count: Some(b),
+                        ..Default::default()
+                    },
+                ),
+            ])
+        };
+        // a = 1 AND b = 2
+        let op = ColumnOp::and(ColumnOp::cmp(a, OpCmp::Eq, 1u64), ColumnOp::cmp(b, OpCmp::Eq, 2u64));
+        let scanned_cols = |source: SourceExpr| {
+            let optimized = QueryExpr::new(source).with_select(op.clone()).optimize(&|_, _| 0);
+            match &optimized.query[0] {
+                Query::IndexScan(scan) => scan.columns.clone(),
+                query => panic!("expected an index scan, got {query:?}"),
+            }
+        };
+        let source = SourceExpr::from(&table);
+        assert_eq!(source.column_stats(), None);
 
-        let lhs_source = SourceExpr::from(&lhs);
-        let rhs_source = SourceExpr::from(&rhs);
+        // Without statistics, the first index is used.
+        assert_eq!(scanned_cols(source.clone()), ColId(0).into());
 
-        let q = QueryExpr::new(lhs_source.clone())
-            .with_join_inner(
-                rhs_source.clone(),
-                FieldName::new(lhs.table_id, 0.into()),
-                FieldName::new(rhs.table_id, 0.into()),
-                false,
-            )
-            .with_project(
-                &[0, 1].map(|c| FieldExpr::Name(FieldName::new(lhs.table_id, c.into()))),
-                Some(TableId(0)),
-            );
-        let q = q.optimize(&|_, _| 0);
+        // `b` is far more selective than `a`, so its index is preferred.
+        let source = source.with_column_stats(stats(3, 30));
+        assert_eq!(source.column_stats(), Some(&stats(3, 30)));
+        assert_eq!(scanned_cols(source.clone()), ColId(1).into());
 
-        assert_eq!(q.source, lhs_source, "Optimized query should read from lhs");
+        // ...and the other way around.
+        let source = source.with_column_stats(stats(1000, 2));
+        assert_eq!(scanned_cols(source), ColId(0).into());
+    }
 
+    #[test]
+    fn join_left() {
+        let lhs = indexed_u64_table(0, "lhs");
+        let rhs = indexed_u64_table(1, "rhs");
+        let lhs_a = FieldName::new(lhs.table_id, 0.into());
+        let lhs_b = FieldName::new(lhs.table_id, 1.into());
+        let rhs_a = FieldName::new(rhs.table_id, 0.into());
+
+        let q = QueryExpr::new(&lhs).with_join_left(QueryExpr::new(&rhs), lhs_a, rhs_a);
+        let table_ids = q.sources().map(|source| source.head().table_id).collect::<Vec<_>>();
+        assert_eq!(table_ids, [lhs.table_id, rhs.table_id]);
+        assert!(q.reads_from_table(&rhs.table_id));
+        assert!(!q.reads_from_table(&TableId(2)));
         assert_eq!(
-            q.query.len(),
-            1,
-            "Optimized query should have a single member, a semijoin"
+            q.explain(),
+            "Source: lhs (table#0)\nJoinLeft: ON table#0.col#0 = table#1.col#0\n  Source: rhs (table#1)\n"
         );
-        match &q.query[0] {
-            Query::JoinInner(JoinExpr { rhs, semi, .. }) => {
-                assert!(semi, "Optimized query should be a semijoin");
-                assert_eq!(rhs.source, rhs_source, "Optimized query should filter with rhs");
-                assert!(
-                    rhs.query.is_empty(),
-                    "Optimized query should not filter rhs before joining"
-                );
-            }
-            wrong => panic!("Expected an inner join, but found {wrong:?}"),
+
+        // Unlike an inner join, the projection onto the lhs isn't turned into a semijoin or an index join,
+        // as that would drop the rows of the lhs without a match.
+        let q = q.with_project(&[lhs_a.into(), lhs_b.into()], Some(lhs.table_id));
+        let optimized = q.clone().optimize(&|_, _| 100);
+        assert_eq!(optimized.query, q.query);
+
+        // A selection on the rhs is applied after the join, rather than pushed into its rhs,
+        // which would make rows of the lhs without a match reappear, padded.
+        let q = QueryExpr::new(&lhs)
+            .with_join_left(QueryExpr::new(&rhs), lhs_a, rhs_a)
+            .with_select(ColumnOp::cmp(rhs_a, OpCmp::Eq, 1u64));
+        match &*q.optimize(&|_, _| 100).query {
+            [Query::JoinLeft(join), Query::Select(_)] => assert!(join.rhs.query.is_empty()),
+            query => panic!("unexpected plan {query:?}"),
         }
     }
 
     #[test]
-    /// Tests that [`QueryExpr::optimize`] will not rewrite inner joins which are not followed by projections to the LHS table.
-    fn optimize_inner_join_no_project() {
+    fn is_not_distinct_from() {
+        let none = AlgebraicValue::OptionNone();
+        let some = |x: u8| AlgebraicValue::OptionSome(x.into());
+        let table_id = TableId(0);
+        let [a, b] = [0, 1].map(|col| FieldName::new(table_id, ColId(col)));
+        let option = AlgebraicType::option(AlgebraicType::U8);
+        let header = Header::new(
+            table_id,
+            "t".into(),
+            vec![Column::new(a, option.clone()), Column::new(b, option)],
+            vec![],
+        );
+        // Evaluates `a cmp b` for the row `(lhs, rhs)`.
+        let eval = |cmp, lhs: &AlgebraicValue, rhs: &AlgebraicValue| {
+            let op = ColumnOp::new(
+                OpQuery::Cmp(cmp),
+                ColumnOp::Field(FieldExpr::Name(a)),
+                ColumnOp::Field(FieldExpr::Name(b)),
+            );
+            let row = RelValue::Projection(product![lhs.clone(), rhs.clone()]);
+            op.compare(&row, &header).unwrap()
+        };
+        let check = |lhs: &AlgebraicValue, rhs: &AlgebraicValue, eq: bool, not_distinct: bool| {
+            assert_eq!(eval(OpCmp::Eq, lhs, rhs), eq, "{lhs:?} = {rhs:?}");
+            assert_eq!(eval(OpCmp::NotEq, lhs, rhs), !eq, "{lhs:?} != {rhs:?}");
+            assert_eq!(
+                eval(OpCmp::IsNotDistinctFrom, lhs, rhs),
+                not_distinct,
+                "{lhs:?} is not distinct from {rhs:?}"
+            );
+            assert_eq!(
+                eval(OpCmp::IsDistinctFrom, lhs, rhs),
+                !not_distinct,
+                "{lhs:?} is distinct from {rhs:?}"
+            );
+        };
+
+        check(&none, &none, true, true);
+        check(&none, &some(1), false, false);
+        check(&some(1), &none, false, false);
+        check(&some(1), &some(1), true, true);
+        check(&some(1), &some(2), false, false);
+        // Unlike `Eq`, the operators compare a some with the value it wraps.
+        check(&some(1), &1u8.into(), false, true);
+        check(&2u8.into(), &some(1), false, false);
+        // A none is distinct from any value.
+        check(&none, &1u8.into(), false, false);
+
+        // Comparing a column with a literal behaves the same.
+        let with_literal = ColumnOp::cmp(a, OpCmp::IsNotDistinctFrom, 1u8);
+        let row = RelValue::Projection(product![some(1), none.clone()]);
+        assert!(with_literal.compare(&row, &header).unwrap());
+        let row = RelValue::Projection(product![none.clone(), none.clone()]);
+        assert!(!with_literal.compare(&row, &header).unwrap());
+
+        // Neither operator is served by an index, even on an indexed column.
+        let (head, [col_a, ..], _) = setup_best_index();
+        let arena = Arena::new();
+        for cmp in [OpCmp::IsNotDistinctFrom, OpCmp::IsDistinctFrom] {
+            let op = make_field_value(&arena, (cmp, col_a, &none)).parent;
+            assert_eq!(
+                select_best_index(&mut <_>::default(), &head, &<_>::default(), &[op]),
+                [scan(&arena, cmp, col_a, &none)].into(),
+            );
+        }
+    }
+
+    #[test]
+    fn read_table_ids() {
+        let table = |id: u32, name: &str| {
+            TableSchema::from_def(
+                TableId(id),
+                TableDef::new(
+                    name.into(),
+                    ProductType::from_iter([AlgebraicType::U64, AlgebraicType::U64]).into(),
+                ),
+            )
+        };
+        let [a, b, c] = [table(0, "a"), table(1, "b"), table(2, "c")];
+        let field = |table: &TableSchema| FieldName::new(table.table_id, 0.into());
+
+        let q = QueryExpr::new(&a)
+            .with_join_inner(QueryExpr::new(&b), field(&a), field(&b), false)
+            .with_join_left(QueryExpr::new(&c), field(&a), field(&c));
+        let expected = [a.table_id, b.table_id, c.table_id].into_iter().collect::<HashSet<_>>();
+        assert_eq!(q.read_table_ids(), expected);
+
+        // A subquery over an in-memory source reads no table.
+        let mem = mem_table(TableId(3), "mem", &[(0, AlgebraicType::U64, false)]);
+        let mem_field = FieldName::new(TableId(3), 0.into());
+        let q = q.with_join_inner(QueryExpr::new(mem), field(&a), mem_field, false);
+        assert_eq!(q.read_table_ids(), expected);
+    }
+
+    #[test]
+    fn then_composes_pipelines() {
+        let table = |id, name: &str| {
+            TableSchema::from_def(
+                TableId(id),
+                TableDef::new(
+                    name.into(),
+                    ProductType::from_iter([AlgebraicType::U64, AlgebraicType::U64]).into(),
+                ),
+            )
+        };
+        let [a, b, c] = [table(0, "a"), table(1, "b"), table(2, "c")];
+        let field = |table: &TableSchema, col: u32| FieldName::new(table.table_id, col.into());
+
+        // A fragment joining `a` with `b`, and one joining its result with a filtered `c`.
+        let inner = QueryExpr::new(&a).with_join_inner(QueryExpr::new(&b), field(&a, 0), field(&b, 0), false);
+        let rhs = QueryExpr::new(&c).with_select(ColumnOp::cmp(field(&c, 1), OpCmp::Eq, 5u64));
+        let next = vec![
+            Query::JoinInner(JoinExpr::new(rhs, field(&b, 1), field(&c, 0), JoinKind::Inner)),
+            Query::Select(ColumnOp::cmp(field(&a, 1), OpCmp::Gt, 2u64)),
+        ];
+
+        let q = inner.clone().then(next.clone());
+        assert_eq!(q.source, inner.source);
+        assert_eq!(q.query, [inner.query, next].concat());
+
+        // The tables of both fragments are read, before and after optimizing.
+        let expected = [a.table_id, b.table_id, c.table_id].into_iter().collect::<HashSet<_>>();
+        assert_eq!(q.read_table_ids(), expected);
+        assert!(q.reads_from_table(&c.table_id));
+        let q = q.optimize(&|_, _| 0);
+        assert_eq!(q.read_table_ids(), expected);
+        assert_eq!(q.sources().count(), 3);
+    }
+
+    #[test]
+    fn optimize_semi_join_in_memory() {
         let lhs = TableSchema::from_def(
             TableId(0),
             TableDef::new(
                 "lhs".into(),
-                ProductType::from_iter([AlgebraicType::I32, AlgebraicType::String]).into(),
-            ),
-        );
-        let rhs = TableSchema::from_def(
-            TableId(1),
-            TableDef::new(
-                "rhs".into(),
-                ProductType::from_iter([AlgebraicType::I32, AlgebraicType::I64]).into(),
+                ProductType::from_iter([AlgebraicType::U64, AlgebraicType::String]).into(),
             ),
         );
+        let lhs_id = FieldName::new(lhs.table_id, 0.into());
+        let ids = mem_table(TableId(1), "ids", &[(0, AlgebraicType::U64, false)]);
+        let ids_id = FieldName::new(TableId(1), 0.into());
+
+        // A semijoin with a single-column in-memory table, read in full or projected to that column.
+        for rhs in [
+            QueryExpr::new(ids.clone()),
+            QueryExpr::new(ids.clone()).with_project(&[ids_id.into()], None),
+        ] {
+            let q = QueryExpr::new(&lhs)
+                .with_join_inner(rhs, lhs_id, ids_id, true)
+                .optimize(&|_, _| 0);
+            assert_eq!(
+                q.query,
+                [Query::SemiJoinInMemory {
+                    source: ids.clone(),
+                    probe_field: lhs_id,
+                }]
+            );
+            assert_eq!(q.sources().collect::<Vec<_>>(), [SourceExpr::from(&lhs), ids.clone()]);
+            assert_eq!(
+                q.explain(),
+                "Source: lhs (table#0)\nSemiJoinInMemory: table#0.col#0 IN ids (source#0)\n"
+            );
+        }
 
-        let lhs_source = SourceExpr::from(&lhs);
-        let rhs_source = SourceExpr::from(&rhs);
-
-        let q = QueryExpr::new(lhs_source.clone()).with_join_inner(
-            rhs_source.clone(),
-            FieldName::new(lhs.table_id, 0.into()),
-            FieldName::new(rhs.table_id, 0.into()),
-            false,
+        // An in-memory table of more than one column is still joined.
+        let pairs = mem_table(
+            TableId(2),
+            "pairs",
+            &[(0, AlgebraicType::U64, false), (1, AlgebraicType::U64, false)],
         );
-        let optimized = q.clone().optimize(&|_, _| 0);
-        assert_eq!(q, optimized);
+        let pairs_id = FieldName::new(TableId(2), 0.into());
+        let q = QueryExpr::new(&lhs)
+            .with_join_inner(QueryExpr::new(pairs), lhs_id, pairs_id, true)
+            .optimize(&|_, _| 0);
+        assert!(matches!(
+            &*q.query,
+            [Query::JoinInner(JoinExpr {
+                kind: JoinKind::Semi,
+                ..
+            })]
+        ));
     }
 
     #[test]
-    /// Tests that [`QueryExpr::optimize`] will not rewrite inner joins followed by projections to the RHS rather than LHS table.
-    fn optimize_inner_join_wrong_project() {
+    fn anti_join_is_kept() {
         let lhs = TableSchema::from_def(
             TableId(0),
             TableDef::new(
                 "lhs".into(),
-                ProductType::from_iter([AlgebraicType::I32, AlgebraicType::String]).into(),
+                ProductType::from_iter([AlgebraicType::U64, AlgebraicType::String]).into(),
             ),
         );
-        let rhs = TableSchema::from_def(
-            TableId(1),
-            TableDef::new(
-                "rhs".into(),
-                ProductType::from_iter([AlgebraicType::I32, AlgebraicType::I64]).into(),
-            ),
+        let lhs_id = FieldName::new(lhs.table_id, 0.into());
+        let ids = mem_table(TableId(1), "ids", &[(0, AlgebraicType::U64, false)]);
+        let ids_id = FieldName::new(TableId(1), 0.into());
+
+        // Unlike a semijoin, an antijoin isn't probed as a `SemiJoinInMemory`.
+        let q = QueryExpr::new(&lhs)
+            .with_join(QueryExpr::new(ids.clone()), lhs_id, ids_id, JoinKind::Anti)
+            .optimize(&|_, _| 0);
+        assert!(matches!(
+            &*q.query,
+            [Query::JoinInner(JoinExpr {
+                kind: JoinKind::Anti,
+                ..
+            })]
+        ));
+        assert_eq!(
+            q.explain(),
+            "Source: lhs (table#0)\nJoinInner: ON table#0.col#0 = table#1.col#0, kind: anti\n  Source: ids (source#0)\n"
         );
 
-        let lhs_source = SourceExpr::from(&lhs);
-        let rhs_source = SourceExpr::from(&rhs);
+        // The rhs is still a source of the query.
+        assert_eq!(q.sources().collect::<Vec<_>>(), [SourceExpr::from(&lhs), ids]);
+        assert!(q.reads_from_table(&lhs.table_id));
+    }
 
-        let q = QueryExpr::new(lhs_source.clone())
-            .with_join_inner(
-                rhs_source.clone(),
-                FieldName::new(lhs.table_id, 0.into()),
-                FieldName::new(rhs.table_id, 0.into()),
-                false,
-            )
-            .with_project(
-                &[0, 1].map(|c| FieldExpr::Name(FieldName::new(rhs.table_id, c.into()))),
-                Some(TableId(1)),
-            );
-        let optimized = q.clone().optimize(&|_, _| 0);
-        assert_eq!(q, optimized);
+    #[test]
+    fn system_vars() {
+        // A value of the variable's type is accepted.
+        assert_eq!(
+            CrudExpr::set_var(SystemVar::SlowAdHocQueryMs, 100u64.into()).unwrap(),
+            CrudExpr::SetVar {
+                name: "slow_ad_hoc_query_ms".into(),
+                value: 100u64.into(),
+            }
+        );
+
+        // Whereas a value of any other type is rejected up front.
+        let err = CrudExpr::set_var(SystemVar::SlowTxUpdateMs, "100".into()).unwrap_err();
+        assert!(matches!(
+            err,
+            ErrorVm::Config(ConfigError::TypeError(name, _, AlgebraicType::U64)) if name == "slow_tx_update_ms"
+        ));
+
+        assert_eq!(
+            CrudExpr::read_var(SystemVar::SlowSubscriptionQueryMs),
+            CrudExpr::ReadVar {
+                name: "slow_subscription_query_ms".into(),
+            }
+        );
+
+        // Names round trip, and unknown names aren't known variables.
+        for var in [
+            SystemVar::SlowAdHocQueryMs,
+            SystemVar::SlowTxUpdateMs,
+            SystemVar::SlowSubscriptionQueryMs,
+        ] {
+            assert_eq!(var.name().parse::<SystemVar>().unwrap(), var);
+        }
+        assert!(matches!("nope".parse::<SystemVar>(), Err(ConfigError::NotFound(_))));
+    }
+
+    #[test]
+    fn len_is_served_by_scan() {
+        let (head, [col_a, ..], _) = setup_best_index();
+        let op = ColumnOp::new(
+            OpQuery::Cmp(OpCmp::Eq),
+            ColumnOp::len_of(col_a),
+            AlgebraicValue::U64(0).into(),
+        );
+        assert_eq!(op.to_string(), format!("len({col_a}) == 0"));
+
+        // Even on an indexed column, which doesn't index the lengths of its values.
+        assert_eq!(
+            select_best_index(&mut <_>::default(), &head, &<_>::default(), &[&op]),
+            [IndexColumnOp::Scan(&op)].into(),
+        );
+
+        // Only the lengths of collections can be taken.
+        let array = Column::new(col_a, AlgebraicType::array(AlgebraicType::U8));
+        let opt_array = Column::new(col_a, AlgebraicType::option(AlgebraicType::array(AlgebraicType::U8)));
+        for col in [array, opt_array] {
+            let head = Header::new(head.table_id, head.table_name.clone(), vec![col], Vec::new());
+            assert!(op.type_check(&head).is_ok());
+        }
+        assert!(matches!(
+            op.type_check(&head),
+            Err(ErrorType::NotCollection(ty)) if ty == AlgebraicType::I8
+        ));
     }
 }
@@ -31,6 +31,7 @@ pub mod eval;
 pub mod expr;
 pub mod iterators;
 pub mod ops;
+pub mod plan;
 pub mod program;
 pub mod rel_ops;
 pub mod relation;
@@ -0,0 +1,1004 @@
+//! A serializable form of compiled query plans, i.e., of [`QueryExpr`]s.
+//!
+//! A plan refers to each in-memory table by its [`SourceId`] rather than by its rows,
+//! so it can be persisted, or shipped to another process,
+//! and then evaluated there against a new [`SourceSet`](crate::expr::SourceSet).
+//! A [`DbTable`] is encoded by its id and [`Header`].
+//!
+//! [`QueryExpr`] implements [`Serialize`] and [`Deserialize`], e.g., to BSATN,
+//! through the private types of this module, which mirror those of the plan.
+//! They describe each [`AlgebraicValue`] by its variant,
+//! as a value cannot otherwise be deserialized without knowing its type.
+//!
+//! An encoded plan declares how many sources it reads,
+//! and deserialization rejects any [`SourceId`] outside of that range.
+//!
+//! Column statistics are hints for the optimizer rather than part of a plan,
+//! so they are not encoded, and a deserialized plan has none.
+
+use crate::expr::{
+    ColumnOp, IndexJoin, IndexMultiScan, IndexScan, JoinExpr, JoinKind, MergeJoinExpr, Query, QueryExpr, SourceExpr,
+    SourceId, UnnestExpr,
+};
+use crate::operator::OpQuery;
+use spacetimedb_lib::de::{self, Deserialize, Deserializer};
+use spacetimedb_lib::ser::{self, Serialize, Serializer};
+use spacetimedb_primitives::{ColId, ColList, Constraints, TableId};
+use spacetimedb_sats::db::auth::{StAccess, StTableType};
+use spacetimedb_sats::relation::{Column, DbTable, FieldExpr, FieldName, Header, PartialIndexPredicate, RowCount};
+use spacetimedb_sats::{AlgebraicValue, ArrayValue, MapValue, ProductValue, SumValue, F32, F64};
+use std::ops::Bound;
+use std::sync::Arc;
+
+impl Serialize for QueryExpr {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let mut encoder = Encoder::default();
+        let expr = encoder.query_expr(self);
+        let source_count = encoder.source_count as u64;
+        Plan { source_count, expr }.serialize(serializer)
+    }
+}
+
+impl<'de> Deserialize<'de> for QueryExpr {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let Plan { source_count, expr } = Plan::deserialize(deserializer)?;
+        Decoder { source_count }.query_expr(expr)
+    }
+}
+
+/// An encoded [`QueryExpr`] which reads the in-memory sources `0..source_count`.
+#[derive(ser::Serialize, de::Deserialize)]
+struct Plan {
+    source_count: u64,
+    expr: PlanQueryExpr,
+}
+
+#[derive(ser::Serialize, de::Deserialize)]
+struct PlanQueryExpr {
+    source: PlanSourceExpr,
+    query: Box<[PlanQuery]>,
+}
+
+#[derive(ser::Serialize, de::Deserialize)]
+enum PlanSourceExpr {
+    InMemory(PlanInMemory),
+    DbTable(PlanDbTable),
+}
+
+#[derive(ser::Serialize, de::Deserialize)]
+struct PlanInMemory {
+    source_id: u64,
+    header: PlanHeader,
+    table_type: StTableType,
+    table_access: StAccess,
+    min_rows: u64,
+    max_rows: Option<u64>,
+}
+
+#[derive(ser::Serialize, de::Deserialize)]
+struct PlanDbTable {
+    head: PlanHeader,
+    table_id: TableId,
+    table_type: StTableType,
+    table_access: StAccess,
+}
+
+#[derive(ser::Serialize, de::Deserialize)]
+struct PlanHeader {
+    table_id: TableId,
+    table_name: Box<str>,
+    fields: Box<[Column]>,
+    constraints: Box<[PlanConstraint]>,
+    partial_indexes: Box<[PlanPartialIndex]>,
+    private_columns: Box<[FieldName]>,
+}
+
+#[derive(ser::Serialize, de::Deserialize)]
+struct PlanConstraint {
+    columns: ColList,
+    constraints: Constraints,
+}
+
+#[derive(ser::Serialize, de::Deserialize)]
+struct PlanPartialIndex {
+    columns: ColList,
+    ranges: Box<[PlanColumnRange]>,
+}
+
+#[derive(ser::Serialize, de::Deserialize)]
+struct PlanColumnRange {
+    col: ColId,
+    lower: PlanBound,
+    upper: PlanBound,
+}
+
+#[derive(ser::Serialize, de::Deserialize)]
+enum PlanQuery {
+    IndexScan(PlanIndexScan),
+    IndexMultiScan(PlanIndexMultiScan),
+    IndexJoin(Box<PlanIndexJoin>),
+    MergeJoin(PlanMergeJoin),
+    Select(PlanColumnOp),
+    Project(PlanProject),
+    JoinInner(Box<PlanJoin>),
+    JoinLeft(Box<PlanJoin>),
+    SemiJoinInMemory(PlanSemiJoinInMemory),
+    Unnest(PlanUnnest),
+    Limit(PlanLimit),
+    NeverReturns,
+}
+
+#[derive(ser::Serialize, de::Deserialize)]
+struct PlanIndexScan {
+    table: PlanDbTable,
+    columns: ColList,
+    lower: PlanBound,
+    upper: PlanBound,
+    covering: bool,
+}
+
+#[derive(ser::Serialize, de::Deserialize)]
+struct PlanIndexMultiScan {
+    table: PlanDbTable,
+    columns: ColList,
+    ranges: Box<[PlanRange]>,
+}
+
+#[derive(ser::Serialize, de::Deserialize)]
+struct PlanRange {
+    lower: PlanBound,
+    upper: PlanBound,
+}
+
+#[derive(ser::Serialize, de::Deserialize)]
+struct PlanIndexJoin {
+    probe_side: PlanQueryExpr,
+    probe_fields: Box<[FieldName]>,
+    index_side: PlanSourceExpr,
+    index_select: Option<PlanColumnOp>,
+    index_cols: ColList,
+    return_index_rows: bool,
+    projection: Option<Box<[PlanFieldExpr]>>,
+}
+
+#[derive(ser::Serialize, de::Deserialize)]
+struct PlanMergeJoin {
+    lhs: PlanDbTable,
+    rhs: PlanDbTable,
+    col_lhs: FieldName,
+    col_rhs: FieldName,
+    semi: bool,
+}
+
+#[derive(ser::Serialize, de::Deserialize)]
+struct PlanProject {
+    fields: Box<[PlanProjectField]>,
+    wildcard_table: Option<TableId>,
+    distinct: bool,
+}
+
+#[derive(ser::Serialize, de::Deserialize)]
+struct PlanProjectField {
+    field: PlanFieldExpr,
+    alias: Option<Box<str>>,
+}
+
+#[derive(ser::Serialize, de::Deserialize)]
+struct PlanJoin {
+    rhs: PlanQueryExpr,
+    col_lhs: FieldName,
+    col_rhs: FieldName,
+    kind: PlanJoinKind,
+}
+
+#[derive(ser::Serialize, de::Deserialize)]
+enum PlanJoinKind {
+    Inner,
+    Semi,
+    Anti,
+    Left,
+}
+
+#[derive(ser::Serialize, de::Deserialize)]
+struct PlanSemiJoinInMemory {
+    source: PlanSourceExpr,
+    probe_field: FieldName,
+}
+
+#[derive(ser::Serialize, de::Deserialize)]
+struct PlanUnnest {
+    field: FieldName,
+    outer: bool,
+}
+
+#[derive(ser::Serialize, de::Deserialize)]
+struct PlanLimit {
+    limit: u64,
+    offset: u64,
+}
+
+#[derive(ser::Serialize, de::Deserialize)]
+enum PlanColumnOp {
+    Field(PlanFieldExpr),
+    Cmp(Box<PlanCmp>),
+    In(PlanIn),
+    InTuples(PlanInTuples),
+    Len(Box<PlanColumnOp>),
+}
+
+#[derive(ser::Serialize, de::Deserialize)]
+struct PlanCmp {
+    op: OpQuery,
+    lhs: PlanColumnOp,
+    rhs: PlanColumnOp,
+}
+
+#[derive(ser::Serialize, de::Deserialize)]
+struct PlanIn {
+    field: FieldName,
+    values: Box<[PlanValue]>,
+}
+
+#[derive(ser::Serialize, de::Deserialize)]
+struct PlanInTuples {
+    fields: Box<[FieldName]>,
+    values: Box<[PlanValue]>,
+}
+
+#[derive(ser::Serialize, de::Deserialize)]
+enum PlanFieldExpr {
+    Name(FieldName),
+    Value(PlanValue),
+}
+
+#[derive(ser::Serialize, de::Deserialize)]
+enum PlanBound {
+    Included(PlanValue),
+    Excluded(PlanValue),
+    Unbounded,
+}
+
+/// An [`AlgebraicValue`] tagged with its variant, so that it can be deserialized without its type.
+#[derive(ser::Serialize, de::Deserialize)]
+enum PlanValue {
+    Sum(PlanSum),
+    Product(Box<[PlanValue]>),
+    Array(PlanArray),
+    Map(Box<[PlanMapEntry]>),
+    Bool(bool),
+    I8(i8),
+    U8(u8),
+    I16(i16),
+    U16(u16),
+    I32(i32),
+    U32(u32),
+    I64(i64),
+    U64(u64),
+    I128(i128),
+    U128(u128),
+    F32(F32),
+    F64(F64),
+    String(Box<str>),
+}
+
+#[derive(ser::Serialize, de::Deserialize)]
+struct PlanSum {
+    tag: u8,
+    value: Box<PlanValue>,
+}
+
+#[derive(ser::Serialize, de::Deserialize)]
+struct PlanMapEntry {
+    key: PlanValue,
+    value: PlanValue,
+}
+
+/// An [`ArrayValue`] tagged with its variant, which also describes the type of an empty array.
+#[derive(ser::Serialize, de::Deserialize)]
+enum PlanArray {
+    Sum(Box<[PlanSum]>),
+    Product(Box<[Box<[PlanValue]>]>),
+    Bool(Box<[bool]>),
+    I8(Box<[i8]>),
+    U8(Box<[u8]>),
+    I16(Box<[i16]>),
+    U16(Box<[u16]>),
+    I32(Box<[i32]>),
+    U32(Box<[u32]>),
+    I64(Box<[i64]>),
+    U64(Box<[u64]>),
+    I128(Box<[i128]>),
+    U128(Box<[u128]>),
+    F32(Box<[F32]>),
+    F64(Box<[F64]>),
+    String(Box<[Box<str>]>),
+    Array(Box<[PlanArray]>),
+    Map(Box<[Box<[PlanMapEntry]>]>),
+}
+
+/// Encodes a [`QueryExpr`], recording how many in-memory sources it reads.
+#[derive(Default)]
+struct Encoder {
+    source_count: usize,
+}
+
+impl Encoder {
+    fn query_expr(&mut self, expr: &QueryExpr) -> PlanQueryExpr {
+        PlanQueryExpr {
+            source: self.source(&expr.source),
+            query: expr.query.iter().map(|query| self.query(query)).collect(),
+        }
+    }
+
+    fn source(&mut self, source: &SourceExpr) -> PlanSourceExpr {
+        match source {
+            SourceExpr::InMemory {
+                source_id,
+                header,
+                table_type,
+                table_access,
+                row_count,
+                column_stats: _,
+            } => {
+                self.source_count = self.source_count.max(source_id.0 + 1);
+                PlanSourceExpr::InMemory(PlanInMemory {
+                    source_id: source_id.0 as u64,
+                    header: (&**header).into(),
+                    table_type: *table_type,
+                    table_access: *table_access,
+                    min_rows: row_count.min as u64,
+                    max_rows: row_count.max.map(|max| max as u64),
+                })
+            }
+            SourceExpr::DbTable(table) => PlanSourceExpr::DbTable(table.into()),
+        }
+    }
+
+    fn query(&mut self, query: &Query) -> PlanQuery {
+        match query {
+            Query::IndexScan(scan) => PlanQuery::IndexScan(PlanIndexScan {
+                table: (&scan.table).into(),
+                columns: scan.columns.clone(),
+                lower: (&scan.bounds.0).into(),
+                upper: (&scan.bounds.1).into(),
+                covering: scan.covering,
+            }),
+            Query::IndexMultiScan(scan) => PlanQuery::IndexMultiScan(PlanIndexMultiScan {
+                table: (&scan.table).into(),
+                columns: scan.columns.clone(),
+                ranges: scan
+                    .ranges
+                    .iter()
+                    .map(|(lower, upper)| PlanRange {
+                        lower: lower.into(),
+                        upper: upper.into(),
+                    })
+                    .collect(),
+            }),
+            Query::IndexJoin(join) => PlanQuery::IndexJoin(Box::new(PlanIndexJoin {
+                probe_side: self.query_expr(&join.probe_side),
+                probe_fields: join.probe_fields.iter().copied().collect(),
+                index_side: self.source(&join.index_side),
+                index_select: join.index_select.as_ref().map(Into::into),
+                index_cols: join.index_cols.clone(),
+                return_index_rows: join.return_index_rows,
+                projection: join
+                    .projection
+                    .as_ref()
+                    .map(|cols| cols.iter().map(Into::into).collect()),
+            })),
+            Query::MergeJoin(join) => PlanQuery::MergeJoin(PlanMergeJoin {
+                lhs: (&join.lhs).into(),
+                rhs: (&join.rhs).into(),
+                col_lhs: join.col_lhs,
+                col_rhs: join.col_rhs,
+                semi: join.semi,
+            }),
+            Query::Select(op) => PlanQuery::Select(op.into()),
+            Query::Project(fields, wildcard_table, distinct) => PlanQuery::Project(PlanProject {
+                fields: fields
+                    .iter()
+                    .map(|(field, alias)| PlanProjectField {
+                        field: field.into(),
+                        alias: alias.clone(),
+                    })
+                    .collect(),
+                wildcard_table: *wildcard_table,
+                distinct: *distinct,
+            }),
+            Query::JoinInner(join) => PlanQuery::JoinInner(Box::new(self.join(join))),
+            Query::JoinLeft(join) => PlanQuery::JoinLeft(Box::new(self.join(join))),
+            Query::SemiJoinInMemory { source, probe_field } => PlanQuery::SemiJoinInMemory(PlanSemiJoinInMemory {
+                source: self.source(source),
+                probe_field: *probe_field,
+            }),
+            Query::Unnest(unnest) => PlanQuery::Unnest(PlanUnnest {
+                field: unnest.field,
+                outer: unnest.outer,
+            }),
+            Query::Limit { limit, offset } => PlanQuery::Limit(PlanLimit {
+                limit: *limit,
+                offset: *offset,
+            }),
+            Query::NeverReturns => PlanQuery::NeverReturns,
+        }
+    }
+
+    fn join(&mut self, join: &JoinExpr) -> PlanJoin {
+        PlanJoin {
+            rhs: self.query_expr(&join.rhs),
+            col_lhs: join.col_lhs,
+            col_rhs: join.col_rhs,
+            kind: join.kind.into(),
+        }
+    }
+}
+
+/// Decodes a [`QueryExpr`], rejecting any in-memory source outside of `0..source_count`.
+struct Decoder {
+    source_count: u64,
+}
+
+impl Decoder {
+    fn query_expr<E: de::Error>(&self, expr: PlanQueryExpr) -> Result<QueryExpr, E> {
+        Ok(QueryExpr {
+            source: self.source(expr.source)?,
+            query: expr
+                .query
+                .into_vec()
+                .into_iter()
+                .map(|query| self.query(query))
+                .collect::<Result<_, _>>()?,
+        })
+    }
+
+    fn source<E: de::Error>(&self, source: PlanSourceExpr) -> Result<SourceExpr, E> {
+        Ok(match source {
+            PlanSourceExpr::InMemory(source) => {
+                if source.source_id >= self.source_count {
+                    return Err(E::custom(format_args!(
+                        "source id {} is out of range, as the plan declares {} sources",
+                        source.source_id, self.source_count
+                    )));
+                }
+                SourceExpr::InMemory {
+                    source_id: SourceId(source.source_id as usize),
+                    header: Arc::new(source.header.into()),
+                    table_type: source.table_type,
+                    table_access: source.table_access,
+                    row_count: RowCount {
+                        min: source.min_rows as usize,
+                        max: source.max_rows.map(|max| max as usize),
+                    },
+                    column_stats: None,
+                }
+            }
+            PlanSourceExpr::DbTable(table) => SourceExpr::DbTable(table.into()),
+        })
+    }
+
+    fn query<E: de::Error>(&self, query: PlanQuery) -> Result<Query, E> {
+        Ok(match query {
+            PlanQuery::IndexScan(scan) => Query::IndexScan(IndexScan {
+                table: scan.table.into(),
+                columns: scan.columns,
+                bounds: (scan.lower.into(), scan.upper.into()),
+                covering: scan.covering,
+            }),
+            PlanQuery::IndexMultiScan(scan) => Query::IndexMultiScan(IndexMultiScan {
+                table: scan.table.into(),
+                columns: scan.columns,
+                ranges: scan
+                    .ranges
+                    .into_vec()
+                    .into_iter()
+                    .map(|range| (range.lower.into(), range.upper.into()))
+                    .collect(),
+            }),
+            PlanQuery::IndexJoin(join) => {
+                let join = *join;
+                Query::IndexJoin(IndexJoin {
+                    probe_side: self.query_expr(join.probe_side)?,
+                    probe_fields: join.probe_fields.into_vec().into(),
+                    index_side: self.source(join.index_side)?,
+                    index_select: join.index_select.map(Into::into),
+                    index_cols: join.index_cols,
+                    return_index_rows: join.return_index_rows,
+                    projection: join
+                        .projection
+                        .map(|cols| cols.into_vec().into_iter().map(Into::into).collect()),
+                })
+            }
+            PlanQuery::MergeJoin(join) => Query::MergeJoin(MergeJoinExpr {
+                lhs: join.lhs.into(),
+                rhs: join.rhs.into(),
+                col_lhs: join.col_lhs,
+                col_rhs: join.col_rhs,
+                semi: join.semi,
+            }),
+            PlanQuery::Select(op) => Query::Select(op.into()),
+            PlanQuery::Project(project) => Query::Project(
+                project
+                    .fields
+                    .into_vec()
+                    .into_iter()
+                    .map(|field| (field.field.into(), field.alias))
+                    .collect(),
+                project.wildcard_table,
+                project.distinct,
+            ),
+            PlanQuery::JoinInner(join) => Query::JoinInner(self.join(*join)?),
+            PlanQuery::JoinLeft(join) => Query::JoinLeft(self.join(*join)?),
+            PlanQuery::SemiJoinInMemory(join) => Query::SemiJoinInMemory {
+                source: self.source(join.source)?,
+                probe_field: join.probe_field,
+            },
+            PlanQuery::Unnest(unnest) => Query::Unnest(UnnestExpr {
+                field: unnest.field,
+                outer: unnest.outer,
+            }),
+            PlanQuery::Limit(limit) => Query::Limit {
+                limit: limit.limit,
+                offset: limit.offset,
+            },
+            PlanQuery::NeverReturns => Query::NeverReturns,
+        })
+    }
+
+    fn join<E: de::Error>(&self, join: PlanJoin) -> Result<JoinExpr, E> {
+        Ok(JoinExpr::new(
+            self.query_expr(join.rhs)?,
+            join.col_lhs,
+            join.col_rhs,
+            join.kind.into(),
+        ))
+    }
+}
+
+impl From<JoinKind> for PlanJoinKind {
+    fn from(kind: JoinKind) -> Self {
+        match kind {
+            JoinKind::Inner => Self::Inner,
+            JoinKind::Semi => Self::Semi,
+            JoinKind::Anti => Self::Anti,
+            JoinKind::Left => Self::Left,
+        }
+    }
+}
+
+impl From<PlanJoinKind> for JoinKind {
+    fn from(kind: PlanJoinKind) -> Self {
+        match kind {
+            PlanJoinKind::Inner => Self::Inner,
+            PlanJoinKind::Semi => Self::Semi,
+            PlanJoinKind::Anti => Self::Anti,
+            PlanJoinKind::Left => Self::Left,
+        }
+    }
+}
+
+impl From<&DbTable> for PlanDbTable {
+    fn from(table: &DbTable) -> Self {
+        Self {
+            head: (&*table.head).into(),
+            table_id: table.table_id,
+            table_type: table.table_type,
+            table_access: table.table_access,
+        }
+    }
+}
+
+impl From<PlanDbTable> for DbTable {
+    fn from(table: PlanDbTable) -> Self {
+        DbTable::new(
+            Arc::new(table.head.into()),
+            table.table_id,
+            table.table_type,
+            table.table_access,
+        )
+    }
+}
+
+impl From<&Header> for PlanHeader {
+    fn from(header: &Header) -> Self {
+        Self {
+            table_id: header.table_id,
+            table_name: header.table_name.clone(),
+            fields: header.fields.iter().cloned().collect(),
+            constraints: header
+                .constraints
+                .iter()
+                .map(|(columns, constraints)| PlanConstraint {
+                    columns: columns.clone(),
+                    constraints: *constraints,
+                })
+                .collect(),
+            partial_indexes: header
+                .partial_indexes
+                .iter()
+                .map(|(columns, predicate)| PlanPartialIndex {
+                    columns: columns.clone(),
+                    ranges: predicate
+                        .ranges
+                        .iter()
+                        .map(|(col, lower, upper)| PlanColumnRange {
+                            col: *col,
+                            lower: lower.into(),
+                            upper: upper.into(),
+                        })
+                        .collect(),
+                })
+                .collect(),
+            private_columns: header.private_columns.iter().copied().collect(),
+        }
+    }
+}
+
+impl From<PlanHeader> for Header {
+    fn from(header: PlanHeader) -> Self {
+        Self {
+            table_id: header.table_id,
+            table_name: header.table_name,
+            fields: header.fields.into_vec(),
+            constraints: header
+                .constraints
+                .into_vec()
+                .into_iter()
+                .map(|constraint| (constraint.columns, constraint.constraints))
+                .collect(),
+            partial_indexes: header
+                .partial_indexes
+                .into_vec()
+                .into_iter()
+                .map(|index| {
+                    let ranges = index
+                        .ranges
+                        .into_vec()
+                        .into_iter()
+                        .map(|range| (range.col, range.lower.into(), range.upper.into()))
+                        .collect();
+                    (index.columns, PartialIndexPredicate { ranges })
+                })
+                .collect(),
+            private_columns: header.private_columns.into_vec(),
+        }
+    }
+}
+
+impl From<&ColumnOp> for PlanColumnOp {
+    fn from(op: &ColumnOp) -> Self {
+        match op {
+            ColumnOp::Field(field) => Self::Field(field.into()),
+            ColumnOp::Cmp { op, lhs, rhs } => Self::Cmp(Box::new(PlanCmp {
+                op: *op,
+                lhs: (&**lhs).into(),
+                rhs: (&**rhs).into(),
+            })),
+            ColumnOp::In { field, values } => Self::In(PlanIn {
+                field: *field,
+                values: values.iter().map(Into::into).collect(),
+            }),
+            ColumnOp::InTuples { fields, values } => Self::InTuples(PlanInTuples {
+                fields: fields.iter().copied().collect(),
+                values: values.iter().map(Into::into).collect(),
+            }),
+            ColumnOp::Len(op) => Self::Len(Box::new((&**op).into())),
+        }
+    }
+}
+
+impl From<PlanColumnOp> for ColumnOp {
+    fn from(op: PlanColumnOp) -> Self {
+        match op {
+            PlanColumnOp::Field(field) => Self::Field(field.into()),
+            PlanColumnOp::Cmp(cmp) => {
+                let PlanCmp { op, lhs, rhs } = *cmp;
+                Self::new(op, lhs.into(), rhs.into())
+            }
+            PlanColumnOp::In(PlanIn { field, values }) => Self::In {
+                field,
+                values: values.into_vec().into_iter().map(Into::into).collect(),
+            },
+            PlanColumnOp::InTuples(PlanInTuples { fields, values }) => Self::InTuples {
+                fields: fields.into_vec(),
+                values: values.into_vec().into_iter().map(Into::into).collect(),
+            },
+            PlanColumnOp::Len(op) => Self::Len(Box::new((*op).into())),
+        }
+    }
+}
+
+impl From<&FieldExpr> for PlanFieldExpr {
+    fn from(field: &FieldExpr) -> Self {
+        match field {
+            FieldExpr::Name(name) => Self::Name(*name),
+            FieldExpr::Value(value) => Self::Value(value.into()),
+        }
+    }
+}
+
+impl From<PlanFieldExpr> for FieldExpr {
+    fn from(field: PlanFieldExpr) -> Self {
+        match field {
+            PlanFieldExpr::Name(name) => Self::Name(name),
+            PlanFieldExpr::Value(value) => Self::Value(value.into()),
+        }
+    }
+}
+
+impl From<&Bound<AlgebraicValue>> for PlanBound {
+    fn from(bound: &Bound<AlgebraicValue>) -> Self {
+        match bound {
+            Bound::Included(value) => Self::Included(value.into()),
+            Bound::Excluded(value) => Self::Excluded(value.into()),
+            Bound::Unbounded => Self::Unbounded,
+        }
+    }
+}
+
+impl From<PlanBound> for Bound<AlgebraicValue> {
+    fn from(bound: PlanBound) -> Self {
+        match bound {
+            PlanBound::Included(value) => Self::Included(value.into()),
+            PlanBound::Excluded(value) => Self::Excluded(value.into()),
+            PlanBound::Unbounded => Self::Unbounded,
+        }
+    }
+}
+
+fn encode_product(product: &ProductValue) -> Box<[PlanValue]> {
+    product.elements.iter().map(Into::into).collect()
+}
+
+fn decode_product(elements: Box<[PlanValue]>) -> ProductValue {
+    elements.into_vec().into_iter().map(AlgebraicValue::from).collect()
+}
+
+fn encode_map<'a>(map: impl IntoIterator<Item = (&'a AlgebraicValue, &'a AlgebraicValue)>) -> Box<[PlanMapEntry]> {
+    map.into_iter()
+        .map(|(key, value)| PlanMapEntry {
+            key: key.into(),
+            value: value.into(),
+        })
+        .collect()
+}
+
+fn decode_map<T: FromIterator<(AlgebraicValue, AlgebraicValue)>>(entries: Box<[PlanMapEntry]>) -> T {
+    entries
+        .into_vec()
+        .into_iter()
+        .map(|entry| (entry.key.into(), entry.value.into()))
+        .collect()
+}
+
+impl From<&SumValue> for PlanSum {
+    fn from(sum: &SumValue) -> Self {
+        Self {
+            tag: sum.tag,
+            value: Box::new((&*sum.value).into()),
+        }
+    }
+}
+
+impl From<PlanSum> for SumValue {
+    fn from(sum: PlanSum) -> Self {
+        Self {
+            tag: sum.tag,
+            value: Box::new((*sum.value).into()),
+        }
+    }
+}
+
+impl From<&AlgebraicValue> for PlanValue {
+    fn from(value: &AlgebraicValue) -> Self {
+        match value {
+            AlgebraicValue::Sum(sum) => Self::Sum(sum.into()),
+            AlgebraicValue::Product(product) => Self::Product(encode_product(product)),
+            AlgebraicValue::Array(array) => Self::Array(array.into()),
+            AlgebraicValue::Map(map) => Self::Map(encode_map(map.iter())),
+            AlgebraicValue::Bool(x) => Self::Bool(*x),
+            AlgebraicValue::I8(x) => Self::I8(*x),
+            AlgebraicValue::U8(x) => Self::U8(*x),
+            AlgebraicValue::I16(x) => Self::I16(*x),
+            AlgebraicValue::U16(x) => Self::U16(*x),
+            AlgebraicValue::I32(x) => Self::I32(*x),
+            AlgebraicValue::U32(x) => Self::U32(*x),
+            AlgebraicValue::I64(x) => Self::I64(*x),
+            AlgebraicValue::U64(x) => Self::U64(*x),
+            AlgebraicValue::I128(x) => Self::I128(x.0),
+            AlgebraicValue::U128(x) => Self::U128(x.0),
+            AlgebraicValue::F32(x) => Self::F32(*x),
+            AlgebraicValue::F64(x) => Self::F64(*x),
+            AlgebraicValue::String(x) => Self::String(x.clone()),
+        }
+    }
+}
+
+impl From<PlanValue> for AlgebraicValue {
+    fn from(value: PlanValue) -> Self {
+        match value {
+            PlanValue::Sum(sum) => Self::Sum(sum.into()),
+            PlanValue::Product(elements) => Self::Product(decode_product(elements)),
+            PlanValue::Array(array) => Self::Array(array.into()),
+            PlanValue::Map(entries) => Self::Map(Box::new(decode_map(entries))),
+            PlanValue::Bool(x) => Self::Bool(x),
+            PlanValue::I8(x) => Self::I8(x),
+            PlanValue::U8(x) => Self::U8(x),
+            PlanValue::I16(x) => Self::I16(x),
+            PlanValue::U16(x) => Self::U16(x),
+            PlanValue::I32(x) => Self::I32(x),
+            PlanValue::U32(x) => Self::U32(x),
+            PlanValue::I64(x) => Self::I64(x),
+            PlanValue::U64(x) => Self::U64(x),
+            PlanValue::I128(x) => x.into(),
+            PlanValue::U128(x) => x.into(),
+            PlanValue::F32(x) => Self::F32(x),
+            PlanValue::F64(x) => Self::F64(x),
+            PlanValue::String(x) => Self::String(x),
+        }
+    }
+}
+
+impl From<&ArrayValue> for PlanArray {
+    fn from(array: &ArrayValue) -> Self {
+        match array {
+            ArrayValue::Sum(xs) => Self::Sum(xs.iter().map(Into::into).collect()),
+            ArrayValue::Product(xs) => Self::Product(xs.iter().map(encode_product).collect()),
+            ArrayValue::Bool(xs) => Self::Bool(xs.clone()),
+            ArrayValue::I8(xs) => Self::I8(xs.clone()),
+            ArrayValue::U8(xs) => Self::U8(xs.clone()),
+            ArrayValue::I16(xs) => Self::I16(xs.clone()),
+            ArrayValue::U16(xs) => Self::U16(xs.clone()),
+            ArrayValue::I32(xs) => Self::I32(xs.clone()),
+            ArrayValue::U32(xs) => Self::U32(xs.clone()),
+            ArrayValue::I64(xs) => Self::I64(xs.clone()),
+            ArrayValue::U64(xs) => Self::U64(xs.clone()),
+            ArrayValue::I128(xs) => Self::I128(xs.clone()),
+            ArrayValue::U128(xs) => Self::U128(xs.clone()),
+            ArrayValue::F32(xs) => Self::F32(xs.clone()),
+            ArrayValue::F64(xs) => Self::F64(xs.clone()),
+            ArrayValue::String(xs) => Self::String(xs.clone()),
+            ArrayValue::Array(xs) => Self::Array(xs.iter().map(Into::into).collect()),
+            ArrayValue::Map(xs) => Self::Map(xs.iter().map(|map| encode_map(map.iter())).collect()),
+        }
+    }
+}
+
+impl From<PlanArray> for ArrayValue {
+    fn from(array: PlanArray) -> Self {
+        match array {
+            PlanArray::Sum(xs) => Self::Sum(xs.into_vec().into_iter().map(Into::into).collect()),
+            PlanArray::Product(xs) => Self::Product(xs.into_vec().into_iter().map(decode_product).collect()),
+            PlanArray::Bool(xs) => Self::Bool(xs),
+            PlanArray::I8(xs) => Self::I8(xs),
+            PlanArray::U8(xs) => Self::U8(xs),
+            PlanArray::I16(xs) => Self::I16(xs),
+            PlanArray::U16(xs) => Self::U16(xs),
+            PlanArray::I32(xs) => Self::I32(xs),
+            PlanArray::U32(xs) => Self::U32(xs),
+            PlanArray::I64(xs) => Self::I64(xs),
+            PlanArray::U64(xs) => Self::U64(xs),
+            PlanArray::I128(xs) => Self::I128(xs),
+            PlanArray::U128(xs) => Self::U128(xs),
+            PlanArray::F32(xs) => Self::F32(xs),
+            PlanArray::F64(xs) => Self::F64(xs),
+            PlanArray::String(xs) => Self::String(xs),
+            PlanArray::Array(xs) => Self::Array(xs.into_vec().into_iter().map(Into::into).collect()),
+            PlanArray::Map(xs) => Self::Map(xs.into_vec().into_iter().map(decode_map::<MapValue>).collect()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::operator::OpCmp;
+    use spacetimedb_lib::bsatn;
+    use spacetimedb_sats::{product, AlgebraicType};
+
+    fn header(table_id: TableId, name: &str) -> Header {
+        let fields = [
+            AlgebraicType::U64,
+            AlgebraicType::String,
+            AlgebraicType::option(AlgebraicType::I32),
+        ];
+        Header::new(
+            table_id,
+            name.into(),
+            fields
+                .into_iter()
+                .enumerate()
+                .map(|(col, ty)| Column::new(FieldName::new(table_id, col.into()), ty))
+                .collect(),
+            vec![(ColId(0).into(), Constraints::indexed())],
+        )
+    }
+
+    fn mem_table(source_id: usize, table_id: TableId, name: &str) -> SourceExpr {
+        SourceExpr::InMemory {
+            source_id: SourceId(source_id),
+            header: Arc::new(header(table_id, name)),
+            table_type: StTableType::User,
+            table_access: StAccess::Public,
+            row_count: RowCount::exact(3),
+            column_stats: None,
+        }
+    }
+
+    fn db_table(table_id: TableId, name: &str) -> DbTable {
+        DbTable::new(
+            Arc::new(header(table_id, name)),
+            table_id,
+            StTableType::User,
+            StAccess::Private,
+        )
+    }
+
+    #[test]
+    fn plan_round_trip() {
+        let lhs = mem_table(0, 0.into(), "lhs");
+        let rhs = mem_table(1, 1.into(), "rhs");
+        let db = db_table(2.into(), "db");
+        let field = |table: u32, col: u32| FieldName::new(table.into(), col.into());
+
+        let rhs_side = QueryExpr::new(rhs)
+            .with_select_cmp(OpCmp::Gt, field(1, 0), AlgebraicValue::U64(7))
+            .with_project(&[FieldExpr::Name(field(1, 1))], None);
+        let mut plan = QueryExpr::new(lhs)
+            .with_join_inner(rhs_side, field(0, 1), field(1, 1), false)
+            .with_join_inner(SourceExpr::DbTable(db.clone()), field(0, 0), field(2, 0), true)
+            .with_join(
+                SourceExpr::DbTable(db.clone()),
+                field(0, 1),
+                field(2, 1),
+                JoinKind::Anti,
+            );
+        plan.query.extend([
+            Query::Select(ColumnOp::In {
+                field: field(0, 2),
+                values: vec![AlgebraicValue::OptionNone(), AlgebraicValue::OptionSome(5i32.into())],
+            }),
+            Query::IndexJoin(IndexJoin {
+                probe_side: mem_table(2, 3.into(), "probe").into(),
+                probe_fields: [field(3, 0)].into_iter().collect(),
+                index_side: SourceExpr::DbTable(db.clone()),
+                index_select: Some(ColumnOp::cmp(
+                    field(2, 1),
+                    OpCmp::Eq,
+                    AlgebraicValue::String("x".into()),
+                )),
+                index_cols: ColId(0).into(),
+                return_index_rows: false,
+                projection: Some(vec![field(3, 1).into(), AlgebraicValue::U8(1).into()]),
+            }),
+            Query::IndexMultiScan(IndexMultiScan {
+                table: db,
+                columns: ColId(0).into(),
+                ranges: vec![
+                    (Bound::Included(1u64.into()), Bound::Excluded(3u64.into())),
+                    (Bound::Included(product![1u64, "a"].into()), Bound::Unbounded),
+                ],
+            }),
+            Query::Limit { limit: 10, offset: 2 },
+        ]);
+        let plan = plan.with_project_distinct(&[field(0, 1).into()], None);
+
+        let bytes = bsatn::to_vec(&plan).unwrap();
+        let decoded: QueryExpr = bsatn::from_slice(&bytes).unwrap();
+        assert_eq!(decoded, plan);
+    }
+
+    #[test]
+    fn plan_source_id_out_of_range() {
+        let plan = Plan {
+            source_count: 1,
+            expr: Encoder::default().query_expr(&mem_table(1, 0.into(), "lhs").into()),
+        };
+        let bytes = bsatn::to_vec(&plan).unwrap();
+        assert!(bsatn::from_slice::<QueryExpr>(&bytes).is_err());
+
+        let plan = QueryExpr::from(mem_table(1, 0.into(), "lhs"));
+        let bytes = bsatn::to_vec(&plan).unwrap();
+        assert_eq!(bsatn::from_slice::<QueryExpr>(&bytes).unwrap(), plan);
+    }
+}
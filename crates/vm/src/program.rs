@@ -4,9 +4,9 @@
 
 use crate::errors::ErrorVm;
 use crate::eval::{build_query, build_source_expr_query};
-use crate::expr::{Code, CrudExpr, SourceSet};
+use crate::expr::{Code, CodeResult, CrudExpr, QueryExpr, SourceSet};
 use crate::rel_ops::RelOps;
-use crate::relation::MemTable;
+use crate::relation::{MemTable, RowStream};
 use spacetimedb_sats::ProductValue;
 
 /// A trait to allow split the execution of `programs` to allow executing
@@ -19,6 +19,20 @@ pub trait ProgramVm {
     /// Allows to execute the query with the state carried by the implementation of this
     /// trait
     fn eval_query<const N: usize>(&mut self, query: CrudExpr, sources: Sources<'_, N>) -> Result<Code, ErrorVm>;
+
+    /// Runs `query` like [`ProgramVm::eval_query`],
+    /// but may return its rows as a [`CodeResult::Stream`] that produces them as they are consumed,
+    /// rather than collecting all of them into a [`MemTable`] up front.
+    ///
+    /// By default, the result is always collected.
+    fn eval_query_stream<'a, const N: usize>(
+        &'a mut self,
+        query: &'a QueryExpr,
+        sources: Sources<'_, N>,
+    ) -> Result<CodeResult<'a>, ErrorVm> {
+        self.eval_query(CrudExpr::Query(query.clone()), sources)
+            .map(CodeResult::from)
+    }
 }
 
 pub type Sources<'a, const N: usize> = &'a mut SourceSet<Vec<ProductValue>, N>;
@@ -61,4 +75,17 @@ impl ProgramVm for Program {
             }
         }
     }
+
+    /// The result is always streamed, as its rows are produced from those of the sources as it is consumed.
+    fn eval_query_stream<'a, const N: usize>(
+        &'a mut self,
+        query: &'a QueryExpr,
+        sources: Sources<'_, N>,
+    ) -> Result<CodeResult<'a>, ErrorVm> {
+        let result = build_source_expr_query(sources, &query.source);
+        let result = build_query(result, &query.query, sources)?;
+
+        let stream = RowStream::from_rel_ops(query.source.table_access(), result);
+        Ok(CodeResult::Stream(stream))
+    }
 }
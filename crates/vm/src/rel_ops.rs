@@ -1,8 +1,11 @@
-use crate::errors::ErrorVm;
+use crate::errors::{ErrorType, ErrorVm};
 use crate::relation::RelValue;
-use spacetimedb_data_structures::map::HashMap;
-use spacetimedb_sats::relation::{FieldExpr, Header, RowCount};
-use spacetimedb_sats::AlgebraicValue;
+use spacetimedb_data_structures::map::{HashMap, HashSet};
+use spacetimedb_sats::array_value::ArrayValueIntoIter;
+use spacetimedb_sats::relation::{FieldExpr, FieldName, Header, RowCount};
+use spacetimedb_sats::{AlgebraicType, AlgebraicValue, ArrayType, BuiltinType, ProductValue};
+use std::cmp::Ordering;
+use std::collections::VecDeque;
 use std::sync::Arc;
 
 /// A trait for dealing with fallible iterators for the database.
@@ -31,6 +34,36 @@ pub trait RelOps<'a> {
         Select::new(self, predicate)
     }
 
+    /// Creates an `Iterator` which skips the first `offset` rows and then yields at most `limit` rows.
+    ///
+    /// Once `limit` rows have been yielded, the underlying iterator is no longer advanced.
+    ///
+    /// Note:
+    ///
+    /// It is the equivalent of a `LIMIT limit OFFSET offset` clause on SQL.
+    #[inline]
+    fn limit(self, limit: u64, offset: u64) -> Limit<Self>
+    where
+        Self: Sized,
+    {
+        Limit::new(self, limit, offset)
+    }
+
+    /// Creates an `Iterator` which yields each distinct row only once, in the order it was first seen.
+    ///
+    /// Every distinct row is held in memory until the iterator is dropped, see [`Distinct`].
+    ///
+    /// Note:
+    ///
+    /// It is the equivalent of a `SELECT DISTINCT` clause on SQL.
+    #[inline]
+    fn distinct(self) -> Distinct<'a, Self>
+    where
+        Self: Sized,
+    {
+        Distinct::new(self)
+    }
+
     /// Creates an `Iterator` which uses a closure that projects to a new [RelValue] extracted from the current.
     ///
     /// Given a [RelValue] the closure must return a subset of the current one.
@@ -41,16 +74,57 @@ pub trait RelOps<'a> {
     ///
     /// It is the equivalent of a `SELECT` clause on SQL.
     #[inline]
-    fn project<P>(self, cols: &[FieldExpr], extractor: P) -> Result<Project<Self, P>, ErrorVm>
+    fn project<P>(self, cols: &[(FieldExpr, Option<Box<str>>)], extractor: P) -> Result<Project<Self, P>, ErrorVm>
     where
-        P: for<'b> FnMut(&[FieldExpr], RelValue<'b>) -> Result<RelValue<'b>, ErrorVm>,
+        P: for<'b> FnMut(&[(FieldExpr, Option<Box<str>>)], RelValue<'b>) -> Result<RelValue<'b>, ErrorVm>,
         Self: Sized,
     {
         let count = self.row_count();
-        let head = self.head().project(cols)?;
+        let head = self.head().project_aliased(cols)?;
         Ok(Project::new(self, count, Arc::new(head), cols, extractor))
     }
 
+    /// Creates an `Iterator` which expands each element of the array column `field` into its own row,
+    /// where the element replaces the array.
+    ///
+    /// Rows with an empty array yield no rows, unless `outer` is set,
+    /// in which case they yield a single row where the element is `None`.
+    /// To distinguish the two, the elements are wrapped in `Some` when `outer` is set.
+    ///
+    /// The [Header] is pre-checked that `field` exists and is an array and return a error otherwise.
+    ///
+    /// Note:
+    ///
+    /// It is the equivalent of a `CROSS JOIN LATERAL UNNEST(field)` clause on SQL,
+    /// or a `LEFT JOIN LATERAL UNNEST(field) ON TRUE` clause when `outer` is set.
+    #[inline]
+    fn unnest(self, field: FieldName, outer: bool) -> Result<Unnest<Self>, ErrorVm>
+    where
+        Self: Sized,
+    {
+        let head = self.head();
+        let col = head.column_pos_or_err(field)?;
+        let ty = &head.fields[col.idx()].algebraic_type;
+        let AlgebraicType::Builtin(BuiltinType::Array(ArrayType { elem_ty })) = ty else {
+            return Err(ErrorType::FieldArray { field, ty: ty.clone() }.into());
+        };
+        let elem_ty = (**elem_ty).clone();
+        let elem_ty = if outer { AlgebraicType::option(elem_ty) } else { elem_ty };
+
+        let mut fields = head.fields.clone();
+        fields[col.idx()].algebraic_type = elem_ty;
+        // Constraints on the array do not carry over to its elements.
+        let constraints = head
+            .constraints
+            .iter()
+            .filter(|(cols, _)| !cols.contains(col))
+            .cloned()
+            .collect();
+        let head = Header::new(head.table_id, head.table_name.clone(), fields, constraints);
+
+        Ok(Unnest::new(self, Arc::new(head), col.idx(), outer))
+    }
+
     /// Intersection between the left and the right, both (non-sorted) `iterators`.
     ///
     /// The hash join strategy requires the right iterator can be collected to a `HashMap`.
@@ -82,6 +156,89 @@ pub trait RelOps<'a> {
         Ok(JoinInner::new(head, self, with, key_lhs, key_rhs, predicate, project))
     }
 
+    /// Left semijoin of the left and the right, both (non-sorted) `iterators`.
+    ///
+    /// Like [`RelOps::join_inner`], but yields each row on the left at most once,
+    /// on its first match, however many rows on the right it matches.
+    #[inline]
+    #[allow(clippy::type_complexity)]
+    fn semi_join<Pred, KeyLhs, KeyRhs, Rhs>(
+        self,
+        with: Rhs,
+        key_lhs: KeyLhs,
+        key_rhs: KeyRhs,
+        predicate: Pred,
+    ) -> Result<JoinInner<'a, Self, Rhs, KeyLhs, KeyRhs, Pred, fn(RelValue<'a>, RelValue<'a>) -> RelValue<'a>>, ErrorVm>
+    where
+        Self: Sized,
+        Pred: FnMut(&RelValue<'a>, &RelValue<'a>) -> bool,
+        KeyLhs: FnMut(&RelValue<'a>) -> AlgebraicValue,
+        KeyRhs: FnMut(&RelValue<'a>) -> AlgebraicValue,
+        Rhs: RelOps<'a>,
+    {
+        let head = self.head().clone();
+        let project: fn(RelValue<'a>, RelValue<'a>) -> RelValue<'a> = |lhs, _| lhs;
+        Ok(JoinInner {
+            semi: true,
+            ..JoinInner::new(head, self, with, key_lhs, key_rhs, predicate, project)
+        })
+    }
+
+    /// Left antijoin of the left and the right, both (non-sorted) `iterators`.
+    ///
+    /// The complement of [`RelOps::semi_join`]:
+    /// yields each row on the left that matches no row on the right, exactly once.
+    #[inline]
+    #[allow(clippy::type_complexity)]
+    fn anti_join<Pred, KeyLhs, KeyRhs, Rhs>(
+        self,
+        with: Rhs,
+        key_lhs: KeyLhs,
+        key_rhs: KeyRhs,
+        predicate: Pred,
+    ) -> Result<JoinInner<'a, Self, Rhs, KeyLhs, KeyRhs, Pred, fn(RelValue<'a>, RelValue<'a>) -> RelValue<'a>>, ErrorVm>
+    where
+        Self: Sized,
+        Pred: FnMut(&RelValue<'a>, &RelValue<'a>) -> bool,
+        KeyLhs: FnMut(&RelValue<'a>) -> AlgebraicValue,
+        KeyRhs: FnMut(&RelValue<'a>) -> AlgebraicValue,
+        Rhs: RelOps<'a>,
+    {
+        Ok(JoinInner {
+            anti: true,
+            ..self.semi_join(with, key_lhs, key_rhs, predicate)?
+        })
+    }
+
+    /// Left outer join of the left and the right, both (non-sorted) `iterators`.
+    ///
+    /// Every row on the left is projected with each row on the right that has the same key,
+    /// or, if there is no such row, with `None` for the right side.
+    ///
+    /// Like [`RelOps::join_inner`], the right iterator is collected to a `HashMap`.
+    ///
+    /// Note:
+    ///
+    /// It is the equivalent of a `LEFT OUTER JOIN` clause on SQL.
+    #[inline]
+    fn join_left<Proj, KeyLhs, KeyRhs, Rhs>(
+        self,
+        with: Rhs,
+        head: Arc<Header>,
+        key_lhs: KeyLhs,
+        key_rhs: KeyRhs,
+        project: Proj,
+    ) -> JoinLeft<'a, Self, Rhs, KeyLhs, KeyRhs, Proj>
+    where
+        Self: Sized,
+        Proj: FnMut(RelValue<'a>, Option<RelValue<'a>>) -> RelValue<'a>,
+        KeyLhs: FnMut(&RelValue<'a>) -> AlgebraicValue,
+        KeyRhs: FnMut(&RelValue<'a>) -> AlgebraicValue,
+        Rhs: RelOps<'a>,
+    {
+        JoinLeft::new(head, self, with, key_lhs, key_rhs, project)
+    }
+
     /// Collect all the rows in this relation into a `Vec<T>` given a function `RelValue<'a> -> T`.
     #[inline]
     fn collect_vec<T>(mut self, mut convert: impl FnMut(RelValue<'a>) -> T) -> Result<Vec<T>, ErrorVm>
@@ -138,6 +295,155 @@ impl<'a> RelOps<'a> for EmptyRelOps {
     }
 }
 
+/// `RelOps` iterator which returns all rows of each of its inputs in turn.
+///
+/// Used to compile index scans over several ranges, like `WHERE x IN (1, 5)`.
+pub struct Concat<'a> {
+    head: Arc<Header>,
+    iters: VecDeque<Box<dyn RelOps<'a> + 'a>>,
+}
+
+impl<'a> Concat<'a> {
+    pub fn new(head: Arc<Header>, iters: impl IntoIterator<Item = Box<dyn RelOps<'a> + 'a>>) -> Self {
+        Self {
+            head,
+            iters: iters.into_iter().collect(),
+        }
+    }
+}
+
+impl<'a> RelOps<'a> for Concat<'a> {
+    fn head(&self) -> &Arc<Header> {
+        &self.head
+    }
+
+    fn next(&mut self) -> Result<Option<RelValue<'a>>, ErrorVm> {
+        while let Some(iter) = self.iters.front_mut() {
+            if let Some(row) = iter.next()? {
+                return Ok(Some(row));
+            }
+            self.iters.pop_front();
+        }
+        Ok(None)
+    }
+}
+
+/// `RelOps` iterator which joins two inputs sorted by their join column,
+/// advancing both in lockstep.
+///
+/// Used to compile equi-joins of two tables read in the order of an index on their join columns.
+/// The rows of `rhs` sharing a key are buffered,
+/// so that every row of `lhs` with that key can be paired with them.
+pub struct MergeJoin<'a, Lhs, Rhs> {
+    head: Arc<Header>,
+    lhs: Lhs,
+    rhs: Rhs,
+    /// The position of the join column in `lhs`.
+    col_lhs: usize,
+    /// The position of the join column in `rhs`.
+    col_rhs: usize,
+    /// If true, returns each row of `lhs` with a match once, rather than the concatenation of the matching rows.
+    semi: bool,
+    /// The first row of `rhs` past the current group, if already fetched.
+    next_rhs: Option<RelValue<'a>>,
+    rhs_done: bool,
+    /// The key of the current group and the rows of `rhs` having it.
+    group_key: Option<AlgebraicValue>,
+    group: Vec<RelValue<'a>>,
+    /// The current row of `lhs` and the position in `group` of the next row to pair it with.
+    current: Option<(RelValue<'a>, usize)>,
+}
+
+impl<'a, Lhs: RelOps<'a>, Rhs: RelOps<'a>> MergeJoin<'a, Lhs, Rhs> {
+    pub fn new(lhs: Lhs, rhs: Rhs, col_lhs: usize, col_rhs: usize, semi: bool) -> Self {
+        let head = if semi {
+            lhs.head().clone()
+        } else {
+            Arc::new(lhs.head().extend(rhs.head()))
+        };
+        Self {
+            head,
+            lhs,
+            rhs,
+            col_lhs,
+            col_rhs,
+            semi,
+            next_rhs: None,
+            rhs_done: false,
+            group_key: None,
+            group: Vec::new(),
+            current: None,
+        }
+    }
+
+    /// Replaces the current group with the rows of `rhs` whose key is `key`,
+    /// skipping those with smaller keys.
+    fn seek_group(&mut self, key: AlgebraicValue) -> Result<(), ErrorVm> {
+        self.group.clear();
+        while !self.rhs_done {
+            let row = match self.next_rhs.take() {
+                Some(row) => row,
+                None => match self.rhs.next()? {
+                    Some(row) => row,
+                    None => {
+                        self.rhs_done = true;
+                        break;
+                    }
+                },
+            };
+            let ord = row.read_column(self.col_rhs).unwrap().as_ref().cmp(&key);
+            match ord {
+                Ordering::Less => {}
+                Ordering::Equal => self.group.push(row),
+                Ordering::Greater => {
+                    self.next_rhs = Some(row);
+                    break;
+                }
+            }
+        }
+        self.group_key = Some(key);
+        Ok(())
+    }
+}
+
+impl<'a, Lhs: RelOps<'a>, Rhs: RelOps<'a>> RelOps<'a> for MergeJoin<'a, Lhs, Rhs> {
+    fn head(&self) -> &Arc<Header> {
+        &self.head
+    }
+
+    fn next(&mut self) -> Result<Option<RelValue<'a>>, ErrorVm> {
+        loop {
+            // Pair the current row of `lhs` with the next row of its group.
+            if let Some((lhs, pos)) = &mut self.current {
+                if let Some(rhs) = self.group.get(*pos) {
+                    *pos += 1;
+                    return Ok(Some(lhs.clone().extend(rhs.clone())));
+                }
+                self.current = None;
+            }
+
+            let Some(lhs) = self.lhs.next()? else {
+                return Ok(None);
+            };
+            let key = lhs.read_column(self.col_lhs).unwrap().into_owned();
+            if self.group_key.as_ref() != Some(&key) {
+                self.seek_group(key)?;
+                if self.group.is_empty() && self.rhs_done && self.next_rhs.is_none() {
+                    // All of `rhs` is behind us, so no further row of `lhs` can match.
+                    return Ok(None);
+                }
+            }
+
+            if !self.group.is_empty() {
+                if self.semi {
+                    return Ok(Some(lhs));
+                }
+                self.current = Some((lhs, 0));
+            }
+        }
+    }
+}
+
 #[derive(Clone, Debug)]
 pub struct Select<I, P> {
     pub(crate) iter: I,
@@ -170,17 +476,158 @@ where
     }
 }
 
+#[derive(Clone, Debug)]
+pub struct Limit<I> {
+    pub(crate) iter: I,
+    /// The number of rows still to be yielded.
+    pub(crate) remaining: u64,
+    /// The number of rows still to be skipped.
+    pub(crate) offset: u64,
+}
+
+impl<I> Limit<I> {
+    pub fn new(iter: I, limit: u64, offset: u64) -> Limit<I> {
+        Limit {
+            iter,
+            remaining: limit,
+            offset,
+        }
+    }
+}
+
+impl<'a, I: RelOps<'a>> RelOps<'a> for Limit<I> {
+    fn head(&self) -> &Arc<Header> {
+        self.iter.head()
+    }
+
+    fn next(&mut self) -> Result<Option<RelValue<'a>>, ErrorVm> {
+        if self.remaining == 0 {
+            return Ok(None);
+        }
+        while self.offset > 0 {
+            if self.iter.next()?.is_none() {
+                self.remaining = 0;
+                return Ok(None);
+            }
+            self.offset -= 1;
+        }
+        let next = self.iter.next()?;
+        self.remaining = if next.is_some() { self.remaining - 1 } else { 0 };
+        Ok(next)
+    }
+}
+
+/// Deduplicates the rows of `iter` by hashing them.
+///
+/// The hash set holds a copy of every distinct row yielded so far,
+/// so for an output of `n` distinct rows it uses about as much memory as collecting them would.
+/// Rows referring into a table are cheap to copy, but projected rows are owned,
+/// e.g., a projection with a string column keeps each distinct string alive until the iterator is dropped.
+#[derive(Clone, Debug)]
+pub struct Distinct<'a, I> {
+    pub(crate) iter: I,
+    /// The rows yielded so far.
+    pub(crate) seen: HashSet<RelValue<'a>>,
+}
+
+impl<'a, I> Distinct<'a, I> {
+    pub fn new(iter: I) -> Distinct<'a, I> {
+        Distinct {
+            iter,
+            seen: HashSet::default(),
+        }
+    }
+}
+
+impl<'a, I: RelOps<'a>> RelOps<'a> for Distinct<'a, I> {
+    fn head(&self) -> &Arc<Header> {
+        self.iter.head()
+    }
+
+    fn next(&mut self) -> Result<Option<RelValue<'a>>, ErrorVm> {
+        while let Some(row) = self.iter.next()? {
+            if self.seen.insert(row.clone()) {
+                return Ok(Some(row));
+            }
+        }
+        Ok(None)
+    }
+}
+
+pub struct Unnest<I> {
+    pub(crate) head: Arc<Header>,
+    pub(crate) iter: I,
+    /// The position of the array column.
+    pub(crate) col: usize,
+    pub(crate) outer: bool,
+    /// The row being expanded and its remaining elements.
+    pub(crate) current: Option<(ProductValue, ArrayValueIntoIter)>,
+}
+
+impl<I> Unnest<I> {
+    pub fn new(iter: I, head: Arc<Header>, col: usize, outer: bool) -> Unnest<I> {
+        Unnest {
+            head,
+            iter,
+            col,
+            outer,
+            current: None,
+        }
+    }
+}
+
+impl<'a, I: RelOps<'a>> RelOps<'a> for Unnest<I> {
+    fn head(&self) -> &Arc<Header> {
+        &self.head
+    }
+
+    fn next(&mut self) -> Result<Option<RelValue<'a>>, ErrorVm> {
+        loop {
+            if let Some((row, elems)) = &mut self.current {
+                if let Some(elem) = elems.next() {
+                    let mut row = row.clone();
+                    row.elements[self.col] = if self.outer {
+                        AlgebraicValue::OptionSome(elem)
+                    } else {
+                        elem
+                    };
+                    return Ok(Some(RelValue::Projection(row)));
+                }
+                self.current = None;
+            }
+
+            let Some(row) = self.iter.next()? else {
+                return Ok(None);
+            };
+            let mut row = row.into_product_value();
+            // The type of the column was checked when constructing `Unnest`.
+            let elems = row.elements[self.col].take().into_array().unwrap();
+            if elems.is_empty() && self.outer {
+                row.elements[self.col] = AlgebraicValue::OptionNone();
+                return Ok(Some(RelValue::Projection(row)));
+            }
+            self.current = Some((row, elems.into_iter()));
+        }
+    }
+}
+
 #[derive(Clone, Debug)]
 pub struct Project<'a, I, P> {
     pub(crate) head: Arc<Header>,
     pub(crate) count: RowCount,
-    pub(crate) cols: &'a [FieldExpr],
+    pub(crate) cols: &'a [(FieldExpr, Option<Box<str>>)],
     pub(crate) iter: I,
     pub(crate) extractor: P,
 }
 
 impl<'a, I, P> Project<'a, I, P> {
-    pub fn new(iter: I, count: RowCount, head: Arc<Header>, cols: &'a [FieldExpr], extractor: P) -> Project<'a, I, P> {
+    pub fn new(
+        iter: I,
+        count: RowCount,
+        head: Arc<Header>,
+        cols: &'a [(FieldExpr, Option<Box<str>>)],
+        extractor: P,
+    ) -> Project<'a, I, P> {
         Project {
             iter,
             count,
@@ -194,7 +641,7 @@ impl<'a, I, P> Project<'a, I, P> {
 impl<'a, I, P> RelOps<'a> for Project<'_, I, P>
 where
     I: RelOps<'a>,
-    P: FnMut(&[FieldExpr], RelValue<'a>) -> Result<RelValue<'a>, ErrorVm>,
+    P: FnMut(&[(FieldExpr, Option<Box<str>>)], RelValue<'a>) -> Result<RelValue<'a>, ErrorVm>,
 {
     fn head(&self) -> &Arc<Header> {
         &self.head
@@ -225,6 +672,10 @@ pub struct JoinInner<'a, Lhs, Rhs, KeyLhs, KeyRhs, Pred, Proj> {
     map: HashMap<AlgebraicValue, Vec<RelValue<'a>>>,
     filled_rhs: bool,
     left: Option<RelValue<'a>>,
+    /// If true, each row of `lhs` is yielded at most once, without projection.
+    semi: bool,
+    /// If true, together with `semi`, the rows of `lhs` yielded are those *without* a match.
+    anti: bool,
 }
 
 impl<'a, Lhs, Rhs, KeyLhs, KeyRhs, Pred, Proj> JoinInner<'a, Lhs, Rhs, KeyLhs, KeyRhs, Pred, Proj> {
@@ -248,6 +699,8 @@ impl<'a, Lhs, Rhs, KeyLhs, KeyRhs, Pred, Proj> JoinInner<'a, Lhs, Rhs, KeyLhs, K
             projection,
             filled_rhs: false,
             left: None,
+            semi: false,
+            anti: false,
         }
     }
 }
@@ -287,6 +740,22 @@ where
             };
             let k = (self.key_lhs)(lhs);
 
+            // A semijoin stops at the first match,
+            // leaving the rhs rows in place for the following lhs rows.
+            // An antijoin does the same, but keeps the rows that found no match.
+            if self.semi {
+                let predicate = &mut self.predicate;
+                let matched = self
+                    .map
+                    .get(&k)
+                    .is_some_and(|rvv| rvv.iter().any(|rhs| predicate(lhs, rhs)));
+                let lhs = self.left.take();
+                if matched != self.anti {
+                    return Ok(lhs);
+                }
+                continue;
+            }
+
             // If we can relate `KeyLhs` and `KeyRhs`, we have candidate.
             // If that candidate still has rhs elements, test against the predicate and yield.
             if let Some(rvv) = self.map.get_mut(&k) {
@@ -301,3 +770,74 @@ where
         }
     }
 }
+
+#[derive(Clone, Debug)]
+pub struct JoinLeft<'a, Lhs, Rhs, KeyLhs, KeyRhs, Proj> {
+    pub(crate) head: Arc<Header>,
+    pub(crate) lhs: Lhs,
+    pub(crate) rhs: Rhs,
+    pub(crate) key_lhs: KeyLhs,
+    pub(crate) key_rhs: KeyRhs,
+    pub(crate) projection: Proj,
+    map: HashMap<AlgebraicValue, Vec<RelValue<'a>>>,
+    filled_rhs: bool,
+    /// The joined rows of the current row on the left still to be yielded, in reverse.
+    pending: Vec<RelValue<'a>>,
+}
+
+impl<'a, Lhs, Rhs, KeyLhs, KeyRhs, Proj> JoinLeft<'a, Lhs, Rhs, KeyLhs, KeyRhs, Proj> {
+    pub fn new(head: Arc<Header>, lhs: Lhs, rhs: Rhs, key_lhs: KeyLhs, key_rhs: KeyRhs, projection: Proj) -> Self {
+        Self {
+            head,
+            lhs,
+            rhs,
+            key_lhs,
+            key_rhs,
+            projection,
+            map: HashMap::new(),
+            filled_rhs: false,
+            pending: Vec::new(),
+        }
+    }
+}
+
+impl<'a, Lhs, Rhs, KeyLhs, KeyRhs, Proj> RelOps<'a> for JoinLeft<'a, Lhs, Rhs, KeyLhs, KeyRhs, Proj>
+where
+    Lhs: RelOps<'a>,
+    Rhs: RelOps<'a>,
+    KeyLhs: FnMut(&RelValue<'a>) -> AlgebraicValue,
+    KeyRhs: FnMut(&RelValue<'a>) -> AlgebraicValue,
+    Proj: FnMut(RelValue<'a>, Option<RelValue<'a>>) -> RelValue<'a>,
+{
+    fn head(&self) -> &Arc<Header> {
+        &self.head
+    }
+
+    fn next(&mut self) -> Result<Option<RelValue<'a>>, ErrorVm> {
+        // Consume `Rhs`, building a map `KeyRhs => Rhs`.
+        if !self.filled_rhs {
+            self.map = HashMap::with_capacity(self.rhs.row_count().min);
+            while let Some(row_rhs) = self.rhs.next()? {
+                let key_rhs = (self.key_rhs)(&row_rhs);
+                self.map.entry(key_rhs).or_default().push(row_rhs);
+            }
+            self.filled_rhs = true;
+        }
+
+        loop {
+            if let Some(row) = self.pending.pop() {
+                return Ok(Some(row));
+            }
+
+            let Some(lhs) = self.lhs.next()? else {
+                return Ok(None);
+            };
+            let Some(matches) = self.map.get(&(self.key_lhs)(&lhs)) else {
+                return Ok(Some((self.projection)(lhs, None)));
+            };
+            let project = &mut self.projection;
+            self.pending
+                .extend(matches.iter().rev().map(|rhs| project(lhs.clone(), Some(rhs.clone()))));
+        }
+    }
+}
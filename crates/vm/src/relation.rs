@@ -1,3 +1,7 @@
+use crate::errors::ErrorVm;
+use crate::eval::IterRows;
+use crate::rel_ops::RelOps;
+use core::fmt;
 use core::hash::{Hash, Hasher};
 use spacetimedb_sats::bsatn::ser::BsatnError;
 use spacetimedb_sats::db::auth::StAccess;
@@ -152,7 +156,11 @@ impl<'a> RelValue<'a> {
         }
     }
 
-    pub fn project_owned(mut self, cols: &[FieldExpr], header: &Header) -> Result<ProductValue, RelationError> {
+    pub fn project_owned<'c>(
+        mut self,
+        cols: impl ExactSizeIterator<Item = &'c FieldExpr>,
+        header: &Header,
+    ) -> Result<ProductValue, RelationError> {
         let mut elements = Vec::with_capacity(cols.len());
         for col in cols {
             let val = match col {
@@ -229,3 +237,76 @@ impl Relation for MemTable {
         RowCount::exact(self.data.len())
     }
 }
+
+/// The rows of a result, produced incrementally rather than materialized up front like in a [`MemTable`].
+///
+/// This allows e.g., sending large results to a client in chunks
+/// without buffering the entire result in memory.
+///
+/// The rows may be read from the tables as they are consumed,
+/// in which case the stream borrows the transaction that reads them, for `'a`.
+pub struct RowStream<'a> {
+    pub head: Arc<Header>,
+    pub table_access: StAccess,
+    rows: Box<dyn Iterator<Item = Result<ProductValue, ErrorVm>> + 'a>,
+}
+
+impl<'a> RowStream<'a> {
+    pub fn new(
+        head: Arc<Header>,
+        table_access: StAccess,
+        rows: impl Iterator<Item = Result<ProductValue, ErrorVm>> + 'a,
+    ) -> Self {
+        Self {
+            head,
+            table_access,
+            rows: Box::new(rows),
+        }
+    }
+
+    /// Returns a stream of the rows of `result`, each converted to a [`ProductValue`] as it is consumed.
+    pub fn from_rel_ops(table_access: StAccess, mut result: Box<IterRows<'a>>) -> Self {
+        let head = result.head().clone();
+        let rows = std::iter::from_fn(move || {
+            result
+                .next()
+                .map(|row| row.map(RelValue::into_product_value))
+                .transpose()
+        });
+        Self::new(head, table_access, rows)
+    }
+
+    /// Collects all the rows of the stream into a [`MemTable`],
+    /// or returns the first error encountered.
+    pub fn materialize(self) -> Result<MemTable, ErrorVm> {
+        let data = self.rows.collect::<Result<_, _>>()?;
+        Ok(MemTable {
+            head: self.head,
+            data,
+            table_access: self.table_access,
+        })
+    }
+}
+
+impl Iterator for RowStream<'_> {
+    type Item = Result<ProductValue, ErrorVm>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.rows.next()
+    }
+}
+
+impl From<MemTable> for RowStream<'_> {
+    fn from(table: MemTable) -> Self {
+        Self::new(table.head, table.table_access, table.data.into_iter().map(Ok))
+    }
+}
+
+impl fmt::Debug for RowStream<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("RowStream")
+            .field("head", &self.head)
+            .field("table_access", &self.table_access)
+            .finish_non_exhaustive()
+    }
+}